@@ -0,0 +1,72 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use doppio::multi_party::communication::{MessagePayload, NetworkMessage};
+use doppio::multi_party::MessageView;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+/// Size of one encoded share in bytes.
+const SHARE_BYTES: usize = 32;
+
+/// Create a share message with `count` random shares.
+fn make_share_message(count: usize) -> NetworkMessage {
+    let mut rng = StdRng::seed_from_u64(0);
+    let shares = (0..count)
+        .map(|_| {
+            let mut share = vec![0; SHARE_BYTES];
+            rng.fill_bytes(&mut share);
+            share
+        })
+        .collect();
+    NetworkMessage::share(0, 1, 0, shares)
+}
+
+/// Sum the share bytes, standing in for the receiver's processing.
+fn checksum<'a>(shares: impl Iterator<Item = &'a [u8]>) -> u64 {
+    shares
+        .map(|share| share.iter().map(|&byte| byte as u64).sum::<u64>())
+        .sum()
+}
+
+fn bench_receive_shares(c: &mut Criterion) {
+    let mut group = c.benchmark_group("receive_shares");
+    group.sample_size(10);
+    for count in [1_000, 100_000] {
+        let message = make_share_message(count);
+        let json = message.to_bytes().unwrap();
+        let zero_copy = message.to_zero_copy_bytes().unwrap();
+        println!(
+            "{} shares: json {} bytes, zero_copy {} bytes",
+            count,
+            json.len(),
+            zero_copy.len()
+        );
+
+        group.throughput(Throughput::Bytes((count * SHARE_BYTES) as u64));
+        group.bench_with_input(BenchmarkId::new("json", count), &json, |b, bytes| {
+            b.iter(|| {
+                let message = NetworkMessage::from_bytes(black_box(bytes)).unwrap();
+                match message.payload {
+                    MessagePayload::Shares(shares) => checksum(shares.iter().map(Vec::as_slice)),
+                    _ => unreachable!(),
+                }
+            })
+        });
+        group.bench_with_input(
+            BenchmarkId::new("zero_copy", count),
+            &zero_copy,
+            |b, bytes| {
+                b.iter(|| {
+                    let view = MessageView::decode(black_box(bytes)).unwrap();
+                    checksum(view.shares().unwrap())
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_receive_shares);
+criterion_main!(benches);
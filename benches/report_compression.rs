@@ -0,0 +1,81 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use doppio::schema::AttributeType::*;
+use doppio::{BatchCodec, ReportVector};
+use rand::rngs::{OsRng, StdRng};
+
+const REPORT_COUNT: usize = 100_000;
+
+const CODECS: [(&str, BatchCodec); 3] = [
+    (
+        "packed",
+        BatchCodec {
+            delta: false,
+            zstd_level: None,
+        },
+    ),
+    (
+        "zstd",
+        BatchCodec {
+            delta: false,
+            zstd_level: Some(3),
+        },
+    ),
+    (
+        "delta_zstd",
+        BatchCodec {
+            delta: true,
+            zstd_level: Some(3),
+        },
+    ),
+];
+
+/// Create a plaintext `ReportVector` as the server stores it after revealing, and
+/// one share of it as the client sends it.
+fn make_report_vectors() -> (ReportVector<2>, ReportVector<2>) {
+    let mut plaintext = ReportVector::<2>::new(&[C10, C8, N16(65521), C4]);
+    plaintext.push_many_zipf::<StdRng>(REPORT_COUNT, 1.2, false);
+    let mut share = plaintext.clone();
+    share.share(&mut OsRng);
+    (plaintext, share)
+}
+
+fn bench_report_compression(c: &mut Criterion) {
+    let (plaintext, share) = make_report_vectors();
+
+    // Print the bytes per report once so the size is tracked next to the timings.
+    let unpacked_size = REPORT_COUNT * 2 * std::mem::size_of::<u32>();
+    println!(
+        "unpacked: {:.2} bytes/report",
+        unpacked_size as f64 / REPORT_COUNT as f64
+    );
+    for (name, codec) in CODECS {
+        for (kind, report_vector) in [("plaintext", &plaintext), ("share", &share)] {
+            println!(
+                "{}/{}: {:.2} bytes/report",
+                kind,
+                name,
+                report_vector.compress(codec).len() as f64 / REPORT_COUNT as f64
+            );
+        }
+    }
+
+    let mut group = c.benchmark_group("report_compression");
+    group.sample_size(10);
+    for (name, codec) in CODECS {
+        group.bench_function(format!("compress/{}", name), |b| {
+            b.iter(|| black_box(&plaintext).compress(codec))
+        });
+
+        let bytes = plaintext.compress(codec);
+        group.bench_function(format!("decompress/{}", name), |b| {
+            b.iter(|| ReportVector::<2>::decompress(black_box(&bytes)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_report_compression);
+criterion_main!(benches);
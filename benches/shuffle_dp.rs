@@ -0,0 +1,100 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use doppio::arith::PrivacyBudget;
+use doppio::dp::{DPConfig, DPMechanism, MechanismType};
+use doppio::schema::{DataPoint, Query, QueryType};
+use doppio::shuffle::ShuffleMechanism;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Create data points with two features, the first taking `bins` distinct values.
+fn make_data(count: usize, bins: u32) -> Vec<DataPoint> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..count)
+        .map(|_| DataPoint::new(vec![rng.gen_range(0..bins) as f64, rng.gen::<f64>()]))
+        .collect()
+}
+
+fn bench_shuffle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("shuffle");
+    group.sample_size(10);
+    for count in [1_000, 100_000, 1_000_000] {
+        let data = make_data(count, 16);
+        let mut mechanism = ShuffleMechanism::new();
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &data, |b, data| {
+            b.iter_batched(
+                || data.clone(),
+                |data| mechanism.shuffle(data, 1).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_mechanisms(c: &mut Criterion) {
+    const COUNT: usize = 100_000;
+    let data = make_data(COUNT, 16);
+    let query = Query::new(
+        QueryType::Mean,
+        vec!["feature1".to_string(), "feature2".to_string()],
+    );
+
+    let mut group = c.benchmark_group("dp_mean");
+    group.throughput(Throughput::Elements(COUNT as u64));
+    for mechanism_type in [
+        MechanismType::Laplace,
+        MechanismType::Gaussian,
+        MechanismType::Exponential,
+    ] {
+        let mechanism = DPMechanism::new(DPConfig {
+            privacy_budget: PrivacyBudget::new(1.0, 1e-5),
+            mechanism_type,
+        });
+        group.bench_function(format!("{:?}", mechanism_type), |b| {
+            b.iter_batched(
+                || data.clone(),
+                |data| {
+                    mechanism
+                        .apply_mechanism(data, black_box(query.clone()))
+                        .unwrap()
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_histogram(c: &mut Criterion) {
+    const COUNT: usize = 100_000;
+    let mechanism = DPMechanism::new(DPConfig::default());
+    let query = Query::new(QueryType::Histogram, vec!["feature1".to_string()]);
+
+    let mut group = c.benchmark_group("dp_histogram");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(COUNT as u64));
+    for bins in [16, 4_096, 65_536] {
+        let data = make_data(COUNT, bins);
+        group.bench_with_input(BenchmarkId::from_parameter(bins), &data, |b, data| {
+            b.iter_batched(
+                || data.clone(),
+                |data| {
+                    mechanism
+                        .apply_mechanism(data, black_box(query.clone()))
+                        .unwrap()
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_shuffle, bench_mechanisms, bench_histogram);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "grpc")]
+    grpc::compile();
+}
+
+/// Generate the client and server of `proto/mpc.proto`.
+///
+/// The messages are written by hand in `src/multi_party/grpc.rs`, like those in
+/// `src/report/wire.rs`, so only the service is generated and no `protoc` is
+/// needed.
+#[cfg(feature = "grpc")]
+mod grpc {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    const MESSAGES: &str = "crate::multi_party::grpc";
+
+    fn unary(name: &str, route_name: &str) -> Method {
+        Method::builder()
+            .name(name)
+            .route_name(route_name)
+            .input_type(format!("{}::{}Request", MESSAGES, route_name))
+            .output_type(format!("{}::{}Response", MESSAGES, route_name))
+            .codec_path("tonic::codec::ProstCodec")
+            .build()
+    }
+
+    pub fn compile() {
+        println!("cargo:rerun-if-changed=proto/mpc.proto");
+
+        let service = Service::builder()
+            .name("MultiPartyService")
+            .package("doppio.mpc.v1")
+            .method(unary("share", "Share"))
+            .method(unary("shuffle", "Shuffle"))
+            .method(unary("reconstruct", "Reconstruct"))
+            .method(unary("query", "Query"))
+            .build();
+        Builder::new().compile(&[service]);
+    }
+}
@@ -0,0 +1,383 @@
+//! Three-server localhost benchmark for the multi-party protocol.
+//!
+//! Spins up three `MultiPartyServer`s connected over loopback TCP, runs the
+//! sharing, shuffling, reconstruction and noise phases and prints per-phase
+//! latency, bytes transferred and throughput.
+//!
+//! Usage: `cargo run --release --example localhost_benchmark -- [RECORDS...]`
+
+use doppio::multi_party::communication::{MessagePayload, MessageType, NetworkMessage};
+use doppio::multi_party::crypto::ThresholdEncryption;
+use doppio::multi_party::share::DataShare;
+use doppio::multi_party::{
+    MultiPartyServer, ProtocolConfig, ProtocolError, ProtocolPhase, ServerRole, ServerState,
+};
+use doppio::schema::DataPoint;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Number of servers in the benchmark topology
+const NUM_SERVERS: usize = 3;
+/// Link identifier used by the coordinator
+const COORDINATOR_ID: usize = NUM_SERVERS;
+/// Records per share message
+const CHUNK_SIZE: usize = 10_000;
+/// Features per record
+const NUM_FEATURES: usize = 2;
+/// Default record counts
+const DEFAULT_SIZES: [usize; 3] = [10_000, 100_000, 1_000_000];
+
+fn io_error(e: std::io::Error) -> ProtocolError {
+    ProtocolError::network_error(e.to_string())
+}
+
+fn codec_error(e: serde_json::Error) -> ProtocolError {
+    ProtocolError::network_error(format!("Failed to encode message: {}", e))
+}
+
+/// Length-prefixed JSON framing over a loopback TCP stream
+struct TcpLink {
+    local_id: usize,
+    peer_id: usize,
+    stream: TcpStream,
+    sequence: u64,
+    bytes_sent: Arc<AtomicU64>,
+}
+
+impl TcpLink {
+    /// Send a message, counting the framed bytes
+    async fn send(&mut self, message_type: MessageType, payload: MessagePayload) -> Result<(), ProtocolError> {
+        let message = NetworkMessage::new(message_type, self.local_id, self.peer_id, self.sequence, payload);
+        self.sequence += 1;
+
+        let bytes = serde_json::to_vec(&message).map_err(codec_error)?;
+        self.stream.write_u32(bytes.len() as u32).await.map_err(io_error)?;
+        self.stream.write_all(&bytes).await.map_err(io_error)?;
+        self.bytes_sent.fetch_add(bytes.len() as u64 + 4, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Receive the next message
+    async fn recv(&mut self) -> Result<NetworkMessage, ProtocolError> {
+        let len = self.stream.read_u32().await.map_err(io_error)? as usize;
+        let mut bytes = vec![0u8; len];
+        self.stream.read_exact(&mut bytes).await.map_err(io_error)?;
+        serde_json::from_slice(&bytes).map_err(codec_error)
+    }
+
+    /// Send an acknowledgment marking the end of a phase
+    async fn ack(&mut self) -> Result<(), ProtocolError> {
+        self.send(MessageType::Ack, MessagePayload::Empty).await
+    }
+
+    /// Wait for an acknowledgment from the peer
+    async fn expect_ack(&mut self) -> Result<(), ProtocolError> {
+        let message = self.recv().await?;
+        match message.message_type {
+            MessageType::Ack => Ok(()),
+            MessageType::Error(e) => Err(ProtocolError::server_error(e)),
+            other => Err(ProtocolError::network_error(format!("Expected ack, got {:?}", other))),
+        }
+    }
+
+    /// Send per-record shares in chunks followed by an ack
+    async fn send_records(&mut self, records: &[Vec<DataShare>]) -> Result<(), ProtocolError> {
+        for chunk in records.chunks(CHUNK_SIZE) {
+            let mut encoded = Vec::with_capacity(chunk.len());
+            for record in chunk {
+                encoded.push(serde_json::to_vec(record).map_err(codec_error)?);
+            }
+            self.send(MessageType::Share, MessagePayload::Shares(encoded)).await?;
+        }
+        self.ack().await
+    }
+
+    /// Receive per-record shares until the peer acks
+    async fn recv_records(&mut self) -> Result<Vec<Vec<DataShare>>, ProtocolError> {
+        let mut records = Vec::new();
+        loop {
+            let message = self.recv().await?;
+            match (message.message_type, message.payload) {
+                (MessageType::Share, MessagePayload::Shares(encoded)) => {
+                    for bytes in encoded {
+                        records.push(serde_json::from_slice(&bytes).map_err(codec_error)?);
+                    }
+                }
+                (MessageType::Ack, _) => return Ok(records),
+                (other, _) => {
+                    return Err(ProtocolError::network_error(format!("Unexpected message {:?}", other)));
+                }
+            }
+        }
+    }
+}
+
+/// Create both ends of a loopback TCP connection
+async fn link_pair(a: usize, b: usize, bytes_sent: &Arc<AtomicU64>) -> Result<(TcpLink, TcpLink), ProtocolError> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.map_err(io_error)?;
+    let addr = listener.local_addr().map_err(io_error)?;
+    let (dialed, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+    let dialed = dialed.map_err(io_error)?;
+    let (accepted, _) = accepted.map_err(io_error)?;
+    dialed.set_nodelay(true).map_err(io_error)?;
+    accepted.set_nodelay(true).map_err(io_error)?;
+
+    let link = |local_id, peer_id, stream| TcpLink {
+        local_id,
+        peer_id,
+        stream,
+        sequence: 0,
+        bytes_sent: bytes_sent.clone(),
+    };
+    Ok((link(a, b, dialed), link(b, a, accepted)))
+}
+
+/// One benchmarked server and its network links
+struct ServerTask {
+    server: MultiPartyServer,
+    coordinator: TcpLink,
+    peers: HashMap<usize, TcpLink>,
+    records: Vec<Vec<DataShare>>,
+}
+
+impl ServerTask {
+    fn peer(&mut self, peer_id: usize) -> Result<&mut TcpLink, ProtocolError> {
+        self.peers.get_mut(&peer_id).ok_or(ProtocolError::InternalError {
+            message: format!("No link to server {}", peer_id),
+        })
+    }
+
+    async fn run(mut self) -> Result<(), ProtocolError> {
+        // Setup
+        self.server.initialize().await?;
+        self.coordinator.ack().await?;
+
+        // Sharing
+        self.records = self.coordinator.recv_records().await?;
+        self.server.set_state(ServerState::Participating);
+        self.coordinator.ack().await?;
+
+        // Shuffling: each server in turn acts as leader for one round
+        for leader in 0..NUM_SERVERS {
+            let permutation = if leader == self.server.id() {
+                let mut permutation: Vec<usize> = (0..self.records.len()).collect();
+                permutation.shuffle(&mut rand::thread_rng());
+                for peer in self.peers.values_mut() {
+                    peer.send(MessageType::Shuffle, MessagePayload::Permutation(permutation.clone()))
+                        .await?;
+                }
+                permutation
+            } else {
+                match self.peer(leader)?.recv().await?.payload {
+                    MessagePayload::Permutation(permutation) => permutation,
+                    _ => return Err(ProtocolError::network_error("Expected permutation".to_string())),
+                }
+            };
+
+            let mut previous: Vec<Option<Vec<DataShare>>> = self.records.drain(..).map(Some).collect();
+            self.records = permutation
+                .iter()
+                .map(|&from| previous[from].take().unwrap_or_default())
+                .collect();
+        }
+        self.coordinator.ack().await?;
+
+        // Reconstruction at the first server
+        if self.server.id() != 0 {
+            let first = self.peer(0)?;
+            first.send_records(&self.records).await?;
+            self.coordinator.ack().await?;
+            self.coordinator.ack().await?;
+            return Ok(());
+        }
+
+        let mut combined = std::mem::take(&mut self.records);
+        for peer_id in 1..NUM_SERVERS {
+            let records = self.peer(peer_id)?.recv_records().await?;
+            for (shares, peer_shares) in combined.iter_mut().zip(records) {
+                shares.extend(peer_shares);
+            }
+        }
+        let data = self.server.reconstruct_data(combined).await?;
+        self.coordinator.ack().await?;
+
+        // Noise
+        let noisy = self.server.add_noise(data).await?;
+        self.server.set_state(ServerState::Completed);
+        log::info!("Released {} noisy records", noisy.len());
+        self.coordinator.ack().await
+    }
+}
+
+/// Measurements for a single phase
+struct PhaseReport {
+    phase: ProtocolPhase,
+    elapsed: Duration,
+    bytes: u64,
+}
+
+/// Tracks phase boundaries against the shared byte counter
+struct PhaseTimer {
+    bytes_sent: Arc<AtomicU64>,
+    start: Instant,
+    start_bytes: u64,
+    reports: Vec<PhaseReport>,
+}
+
+impl PhaseTimer {
+    fn new(bytes_sent: Arc<AtomicU64>) -> Self {
+        Self {
+            start: Instant::now(),
+            start_bytes: bytes_sent.load(Ordering::Relaxed),
+            bytes_sent,
+            reports: Vec::new(),
+        }
+    }
+
+    fn finish(&mut self, phase: ProtocolPhase) {
+        let bytes = self.bytes_sent.load(Ordering::Relaxed);
+        self.reports.push(PhaseReport {
+            phase,
+            elapsed: self.start.elapsed(),
+            bytes: bytes - self.start_bytes,
+        });
+        self.start = Instant::now();
+        self.start_bytes = bytes;
+    }
+}
+
+async fn expect_all_acks(links: &mut [TcpLink]) -> Result<(), ProtocolError> {
+    for link in links.iter_mut() {
+        link.expect_ack().await?;
+    }
+    Ok(())
+}
+
+async fn run_benchmark(num_records: usize) -> Result<Vec<PhaseReport>, ProtocolError> {
+    let bytes_sent = Arc::new(AtomicU64::new(0));
+    let mut timer = PhaseTimer::new(bytes_sent.clone());
+    let config = ProtocolConfig {
+        num_servers: NUM_SERVERS,
+        ..Default::default()
+    };
+
+    // Setup: full mesh between servers plus a link from the coordinator to each
+    let mut peers: Vec<HashMap<usize, TcpLink>> = (0..NUM_SERVERS).map(|_| HashMap::new()).collect();
+    for a in 0..NUM_SERVERS {
+        for b in (a + 1)..NUM_SERVERS {
+            let (link_a, link_b) = link_pair(a, b, &bytes_sent).await?;
+            peers[a].insert(b, link_a);
+            peers[b].insert(a, link_b);
+        }
+    }
+
+    let roles = [ServerRole::First, ServerRole::Second, ServerRole::Third];
+    let mut coordinator_links = Vec::with_capacity(NUM_SERVERS);
+    let mut handles = Vec::with_capacity(NUM_SERVERS);
+    for (id, server_peers) in peers.into_iter().enumerate() {
+        let (coordinator, server_link) = link_pair(COORDINATOR_ID, id, &bytes_sent).await?;
+        coordinator_links.push(coordinator);
+        let task = ServerTask {
+            server: MultiPartyServer::new(id, roles[id].clone(), config.clone()),
+            coordinator: server_link,
+            peers: server_peers,
+            records: Vec::new(),
+        };
+        handles.push(tokio::spawn(task.run()));
+    }
+    expect_all_acks(&mut coordinator_links).await?;
+    timer.finish(ProtocolPhase::Setup);
+
+    // Sharing: the coordinator acts as the client population
+    let mut dealer = ThresholdEncryption::new(config.threshold, NUM_SERVERS)?;
+    dealer.initialize().await?;
+    let mut rng = rand::thread_rng();
+    let mut per_server: Vec<Vec<Vec<DataShare>>> = vec![Vec::with_capacity(num_records); NUM_SERVERS];
+    for _ in 0..num_records {
+        let features = (0..NUM_FEATURES).map(|_| rng.gen_range(0..100) as f64).collect();
+        let mut record: Vec<Vec<DataShare>> = vec![Vec::new(); NUM_SERVERS];
+        for share in dealer.share_data(DataPoint::new(features)).await? {
+            record[share.server_id].push(share);
+        }
+        for (server_records, shares) in per_server.iter_mut().zip(record) {
+            server_records.push(shares);
+        }
+    }
+    for (link, records) in coordinator_links.iter_mut().zip(&per_server) {
+        link.send_records(records).await?;
+    }
+    drop(per_server);
+    expect_all_acks(&mut coordinator_links).await?;
+    timer.finish(ProtocolPhase::Sharing);
+
+    expect_all_acks(&mut coordinator_links).await?;
+    timer.finish(ProtocolPhase::Shuffling);
+
+    expect_all_acks(&mut coordinator_links).await?;
+    timer.finish(ProtocolPhase::Reconstruction);
+
+    expect_all_acks(&mut coordinator_links).await?;
+    timer.finish(ProtocolPhase::Noise);
+
+    for handle in handles {
+        handle
+            .await
+            .map_err(|e| ProtocolError::server_error(format!("Server task panicked: {}", e)))??;
+    }
+
+    Ok(timer.reports)
+}
+
+fn print_report(num_records: usize, reports: &[PhaseReport]) {
+    println!("=== {} records ===", num_records);
+    println!("  {:<16} {:>12} {:>16}", "phase", "latency", "bytes");
+    for report in reports {
+        println!(
+            "  {:<16} {:>10.1}ms {:>16}",
+            format!("{:?}", report.phase),
+            report.elapsed.as_secs_f64() * 1000.0,
+            report.bytes
+        );
+    }
+
+    let total: Duration = reports.iter().map(|r| r.elapsed).sum();
+    let total_bytes: u64 = reports.iter().map(|r| r.bytes).sum();
+    println!(
+        "  {:<16} {:>10.1}ms {:>16}",
+        "total",
+        total.as_secs_f64() * 1000.0,
+        total_bytes
+    );
+    println!(
+        "  Throughput: {:.0} records/second, {:.1} bytes/record",
+        num_records as f64 / total.as_secs_f64(),
+        total_bytes as f64 / num_records as f64
+    );
+    println!();
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    let sizes: Vec<usize> = std::env::args()
+        .skip(1)
+        .map(|arg| arg.replace('_', "").parse())
+        .collect::<Result<_, _>>()?;
+    let sizes = if sizes.is_empty() { DEFAULT_SIZES.to_vec() } else { sizes };
+
+    println!("=== Three-Server Localhost Benchmark ===");
+    println!();
+    for num_records in sizes {
+        let reports = run_benchmark(num_records).await?;
+        print_report(num_records, &reports);
+    }
+
+    Ok(())
+}
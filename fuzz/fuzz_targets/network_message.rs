@@ -0,0 +1,17 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+#![no_main]
+
+use doppio::multi_party::communication::NetworkMessage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Any input must decode to a valid message or an error, and a decoded message
+    // must survive a round trip.
+    if let Ok(message) = NetworkMessage::from_bytes(data) {
+        let _ = message.age_seconds();
+        let bytes = message.to_bytes().expect("a decoded message encodes");
+        NetworkMessage::from_bytes(&bytes).expect("an encoded message decodes");
+    }
+});
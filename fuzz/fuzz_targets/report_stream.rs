@@ -0,0 +1,18 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+#![no_main]
+
+use doppio::ReportStreamReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Read chunks until the end marker or the first error.
+    if let Ok(reader) = ReportStreamReader::<_, 4>::new(data) {
+        for chunk in reader {
+            if chunk.is_err() {
+                break;
+            }
+        }
+    }
+});
@@ -0,0 +1,26 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+#![no_main]
+
+use doppio::schema::AttributeType;
+use doppio::ReportVector;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // A whole `ReportVectorProto` message, which must round-trip if it decodes.
+    if let Ok(report_vector) = ReportVector::<4>::decode_wire(data) {
+        let bytes = report_vector.encode_wire();
+        assert_eq!(ReportVector::<4>::decode_wire(&bytes), Ok(report_vector));
+    }
+
+    // A single `ReportProto` message as submitted by a client.
+    use AttributeType::*;
+    let mut report_vector = ReportVector::<1>::new(&[C2, N5(31), C10]);
+    let _ = report_vector.push_wire_report(data);
+
+    // The serde encoding, e.g. as stored by a server.
+    if let Ok(report_vector) = serde_json::from_slice::<ReportVector<2>>(data) {
+        let _ = report_vector.encode_wire();
+    }
+});
@@ -0,0 +1,27 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+#![no_main]
+
+use doppio::schema::data_points_from_csv;
+use doppio::Schema;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The first line is a JSON schema and the rest a CSV file with a header row.
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let (schema_json, csv) = text.split_once('\n').unwrap_or((text, ""));
+    let Ok(schema) = Schema::try_from(schema_json) else {
+        return;
+    };
+
+    // A parsed schema is valid, so its accessors must not panic.
+    let _ = schema.get_attr_sizes();
+    if let Ok(data) = data_points_from_csv(csv.as_bytes(), &schema) {
+        assert!(data
+            .iter()
+            .all(|point| point.features().len() == schema.len()));
+    }
+});
@@ -0,0 +1,72 @@
+/* Copyright (c) Microsoft Corporation. All rights reserved.
+ * Licensed under the MIT license. */
+
+/* C API for the doppio shuffle and DP pipeline. Build the library with
+ * `cargo build --release --features ffi`.
+ *
+ * A data batch is encoded as a little-endian uint32 row count and a uint32
+ * feature count, followed by the features as row-major little-endian doubles.
+ * Buffers returned by the library must be released with doppio_buffer_free. */
+
+#ifndef DOPPIO_H
+#define DOPPIO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+    DOPPIO_OK = 0,
+    DOPPIO_NULL_POINTER = 1,
+    DOPPIO_INVALID_INPUT = 2,
+    DOPPIO_SHUFFLE_FAILED = 3,
+    DOPPIO_MECHANISM_FAILED = 4,
+} DoppioStatus;
+
+typedef enum {
+    DOPPIO_LAPLACE = 0,
+    DOPPIO_GAUSSIAN = 1,
+    DOPPIO_EXPONENTIAL = 2,
+} DoppioMechanismType;
+
+typedef enum {
+    DOPPIO_QUERY_MEAN = 0,
+    DOPPIO_QUERY_HISTOGRAM = 1,
+} DoppioQueryType;
+
+typedef struct {
+    uint8_t *data;
+    size_t len;
+} DoppioBuffer;
+
+typedef struct DoppioShuffler DoppioShuffler;
+typedef struct DoppioMechanism DoppioMechanism;
+
+/* Message of the last error on this thread, or NULL. */
+const char *doppio_last_error(void);
+
+void doppio_buffer_free(DoppioBuffer buffer);
+
+DoppioShuffler *doppio_shuffler_new(uint32_t rounds);
+void doppio_shuffler_free(DoppioShuffler *shuffler);
+DoppioStatus doppio_shuffle(DoppioShuffler *shuffler, const uint8_t *input, size_t input_len,
+                            DoppioBuffer *output);
+
+/* Returns NULL for an unknown mechanism type or an invalid budget. */
+DoppioMechanism *doppio_mechanism_new(uint32_t mechanism_type, double epsilon, double delta);
+void doppio_mechanism_free(DoppioMechanism *mechanism);
+
+/* The result is a uint32 count followed by that many doubles. */
+DoppioStatus doppio_apply_mechanism(const DoppioMechanism *mechanism, const uint8_t *input,
+                                    size_t input_len, uint32_t query_type,
+                                    const uint32_t *features, size_t feature_count,
+                                    DoppioBuffer *output);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DOPPIO_H */
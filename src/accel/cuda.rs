@@ -0,0 +1,178 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::{AccelError, FieldBackend};
+use crate::field::PrimeField;
+use cudarc::driver::{CudaDevice, CudaFunction, LaunchAsync, LaunchConfig};
+use cudarc::nvrtc::compile_ptx;
+use std::fmt;
+use std::sync::Arc;
+
+/// Name of the module holding the kernels.
+const MODULE: &str = "doppio_field";
+
+/// Kernels, compiled with NVRTC when the backend is created.
+///
+/// Elements are reduced, so a modular addition overflows at most once; the
+/// multiplication doubles and adds to stay within 64 bits for any modulus.
+const KERNELS: &str = r#"
+typedef unsigned long long u64;
+
+__device__ u64 add_mod(u64 a, u64 b, u64 p) {
+    u64 r = a + b;
+    if (r < a || r >= p) {
+        r -= p;
+    }
+    return r;
+}
+
+__device__ u64 mul_mod(u64 a, u64 b, u64 p) {
+    u64 r = 0;
+    while (b != 0) {
+        if (b & 1) {
+            r = add_mod(r, a, p);
+        }
+        a = add_mod(a, a, p);
+        b >>= 1;
+    }
+    return r;
+}
+
+extern "C" __global__ void permute(u64 *out, const u64 *values, const u64 *permutation, u64 n) {
+    u64 i = (u64)blockIdx.x * blockDim.x + threadIdx.x;
+    if (i < n) {
+        out[permutation[i]] = values[i];
+    }
+}
+
+extern "C" __global__ void mat_vec(
+    u64 *out, const u64 *matrix, const u64 *vector, u64 rows, u64 cols, u64 p) {
+    u64 row = (u64)blockIdx.x * blockDim.x + threadIdx.x;
+    if (row < rows) {
+        u64 acc = 0;
+        for (u64 col = 0; col < cols; col++) {
+            acc = add_mod(acc, mul_mod(matrix[row * cols + col], vector[col], p), p);
+        }
+        out[row] = acc;
+    }
+}
+"#;
+
+fn device_error(error: impl fmt::Display) -> AccelError {
+    AccelError::Device(error.to_string())
+}
+
+/// Bulk field operations on an NVIDIA GPU through CUDA.
+pub struct CudaBackend {
+    device: Arc<CudaDevice>,
+    ordinal: usize,
+}
+
+impl fmt::Debug for CudaBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CudaBackend")
+            .field("ordinal", &self.ordinal)
+            .finish()
+    }
+}
+
+impl CudaBackend {
+    /// Open the GPU with the given ordinal and compile the kernels.
+    pub fn new(ordinal: usize) -> Result<Self, AccelError> {
+        let device = CudaDevice::new(ordinal).map_err(device_error)?;
+        let ptx = compile_ptx(KERNELS).map_err(device_error)?;
+        device
+            .load_ptx(ptx, MODULE, &["permute", "mat_vec"])
+            .map_err(device_error)?;
+        Ok(Self { device, ordinal })
+    }
+
+    fn function(&self, name: &str) -> Result<CudaFunction, AccelError> {
+        self.device
+            .get_func(MODULE, name)
+            .ok_or_else(|| AccelError::Device(format!("Kernel {} is not loaded", name)))
+    }
+
+    /// Launch configuration with one thread per element.
+    fn launch_config(elements: usize) -> Result<LaunchConfig, AccelError> {
+        let elements = u32::try_from(elements)
+            .map_err(|_| AccelError::Device(format!("{} elements exceed one launch", elements)))?;
+        Ok(LaunchConfig::for_num_elems(elements))
+    }
+}
+
+impl FieldBackend for CudaBackend {
+    fn name(&self) -> &'static str {
+        "cuda"
+    }
+
+    fn permute(&self, values: &[u64], permutation: &[usize]) -> Result<Vec<u64>, AccelError> {
+        if values.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let config = Self::launch_config(values.len())?;
+        let permutation = permutation.iter().map(|&i| i as u64).collect::<Vec<_>>();
+        let values_device = self.device.htod_sync_copy(values).map_err(device_error)?;
+        let permutation_device = self
+            .device
+            .htod_sync_copy(&permutation)
+            .map_err(device_error)?;
+        let mut out = self
+            .device
+            .alloc_zeros::<u64>(values.len())
+            .map_err(device_error)?;
+
+        // SAFETY: the argument types match the kernel signature and every
+        // permutation entry is below `n`, the length of `out`.
+        unsafe {
+            self.function("permute")?.launch(
+                config,
+                (
+                    &mut out,
+                    &values_device,
+                    &permutation_device,
+                    values.len() as u64,
+                ),
+            )
+        }
+        .map_err(device_error)?;
+        self.device.dtoh_sync_copy(&out).map_err(device_error)
+    }
+
+    fn mat_vec(
+        &self,
+        field: &PrimeField,
+        matrix: &[u64],
+        cols: usize,
+        vector: &[u64],
+    ) -> Result<Vec<u64>, AccelError> {
+        if cols == 0 || matrix.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let rows = matrix.len() / cols;
+        let config = Self::launch_config(rows)?;
+        let matrix_device = self.device.htod_sync_copy(matrix).map_err(device_error)?;
+        let vector_device = self.device.htod_sync_copy(vector).map_err(device_error)?;
+        let mut out = self.device.alloc_zeros::<u64>(rows).map_err(device_error)?;
+
+        // SAFETY: the argument types match the kernel signature, `matrix` holds
+        // `rows * cols` elements, `vector` holds `cols`, and `out` holds `rows`.
+        unsafe {
+            self.function("mat_vec")?.launch(
+                config,
+                (
+                    &mut out,
+                    &matrix_device,
+                    &vector_device,
+                    rows as u64,
+                    cols as u64,
+                    field.modulus(),
+                ),
+            )
+        }
+        .map_err(device_error)?;
+        self.device.dtoh_sync_copy(&out).map_err(device_error)
+    }
+}
@@ -0,0 +1,338 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Bulk field operations on an optional GPU.
+//!
+//! The online phase of a million-user epoch permutes share vectors and multiplies
+//! large matrices by vectors over the field. A `FieldBackend` runs these bulk
+//! operations; `CpuBackend` is always available and `CudaBackend`, behind the
+//! `cuda` feature, runs them on an NVIDIA GPU. An `Accelerator` sends large
+//! inputs to the GPU and falls back to the CPU when there is no GPU or it fails.
+
+#[cfg(feature = "cuda")]
+pub mod cuda;
+
+#[cfg(feature = "cuda")]
+pub use cuda::CudaBackend;
+
+use crate::field::{FieldError, PrimeField};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Errors that can occur in bulk field operations.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AccelError {
+    /// The permutation is not a bijection on the values
+    #[error("Invalid permutation: {0}")]
+    InvalidPermutation(String),
+
+    /// The matrix and vector dimensions do not match
+    #[error("Dimension mismatch: {0}")]
+    DimensionMismatch(String),
+
+    /// An input is not a field element
+    #[error("Field error: {0}")]
+    Field(#[from] FieldError),
+
+    /// The device failed
+    #[error("Device error: {0}")]
+    Device(String),
+}
+
+/// Backend for bulk operations over a prime field.
+///
+/// Callers validate the inputs with `check_permutation` and `check_mat_vec`
+/// first, so backends may assume a permutation is a bijection, the dimensions
+/// match, and all elements are reduced.
+pub trait FieldBackend: fmt::Debug + Send + Sync {
+    /// Name of the backend, e.g. for logs
+    fn name(&self) -> &'static str;
+
+    /// Move `values[i]` to position `permutation[i]`.
+    fn permute(&self, values: &[u64], permutation: &[usize]) -> Result<Vec<u64>, AccelError>;
+
+    /// Multiply the row-major `matrix` with `cols` columns by `vector`.
+    fn mat_vec(
+        &self,
+        field: &PrimeField,
+        matrix: &[u64],
+        cols: usize,
+        vector: &[u64],
+    ) -> Result<Vec<u64>, AccelError>;
+}
+
+/// Check that `permutation` is a bijection on `len` values.
+pub fn check_permutation(len: usize, permutation: &[usize]) -> Result<(), AccelError> {
+    if permutation.len() != len {
+        return Err(AccelError::InvalidPermutation(format!(
+            "Permutation of length {} applied to {} values",
+            permutation.len(),
+            len
+        )));
+    }
+
+    let mut seen = vec![false; len];
+    for &position in permutation {
+        if position >= len || std::mem::replace(&mut seen[position], true) {
+            return Err(AccelError::InvalidPermutation(format!(
+                "Position {} is out of range or repeated",
+                position
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Check that a matrix-vector product is well formed and its inputs are reduced.
+pub fn check_mat_vec(
+    field: &PrimeField,
+    matrix: &[u64],
+    cols: usize,
+    vector: &[u64],
+) -> Result<(), AccelError> {
+    let well_formed = match cols {
+        0 => matrix.is_empty() && vector.is_empty(),
+        cols => vector.len() == cols && matrix.chunks_exact(cols).remainder().is_empty(),
+    };
+    if !well_formed {
+        return Err(AccelError::DimensionMismatch(format!(
+            "{} matrix elements in rows of {} multiplied by {} elements",
+            matrix.len(),
+            cols,
+            vector.len()
+        )));
+    }
+
+    for &element in matrix.iter().chain(vector) {
+        field.check(element)?;
+    }
+    Ok(())
+}
+
+/// Bulk field operations on the CPU.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuBackend;
+
+impl FieldBackend for CpuBackend {
+    fn name(&self) -> &'static str {
+        "cpu"
+    }
+
+    fn permute(&self, values: &[u64], permutation: &[usize]) -> Result<Vec<u64>, AccelError> {
+        let mut permuted = vec![0; values.len()];
+        for (&value, &position) in values.iter().zip(permutation) {
+            permuted[position] = value;
+        }
+        Ok(permuted)
+    }
+
+    fn mat_vec(
+        &self,
+        field: &PrimeField,
+        matrix: &[u64],
+        cols: usize,
+        vector: &[u64],
+    ) -> Result<Vec<u64>, AccelError> {
+        if cols == 0 {
+            return Ok(Vec::new());
+        }
+
+        Ok(matrix
+            .chunks(cols)
+            .map(|row| {
+                row.iter()
+                    .zip(vector)
+                    .fold(0, |acc, (&a, &b)| field.add(acc, field.mul(a, b)))
+            })
+            .collect())
+    }
+}
+
+/// Smallest input, in elements, sent to the GPU by default.
+///
+/// Below this, copying to and from the device costs more than it saves.
+pub const DEFAULT_MIN_GPU_ELEMENTS: usize = 1 << 16;
+
+/// Dispatches bulk field operations to a GPU, falling back to the CPU.
+///
+/// Inputs smaller than `min_gpu_elements` always run on the CPU. If the GPU
+/// fails, the operation is retried on the CPU, so callers only see input errors.
+#[derive(Debug, Clone)]
+pub struct Accelerator {
+    gpu: Option<Arc<dyn FieldBackend>>,
+    cpu: CpuBackend,
+    min_gpu_elements: usize,
+}
+
+impl Default for Accelerator {
+    fn default() -> Self {
+        Self::cpu()
+    }
+}
+
+impl Accelerator {
+    /// Create an accelerator that only uses the CPU.
+    pub fn cpu() -> Self {
+        Self {
+            gpu: None,
+            cpu: CpuBackend,
+            min_gpu_elements: DEFAULT_MIN_GPU_ELEMENTS,
+        }
+    }
+
+    /// Create an accelerator on the given GPU backend.
+    pub fn with_gpu(gpu: Arc<dyn FieldBackend>) -> Self {
+        Self {
+            gpu: Some(gpu),
+            ..Self::cpu()
+        }
+    }
+
+    /// Create an accelerator on the first GPU if the `cuda` feature is enabled
+    /// and a device is available, and on the CPU otherwise.
+    pub fn auto() -> Self {
+        #[cfg(feature = "cuda")]
+        match CudaBackend::new(0) {
+            Ok(gpu) => return Self::with_gpu(Arc::new(gpu)),
+            Err(error) => log::warn!("No usable GPU, using the CPU: {}", error),
+        }
+        Self::cpu()
+    }
+
+    /// Set the smallest input, in elements, sent to the GPU.
+    pub fn with_min_gpu_elements(mut self, min_gpu_elements: usize) -> Self {
+        self.min_gpu_elements = min_gpu_elements;
+        self
+    }
+
+    /// Return the name of the backend used for large inputs.
+    pub fn backend_name(&self) -> &'static str {
+        self.gpu.as_ref().map_or(self.cpu.name(), |gpu| gpu.name())
+    }
+
+    /// Return the GPU backend for inputs of `elements` elements, if any.
+    fn gpu_for(&self, elements: usize) -> Option<&dyn FieldBackend> {
+        self.gpu
+            .as_deref()
+            .filter(|_| elements >= self.min_gpu_elements)
+    }
+
+    /// Move `values[i]` to position `permutation[i]`.
+    pub fn permute(&self, values: &[u64], permutation: &[usize]) -> Result<Vec<u64>, AccelError> {
+        check_permutation(values.len(), permutation)?;
+
+        if let Some(gpu) = self.gpu_for(values.len()) {
+            match gpu.permute(values, permutation) {
+                Ok(permuted) => return Ok(permuted),
+                Err(error) => log::warn!(
+                    "{} permutation failed, using the CPU: {}",
+                    gpu.name(),
+                    error
+                ),
+            }
+        }
+        self.cpu.permute(values, permutation)
+    }
+
+    /// Multiply the row-major `matrix` with `cols` columns by `vector`.
+    pub fn mat_vec(
+        &self,
+        field: &PrimeField,
+        matrix: &[u64],
+        cols: usize,
+        vector: &[u64],
+    ) -> Result<Vec<u64>, AccelError> {
+        check_mat_vec(field, matrix, cols, vector)?;
+
+        if let Some(gpu) = self.gpu_for(matrix.len()) {
+            match gpu.mat_vec(field, matrix, cols, vector) {
+                Ok(product) => return Ok(product),
+                Err(error) => log::warn!(
+                    "{} matrix-vector product failed, using the CPU: {}",
+                    gpu.name(),
+                    error
+                ),
+            }
+        }
+        self.cpu.mat_vec(field, matrix, cols, vector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A GPU backend whose device always fails.
+    #[derive(Debug)]
+    struct FailingBackend;
+
+    impl FieldBackend for FailingBackend {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        fn permute(&self, _: &[u64], _: &[usize]) -> Result<Vec<u64>, AccelError> {
+            Err(AccelError::Device("Device lost".to_string()))
+        }
+
+        fn mat_vec(
+            &self,
+            _: &PrimeField,
+            _: &[u64],
+            _: usize,
+            _: &[u64],
+        ) -> Result<Vec<u64>, AccelError> {
+            Err(AccelError::Device("Device lost".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_accelerator() {
+        let field = PrimeField::new(97).unwrap();
+        for accelerator in [
+            Accelerator::cpu(),
+            Accelerator::with_gpu(Arc::new(FailingBackend)).with_min_gpu_elements(0),
+        ] {
+            assert_eq!(
+                accelerator.permute(&[10, 20, 30], &[2, 0, 1]).unwrap(),
+                vec![20, 30, 10]
+            );
+            assert!(accelerator.permute(&[10, 20, 30], &[2, 2, 1]).is_err());
+
+            let matrix = [1, 2, 3, 96, 50, 50];
+            assert_eq!(
+                accelerator.mat_vec(&field, &matrix, 3, &[1, 1, 1]).unwrap(),
+                vec![6, 2]
+            );
+            assert!(accelerator.mat_vec(&field, &matrix, 2, &[1, 1, 1]).is_err());
+            assert!(accelerator
+                .mat_vec(&field, &matrix, 3, &[1, 1, 97])
+                .is_err());
+        }
+        assert_eq!(Accelerator::cpu().backend_name(), "cpu");
+    }
+
+    #[cfg(feature = "cuda")]
+    #[test]
+    #[ignore = "needs a CUDA device"]
+    fn test_cuda_backend_matches_cpu() {
+        let field = PrimeField::new(0xFFFFFFFFFFFFFFC5).unwrap();
+        let gpu = CudaBackend::new(0).unwrap();
+        let values = (0..1000u64)
+            .map(|i| field.reduce(i.wrapping_mul(0x9E3779B97F4A7C15)))
+            .collect::<Vec<_>>();
+        let permutation = (0..1000usize).map(|i| (i * 7) % 1000).collect::<Vec<_>>();
+
+        assert_eq!(
+            gpu.permute(&values, &permutation).unwrap(),
+            CpuBackend.permute(&values, &permutation).unwrap()
+        );
+        assert_eq!(
+            gpu.mat_vec(&field, &values, 100, &values[..100]).unwrap(),
+            CpuBackend
+                .mat_vec(&field, &values, 100, &values[..100])
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,342 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Black-box auditing of the privacy of DP mechanisms and shufflers.
+//!
+//! An `Auditor` runs a membership-inference attack against any `AuditTarget`: the
+//! target answers a query many times on each of two neighboring datasets, which
+//! differ in one canary record, and a distinguisher guesses from each answer
+//! whether the canary was present. The false positive and false negative rates of
+//! any distinguisher bound epsilon from below, since an (epsilon, delta)-DP release
+//! forces `FPR + e^epsilon * FNR >= 1 - delta`. The auditor converts Clopper-Pearson
+//! upper bounds on both rates into a lower bound on epsilon that holds with the
+//! configured confidence, and flags the target when that bound exceeds the claimed
+//! epsilon. A passing audit does not prove the claim; a failing one disproves it.
+
+use crate::dp::{DPError, DPMechanism};
+use crate::schema::{DataPoint, Query, QueryType};
+use crate::shuffle::{ShuffleError, Shuffler};
+use serde::{Deserialize, Serialize};
+use statrs::function::beta::beta_reg;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AuditError {
+    #[error("Invalid audit configuration: {0}")]
+    InvalidConfig(String),
+    #[error("DP error: {0}")]
+    DP(#[from] DPError),
+    #[error("Shuffle error: {0}")]
+    Shuffle(#[from] ShuffleError),
+}
+
+/// A randomized release that can be audited as a black box.
+pub trait AuditTarget {
+    /// Answer `query` on `data` with fresh randomness.
+    fn release(&mut self, data: Vec<DataPoint>, query: &Query) -> Result<Vec<f64>, AuditError>;
+}
+
+impl AuditTarget for DPMechanism {
+    fn release(&mut self, data: Vec<DataPoint>, query: &Query) -> Result<Vec<f64>, AuditError> {
+        let result = self.apply_mechanism(data, query.clone())?;
+        Ok(result.values().to_vec())
+    }
+}
+
+impl AuditTarget for Shuffler {
+    fn release(&mut self, data: Vec<DataPoint>, query: &Query) -> Result<Vec<f64>, AuditError> {
+        let result = self.process_query(query.clone(), data)?;
+        Ok(result.values().to_vec())
+    }
+}
+
+/// Parameters of an audit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// Epsilon the target claims to satisfy.
+    pub claimed_epsilon: f64,
+    /// Delta the target claims to satisfy.
+    pub claimed_delta: f64,
+    /// Number of releases on each of the two datasets. Half of them train the
+    /// distinguisher and the other half measure it.
+    pub trials: usize,
+    /// Probability that the reported lower bound holds.
+    pub confidence: f64,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            claimed_epsilon: 1.0,
+            claimed_delta: 1e-5,
+            trials: 1000,
+            confidence: 0.95,
+        }
+    }
+}
+
+/// Outcome of an audit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditReport {
+    pub claimed_epsilon: f64,
+    pub claimed_delta: f64,
+    /// Lower bound on the epsilon of the target, holding with probability
+    /// `confidence`.
+    pub epsilon_lower_bound: f64,
+    pub confidence: f64,
+    /// Measured false positive rate of the distinguisher: it claimed the canary
+    /// was present when it was not.
+    pub false_positive_rate: f64,
+    /// Measured false negative rate of the distinguisher.
+    pub false_negative_rate: f64,
+}
+
+impl AuditReport {
+    /// Whether the measured leakage disproves the claimed epsilon.
+    pub fn exceeds_claim(&self) -> bool {
+        self.epsilon_lower_bound > self.claimed_epsilon
+    }
+}
+
+/// Runs membership-inference audits.
+#[derive(Clone, Debug)]
+pub struct Auditor {
+    config: AuditConfig,
+}
+
+impl Auditor {
+    pub fn new(config: AuditConfig) -> Result<Self, AuditError> {
+        if !(config.claimed_epsilon >= 0.0 && config.claimed_epsilon.is_finite()) {
+            return Err(AuditError::InvalidConfig(
+                "claimed epsilon must be non-negative".to_string(),
+            ));
+        }
+        if !(0.0..1.0).contains(&config.claimed_delta) {
+            return Err(AuditError::InvalidConfig(
+                "claimed delta must be in [0, 1)".to_string(),
+            ));
+        }
+        if config.trials < 4 {
+            return Err(AuditError::InvalidConfig(
+                "at least 4 trials are needed".to_string(),
+            ));
+        }
+        if !(config.confidence > 0.0 && config.confidence < 1.0) {
+            return Err(AuditError::InvalidConfig(
+                "confidence must be in (0, 1)".to_string(),
+            ));
+        }
+
+        Ok(Self { config })
+    }
+
+    pub fn config(&self) -> &AuditConfig {
+        &self.config
+    }
+
+    /// Audit `target` on `query` over the neighboring datasets `without` and `with`,
+    /// where `with` contains the canary record.
+    pub fn audit<T: AuditTarget + ?Sized>(
+        &self,
+        target: &mut T,
+        without: &[DataPoint],
+        with: &[DataPoint],
+        query: &Query,
+    ) -> Result<AuditReport, AuditError> {
+        let mut outputs_without = Vec::with_capacity(self.config.trials);
+        let mut outputs_with = Vec::with_capacity(self.config.trials);
+        for _ in 0..self.config.trials {
+            outputs_without.push(target.release(without.to_vec(), query)?);
+            outputs_with.push(target.release(with.to_vec(), query)?);
+        }
+
+        // Histogram counts come without their values, so compare them by rank.
+        if query.query_type == QueryType::Histogram {
+            for output in outputs_without.iter_mut().chain(outputs_with.iter_mut()) {
+                output.sort_unstable_by(|a, b| b.total_cmp(a));
+            }
+        }
+
+        let half = self.config.trials / 2;
+        let (train_without, test_without) = outputs_without.split_at(half);
+        let (train_with, test_with) = outputs_with.split_at(half);
+
+        // The distinguisher projects an output on the difference of the mean
+        // outputs, and claims the canary is present above a threshold. Both are
+        // fitted on the training half only, so the test half measures it fairly.
+        let direction = mean_difference(train_without, train_with);
+        let score = |outputs: &[Vec<f64>]| {
+            outputs
+                .iter()
+                .map(|output| dot(output, &direction))
+                .collect::<Vec<_>>()
+        };
+        let threshold = self.best_threshold(&score(train_without), &score(train_with));
+
+        let (scores_without, scores_with) = (score(test_without), score(test_with));
+        let false_positives = scores_without.iter().filter(|&&s| s >= threshold).count();
+        let false_negatives = scores_with.iter().filter(|&&s| s < threshold).count();
+
+        // Split the error probability between the two one-sided bounds.
+        let alpha = (1.0 - self.config.confidence) / 2.0;
+        let fpr_upper = clopper_pearson_upper(false_positives, scores_without.len(), alpha);
+        let fnr_upper = clopper_pearson_upper(false_negatives, scores_with.len(), alpha);
+
+        Ok(AuditReport {
+            claimed_epsilon: self.config.claimed_epsilon,
+            claimed_delta: self.config.claimed_delta,
+            epsilon_lower_bound: epsilon_bound(fpr_upper, fnr_upper, self.config.claimed_delta),
+            confidence: self.config.confidence,
+            false_positive_rate: false_positives as f64 / scores_without.len() as f64,
+            false_negative_rate: false_negatives as f64 / scores_with.len() as f64,
+        })
+    }
+
+    /// Threshold that maximizes the epsilon estimated from add-one smoothed rates.
+    fn best_threshold(&self, scores_without: &[f64], scores_with: &[f64]) -> f64 {
+        let rate = |count: usize, total: usize| (count + 1) as f64 / (total + 2) as f64;
+        scores_without
+            .iter()
+            .chain(scores_with)
+            .copied()
+            .map(|threshold| {
+                let false_positives = scores_without.iter().filter(|&&s| s >= threshold).count();
+                let false_negatives = scores_with.iter().filter(|&&s| s < threshold).count();
+                let estimate = epsilon_bound(
+                    rate(false_positives, scores_without.len()),
+                    rate(false_negatives, scores_with.len()),
+                    self.config.claimed_delta,
+                );
+                (threshold, estimate)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(0.0, |(threshold, _)| threshold)
+    }
+}
+
+/// Return `canary` added to `data` as its last record, giving the neighboring
+/// pair `(data, data with canary)` to audit.
+pub fn with_canary(data: &[DataPoint], canary: DataPoint) -> (Vec<DataPoint>, Vec<DataPoint>) {
+    let mut with = data.to_vec();
+    with.push(canary);
+    (data.to_vec(), with)
+}
+
+/// Lower bound on epsilon implied by error rates of a distinguisher.
+fn epsilon_bound(fpr: f64, fnr: f64, delta: f64) -> f64 {
+    let bound = |a: f64, b: f64| ((1.0 - delta - a) / b).ln();
+    bound(fpr, fnr).max(bound(fnr, fpr)).max(0.0)
+}
+
+fn mean_difference(without: &[Vec<f64>], with: &[Vec<f64>]) -> Vec<f64> {
+    let len = without.iter().chain(with).map(Vec::len).max().unwrap_or(0);
+    let mean = |outputs: &[Vec<f64>]| {
+        let mut sums = vec![0.0; len];
+        for output in outputs {
+            for (sum, value) in sums.iter_mut().zip(output) {
+                *sum += value;
+            }
+        }
+        sums.iter()
+            .map(|sum| sum / outputs.len().max(1) as f64)
+            .collect::<Vec<_>>()
+    };
+
+    mean(with)
+        .iter()
+        .zip(mean(without))
+        .map(|(a, b)| a - b)
+        .collect()
+}
+
+/// Dot product, treating missing values of the shorter vector as zero.
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Upper Clopper-Pearson bound on a probability after `successes` in `trials`,
+/// exceeded with probability at most `alpha`.
+fn clopper_pearson_upper(successes: usize, trials: usize, alpha: f64) -> f64 {
+    if successes >= trials {
+        return 1.0;
+    }
+
+    // The bound is the `1 - alpha` quantile of Beta(successes + 1, trials - successes),
+    // found by bisection on its CDF.
+    let (a, b) = ((successes + 1) as f64, (trials - successes) as f64);
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..60 {
+        let mid = (low + high) / 2.0;
+        if beta_reg(a, b, mid) < 1.0 - alpha {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    high
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arith::PrivacyBudget;
+    use crate::dp::{DPConfig, MechanismType};
+
+    #[test]
+    fn test_clopper_pearson() {
+        // With no successes the bound is 1 - alpha^(1 / trials).
+        let upper = clopper_pearson_upper(0, 100, 0.05);
+        assert!((upper - (1.0 - 0.05f64.powf(0.01))).abs() < 1e-9);
+        assert_eq!(clopper_pearson_upper(10, 10, 0.05), 1.0);
+        assert!(clopper_pearson_upper(50, 100, 0.05) > 0.5);
+        assert_eq!(epsilon_bound(0.5, 0.5, 0.0), 0.0);
+        assert!((epsilon_bound(0.1, 0.3, 0.0) - 3f64.ln()).abs() < 1e-12);
+    }
+
+    fn laplace(epsilon: f64) -> DPMechanism {
+        DPMechanism::new(DPConfig {
+            privacy_budget: PrivacyBudget::new(epsilon, 1e-5),
+            mechanism_type: MechanismType::Laplace,
+        })
+    }
+
+    #[test]
+    fn test_audit() {
+        let query = Query::new(QueryType::Mean, vec!["feature1".to_string()]);
+        let config = AuditConfig {
+            claimed_epsilon: 1.0,
+            trials: 2000,
+            confidence: 0.999,
+            ..Default::default()
+        };
+        let auditor = Auditor::new(config).unwrap();
+
+        // A mean of one record moves by the full change of its value, which the
+        // mechanism's sensitivity of one covers.
+        let report = auditor
+            .audit(
+                &mut laplace(1.0),
+                &[DataPoint::new(vec![0.0])],
+                &[DataPoint::new(vec![1.0])],
+                &query,
+            )
+            .unwrap();
+        assert!(!report.exceeds_claim());
+
+        // A canary of 100 moves the mean far beyond the sensitivity of one.
+        let (without, with) =
+            with_canary(&[DataPoint::new(vec![0.0])], DataPoint::new(vec![100.0]));
+        let report = auditor
+            .audit(&mut laplace(1.0), &without, &with, &query)
+            .unwrap();
+        assert!(report.exceeds_claim());
+        assert!(report.false_positive_rate < 0.01 && report.false_negative_rate < 0.01);
+
+        assert!(Auditor::new(AuditConfig {
+            confidence: 1.0,
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use doppio::arith::PrivacyBudget;
+use doppio::dp::calibration;
+use doppio::dp::{DPConfig, DPMechanism, MechanismType};
+use doppio::schema::{data_points_from_csv, DataPoint, Query, QueryType};
+use doppio::shuffle::{ShuffleConfig, Shuffler};
+use doppio::Schema;
+use serde_json::json;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Shuffle and query CSV data with differential privacy.
+///
+/// The schema file is a JSON schema such as `[["age","c7"],["income",{"n20":65537}]]`.
+/// Its attribute names select the CSV columns, which must have a header row.
+#[derive(Parser)]
+#[command(name = "doppio", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Shuffle the rows of a CSV file
+    Shuffle {
+        /// JSON schema file
+        #[arg(short, long)]
+        schema: PathBuf,
+        /// CSV file to shuffle
+        #[arg(short, long)]
+        input: PathBuf,
+        /// CSV file for the shuffled rows with the schema columns
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Number of shuffle rounds
+        #[arg(long, default_value_t = 1)]
+        rounds: usize,
+    },
+    /// Run a DP query on a CSV file and print the noisy result
+    Query {
+        /// JSON schema file
+        #[arg(short, long)]
+        schema: PathBuf,
+        /// CSV file to query
+        #[arg(short, long)]
+        input: PathBuf,
+        /// Query type
+        #[arg(short, long, value_enum)]
+        query: QueryKind,
+        /// Attributes to query, separated by commas
+        #[arg(short, long, value_delimiter = ',', required = true)]
+        attrs: Vec<String>,
+        #[command(flatten)]
+        budget: BudgetArgs,
+    },
+    /// Print the noise scale a mechanism uses for a sensitivity and budget
+    Calibrate {
+        /// Sensitivity of the query
+        #[arg(long, default_value_t = 1.0)]
+        sensitivity: f64,
+        #[command(flatten)]
+        budget: BudgetArgs,
+    },
+}
+
+/// Privacy budget and mechanism arguments
+#[derive(clap::Args)]
+struct BudgetArgs {
+    /// Privacy parameter epsilon
+    #[arg(short, long)]
+    epsilon: f64,
+    /// Privacy parameter delta
+    #[arg(short, long, default_value_t = 1e-5)]
+    delta: f64,
+    /// DP mechanism
+    #[arg(short, long, value_enum, default_value_t = Mechanism::Laplace)]
+    mechanism: Mechanism,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum QueryKind {
+    Mean,
+    Histogram,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Mechanism {
+    Laplace,
+    Gaussian,
+    Exponential,
+}
+
+impl From<Mechanism> for MechanismType {
+    fn from(mechanism: Mechanism) -> Self {
+        match mechanism {
+            Mechanism::Laplace => MechanismType::Laplace,
+            Mechanism::Gaussian => MechanismType::Gaussian,
+            Mechanism::Exponential => MechanismType::Exponential,
+        }
+    }
+}
+
+impl BudgetArgs {
+    /// Validate the budget and build a DP configuration
+    fn dp_config(&self) -> Result<DPConfig, Box<dyn Error>> {
+        if self.epsilon.is_nan() || self.epsilon <= 0.0 || !(0.0..1.0).contains(&self.delta) {
+            return Err(
+                format!("Invalid privacy budget ({}, {})", self.epsilon, self.delta).into(),
+            );
+        }
+
+        Ok(DPConfig {
+            privacy_budget: PrivacyBudget::new(self.epsilon, self.delta),
+            mechanism_type: self.mechanism.into(),
+        })
+    }
+}
+
+/// Read a JSON schema file
+fn read_schema(path: &Path) -> Result<Schema, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Schema::try_from(text.as_str()).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// Read the schema columns of a CSV file
+fn read_data(path: &Path, schema: &Schema) -> Result<Vec<DataPoint>, Box<dyn Error>> {
+    let file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    data_points_from_csv(file, schema).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// Write `DataPoint`s as CSV with the schema columns
+fn write_data(path: &Path, schema: &Schema, data: &[DataPoint]) -> Result<(), Box<dyn Error>> {
+    let mut csv = csv::Writer::from_path(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    csv.write_record(schema.get_attr_names())?;
+    for point in data {
+        csv.write_record(point.features().iter().map(f64::to_string))?;
+    }
+    csv.flush()?;
+
+    Ok(())
+}
+
+/// Build a query over named attributes; the mechanisms address features by position
+fn build_query(
+    schema: &Schema,
+    kind: QueryKind,
+    attrs: &[String],
+) -> Result<Query, Box<dyn Error>> {
+    let query_type = match kind {
+        QueryKind::Mean => QueryType::Mean,
+        QueryKind::Histogram => QueryType::Histogram,
+    };
+    let features = attrs
+        .iter()
+        .map(|attr_name| {
+            schema
+                .get_attr_index(attr_name)
+                .map(|index| format!("feature{}", index + 1))
+                .ok_or_else(|| format!("Unknown attribute {}", attr_name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Query::new(query_type, features))
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Shuffle {
+            schema,
+            input,
+            output,
+            rounds,
+        } => {
+            let schema = read_schema(&schema)?;
+            let data = read_data(&input, &schema)?;
+            let config = ShuffleConfig::builder()
+                .schema(schema.clone())
+                .shuffle_rounds(rounds.max(1))
+                .build();
+            let shuffled = Shuffler::new(config).shuffle_data(data)?;
+            write_data(&output, &schema, &shuffled)?;
+            println!(
+                "Wrote {} shuffled rows to {}",
+                shuffled.len(),
+                output.display()
+            );
+        }
+        Command::Query {
+            schema,
+            input,
+            query,
+            attrs,
+            budget,
+        } => {
+            let schema = read_schema(&schema)?;
+            let data = read_data(&input, &schema)?;
+            let query = build_query(&schema, query, &attrs)?;
+            let result = DPMechanism::new(budget.dp_config()?).apply_mechanism(data, query)?;
+            let output = json!({
+                "attrs": attrs,
+                "values": result.values(),
+                "budget_spent": { "epsilon": budget.epsilon, "delta": budget.delta },
+            });
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        Command::Calibrate {
+            sensitivity,
+            budget,
+        } => {
+            budget.dp_config()?;
+            let output = match budget.mechanism {
+                Mechanism::Laplace | Mechanism::Exponential => json!({
+                    "scale": calibration::laplace_scale(sensitivity, budget.epsilon),
+                }),
+                Mechanism::Gaussian => json!({
+                    "sigma": calibration::gaussian_sigma(sensitivity, budget.epsilon, budget.delta),
+                }),
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_data_and_query() {
+        let schema = Schema::try_from(r#"[["age","c7"],["income",{"n20":65537}]]"#).unwrap();
+        let csv = "id,income,age\n0, 52, 31\n1, 7, 45\n";
+        let data = data_points_from_csv(csv.as_bytes(), &schema).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[1].features(), &[45.0, 7.0]);
+
+        let query = build_query(&schema, QueryKind::Mean, &["income".to_string()]).unwrap();
+        assert_eq!(query.features, vec!["feature2".to_string()]);
+        assert!(build_query(&schema, QueryKind::Mean, &["height".to_string()]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT license.
 
+use crate::report::compress::BatchCodec;
 use crate::report::report_handler::ReportHandler;
 use crate::report::report_vector::ReportVector;
 use crate::schema::{Attribute, Schema};
@@ -107,6 +108,22 @@ pub fn create_report_shares<const REPORT_U32_SIZE: usize>(
     ))
 }
 
+/// Create report shares as in `create_report_shares` and encode each share as a
+/// compressed batch for transport to its server. The shares are uniformly random,
+/// so they are only bit-packed.
+pub fn create_compressed_report_shares<const REPORT_U32_SIZE: usize>(
+    rng: &mut (impl Rng + ?Sized),
+    input_json: &str,
+) -> Result<(Schema, Vec<u8>, Vec<u8>), String> {
+    let (schema, _, report_vector_share1, report_vector_share2) =
+        create_report_shares::<REPORT_U32_SIZE>(rng, input_json)?;
+    Ok((
+        schema,
+        report_vector_share1.compress(BatchCodec::packed()),
+        report_vector_share2.compress(BatchCodec::packed()),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -284,4 +301,31 @@ mod tests {
             report_handler.create_report(&[1, 3, 5, 16384])
         );
     }
+
+    #[test]
+    fn test_create_compressed_report_shares() {
+        let json = r#"
+            {
+                "schema":[["attr1","c2"],["attr2",{"n3":6}]],
+                "reports":[
+                    {"attributes":[{"c2":2},{"n3":[5,6]}]},
+                    {"attributes":[{"c2":0},{"n3":[0,6]}]}
+                ]
+            }"#;
+
+        let mut rng = OsRng;
+        let (_, bytes1, bytes2) = create_compressed_report_shares::<1>(&mut rng, json).unwrap();
+        let mut share1 = ReportVector::<1>::decompress(&bytes1).unwrap();
+        share1.reveal(ReportVector::<1>::decompress(&bytes2).unwrap());
+
+        let report_handler = share1.report_handler();
+        assert_eq!(
+            *share1.get(0).unwrap(),
+            report_handler.create_report(&[2, 5])
+        );
+        assert_eq!(
+            *share1.get(1).unwrap(),
+            report_handler.create_report(&[0, 0])
+        );
+    }
 }
@@ -0,0 +1,206 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Errors that can occur when loading a configuration file.
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    /// The file could not be read
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    /// The file extension is not `.toml`, `.yaml`, or `.yml`
+    #[error("{}: Unsupported configuration format, expected .toml, .yaml, or .yml", path.display())]
+    UnsupportedFormat { path: PathBuf },
+
+    /// The file is not valid TOML or YAML, or does not match the configuration
+    #[error("{}:{line}:{column}: {message}", path.display())]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    /// The configuration parsed but failed validation
+    #[error("{}: {message}", path.display())]
+    Invalid { path: PathBuf, message: String },
+}
+
+/// Format of a configuration file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Return the format for a file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a configuration from text. Parse errors point at the 1-based line and
+/// column of the offending value.
+pub(crate) fn parse<T: DeserializeOwned>(
+    text: &str,
+    format: ConfigFormat,
+    path: &Path,
+) -> Result<T, ConfigError> {
+    let parse_error = |line, column, message| ConfigError::Parse {
+        path: path.to_path_buf(),
+        line,
+        column,
+        message,
+    };
+
+    match format {
+        ConfigFormat::Toml => toml::from_str(text).map_err(|e| {
+            let (line, column) = e
+                .span()
+                .map_or((0, 0), |span| line_and_column(text, span.start));
+            parse_error(line, column, e.message().to_string())
+        }),
+        ConfigFormat::Yaml => serde_yaml::from_str(text).map_err(|e| {
+            let (line, column) = e
+                .location()
+                .map_or((0, 0), |location| (location.line(), location.column()));
+            parse_error(line, column, e.to_string())
+        }),
+    }
+}
+
+/// Read and parse a configuration file, choosing the format by its extension, and
+/// validate the result.
+pub(crate) fn load<T, F>(path: &Path, validate: F) -> Result<T, ConfigError>
+where
+    T: DeserializeOwned,
+    F: FnOnce(&T) -> Result<(), String>,
+{
+    let format = ConfigFormat::from_path(path).ok_or_else(|| ConfigError::UnsupportedFormat {
+        path: path.to_path_buf(),
+    })?;
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let config = parse(&text, format, path)?;
+    validate(&config).map_err(|message| ConfigError::Invalid {
+        path: path.to_path_buf(),
+        message,
+    })?;
+    Ok(config)
+}
+
+/// Check that a privacy budget read from a file is usable.
+pub(crate) fn validate_budget(epsilon: f64, delta: f64) -> Result<(), String> {
+    if epsilon.is_nan() || epsilon <= 0.0 {
+        return Err(format!("epsilon must be positive, got {}", epsilon));
+    }
+    if !(0.0..1.0).contains(&delta) {
+        return Err(format!("delta must be in [0, 1), got {}", delta));
+    }
+    Ok(())
+}
+
+/// Return the 1-based line and column of a byte offset.
+fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dp::DPConfig;
+    use crate::multi_party::communication::NetworkConfig;
+    use crate::multi_party::crypto::SharingScheme;
+    use crate::multi_party::ProtocolConfig;
+    use crate::shuffle::ShuffleConfig;
+    use std::io::Write;
+
+    fn write_config(extension: &str, text: &str) -> tempfile::TempPath {
+        let mut file = tempfile::Builder::new()
+            .suffix(extension)
+            .tempfile()
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        file.into_temp_path()
+    }
+
+    #[test]
+    fn test_load_toml_and_yaml() {
+        let path = write_config(".toml", "shuffle_rounds = 4\nepsilon = 0.5\n");
+        let config = ShuffleConfig::from_file(&path).unwrap();
+        assert_eq!(config.shuffle_rounds, 4);
+        assert_eq!(config.privacy_budget.epsilon(), 0.5);
+
+        let path = write_config(
+            ".yaml",
+            "epsilon: 2.0\ndelta: 1.0e-6\nmechanism: gaussian\n",
+        );
+        let config = DPConfig::from_file(&path).unwrap();
+        assert_eq!(config.privacy_budget.delta(), 1e-6);
+
+        let path = write_config(".yml", "message_timeout_ms: 100\n");
+        let config = NetworkConfig::from_file(&path).unwrap();
+        assert_eq!(config.message_timeout_ms, 100);
+        assert_eq!(config.max_retries, NetworkConfig::default().max_retries);
+
+        let path = write_config(
+            ".toml",
+            "num_servers = 5\nthreshold = 3\nsharing = \"additive\"\n\n[cross_check]\nsample_rate = 0.1\n",
+        );
+        let config = ProtocolConfig::from_file(&path).unwrap();
+        assert_eq!((config.num_servers, config.threshold), (5, 3));
+        assert_eq!(config.sharing, SharingScheme::Additive);
+        assert_eq!(config.cross_check.unwrap().sample_rate, 0.1);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let path = write_config(".toml", "num_servers = 3\nthreshold = \"two\"\n");
+        match ProtocolConfig::from_file(&path) {
+            Err(ConfigError::Parse { line, column, .. }) => assert_eq!((line, column), (2, 13)),
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+
+        let path = write_config(".yaml", "threshold: 4\n");
+        assert!(matches!(
+            ProtocolConfig::from_file(&path),
+            Err(ConfigError::Invalid { .. })
+        ));
+
+        let path = write_config(".toml", "sharing = \"additive\"\nauthenticated_shares = true\n");
+        assert!(matches!(
+            ProtocolConfig::from_file(&path),
+            Err(ConfigError::Invalid { .. })
+        ));
+
+        let path = write_config(".toml", "shuffle_round = 4\n");
+        assert!(matches!(
+            ShuffleConfig::from_file(&path),
+            Err(ConfigError::Parse { line: 1, .. })
+        ));
+
+        let path = write_config(".json", "{}");
+        assert!(matches!(
+            DPConfig::from_file(&path),
+            Err(ConfigError::UnsupportedFormat { .. })
+        ));
+    }
+}
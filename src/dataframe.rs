@@ -0,0 +1,239 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use crate::report::attr::AttrValueType;
+use crate::schema::{attr_from_attr_value, DataPoint, QueryResult, Schema};
+use polars::prelude::*;
+use thiserror::Error;
+
+/// Errors that can occur when converting a `DataFrame` to `DataPoint`s.
+#[derive(Error, Debug)]
+pub enum DataFrameError {
+    /// No column matches a schema attribute
+    #[error("No column matches the attribute {0}")]
+    MissingColumn(String),
+
+    /// The column matching an attribute is not numeric
+    #[error("Column {column} has the non-numeric type {dtype}")]
+    NonNumericColumn { column: String, dtype: String },
+
+    /// A value is null
+    #[error("Row {row} of column {column} is null")]
+    NullValue { row: usize, column: String },
+
+    /// A value is not valid for its attribute type
+    #[error("Row {row} of column {column}: {message}")]
+    InvalidValue {
+        row: usize,
+        column: String,
+        message: String,
+    },
+
+    /// Polars failed
+    #[error("Polars error: {0}")]
+    Polars(#[from] PolarsError),
+}
+
+/// Normalize a column or attribute name for matching: case, spaces, hyphens, and
+/// underscores are ignored.
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Return the column of the `DataFrame` for each schema attribute. A column with
+/// exactly the attribute name is preferred; otherwise the names are compared
+/// ignoring case, spaces, hyphens, and underscores.
+pub fn map_columns(df: &DataFrame, schema: &Schema) -> Result<Vec<String>, DataFrameError> {
+    let column_names = df.get_column_names();
+    schema
+        .get_attr_names()
+        .into_iter()
+        .map(|attr_name| {
+            let normalized = normalize_name(&attr_name);
+            column_names
+                .iter()
+                .find(|column_name| **column_name == attr_name)
+                .or_else(|| {
+                    column_names
+                        .iter()
+                        .find(|column_name| normalize_name(column_name) == normalized)
+                })
+                .map(|column_name| column_name.to_string())
+                .ok_or(DataFrameError::MissingColumn(attr_name))
+        })
+        .collect()
+}
+
+/// Convert the rows of a `DataFrame` to `DataPoint`s, with one feature per schema
+/// attribute in schema order. Every value must be a valid value of its attribute
+/// type, so the data can also be encoded as `Report`s.
+pub fn data_points_from_dataframe(
+    df: &DataFrame,
+    schema: &Schema,
+) -> Result<Vec<DataPoint>, DataFrameError> {
+    let columns = map_columns(df, schema)?
+        .into_iter()
+        .map(|column_name| {
+            let column = df.column(&column_name)?;
+            if !column.dtype().is_numeric() {
+                return Err(DataFrameError::NonNumericColumn {
+                    column: column_name,
+                    dtype: column.dtype().to_string(),
+                });
+            }
+            Ok((column_name, column.cast(&DataType::Float64)?))
+        })
+        .collect::<Result<Vec<_>, DataFrameError>>()?;
+
+    let attr_types = schema.get_attr_types();
+    let mut data = Vec::with_capacity(df.height());
+    for row in 0..df.height() {
+        let mut features = Vec::with_capacity(columns.len());
+        let mut attributes = Vec::with_capacity(columns.len());
+        for ((column_name, column), attr_type) in columns.iter().zip(&attr_types) {
+            let value = column
+                .f64()?
+                .get(row)
+                .ok_or_else(|| DataFrameError::NullValue {
+                    row,
+                    column: column_name.clone(),
+                })?;
+            let invalid_value = |message: String| DataFrameError::InvalidValue {
+                row,
+                column: column_name.clone(),
+                message,
+            };
+            if value.fract() != 0.0 || value < 0.0 || value > AttrValueType::MAX as f64 {
+                return Err(invalid_value(format!(
+                    "Given value {} is not an attribute value.",
+                    value
+                )));
+            }
+
+            attributes.push(
+                attr_from_attr_value(*attr_type, value as AttrValueType).map_err(invalid_value)?,
+            );
+            features.push(value);
+        }
+        data.push(DataPoint::with_attributes(features, attributes));
+    }
+
+    Ok(data)
+}
+
+/// Convert `DataPoint`s, e.g. shuffled ones, back to a `DataFrame` with one column
+/// per schema attribute.
+pub fn data_points_to_dataframe(data: &[DataPoint], schema: &Schema) -> PolarsResult<DataFrame> {
+    let columns = schema
+        .get_attr_names()
+        .iter()
+        .enumerate()
+        .map(|(index, attr_name)| {
+            let values = data
+                .iter()
+                .map(|point| point.features().get(index).copied())
+                .collect::<Vec<_>>();
+            Series::new(attr_name, values)
+        })
+        .collect::<Vec<_>>();
+
+    DataFrame::new(columns)
+}
+
+/// Convert a `QueryResult` to a `DataFrame` with a `label` and a `value` column,
+/// and the noise metadata in `noisy` and `privacy_budget_used` columns. The labels
+/// name the values, e.g. the queried attributes of a mean query.
+pub fn query_result_to_dataframe(
+    result: &QueryResult,
+    labels: &[String],
+) -> PolarsResult<DataFrame> {
+    if labels.len() != result.values().len() {
+        return Err(PolarsError::ShapeMismatch(
+            format!(
+                "{} labels for {} query result values",
+                labels.len(),
+                result.values().len()
+            )
+            .into(),
+        ));
+    }
+
+    let len = labels.len();
+    df!(
+        "label" => labels,
+        "value" => result.values(),
+        "noisy" => vec![result.has_noise(); len],
+        "privacy_budget_used" => vec![result.privacy_budget_used(); len],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Schema {
+        Schema::try_from(r#"[["age_group","c3"],["income",{"n10":1000}]]"#).unwrap()
+    }
+
+    #[test]
+    fn test_dataframe_round_trip() {
+        let df = df!(
+            "user" => ["a", "b", "c"],
+            "Income" => [10i64, 999, 0],
+            "age_group" => [1u32, 7, 0],
+        )
+        .unwrap();
+
+        assert_eq!(
+            map_columns(&df, &schema()).unwrap(),
+            vec!["age_group", "Income"]
+        );
+        let data = data_points_from_dataframe(&df, &schema()).unwrap();
+        assert_eq!(data.len(), 3);
+        assert_eq!(data[1].features(), &[7.0, 999.0]);
+        assert_eq!(data[1].attributes().len(), 2);
+
+        let back = data_points_to_dataframe(&data, &schema()).unwrap();
+        assert_eq!(back.get_column_names(), vec!["age_group", "income"]);
+        assert_eq!(
+            back.column("income").unwrap().f64().unwrap().get(0),
+            Some(10.0)
+        );
+
+        let result = QueryResult::with_noise(vec![3.5, 410.2], 0.5);
+        let labels = vec!["age_group".to_string(), "income".to_string()];
+        let result_df = query_result_to_dataframe(&result, &labels).unwrap();
+        assert_eq!(result_df.shape(), (2, 4));
+        assert!(query_result_to_dataframe(&result, &labels[..1]).is_err());
+    }
+
+    #[test]
+    fn test_dataframe_errors() {
+        let df = df!("age_group" => [1u32], "salary" => [10u32]).unwrap();
+        assert!(matches!(
+            data_points_from_dataframe(&df, &schema()),
+            Err(DataFrameError::MissingColumn(_))
+        ));
+
+        let df = df!("age_group" => [8u32], "income" => [10u32]).unwrap();
+        assert!(matches!(
+            data_points_from_dataframe(&df, &schema()),
+            Err(DataFrameError::InvalidValue { row: 0, .. })
+        ));
+
+        let df = df!("age_group" => [Some(1u32), None], "income" => [10u32, 11]).unwrap();
+        assert!(matches!(
+            data_points_from_dataframe(&df, &schema()),
+            Err(DataFrameError::NullValue { row: 1, .. })
+        ));
+
+        let df = df!("age_group" => ["young"], "income" => [10u32]).unwrap();
+        assert!(matches!(
+            data_points_from_dataframe(&df, &schema()),
+            Err(DataFrameError::NonNumericColumn { .. })
+        ));
+    }
+}
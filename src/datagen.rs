@@ -0,0 +1,453 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Synthetic populations with known ground truth.
+//!
+//! A `PopulationSpec` assigns a `Generator` to each attribute of a `Schema`:
+//! uniform values, Zipfian categoricals, Gaussian mixtures, or values correlated
+//! with an earlier attribute. Generated values are rounded and clamped to the valid
+//! values of each attribute type, so every `DataPoint` also carries valid
+//! `Attribute`s. The `GroundTruth` of a `Population` records the exact statistics
+//! of the generated data and answers any `Query` without noise, so the error of a
+//! mechanism on that query can be measured directly.
+
+use crate::random::zipf::ZipfDistribution;
+use crate::schema::{attr_from_attr_value, DataPoint, Query, QueryType, Schema};
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// One component of a Gaussian mixture.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MixtureComponent {
+    /// Relative weight; the weights of a mixture need not sum to one.
+    pub weight: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+/// How the values of one attribute are generated.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Generator {
+    /// Uniform over the valid values.
+    Uniform,
+
+    /// Zipf distribution with exponent `s` over the valid values, with value 0 the
+    /// most frequent. Sampling keeps a table over all valid values, so this suits
+    /// categorical attributes and small numerical ranges.
+    Zipf { s: f64 },
+
+    /// Mixture of Gaussians.
+    GaussianMixture(Vec<MixtureComponent>),
+
+    /// `slope * source + intercept` plus Gaussian noise, where `source` is the
+    /// index of an earlier attribute.
+    Correlated {
+        source: usize,
+        slope: f64,
+        intercept: f64,
+        noise_std_dev: f64,
+    },
+}
+
+/// A description of a synthetic population over a `Schema`.
+#[derive(Clone, Debug)]
+pub struct PopulationSpec {
+    schema: Schema,
+    generators: Vec<Generator>,
+}
+
+impl PopulationSpec {
+    /// Create a specification with uniform values for every attribute.
+    pub fn new(schema: Schema) -> Self {
+        let generators = vec![Generator::Uniform; schema.len()];
+        Self { schema, generators }
+    }
+
+    /// Set the generator of a named attribute.
+    pub fn with_generator(mut self, attr_name: &str, generator: Generator) -> Result<Self, String> {
+        let attr_index = self
+            .schema
+            .get_attr_index(attr_name)
+            .ok_or_else(|| format!("Attribute name {} is invalid.", attr_name))?;
+        self.validate_generator(attr_index, &generator)?;
+        self.generators[attr_index] = generator;
+        Ok(self)
+    }
+
+    /// Return the schema.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn validate_generator(&self, attr_index: usize, generator: &Generator) -> Result<(), String> {
+        let valid = match generator {
+            Generator::Uniform => true,
+            Generator::Zipf { s } => *s > 0.0,
+            Generator::GaussianMixture(components) => {
+                !components.is_empty()
+                    && components
+                        .iter()
+                        .all(|c| c.weight >= 0.0 && c.mean.is_finite() && c.std_dev >= 0.0)
+                    && components.iter().map(|c| c.weight).sum::<f64>() > 0.0
+            }
+            Generator::Correlated {
+                source,
+                slope,
+                intercept,
+                noise_std_dev,
+            } => {
+                *source < attr_index
+                    && slope.is_finite()
+                    && intercept.is_finite()
+                    && *noise_std_dev >= 0.0
+            }
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(format!(
+                "Invalid generator {:?} for attribute {}.",
+                generator, attr_index
+            ))
+        }
+    }
+
+    /// Generate a population of `count` `DataPoint`s and record its ground truth.
+    pub fn generate<R: Rng + ?Sized>(
+        &self,
+        count: usize,
+        rng: &mut R,
+    ) -> Result<Population, String> {
+        let attr_types = self.schema.get_attr_types();
+        if attr_types
+            .iter()
+            .any(|attr_type| attr_type.get_value_count() == 0)
+        {
+            return Err("Every attribute must have at least one valid value.".to_string());
+        }
+
+        let zipfs = attr_types
+            .iter()
+            .zip(&self.generators)
+            .map(|(attr_type, generator)| match generator {
+                Generator::Zipf { s } => {
+                    let max_value = (attr_type.get_value_count() - 1) as u32;
+                    ZipfDistribution::new(max_value, *s, false)
+                        .map(Some)
+                        .map_err(|_| format!("Invalid Zipf exponent {}.", s))
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut data = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut values = Vec::with_capacity(attr_types.len());
+            for (attr_index, generator) in self.generators.iter().enumerate() {
+                let max_value = (attr_types[attr_index].get_value_count() - 1) as f64;
+                let value = match generator {
+                    Generator::Uniform => {
+                        rng.gen_range(0..attr_types[attr_index].get_value_count()) as f64
+                    }
+                    Generator::Zipf { .. } => {
+                        zipfs[attr_index].as_ref().unwrap().sample(rng) as f64
+                    }
+                    Generator::GaussianMixture(components) => sample_mixture(components, rng),
+                    Generator::Correlated {
+                        source,
+                        slope,
+                        intercept,
+                        noise_std_dev,
+                    } => {
+                        slope * values[*source]
+                            + intercept
+                            + sample_normal(0.0, *noise_std_dev, rng)
+                    }
+                };
+                values.push(value.round().clamp(0.0, max_value));
+            }
+
+            let attributes = attr_types
+                .iter()
+                .zip(&values)
+                .map(|(&attr_type, &value)| attr_from_attr_value(attr_type, value as u32))
+                .collect::<Result<Vec<_>, _>>()?;
+            data.push(DataPoint::with_attributes(values, attributes));
+        }
+
+        let truth = GroundTruth::from_data(&data, &self.schema);
+        Ok(Population { data, truth })
+    }
+}
+
+fn sample_normal<R: Rng + ?Sized>(mean: f64, std_dev: f64, rng: &mut R) -> f64 {
+    if std_dev == 0.0 {
+        return mean;
+    }
+    Normal::new(mean, std_dev).unwrap().sample(rng)
+}
+
+fn sample_mixture<R: Rng + ?Sized>(components: &[MixtureComponent], rng: &mut R) -> f64 {
+    let total_weight = components.iter().map(|c| c.weight).sum::<f64>();
+    let mut target = rng.gen_range(0.0..total_weight);
+    let component = components
+        .iter()
+        .find(|c| {
+            target -= c.weight;
+            target < 0.0
+        })
+        .unwrap_or(&components[components.len() - 1]);
+    sample_normal(component.mean, component.std_dev, rng)
+}
+
+/// A generated population and its exact statistics.
+#[derive(Clone, Debug)]
+pub struct Population {
+    pub data: Vec<DataPoint>,
+    pub truth: GroundTruth,
+}
+
+/// Exact statistics of a dataset, per attribute in schema order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GroundTruth {
+    pub attr_names: Vec<String>,
+    pub count: usize,
+    pub sums: Vec<f64>,
+    pub means: Vec<f64>,
+    /// Population variances.
+    pub variances: Vec<f64>,
+    pub mins: Vec<f64>,
+    pub maxs: Vec<f64>,
+    /// Number of occurrences of each value.
+    pub histograms: Vec<BTreeMap<u32, u64>>,
+    /// Pearson correlation between every pair of attributes.
+    pub correlations: Vec<Vec<f64>>,
+}
+
+impl GroundTruth {
+    /// Compute the statistics of `DataPoint`s with one feature per schema attribute.
+    pub fn from_data(data: &[DataPoint], schema: &Schema) -> Self {
+        let num_attrs = schema.len();
+        let count = data.len();
+        let column = |attr_index: usize| data.iter().map(move |point| point.features()[attr_index]);
+
+        let sums = (0..num_attrs)
+            .map(|i| column(i).sum::<f64>())
+            .collect::<Vec<_>>();
+        let means = sums
+            .iter()
+            .map(|sum| if count > 0 { sum / count as f64 } else { 0.0 })
+            .collect::<Vec<_>>();
+        let covariance = |i: usize, j: usize| {
+            if count == 0 {
+                return 0.0;
+            }
+            column(i)
+                .zip(column(j))
+                .map(|(x, y)| (x - means[i]) * (y - means[j]))
+                .sum::<f64>()
+                / count as f64
+        };
+        let variances = (0..num_attrs).map(|i| covariance(i, i)).collect::<Vec<_>>();
+        let correlations = (0..num_attrs)
+            .map(|i| {
+                (0..num_attrs)
+                    .map(|j| {
+                        let scale = (variances[i] * variances[j]).sqrt();
+                        if scale > 0.0 {
+                            covariance(i, j) / scale
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut histograms = vec![BTreeMap::new(); num_attrs];
+        for point in data {
+            for (histogram, &value) in histograms.iter_mut().zip(point.features()) {
+                *histogram.entry(value as u32).or_insert(0) += 1;
+            }
+        }
+
+        Self {
+            attr_names: schema.get_attr_names(),
+            count,
+            mins: (0..num_attrs)
+                .map(|i| column(i).fold(f64::INFINITY, f64::min))
+                .collect(),
+            maxs: (0..num_attrs)
+                .map(|i| column(i).fold(f64::NEG_INFINITY, f64::max))
+                .collect(),
+            sums,
+            means,
+            variances,
+            histograms,
+            correlations,
+        }
+    }
+
+    /// Return the attribute index of a query feature: an attribute name, or a
+    /// positional name `featureN` counting from one, as used by the mechanisms.
+    pub fn feature_index(&self, feature: &str) -> Option<usize> {
+        self.attr_names
+            .iter()
+            .position(|attr_name| attr_name == feature)
+            .or_else(|| {
+                let index = feature.strip_prefix("feature")?.parse::<usize>().ok()?;
+                (1..=self.attr_names.len())
+                    .contains(&index)
+                    .then(|| index - 1)
+            })
+    }
+
+    /// Return the exact answer to a query, computed the same way as the mechanisms
+    /// compute it before adding noise. Histogram counts of all queried features
+    /// are combined and listed in increasing order of value. Returns `None` for
+    /// unknown features.
+    pub fn answer(&self, query: &Query) -> Option<Vec<f64>> {
+        let indices = query
+            .features
+            .iter()
+            .map(|feature| self.feature_index(feature))
+            .collect::<Option<Vec<_>>>()?;
+
+        let per_feature = |stat: &dyn Fn(usize) -> f64| indices.iter().map(|&i| stat(i)).collect();
+        let answer = match query.query_type {
+            QueryType::Mean => per_feature(&|i| self.means[i]),
+            QueryType::Variance => per_feature(&|i| self.variances[i]),
+            QueryType::Sum => per_feature(&|i| self.sums[i]),
+            QueryType::Count => per_feature(&|_| self.count as f64),
+            QueryType::Range => per_feature(&|i| {
+                if self.count > 0 {
+                    self.maxs[i] - self.mins[i]
+                } else {
+                    0.0
+                }
+            }),
+            QueryType::Histogram => {
+                let mut combined = BTreeMap::<u32, u64>::new();
+                for &i in &indices {
+                    for (&value, &count) in &self.histograms[i] {
+                        *combined.entry(value).or_insert(0) += count;
+                    }
+                }
+                combined.into_values().map(|count| count as f64).collect()
+            }
+        };
+
+        Some(answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn spec() -> PopulationSpec {
+        let schema =
+            Schema::try_from(r#"[["region","c4"],["age",{"n8":128}],["income",{"n16":65521}]]"#)
+                .unwrap();
+        PopulationSpec::new(schema)
+            .with_generator("region", Generator::Zipf { s: 1.5 })
+            .unwrap()
+            .with_generator(
+                "age",
+                Generator::GaussianMixture(vec![
+                    MixtureComponent {
+                        weight: 1.0,
+                        mean: 30.0,
+                        std_dev: 5.0,
+                    },
+                    MixtureComponent {
+                        weight: 1.0,
+                        mean: 70.0,
+                        std_dev: 5.0,
+                    },
+                ]),
+            )
+            .unwrap()
+            .with_generator(
+                "income",
+                Generator::Correlated {
+                    source: 1,
+                    slope: 500.0,
+                    intercept: 1000.0,
+                    noise_std_dev: 2000.0,
+                },
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let population = spec().generate(10_000, &mut rng).unwrap();
+        let truth = &population.truth;
+
+        assert_eq!(population.data.len(), 10_000);
+        assert!(population
+            .data
+            .iter()
+            .all(|point| spec().schema().is_compatible_attr_array(point.attributes())));
+
+        // Zipf puts the most mass on value 0; the mixture is bimodal around 50.
+        let region = &truth.histograms[0];
+        assert!(region[&0] > region[&1] && region[&1] > region[&2]);
+        assert!((truth.means[1] - 50.0).abs() < 1.0);
+        assert!(truth.variances[1] > 400.0);
+        assert!(truth.correlations[1][2] > 0.9);
+        assert!((truth.correlations[0][1]).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_answer() {
+        let schema = Schema::try_from(r#"[["a","c3"],["b","c3"]]"#).unwrap();
+        let data = vec![
+            DataPoint::new(vec![1.0, 2.0]),
+            DataPoint::new(vec![3.0, 2.0]),
+            DataPoint::new(vec![2.0, 6.0]),
+        ];
+        let truth = GroundTruth::from_data(&data, &schema);
+
+        let query = |query_type, features: &[&str]| {
+            Query::new(query_type, features.iter().map(|f| f.to_string()).collect())
+        };
+        assert_eq!(
+            truth.answer(&query(QueryType::Mean, &["a", "feature2"])),
+            Some(vec![2.0, 10.0 / 3.0])
+        );
+        assert_eq!(
+            truth.answer(&query(QueryType::Range, &["b"])),
+            Some(vec![4.0])
+        );
+        assert_eq!(
+            truth.answer(&query(QueryType::Count, &["a"])),
+            Some(vec![3.0])
+        );
+        assert_eq!(
+            truth.answer(&query(QueryType::Histogram, &["a", "b"])),
+            Some(vec![1.0, 3.0, 1.0, 1.0])
+        );
+        assert_eq!(truth.answer(&query(QueryType::Mean, &["feature3"])), None);
+
+        assert!(PopulationSpec::new(schema)
+            .with_generator(
+                "a",
+                Generator::Correlated {
+                    source: 1,
+                    slope: 1.0,
+                    intercept: 0.0,
+                    noise_std_dev: 0.0,
+                },
+            )
+            .is_err());
+    }
+}
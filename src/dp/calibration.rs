@@ -0,0 +1,107 @@
+use rand::Rng;
+
+/// Scale of the Laplace noise that gives epsilon-DP for a query with the given
+/// L1 sensitivity
+pub fn laplace_scale(sensitivity: f64, epsilon: f64) -> f64 {
+    assert!(epsilon > 0.0, "laplace_scale: epsilon must be positive");
+    sensitivity / epsilon
+}
+
+/// Standard deviation of the Gaussian noise that gives (epsilon, delta)-DP for a
+/// query with the given L2 sensitivity, using the classic bound
+/// `sigma = sensitivity * sqrt(2 ln(1.25 / delta)) / epsilon`
+pub fn gaussian_sigma(sensitivity: f64, epsilon: f64, delta: f64) -> f64 {
+    assert!(epsilon > 0.0, "gaussian_sigma: epsilon must be positive");
+    assert!(
+        delta > 0.0 && delta < 1.0,
+        "gaussian_sigma: delta must be in (0, 1)"
+    );
+    sensitivity * (2.0 * (1.25 / delta).ln()).sqrt() / epsilon
+}
+
+/// Probability that k-ary randomized response keeps the true value
+pub fn randomized_response_keep_probability(domain_size: u32, epsilon: f64) -> f64 {
+    assert!(
+        domain_size >= 2,
+        "randomized_response: domain must have at least two values"
+    );
+    let e = epsilon.exp();
+    e / (e + domain_size as f64 - 1.0)
+}
+
+/// Locally randomize a categorical value in `0..domain_size` with k-ary randomized
+/// response: keep it with probability `e^epsilon / (e^epsilon + k - 1)`, and
+/// otherwise report one of the other values uniformly at random
+pub fn randomized_response<R: Rng + ?Sized>(
+    value: u32,
+    domain_size: u32,
+    epsilon: f64,
+    rng: &mut R,
+) -> u32 {
+    assert!(
+        value < domain_size,
+        "randomized_response: value is outside the domain"
+    );
+    if rng.gen_bool(randomized_response_keep_probability(domain_size, epsilon)) {
+        return value;
+    }
+
+    // Sample from the other k - 1 values.
+    let other = rng.gen_range(0..domain_size - 1);
+    if other >= value {
+        other + 1
+    } else {
+        other
+    }
+}
+
+/// Unbiased frequency estimates from the counts of randomized responses
+pub fn randomized_response_estimate(counts: &[u64], epsilon: f64) -> Vec<f64> {
+    let domain_size = counts.len() as u32;
+    let total = counts.iter().sum::<u64>() as f64;
+    let p = randomized_response_keep_probability(domain_size, epsilon);
+    let q = (1.0 - p) / (domain_size as f64 - 1.0);
+
+    counts
+        .iter()
+        .map(|&count| (count as f64 - total * q) / (p - q))
+        .collect()
+}
+
+/// Sample Laplace noise with the given scale
+pub fn sample_laplace<R: Rng + ?Sized>(scale: f64, rng: &mut R) -> f64 {
+    let u: f64 = rng.gen_range(-0.5..0.5);
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_calibration() {
+        assert_eq!(laplace_scale(2.0, 0.5), 4.0);
+
+        let sigma = gaussian_sigma(1.0, 1.0, 1e-5);
+        assert!((sigma - 4.8448).abs() < 1e-3);
+        assert!(gaussian_sigma(1.0, 2.0, 1e-5) < sigma);
+    }
+
+    #[test]
+    fn test_randomized_response_estimate() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let epsilon = 2.0;
+        let mut counts = vec![0u64; 4];
+        for i in 0..40_000u32 {
+            let value = if i % 4 == 0 { 1 } else { 3 };
+            counts[randomized_response(value, 4, epsilon, &mut rng) as usize] += 1;
+        }
+
+        let estimate = randomized_response_estimate(&counts, epsilon);
+        assert!((estimate[1] - 10_000.0).abs() < 800.0);
+        assert!((estimate[3] - 30_000.0).abs() < 800.0);
+        assert!(estimate[0].abs() < 800.0);
+    }
+}
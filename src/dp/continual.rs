@@ -0,0 +1,282 @@
+use super::{calibration, DPError};
+use crate::report::store::ReportStore;
+use rand::Rng;
+
+/// Continual release of a running count with the binary tree mechanism
+///
+/// The stream is split into dyadic intervals, the nodes of a binary tree over the
+/// `horizon` release times. Each interval sum is noised once, and the running total
+/// after `t` updates is the sum of the noisy intervals given by the binary digits of
+/// `t`. Every update touches at most `levels` nodes, so each node gets Laplace noise
+/// of scale `sensitivity * levels / epsilon`, and the whole stream of releases is
+/// epsilon-DP with an error of `O(log(horizon)^1.5 / epsilon)` at every time.
+#[derive(Debug, Clone)]
+pub struct ContinualCounter {
+    epsilon: f64,
+    sensitivity: f64,
+    horizon: u64,
+    /// Exact sums of the open interval at each level
+    partial: Vec<f64>,
+    /// Noisy sums of the open interval at each level
+    noisy: Vec<f64>,
+    /// Number of updates so far
+    time: u64,
+}
+
+impl ContinualCounter {
+    /// Create a counter for up to `horizon` releases where one individual changes
+    /// the sum of all increments by at most `sensitivity`
+    pub fn new(epsilon: f64, sensitivity: f64, horizon: u64) -> Result<Self, DPError> {
+        if epsilon.is_nan() || epsilon <= 0.0 || sensitivity.is_nan() || sensitivity < 0.0 {
+            return Err(DPError::InvalidInput);
+        }
+        if horizon == 0 {
+            return Err(DPError::InvalidInput);
+        }
+
+        let levels = Self::levels_for(horizon);
+        Ok(Self {
+            epsilon,
+            sensitivity,
+            horizon,
+            partial: vec![0.0; levels],
+            noisy: vec![0.0; levels],
+            time: 0,
+        })
+    }
+
+    /// Number of tree levels for a horizon, i.e. `floor(log2(horizon)) + 1`
+    fn levels_for(horizon: u64) -> usize {
+        (u64::BITS - horizon.leading_zeros()) as usize
+    }
+
+    /// Number of tree levels, the most nodes a single update touches
+    pub fn levels(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Scale of the Laplace noise added to each tree node
+    pub fn noise_scale(&self) -> f64 {
+        calibration::laplace_scale(self.sensitivity * self.levels() as f64, self.epsilon)
+    }
+
+    /// Standard deviation of a released total, which sums at most `levels` nodes
+    pub fn error_std(&self) -> f64 {
+        (2.0 * self.levels() as f64).sqrt() * self.noise_scale()
+    }
+
+    /// Number of releases so far
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Number of releases the privacy budget covers
+    pub fn horizon(&self) -> u64 {
+        self.horizon
+    }
+
+    /// Add the increment of the next interval and release the noisy running total
+    ///
+    /// Fails with `PrivacyBudgetExceeded` once `horizon` totals have been released.
+    pub fn update<R: Rng + ?Sized>(&mut self, increment: f64, rng: &mut R) -> Result<f64, DPError> {
+        if self.time >= self.horizon {
+            return Err(DPError::PrivacyBudgetExceeded);
+        }
+        if !increment.is_finite() {
+            return Err(DPError::InvalidInput);
+        }
+        self.time += 1;
+
+        // The interval ending now is at the level of the lowest set bit of the time;
+        // it absorbs the intervals below it, which are closed.
+        let level = self.time.trailing_zeros() as usize;
+        let absorbed = self.partial[..level].iter().sum::<f64>();
+        self.partial[level] = absorbed + increment;
+        self.partial[..level].fill(0.0);
+        self.noisy[..level].fill(0.0);
+        self.noisy[level] =
+            self.partial[level] + calibration::sample_laplace(self.noise_scale(), rng);
+
+        Ok(self.current())
+    }
+
+    /// The last released running total, without spending more budget
+    pub fn current(&self) -> f64 {
+        (0..self.levels())
+            .filter(|&level| self.time & (1 << level) != 0)
+            .map(|level| self.noisy[level])
+            .sum()
+    }
+}
+
+/// Releases a running count of the reports in a `ReportStore` once per epoch
+///
+/// Every epoch from the first one is released in order, with the epochs that hold
+/// no reports counting zero, so the counter's time matches the store's epochs.
+/// Each report must belong to a different individual, or `sensitivity` must bound
+/// the reports of one individual over the whole horizon.
+#[derive(Debug, Clone)]
+pub struct EpochCounter {
+    counter: ContinualCounter,
+    next_epoch: u64,
+    filter: Option<(usize, u32)>,
+}
+
+impl EpochCounter {
+    /// Count the reports from `first_epoch` on, for as many epochs as the counter's
+    /// horizon
+    pub fn new(counter: ContinualCounter, first_epoch: u64) -> Self {
+        Self {
+            counter,
+            next_epoch: first_epoch,
+            filter: None,
+        }
+    }
+
+    /// Count only the reports whose attribute `attr_index` has the value `attr_value`,
+    /// e.g. one bin of a histogram
+    pub fn with_filter(mut self, attr_index: usize, attr_value: u32) -> Self {
+        self.filter = Some((attr_index, attr_value));
+        self
+    }
+
+    /// The next epoch to be released
+    pub fn next_epoch(&self) -> u64 {
+        self.next_epoch
+    }
+
+    /// The underlying continual counter
+    pub fn counter(&self) -> &ContinualCounter {
+        &self.counter
+    }
+
+    /// Release the running count after every epoch up to and including `epoch`
+    /// that has not been released yet, returning `(epoch, noisy total)` pairs
+    ///
+    /// Epochs are released once they are complete, so `epoch` must be before the
+    /// store's current epoch, and released before the retention policy deletes them.
+    /// Reports of released epochs are not counted again.
+    pub fn release_through<R: Rng + ?Sized, const U32_SIZE: usize>(
+        &mut self,
+        store: &ReportStore<U32_SIZE>,
+        epoch: u64,
+        rng: &mut R,
+    ) -> Result<Vec<(u64, f64)>, DPError> {
+        if epoch >= store.current_epoch() {
+            return Err(DPError::InvalidInput);
+        }
+        if let Some((attr_index, _)) = self.filter {
+            if attr_index >= store.attr_types().len() {
+                return Err(DPError::InvalidInput);
+            }
+        }
+
+        let mut released = Vec::new();
+        while self.next_epoch <= epoch {
+            let count = store
+                .get_epoch(self.next_epoch)
+                .map_or(0, |reports| match self.filter {
+                    None => reports.len(),
+                    Some((attr_index, attr_value)) => {
+                        let report_handler = reports.report_handler();
+                        reports
+                            .iter()
+                            .filter(|report| {
+                                report_handler.has_attr_value(report, attr_index, attr_value)
+                            })
+                            .count()
+                    }
+                });
+            let total = self.counter.update(count as f64, rng)?;
+            released.push((self.next_epoch, total));
+            self.next_epoch += 1;
+        }
+
+        Ok(released)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::report_vector::ReportVector;
+    use crate::report::store::RetentionPolicy;
+    use crate::schema::AttributeType::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_tree_sums() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // A huge epsilon makes the noise negligible, exposing the tree bookkeeping
+        let mut counter = ContinualCounter::new(1e9, 1.0, 100).unwrap();
+        assert_eq!(counter.levels(), 7);
+
+        let mut total = 0.0;
+        for t in 1..=100u64 {
+            total += t as f64;
+            let released = counter.update(t as f64, &mut rng).unwrap();
+            assert!((released - total).abs() < 1e-3, "t = {}", t);
+        }
+        assert!(matches!(
+            counter.update(1.0, &mut rng),
+            Err(DPError::PrivacyBudgetExceeded)
+        ));
+        assert!(ContinualCounter::new(0.0, 1.0, 10).is_err());
+    }
+
+    #[test]
+    fn test_error_is_polylogarithmic() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let horizon = 1 << 12;
+        let mut counter = ContinualCounter::new(1.0, 1.0, horizon).unwrap();
+
+        let mut max_error: f64 = 0.0;
+        for t in 1..=horizon {
+            let released = counter.update(1.0, &mut rng).unwrap();
+            max_error = max_error.max((released - t as f64).abs());
+        }
+        // Releasing every prefix sum with independent noise would need a scale of
+        // `horizon / epsilon`; the tree keeps every error within a few `error_std`.
+        assert!(max_error < 6.0 * counter.error_std());
+        assert!(counter.error_std() < horizon as f64 / 20.0);
+    }
+
+    #[test]
+    fn test_epoch_counter() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let attr_types = [C4, N5(31)];
+        let mut store = ReportStore::<1>::new(&attr_types, RetentionPolicy::KeepAll, 5);
+        let mut reports = ReportVector::<1>::new(&attr_types);
+        for value in [3, 3, 7] {
+            let report = reports.report_handler().create_report(&[value, 0]);
+            reports.push(report);
+        }
+        store.ingest(reports.clone()).unwrap();
+        store.set_epoch(7);
+        store.ingest(reports).unwrap();
+
+        let counter = ContinualCounter::new(1e9, 1.0, 16).unwrap();
+        let mut all = EpochCounter::new(counter.clone(), 5);
+        let mut threes = EpochCounter::new(counter, 5).with_filter(0, 3);
+
+        // Epoch 7 is still open.
+        assert!(all.release_through(&store, 7, &mut rng).is_err());
+        let released = all.release_through(&store, 6, &mut rng).unwrap();
+        assert_eq!(released.len(), 2);
+        assert!((released[1].1 - 3.0).abs() < 1e-3);
+
+        store.advance_epoch();
+        let released = threes.release_through(&store, 7, &mut rng).unwrap();
+        assert_eq!(
+            released.iter().map(|(epoch, _)| *epoch).collect::<Vec<_>>(),
+            vec![5, 6, 7]
+        );
+        assert!((released[2].1 - 4.0).abs() < 1e-3);
+        assert_eq!(threes.next_epoch(), 8);
+        assert!(threes
+            .release_through(&store, 7, &mut rng)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,7 +1,100 @@
 use crate::schema::{DataPoint, Query, QueryResult};
 use crate::arith::PrivacyBudget;
 use crate::random;
-use super::{DPConfig, DPError, MechanismType};
+use super::{calibration, DPConfig, DPError, MechanismType};
+use std::collections::HashMap;
+
+/// Running aggregate of a query over batches of data points
+///
+/// The DP stage consumes shuffled data batch by batch, e.g. borrowed chunks of
+/// the shuffler's output or owned chunks received from a channel, so an epoch
+/// never has to be collected or cloned into one vector for aggregation.
+pub struct Aggregation {
+    query: Query,
+    state: AggregationState,
+    points: usize,
+}
+
+enum AggregationState {
+    Mean { sums: Vec<f64>, counts: Vec<usize> },
+    /// Counts keyed by the bits of the feature value
+    Histogram(HashMap<u64, usize>),
+}
+
+impl Aggregation {
+    /// Start aggregating `query`, which must be a mean or histogram query
+    pub fn new(query: Query) -> Result<Self, DPError> {
+        let state = match query.query_type {
+            crate::schema::QueryType::Mean => AggregationState::Mean {
+                sums: vec![0.0; query.features.len()],
+                counts: vec![0; query.features.len()],
+            },
+            crate::schema::QueryType::Histogram => AggregationState::Histogram(HashMap::new()),
+            _ => return Err(DPError::InvalidInput),
+        };
+
+        Ok(Self { query, state, points: 0 })
+    }
+
+    /// Add a batch of data points
+    pub fn push(&mut self, batch: &[DataPoint]) {
+        self.points += batch.len();
+
+        match &mut self.state {
+            AggregationState::Mean { sums, counts } => {
+                for point in batch {
+                    for (i, feature) in self.query.features.iter().enumerate() {
+                        if let Some(value) = point.get_feature(feature) {
+                            sums[i] += value;
+                            counts[i] += 1;
+                        }
+                    }
+                }
+            }
+            AggregationState::Histogram(histogram) => {
+                for point in batch {
+                    for feature in &self.query.features {
+                        if let Some(value) = point.get_feature(feature) {
+                            *histogram.entry(value.to_bits()).or_insert(0) += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of data points aggregated so far
+    pub fn len(&self) -> usize {
+        self.points
+    }
+
+    /// Whether no data points were aggregated
+    pub fn is_empty(&self) -> bool {
+        self.points == 0
+    }
+
+    /// The query being aggregated
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// The exact result, before noise
+    fn finish(self) -> QueryResult {
+        match self.state {
+            AggregationState::Mean { sums, counts } => {
+                let means: Vec<f64> = sums.iter()
+                    .zip(counts.iter())
+                    .map(|(&sum, &count)| if count > 0 { sum / count as f64 } else { 0.0 })
+                    .collect();
+                QueryResult::new(means)
+            }
+            AggregationState::Histogram(histogram) => {
+                let values: Vec<f64> = histogram.values().map(|&v| v as f64).collect();
+                QueryResult::new(values)
+            }
+        }
+    }
+}
 
 pub struct DPMechanismImpl {
     mechanism_type: MechanismType,
@@ -13,12 +106,30 @@ impl DPMechanismImpl {
     }
 
     pub fn apply(&self, data: Vec<DataPoint>, query: Query, config: &DPConfig) -> Result<QueryResult, DPError> {
-        if data.is_empty() {
+        self.apply_batches(std::iter::once(data.as_slice()), query, config)
+    }
+
+    /// Aggregate `query` over batches of data points and release it with noise
+    pub fn apply_batches<'a, I>(&self, batches: I, query: Query, config: &DPConfig) -> Result<QueryResult, DPError>
+    where
+        I: IntoIterator<Item = &'a [DataPoint]>,
+    {
+        let mut aggregation = Aggregation::new(query)?;
+        for batch in batches {
+            aggregation.push(batch);
+        }
+
+        self.release(aggregation, config)
+    }
+
+    /// Add noise to a finished aggregation
+    pub fn release(&self, aggregation: Aggregation, config: &DPConfig) -> Result<QueryResult, DPError> {
+        if aggregation.is_empty() {
             return Err(DPError::InvalidInput);
         }
 
         // Calculate raw result
-        let raw_result = self.compute_raw_result(&data, &query)?;
+        let raw_result = aggregation.finish();
         
         // Add noise based on mechanism type
         let noisy_result = match self.mechanism_type {
@@ -38,53 +149,9 @@ impl DPMechanismImpl {
         }
     }
 
-    fn compute_raw_result(&self, data: &[DataPoint], query: &Query) -> Result<QueryResult, DPError> {
-        match query.query_type {
-            crate::schema::QueryType::Mean => self.compute_mean(data, query),
-            crate::schema::QueryType::Histogram => self.compute_histogram(data, query),
-            _ => Err(DPError::InvalidInput),
-        }
-    }
-
-    fn compute_mean(&self, data: &[DataPoint], query: &Query) -> Result<QueryResult, DPError> {
-        let mut sums = vec![0.0; query.features.len()];
-        let mut counts = vec![0; query.features.len()];
-
-        for point in data {
-            for (i, feature) in query.features.iter().enumerate() {
-                if let Some(value) = point.get_feature(feature) {
-                    sums[i] += value;
-                    counts[i] += 1;
-                }
-            }
-        }
-
-        let means: Vec<f64> = sums.iter()
-            .zip(counts.iter())
-            .map(|(&sum, &count)| if count > 0 { sum / count as f64 } else { 0.0 })
-            .collect();
-
-        Ok(QueryResult::new(means))
-    }
-
-    fn compute_histogram(&self, data: &[DataPoint], query: &Query) -> Result<QueryResult, DPError> {
-        let mut histogram = std::collections::HashMap::new();
-
-        for point in data {
-            for feature in &query.features {
-                if let Some(value) = point.get_feature(feature) {
-                    *histogram.entry(value).or_insert(0) += 1;
-                }
-            }
-        }
-
-        let values: Vec<f64> = histogram.values().map(|&v| v as f64).collect();
-        Ok(QueryResult::new(values))
-    }
-
     fn add_laplace_noise(&self, mut result: QueryResult, config: &DPConfig) -> QueryResult {
         let sensitivity = self.get_sensitivity(&result.query);
-        let scale = sensitivity / config.privacy_budget.epsilon();
+        let scale = calibration::laplace_scale(sensitivity, config.privacy_budget.epsilon());
         
         for value in result.values_mut() {
             *value += random::laplace_noise(scale);
@@ -95,7 +162,11 @@ impl DPMechanismImpl {
 
     fn add_gaussian_noise(&self, mut result: QueryResult, config: &DPConfig) -> QueryResult {
         let sensitivity = self.get_sensitivity(&result.query);
-        let sigma = sensitivity * (2.0 * config.privacy_budget.delta().ln()).sqrt() / config.privacy_budget.epsilon();
+        let sigma = calibration::gaussian_sigma(
+            sensitivity,
+            config.privacy_budget.epsilon(),
+            config.privacy_budget.delta(),
+        );
         
         for value in result.values_mut() {
             *value += random::gaussian_noise(sigma);
@@ -157,6 +228,27 @@ mod tests {
         assert!(result.has_noise());
     }
 
+    #[test]
+    fn test_aggregation_over_batches() {
+        let data = vec![
+            DataPoint::new(vec![1.0, 2.0]),
+            DataPoint::new(vec![3.0, 4.0]),
+            DataPoint::new(vec![5.0, 6.0]),
+        ];
+        let query = Query::new(QueryType::Mean, vec!["feature1".to_string(), "feature2".to_string()]);
+
+        let mut aggregation = Aggregation::new(query).unwrap();
+        for batch in data.chunks(2) {
+            aggregation.push(batch);
+        }
+        assert_eq!(aggregation.len(), 3);
+        assert_eq!(aggregation.finish().values(), &[3.0, 4.0]);
+
+        let mechanism = DPMechanismImpl::new(MechanismType::Laplace);
+        let query = Query::new(QueryType::Mean, vec!["feature1".to_string()]);
+        assert!(mechanism.apply_batches(std::iter::empty(), query, &DPConfig::default()).is_err());
+    }
+
     #[test]
     fn test_sensitivity_calculation() {
         let mechanism = DPMechanismImpl::new(MechanismType::Laplace);
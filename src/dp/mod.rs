@@ -1,9 +1,22 @@
+pub mod calibration;
+pub mod continual;
 mod mechanisms;
+#[cfg(feature = "opendp")]
+pub mod opendp;
+pub mod sketch;
 
 use crate::schema::{DataPoint, Query, QueryResult};
 use crate::arith::PrivacyBudget;
+use crate::config::{self, ConfigError};
+use crate::telemetry::{self, Operation};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 use thiserror::Error;
 
+pub use continual::{ContinualCounter, EpochCounter};
+pub use mechanisms::Aggregation;
+pub use sketch::{CountMinSketch, HyperLogLog, NoisyCountMin};
+
 #[derive(Error, Debug)]
 pub enum DPError {
     #[error("Invalid input data")]
@@ -12,6 +25,9 @@ pub enum DPError {
     PrivacyBudgetExceeded,
     #[error("DP mechanism failed")]
     MechanismFailed,
+    #[cfg(feature = "opendp")]
+    #[error("OpenDP error: {0}")]
+    OpenDP(String),
 }
 
 pub struct DPConfig {
@@ -19,7 +35,8 @@ pub struct DPConfig {
     pub mechanism_type: MechanismType,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MechanismType {
     Laplace,
     Gaussian,
@@ -35,6 +52,39 @@ impl Default for DPConfig {
     }
 }
 
+impl DPConfig {
+    /// Load a configuration from a TOML or YAML file with the fields `epsilon`,
+    /// `delta`, and `mechanism` (`laplace`, `gaussian`, or `exponential`)
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let file: DPConfigFile = config::load(path.as_ref(), |file: &DPConfigFile| {
+            config::validate_budget(file.epsilon, file.delta)
+        })?;
+        Ok(Self {
+            privacy_budget: PrivacyBudget::new(file.epsilon, file.delta),
+            mechanism_type: file.mechanism,
+        })
+    }
+}
+
+/// `DPConfig` as written in a configuration file
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DPConfigFile {
+    epsilon: f64,
+    delta: f64,
+    mechanism: MechanismType,
+}
+
+impl Default for DPConfigFile {
+    fn default() -> Self {
+        Self {
+            epsilon: 1.0,
+            delta: 1e-5,
+            mechanism: MechanismType::Laplace,
+        }
+    }
+}
+
 pub struct DPMechanism {
     config: DPConfig,
     mechanism: mechanisms::DPMechanismImpl,
@@ -49,7 +99,37 @@ impl DPMechanism {
     }
 
     pub fn apply_mechanism(&self, data: Vec<DataPoint>, query: Query) -> Result<QueryResult, DPError> {
-        self.mechanism.apply(data, query, &self.config)
+        telemetry::record("dp", "apply_mechanism", data.len(), || {
+            self.mechanism.apply(data, query, &self.config)
+        })
+    }
+
+    /// Answer `query` on data arriving in batches, e.g. borrowed chunks of a
+    /// shuffled epoch, without collecting it into one vector
+    pub fn apply_mechanism_batches<'a, I>(&self, batches: I, query: Query) -> Result<QueryResult, DPError>
+    where
+        I: IntoIterator<Item = &'a [DataPoint]>,
+    {
+        let mut aggregation = Aggregation::new(query)?;
+        for batch in batches {
+            aggregation.push(batch);
+        }
+        self.release(aggregation)
+    }
+
+    /// Start aggregating `query` over batches pushed one at a time, e.g. owned
+    /// chunks received from a channel; release the result with `release`
+    pub fn aggregation(&self, query: Query) -> Result<Aggregation, DPError> {
+        Aggregation::new(query)
+    }
+
+    /// Add noise to a finished aggregation and release the result
+    pub fn release(&self, aggregation: Aggregation) -> Result<QueryResult, DPError> {
+        let operation = Operation::start("dp", "apply_mechanism");
+        let items = aggregation.len();
+        let result = self.mechanism.release(aggregation, &self.config);
+        operation.finish(&result, items);
+        result
     }
 
     pub fn get_sensitivity(&self, query: &Query) -> f64 {
@@ -83,6 +163,24 @@ mod tests {
         assert!(result.has_noise());
     }
 
+    #[test]
+    fn test_dp_mechanism_batches() {
+        let mechanism = DPMechanism::new(DPConfig::default());
+        let data: Vec<DataPoint> = (0..10).map(|i| DataPoint::new(vec![i as f64])).collect();
+        let query = Query::new(QueryType::Mean, vec!["feature1".to_string()]);
+
+        let result = mechanism.apply_mechanism_batches(data.chunks(3), query.clone()).unwrap();
+        assert!(result.has_noise());
+
+        // Owned chunks, as received from a channel
+        let mut aggregation = mechanism.aggregation(query).unwrap();
+        for chunk in data.chunks(4).map(|chunk| chunk.to_vec()) {
+            aggregation.push(&chunk);
+        }
+        assert_eq!(aggregation.len(), 10);
+        assert!(mechanism.release(aggregation).unwrap().has_noise());
+    }
+
     #[test]
     fn test_dp_mechanism_gaussian() {
         let config = DPConfig {
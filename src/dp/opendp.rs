@@ -0,0 +1,162 @@
+use super::{calibration, DPError, DPMechanism, MechanismType};
+use crate::random;
+use opendp::core::{Function, Measurement, PrivacyMap, Transformation};
+use opendp::domains::{AtomDomain, VectorDomain};
+use opendp::error::Fallible;
+use opendp::measures::{MaxDivergence, ZeroConcentratedDivergence};
+use opendp::metrics::{AbsoluteDistance, SymmetricDistance};
+use opendp::{err, fallible};
+
+/// OpenDP transformation from a dataset of numbers to a single aggregate, e.g. a
+/// clamped sum, whose output `DPMechanism::apply_transformation` noises
+pub type ScalarTransformation = Transformation<
+    VectorDomain<AtomDomain<f64>>,
+    AtomDomain<f64>,
+    SymmetricDistance,
+    AbsoluteDistance<f64>,
+>;
+
+/// OpenDP measurement adding this crate's Laplace noise with the given scale
+///
+/// The privacy map sends a sensitivity `d_in` to `epsilon = d_in / scale`.
+pub fn laplace_measurement(
+    scale: f64,
+) -> Fallible<Measurement<AtomDomain<f64>, f64, AbsoluteDistance<f64>, MaxDivergence>> {
+    if !(scale.is_finite() && scale > 0.0) {
+        return fallible!(MakeMeasurement, "scale must be positive");
+    }
+
+    Measurement::new(
+        AtomDomain::new_non_nan(),
+        Function::new(move |value: &f64| {
+            value + calibration::sample_laplace(scale, &mut rand::thread_rng())
+        }),
+        AbsoluteDistance::default(),
+        MaxDivergence::default(),
+        PrivacyMap::new_fallible(move |d_in: &f64| {
+            if d_in.is_sign_negative() {
+                return fallible!(InvalidDistance, "sensitivity must be non-negative");
+            }
+            Ok(d_in / scale)
+        }),
+    )
+}
+
+/// OpenDP measurement adding this crate's Gaussian noise with the given standard
+/// deviation
+///
+/// The privacy map sends a sensitivity `d_in` to `rho = (d_in / sigma)^2 / 2`.
+pub fn gaussian_measurement(
+    sigma: f64,
+) -> Fallible<Measurement<AtomDomain<f64>, f64, AbsoluteDistance<f64>, ZeroConcentratedDivergence>>
+{
+    if !(sigma.is_finite() && sigma > 0.0) {
+        return fallible!(MakeMeasurement, "sigma must be positive");
+    }
+
+    Measurement::new(
+        AtomDomain::new_non_nan(),
+        Function::new(move |value: &f64| value + random::gaussian_noise(sigma)),
+        AbsoluteDistance::default(),
+        ZeroConcentratedDivergence::default(),
+        PrivacyMap::new_fallible(move |d_in: &f64| {
+            if d_in.is_sign_negative() {
+                return fallible!(InvalidDistance, "sensitivity must be non-negative");
+            }
+            Ok((d_in / sigma).powi(2) / 2.0)
+        }),
+    )
+}
+
+impl DPMechanism {
+    /// Wrap the mechanism as an OpenDP measurement for queries with the given
+    /// sensitivity, calibrated to the configured privacy budget
+    pub fn to_laplace_measurement(
+        &self,
+        sensitivity: f64,
+    ) -> Fallible<Measurement<AtomDomain<f64>, f64, AbsoluteDistance<f64>, MaxDivergence>> {
+        match self.config.mechanism_type {
+            MechanismType::Laplace => laplace_measurement(calibration::laplace_scale(
+                sensitivity,
+                self.config.privacy_budget.epsilon(),
+            )),
+            mechanism_type => Err(err!(
+                MakeMeasurement,
+                "{:?} mechanism is not a Laplace mechanism",
+                mechanism_type
+            )),
+        }
+    }
+
+    /// Run an OpenDP transformation on a dataset and add noise calibrated to the
+    /// sensitivity its stability map gives for datasets differing in `d_in` records
+    pub fn apply_transformation(
+        &self,
+        transformation: &ScalarTransformation,
+        data: &[f64],
+        d_in: u32,
+    ) -> Result<f64, DPError> {
+        let value = transformation
+            .invoke(&data.to_vec())
+            .map_err(|e| DPError::OpenDP(e.to_string()))?;
+        let sensitivity = transformation
+            .map(&d_in)
+            .map_err(|e| DPError::OpenDP(e.to_string()))?;
+
+        let budget = &self.config.privacy_budget;
+        let noise = match self.config.mechanism_type {
+            MechanismType::Laplace => calibration::sample_laplace(
+                calibration::laplace_scale(sensitivity, budget.epsilon()),
+                &mut rand::thread_rng(),
+            ),
+            MechanismType::Gaussian => random::gaussian_noise(calibration::gaussian_sigma(
+                sensitivity,
+                budget.epsilon(),
+                budget.delta(),
+            )),
+            MechanismType::Exponential => return Err(DPError::InvalidInput),
+        };
+
+        Ok(value + noise)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arith::PrivacyBudget;
+    use crate::dp::DPConfig;
+    use opendp::transformations::{make_clamp, then_sum};
+
+    #[test]
+    fn test_laplace_measurement_privacy_map() {
+        let measurement = laplace_measurement(2.0).unwrap();
+        assert_eq!(measurement.map(&1.0).unwrap(), 0.5);
+        assert!(measurement.check(&1.0, &0.5).unwrap());
+        assert!(measurement.invoke(&10.0).unwrap().is_finite());
+        assert!(laplace_measurement(0.0).is_err());
+
+        let mechanism = DPMechanism::new(DPConfig::default());
+        let measurement = mechanism.to_laplace_measurement(1.0).unwrap();
+        assert_eq!(measurement.map(&1.0).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_apply_transformation() -> Fallible<()> {
+        let transformation = (make_clamp(
+            VectorDomain::new(AtomDomain::new_non_nan()),
+            SymmetricDistance,
+            (0.0, 10.0),
+        )? >> then_sum())?;
+
+        let mechanism = DPMechanism::new(DPConfig {
+            privacy_budget: PrivacyBudget::new(1000.0, 1e-5),
+            mechanism_type: MechanismType::Laplace,
+        });
+        let noisy_sum = mechanism
+            .apply_transformation(&transformation, &[1.0, 2.0, 30.0], 1)
+            .unwrap();
+        assert!((noisy_sum - 13.0).abs() < 1.0);
+        Ok(())
+    }
+}
@@ -0,0 +1,342 @@
+use super::{calibration, DPError};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Seeded 64-bit hash of an item: FNV-1a over the bytes, finished with the
+/// SplitMix64 mixer
+///
+/// The hash is fixed so sketches built in different processes with the same seed
+/// can be merged.
+fn hash64(seed: u64, item: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325 ^ mix64(seed);
+    for &byte in item {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    mix64(hash)
+}
+
+fn mix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn check_budget(epsilon: f64, contribution_bound: u64) -> Result<(), DPError> {
+    if epsilon.is_nan() || epsilon <= 0.0 || contribution_bound == 0 {
+        return Err(DPError::InvalidInput);
+    }
+    Ok(())
+}
+
+/// Count-Min sketch of item frequencies, released with Laplace noise
+///
+/// Every item increments one counter in each of `depth` rows of `width` counters.
+/// Sketches with the same dimensions and seed merge by adding counters, so servers
+/// can sketch their shards and combine them before a single release. Only the
+/// release is differentially private; the raw sketch must stay with the server.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    seed: u64,
+    counters: Vec<u64>,
+}
+
+impl CountMinSketch {
+    /// Create an empty sketch. With `width = ceil(e / eta)` and
+    /// `depth = ceil(ln(1 / beta))`, an estimate exceeds the true count by more
+    /// than `eta` times the total count with probability at most `beta`.
+    pub fn new(width: usize, depth: usize, seed: u64) -> Result<Self, DPError> {
+        if width == 0 || depth == 0 {
+            return Err(DPError::InvalidInput);
+        }
+        Ok(Self {
+            width,
+            depth,
+            seed,
+            counters: vec![0; width * depth],
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn cell(&self, row: usize, item: &[u8]) -> usize {
+        let column = hash64(self.seed.wrapping_add(row as u64), item) % self.width as u64;
+        row * self.width + column as usize
+    }
+
+    /// Add `count` occurrences of an item
+    pub fn update(&mut self, item: &[u8], count: u64) {
+        for row in 0..self.depth {
+            let cell = self.cell(row, item);
+            self.counters[cell] = self.counters[cell].saturating_add(count);
+        }
+    }
+
+    /// Upper estimate of the count of an item, without noise
+    pub fn estimate(&self, item: &[u8]) -> u64 {
+        (0..self.depth)
+            .map(|row| self.counters[self.cell(row, item)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Add the counters of a sketch with the same dimensions and seed
+    pub fn merge(&mut self, other: &Self) -> Result<(), DPError> {
+        if (self.width, self.depth, self.seed) != (other.width, other.depth, other.seed) {
+            return Err(DPError::InvalidInput);
+        }
+        for (counter, other) in self.counters.iter_mut().zip(&other.counters) {
+            *counter = counter.saturating_add(*other);
+        }
+        Ok(())
+    }
+
+    /// Release the sketch with epsilon-DP, for individuals who each add at most
+    /// `contribution_bound` occurrences in total
+    ///
+    /// One individual changes the counters of every row by at most
+    /// `contribution_bound` in total, so every counter gets Laplace noise of scale
+    /// `depth * contribution_bound / epsilon`.
+    pub fn release<R: Rng + ?Sized>(
+        &self,
+        epsilon: f64,
+        contribution_bound: u64,
+        rng: &mut R,
+    ) -> Result<NoisyCountMin, DPError> {
+        check_budget(epsilon, contribution_bound)?;
+        let scale =
+            calibration::laplace_scale((self.depth as u64 * contribution_bound) as f64, epsilon);
+
+        Ok(NoisyCountMin {
+            width: self.width,
+            depth: self.depth,
+            seed: self.seed,
+            counters: self
+                .counters
+                .iter()
+                .map(|&counter| counter as f64 + calibration::sample_laplace(scale, rng))
+                .collect(),
+        })
+    }
+}
+
+/// A released `CountMinSketch`, which answers any number of frequency queries
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoisyCountMin {
+    width: usize,
+    depth: usize,
+    seed: u64,
+    counters: Vec<f64>,
+}
+
+impl NoisyCountMin {
+    /// Estimate the count of an item
+    ///
+    /// The minimum over rows would be biased down by the noise, so the estimate is
+    /// the median of the noisy counters, which is robust to both noise and hash
+    /// collisions.
+    pub fn estimate(&self, item: &[u8]) -> f64 {
+        let sketch = CountMinSketch {
+            width: self.width,
+            depth: self.depth,
+            seed: self.seed,
+            counters: Vec::new(),
+        };
+        let mut values = (0..self.depth)
+            .map(|row| self.counters[sketch.cell(row, item)])
+            .collect::<Vec<_>>();
+        values.sort_unstable_by(f64::total_cmp);
+
+        let middle = values.len() / 2;
+        if values.len() % 2 == 1 {
+            values[middle]
+        } else {
+            (values[middle - 1] + values[middle]) / 2.0
+        }
+    }
+}
+
+/// HyperLogLog sketch of the number of distinct items, released with Laplace noise
+///
+/// Every item updates one of `2^precision` registers to the maximum of its value
+/// and the position of the first set bit in the rest of the item's hash. Sketches
+/// with the same precision and seed merge by taking register maxima. Only the
+/// release is differentially private; the raw sketch must stay with the server.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HyperLogLog {
+    precision: u8,
+    seed: u64,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// Create an empty sketch with `2^precision` registers, for a precision in
+    /// `4..=16`. The relative standard error is about `1.04 / sqrt(2^precision)`.
+    pub fn new(precision: u8, seed: u64) -> Result<Self, DPError> {
+        if !(4..=16).contains(&precision) {
+            return Err(DPError::InvalidInput);
+        }
+        Ok(Self {
+            precision,
+            seed,
+            registers: vec![0; 1 << precision],
+        })
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Add an item
+    pub fn insert(&mut self, item: &[u8]) {
+        let hash = hash64(self.seed, item);
+        let index = (hash >> (64 - self.precision)) as usize;
+        let rest = hash << self.precision;
+        let rank = (rest.leading_zeros() + 1).min(64 - self.precision as u32 + 1) as u8;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    /// Take the register maxima with a sketch of the same precision and seed
+    pub fn merge(&mut self, other: &Self) -> Result<(), DPError> {
+        if (self.precision, self.seed) != (other.precision, other.seed) {
+            return Err(DPError::InvalidInput);
+        }
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+        Ok(())
+    }
+
+    /// Harmonic sum of the registers and number of empty registers
+    fn statistics(&self) -> (f64, f64) {
+        let sum = self
+            .registers
+            .iter()
+            .map(|&register| (-(register as f64)).exp2())
+            .sum();
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|&&register| register == 0)
+            .count();
+        (sum, zeros as f64)
+    }
+
+    /// Estimate the number of distinct items from the register statistics, with
+    /// linear counting for small cardinalities
+    fn estimate_from(&self, sum: f64, zeros: f64) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+
+        let estimate = alpha * m * m / sum;
+        if estimate <= 2.5 * m && zeros > 0.0 {
+            m * (m / zeros).ln()
+        } else {
+            estimate
+        }
+    }
+
+    /// Estimate the number of distinct items, without noise
+    pub fn estimate(&self) -> f64 {
+        let (sum, zeros) = self.statistics();
+        self.estimate_from(sum, zeros)
+    }
+
+    /// Release an estimate of the number of distinct items with epsilon-DP, for
+    /// individuals who each add at most `contribution_bound` distinct items
+    ///
+    /// The estimate depends on the registers only through their harmonic sum and
+    /// the number of empty registers. One individual changes at most
+    /// `contribution_bound` registers, each moving the sum by less than one and the
+    /// empty count by at most one, so both get Laplace noise of scale
+    /// `2 * contribution_bound / epsilon`, splitting epsilon evenly.
+    pub fn release<R: Rng + ?Sized>(
+        &self,
+        epsilon: f64,
+        contribution_bound: u64,
+        rng: &mut R,
+    ) -> Result<f64, DPError> {
+        check_budget(epsilon, contribution_bound)?;
+        let scale = calibration::laplace_scale(contribution_bound as f64, epsilon / 2.0);
+        let m = self.registers.len() as f64;
+        let (sum, zeros) = self.statistics();
+
+        // Clamp the noisy statistics to the values the registers can produce.
+        let min_sum = m * (-(64.0 - self.precision as f64 + 1.0)).exp2();
+        let sum = (sum + calibration::sample_laplace(scale, rng)).clamp(min_sum, m);
+        let zeros = (zeros + calibration::sample_laplace(scale, rng))
+            .round()
+            .clamp(0.0, m);
+
+        Ok(self.estimate_from(sum, zeros))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_count_min() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut left = CountMinSketch::new(272, 5, 7).unwrap();
+        let mut right = left.clone();
+        for item in 0u32..1000 {
+            left.update(&item.to_le_bytes(), 1);
+        }
+        right.update(b"popular", 500);
+        left.merge(&right).unwrap();
+
+        assert!(left.estimate(b"popular") >= 500);
+        assert!(left.estimate(b"popular") <= 500 + 1000 / 100);
+        assert!(left
+            .merge(&CountMinSketch::new(272, 4, 7).unwrap())
+            .is_err());
+
+        let released = left.release(1.0, 1, &mut rng).unwrap();
+        assert!((released.estimate(b"popular") - 500.0).abs() < 50.0);
+        assert!(left.release(0.0, 1, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_hyperloglog() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut left = HyperLogLog::new(12, 3).unwrap();
+        let mut right = left.clone();
+        for item in 0u32..60_000 {
+            left.insert(&item.to_le_bytes());
+        }
+        for item in 40_000u32..100_000 {
+            right.insert(&item.to_le_bytes());
+        }
+        left.merge(&right).unwrap();
+
+        let relative_error = |estimate: f64| (estimate / 100_000.0 - 1.0).abs();
+        assert!(relative_error(left.estimate()) < 0.05);
+        assert!(relative_error(left.release(1.0, 1, &mut rng).unwrap()) < 0.1);
+
+        let mut small = HyperLogLog::new(12, 3).unwrap();
+        for item in 0u32..100 {
+            small.insert(&item.to_le_bytes());
+        }
+        assert!((small.estimate() - 100.0).abs() < 5.0);
+        assert!(HyperLogLog::new(3, 0).is_err());
+        assert!(left.merge(&HyperLogLog::new(12, 4).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Federated analytics on a shared epoch clock.
+//!
+//! An `EpochClock` divides time into fixed-length epochs that clients and the
+//! server agree on. During an epoch each client submits one `DataPoint` under the
+//! `Nullifier` its `DeviceKey` derives for that epoch, so repeated submissions from
+//! a device are rejected without identifying it. When the epoch ends the
+//! `EpochCoordinator` closes it: the submissions are shuffled, every configured
+//! query is answered with the DP mechanism, and the results are published as an
+//! `EpochResult`. The raw submissions of an epoch are dropped once it is closed,
+//! so they are never aggregated twice.
+
+use crate::dp::{DPConfig, DPError, DPMechanism};
+use crate::report::nullifier::{Nullifier, NullifierSet};
+use crate::schema::{DataPoint, Query, QueryResult};
+use crate::shuffle::{ShuffleConfig, ShuffleError, Shuffler};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EpochError {
+    #[error("Submission for epoch {submitted} while epoch {current} is open")]
+    WrongEpoch { submitted: u64, current: u64 },
+    #[error("Duplicate submission in epoch {0}")]
+    DuplicateSubmission(u64),
+    #[error("Shuffle error: {0}")]
+    Shuffle(#[from] ShuffleError),
+    #[error("DP error: {0}")]
+    DP(#[from] DPError),
+}
+
+/// Fixed-length epochs of `length` seconds starting at the Unix time `origin`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochClock {
+    pub origin: u64,
+    pub length: u64,
+}
+
+impl EpochClock {
+    /// Create a clock. Panics if `length` is zero.
+    pub fn new(origin: u64, length: u64) -> Self {
+        assert!(length > 0, "EpochClock::new: Epochs must not be empty.");
+        Self { origin, length }
+    }
+
+    /// Return the epoch containing the Unix time `timestamp`. Times before the
+    /// origin belong to epoch 0.
+    pub fn epoch_at(&self, timestamp: u64) -> u64 {
+        timestamp.saturating_sub(self.origin) / self.length
+    }
+
+    /// Return the Unix time at which an epoch starts.
+    pub fn epoch_start(&self, epoch: u64) -> u64 {
+        self.origin + epoch * self.length
+    }
+}
+
+/// What the coordinator computes at the end of every epoch.
+pub struct EpochConfig {
+    pub shuffle_config: ShuffleConfig,
+    /// Budget of each query in each epoch. A client submits once per epoch, so
+    /// every epoch spends `queries.len()` times this budget on each client.
+    pub dp_config: DPConfig,
+    pub queries: Vec<Query>,
+    /// Epochs with fewer submissions are closed without releasing results.
+    pub min_submissions: usize,
+}
+
+impl EpochConfig {
+    /// Create a configuration with the default shuffle and DP parameters.
+    pub fn new(queries: Vec<Query>) -> Self {
+        Self {
+            shuffle_config: ShuffleConfig::default(),
+            dp_config: DPConfig::default(),
+            queries,
+            min_submissions: 1,
+        }
+    }
+}
+
+/// The published results of a closed epoch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EpochResult {
+    pub epoch: u64,
+    pub submissions: usize,
+    /// One result per configured query, or none if the epoch had fewer than
+    /// `min_submissions` submissions.
+    pub results: Vec<QueryResult>,
+}
+
+impl EpochResult {
+    /// Whether the results were withheld for lack of submissions.
+    pub fn is_suppressed(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+/// Collects the submissions of the open epoch and publishes the results of the
+/// closed ones.
+pub struct EpochCoordinator {
+    clock: EpochClock,
+    current_epoch: u64,
+    pending: Vec<DataPoint>,
+    nullifiers: NullifierSet,
+    shuffler: Shuffler,
+    dp_mechanism: DPMechanism,
+    queries: Vec<Query>,
+    min_submissions: usize,
+    published: BTreeMap<u64, EpochResult>,
+}
+
+impl EpochCoordinator {
+    /// Create a coordinator whose open epoch is the one containing `now`.
+    pub fn new(config: EpochConfig, clock: EpochClock, now: u64) -> Self {
+        Self {
+            clock,
+            current_epoch: clock.epoch_at(now),
+            pending: Vec::new(),
+            nullifiers: NullifierSet::new(),
+            shuffler: Shuffler::new(config.shuffle_config),
+            dp_mechanism: DPMechanism::new(config.dp_config),
+            queries: config.queries,
+            min_submissions: config.min_submissions,
+            published: BTreeMap::new(),
+        }
+    }
+
+    /// Return the shared clock.
+    pub fn clock(&self) -> EpochClock {
+        self.clock
+    }
+
+    /// Return the open epoch.
+    pub fn current_epoch(&self) -> u64 {
+        self.current_epoch
+    }
+
+    /// Return the number of submissions to the open epoch.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Accept a client's submission to the open epoch. The nullifier must be the
+    /// one the client's `DeviceKey` derives for `epoch`.
+    pub fn submit(
+        &mut self,
+        epoch: u64,
+        nullifier: Nullifier,
+        point: DataPoint,
+    ) -> Result<(), EpochError> {
+        if epoch != self.current_epoch {
+            return Err(EpochError::WrongEpoch {
+                submitted: epoch,
+                current: self.current_epoch,
+            });
+        }
+        if !self.nullifiers.insert(epoch, nullifier) {
+            return Err(EpochError::DuplicateSubmission(epoch));
+        }
+
+        self.pending.push(point);
+        Ok(())
+    }
+
+    /// Close the open epoch, publish its results, and open the next one.
+    ///
+    /// The epoch is closed and its submissions dropped even if aggregation fails,
+    /// in which case nothing is published for it.
+    pub fn close_epoch(&mut self) -> Result<&EpochResult, EpochError> {
+        let epoch = self.current_epoch;
+        let submissions = std::mem::take(&mut self.pending);
+        self.current_epoch += 1;
+        self.nullifiers.drop_epochs_before(self.current_epoch);
+
+        let count = submissions.len();
+        let results = if count < self.min_submissions.max(1) {
+            Vec::new()
+        } else {
+            let shuffled = self.shuffler.shuffle_data(submissions)?;
+            self.queries
+                .iter()
+                .map(|query| {
+                    self.dp_mechanism
+                        .apply_mechanism(shuffled.clone(), query.clone())
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let result = EpochResult {
+            epoch,
+            submissions: count,
+            results,
+        };
+        Ok(self.published.entry(epoch).or_insert(result))
+    }
+
+    /// Close every epoch that has ended by the Unix time `now`. Returns the closed
+    /// epochs; those without submissions are published as suppressed.
+    pub fn advance_to(&mut self, now: u64) -> Result<Vec<u64>, EpochError> {
+        let mut closed = Vec::new();
+        while self.current_epoch < self.clock.epoch_at(now) {
+            closed.push(self.close_epoch()?.epoch);
+        }
+        Ok(closed)
+    }
+
+    /// Return the published results of an epoch.
+    pub fn result(&self, epoch: u64) -> Option<&EpochResult> {
+        self.published.get(&epoch)
+    }
+
+    /// Return the published results in increasing order of epoch.
+    pub fn results(&self) -> impl Iterator<Item = &EpochResult> + '_ {
+        self.published.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::nullifier::DeviceKey;
+    use crate::schema::QueryType;
+    use rand::rngs::OsRng;
+
+    fn coordinator() -> EpochCoordinator {
+        let mut config = EpochConfig::new(vec![
+            Query::new(QueryType::Mean, vec!["feature1".to_string()]),
+            Query::new(QueryType::Histogram, vec!["feature2".to_string()]),
+        ]);
+        config.min_submissions = 2;
+        EpochCoordinator::new(config, EpochClock::new(1_000, 60), 1_130)
+    }
+
+    #[test]
+    fn test_epoch_clock() {
+        let clock = EpochClock::new(1_000, 60);
+        assert_eq!(clock.epoch_at(999), 0);
+        assert_eq!(clock.epoch_at(1_059), 0);
+        assert_eq!(clock.epoch_at(1_060), 1);
+        assert_eq!(clock.epoch_start(2), 1_120);
+    }
+
+    #[test]
+    fn test_submit_and_close() {
+        let mut coordinator = coordinator();
+        assert_eq!(coordinator.current_epoch(), 2);
+
+        let devices = (0..3)
+            .map(|_| DeviceKey::random(&mut OsRng))
+            .collect::<Vec<_>>();
+        for (i, device) in devices.iter().enumerate() {
+            let point = DataPoint::new(vec![i as f64, 1.0]);
+            coordinator.submit(2, device.nullifier(2), point).unwrap();
+        }
+        assert!(matches!(
+            coordinator.submit(2, devices[0].nullifier(2), DataPoint::new(vec![0.0, 0.0])),
+            Err(EpochError::DuplicateSubmission(2))
+        ));
+        assert!(matches!(
+            coordinator.submit(3, devices[0].nullifier(3), DataPoint::new(vec![0.0, 0.0])),
+            Err(EpochError::WrongEpoch {
+                submitted: 3,
+                current: 2
+            })
+        ));
+
+        let result = coordinator.close_epoch().unwrap();
+        assert_eq!(result.submissions, 3);
+        assert_eq!(result.results.len(), 2);
+        assert_eq!(coordinator.current_epoch(), 3);
+        assert_eq!(coordinator.pending_len(), 0);
+
+        // A device submits again in the next epoch under a new nullifier.
+        coordinator
+            .submit(3, devices[0].nullifier(3), DataPoint::new(vec![0.0, 0.0]))
+            .unwrap();
+        assert_eq!(coordinator.advance_to(1_300).unwrap(), vec![3, 4]);
+        assert!(coordinator.result(3).unwrap().is_suppressed());
+        assert!(coordinator.result(4).unwrap().is_suppressed());
+        assert_eq!(
+            coordinator.results().map(|r| r.epoch).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Accuracy evaluation of the DP mechanisms against ground truth.
+//!
+//! `evaluate` sweeps a list of epsilon values, runs a query through a `DPMechanism`
+//! many times at each epsilon, and summarizes the error of the noisy answers
+//! against the exact answer of a `GroundTruth`: bias, mean squared error, and
+//! quantiles of the absolute error. The resulting `EvaluationReport` is written as
+//! CSV or JSON, one row per epsilon, for plotting error against epsilon before
+//! choosing the parameters of a deployment.
+
+use crate::arith::PrivacyBudget;
+use crate::datagen::GroundTruth;
+use crate::dp::{DPConfig, DPError, DPMechanism, MechanismType};
+use crate::schema::{DataPoint, Query, QueryType};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EvaluationError {
+    #[error("Invalid evaluation configuration: {0}")]
+    InvalidConfig(String),
+    #[error("The ground truth cannot answer the query")]
+    UnknownFeature,
+    #[error("The mechanism returned {found} values where the ground truth has {expected}")]
+    LengthMismatch { expected: usize, found: usize },
+    #[error("DP error: {0}")]
+    DP(#[from] DPError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Parameters of an epsilon sweep.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvaluationConfig {
+    pub epsilons: Vec<f64>,
+    /// Delta of the privacy budget, used by the Gaussian mechanism.
+    pub delta: f64,
+    pub mechanism_type: MechanismType,
+    /// Number of runs of the query at each epsilon.
+    pub trials: usize,
+    /// Quantiles of the absolute error to report, each in `[0, 1]`.
+    pub quantiles: Vec<f64>,
+}
+
+impl Default for EvaluationConfig {
+    fn default() -> Self {
+        Self {
+            epsilons: vec![0.1, 0.2, 0.5, 1.0, 2.0, 5.0],
+            delta: 1e-5,
+            mechanism_type: MechanismType::Laplace,
+            trials: 100,
+            quantiles: vec![0.5, 0.9, 0.99],
+        }
+    }
+}
+
+impl EvaluationConfig {
+    fn validate(&self) -> Result<(), EvaluationError> {
+        if self.epsilons.is_empty() || self.epsilons.iter().any(|e| !(*e > 0.0 && e.is_finite())) {
+            return Err(EvaluationError::InvalidConfig(
+                "epsilons must be a non-empty list of positive values".to_string(),
+            ));
+        }
+        if !(self.delta > 0.0 && self.delta < 1.0) {
+            return Err(EvaluationError::InvalidConfig(
+                "delta must be in (0, 1)".to_string(),
+            ));
+        }
+        if self.trials == 0 {
+            return Err(EvaluationError::InvalidConfig(
+                "trials must be positive".to_string(),
+            ));
+        }
+        if self.quantiles.iter().any(|q| !(0.0..=1.0).contains(q)) {
+            return Err(EvaluationError::InvalidConfig(
+                "quantiles must be in [0, 1]".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Error statistics at one epsilon, pooled over all trials and all values of the
+/// query result. The error of a value is the noisy value minus the true value.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErrorSummary {
+    pub epsilon: f64,
+    pub bias: f64,
+    pub mse: f64,
+    pub mean_abs_error: f64,
+    /// Quantiles of the absolute error, in the order of `EvaluationConfig::quantiles`.
+    pub abs_error_quantiles: Vec<f64>,
+}
+
+impl ErrorSummary {
+    /// Summarize the errors measured at `epsilon`.
+    pub fn from_errors(epsilon: f64, errors: &[f64], quantiles: &[f64]) -> Self {
+        let n = errors.len().max(1) as f64;
+        let mut abs_errors = errors.iter().map(|e| e.abs()).collect::<Vec<_>>();
+        abs_errors.sort_unstable_by(f64::total_cmp);
+
+        Self {
+            epsilon,
+            bias: errors.iter().sum::<f64>() / n,
+            mse: errors.iter().map(|e| e * e).sum::<f64>() / n,
+            mean_abs_error: abs_errors.iter().sum::<f64>() / n,
+            abs_error_quantiles: quantiles
+                .iter()
+                .map(|&q| quantile(&abs_errors, q))
+                .collect(),
+        }
+    }
+
+    /// Root mean squared error.
+    pub fn rmse(&self) -> f64 {
+        self.mse.sqrt()
+    }
+}
+
+/// Quantile of sorted values, interpolating linearly between ranks.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+
+    let rank = q * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (rank - lower as f64) * (sorted[upper] - sorted[lower])
+}
+
+/// Result of an epsilon sweep.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvaluationReport {
+    pub query_type: QueryType,
+    pub mechanism_type: MechanismType,
+    pub delta: f64,
+    pub trials: usize,
+    pub quantiles: Vec<f64>,
+    /// One summary per epsilon, in the order of `EvaluationConfig::epsilons`.
+    pub summaries: Vec<ErrorSummary>,
+}
+
+impl EvaluationReport {
+    /// Write the report as CSV with a header row and one row per epsilon. The
+    /// quantile columns are named after the percentile, e.g. `abs_error_p90`.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> Result<(), EvaluationError> {
+        write!(writer, "epsilon,bias,mse,rmse,mean_abs_error")?;
+        for q in &self.quantiles {
+            write!(writer, ",abs_error_p{}", q * 100.0)?;
+        }
+        writeln!(writer)?;
+
+        for summary in &self.summaries {
+            write!(
+                writer,
+                "{},{},{},{},{}",
+                summary.epsilon,
+                summary.bias,
+                summary.mse,
+                summary.rmse(),
+                summary.mean_abs_error
+            )?;
+            for value in &summary.abs_error_quantiles {
+                write!(writer, ",{}", value)?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the report as pretty-printed JSON.
+    pub fn write_json<W: Write>(&self, writer: W) -> Result<(), EvaluationError> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+/// Run `query` on `data` `config.trials` times at each epsilon of the sweep and
+/// summarize the errors against `truth`, which must describe `data`.
+///
+/// The mechanisms release histogram counts without their values, so histogram
+/// counts are compared with the true counts in increasing order of count.
+pub fn evaluate(
+    data: &[DataPoint],
+    truth: &GroundTruth,
+    query: &Query,
+    config: &EvaluationConfig,
+) -> Result<EvaluationReport, EvaluationError> {
+    config.validate()?;
+    let mut expected = truth.answer(query).ok_or(EvaluationError::UnknownFeature)?;
+    if query.query_type == QueryType::Histogram {
+        expected.sort_unstable_by(f64::total_cmp);
+    }
+
+    let mut summaries = Vec::with_capacity(config.epsilons.len());
+    for &epsilon in &config.epsilons {
+        let mechanism = DPMechanism::new(DPConfig {
+            privacy_budget: PrivacyBudget::new(epsilon, config.delta),
+            mechanism_type: config.mechanism_type,
+        });
+
+        let mut errors = Vec::with_capacity(config.trials * expected.len());
+        for _ in 0..config.trials {
+            let result = mechanism.apply_mechanism(data.to_vec(), query.clone())?;
+            let mut values = result.values().to_vec();
+            if values.len() != expected.len() {
+                return Err(EvaluationError::LengthMismatch {
+                    expected: expected.len(),
+                    found: values.len(),
+                });
+            }
+            if query.query_type == QueryType::Histogram {
+                values.sort_unstable_by(f64::total_cmp);
+            }
+            errors.extend(values.iter().zip(&expected).map(|(v, e)| v - e));
+        }
+
+        summaries.push(ErrorSummary::from_errors(
+            epsilon,
+            &errors,
+            &config.quantiles,
+        ));
+    }
+
+    Ok(EvaluationReport {
+        query_type: query.query_type.clone(),
+        mechanism_type: config.mechanism_type,
+        delta: config.delta,
+        trials: config.trials,
+        quantiles: config.quantiles.clone(),
+        summaries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datagen::PopulationSpec;
+    use crate::schema::Schema;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_error_summary() {
+        let summary =
+            ErrorSummary::from_errors(1.0, &[-1.0, 1.0, 3.0, -3.0, 5.0], &[0.0, 0.5, 1.0]);
+        assert_eq!(summary.bias, 1.0);
+        assert_eq!(summary.mse, 13.0);
+        assert_eq!(summary.mean_abs_error, 2.6);
+        assert_eq!(summary.abs_error_quantiles, vec![1.0, 3.0, 5.0]);
+        assert_eq!(quantile(&[0.0, 10.0], 0.25), 2.5);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let schema = Schema::try_from(r#"[["a",{"n8":100}],["b","c3"]]"#).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let population = PopulationSpec::new(schema).generate(200, &mut rng).unwrap();
+        let query = Query::new(QueryType::Mean, vec!["feature1".to_string()]);
+        let config = EvaluationConfig {
+            epsilons: vec![0.1, 10.0],
+            trials: 200,
+            ..Default::default()
+        };
+
+        let report = evaluate(&population.data, &population.truth, &query, &config).unwrap();
+        assert_eq!(report.summaries.len(), 2);
+        assert!(report.summaries[0].mse > report.summaries[1].mse);
+        assert!(
+            report.summaries[0].abs_error_quantiles[2]
+                >= report.summaries[0].abs_error_quantiles[0]
+        );
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("epsilon,bias,mse,rmse,mean_abs_error,abs_error_p50,abs_error_p90,abs_error_p99")
+        );
+        assert_eq!(lines.count(), 2);
+
+        let mut json = Vec::new();
+        report.write_json(&mut json).unwrap();
+        let parsed: EvaluationReport = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed, report);
+
+        let bad = EvaluationConfig {
+            epsilons: vec![0.0],
+            ..Default::default()
+        };
+        assert!(matches!(
+            evaluate(&population.data, &population.truth, &query, &bad),
+            Err(EvaluationError::InvalidConfig(_))
+        ));
+        let unknown = Query::new(QueryType::Mean, vec!["feature9".to_string()]);
+        assert!(matches!(
+            evaluate(&population.data, &population.truth, &unknown, &config),
+            Err(EvaluationError::UnknownFeature)
+        ));
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! C API around the shuffle and DP pipeline, declared in `include/doppio.h`.
+//!
+//! Shufflers and mechanisms are opaque handles owned by the caller and released
+//! with the matching `_free` function. Data crosses the boundary as byte buffers:
+//! a data batch is a `u32` row count and a `u32` feature count followed by the
+//! features as row-major `f64`s, all little-endian. Buffers returned by the
+//! library must be released with `doppio_buffer_free`.
+
+use crate::arith::PrivacyBudget;
+use crate::dp::{DPConfig, DPMechanism, MechanismType};
+use crate::schema::{DataPoint, Query, QueryType};
+use crate::shuffle::{ShuffleConfig, Shuffler};
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::mem::size_of;
+use std::ptr;
+use std::slice;
+
+/// Status codes returned by the C API.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoppioStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidInput = 2,
+    ShuffleFailed = 3,
+    MechanismFailed = 4,
+}
+
+/// A byte buffer allocated by the library.
+#[repr(C)]
+pub struct DoppioBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// Opaque handle to a `Shuffler`.
+pub struct DoppioShuffler(Shuffler);
+
+/// Opaque handle to a `DPMechanism`.
+pub struct DoppioMechanism(DPMechanism);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Record the message of the last error on this thread.
+fn set_last_error(message: impl ToString) {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Decode a data batch from its byte encoding.
+fn decode_data_points(bytes: &[u8]) -> Result<Vec<DataPoint>, String> {
+    let header_size = 2 * size_of::<u32>();
+    if bytes.len() < header_size {
+        return Err("Data batch is shorter than its header".to_string());
+    }
+
+    let rows = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
+    let cols = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+    let expected = rows
+        .checked_mul(cols)
+        .and_then(|count| count.checked_mul(size_of::<f64>()))
+        .and_then(|size| size.checked_add(header_size));
+    if expected != Some(bytes.len()) {
+        return Err(format!(
+            "Data batch of {} bytes does not hold {} rows of {} features",
+            bytes.len(),
+            rows,
+            cols
+        ));
+    }
+
+    let values = bytes[header_size..]
+        .chunks_exact(size_of::<f64>())
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect::<Vec<_>>();
+    Ok(values
+        .chunks(cols.max(1))
+        .take(rows)
+        .map(|row| DataPoint::new(row.to_vec()))
+        .collect())
+}
+
+/// Encode a data batch into its byte encoding.
+fn encode_data_points(data: &[DataPoint]) -> Vec<u8> {
+    let cols = data.first().map_or(0, |point| point.features().len());
+    let mut bytes = Vec::with_capacity(8 + data.len() * cols * size_of::<f64>());
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(cols as u32).to_le_bytes());
+    for point in data {
+        for value in point.features() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Hand a byte vector to the caller as a `DoppioBuffer`.
+unsafe fn write_buffer(bytes: Vec<u8>, output: *mut DoppioBuffer) {
+    let mut bytes = bytes.into_boxed_slice();
+    let buffer = DoppioBuffer {
+        data: bytes.as_mut_ptr(),
+        len: bytes.len(),
+    };
+    std::mem::forget(bytes);
+    ptr::write(output, buffer);
+}
+
+/// Borrow an input byte buffer, treating a null pointer as empty only if the
+/// length is zero.
+unsafe fn read_input<'a>(input: *const u8, input_len: usize) -> Option<&'a [u8]> {
+    if input.is_null() {
+        return (input_len == 0).then_some(&[]);
+    }
+    Some(slice::from_raw_parts(input, input_len))
+}
+
+/// Return the message of the last error on this thread, or null if there was
+/// none. The string stays valid until the next call into the library.
+#[no_mangle]
+pub extern "C" fn doppio_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Release a buffer returned by the library.
+///
+/// # Safety
+/// The buffer must have been returned by this library and not been freed before.
+#[no_mangle]
+pub unsafe extern "C" fn doppio_buffer_free(buffer: DoppioBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(slice::from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
+
+/// Create a shuffler that runs the given number of shuffle rounds.
+#[no_mangle]
+pub extern "C" fn doppio_shuffler_new(rounds: u32) -> *mut DoppioShuffler {
+    let config = ShuffleConfig::builder()
+        .shuffle_rounds(rounds.max(1) as usize)
+        .build();
+    Box::into_raw(Box::new(DoppioShuffler(Shuffler::new(config))))
+}
+
+/// Release a shuffler.
+///
+/// # Safety
+/// The handle must have been returned by `doppio_shuffler_new` and not been freed
+/// before.
+#[no_mangle]
+pub unsafe extern "C" fn doppio_shuffler_free(shuffler: *mut DoppioShuffler) {
+    if !shuffler.is_null() {
+        drop(Box::from_raw(shuffler));
+    }
+}
+
+/// Shuffle a data batch. On success the shuffled batch is written to `output`.
+///
+/// # Safety
+/// `shuffler` must be a live handle, `input` must point to `input_len` readable
+/// bytes, and `output` must point to writable memory for a `DoppioBuffer`.
+#[no_mangle]
+pub unsafe extern "C" fn doppio_shuffle(
+    shuffler: *mut DoppioShuffler,
+    input: *const u8,
+    input_len: usize,
+    output: *mut DoppioBuffer,
+) -> DoppioStatus {
+    if shuffler.is_null() || output.is_null() {
+        set_last_error("Null shuffler or output pointer");
+        return DoppioStatus::NullPointer;
+    }
+    let Some(input) = read_input(input, input_len) else {
+        set_last_error("Null input pointer");
+        return DoppioStatus::NullPointer;
+    };
+
+    let data = match decode_data_points(input) {
+        Ok(data) => data,
+        Err(e) => {
+            set_last_error(e);
+            return DoppioStatus::InvalidInput;
+        }
+    };
+
+    match (*shuffler).0.shuffle_data(data) {
+        Ok(shuffled) => {
+            write_buffer(encode_data_points(&shuffled), output);
+            DoppioStatus::Ok
+        }
+        Err(e) => {
+            set_last_error(e);
+            DoppioStatus::ShuffleFailed
+        }
+    }
+}
+
+/// Create a DP mechanism. `mechanism_type` is 0 for Laplace, 1 for Gaussian, and 2
+/// for the exponential mechanism. Returns null for an unknown mechanism type or an
+/// invalid budget.
+#[no_mangle]
+pub extern "C" fn doppio_mechanism_new(
+    mechanism_type: u32,
+    epsilon: f64,
+    delta: f64,
+) -> *mut DoppioMechanism {
+    let mechanism_type = match mechanism_type {
+        0 => MechanismType::Laplace,
+        1 => MechanismType::Gaussian,
+        2 => MechanismType::Exponential,
+        _ => {
+            set_last_error(format!("Unknown mechanism type {}", mechanism_type));
+            return ptr::null_mut();
+        }
+    };
+    if epsilon.is_nan() || epsilon <= 0.0 || !(0.0..1.0).contains(&delta) {
+        set_last_error(format!("Invalid privacy budget ({}, {})", epsilon, delta));
+        return ptr::null_mut();
+    }
+
+    let config = DPConfig {
+        privacy_budget: PrivacyBudget::new(epsilon, delta),
+        mechanism_type,
+    };
+    Box::into_raw(Box::new(DoppioMechanism(DPMechanism::new(config))))
+}
+
+/// Release a DP mechanism.
+///
+/// # Safety
+/// The handle must have been returned by `doppio_mechanism_new` and not been freed
+/// before.
+#[no_mangle]
+pub unsafe extern "C" fn doppio_mechanism_free(mechanism: *mut DoppioMechanism) {
+    if !mechanism.is_null() {
+        drop(Box::from_raw(mechanism));
+    }
+}
+
+/// Run a query on a data batch under the mechanism. `query_type` is 0 for a mean
+/// and 1 for a histogram query, over the zero-based feature columns in
+/// `features`. On success the noisy result is written to `output` as a `u32`
+/// count followed by that many little-endian `f64`s.
+///
+/// # Safety
+/// `mechanism` must be a live handle, `input` must point to `input_len` readable
+/// bytes, `features` to `feature_count` readable `u32`s, and `output` to writable
+/// memory for a `DoppioBuffer`.
+#[no_mangle]
+pub unsafe extern "C" fn doppio_apply_mechanism(
+    mechanism: *const DoppioMechanism,
+    input: *const u8,
+    input_len: usize,
+    query_type: u32,
+    features: *const u32,
+    feature_count: usize,
+    output: *mut DoppioBuffer,
+) -> DoppioStatus {
+    if mechanism.is_null() || output.is_null() || (features.is_null() && feature_count > 0) {
+        set_last_error("Null mechanism, feature, or output pointer");
+        return DoppioStatus::NullPointer;
+    }
+    let Some(input) = read_input(input, input_len) else {
+        set_last_error("Null input pointer");
+        return DoppioStatus::NullPointer;
+    };
+
+    let query_type = match query_type {
+        0 => QueryType::Mean,
+        1 => QueryType::Histogram,
+        _ => {
+            set_last_error(format!("Unsupported query type {}", query_type));
+            return DoppioStatus::InvalidInput;
+        }
+    };
+    let features = if feature_count == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(features, feature_count)
+    };
+    let query = Query::new(
+        query_type,
+        features
+            .iter()
+            .map(|index| format!("feature{}", index + 1))
+            .collect(),
+    );
+
+    let data = match decode_data_points(input) {
+        Ok(data) => data,
+        Err(e) => {
+            set_last_error(e);
+            return DoppioStatus::InvalidInput;
+        }
+    };
+
+    match (*mechanism).0.apply_mechanism(data, query) {
+        Ok(result) => {
+            let mut bytes = (result.values().len() as u32).to_le_bytes().to_vec();
+            for value in result.values() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            write_buffer(bytes, output);
+            DoppioStatus::Ok
+        }
+        Err(e) => {
+            set_last_error(e);
+            DoppioStatus::MechanismFailed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_rows(rows: &[[f64; 2]]) -> Vec<u8> {
+        encode_data_points(
+            &rows
+                .iter()
+                .map(|row| DataPoint::new(row.to_vec()))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn take_buffer(buffer: DoppioBuffer) -> Vec<u8> {
+        let bytes = unsafe { slice::from_raw_parts(buffer.data, buffer.len).to_vec() };
+        unsafe { doppio_buffer_free(buffer) };
+        bytes
+    }
+
+    #[test]
+    fn test_shuffle_round_trip() {
+        let input = encode_rows(&[[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]]);
+        let shuffler = doppio_shuffler_new(3);
+        let mut output = DoppioBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+
+        let status = unsafe { doppio_shuffle(shuffler, input.as_ptr(), input.len(), &mut output) };
+        assert_eq!(status, DoppioStatus::Ok);
+        let mut shuffled = decode_data_points(&take_buffer(output))
+            .unwrap()
+            .iter()
+            .map(|point| point.features()[0])
+            .collect::<Vec<_>>();
+        shuffled.sort_by(f64::total_cmp);
+        assert_eq!(shuffled, vec![1.0, 3.0, 5.0]);
+
+        // A truncated batch is rejected with a message.
+        let status = unsafe { doppio_shuffle(shuffler, input.as_ptr(), 9, &mut output) };
+        assert_eq!(status, DoppioStatus::InvalidInput);
+        assert!(!doppio_last_error().is_null());
+
+        unsafe { doppio_shuffler_free(shuffler) };
+    }
+
+    #[test]
+    fn test_apply_mechanism() {
+        assert!(doppio_mechanism_new(7, 1.0, 1e-5).is_null());
+        assert!(doppio_mechanism_new(0, -1.0, 1e-5).is_null());
+
+        let input = encode_rows(&[[1.0, 2.0], [3.0, 4.0]]);
+        let mechanism = doppio_mechanism_new(0, 1.0, 1e-5);
+        let features = [0u32, 1];
+        let mut output = DoppioBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        };
+
+        let status = unsafe {
+            doppio_apply_mechanism(
+                mechanism,
+                input.as_ptr(),
+                input.len(),
+                0,
+                features.as_ptr(),
+                features.len(),
+                &mut output,
+            )
+        };
+        assert_eq!(status, DoppioStatus::Ok);
+        let bytes = take_buffer(output);
+        assert_eq!(u32::from_le_bytes(bytes[..4].try_into().unwrap()), 2);
+        assert_eq!(bytes.len(), 4 + 2 * size_of::<f64>());
+
+        unsafe { doppio_mechanism_free(mechanism) };
+    }
+}
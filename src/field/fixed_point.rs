@@ -0,0 +1,113 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Fixed-point encoding of real numbers as field elements.
+//!
+//! A real `x` is encoded as `round(x * 2^fractional_bits)`, with negative values
+//! wrapping around the modulus. The encoding is additive: the sum of encodings
+//! decodes to the sum of the reals, as long as the sum stays within
+//! `max_magnitude`. Encoding and decoding use only `core` float operations.
+
+use super::{FieldError, PrimeField};
+use serde::{Deserialize, Serialize};
+
+/// Encodes reals with a fixed number of fractional bits into a `PrimeField`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixedPointCodec {
+    field: PrimeField,
+    fractional_bits: u32,
+}
+
+impl FixedPointCodec {
+    /// Create a codec with `fractional_bits` bits after the binary point, at most 52.
+    pub fn new(field: PrimeField, fractional_bits: u32) -> Result<Self, FieldError> {
+        if fractional_bits > 52 {
+            return Err(FieldError::Unrepresentable);
+        }
+        Ok(Self {
+            field,
+            fractional_bits,
+        })
+    }
+
+    /// Return the field.
+    pub fn field(&self) -> &PrimeField {
+        &self.field
+    }
+
+    /// Return the number of fractional bits.
+    pub fn fractional_bits(&self) -> u32 {
+        self.fractional_bits
+    }
+
+    fn scale(&self) -> f64 {
+        (1u64 << self.fractional_bits) as f64
+    }
+
+    /// Return the largest encoded integer magnitude; larger values would wrap
+    /// into the other sign.
+    fn max_encoded(&self) -> u64 {
+        (self.field.modulus() - 1) / 2
+    }
+
+    /// Return the largest magnitude of a real that can be encoded.
+    pub fn max_magnitude(&self) -> f64 {
+        self.max_encoded() as f64 / self.scale()
+    }
+
+    /// Encode a real, rounding to the nearest multiple of `2^-fractional_bits`.
+    pub fn encode(&self, x: f64) -> Result<u64, FieldError> {
+        if !x.is_finite() {
+            return Err(FieldError::Unrepresentable);
+        }
+
+        let scaled = x * self.scale();
+        let magnitude = (if scaled < 0.0 { -scaled } else { scaled }) + 0.5;
+        if magnitude >= self.max_encoded() as f64 + 1.0 {
+            return Err(FieldError::Unrepresentable);
+        }
+
+        // Truncating `|scaled| + 0.5` rounds half away from zero.
+        let magnitude = (magnitude as u64).min(self.max_encoded());
+        Ok(if scaled < 0.0 {
+            self.field.neg(magnitude)
+        } else {
+            magnitude
+        })
+    }
+
+    /// Decode a field element, reading values above half the modulus as negative.
+    pub fn decode(&self, value: u64) -> f64 {
+        let value = self.field.reduce(value);
+        if value > self.max_encoded() {
+            -((self.field.modulus() - value) as f64) / self.scale()
+        } else {
+            value as f64 / self.scale()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_point() {
+        let field = PrimeField::new(2_147_483_647).unwrap();
+        let codec = FixedPointCodec::new(field, 8).unwrap();
+
+        assert_eq!(codec.decode(codec.encode(1.5).unwrap()), 1.5);
+        assert_eq!(codec.decode(codec.encode(-2.25).unwrap()), -2.25);
+        assert_eq!(codec.encode(1.0 / 512.0).unwrap(), 1);
+
+        let sum = [3.5, -1.25, 0.75]
+            .iter()
+            .map(|&x| codec.encode(x).unwrap())
+            .fold(0, |acc, x| field.add(acc, x));
+        assert_eq!(codec.decode(sum), 3.0);
+
+        assert!(codec.encode(codec.max_magnitude() * 2.0).is_err());
+        assert!(codec.encode(f64::NAN).is_err());
+        assert!(FixedPointCodec::new(field, 53).is_err());
+    }
+}
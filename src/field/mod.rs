@@ -0,0 +1,196 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Field arithmetic, Shamir shares, and fixed-point codecs that build without
+//! `std`.
+//!
+//! This module only needs `alloc`, so embedded and secure-enclave components can
+//! enable the `core` feature without the default `std` feature and produce
+//! shares with exactly the code the servers use. The `multi_party` module builds
+//! its `ShamirSecretSharing` on top of it.
+
+pub mod fixed_point;
+pub mod shamir;
+
+pub use fixed_point::FixedPointCodec;
+pub use shamir::SecretShare;
+
+use core::fmt;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Errors from the field arithmetic, sharing, and encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldError {
+    /// The modulus is smaller than 2.
+    InvalidModulus(u64),
+    /// A value is not smaller than the modulus.
+    OutOfRange { value: u64, modulus: u64 },
+    /// The threshold is below 2 or above the number of shares.
+    InvalidThreshold { threshold: usize, num_shares: usize },
+    /// Fewer shares than the threshold were given.
+    InsufficientShares { available: usize, required: usize },
+    /// Two shares have the same ID.
+    DuplicateShareId(usize),
+    /// A value has no inverse modulo the modulus.
+    NotInvertible,
+    /// A real number cannot be encoded with the codec.
+    Unrepresentable,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidModulus(modulus) => write!(f, "Modulus {} is too small", modulus),
+            Self::OutOfRange { value, modulus } => {
+                write!(f, "Value {} must be less than modulus {}", value, modulus)
+            }
+            Self::InvalidThreshold {
+                threshold,
+                num_shares,
+            } => write!(
+                f,
+                "Threshold {} must be between 2 and the number of shares {}",
+                threshold, num_shares
+            ),
+            Self::InsufficientShares {
+                available,
+                required,
+            } => write!(
+                f,
+                "{} shares are fewer than the threshold {}",
+                available, required
+            ),
+            Self::DuplicateShareId(id) => write!(f, "Duplicate share ID {}", id),
+            Self::NotInvertible => write!(f, "Share IDs are not invertible modulo the field"),
+            Self::Unrepresentable => write!(f, "Value is not representable by the codec"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FieldError {}
+
+/// Integers modulo `modulus`, which must be prime for division and sharing.
+/// Products are computed in `u128`, so any `u64` modulus works.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PrimeField {
+    modulus: u64,
+}
+
+impl PrimeField {
+    /// Create the field of integers modulo `modulus`. Primality is not checked.
+    pub fn new(modulus: u64) -> Result<Self, FieldError> {
+        if modulus < 2 {
+            return Err(FieldError::InvalidModulus(modulus));
+        }
+        Ok(Self { modulus })
+    }
+
+    /// Return the modulus.
+    pub fn modulus(&self) -> u64 {
+        self.modulus
+    }
+
+    /// Reduce any integer into the field.
+    pub fn reduce(&self, a: u64) -> u64 {
+        a % self.modulus
+    }
+
+    /// Check that a value is a reduced field element.
+    pub fn check(&self, a: u64) -> Result<u64, FieldError> {
+        if a < self.modulus {
+            Ok(a)
+        } else {
+            Err(FieldError::OutOfRange {
+                value: a,
+                modulus: self.modulus,
+            })
+        }
+    }
+
+    /// Modular addition.
+    pub fn add(&self, a: u64, b: u64) -> u64 {
+        ((a as u128 + b as u128) % self.modulus as u128) as u64
+    }
+
+    /// Modular subtraction.
+    pub fn sub(&self, a: u64, b: u64) -> u64 {
+        ((a as u128 + self.modulus as u128 - (b % self.modulus) as u128) % self.modulus as u128)
+            as u64
+    }
+
+    /// Modular negation.
+    pub fn neg(&self, a: u64) -> u64 {
+        self.sub(0, a)
+    }
+
+    /// Modular multiplication.
+    pub fn mul(&self, a: u64, b: u64) -> u64 {
+        ((a as u128 * b as u128) % self.modulus as u128) as u64
+    }
+
+    /// Modular exponentiation by squaring.
+    pub fn pow(&self, base: u64, mut exponent: u64) -> u64 {
+        let mut result = 1 % self.modulus;
+        let mut base = self.reduce(base);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = self.mul(result, base);
+            }
+            base = self.mul(base, base);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    /// Modular multiplicative inverse, if one exists.
+    pub fn inv(&self, a: u64) -> Option<u64> {
+        let mut t = 0i128;
+        let mut new_t = 1i128;
+        let mut r = self.modulus as i128;
+        let mut new_r = self.reduce(a) as i128;
+
+        while new_r != 0 {
+            let quotient = r / new_r;
+            (t, new_t) = (new_t, t - quotient * new_t);
+            (r, new_r) = (new_r, r - quotient * new_r);
+        }
+
+        if r > 1 {
+            return None;
+        }
+        if t < 0 {
+            t += self.modulus as i128;
+        }
+        Some(t as u64)
+    }
+
+    /// Sample a uniformly random field element.
+    pub fn random<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        rng.gen_range(0..self.modulus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prime_field() {
+        let field = PrimeField::new(97).unwrap();
+        assert_eq!(field.add(90, 10), 3);
+        assert_eq!(field.sub(3, 10), 90);
+        assert_eq!(field.neg(0), 0);
+        assert_eq!(field.mul(field.inv(10).unwrap(), 10), 1);
+        assert_eq!(field.pow(5, 96), 1);
+        assert!(PrimeField::new(1).is_err());
+        assert!(field.check(97).is_err());
+
+        // Products of large elements must not overflow.
+        let field = PrimeField::new(0xFFFF_FFFF_FFFF_FFC5).unwrap();
+        let a = field.modulus() - 1;
+        assert_eq!(field.mul(a, a), 1);
+        assert_eq!(field.add(a, a), a - 1);
+    }
+}
@@ -0,0 +1,340 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Shamir secret sharing over a `PrimeField`.
+//!
+//! The secret is the constant term of a random polynomial of degree
+//! `threshold - 1`; share `i` is its evaluation at `x = i + 1`. Any `threshold`
+//! shares with distinct IDs reconstruct the secret by Lagrange interpolation at
+//! zero.
+
+use super::{FieldError, PrimeField};
+use alloc::vec::Vec;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Secret share for Shamir's secret sharing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretShare {
+    /// Share ID
+    pub id: usize,
+    /// Share value
+    pub value: u64,
+    /// Share polynomial coefficient
+    pub coefficient: u64,
+    /// Prime modulus
+    pub modulus: u64,
+}
+
+impl SecretShare {
+    /// Create a new secret share
+    pub fn new(id: usize, value: u64, coefficient: u64, modulus: u64) -> Self {
+        Self {
+            id,
+            value,
+            coefficient,
+            modulus,
+        }
+    }
+
+    /// Evaluate the share at a given point
+    pub fn evaluate(&self, x: u64) -> u64 {
+        let mut result = self.value;
+        let mut power = 1;
+
+        for _ in 0..self.coefficient {
+            power = (power * x) % self.modulus;
+            result = (result + power) % self.modulus;
+        }
+
+        result
+    }
+}
+
+/// Return the evaluation point of the share with the given ID.
+pub fn evaluation_point(field: &PrimeField, id: usize) -> u64 {
+    field.reduce(id as u64 + 1)
+}
+
+/// Evaluate a polynomial at `x` using Horner's rule.
+fn evaluate_polynomial(field: &PrimeField, coefficients: &[u64], x: u64) -> u64 {
+    coefficients.iter().rev().fold(0, |acc, &coefficient| {
+        field.add(field.mul(acc, x), coefficient)
+    })
+}
+
+/// Share `secret` among `num_shares` parties so that any `threshold` of them can
+/// reconstruct it.
+pub fn share<R: Rng + ?Sized>(
+    field: &PrimeField,
+    secret: u64,
+    threshold: usize,
+    num_shares: usize,
+    rng: &mut R,
+) -> Result<Vec<SecretShare>, FieldError> {
+    let mut coefficients = Vec::with_capacity(threshold);
+    let mut shares = Vec::with_capacity(num_shares);
+    share_into(
+        field,
+        secret,
+        threshold,
+        num_shares,
+        rng,
+        &mut coefficients,
+        &mut shares,
+    )?;
+    Ok(shares)
+}
+
+/// Like `share`, but writes into caller-provided buffers.
+///
+/// `coefficients` is scratch space and `shares` receives the shares; both are
+/// cleared first, so reusing them across secrets avoids allocating per secret.
+pub fn share_into<R: Rng + ?Sized>(
+    field: &PrimeField,
+    secret: u64,
+    threshold: usize,
+    num_shares: usize,
+    rng: &mut R,
+    coefficients: &mut Vec<u64>,
+    shares: &mut Vec<SecretShare>,
+) -> Result<(), FieldError> {
+    if threshold < 2 || threshold > num_shares {
+        return Err(FieldError::InvalidThreshold {
+            threshold,
+            num_shares,
+        });
+    }
+    field.check(secret)?;
+
+    coefficients.clear();
+    coefficients.push(secret);
+    for _ in 1..threshold {
+        coefficients.push(field.random(rng));
+    }
+
+    shares.clear();
+    shares.extend((0..num_shares).map(|id| {
+        let value = evaluate_polynomial(field, coefficients, evaluation_point(field, id));
+        SecretShare::new(id, value, 0, field.modulus())
+    }));
+    Ok(())
+}
+
+/// Lagrange coefficients for interpolating at zero from the given share IDs.
+pub fn lagrange_coefficients(field: &PrimeField, ids: &[usize]) -> Result<Vec<u64>, FieldError> {
+    let mut coefficients = Vec::with_capacity(ids.len());
+
+    for (i, &id_i) in ids.iter().enumerate() {
+        let x_i = evaluation_point(field, id_i);
+        let mut numerator = 1;
+        let mut denominator = 1;
+
+        for (j, &id_j) in ids.iter().enumerate() {
+            if i != j {
+                if id_i == id_j {
+                    return Err(FieldError::DuplicateShareId(id_i));
+                }
+                let x_j = evaluation_point(field, id_j);
+                numerator = field.mul(numerator, x_j);
+                denominator = field.mul(denominator, field.sub(x_j, x_i));
+            }
+        }
+
+        let inverse = field.inv(denominator).ok_or(FieldError::NotInvertible)?;
+        coefficients.push(field.mul(numerator, inverse));
+    }
+
+    Ok(coefficients)
+}
+
+/// Reconstruct the secret from the first `threshold` shares; extra shares are
+/// ignored.
+pub fn reconstruct(
+    field: &PrimeField,
+    shares: &[SecretShare],
+    threshold: usize,
+) -> Result<u64, FieldError> {
+    if shares.len() < threshold {
+        return Err(FieldError::InsufficientShares {
+            available: shares.len(),
+            required: threshold,
+        });
+    }
+
+    let shares = &shares[..threshold];
+    let ids = shares.iter().map(|share| share.id).collect::<Vec<_>>();
+    let coefficients = lagrange_coefficients(field, &ids)?;
+
+    Ok(interpolate(field, shares, &coefficients))
+}
+
+/// Combine share values with Lagrange coefficients.
+fn interpolate(field: &PrimeField, shares: &[SecretShare], coefficients: &[u64]) -> u64 {
+    shares
+        .iter()
+        .zip(coefficients)
+        .fold(0, |acc, (share, &coefficient)| {
+            field.add(acc, field.mul(field.reduce(share.value), coefficient))
+        })
+}
+
+/// Lagrange coefficients precomputed for a fixed set of share IDs.
+///
+/// Reconstructing each secret with `reconstruct` recomputes the coefficients,
+/// which costs a field inversion per share. When many secrets were shared at
+/// the same evaluation points, compute them once and reuse them here.
+#[derive(Clone, Debug)]
+pub struct Reconstructor {
+    field: PrimeField,
+    ids: Vec<usize>,
+    coefficients: Vec<u64>,
+}
+
+impl Reconstructor {
+    /// Precompute the coefficients for shares with the given IDs; the number of
+    /// IDs is the threshold.
+    pub fn new(field: PrimeField, ids: &[usize]) -> Result<Self, FieldError> {
+        let coefficients = lagrange_coefficients(&field, ids)?;
+        Ok(Self {
+            field,
+            ids: ids.to_vec(),
+            coefficients,
+        })
+    }
+
+    /// Precompute the coefficients for the IDs of the first `threshold` shares.
+    pub fn for_shares(
+        field: PrimeField,
+        shares: &[SecretShare],
+        threshold: usize,
+    ) -> Result<Self, FieldError> {
+        if shares.len() < threshold {
+            return Err(FieldError::InsufficientShares {
+                available: shares.len(),
+                required: threshold,
+            });
+        }
+
+        let ids = shares[..threshold]
+            .iter()
+            .map(|share| share.id)
+            .collect::<Vec<_>>();
+        Self::new(field, &ids)
+    }
+
+    /// Number of shares needed to reconstruct a secret.
+    pub fn threshold(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Reconstruct a secret from its first `threshold` shares.
+    ///
+    /// Shares with other IDs than the precomputed ones are interpolated on
+    /// their own, as with `reconstruct`.
+    pub fn reconstruct(&self, shares: &[SecretShare]) -> Result<u64, FieldError> {
+        let threshold = self.threshold();
+        if shares.len() < threshold {
+            return Err(FieldError::InsufficientShares {
+                available: shares.len(),
+                required: threshold,
+            });
+        }
+
+        let shares = &shares[..threshold];
+        if shares
+            .iter()
+            .zip(&self.ids)
+            .any(|(share, &id)| share.id != id)
+        {
+            return reconstruct(&self.field, shares, threshold);
+        }
+        Ok(interpolate(&self.field, shares, &self.coefficients))
+    }
+}
+
+/// Reconstruct many secrets, each from its own shares.
+///
+/// The Lagrange coefficients are computed once from the IDs of the first
+/// secret's shares, so this is much faster than calling `reconstruct` per
+/// secret when all secrets were shared at the same evaluation points.
+pub fn reconstruct_many<S: AsRef<[SecretShare]>>(
+    field: &PrimeField,
+    shares: &[S],
+    threshold: usize,
+) -> Result<Vec<u64>, FieldError> {
+    let Some(first) = shares.first() else {
+        return Ok(Vec::new());
+    };
+
+    let reconstructor = Reconstructor::for_shares(*field, first.as_ref(), threshold)?;
+    shares
+        .iter()
+        .map(|shares| reconstructor.reconstruct(shares.as_ref()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_share_and_reconstruct() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let field = PrimeField::new(2_147_483_647).unwrap();
+        let shares = share(&field, 123_456, 3, 5, &mut rng).unwrap();
+
+        assert_eq!(reconstruct(&field, &shares, 3).unwrap(), 123_456);
+        assert_eq!(reconstruct(&field, &shares[2..], 3).unwrap(), 123_456);
+        assert!(matches!(
+            reconstruct(&field, &shares[..2], 3),
+            Err(FieldError::InsufficientShares { .. })
+        ));
+
+        let duplicated = [shares[0].clone(), shares[0].clone(), shares[1].clone()];
+        assert_eq!(
+            reconstruct(&field, &duplicated, 3),
+            Err(FieldError::DuplicateShareId(0))
+        );
+        assert!(share(&field, field.modulus(), 3, 5, &mut rng).is_err());
+        assert!(share(&field, 1, 6, 5, &mut rng).is_err());
+
+        let (mut coefficients, mut shares) = (Vec::new(), Vec::new());
+        for secret in [7, 8] {
+            share_into(
+                &field,
+                secret,
+                3,
+                5,
+                &mut rng,
+                &mut coefficients,
+                &mut shares,
+            )
+            .unwrap();
+            assert_eq!(shares.len(), 5);
+            assert_eq!(reconstruct(&field, &shares, 3).unwrap(), secret);
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_many() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let field = PrimeField::new(2_147_483_647).unwrap();
+        let mut batches = (0..100)
+            .map(|secret| share(&field, secret, 3, 5, &mut rng).unwrap())
+            .collect::<Vec<_>>();
+        // Shares at other evaluation points fall back to interpolating alone
+        batches[7].reverse();
+
+        let secrets = reconstruct_many(&field, &batches, 3).unwrap();
+        assert_eq!(secrets, (0..100).collect::<Vec<_>>());
+
+        batches[9].truncate(2);
+        assert!(reconstruct_many(&field, &batches, 3).is_err());
+        assert!(reconstruct_many::<Vec<SecretShare>>(&field, &[], 3)
+            .unwrap()
+            .is_empty());
+    }
+}
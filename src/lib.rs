@@ -2,19 +2,83 @@
 // Licensed under the MIT license.
 
 #![allow(dead_code)]
+// Without `std`, only the `field` module is built, see the `core` feature.
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod accel;
+#[cfg(feature = "std")]
 pub mod arith;
+#[cfg(feature = "std")]
+pub mod audit;
+#[cfg(feature = "std")]
 pub mod client;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "polars")]
+pub mod dataframe;
+#[cfg(feature = "std")]
+pub mod datagen;
+#[cfg(feature = "std")]
 pub mod dp;
+#[cfg(feature = "std")]
+pub mod epoch;
+#[cfg(feature = "std")]
+pub mod evaluation;
+#[cfg(feature = "core")]
+pub mod field;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
 pub mod multi_party;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
 pub mod random;
+#[cfg(feature = "std")]
 pub mod report;
+#[cfg(feature = "std")]
 pub mod schema;
+#[cfg(feature = "std")]
 pub mod server;
+#[cfg(feature = "std")]
 pub mod shuffle;
+#[cfg(feature = "std")]
+pub mod simulation;
+#[cfg(feature = "std")]
+pub mod storage;
+#[cfg(feature = "std")]
+pub mod telemetry;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+#[cfg(feature = "std")]
 pub use random::hist_noise;
+#[cfg(feature = "std")]
 pub use report::report::Report;
+#[cfg(feature = "std")]
 pub use report::report_vector::test_distr;
+#[cfg(feature = "std")]
 pub use report::report_vector::ReportVector;
+#[cfg(feature = "std")]
+pub use report::compress::{BatchCodec, CompressionError};
+#[cfg(feature = "std")]
+pub use report::ingest::{IngestConfig, IngestError, IngestRecord, IngestStats, ReportIngestor};
+#[cfg(feature = "std")]
+pub use report::nullifier::{DeviceKey, NullifiedReport, Nullifier, NullifierSet};
+#[cfg(feature = "std")]
+pub use report::seal::{SealError, SealedReport, Unsealed};
+#[cfg(feature = "std")]
+pub use report::sign::{ReportVerifier, SignaturePolicy, SignedReport};
+#[cfg(feature = "std")]
+pub use report::store::{ReportStore, RetentionPolicy};
+#[cfg(feature = "std")]
+pub use report::stream::{ReportStreamReader, ReportStreamWriter, StreamError};
+#[cfg(feature = "std")]
+pub use report::wire::WireError;
+#[cfg(feature = "std")]
 pub use schema::Schema;
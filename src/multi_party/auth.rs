@@ -0,0 +1,362 @@
+use crate::multi_party::communication::{MessagePayload, MessageType, NetworkMessage};
+use crate::multi_party::protocol::{ProtocolError, ProtocolPhase};
+use crate::multi_party::reliable::DEDUP_WINDOW;
+use crate::multi_party::secure::{IdentityConfig, KEY_BYTES};
+use crate::multi_party::zero_copy::message_type_tag;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Domain separator of message authentication tags
+const MAC_DOMAIN: &[u8] = b"doppio-message-v1";
+
+/// Size of a message authentication tag
+pub const MAC_BYTES: usize = 32;
+
+fn security_error(message: impl Into<String>) -> ProtocolError {
+    ProtocolError::Security(message.into())
+}
+
+/// Feed a length-prefixed byte string into the MAC
+fn update_bytes(mac: &mut HmacSha256, bytes: &[u8]) {
+    mac.update(&(bytes.len() as u64).to_le_bytes());
+    mac.update(bytes);
+}
+
+/// MAC over every field of the message except the tag itself
+///
+/// The fields are fed in a fixed, unambiguous order, so the tag does not depend
+/// on the codec the message was sent with. Data points, queries and results
+/// are fed as JSON with sorted keys.
+fn authenticate(key: &[u8; KEY_BYTES], message: &NetworkMessage) -> Result<HmacSha256, ProtocolError> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(MAC_DOMAIN);
+    mac.update(&[message_type_tag(&message.message_type)]);
+    if let MessageType::Error(error) = &message.message_type {
+        update_bytes(&mut mac, error.as_bytes());
+    }
+    for value in [message.source_id as u64, message.target_id as u64, message.sequence, message.timestamp] {
+        mac.update(&value.to_le_bytes());
+    }
+    mac.update(&message.version.to_le_bytes());
+    mac.update(&[message.phase.map_or(u8::MAX, ProtocolPhase::tag)]);
+
+    match &message.payload {
+        MessagePayload::Empty => mac.update(&[0]),
+        MessagePayload::Heartbeat => mac.update(&[1]),
+        MessagePayload::Shares(shares) => {
+            mac.update(&[2]);
+            mac.update(&(shares.len() as u64).to_le_bytes());
+            for share in shares {
+                update_bytes(&mut mac, share);
+            }
+        }
+        MessagePayload::Permutation(permutation) => {
+            mac.update(&[3]);
+            mac.update(&(permutation.len() as u64).to_le_bytes());
+            for &position in permutation {
+                mac.update(&(position as u64).to_le_bytes());
+            }
+        }
+        MessagePayload::Handshake(bytes) => {
+            mac.update(&[4]);
+            update_bytes(&mut mac, bytes);
+        }
+        MessagePayload::Encrypted(bytes) => {
+            mac.update(&[5]);
+            update_bytes(&mut mac, bytes);
+        }
+        MessagePayload::Error(error) => {
+            mac.update(&[6]);
+            update_bytes(&mut mac, error.as_bytes());
+        }
+        payload @ (MessagePayload::Data(_)
+        | MessagePayload::Query(_)
+        | MessagePayload::QueryResult(_)
+        | MessagePayload::Versions(_)
+        | MessagePayload::Broadcast(_)
+        | MessagePayload::ObliviousTransfer(_)) => {
+            // Serializing through a `Value` sorts the keys of maps such as query parameters
+            let json = serde_json::to_value(payload)
+                .and_then(|value| serde_json::to_vec(&value))
+                .map_err(|e| ProtocolError::MalformedMessage(e.to_string()))?;
+            mac.update(&[7]);
+            update_bytes(&mut mac, &json);
+        }
+    }
+    Ok(mac)
+}
+
+/// Sequence numbers of the messages verified from one peer
+///
+/// Remembers the sequence numbers within `DEDUP_WINDOW` of the highest one,
+/// and counts every older one as seen, so it takes bounded memory however
+/// long the channel runs.
+#[derive(Debug, Clone, Default)]
+struct ReplayWindow {
+    /// Highest sequence number seen
+    highest: Option<u64>,
+    /// Sequence numbers seen within the window
+    seen: BTreeSet<u64>,
+}
+
+impl ReplayWindow {
+    /// Record `sequence`, returning false if it was seen before or is too old to tell
+    fn insert(&mut self, sequence: u64) -> bool {
+        let window = DEDUP_WINDOW as u64;
+        if let Some(highest) = self.highest {
+            if sequence.saturating_add(window) <= highest || !self.seen.insert(sequence) {
+                return false;
+            }
+        } else {
+            self.seen.insert(sequence);
+        }
+
+        let highest = self.highest.map_or(sequence, |highest| highest.max(sequence));
+        self.highest = Some(highest);
+        if let Some(oldest) = highest.checked_sub(window) {
+            self.seen = self.seen.split_off(&(oldest + 1));
+        }
+        true
+    }
+}
+
+/// Authenticates messages between servers with HMAC-SHA256
+///
+/// Every pair of servers shares a key, so a tag proves that a message was sent
+/// by its source server to its target server and not altered on the way. Keys
+/// are derived from the X25519 keys of an `IdentityConfig`.
+///
+/// A valid tag does not prove that a message is new, so the authenticator
+/// also remembers the sequence numbers of the messages it verified from each
+/// peer and rejects a message that requires an acknowledgement if its
+/// sequence number was seen before. Heartbeats, acknowledgements and
+/// handshake messages are numbered apart from the messages of a channel, so
+/// they are not checked.
+#[derive(Clone, Default)]
+pub struct MessageAuthenticator {
+    /// ID of this server
+    local_id: usize,
+    /// Key shared with each peer, by server ID
+    keys: HashMap<usize, [u8; KEY_BYTES]>,
+    /// Sequence numbers of the messages verified from each peer, by server ID
+    verified: HashMap<usize, ReplayWindow>,
+}
+
+impl fmt::Debug for MessageAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut peers = self.keys.keys().collect::<Vec<_>>();
+        peers.sort();
+        f.debug_struct("MessageAuthenticator")
+            .field("local_id", &self.local_id)
+            .field("peers", &peers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MessageAuthenticator {
+    /// Create an authenticator for `local_id` without any peers
+    pub fn new(local_id: usize) -> Self {
+        Self {
+            local_id,
+            keys: HashMap::new(),
+            verified: HashMap::new(),
+        }
+    }
+
+    /// Create an authenticator for `local_id` with a key for every peer in `identity`
+    pub fn from_identity(local_id: usize, identity: &IdentityConfig) -> Result<Self, ProtocolError> {
+        let keys = identity.keys()?;
+        let mut authenticator = Self::new(local_id);
+        for &peer_id in identity.peers.keys().filter(|&&peer_id| peer_id != local_id) {
+            let key = keys.pair_key(local_id, peer_id, &identity.peer_key(peer_id)?)?;
+            authenticator.add_peer(peer_id, key);
+        }
+        Ok(authenticator)
+    }
+
+    /// Set the key shared with `peer_id`
+    pub fn add_peer(&mut self, peer_id: usize, key: [u8; KEY_BYTES]) {
+        self.keys.insert(peer_id, key);
+    }
+
+    /// ID of this server
+    pub fn local_id(&self) -> usize {
+        self.local_id
+    }
+
+    fn key(&self, peer_id: usize) -> Result<&[u8; KEY_BYTES], ProtocolError> {
+        self.keys
+            .get(&peer_id)
+            .ok_or_else(|| security_error(format!("No message key for server {}", peer_id)))
+    }
+
+    /// Set the tag of a message sent by this server
+    pub fn sign(&self, message: &mut NetworkMessage) -> Result<(), ProtocolError> {
+        if message.source_id != self.local_id {
+            return Err(security_error(format!(
+                "Server {} cannot sign a message from server {}",
+                self.local_id, message.source_id
+            )));
+        }
+
+        let tag = authenticate(self.key(message.target_id)?, message)?.finalize();
+        message.mac = Some(tag.into_bytes().to_vec());
+        Ok(())
+    }
+
+    /// Check the tag of a message received by this server, rejecting replays
+    pub fn verify(&mut self, message: &NetworkMessage) -> Result<(), ProtocolError> {
+        if self.verify_fresh(message)? {
+            Ok(())
+        } else {
+            Err(security_error(format!(
+                "Replayed message {} from server {}",
+                message.sequence, message.source_id
+            )))
+        }
+    }
+
+    /// Check the tag of a message received by this server, returning false if
+    /// it is a replay of a message verified before
+    ///
+    /// Lets a reliable channel acknowledge a replay again and drop it, since
+    /// it cannot tell a replay from a resend whose acknowledgement was lost.
+    pub fn verify_fresh(&mut self, message: &NetworkMessage) -> Result<bool, ProtocolError> {
+        if message.target_id != self.local_id {
+            return Err(security_error(format!(
+                "Message for server {} received by server {}",
+                message.target_id, self.local_id
+            )));
+        }
+
+        let tag = message
+            .mac
+            .as_deref()
+            .ok_or_else(|| security_error(format!("Unauthenticated message from server {}", message.source_id)))?;
+        authenticate(self.key(message.source_id)?, message)?
+            .verify_slice(tag)
+            .map_err(|_| {
+                security_error(format!(
+                    "Invalid tag on message {} from server {}",
+                    message.sequence, message.source_id
+                ))
+            })?;
+
+        Ok(!message.requires_ack() || self.verified.entry(message.source_id).or_default().insert(message.sequence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::secure::ServerKeys;
+    use crate::schema::{Query, QueryType};
+    use rand::rngs::OsRng;
+    use std::collections::BTreeMap;
+
+    fn authenticators() -> Vec<MessageAuthenticator> {
+        let keys = (0..3).map(|_| ServerKeys::generate(&mut OsRng)).collect::<Vec<_>>();
+        let peers = keys
+            .iter()
+            .enumerate()
+            .map(|(id, keys)| (id, keys.public_key_hex()))
+            .collect::<BTreeMap<_, _>>();
+
+        keys.iter()
+            .enumerate()
+            .map(|(id, keys)| {
+                let identity = IdentityConfig {
+                    private_key: keys.private_key_hex(),
+                    peers: peers.clone(),
+                };
+                MessageAuthenticator::from_identity(id, &identity).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_message_authentication() {
+        let mut servers = authenticators();
+
+        let mut message = NetworkMessage::share(0, 1, 3, vec![vec![1, 2, 3], vec![4]]);
+        servers[0].sign(&mut message).unwrap();
+        assert_eq!(message.mac.as_ref().map(Vec::len), Some(MAC_BYTES));
+
+        // Survives a codec round trip
+        let decoded = NetworkMessage::from_bytes(&message.to_bytes().unwrap()).unwrap();
+        servers[1].verify(&decoded).unwrap();
+
+        // Any change to the message or the tag is detected
+        let mut tampered = message.clone();
+        tampered.payload = MessagePayload::Shares(vec![vec![1, 2, 4], vec![4]]);
+        assert!(servers[1].verify(&tampered).is_err());
+        let mut tampered = message.clone();
+        tampered.sequence += 1;
+        assert!(servers[1].verify(&tampered).is_err());
+        let mut tampered = message.clone();
+        tampered.mac.as_mut().unwrap()[0] ^= 1;
+        assert!(servers[1].verify(&tampered).is_err());
+        let mut unsigned = message.clone();
+        unsigned.mac = None;
+        assert!(servers[1].verify(&unsigned).is_err());
+
+        // Only the target can verify, and only the source can sign
+        assert!(servers[2].verify(&message).is_err());
+        let mut forged = message.clone();
+        forged.target_id = 2;
+        assert!(servers[2].verify(&forged).is_err());
+        assert!(servers[2].sign(&mut message).is_err());
+    }
+
+    #[test]
+    fn test_replay_rejection() {
+        let mut servers = authenticators();
+        let signed = |servers: &[MessageAuthenticator], mut message: NetworkMessage| {
+            servers[message.source_id].sign(&mut message).unwrap();
+            message
+        };
+
+        let message = signed(&servers, NetworkMessage::share(0, 1, 3, vec![vec![1, 2, 3]]));
+        servers[1].verify(&message).unwrap();
+
+        // A replay is rejected however it is encoded, and reported to a reliable channel
+        let replayed = NetworkMessage::from_bytes(&message.to_bytes().unwrap()).unwrap();
+        assert!(matches!(servers[1].verify(&replayed), Err(ProtocolError::Security(_))));
+        assert!(!servers[1].verify_fresh(&message).unwrap());
+
+        // Sequence numbers are kept per peer
+        let other = signed(&servers, NetworkMessage::share(2, 1, 3, vec![vec![4]]));
+        servers[1].verify(&other).unwrap();
+
+        // Messages may arrive out of order, but not from before the window
+        for sequence in [5, 4, DEDUP_WINDOW as u64 + 5, 6] {
+            let message = signed(&servers, NetworkMessage::share(0, 1, sequence, vec![]));
+            servers[1].verify(&message).unwrap();
+        }
+        for sequence in [2, 4] {
+            let message = signed(&servers, NetworkMessage::share(0, 1, sequence, vec![]));
+            assert!(servers[1].verify(&message).is_err());
+        }
+
+        // Heartbeats are numbered apart from the messages of a channel
+        let heartbeat = signed(&servers, NetworkMessage::heartbeat(0, 1, 3));
+        servers[1].verify(&heartbeat).unwrap();
+        servers[1].verify(&heartbeat).unwrap();
+    }
+
+    #[test]
+    fn test_structured_payload_authentication() {
+        let mut servers = authenticators();
+        let parameters = (0..16).map(|i| (format!("p{}", i), i as f64 / 3.0)).collect();
+        let query = Query::with_parameters(QueryType::Histogram, vec!["feature1".to_string()], parameters);
+
+        let mut message = NetworkMessage::query(2, 0, 1, query);
+        servers[2].sign(&mut message).unwrap();
+        let decoded = NetworkMessage::from_bytes(&message.to_bytes().unwrap()).unwrap();
+        servers[0].verify(&decoded).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use crate::field::PrimeField;
+use crate::multi_party::crypto::{
+    AdditiveSecretSharing, AdditiveShare, SecretShare, ShamirSecretSharing, SharingScheme, SHARE_MODULUS,
+    WRAPPING_MODULUS,
+};
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::share::DataShare;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// A server's shares of a multiplication triple `(a, b, a * b)` of random values
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BeaverTriple {
+    /// Share of `a`
+    pub a: DataShare,
+    /// Share of `b`
+    pub b: DataShare,
+    /// Share of `c = a * b`
+    pub c: DataShare,
+}
+
+impl BeaverTriple {
+    /// Server holding the shares
+    pub fn server_id(&self) -> usize {
+        self.a.server_id
+    }
+}
+
+/// Arithmetic of shares with one modulus, wrapping for additive shares
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Ring {
+    Field(PrimeField),
+    Wrapping,
+}
+
+impl Ring {
+    pub(crate) fn of(modulus: u64) -> Result<Self, ProtocolError> {
+        if modulus == WRAPPING_MODULUS {
+            Ok(Ring::Wrapping)
+        } else {
+            Ok(Ring::Field(PrimeField::new(modulus)?))
+        }
+    }
+
+    pub(crate) fn add(self, a: u64, b: u64) -> u64 {
+        match self {
+            Ring::Field(field) => field.add(a, b),
+            Ring::Wrapping => a.wrapping_add(b),
+        }
+    }
+
+    pub(crate) fn sub(self, a: u64, b: u64) -> u64 {
+        match self {
+            Ring::Field(field) => field.sub(a, b),
+            Ring::Wrapping => a.wrapping_sub(b),
+        }
+    }
+
+    pub(crate) fn mul(self, a: u64, b: u64) -> u64 {
+        match self {
+            Ring::Field(field) => field.mul(a, b),
+            Ring::Wrapping => a.wrapping_mul(b),
+        }
+    }
+}
+
+/// Dealer of Beaver triples in the offline phase
+///
+/// The dealer, e.g. the auxiliary server, knows every triple it deals, so it
+/// must not collude with the servers multiplying with them.
+pub struct TripleDealer {
+    scheme: SharingScheme,
+    shamir: ShamirSecretSharing,
+    additive: AdditiveSecretSharing,
+}
+
+impl TripleDealer {
+    /// Create a dealer for `num_servers` servers sharing data with `scheme`
+    ///
+    /// Shamir triples use `SHARE_MODULUS`, like `ThresholdEncryption`, and
+    /// additive triples wrap modulo 2^64.
+    pub fn new(threshold: usize, num_servers: usize, scheme: SharingScheme) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            scheme,
+            shamir: ShamirSecretSharing::new(threshold, num_servers, SHARE_MODULUS)?,
+            additive: AdditiveSecretSharing::new(num_servers)?,
+        })
+    }
+
+    /// Deal `count` triples, returned as the triples of each server by server ID
+    pub fn deal(&self, count: usize) -> Result<Vec<Vec<BeaverTriple>>, ProtocolError> {
+        let num_servers = self.shamir.num_shares;
+        let mut triples: Vec<Vec<BeaverTriple>> = (0..num_servers).map(|_| Vec::with_capacity(count)).collect();
+        let mut rng = rand::thread_rng();
+
+        for index in 0..count {
+            let (a, b, c) = match self.scheme {
+                SharingScheme::Shamir => {
+                    let field = PrimeField::new(SHARE_MODULUS)?;
+                    let (a, b) = (field.random(&mut rng), field.random(&mut rng));
+                    (a, b, field.mul(a, b))
+                }
+                SharingScheme::Additive => {
+                    let (a, b): (u64, u64) = (rng.gen(), rng.gen());
+                    (a, b, a.wrapping_mul(b))
+                }
+            };
+
+            let (a, b, c) = (self.share(a, index)?, self.share(b, index)?, self.share(c, index)?);
+            for ((a, b), c) in a.into_iter().zip(b).zip(c) {
+                triples[a.server_id].push(BeaverTriple { a, b, c });
+            }
+        }
+
+        Ok(triples)
+    }
+
+    /// Share one component of triple `index` among the servers
+    fn share(&self, secret: u64, index: usize) -> Result<Vec<DataShare>, ProtocolError> {
+        Ok(match self.scheme {
+            SharingScheme::Shamir => self
+                .shamir
+                .share_secret(secret)?
+                .into_iter()
+                .map(|share| DataShare::triple(share.id, index, share.value, SHARE_MODULUS))
+                .collect(),
+            SharingScheme::Additive => self
+                .additive
+                .share_secret(secret)
+                .into_iter()
+                .map(|share| DataShare::triple(share.id, index, share.value, WRAPPING_MODULUS))
+                .collect(),
+        })
+    }
+}
+
+/// Online multiplication of shared values with Beaver triples
+///
+/// Multiplying `x` by `y` takes one round. Each server sends every other
+/// server its shares of `d = x - a` and `e = y - b` from `mask`, the servers
+/// `open` `d` and `e`, and `finish` gives each server its share of
+/// `x * y = c + d * b + e * a + d * e`. Since `a` and `b` are random, `d` and
+/// `e` reveal nothing about `x` and `y`, as long as each triple is used for
+/// one multiplication only. Shares of either `SharingScheme` work, told apart
+/// by their modulus.
+pub struct MultiplicationGate {
+    threshold: usize,
+    num_servers: usize,
+}
+
+impl MultiplicationGate {
+    /// Create a gate for shares among `num_servers` servers, any `threshold` of which reconstruct Shamir shares
+    pub fn new(threshold: usize, num_servers: usize) -> Result<Self, ProtocolError> {
+        if threshold < 2 || threshold > num_servers {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Threshold {} must be between 2 and the number of servers {}",
+                threshold, num_servers
+            )));
+        }
+
+        Ok(Self {
+            threshold,
+            num_servers,
+        })
+    }
+
+    /// This server's shares of `d = x - a` and `e = y - b`, to open with the other servers
+    pub fn mask(
+        &self,
+        x: &DataShare,
+        y: &DataShare,
+        triple: &BeaverTriple,
+    ) -> Result<(DataShare, DataShare), ProtocolError> {
+        let ring = Self::check(&[x, y, &triple.a, &triple.b, &triple.c])?;
+        let mut d = x.clone();
+        d.value = ring.sub(x.value, triple.a.value);
+        let mut e = y.clone();
+        e.value = ring.sub(y.value, triple.b.value);
+        Ok((d, e))
+    }
+
+    /// Open a masked value from the shares of the servers
+    ///
+    /// Shamir shares need `threshold` servers, additive shares all of them.
+    pub fn open(&self, shares: &[DataShare]) -> Result<u64, ProtocolError> {
+        let first = shares.first().ok_or(ProtocolError::InsufficientServers {
+            available: 0,
+            required: self.threshold,
+        })?;
+        if let Some(share) = shares.iter().find(|share| share.modulus != first.modulus) {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Shares of moduli {} and {} cannot be opened together",
+                first.modulus, share.modulus
+            )));
+        }
+
+        if first.modulus == WRAPPING_MODULUS {
+            let shares: Vec<AdditiveShare> = shares
+                .iter()
+                .map(|share| AdditiveShare::new(share.server_id, share.value))
+                .collect();
+            AdditiveSecretSharing::new(self.num_servers)?.reconstruct_secret(&shares)
+        } else {
+            let shares: Vec<SecretShare> = shares
+                .iter()
+                .map(|share| SecretShare::new(share.server_id, share.value, 0, share.modulus))
+                .collect();
+            ShamirSecretSharing::new(self.threshold, self.num_servers, first.modulus)?.reconstruct_secret(&shares)
+        }
+    }
+
+    /// This server's share of `x * y`, from its share of `x`, its triple and the opened `d` and `e`
+    ///
+    /// The product keeps the feature index and type of the share of `x`.
+    pub fn finish(&self, x: &DataShare, triple: &BeaverTriple, d: u64, e: u64) -> Result<DataShare, ProtocolError> {
+        let ring = Self::check(&[x, &triple.a, &triple.b, &triple.c])?;
+        let mut value = ring.add(triple.c.value, ring.add(ring.mul(d, triple.b.value), ring.mul(e, triple.a.value)));
+
+        // A public constant is added to every Shamir share, but to one additive share only
+        if x.modulus != WRAPPING_MODULUS || x.server_id == 0 {
+            value = ring.add(value, ring.mul(d, e));
+        }
+
+        let mut product = x.clone();
+        product.value = value;
+        Ok(product)
+    }
+
+    /// Check that shares belong to one server and modulus
+    fn check(shares: &[&DataShare]) -> Result<Ring, ProtocolError> {
+        let first = shares[0];
+        if let Some(share) = shares
+            .iter()
+            .find(|share| share.server_id != first.server_id || share.modulus != first.modulus)
+        {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Shares of server {} modulo {} cannot be multiplied with shares of server {} modulo {}",
+                first.server_id, first.modulus, share.server_id, share.modulus
+            )));
+        }
+
+        Ring::of(first.modulus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::crypto::ThresholdEncryption;
+    use crate::schema::DataPoint;
+
+    /// Run one multiplication on every server, given each server's shares and triple
+    fn multiply(gate: &MultiplicationGate, x: &[DataShare], y: &[DataShare], triples: &[BeaverTriple]) -> Vec<DataShare> {
+        let masked: Vec<(DataShare, DataShare)> = (0..x.len()).map(|i| gate.mask(&x[i], &y[i], &triples[i]).unwrap()).collect();
+        let d = gate.open(&masked.iter().map(|(d, _)| d.clone()).collect::<Vec<_>>()).unwrap();
+        let e = gate.open(&masked.iter().map(|(_, e)| e.clone()).collect::<Vec<_>>()).unwrap();
+        (0..x.len()).map(|i| gate.finish(&x[i], &triples[i], d, e).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_beaver_multiplication() {
+        let gate = MultiplicationGate::new(2, 3).unwrap();
+        for scheme in [SharingScheme::Shamir, SharingScheme::Additive] {
+            let mut crypto = ThresholdEncryption::new(2, 3).unwrap().with_scheme(scheme);
+            crypto.initialize().await.unwrap();
+            let triples = TripleDealer::new(2, 3, scheme).unwrap().deal(2).unwrap();
+            assert!(triples.iter().enumerate().all(|(id, triples)| triples.iter().all(|t| t.server_id() == id && t.c.is_triple())));
+
+            // Feature 0 is a filter bit, feature 1 a value
+            let shares = crypto.share_data(DataPoint::new(vec![1.0, 12.0])).await.unwrap();
+            let feature = |index: usize| -> Vec<DataShare> {
+                let mut shares: Vec<DataShare> = shares.iter().filter(|share| share.feature_index == index).cloned().collect();
+                shares.sort_by_key(|share| share.server_id);
+                shares
+            };
+            let (bit, value) = (feature(0), feature(1));
+            let round = |i: usize| -> Vec<BeaverTriple> { triples.iter().map(|triples| triples[i].clone()).collect() };
+
+            let filtered = multiply(&gate, &value, &bit, &round(0));
+            assert_eq!(gate.open(&filtered).unwrap(), 12, "{:?}", scheme);
+            assert_eq!(filtered[0].feature_index, 1);
+            let squared = multiply(&gate, &value, &value, &round(1));
+            assert_eq!(gate.open(&squared).unwrap(), 144, "{:?}", scheme);
+        }
+
+        let triples = TripleDealer::new(2, 3, SharingScheme::Shamir).unwrap().deal(1).unwrap();
+        let x = DataShare::feature(0, 0, 1, SHARE_MODULUS);
+        let other = DataShare::feature(1, 0, 1, SHARE_MODULUS);
+        assert!(gate.mask(&x, &other, &triples[0][0]).is_err());
+        assert!(gate.mask(&x, &x, &triples[1][0]).is_err());
+        assert!(gate.open(&[]).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use crate::multi_party::protocol::ProtocolError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Step of Bracha's reliable broadcast a message belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BroadcastStep {
+    /// The sender proposes its value
+    Send,
+    /// A server relays the value it got from the sender
+    Echo,
+    /// A server is ready to deliver a value enough servers echoed
+    Ready,
+}
+
+/// Message of one instance of reliable broadcast, sent to every server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastMessage {
+    /// Server broadcasting the value
+    pub sender: usize,
+    /// Instance of the broadcast, chosen by the protocol
+    pub instance: u64,
+    /// Step of the broadcast
+    pub step: BroadcastStep,
+    /// Value broadcast
+    pub value: Vec<u8>,
+}
+
+/// Faulty servers out of `num_servers` a broadcast stays consistent with
+///
+/// With fewer than `3 * faults + 1` servers, e.g. one of three, an equivocating
+/// sender may keep the honest servers from delivering, but never makes them
+/// deliver different values.
+pub fn max_faults(num_servers: usize) -> usize {
+    num_servers.saturating_sub(1) / 2
+}
+
+/// One instance of Bracha's reliable broadcast at one server
+///
+/// The sender sends its value to every server, which echoes it to every
+/// server. A server that receives the same echo from a quorum of servers, or
+/// the same ready from `faults + 1` servers, sends ready, and delivers a value
+/// once `2 * faults + 1` servers are ready to deliver it. Any two echo quorums
+/// share an honest server, which echoes the same value to all, so honest
+/// servers never deliver different values.
+///
+/// Messages returned by `start` and `handle` are meant for every server,
+/// including this one.
+#[derive(Debug)]
+pub struct ReliableBroadcast {
+    local_id: usize,
+    num_servers: usize,
+    sender: usize,
+    instance: u64,
+    faults: usize,
+    ready: bool,
+    /// Value received from the sender, and echoed
+    proposal: Option<Vec<u8>>,
+    echoes: HashMap<usize, Vec<u8>>,
+    readies: HashMap<usize, Vec<u8>>,
+    delivered: Option<Vec<u8>>,
+}
+
+impl ReliableBroadcast {
+    /// Take part in instance `instance` of the broadcast from `sender` among `num_servers` servers
+    pub fn new(local_id: usize, num_servers: usize, sender: usize, instance: u64, faults: usize) -> Result<Self, ProtocolError> {
+        if 2 * faults + 1 > num_servers {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Reliable broadcast among {} servers cannot tolerate {} faulty servers",
+                num_servers, faults
+            )));
+        }
+        Ok(Self {
+            local_id,
+            num_servers,
+            sender,
+            instance,
+            faults,
+            ready: false,
+            proposal: None,
+            echoes: HashMap::new(),
+            readies: HashMap::new(),
+            delivered: None,
+        })
+    }
+
+    /// Servers that must echo a value before anyone is ready to deliver it
+    pub fn echo_quorum(&self) -> usize {
+        (self.num_servers + self.faults + 1).div_ceil(2)
+    }
+
+    /// Broadcast `value` as the sender
+    pub fn start(&mut self, value: Vec<u8>) -> Result<Vec<BroadcastMessage>, ProtocolError> {
+        if self.local_id != self.sender {
+            return Err(ProtocolError::InternalError {
+                message: format!("Server {} cannot start the broadcast of server {}", self.local_id, self.sender),
+            });
+        }
+        Ok(vec![self.message(BroadcastStep::Send, value)])
+    }
+
+    /// Handle a message from server `from`, returning the messages to send in turn
+    ///
+    /// Fails with `CheatDetected` once a server is caught sending two values,
+    /// or the echoes conflict so that no value can be delivered.
+    pub fn handle(&mut self, from: usize, message: BroadcastMessage) -> Result<Vec<BroadcastMessage>, ProtocolError> {
+        if message.sender != self.sender || message.instance != self.instance {
+            return Err(ProtocolError::InternalError {
+                message: format!(
+                    "Broadcast {} of server {} handled by instance {} of server {}",
+                    message.instance, message.sender, self.instance, self.sender
+                ),
+            });
+        }
+
+        let mut outgoing = Vec::new();
+        match message.step {
+            BroadcastStep::Send => {
+                if from != self.sender {
+                    return Err(self.cheating(vec![from]));
+                }
+                if let Some(proposal) = &self.proposal {
+                    if *proposal != message.value {
+                        return Err(self.cheating(vec![from]));
+                    }
+                } else {
+                    self.proposal = Some(message.value.clone());
+                    outgoing.push(self.message(BroadcastStep::Echo, message.value));
+                }
+            }
+            BroadcastStep::Echo => {
+                if !record(&mut self.echoes, from, &message.value) {
+                    return Err(self.cheating(vec![from]));
+                }
+                if !self.ready && count(&self.echoes, &message.value) >= self.echo_quorum() {
+                    self.ready = true;
+                    outgoing.push(self.message(BroadcastStep::Ready, message.value));
+                } else if !self.ready && !self.can_reach_quorum() {
+                    let mut servers = self.conflicting_echoes();
+                    servers.push(self.sender);
+                    servers.sort_unstable();
+                    servers.dedup();
+                    return Err(self.cheating(servers));
+                }
+            }
+            BroadcastStep::Ready => {
+                if !record(&mut self.readies, from, &message.value) {
+                    return Err(self.cheating(vec![from]));
+                }
+                let readies = count(&self.readies, &message.value);
+                if !self.ready && readies > self.faults {
+                    self.ready = true;
+                    outgoing.push(self.message(BroadcastStep::Ready, message.value.clone()));
+                }
+                if self.delivered.is_none() && readies > 2 * self.faults {
+                    self.delivered = Some(message.value);
+                }
+            }
+        }
+        Ok(outgoing)
+    }
+
+    /// Value delivered by the broadcast, once enough servers are ready
+    pub fn delivered(&self) -> Option<&[u8]> {
+        self.delivered.as_deref()
+    }
+
+    fn message(&self, step: BroadcastStep, value: Vec<u8>) -> BroadcastMessage {
+        BroadcastMessage {
+            sender: self.sender,
+            instance: self.instance,
+            step,
+            value,
+        }
+    }
+
+    /// Check if some value may still be echoed by a quorum
+    fn can_reach_quorum(&self) -> bool {
+        let unheard = self.num_servers.saturating_sub(self.echoes.len());
+        let most_echoed = tally(&self.echoes).into_values().max().unwrap_or(0);
+        most_echoed + unheard >= self.echo_quorum()
+    }
+
+    /// Servers whose echo differs from the value this server got from the sender
+    fn conflicting_echoes(&self) -> Vec<usize> {
+        let Some(proposal) = &self.proposal else {
+            return Vec::new();
+        };
+        self.echoes
+            .iter()
+            .filter(|(_, value)| *value != proposal)
+            .map(|(server, _)| *server)
+            .collect()
+    }
+
+    fn cheating(&self, servers: Vec<usize>) -> ProtocolError {
+        log::warn!(
+            "Broadcast {} of server {} failed: servers {:?} sent conflicting values",
+            self.instance,
+            self.sender,
+            servers
+        );
+        ProtocolError::cheat_detected(self.instance as usize, servers)
+    }
+}
+
+/// Record the value a server sent, returning false if it sent another one before
+fn record(values: &mut HashMap<usize, Vec<u8>>, from: usize, value: &[u8]) -> bool {
+    match values.get(&from) {
+        Some(previous) => previous == value,
+        None => {
+            values.insert(from, value.to_vec());
+            true
+        }
+    }
+}
+
+fn count(values: &HashMap<usize, Vec<u8>>, value: &[u8]) -> usize {
+    values.values().filter(|v| v.as_slice() == value).count()
+}
+
+fn tally(values: &HashMap<usize, Vec<u8>>) -> BTreeMap<&[u8], usize> {
+    let mut tally = BTreeMap::new();
+    for value in values.values() {
+        *tally.entry(value.as_slice()).or_insert(0) += 1;
+    }
+    tally
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deliver every message to every server until no more are sent
+    ///
+    /// `corrupt` replaces the messages of server 0 to each target.
+    fn run(
+        servers: &mut [ReliableBroadcast],
+        initial: Vec<(usize, BroadcastMessage)>,
+        corrupt: impl Fn(usize, &BroadcastMessage) -> BroadcastMessage,
+    ) -> Vec<Result<(), ProtocolError>> {
+        let mut results = servers.iter().map(|_| Ok(())).collect::<Vec<_>>();
+        let mut queue = initial;
+        while let Some((from, message)) = queue.pop() {
+            for (target, server) in servers.iter_mut().enumerate() {
+                if results[target].is_err() {
+                    continue;
+                }
+                let message = if from == 0 { corrupt(target, &message) } else { message.clone() };
+                match server.handle(from, message) {
+                    Ok(outgoing) => queue.extend(outgoing.into_iter().map(|message| (target, message))),
+                    Err(e) => results[target] = Err(e),
+                }
+            }
+        }
+        results
+    }
+
+    fn servers(n: usize) -> Vec<ReliableBroadcast> {
+        (0..n).map(|id| ReliableBroadcast::new(id, n, 0, 7, max_faults(n)).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_honest_broadcast() {
+        for n in [3, 4, 5] {
+            let mut servers = servers(n);
+            let initial = servers[0].start(b"commitment".to_vec()).unwrap();
+            let results = run(&mut servers, initial.into_iter().map(|m| (0, m)).collect(), |_, m| m.clone());
+            assert!(results.iter().all(Result::is_ok));
+            assert!(servers.iter().all(|server| server.delivered() == Some(&b"commitment"[..])));
+        }
+        assert!(ReliableBroadcast::new(1, 3, 0, 7, 2).is_err());
+        assert!(servers(3)[1].start(vec![1]).is_err());
+    }
+
+    #[test]
+    fn test_equivocating_sender() {
+        // Server 0 sends and echoes one value to server 1 and another to server 2
+        let mut servers = servers(3);
+        let initial = servers[0].start(b"a".to_vec()).unwrap();
+        let results = run(&mut servers, initial.into_iter().map(|m| (0, m)).collect(), |target, message| {
+            let mut message = message.clone();
+            if target == 2 && message.step != BroadcastStep::Ready {
+                message.value = b"b".to_vec();
+            }
+            message
+        });
+
+        // No two honest servers deliver different values
+        let delivered = servers[1..].iter().filter_map(|server| server.delivered()).collect::<Vec<_>>();
+        assert!(delivered.windows(2).all(|pair| pair[0] == pair[1]));
+        assert!(delivered.len() < 2);
+        assert!(results[1..]
+            .iter()
+            .any(|result| matches!(result, Err(ProtocolError::CheatDetected { index: 7, servers }) if servers.contains(&0))));
+    }
+}
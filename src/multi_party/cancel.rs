@@ -0,0 +1,124 @@
+use crate::multi_party::protocol::ProtocolError;
+use futures::future::{self, Either};
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Lets a caller abort a protocol run, under any runtime
+///
+/// Clones share their state, so a token handed to a `NetworkManager` or a
+/// `ProtocolDriver` is cancelled from another task by cancelling a clone.
+/// Steps run with `run` then stop at their next await point and fail with
+/// `ProtocolError::Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    waiters: Mutex<Vec<Waker>>,
+}
+
+impl CancellationToken {
+    /// Create a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the run, waking every task waiting on the token
+    pub fn cancel(&self) {
+        if !self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            log::info!("Protocol run cancelled");
+        }
+        let waiters = std::mem::take(&mut *self.inner.waiters.lock().unwrap());
+        waiters.into_iter().for_each(Waker::wake);
+    }
+
+    /// Check if the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fail with `Cancelled` if the token was cancelled
+    pub fn check(&self) -> Result<(), ProtocolError> {
+        if self.is_cancelled() {
+            return Err(ProtocolError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Complete once the token is cancelled
+    pub fn cancelled(&self) -> impl Future<Output = ()> + Send + '_ {
+        Cancelled { inner: &self.inner }
+    }
+
+    /// Run `step` until it finishes or the token is cancelled, dropping it in the latter case
+    pub async fn run<T, F>(&self, step: F) -> Result<T, ProtocolError>
+    where
+        F: Future<Output = Result<T, ProtocolError>>,
+    {
+        self.check()?;
+        match future::select(pin!(self.cancelled()), pin!(step)).await {
+            Either::Left(((), _)) => Err(ProtocolError::Cancelled),
+            Either::Right((result, _)) => result,
+        }
+    }
+}
+
+/// Future returned by `CancellationToken::cancelled`
+struct Cancelled<'a> {
+    inner: &'a Inner,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.inner.cancelled.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        let mut waiters = self.inner.waiters.lock().unwrap();
+        // Checked again under the lock, which `cancel` takes after setting the flag
+        if self.inner.cancelled.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        if !waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+            waiters.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::runtime::{ManualRuntime, Runtime};
+    use std::time::Duration;
+
+    #[test]
+    fn test_cancel_running_step() {
+        let runtime = Arc::new(ManualRuntime::new());
+        let token = CancellationToken::new();
+
+        runtime.block_on(async {
+            assert_eq!(token.run(async { Ok(3) }).await.unwrap(), 3);
+
+            let canceller = token.clone();
+            let sleeper = runtime.clone();
+            runtime.spawn(Box::pin(async move {
+                sleeper.sleep(Duration::from_millis(20)).await;
+                canceller.cancel();
+            }));
+            let stuck = token.run(future::pending::<Result<(), ProtocolError>>()).await;
+            assert!(matches!(stuck, Err(ProtocolError::Cancelled)));
+        });
+
+        // Once cancelled, later steps do not start
+        assert!(token.is_cancelled());
+        assert!(matches!(runtime.block_on(token.run(async { Ok(()) })), Err(ProtocolError::Cancelled)));
+    }
+}
@@ -0,0 +1,943 @@
+use crate::multi_party::communication::{MessagePayload, MessageType, NetworkMessage, MAX_MESSAGE_BYTES};
+use crate::multi_party::protocol::{ProtocolError, ProtocolPhase};
+use crate::server::ot::ExtensionMessage;
+use bincode::Options;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Size of the length prefix of a frame
+const LENGTH_BYTES: usize = 4;
+
+/// Encoded messages shorter than this are never compressed
+pub const COMPRESSION_THRESHOLD: usize = 512;
+
+fn malformed(message: impl Into<String>) -> ProtocolError {
+    ProtocolError::MalformedMessage(message.into())
+}
+
+/// Encoding of `NetworkMessage`s on the wire
+///
+/// Decoding must reject malformed input with `ProtocolError::MalformedMessage`
+/// and never panic, and the decoded message must pass `NetworkMessage::validate`.
+pub trait MessageCodec: fmt::Debug + Send + Sync {
+    /// Name of the codec, e.g. for logs
+    fn name(&self) -> &'static str;
+
+    /// Encode a message
+    fn encode(&self, message: &NetworkMessage) -> Result<Vec<u8>, ProtocolError>;
+
+    /// Decode a message of at most `max_bytes` bytes
+    fn decode(&self, bytes: &[u8], max_bytes: usize) -> Result<NetworkMessage, ProtocolError>;
+}
+
+/// Codec selectable in `NetworkConfig`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodecKind {
+    /// `JsonCodec`
+    #[default]
+    Json,
+    /// `BincodeCodec`
+    Bincode,
+    /// `ProtobufCodec`
+    Protobuf,
+    /// `CborCodec`
+    Cbor,
+}
+
+impl CodecKind {
+    /// Get the codec
+    pub fn codec(self) -> &'static dyn MessageCodec {
+        match self {
+            CodecKind::Json => &JsonCodec,
+            CodecKind::Bincode => &BincodeCodec,
+            CodecKind::Protobuf => &ProtobufCodec,
+            CodecKind::Cbor => &CborCodec,
+        }
+    }
+
+    /// Tag of the codec in a frame
+    fn tag(self) -> u8 {
+        match self {
+            CodecKind::Json => 0,
+            CodecKind::Bincode => 1,
+            CodecKind::Protobuf => 2,
+            CodecKind::Cbor => 3,
+        }
+    }
+}
+
+/// Compression of encoded messages in frames, selectable in `NetworkConfig`
+///
+/// Two servers compress the frames of their link only if both select the same
+/// compression, see `TcpTransport`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// Frames are not compressed
+    #[default]
+    None,
+    /// zstd at the default level
+    Zstd,
+    /// LZ4 block format, faster than zstd but compressing less
+    Lz4,
+}
+
+impl Compression {
+    /// Tag of the compression in a frame
+    fn tag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+            Compression::Lz4 => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Result<Self, ProtocolError> {
+        match tag {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Zstd),
+            2 => Ok(Compression::Lz4),
+            tag => Err(malformed(format!("Unknown compression {}", tag))),
+        }
+    }
+
+    /// Compress an encoded message, prefixed with its length
+    fn compress(self, body: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+        let length = u32::try_from(body.len()).map_err(|_| malformed("Message too long to compress"))?;
+        let mut compressed = length.to_le_bytes().to_vec();
+        match self {
+            Compression::None => compressed.extend_from_slice(body),
+            Compression::Zstd => compressed.extend(
+                zstd::bulk::compress(body, zstd::DEFAULT_COMPRESSION_LEVEL).map_err(|e| malformed(e.to_string()))?,
+            ),
+            Compression::Lz4 => compressed.extend(lz4_flex::block::compress(body)),
+        }
+        Ok(compressed)
+    }
+
+    /// Decompress a message compressed by `compress`
+    ///
+    /// The claimed length is checked against `max_bytes` before anything is
+    /// allocated, so a small frame cannot expand beyond the limit.
+    fn decompress(self, compressed: &[u8], max_bytes: usize) -> Result<Vec<u8>, ProtocolError> {
+        let (length, data) = compressed
+            .split_at_checked(LENGTH_BYTES)
+            .ok_or_else(|| malformed("Compressed message without a length"))?;
+        let length = u32::from_le_bytes(length.try_into().expect("length has LENGTH_BYTES bytes")) as usize;
+        check_size(length, max_bytes)?;
+
+        let body = match self {
+            Compression::None => data.to_vec(),
+            Compression::Zstd => zstd::bulk::decompress(data, length).map_err(|e| malformed(e.to_string()))?,
+            Compression::Lz4 => lz4_flex::block::decompress(data, length).map_err(|e| malformed(e.to_string()))?,
+        };
+        if body.len() != length {
+            return Err(malformed(format!(
+                "Message decompressed to {} bytes instead of {}",
+                body.len(),
+                length
+            )));
+        }
+        Ok(body)
+    }
+}
+
+/// Frames sent and received on the links of a `TcpTransport`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Frames sent
+    pub frames_sent: u64,
+    /// Frames sent compressed
+    pub compressed_frames_sent: u64,
+    /// Bytes of the sent messages before compression
+    pub raw_bytes_sent: u64,
+    /// Bytes of the sent messages after compression
+    pub wire_bytes_sent: u64,
+    /// Frames received
+    pub frames_received: u64,
+    /// Bytes of the received messages after decompression
+    pub raw_bytes_received: u64,
+    /// Bytes of the received messages before decompression
+    pub wire_bytes_received: u64,
+}
+
+impl CompressionStats {
+    /// Sent bytes after compression per byte before, 1 if nothing was sent
+    pub fn ratio(&self) -> f64 {
+        if self.raw_bytes_sent == 0 {
+            return 1.0;
+        }
+        self.wire_bytes_sent as f64 / self.raw_bytes_sent as f64
+    }
+}
+
+/// Counters behind `CompressionStats`, shared by the links of a transport
+#[derive(Debug, Default)]
+pub struct FrameCounters {
+    frames_sent: AtomicU64,
+    compressed_frames_sent: AtomicU64,
+    raw_bytes_sent: AtomicU64,
+    wire_bytes_sent: AtomicU64,
+    frames_received: AtomicU64,
+    raw_bytes_received: AtomicU64,
+    wire_bytes_received: AtomicU64,
+}
+
+impl FrameCounters {
+    fn sent(&self, raw: usize, wire: usize, compression: Compression) {
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+        if compression != Compression::None {
+            self.compressed_frames_sent.fetch_add(1, Ordering::Relaxed);
+        }
+        self.raw_bytes_sent.fetch_add(raw as u64, Ordering::Relaxed);
+        self.wire_bytes_sent.fetch_add(wire as u64, Ordering::Relaxed);
+    }
+
+    fn received(&self, raw: usize, wire: usize) {
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+        self.raw_bytes_received.fetch_add(raw as u64, Ordering::Relaxed);
+        self.wire_bytes_received.fetch_add(wire as u64, Ordering::Relaxed);
+    }
+
+    /// Get the counters
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            frames_sent: self.frames_sent.load(Ordering::Relaxed),
+            compressed_frames_sent: self.compressed_frames_sent.load(Ordering::Relaxed),
+            raw_bytes_sent: self.raw_bytes_sent.load(Ordering::Relaxed),
+            wire_bytes_sent: self.wire_bytes_sent.load(Ordering::Relaxed),
+            frames_received: self.frames_received.load(Ordering::Relaxed),
+            raw_bytes_received: self.raw_bytes_received.load(Ordering::Relaxed),
+            wire_bytes_received: self.wire_bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// JSON, as produced by `NetworkMessage::to_bytes`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl MessageCodec for JsonCodec {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode(&self, message: &NetworkMessage) -> Result<Vec<u8>, ProtocolError> {
+        message.to_bytes()
+    }
+
+    fn decode(&self, bytes: &[u8], max_bytes: usize) -> Result<NetworkMessage, ProtocolError> {
+        check_size(bytes.len(), max_bytes)?;
+        NetworkMessage::from_bytes(bytes)
+    }
+}
+
+/// CBOR (RFC 8949) through serde
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+impl MessageCodec for CborCodec {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn encode(&self, message: &NetworkMessage) -> Result<Vec<u8>, ProtocolError> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(message, &mut bytes).map_err(|e| malformed(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8], max_bytes: usize) -> Result<NetworkMessage, ProtocolError> {
+        check_size(bytes.len(), max_bytes)?;
+        let message: NetworkMessage = ciborium::de::from_reader(bytes).map_err(|e| malformed(e.to_string()))?;
+        message.validate()?;
+        Ok(message)
+    }
+}
+
+/// Payload of a compact encoding
+///
+/// Shares, permutations and other byte payloads are encoded natively, and
+/// oblivious transfer steps with `ExtensionMessage::to_bytes`; data points,
+/// queries and results are carried as JSON, as in zero-copy frames.
+#[derive(Serialize, Deserialize)]
+enum CompactPayload {
+    Empty,
+    Heartbeat,
+    Shares(Vec<Vec<u8>>),
+    Permutation(Vec<u64>),
+    Handshake(Vec<u8>),
+    Encrypted(Vec<u8>),
+    Error(String),
+    Json(Vec<u8>),
+    ObliviousTransfer(Vec<u8>),
+}
+
+impl CompactPayload {
+    fn from_payload(payload: &MessagePayload) -> Result<Self, ProtocolError> {
+        Ok(match payload {
+            MessagePayload::Empty => CompactPayload::Empty,
+            MessagePayload::Heartbeat => CompactPayload::Heartbeat,
+            MessagePayload::Shares(shares) => CompactPayload::Shares(shares.clone()),
+            MessagePayload::Permutation(permutation) => {
+                CompactPayload::Permutation(permutation.iter().map(|&i| i as u64).collect())
+            }
+            MessagePayload::Handshake(bytes) => CompactPayload::Handshake(bytes.clone()),
+            MessagePayload::Encrypted(bytes) => CompactPayload::Encrypted(bytes.clone()),
+            MessagePayload::Error(error) => CompactPayload::Error(error.clone()),
+            MessagePayload::ObliviousTransfer(step) => CompactPayload::ObliviousTransfer(step.to_bytes().map_err(malformed)?),
+            payload => CompactPayload::Json(serde_json::to_vec(payload).map_err(|e| malformed(e.to_string()))?),
+        })
+    }
+
+    fn into_payload(self) -> Result<MessagePayload, ProtocolError> {
+        Ok(match self {
+            CompactPayload::Empty => MessagePayload::Empty,
+            CompactPayload::Heartbeat => MessagePayload::Heartbeat,
+            CompactPayload::Shares(shares) => MessagePayload::Shares(shares),
+            CompactPayload::Permutation(permutation) => MessagePayload::Permutation(
+                permutation.into_iter().map(|i| to_usize(i, "permutation entry")).collect::<Result<_, _>>()?,
+            ),
+            CompactPayload::Handshake(bytes) => MessagePayload::Handshake(bytes),
+            CompactPayload::Encrypted(bytes) => MessagePayload::Encrypted(bytes),
+            CompactPayload::Error(error) => MessagePayload::Error(error),
+            CompactPayload::Json(json) => serde_json::from_slice(&json).map_err(|e| malformed(e.to_string()))?,
+            CompactPayload::ObliviousTransfer(bytes) => {
+                MessagePayload::ObliviousTransfer(ExtensionMessage::from_bytes(&bytes).map_err(malformed)?)
+            }
+        })
+    }
+}
+
+fn to_usize(value: u64, what: &str) -> Result<usize, ProtocolError> {
+    usize::try_from(value).map_err(|_| malformed(format!("{} {} is out of range", what, value)))
+}
+
+fn check_size(len: usize, max_bytes: usize) -> Result<(), ProtocolError> {
+    if len > max_bytes {
+        return Err(malformed(format!("{} bytes exceed the limit of {} bytes", len, max_bytes)));
+    }
+    Ok(())
+}
+
+/// `NetworkMessage` with platform-independent integers and a compact payload
+#[derive(Serialize, Deserialize)]
+struct BincodeMessage {
+    message_type: MessageType,
+    source_id: u64,
+    target_id: u64,
+    sequence: u64,
+    timestamp: u64,
+    payload: CompactPayload,
+    mac: Option<Vec<u8>>,
+    version: u16,
+    phase: Option<ProtocolPhase>,
+}
+
+/// bincode with variable-length integers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+impl MessageCodec for BincodeCodec {
+    fn name(&self) -> &'static str {
+        "bincode"
+    }
+
+    fn encode(&self, message: &NetworkMessage) -> Result<Vec<u8>, ProtocolError> {
+        let wire = BincodeMessage {
+            message_type: message.message_type.clone(),
+            source_id: message.source_id as u64,
+            target_id: message.target_id as u64,
+            sequence: message.sequence,
+            timestamp: message.timestamp,
+            payload: CompactPayload::from_payload(&message.payload)?,
+            mac: message.mac.clone(),
+            version: message.version,
+            phase: message.phase,
+        };
+        bincode::DefaultOptions::new()
+            .serialize(&wire)
+            .map_err(|e| malformed(e.to_string()))
+    }
+
+    fn decode(&self, bytes: &[u8], max_bytes: usize) -> Result<NetworkMessage, ProtocolError> {
+        check_size(bytes.len(), max_bytes)?;
+        // The limit also bounds the lengths claimed inside the message
+        let wire: BincodeMessage = bincode::DefaultOptions::new()
+            .with_limit(bytes.len() as u64)
+            .reject_trailing_bytes()
+            .deserialize(bytes)
+            .map_err(|e| malformed(e.to_string()))?;
+
+        let message = NetworkMessage {
+            message_type: wire.message_type,
+            source_id: to_usize(wire.source_id, "source_id")?,
+            target_id: to_usize(wire.target_id, "target_id")?,
+            sequence: wire.sequence,
+            payload: wire.payload.into_payload()?,
+            timestamp: wire.timestamp,
+            mac: wire.mac,
+            version: wire.version,
+            phase: wire.phase,
+        };
+        message.validate()?;
+        Ok(message)
+    }
+}
+
+/// Wire messages of `ProtobufCodec`, mirroring `proto/network.proto`
+pub mod proto {
+    use prost::{Enumeration, Message, Oneof};
+
+    /// Type of a `NetworkMessage`
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Enumeration)]
+    #[repr(i32)]
+    pub enum MessageKind {
+        Init = 0,
+        Share = 1,
+        Shuffle = 2,
+        Reconstruct = 3,
+        Query = 4,
+        QueryResponse = 5,
+        Heartbeat = 6,
+        Error = 7,
+        Ack = 8,
+        Broadcast = 9,
+        ObliviousTransfer = 10,
+    }
+
+    /// Wire message for a `NetworkMessage`
+    #[derive(Clone, PartialEq, Message)]
+    pub struct NetworkMessageProto {
+        #[prost(enumeration = "MessageKind", tag = "1")]
+        pub message_type: i32,
+        /// Text of an error message type
+        #[prost(string, tag = "2")]
+        pub error: String,
+        #[prost(uint64, tag = "3")]
+        pub source_id: u64,
+        #[prost(uint64, tag = "4")]
+        pub target_id: u64,
+        #[prost(uint64, tag = "5")]
+        pub sequence: u64,
+        #[prost(uint64, tag = "6")]
+        pub timestamp: u64,
+        /// Authentication tag
+        #[prost(bytes = "vec", optional, tag = "7")]
+        pub mac: Option<Vec<u8>>,
+        /// Version of the wire protocol
+        #[prost(uint32, tag = "8")]
+        pub version: u32,
+        /// Phase of the protocol, in the order of `ProtocolPhase`
+        #[prost(uint32, optional, tag = "9")]
+        pub phase: Option<u32>,
+        #[prost(oneof = "Payload", tags = "10, 11, 12, 13, 14, 15, 16, 17, 18")]
+        pub payload: Option<Payload>,
+    }
+
+    /// Payload without content
+    #[derive(Clone, PartialEq, Message)]
+    pub struct EmptyProto {}
+
+    /// Shares of a share payload
+    #[derive(Clone, PartialEq, Message)]
+    pub struct SharesProto {
+        #[prost(bytes = "vec", repeated, tag = "1")]
+        pub shares: Vec<Vec<u8>>,
+    }
+
+    /// Positions of a permutation payload
+    #[derive(Clone, PartialEq, Message)]
+    pub struct PermutationProto {
+        #[prost(uint64, repeated, tag = "1")]
+        pub positions: Vec<u64>,
+    }
+
+    /// Payload of a `NetworkMessageProto`
+    #[derive(Clone, PartialEq, Oneof)]
+    pub enum Payload {
+        #[prost(message, tag = "10")]
+        Empty(EmptyProto),
+        #[prost(message, tag = "11")]
+        Heartbeat(EmptyProto),
+        #[prost(message, tag = "12")]
+        Shares(SharesProto),
+        #[prost(message, tag = "13")]
+        Permutation(PermutationProto),
+        #[prost(bytes, tag = "14")]
+        Handshake(Vec<u8>),
+        #[prost(bytes, tag = "15")]
+        Encrypted(Vec<u8>),
+        #[prost(string, tag = "16")]
+        Error(String),
+        /// Data points, a query, a query result, protocol versions or a broadcast step as JSON
+        #[prost(bytes, tag = "17")]
+        Json(Vec<u8>),
+        /// Step of an oblivious transfer, see `ExtensionMessage::to_bytes`
+        #[prost(bytes, tag = "18")]
+        ObliviousTransfer(Vec<u8>),
+    }
+}
+
+/// Protocol Buffers with the schema in `proto/network.proto`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProtobufCodec;
+
+impl MessageCodec for ProtobufCodec {
+    fn name(&self) -> &'static str {
+        "protobuf"
+    }
+
+    fn encode(&self, message: &NetworkMessage) -> Result<Vec<u8>, ProtocolError> {
+        use proto::{EmptyProto, MessageKind, Payload, PermutationProto, SharesProto};
+        use prost::Message;
+
+        let (kind, error) = match &message.message_type {
+            MessageType::Init => (MessageKind::Init, String::new()),
+            MessageType::Share => (MessageKind::Share, String::new()),
+            MessageType::Shuffle => (MessageKind::Shuffle, String::new()),
+            MessageType::Reconstruct => (MessageKind::Reconstruct, String::new()),
+            MessageType::Query => (MessageKind::Query, String::new()),
+            MessageType::QueryResponse => (MessageKind::QueryResponse, String::new()),
+            MessageType::Heartbeat => (MessageKind::Heartbeat, String::new()),
+            MessageType::Error(error) => (MessageKind::Error, error.clone()),
+            MessageType::Ack => (MessageKind::Ack, String::new()),
+            MessageType::Broadcast => (MessageKind::Broadcast, String::new()),
+            MessageType::ObliviousTransfer => (MessageKind::ObliviousTransfer, String::new()),
+        };
+        let payload = match CompactPayload::from_payload(&message.payload)? {
+            CompactPayload::Empty => Payload::Empty(EmptyProto {}),
+            CompactPayload::Heartbeat => Payload::Heartbeat(EmptyProto {}),
+            CompactPayload::Shares(shares) => Payload::Shares(SharesProto { shares }),
+            CompactPayload::Permutation(positions) => Payload::Permutation(PermutationProto { positions }),
+            CompactPayload::Handshake(bytes) => Payload::Handshake(bytes),
+            CompactPayload::Encrypted(bytes) => Payload::Encrypted(bytes),
+            CompactPayload::Error(error) => Payload::Error(error),
+            CompactPayload::Json(json) => Payload::Json(json),
+            CompactPayload::ObliviousTransfer(bytes) => Payload::ObliviousTransfer(bytes),
+        };
+
+        Ok(proto::NetworkMessageProto {
+            message_type: kind as i32,
+            error,
+            source_id: message.source_id as u64,
+            target_id: message.target_id as u64,
+            sequence: message.sequence,
+            timestamp: message.timestamp,
+            mac: message.mac.clone(),
+            version: message.version.into(),
+            phase: message.phase.map(|phase| phase.tag().into()),
+            payload: Some(payload),
+        }
+        .encode_to_vec())
+    }
+
+    fn decode(&self, bytes: &[u8], max_bytes: usize) -> Result<NetworkMessage, ProtocolError> {
+        use proto::{MessageKind, Payload};
+        use prost::Message;
+
+        check_size(bytes.len(), max_bytes)?;
+        let wire = proto::NetworkMessageProto::decode(bytes).map_err(|e| malformed(e.to_string()))?;
+
+        let message_type = match MessageKind::try_from(wire.message_type) {
+            Ok(MessageKind::Init) => MessageType::Init,
+            Ok(MessageKind::Share) => MessageType::Share,
+            Ok(MessageKind::Shuffle) => MessageType::Shuffle,
+            Ok(MessageKind::Reconstruct) => MessageType::Reconstruct,
+            Ok(MessageKind::Query) => MessageType::Query,
+            Ok(MessageKind::QueryResponse) => MessageType::QueryResponse,
+            Ok(MessageKind::Heartbeat) => MessageType::Heartbeat,
+            Ok(MessageKind::Error) => MessageType::Error(wire.error),
+            Ok(MessageKind::Ack) => MessageType::Ack,
+            Ok(MessageKind::Broadcast) => MessageType::Broadcast,
+            Ok(MessageKind::ObliviousTransfer) => MessageType::ObliviousTransfer,
+            Err(_) => return Err(malformed(format!("Unknown message type {}", wire.message_type))),
+        };
+        let payload = match wire.payload.ok_or_else(|| malformed("Message without a payload"))? {
+            Payload::Empty(_) => CompactPayload::Empty,
+            Payload::Heartbeat(_) => CompactPayload::Heartbeat,
+            Payload::Shares(shares) => CompactPayload::Shares(shares.shares),
+            Payload::Permutation(permutation) => CompactPayload::Permutation(permutation.positions),
+            Payload::Handshake(bytes) => CompactPayload::Handshake(bytes),
+            Payload::Encrypted(bytes) => CompactPayload::Encrypted(bytes),
+            Payload::Error(error) => CompactPayload::Error(error),
+            Payload::Json(json) => CompactPayload::Json(json),
+            Payload::ObliviousTransfer(bytes) => CompactPayload::ObliviousTransfer(bytes),
+        };
+
+        let message = NetworkMessage {
+            message_type,
+            source_id: to_usize(wire.source_id, "source_id")?,
+            target_id: to_usize(wire.target_id, "target_id")?,
+            sequence: wire.sequence,
+            payload: payload.into_payload()?,
+            timestamp: wire.timestamp,
+            mac: wire.mac,
+            version: u16::try_from(wire.version).map_err(|_| malformed(format!("Unknown version {}", wire.version)))?,
+            phase: wire
+                .phase
+                .map(|tag| {
+                    u8::try_from(tag)
+                        .ok()
+                        .and_then(ProtocolPhase::from_tag)
+                        .ok_or_else(|| malformed(format!("Unknown phase {}", tag)))
+                })
+                .transpose()?,
+        };
+        message.validate()?;
+        Ok(message)
+    }
+}
+
+/// Length-prefixed frames of encoded messages
+///
+/// A frame is the length of the rest of the frame as a little-endian `u32`, a
+/// byte naming the codec in its low and the compression in its high four bits,
+/// and the encoded message. Frames longer than `max_message_bytes` are
+/// rejected before they are buffered, so a peer cannot make the receiver
+/// allocate more than the limit.
+///
+/// Messages of at least `COMPRESSION_THRESHOLD` bytes are sent compressed
+/// with `compression` if that makes them shorter, and compressed frames are
+/// prefixed with the length of the message. Frames of any compression are
+/// decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framing {
+    /// Codec of the messages
+    pub codec: CodecKind,
+    /// Largest encoded message sent or accepted
+    pub max_message_bytes: usize,
+    /// Compression of the messages sent
+    pub compression: Compression,
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Self::new(CodecKind::default(), MAX_MESSAGE_BYTES)
+    }
+}
+
+impl Framing {
+    /// Create a framing with the given codec and size limit
+    pub fn new(codec: CodecKind, max_message_bytes: usize) -> Self {
+        Self {
+            codec,
+            max_message_bytes,
+            compression: Compression::None,
+        }
+    }
+
+    /// Compress the messages sent
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encode a message as one frame
+    pub fn encode(&self, message: &NetworkMessage) -> Result<Vec<u8>, ProtocolError> {
+        self.encode_frame(message, false).map(|(frame, _, _)| frame)
+    }
+
+    /// Encode a message as one frame, counting it in `counters`
+    pub fn encode_counted(&self, message: &NetworkMessage, counters: &FrameCounters) -> Result<Vec<u8>, ProtocolError> {
+        let (frame, raw, compression) = self.encode_frame(message, false)?;
+        counters.sent(raw, frame.len() - LENGTH_BYTES - 1, compression);
+        Ok(frame)
+    }
+
+    /// Encode a message as one frame compressed with `compression` whatever its length
+    ///
+    /// The compression of a hello frame offers it for the link, see `TcpTransport`.
+    pub(crate) fn encode_hello(&self, message: &NetworkMessage) -> Result<Vec<u8>, ProtocolError> {
+        self.encode_frame(message, true).map(|(frame, _, _)| frame)
+    }
+
+    /// Encode a frame, returning it with the length of the message before compression
+    fn encode_frame(&self, message: &NetworkMessage, force: bool) -> Result<(Vec<u8>, usize, Compression), ProtocolError> {
+        let body = self.codec.codec().encode(message)?;
+        check_size(body.len(), self.max_message_bytes)?;
+        let raw = body.len();
+
+        let (compression, body) = match self.compression {
+            Compression::None => (Compression::None, body),
+            _ if !force && raw < COMPRESSION_THRESHOLD => (Compression::None, body),
+            compression => {
+                let compressed = compression.compress(&body)?;
+                if force || compressed.len() < raw {
+                    (compression, compressed)
+                } else {
+                    // Shares of uniformly random field elements barely compress
+                    (Compression::None, body)
+                }
+            }
+        };
+        let length = u32::try_from(body.len() + 1)
+            .map_err(|_| malformed(format!("Message of {} bytes does not fit in a frame", body.len())))?;
+
+        let mut frame = Vec::with_capacity(LENGTH_BYTES + 1 + body.len());
+        frame.extend_from_slice(&length.to_le_bytes());
+        frame.push(self.codec.tag() | compression.tag() << 4);
+        frame.extend_from_slice(&body);
+        Ok((frame, raw, compression))
+    }
+
+    /// Check the length prefix of a frame, returning the length of the rest
+    fn frame_length(&self, prefix: [u8; LENGTH_BYTES]) -> Result<usize, ProtocolError> {
+        let length = u32::from_le_bytes(prefix) as usize;
+        if length == 0 {
+            return Err(malformed("Empty frame"));
+        }
+        check_size(length - 1, self.max_message_bytes)?;
+        Ok(length)
+    }
+
+    /// Decode the rest of a frame after its length prefix
+    ///
+    /// Returns the message with its compression and its length before compression.
+    fn decode_body(&self, frame: &[u8]) -> Result<(NetworkMessage, Compression, usize), ProtocolError> {
+        let (&tag, body) = frame.split_first().ok_or_else(|| malformed("Empty frame"))?;
+        if tag & 0x0f != self.codec.tag() {
+            return Err(malformed(format!(
+                "Frame encoded with codec {}, expected {}",
+                tag & 0x0f,
+                self.codec.codec().name()
+            )));
+        }
+
+        let codec = self.codec.codec();
+        match Compression::from_tag(tag >> 4)? {
+            Compression::None => Ok((codec.decode(body, self.max_message_bytes)?, Compression::None, body.len())),
+            compression => {
+                let body = compression.decompress(body, self.max_message_bytes)?;
+                Ok((codec.decode(&body, self.max_message_bytes)?, compression, body.len()))
+            }
+        }
+    }
+
+    /// Decode the first frame in `buffer` with `decode` and remove it
+    ///
+    /// Returns `None` if the buffer does not hold a complete frame yet.
+    fn decode_next<T>(
+        &self,
+        buffer: &mut Vec<u8>,
+        decode: impl FnOnce(&[u8]) -> Result<T, ProtocolError>,
+    ) -> Result<Option<T>, ProtocolError> {
+        let Some(prefix) = buffer.get(..LENGTH_BYTES) else {
+            return Ok(None);
+        };
+        let length = self.frame_length(prefix.try_into().expect("prefix has LENGTH_BYTES bytes"))?;
+        if buffer.len() < LENGTH_BYTES + length {
+            return Ok(None);
+        }
+
+        let decoded = decode(&buffer[LENGTH_BYTES..LENGTH_BYTES + length]);
+        buffer.drain(..LENGTH_BYTES + length);
+        decoded.map(Some)
+    }
+
+    /// Decode the first frame in `buffer` and remove it
+    ///
+    /// Returns `None` if the buffer does not hold a complete frame yet.
+    pub fn decode(&self, buffer: &mut Vec<u8>) -> Result<Option<NetworkMessage>, ProtocolError> {
+        self.decode_next(buffer, |frame| self.decode_body(frame).map(|(message, _, _)| message))
+    }
+
+    /// Decode the first frame in `buffer` and remove it, counting it in `counters`
+    pub fn decode_counted(
+        &self,
+        buffer: &mut Vec<u8>,
+        counters: &FrameCounters,
+    ) -> Result<Option<NetworkMessage>, ProtocolError> {
+        self.decode_next(buffer, |frame| {
+            let (message, _, raw) = self.decode_body(frame)?;
+            counters.received(raw, frame.len() - 1);
+            Ok(message)
+        })
+    }
+
+    /// Decode the first frame in `buffer` and remove it, returning the compression it offers
+    pub(crate) fn decode_hello(
+        &self,
+        buffer: &mut Vec<u8>,
+    ) -> Result<Option<(NetworkMessage, Compression)>, ProtocolError> {
+        self.decode_next(buffer, |frame| {
+            self.decode_body(frame).map(|(message, compression, _)| (message, compression))
+        })
+    }
+
+    /// Write a message as one frame
+    pub async fn write<W: AsyncWrite + Unpin>(&self, writer: &mut W, message: &NetworkMessage) -> Result<(), ProtocolError> {
+        let frame = self.encode(message)?;
+        writer.write_all(&frame).await.map_err(io_error)?;
+        writer.flush().await.map_err(io_error)
+    }
+
+    /// Read the next frame
+    ///
+    /// Returns `None` if the reader ends between frames.
+    pub async fn read<R: AsyncRead + Unpin>(&self, reader: &mut R) -> Result<Option<NetworkMessage>, ProtocolError> {
+        let mut prefix = [0u8; LENGTH_BYTES];
+        let mut read = 0;
+        while read < LENGTH_BYTES {
+            match reader.read(&mut prefix[read..]).await.map_err(io_error)? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(io_error(io::ErrorKind::UnexpectedEof.into())),
+                n => read += n,
+            }
+        }
+
+        let mut frame = vec![0u8; self.frame_length(prefix)?];
+        reader.read_exact(&mut frame).await.map_err(io_error)?;
+        self.decode_body(&frame).map(|(message, _, _)| Some(message))
+    }
+}
+
+/// Convert an I/O error into a protocol error
+fn io_error(error: io::Error) -> ProtocolError {
+    ProtocolError::network_error(format!("Frame I/O failed: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{DataPoint, Query, QueryType};
+
+    fn messages() -> Vec<NetworkMessage> {
+        vec![
+            NetworkMessage::share(0, 1, 1, vec![vec![1, 2, 3], vec![]]),
+            NetworkMessage::shuffle(1, 2, 2, vec![2, 0, 1]),
+            NetworkMessage::query(2, 0, 3, Query::new(QueryType::Mean, vec!["feature1".to_string()])),
+            NetworkMessage::new(
+                MessageType::Share,
+                0,
+                1,
+                4,
+                MessagePayload::Data(vec![DataPoint::new(vec![1.5, -2.0])]),
+            ),
+            NetworkMessage::error(1, 0, 5, "failed".to_string()),
+            NetworkMessage::heartbeat(0, 2, 6),
+            NetworkMessage::new(MessageType::Init, 0, 1, 7, MessagePayload::Handshake(vec![9; 48])),
+            NetworkMessage::oblivious_transfer(1, 0, 8, ExtensionMessage::BaseReceiverPoints(vec![[7; 32]; 3])),
+        ]
+    }
+
+    #[test]
+    fn test_codecs_roundtrip() {
+        for kind in [CodecKind::Json, CodecKind::Bincode, CodecKind::Protobuf, CodecKind::Cbor] {
+            let codec = kind.codec();
+            for message in messages() {
+                let bytes = codec.encode(&message).unwrap();
+                let decoded = codec.decode(&bytes, MAX_MESSAGE_BYTES).unwrap();
+                assert_eq!(
+                    serde_json::to_value(&decoded).unwrap(),
+                    serde_json::to_value(&message).unwrap(),
+                    "{} codec",
+                    codec.name()
+                );
+                assert!(codec.decode(&bytes, bytes.len() - 1).is_err());
+                assert!(codec.decode(&bytes[..bytes.len() / 2], MAX_MESSAGE_BYTES).is_err());
+            }
+
+            // Payloads that do not match the message type are rejected
+            let mut message = NetworkMessage::shuffle(0, 1, 7, vec![0, 0, 3]);
+            assert!(codec.decode(&codec.encode(&message).unwrap(), MAX_MESSAGE_BYTES).is_err());
+            message.payload = MessagePayload::Heartbeat;
+            assert!(codec.decode(&codec.encode(&message).unwrap(), MAX_MESSAGE_BYTES).is_err());
+        }
+    }
+
+    #[test]
+    fn test_framing() {
+        let framing = Framing::new(CodecKind::Bincode, 1024);
+        let mut buffer = Vec::new();
+        for message in messages() {
+            buffer.extend(framing.encode(&message).unwrap());
+        }
+
+        // Frames arrive split at arbitrary points
+        let mut received = Vec::new();
+        let mut pending = Vec::new();
+        for chunk in buffer.chunks(5) {
+            pending.extend_from_slice(chunk);
+            while let Some(message) = framing.decode(&mut pending).unwrap() {
+                received.push(message.sequence);
+            }
+        }
+        assert_eq!(received, (1..=7).collect::<Vec<_>>());
+        assert!(pending.is_empty());
+
+        let large = NetworkMessage::share(0, 1, 8, vec![vec![0; 2048]]);
+        assert!(framing.encode(&large).is_err());
+        let mut oversized = Framing::new(CodecKind::Bincode, 4096).encode(&large).unwrap();
+        oversized.truncate(LENGTH_BYTES);
+        assert!(framing.decode(&mut oversized).is_err());
+
+        let mut cbor = Framing::new(CodecKind::Cbor, 1024).encode(&messages()[0]).unwrap();
+        assert!(framing.decode(&mut cbor).is_err());
+    }
+
+    #[test]
+    fn test_compressed_framing() {
+        let large = NetworkMessage::share(0, 1, 1, vec![vec![7; 4096]]);
+        let small = NetworkMessage::heartbeat(0, 1, 2);
+        let plain = Framing::new(CodecKind::Bincode, 1 << 16);
+
+        for compression in [Compression::Zstd, Compression::Lz4] {
+            let framing = plain.with_compression(compression);
+            let counters = FrameCounters::default();
+            let mut buffer = framing.encode_counted(&large, &counters).unwrap();
+            assert!(buffer.len() < plain.encode(&large).unwrap().len() / 4);
+            buffer.extend(framing.encode_counted(&small, &counters).unwrap());
+
+            // Any framing with the same codec decodes compressed frames
+            let received = FrameCounters::default();
+            assert_eq!(plain.decode_counted(&mut buffer, &received).unwrap().unwrap().sequence, 1);
+            assert_eq!(plain.decode_counted(&mut buffer, &received).unwrap().unwrap().sequence, 2);
+            assert!(buffer.is_empty());
+
+            let stats = counters.stats();
+            assert_eq!((stats.frames_sent, stats.compressed_frames_sent), (2, 1));
+            assert!(stats.ratio() < 0.25);
+            let received = received.stats();
+            assert_eq!(received.raw_bytes_received, stats.raw_bytes_sent);
+            assert_eq!(received.wire_bytes_received, stats.wire_bytes_sent);
+
+            // Hello frames name the compression even when short
+            let mut hello = framing.encode_hello(&small).unwrap();
+            assert_eq!(plain.decode_hello(&mut hello).unwrap().unwrap().1, compression);
+
+            // A frame that decompresses beyond the limit is rejected
+            let mut bomb = framing.encode(&large).unwrap();
+            assert!(Framing::new(CodecKind::Bincode, 1024).decode(&mut bomb).is_err());
+        }
+
+        let mut unknown = plain.encode(&small).unwrap();
+        unknown[LENGTH_BYTES] |= 0xf0;
+        assert!(plain.decode(&mut unknown).is_err());
+    }
+
+    #[test]
+    fn test_framing_io() {
+        let framing = Framing::new(CodecKind::Protobuf, 1024);
+        let mut stream = futures::io::Cursor::new(Vec::new());
+        futures::executor::block_on(async {
+            for message in messages() {
+                framing.write(&mut stream, &message).await.unwrap();
+            }
+
+            stream.set_position(0);
+            let mut sequences = Vec::new();
+            while let Some(message) = framing.read(&mut stream).await.unwrap() {
+                sequences.push(message.sequence);
+            }
+            assert_eq!(sequences, (1..=7).collect::<Vec<_>>());
+
+            let mut truncated = futures::io::Cursor::new(framing.encode(&messages()[0]).unwrap()[..6].to_vec());
+            assert!(framing.read(&mut truncated).await.is_err());
+        });
+    }
+}
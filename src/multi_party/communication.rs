@@ -1,9 +1,33 @@
+use futures::future::{self, BoxFuture};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio::time::{Duration, timeout};
-use crate::multi_party::protocol::ProtocolError;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::config::{self, ConfigError};
+use crate::multi_party::auth::MessageAuthenticator;
+use crate::multi_party::broadcast::{self, BroadcastMessage, ReliableBroadcast};
+use crate::multi_party::cancel::CancellationToken;
+use crate::multi_party::codec::{CodecKind, Compression, CompressionStats, FrameCounters, Framing};
+use crate::multi_party::flow::{FlowControl, FlowMetrics, FlowState, DEFAULT_QUEUE_CAPACITY};
+use crate::multi_party::heartbeat::{
+    FailureDetector, HeartbeatHandle, HeartbeatSender, HeartbeatState, HeartbeatTask, LivenessCallback, SharedHeartbeat,
+    Suspicion, SuspicionCallback,
+};
+use crate::multi_party::outbox::{ChannelState, Outbox, SessionState};
+use crate::multi_party::protocol::{ProtocolError, ProtocolPhase};
+use crate::multi_party::reliable::{RecentMessages, ResendBuffer, RetryPolicy, DEDUP_WINDOW};
+use crate::multi_party::runtime::{self, Receiver, Sender, SharedRuntime};
+use crate::multi_party::secure::{Handshake, IdentityConfig, SecureSession, ServerKeys, KEY_BYTES};
+use crate::multi_party::topology::Topology;
+use crate::multi_party::traffic::{self, TrafficStats};
+use crate::multi_party::transcript::{Direction, Transcript, TranscriptRecorder};
+use crate::multi_party::transport::TcpTransport;
+use crate::multi_party::version::{VersionRange, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
 use crate::schema::{DataPoint, Query, QueryResult};
+use crate::server::ot::ExtensionMessage;
 
 /// Types of messages that can be sent between servers
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,8 +50,34 @@ pub enum MessageType {
     Error(String),
     /// Acknowledge message
     Ack,
+    /// Step of a reliable broadcast, see `NetworkManager::reliable_broadcast`
+    Broadcast,
+    /// Step of an oblivious transfer, see `server::ot`
+    ObliviousTransfer,
 }
 
+impl MessageType {
+    /// Name of the type, e.g. to count traffic by
+    pub fn name(&self) -> &'static str {
+        match self {
+            MessageType::Init => "init",
+            MessageType::Share => "share",
+            MessageType::Shuffle => "shuffle",
+            MessageType::Reconstruct => "reconstruct",
+            MessageType::Query => "query",
+            MessageType::QueryResponse => "query response",
+            MessageType::Heartbeat => "heartbeat",
+            MessageType::Error(_) => "error",
+            MessageType::Ack => "ack",
+            MessageType::Broadcast => "broadcast",
+            MessageType::ObliviousTransfer => "oblivious transfer",
+        }
+    }
+}
+
+/// Largest encoded `NetworkMessage` accepted by `NetworkMessage::from_bytes`
+pub const MAX_MESSAGE_BYTES: usize = 64 << 20;
+
 /// Network message structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkMessage {
@@ -43,6 +93,20 @@ pub struct NetworkMessage {
     pub payload: MessagePayload,
     /// Timestamp
     pub timestamp: u64,
+    /// Authentication tag, see `MessageAuthenticator`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<Vec<u8>>,
+    /// Version of the wire protocol, see `VersionRange`
+    #[serde(default = "default_version")]
+    pub version: u16,
+    /// Phase the message belongs to, see `NetworkManager::set_phase`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<ProtocolPhase>,
+}
+
+/// Version of messages from servers that predate the version field
+fn default_version() -> u16 {
+    MIN_PROTOCOL_VERSION
 }
 
 /// Message payload types
@@ -64,6 +128,16 @@ pub enum MessagePayload {
     Error(String),
     /// Heartbeat payload
     Heartbeat,
+    /// Noise handshake message
+    Handshake(Vec<u8>),
+    /// Encrypted message, see `CommunicationChannel::with_encryption`
+    Encrypted(Vec<u8>),
+    /// Protocol versions offered when a channel connects, see `CommunicationChannel::with_versions`
+    Versions(VersionRange),
+    /// Step of a reliable broadcast
+    Broadcast(BroadcastMessage),
+    /// Step of an oblivious transfer
+    ObliviousTransfer(ExtensionMessage),
 }
 
 impl NetworkMessage {
@@ -85,6 +159,9 @@ impl NetworkMessage {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs(),
+            mac: None,
+            version: PROTOCOL_VERSION,
+            phase: None,
         }
     }
 
@@ -93,6 +170,21 @@ impl NetworkMessage {
         Self::new(MessageType::Init, source_id, target_id, sequence, MessagePayload::Empty)
     }
 
+    /// Create a message offering the protocol versions `versions`
+    pub fn versions(source_id: usize, target_id: usize, sequence: u64, versions: VersionRange) -> Self {
+        Self::new(MessageType::Init, source_id, target_id, sequence, MessagePayload::Versions(versions))
+    }
+
+    /// Create a message carrying a step of a reliable broadcast
+    pub fn broadcast(source_id: usize, target_id: usize, sequence: u64, step: BroadcastMessage) -> Self {
+        Self::new(MessageType::Broadcast, source_id, target_id, sequence, MessagePayload::Broadcast(step))
+    }
+
+    /// Create a message carrying a step of an oblivious transfer
+    pub fn oblivious_transfer(source_id: usize, target_id: usize, sequence: u64, step: ExtensionMessage) -> Self {
+        Self::new(MessageType::ObliviousTransfer, source_id, target_id, sequence, MessagePayload::ObliviousTransfer(step))
+    }
+
     /// Create a share message
     pub fn share(source_id: usize, target_id: usize, sequence: u64, shares: Vec<Vec<u8>>) -> Self {
         Self::new(MessageType::Share, source_id, target_id, sequence, MessagePayload::Shares(shares))
@@ -120,7 +212,7 @@ impl NetworkMessage {
 
     /// Create an error message
     pub fn error(source_id: usize, target_id: usize, sequence: u64, error: String) -> Self {
-        Self::new(MessageType::Error(error), source_id, target_id, sequence, MessagePayload::Error(error))
+        Self::new(MessageType::Error(error.clone()), source_id, target_id, sequence, MessagePayload::Error(error))
     }
 
     /// Create an acknowledgment message
@@ -135,7 +227,7 @@ impl NetworkMessage {
             .unwrap_or_default()
             .as_secs();
         
-        current_time - self.timestamp > max_age_seconds
+        current_time.saturating_sub(self.timestamp) > max_age_seconds
     }
 
     /// Get message age in seconds
@@ -145,11 +237,137 @@ impl NetworkMessage {
             .unwrap_or_default()
             .as_secs();
         
-        current_time - self.timestamp
+        current_time.saturating_sub(self.timestamp)
+    }
+
+    /// Encode the message for the wire
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ProtocolError> {
+        serde_json::to_vec(self).map_err(|e| ProtocolError::MalformedMessage(e.to_string()))
+    }
+
+    /// Decode a message received from the wire
+    ///
+    /// Malformed input, e.g. from a faulty or malicious peer, is rejected with
+    /// `ProtocolError::MalformedMessage` and never panics. Besides the encoding,
+    /// the payload must match the message type and permutations must be bijections.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        if bytes.len() > MAX_MESSAGE_BYTES {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "{} bytes exceed the limit of {} bytes",
+                bytes.len(),
+                MAX_MESSAGE_BYTES
+            )));
+        }
+
+        let message: Self = serde_json::from_slice(bytes)
+            .map_err(|e| ProtocolError::MalformedMessage(e.to_string()))?;
+        message.validate()?;
+        Ok(message)
+    }
+
+    /// Check if the receiver acknowledges the message on a reliable channel
+    ///
+    /// Handshakes, heartbeats and acknowledgements themselves are not acknowledged.
+    pub fn requires_ack(&self) -> bool {
+        !matches!(self.message_type, MessageType::Init | MessageType::Heartbeat | MessageType::Ack)
+    }
+
+    /// Check that the payload is the one the message type carries
+    pub fn validate(&self) -> Result<(), ProtocolError> {
+        let consistent = match (&self.message_type, &self.payload) {
+            (MessageType::Init | MessageType::Ack | MessageType::Reconstruct, MessagePayload::Empty) => true,
+            (MessageType::Init, MessagePayload::Handshake(_) | MessagePayload::Versions(_)) => true,
+            // The inner message is checked when it is decrypted
+            (_, MessagePayload::Encrypted(_)) => true,
+            (MessageType::Share | MessageType::Reconstruct | MessageType::Shuffle, MessagePayload::Shares(_)) => true,
+            (MessageType::Share, MessagePayload::Data(_)) => true,
+            (MessageType::Shuffle, MessagePayload::Permutation(permutation)) => {
+                let mut seen = vec![false; permutation.len()];
+                if !permutation.iter().all(|&i| i < seen.len() && !std::mem::replace(&mut seen[i], true)) {
+                    return Err(ProtocolError::MalformedMessage(
+                        "Shuffle permutation is not a bijection".to_string(),
+                    ));
+                }
+                true
+            }
+            (MessageType::Query, MessagePayload::Query(_)) => true,
+            (MessageType::QueryResponse, MessagePayload::QueryResult(_)) => true,
+            (MessageType::Heartbeat, MessagePayload::Heartbeat) => true,
+            (MessageType::Error(_), MessagePayload::Error(_)) => true,
+            (MessageType::Broadcast, MessagePayload::Broadcast(_)) => true,
+            (MessageType::ObliviousTransfer, MessagePayload::ObliviousTransfer(_)) => true,
+            _ => false,
+        };
+
+        if !consistent {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "{:?} message with a {} payload",
+                self.message_type,
+                self.payload.kind()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl MessagePayload {
+    /// Name of the payload variant, for error messages
+    fn kind(&self) -> &'static str {
+        match self {
+            MessagePayload::Empty => "empty",
+            MessagePayload::Data(_) => "data",
+            MessagePayload::Shares(_) => "shares",
+            MessagePayload::Query(_) => "query",
+            MessagePayload::QueryResult(_) => "query result",
+            MessagePayload::Permutation(_) => "permutation",
+            MessagePayload::Error(_) => "error",
+            MessagePayload::Heartbeat => "heartbeat",
+            MessagePayload::Handshake(_) => "handshake",
+            MessagePayload::Encrypted(_) => "encrypted",
+            MessagePayload::Versions(_) => "versions",
+            MessagePayload::Broadcast(_) => "broadcast",
+            MessagePayload::ObliviousTransfer(_) => "oblivious transfer",
+        }
     }
 }
 
+/// Default time to wait for each handshake message in `CommunicationChannel::connect`
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Keys a channel authenticates with
+struct ChannelIdentity {
+    local_id: usize,
+    keys: ServerKeys,
+    peer_public_key: [u8; KEY_BYTES],
+}
+
+/// Retransmission and deduplication state of a reliable channel
+struct Reliability {
+    policy: RetryPolicy,
+    /// Sent messages waiting for an acknowledgement
+    unacked: std::sync::Mutex<ResendBuffer>,
+    /// Messages delivered recently, kept across reconnections
+    delivered: RecentMessages,
+}
+
 /// Communication channel between servers
+///
+/// A channel created with `with_encryption` runs a Noise handshake in
+/// `connect` and from then on only sends and accepts encrypted messages.
+///
+/// A channel created with `with_retry` acknowledges the messages it receives,
+/// drops duplicates by source and sequence number, and resends the messages
+/// the target does not acknowledge in time. Resends happen while the channel
+/// waits in `receive_timeout` or when `resend_due` is called. Both ends of a
+/// channel must enable it, and messages need distinct sequence numbers, as
+/// given by `next_sequence`.
+///
+/// A channel created with `with_flow_control` pauses while too many of its
+/// messages wait in the queue to the target, see `FlowControl`.
+///
+/// A channel created with `with_ordering` delivers the messages that require
+/// an acknowledgement in the order the target sent them, holding back those
+/// that overtook an earlier one.
 pub struct CommunicationChannel {
     /// Target server ID
     pub target_id: usize,
@@ -163,10 +381,66 @@ pub struct CommunicationChannel {
     pub last_heartbeat: u64,
     /// Message sequence counter
     pub sequence_counter: u64,
+    /// Runtime whose clock times out receives
+    pub runtime: SharedRuntime,
+    /// Keys of an encrypted channel
+    identity: Option<ChannelIdentity>,
+    /// Session of an encrypted channel, held while sending to keep the
+    /// messages in the order they were encrypted
+    session: Option<Arc<futures::lock::Mutex<SecureSession>>>,
+    /// Signs sent messages and verifies received ones
+    authenticator: Option<MessageAuthenticator>,
+    /// Acknowledgements and resends of a reliable channel
+    reliability: Option<Reliability>,
+    /// Liveness of the target, shared with the heartbeat task
+    heartbeat: SharedHeartbeat,
+    /// Backpressure from the queue to the target
+    flow: Option<Mutex<FlowState>>,
+    /// Protocol versions offered on `connect`
+    versions: Option<VersionOffer>,
+    /// Protocol version agreed on with the target
+    version: Option<u16>,
+    /// Whether messages are numbered and delivered in order
+    ordered: bool,
+    /// Numbering and held back messages of an ordered channel
+    order: DeliveryOrder,
+    /// Codec messages are sized with when counting traffic
+    codec: CodecKind,
+    /// Messages sent and received
+    traffic: Mutex<TrafficStats>,
+}
+
+/// Sequence numbers and held back messages of an ordered channel
+#[derive(Default)]
+struct DeliveryOrder {
+    /// Sequence number of the next ordered message sent
+    next_send: AtomicU64,
+    /// Sequence number of the next message to deliver
+    next_delivery: u64,
+    /// Messages received ahead of `next_delivery`, by sequence number
+    held: BTreeMap<u64, NetworkMessage>,
+    /// Held messages whose turn came, delivered before any received later
+    released: VecDeque<NetworkMessage>,
+}
+
+impl DeliveryOrder {
+    /// Release the held messages that are next in order
+    fn release(&mut self) {
+        while let Some(message) = self.held.remove(&self.next_delivery) {
+            self.next_delivery += 1;
+            self.released.push_back(message);
+        }
+    }
+}
+
+/// Protocol versions a channel offers its target
+struct VersionOffer {
+    local_id: usize,
+    versions: VersionRange,
 }
 
 impl CommunicationChannel {
-    /// Create a new communication channel
+    /// Create a new communication channel on the default runtime
     pub fn new(target_id: usize, sender: Sender<NetworkMessage>, receiver: Receiver<NetworkMessage>) -> Self {
         Self {
             target_id,
@@ -175,57 +449,706 @@ impl CommunicationChannel {
             connected: false,
             last_heartbeat: 0,
             sequence_counter: 0,
+            runtime: runtime::default_runtime(),
+            identity: None,
+            session: None,
+            authenticator: None,
+            reliability: None,
+            heartbeat: Default::default(),
+            flow: None,
+            versions: None,
+            version: None,
+            ordered: false,
+            order: Default::default(),
+            codec: CodecKind::default(),
+            traffic: Default::default(),
+        }
+    }
+
+    /// Use another runtime for timeouts
+    pub fn with_runtime(mut self, runtime: SharedRuntime) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    /// Encrypt the channel between `local_id` and the target server
+    ///
+    /// The target must present the static key `peer_public_key` during the
+    /// handshake, and expect `keys` from this server.
+    pub fn with_encryption(mut self, local_id: usize, keys: ServerKeys, peer_public_key: [u8; KEY_BYTES]) -> Self {
+        self.set_encryption(local_id, keys, peer_public_key);
+        self
+    }
+
+    /// Encrypt the channel from the next `connect` on
+    pub fn set_encryption(&mut self, local_id: usize, keys: ServerKeys, peer_public_key: [u8; KEY_BYTES]) {
+        self.identity = Some(ChannelIdentity {
+            local_id,
+            keys,
+            peer_public_key,
+        });
+        self.session = None;
+        self.connected = false;
+    }
+
+    /// Count the bytes of messages as encoded by `codec`, JSON unless set
+    pub fn set_codec(&mut self, codec: CodecKind) {
+        self.codec = codec;
+    }
+
+    /// Messages sent and received so far, by type and by phase
+    pub fn traffic(&self) -> TrafficStats {
+        self.traffic.lock().unwrap().clone()
+    }
+
+    /// Agree on a protocol version in `versions` with the target on every `connect`
+    ///
+    /// The channel then stamps sent messages with that version and rejects
+    /// received messages of another one.
+    pub fn with_versions(mut self, local_id: usize, versions: VersionRange) -> Self {
+        self.set_versions(local_id, versions);
+        self
+    }
+
+    /// Agree on a protocol version in `versions` with the target from the next `connect` on
+    pub fn set_versions(&mut self, local_id: usize, versions: VersionRange) {
+        self.versions = Some(VersionOffer { local_id, versions });
+        self.version = None;
+        self.connected = false;
+    }
+
+    /// Protocol version agreed on with the target, if the channel negotiates one
+    pub fn version(&self) -> Option<u16> {
+        self.version
+    }
+
+    /// Deliver the messages that require an acknowledgement in the order they were sent
+    ///
+    /// The channel numbers such messages itself, replacing the sequence
+    /// numbers they were sent with, and holds back a received message until
+    /// the ones sent before it are delivered. Both ends of a channel must
+    /// enable it. Without `with_retry`, a lost message holds back every later
+    /// one.
+    pub fn with_ordering(mut self) -> Self {
+        self.set_ordering();
+        self
+    }
+
+    /// Deliver the messages that require an acknowledgement in the order they were sent
+    pub fn set_ordering(&mut self) {
+        self.ordered = true;
+    }
+
+    /// Check if the channel delivers messages in order
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+
+    /// Number of received messages held back until an earlier one arrives
+    pub fn held_messages(&self) -> usize {
+        self.order.held.len()
+    }
+
+    /// Check if messages on the channel are encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// Sign every sent message and verify every received one
+    pub fn set_authenticator(&mut self, authenticator: Option<MessageAuthenticator>) {
+        self.authenticator = authenticator;
+    }
+
+    /// Acknowledge, deduplicate and resend messages according to `policy`
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.set_retry(policy);
+        self
+    }
+
+    /// Acknowledge, deduplicate and resend messages according to `policy`
+    ///
+    /// A channel that is already reliable keeps its unacknowledged and
+    /// delivered messages.
+    pub fn set_retry(&mut self, policy: RetryPolicy) {
+        match &mut self.reliability {
+            Some(reliability) => reliability.policy = policy,
+            None => {
+                self.reliability = Some(Reliability {
+                    policy,
+                    unacked: Default::default(),
+                    delivered: RecentMessages::new(DEDUP_WINDOW),
+                })
+            }
+        }
+    }
+
+    /// Number of sent messages waiting for an acknowledgement
+    pub fn pending_acks(&self) -> usize {
+        self.reliability
+            .as_ref()
+            .map_or(0, |reliability| reliability.unacked.lock().unwrap().len())
+    }
+
+    /// Hold back messages while the queue to the target is above the watermarks of `control`
+    pub fn with_flow_control(mut self, control: FlowControl) -> Self {
+        self.set_flow_control(control);
+        self
+    }
+
+    /// Hold back messages while the queue to the target is above the watermarks of `control`
+    ///
+    /// A channel with flow control already keeps its metrics.
+    pub fn set_flow_control(&mut self, control: FlowControl) {
+        match &mut self.flow {
+            Some(flow) => flow.get_mut().unwrap().control = control,
+            None => self.flow = Some(Mutex::new(FlowState::new(control))),
+        }
+    }
+
+    /// Check if the channel is paused, i.e. `try_send` refuses messages and `send` waits
+    pub fn is_paused(&self) -> bool {
+        self.flow.as_ref().is_some_and(|flow| flow.lock().unwrap().is_paused())
+    }
+
+    /// Queue depths of the channel, and how often it was paused
+    pub fn flow_metrics(&self) -> FlowMetrics {
+        let metrics = self.flow.as_ref().map(|flow| flow.lock().unwrap().metrics()).unwrap_or_default();
+        FlowMetrics {
+            outbound_depth: self.sender.depth(),
+            inbound_depth: self.receiver.depth(),
+            ..metrics
+        }
+    }
+
+    /// Liveness of the target as last seen by the channel and the heartbeat task
+    pub fn heartbeat(&self) -> HeartbeatState {
+        *self.heartbeat.lock().unwrap()
+    }
+
+    /// Sender of heartbeats from `local_id` sharing the link, session and state of the channel
+    pub(crate) fn heartbeat_sender(&self, local_id: usize) -> HeartbeatSender {
+        HeartbeatSender {
+            local_id,
+            target_id: self.target_id,
+            sender: self.sender.clone(),
+            session: self.session.clone(),
+            authenticator: self.authenticator.clone(),
+            state: self.heartbeat.clone(),
+            version: self.version.unwrap_or(PROTOCOL_VERSION),
         }
     }
 
     /// Send a message through the channel
+    ///
+    /// On a reliable channel, a message that fails to send is resent later like
+    /// a lost one, so only failures to sign or encrypt it are returned. A paused
+    /// channel waits for the target to drain its queue, and fails with a
+    /// `Backpressure` error if it does not in time.
     pub async fn send(&self, message: NetworkMessage) -> Result<(), ProtocolError> {
         if !self.connected {
             return Err(ProtocolError::network_error("Channel not connected".to_string()));
         }
+        self.admit(true).await?;
+        self.dispatch(message).await
+    }
+
+    /// Send a message unless the channel is paused, failing with a `Backpressure` error then
+    ///
+    /// Lets a server that both sends and receives a large round keep
+    /// receiving instead of waiting on a target that waits on it too.
+    pub async fn try_send(&self, message: NetworkMessage) -> Result<(), ProtocolError> {
+        if !self.connected {
+            return Err(ProtocolError::network_error("Channel not connected".to_string()));
+        }
+        self.admit(false).await?;
+        self.dispatch(message).await
+    }
+
+    /// Wait, if `wait` is set, until a message may be sent under flow control
+    async fn admit(&self, wait: bool) -> Result<(), ProtocolError> {
+        let Some(flow) = &self.flow else {
+            return Ok(());
+        };
+        let backpressure = |depth| ProtocolError::Backpressure {
+            target_id: self.target_id,
+            depth,
+        };
+
+        let depth = self.sender.depth();
+        let control = {
+            let mut flow = flow.lock().unwrap();
+            if flow.admit(depth) {
+                return Ok(());
+            }
+            if !wait {
+                flow.reject(Duration::ZERO);
+                return Err(backpressure(depth));
+            }
+            flow.control
+        };
+
+        log::debug!("Channel to server {} paused with {} messages queued", self.target_id, depth);
+        let started = self.runtime.now();
+        let drained = runtime::timeout(&*self.runtime, control.max_wait, self.sender.drained(control.low_watermark)).await;
+        let waited = self.runtime.now().saturating_sub(started);
+        let mut flow = flow.lock().unwrap();
+        match drained {
+            Ok(()) => {
+                flow.resume(waited);
+                Ok(())
+            }
+            Err(_) => {
+                flow.reject(waited);
+                Err(backpressure(self.sender.depth()))
+            }
+        }
+    }
+
+    /// Send a message admitted by flow control, numbering it on an ordered channel and
+    /// tracking it on a reliable one
+    async fn dispatch(&self, mut message: NetworkMessage) -> Result<(), ProtocolError> {
+        if self.ordered && message.requires_ack() {
+            message.sequence = self.order.next_send.fetch_add(1, Ordering::SeqCst);
+        }
+        let reliability = match &self.reliability {
+            Some(reliability) if message.requires_ack() => reliability,
+            _ => return self.transmit(message).await,
+        };
+        let sequence = message.sequence;
+        let retry_at = self.runtime.now() + reliability.policy.backoff(0);
+        reliability.unacked.lock().unwrap().track(message.clone(), retry_at);
+        match self.transmit(message).await {
+            Err(ProtocolError::NetworkError { message: error }) => {
+                log::debug!("Failed to send message {} to server {}, will resend: {}", sequence, self.target_id, error);
+                Ok(())
+            }
+            Err(e) => {
+                reliability.unacked.lock().unwrap().acknowledge(sequence);
+                Err(e)
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Resend the messages whose acknowledgement is overdue, returning how many were resent
+    ///
+    /// A message still unacknowledged after the retries of the policy is given
+    /// up with an error.
+    pub async fn resend_due(&self) -> Result<usize, ProtocolError> {
+        let reliability = match &self.reliability {
+            Some(reliability) if self.connected => reliability,
+            _ => return Ok(0),
+        };
+
+        let (due, given_up) = reliability.unacked.lock().unwrap().take_due(self.runtime.now(), &reliability.policy);
+        let resent = due.len();
+        for message in due {
+            let sequence = message.sequence;
+            if let Err(e) = self.transmit(message).await {
+                log::debug!("Failed to resend message {} to server {}: {}", sequence, self.target_id, e);
+            }
+        }
+
+        match given_up.first() {
+            Some(sequence) => Err(ProtocolError::network_error(format!(
+                "Message {} to server {} was not acknowledged after {} retries",
+                sequence, self.target_id, reliability.policy.max_retries
+            ))),
+            None => Ok(resent),
+        }
+    }
+
+    /// Sign, encrypt and send a message once
+    async fn transmit(&self, mut message: NetworkMessage) -> Result<(), ProtocolError> {
+        // Resent messages take the version of the link they are resent on
+        if let Some(version) = self.version {
+            message.version = version;
+        }
+        if let Some(authenticator) = &self.authenticator {
+            authenticator.sign(&mut message)?;
+        }
+
+        match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                let sealed = seal_message(&mut session, &message)?;
+                self.send_raw(sealed).await
+            }
+            None if self.identity.is_some() => {
+                Err(ProtocolError::Security("Encrypted channel has no session".to_string()))
+            }
+            None => self.send_raw(message).await,
+        }
+    }
 
+    async fn send_raw(&self, message: NetworkMessage) -> Result<(), ProtocolError> {
+        let measured = traffic::measure(self.codec, &message);
         self.sender.send(message).await
             .map_err(|e| ProtocolError::network_error(format!("Failed to send message: {}", e)))?;
-
+        self.traffic.lock().unwrap().sent.record(measured);
         Ok(())
     }
 
-    /// Receive a message from the channel
-    pub async fn receive(&mut self) -> Result<Option<NetworkMessage>, ProtocolError> {
+    /// Count a message received from the target
+    fn count_received(&self, message: &NetworkMessage) {
+        let measured = traffic::measure(self.codec, message);
+        self.traffic.lock().unwrap().received.record(measured);
+    }
+
+    /// Decrypt a message received on an encrypted channel
+    async fn open(&self, message: NetworkMessage) -> Result<NetworkMessage, ProtocolError> {
+        match &self.session {
+            Some(session) => open_message(&mut *session.lock().await, message),
+            None if self.identity.is_some() => {
+                Err(ProtocolError::Security("Encrypted channel has no session".to_string()))
+            }
+            None => Ok(message),
+        }
+    }
+
+    /// Decrypt and verify a received message, and handle it on a reliable or ordered channel
+    ///
+    /// Returns `None` for the messages the caller does not see yet or at all:
+    /// acknowledgements, duplicates of messages already delivered, and
+    /// messages held back on an ordered channel. A replay of an authenticated
+    /// message is dropped on a reliable channel, which cannot tell it from a
+    /// resend, and rejected on any other.
+    async fn accept(&mut self, message: NetworkMessage) -> Result<Option<NetworkMessage>, ProtocolError> {
+        let message = self.open(message).await?;
+        let replayed = match &mut self.authenticator {
+            Some(authenticator) => !authenticator.verify_fresh(&message)?,
+            None => false,
+        };
+        if replayed && self.reliability.is_none() {
+            return Err(ProtocolError::Security(format!(
+                "Replayed message {} from server {}",
+                message.sequence, message.source_id
+            )));
+        }
+        if let Some(version) = self.version.filter(|&version| version != message.version) {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "Message {} from server {} has protocol version {}, not the agreed {}",
+                message.sequence, message.source_id, message.version, version
+            )));
+        }
+        self.update_heartbeat();
+
+        if let Some(reliability) = &mut self.reliability {
+            if matches!(message.message_type, MessageType::Ack) {
+                reliability.unacked.get_mut().unwrap().acknowledge(message.sequence);
+                return Ok(None);
+            }
+
+            // Heartbeats are numbered apart from the messages of the channel, so only
+            // acknowledged messages are deduplicated
+            let fresh = !replayed
+                && (!message.requires_ack() || reliability.delivered.insert(message.source_id, message.sequence));
+            if message.requires_ack() {
+                // Duplicates are acknowledged again in case the first acknowledgement was lost
+                let ack = NetworkMessage::ack(message.target_id, message.source_id, message.sequence);
+                if let Err(e) = self.transmit(ack).await {
+                    log::warn!("Failed to acknowledge message {} from server {}: {}", message.sequence, self.target_id, e);
+                }
+            }
+            if !fresh {
+                log::debug!("Dropping duplicate message {} from server {}", message.sequence, message.source_id);
+                return Ok(None);
+            }
+        }
+        self.deliver_in_order(message)
+    }
+
+    /// Deliver a message now if it is next on an ordered channel, or hold it back until it is
+    ///
+    /// Messages of an ordered channel delivered before are dropped, and more
+    /// than `DEDUP_WINDOW` held back messages fail the channel.
+    fn deliver_in_order(&mut self, message: NetworkMessage) -> Result<Option<NetworkMessage>, ProtocolError> {
+        if !self.ordered || !message.requires_ack() {
+            return Ok(Some(message));
+        }
+
+        let order = &mut self.order;
+        match message.sequence.cmp(&order.next_delivery) {
+            std::cmp::Ordering::Less => {
+                log::debug!("Dropping message {} from server {} delivered before", message.sequence, message.source_id);
+                Ok(None)
+            }
+            std::cmp::Ordering::Greater if order.held.len() >= DEDUP_WINDOW => Err(ProtocolError::MalformedMessage(format!(
+                "Server {} is {} messages ahead of message {}",
+                message.source_id, order.held.len(), order.next_delivery
+            ))),
+            std::cmp::Ordering::Greater => {
+                log::debug!(
+                    "Holding back message {} from server {} until message {}",
+                    message.sequence, message.source_id, order.next_delivery
+                );
+                order.held.insert(message.sequence, message);
+                Ok(None)
+            }
+            std::cmp::Ordering::Equal => {
+                order.next_delivery += 1;
+                order.release();
+                Ok(Some(message))
+            }
+        }
+    }
+
+    /// Held back message whose turn came, if there is one
+    fn take_released(&mut self) -> Option<NetworkMessage> {
+        self.order.released.pop_front()
+    }
+
+    /// Receive the next message for the caller, waiting up to `timeout_duration` if given
+    ///
+    /// A reliable channel resends overdue messages while it waits.
+    async fn receive_next(&mut self, timeout_duration: Option<Duration>) -> Result<NetworkMessage, ProtocolError> {
         if !self.connected {
             return Err(ProtocolError::network_error("Channel not connected".to_string()));
         }
+        if let Some(message) = self.take_released() {
+            return Ok(message);
+        }
+
+        let deadline = timeout_duration.map(|duration| self.runtime.now() + duration);
+        loop {
+            let now = self.runtime.now();
+            let retry_at = self
+                .reliability
+                .as_ref()
+                .and_then(|reliability| reliability.unacked.lock().unwrap().next_retry());
+            let until_deadline = match (deadline, retry_at) {
+                (Some(deadline), Some(retry_at)) => deadline <= retry_at,
+                (deadline, _) => deadline.is_some(),
+            };
+            let received = match deadline.into_iter().chain(retry_at).min() {
+                Some(wake_at) => self.receive_raw(wake_at.saturating_sub(now)).await,
+                None => self.receiver.recv().await
+                    .inspect(|message| self.count_received(message))
+                    .ok_or_else(|| ProtocolError::network_error("Channel closed".to_string())),
+            };
+
+            match received {
+                Ok(message) => {
+                    if let Some(message) = self.accept(message).await? {
+                        return Ok(message);
+                    }
+                }
+                Err(ProtocolError::Timeout { .. }) => {
+                    self.resend_due().await?;
+                    if until_deadline {
+                        let duration = timeout_duration.unwrap_or_default();
+                        return Err(ProtocolError::timeout(duration.as_millis() as u64));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
 
-        self.receiver.recv().await
-            .ok_or_else(|| ProtocolError::network_error("Channel closed".to_string()))
+    /// Receive a message from the channel
+    pub async fn receive(&mut self) -> Result<Option<NetworkMessage>, ProtocolError> {
+        self.receive_next(None).await.map(Some)
     }
 
     /// Receive a message with timeout
     pub async fn receive_timeout(&mut self, timeout_duration: Duration) -> Result<Option<NetworkMessage>, ProtocolError> {
-        if !self.connected {
-            return Err(ProtocolError::network_error("Channel not connected".to_string()));
-        }
+        self.receive_next(Some(timeout_duration)).await.map(Some)
+    }
 
-        match timeout(timeout_duration, self.receiver.recv()).await {
-            Ok(Some(message)) => Ok(Some(message)),
+    async fn receive_raw(&mut self, timeout_duration: Duration) -> Result<NetworkMessage, ProtocolError> {
+        match runtime::timeout(&*self.runtime, timeout_duration, self.receiver.recv()).await {
+            Ok(Some(message)) => {
+                self.count_received(&message);
+                Ok(message)
+            }
             Ok(None) => Err(ProtocolError::network_error("Channel closed".to_string())),
             Err(_) => Err(ProtocolError::timeout(timeout_duration.as_millis() as u64)),
         }
     }
 
     /// Establish connection
+    ///
+    /// An encrypted channel waits up to `DEFAULT_HANDSHAKE_TIMEOUT` for each
+    /// handshake message from the target.
     pub async fn connect(&mut self) -> Result<(), ProtocolError> {
+        self.connect_timeout(DEFAULT_HANDSHAKE_TIMEOUT).await
+    }
+
+    /// Establish connection, waiting up to `timeout_duration` for each
+    /// handshake message from the target
+    pub async fn connect_timeout(&mut self, timeout_duration: Duration) -> Result<(), ProtocolError> {
+        self.session = None;
+        self.version = None;
+        if self.versions.is_some() {
+            self.version = Some(self.negotiate_version(timeout_duration).await?);
+        }
+        if self.identity.is_some() {
+            let session = self.handshake(timeout_duration).await?;
+            self.session = Some(Arc::new(futures::lock::Mutex::new(session)));
+        }
+
         self.connected = true;
-        self.last_heartbeat = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        self.update_heartbeat();
         Ok(())
     }
 
+    /// Offer the protocol versions of the channel and agree on the highest one the target offers too
+    ///
+    /// Both servers send their offer before reading the other, so neither waits
+    /// for the other.
+    async fn negotiate_version(&mut self, timeout_duration: Duration) -> Result<u16, ProtocolError> {
+        let (local_id, versions) = match &self.versions {
+            Some(offer) => (offer.local_id, offer.versions),
+            None => return Err(ProtocolError::InvalidConfiguration("Channel offers no versions".to_string())),
+        };
+
+        let sequence = self.next_sequence();
+        self.send_raw(NetworkMessage::versions(local_id, self.target_id, sequence, versions))
+            .await?;
+        let message = self.receive_raw(timeout_duration).await?;
+        match message.payload {
+            MessagePayload::Versions(peer) if message.source_id == self.target_id => {
+                let version = versions.negotiate(self.target_id, peer)?;
+                log::debug!("Speaking protocol version {} with server {}", version, self.target_id);
+                Ok(version)
+            }
+            payload => Err(ProtocolError::MalformedMessage(format!(
+                "Expected the protocol versions of server {}, got a {} payload from server {}",
+                self.target_id,
+                payload.kind(),
+                message.source_id
+            ))),
+        }
+    }
+
+    /// Run the Noise handshake with the target
+    async fn handshake(&mut self, timeout_duration: Duration) -> Result<SecureSession, ProtocolError> {
+        let (local_id, mut handshake) = match &self.identity {
+            Some(identity) => (
+                identity.local_id,
+                Handshake::new(identity.local_id, self.target_id, &identity.keys, &identity.peer_public_key)?,
+            ),
+            None => return Err(ProtocolError::Security("Channel has no identity".to_string())),
+        };
+
+        while !handshake.is_finished() {
+            if handshake.is_my_turn() {
+                let payload = MessagePayload::Handshake(handshake.write_message()?);
+                let sequence = self.next_sequence();
+                self.send_raw(NetworkMessage::new(MessageType::Init, local_id, self.target_id, sequence, payload))
+                    .await?;
+            } else {
+                let message = self.receive_raw(timeout_duration).await?;
+                match message.payload {
+                    MessagePayload::Handshake(bytes) if message.source_id == self.target_id => {
+                        handshake.read_message(&bytes)?
+                    }
+                    payload => {
+                        return Err(ProtocolError::Security(format!(
+                            "Expected a handshake from server {}, got a {} payload from server {}",
+                            self.target_id,
+                            payload.kind(),
+                            message.source_id
+                        )))
+                    }
+                }
+            }
+        }
+        handshake.into_session()
+    }
+
     /// Disconnect
+    ///
+    /// An encrypted channel runs a new handshake on the next `connect`.
     pub fn disconnect(&mut self) {
         self.connected = false;
+        self.session = None;
+        self.version = None;
+    }
+
+    /// Replace the link to the target, which then needs a new `connect`
+    ///
+    /// Unacknowledged and delivered messages are kept, so `replay_unacked` can
+    /// resume the exchange where the old link left it.
+    pub fn replace_link(&mut self, sender: Sender<NetworkMessage>, receiver: Receiver<NetworkMessage>) {
+        self.sender = sender;
+        self.receiver = receiver;
+        self.disconnect();
+    }
+
+    /// Send every unacknowledged message again now, returning how many were sent
+    pub async fn replay_unacked(&self) -> usize {
+        let Some(reliability) = &self.reliability else {
+            return 0;
+        };
+
+        let messages = reliability.unacked.lock().unwrap().restart(self.runtime.now(), &reliability.policy);
+        let replayed = messages.len();
+        for message in messages {
+            let sequence = message.sequence;
+            if let Err(e) = self.transmit(message).await {
+                log::debug!("Failed to replay message {} to server {}: {}", sequence, self.target_id, e);
+            }
+        }
+        replayed
+    }
+
+    /// State to save in an `Outbox`
+    pub fn state(&self) -> ChannelState {
+        let (unacked, delivered) = match &self.reliability {
+            Some(reliability) => (
+                reliability.unacked.lock().unwrap().messages(),
+                reliability.delivered.iter().collect(),
+            ),
+            None => Default::default(),
+        };
+        let held = self.order.held.values().chain(&self.order.released).cloned().collect();
+        ChannelState {
+            sequence_counter: self.sequence_counter,
+            unacked,
+            delivered,
+            next_ordered: self.order.next_send.load(Ordering::SeqCst),
+            next_delivery: self.order.next_delivery - self.order.released.len() as u64,
+            held,
+        }
+    }
+
+    /// Continue from a state saved in an `Outbox`
+    ///
+    /// The unacknowledged messages are sent on the next `replay_unacked`, or
+    /// once their acknowledgement is overdue.
+    pub fn restore(&mut self, state: ChannelState) {
+        self.sequence_counter = self.sequence_counter.max(state.sequence_counter);
+        let order = &mut self.order;
+        let next_send = order.next_send.get_mut();
+        *next_send = (*next_send).max(state.next_ordered);
+        order.next_delivery = order.next_delivery.max(state.next_delivery);
+        for message in state.held {
+            if message.sequence >= order.next_delivery {
+                order.held.insert(message.sequence, message);
+            }
+        }
+        order.release();
+
+        let Some(reliability) = &mut self.reliability else {
+            if !state.unacked.is_empty() {
+                log::warn!(
+                    "Dropping {} unacknowledged messages to server {} on a channel without retries",
+                    state.unacked.len(),
+                    self.target_id
+                );
+            }
+            return;
+        };
+
+        let retry_at = self.runtime.now();
+        let unacked = reliability.unacked.get_mut().unwrap();
+        for message in state.unacked {
+            unacked.track(message, retry_at);
+        }
+        for (source_id, sequence) in state.delivered {
+            reliability.delivered.insert(source_id, sequence);
+        }
     }
 
     /// Send heartbeat
@@ -235,18 +1158,17 @@ impl CommunicationChannel {
         self.send(message).await
     }
 
-    /// Check if channel is healthy
+    /// Check if channel is healthy, i.e. connected and heard from within
+    /// `max_heartbeat_age` seconds on the runtime clock
     pub fn is_healthy(&self, max_heartbeat_age: u64) -> bool {
         if !self.connected {
             return false;
         }
 
-        let current_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
-        current_time - self.last_heartbeat <= max_heartbeat_age
+        let now = self.runtime.now();
+        self.heartbeat()
+            .last_seen
+            .is_some_and(|seen| now.saturating_sub(seen) <= Duration::from_secs(max_heartbeat_age))
     }
 
     /// Update heartbeat time
@@ -255,6 +1177,7 @@ impl CommunicationChannel {
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
+        self.heartbeat.lock().unwrap().seen(self.runtime.now());
     }
 
     /// Get next sequence number
@@ -265,6 +1188,56 @@ impl CommunicationChannel {
     }
 }
 
+/// Encrypt a message into an `Encrypted` message with the same header
+///
+/// The text of an error message type is only sent encrypted.
+pub(crate) fn seal_message(session: &mut SecureSession, message: &NetworkMessage) -> Result<NetworkMessage, ProtocolError> {
+    let ciphertext = session.encrypt(&message.to_bytes()?)?;
+    let message_type = match &message.message_type {
+        MessageType::Error(_) => MessageType::Error(String::new()),
+        message_type => message_type.clone(),
+    };
+
+    Ok(NetworkMessage {
+        message_type,
+        source_id: message.source_id,
+        target_id: message.target_id,
+        sequence: message.sequence,
+        payload: MessagePayload::Encrypted(ciphertext),
+        timestamp: message.timestamp,
+        mac: None,
+        version: message.version,
+        phase: message.phase,
+    })
+}
+
+/// Decrypt an `Encrypted` message, checking that its header was not altered
+fn open_message(session: &mut SecureSession, message: NetworkMessage) -> Result<NetworkMessage, ProtocolError> {
+    let ciphertext = match message.payload {
+        MessagePayload::Encrypted(ciphertext) => ciphertext,
+        payload => {
+            return Err(ProtocolError::Security(format!(
+                "Unencrypted {} payload on an encrypted channel",
+                payload.kind()
+            )))
+        }
+    };
+
+    let inner = NetworkMessage::from_bytes(&session.decrypt(&ciphertext)?)?;
+    let consistent = std::mem::discriminant(&inner.message_type) == std::mem::discriminant(&message.message_type)
+        && inner.source_id == message.source_id
+        && inner.target_id == message.target_id
+        && inner.sequence == message.sequence
+        && inner.version == message.version
+        && inner.phase == message.phase;
+    if !consistent {
+        return Err(ProtocolError::Security(
+            "Header of an encrypted message does not match its contents".to_string(),
+        ));
+    }
+    Ok(inner)
+}
+
 /// Network manager for coordinating communication between servers
 pub struct NetworkManager {
     /// Server ID
@@ -275,21 +1248,86 @@ pub struct NetworkManager {
     pub handlers: HashMap<MessageType, Box<dyn MessageHandler + Send + Sync>>,
     /// Network configuration
     pub config: NetworkConfig,
+    /// Runtime running the heartbeat task
+    pub runtime: SharedRuntime,
+    /// Authenticator of sent and received messages, set up by `start`
+    pub authenticator: Option<MessageAuthenticator>,
+    /// Opens new links to reconnect lost channels
+    pub connector: Option<Arc<dyn Connector>>,
+    /// Phase of the protocol this server is in
+    pub phase: ProtocolPhase,
+    /// File the session is saved to, to resume after a restart
+    pub outbox: Option<Outbox>,
+    /// Connected channels the heartbeat task sends on
+    heartbeat_links: Arc<Mutex<HashMap<usize, HeartbeatSender>>>,
+    /// Called by the heartbeat task when a server stops or resumes answering
+    liveness_callbacks: Vec<LivenessCallback>,
+    /// Called by the heartbeat task when the failure detector changes its mind about a server
+    suspicion_callbacks: Vec<SuspicionCallback>,
+    /// Frames sent and received over TCP, set up by `bootstrap`
+    frame_counters: Option<Arc<FrameCounters>>,
+    /// Reliable broadcasts by sender and instance, kept after delivery for late steps
+    broadcasts: HashMap<(usize, u64), ReliableBroadcast>,
+    /// Messages received while waiting for a broadcast, for `process_messages`
+    deferred: VecDeque<(usize, NetworkMessage)>,
+    /// Messages of phases this server has not entered yet, by phase
+    early: BTreeMap<ProtocolPhase, Vec<(usize, NetworkMessage)>>,
+    /// Aborts the receive loops when cancelled
+    cancellation: CancellationToken,
+    /// Records every message sent and received
+    transcript: Option<Arc<TranscriptRecorder>>,
 }
 
 /// Network configuration
-#[derive(Debug, Clone)]
+///
+/// Fields missing from a configuration file take their default values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct NetworkConfig {
     /// Heartbeat interval in seconds
     pub heartbeat_interval: u64,
-    /// Maximum heartbeat age in seconds
+    /// Maximum heartbeat age in seconds, after which a silent server is suspected of having failed
     pub max_heartbeat_age: u64,
+    /// Silence in seconds after which a server is declared failed
+    pub failure_timeout: u64,
     /// Message timeout in milliseconds
     pub message_timeout_ms: u64,
-    /// Maximum retries for failed messages
+    /// Maximum retries for failed messages, or 0 to neither acknowledge nor resend messages
     pub max_retries: usize,
-    /// Whether to enable message encryption
+    /// Wait for the acknowledgement of a message before the first resend, in milliseconds
+    pub retry_backoff_ms: u64,
+    /// Longest wait between two resends in milliseconds
+    pub max_retry_backoff_ms: u64,
+    /// Whether to encrypt the channels, which requires `identity`
     pub enable_encryption: bool,
+    /// Whether to authenticate every message, which requires `identity`
+    pub authenticate_messages: bool,
+    /// Keys of this server and its peers for encrypted channels
+    pub identity: Option<IdentityConfig>,
+    /// Encoding of messages on the wire
+    pub codec: CodecKind,
+    /// Largest encoded message sent or accepted, in bytes
+    pub max_message_bytes: usize,
+    /// Compression of large messages, used on a link if both servers select it
+    pub compression: Compression,
+    /// Attempts to reconnect a lost channel before giving up, or 0 to not reconnect
+    pub max_reconnect_attempts: usize,
+    /// Wait before the second attempt to reconnect, doubling with every attempt, in milliseconds
+    pub reconnect_backoff_ms: u64,
+    /// File to save the session to, to resume it after a restart
+    pub outbox_path: Option<PathBuf>,
+    /// Messages queued on each link before sends block
+    pub queue_capacity: usize,
+    /// Messages queued to a server that pause its channel
+    pub high_watermark: usize,
+    /// Messages queued to a server that resume its paused channel
+    pub low_watermark: usize,
+    /// Oldest protocol version this server agrees to speak
+    pub min_protocol_version: u16,
+    /// Newest protocol version this server agrees to speak
+    pub max_protocol_version: u16,
+    /// Whether each server receives the messages of another in the order they were sent
+    pub ordered_delivery: bool,
 }
 
 impl Default for NetworkConfig {
@@ -297,34 +1335,548 @@ impl Default for NetworkConfig {
         Self {
             heartbeat_interval: 30,
             max_heartbeat_age: 90,
+            failure_timeout: 300,
             message_timeout_ms: 5000,
             max_retries: 3,
+            retry_backoff_ms: 200,
+            max_retry_backoff_ms: 5000,
             enable_encryption: true,
+            authenticate_messages: true,
+            identity: None,
+            codec: CodecKind::default(),
+            max_message_bytes: MAX_MESSAGE_BYTES,
+            compression: Compression::None,
+            max_reconnect_attempts: 5,
+            reconnect_backoff_ms: 500,
+            outbox_path: None,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            high_watermark: FlowControl::default().high_watermark,
+            low_watermark: FlowControl::default().low_watermark,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            max_protocol_version: PROTOCOL_VERSION,
+            ordered_delivery: true,
         }
     }
 }
 
-/// Message handler trait
-pub trait MessageHandler {
-    /// Handle incoming message
-    fn handle(&self, message: &NetworkMessage) -> Result<(), ProtocolError>;
-}
-
-impl NetworkManager {
-    /// Create a new network manager
-    pub fn new(server_id: usize, config: NetworkConfig) -> Self {
-        Self {
-            server_id,
-            channels: HashMap::new(),
-            handlers: HashMap::new(),
-            config,
-        }
+impl NetworkConfig {
+    /// Load a configuration from a TOML or YAML file
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        config::load(path.as_ref(), Self::validate)
     }
 
-    /// Add communication channel
-    pub fn add_channel(&mut self, target_id: usize, channel: CommunicationChannel) {
-        self.channels.insert(target_id, channel);
-    }
+    /// Validate the intervals and timeouts
+    pub fn validate(&self) -> Result<(), String> {
+        if self.heartbeat_interval == 0 {
+            return Err("heartbeat_interval must be at least 1 second".to_string());
+        }
+        if self.max_heartbeat_age < self.heartbeat_interval {
+            return Err(format!(
+                "max_heartbeat_age {} is shorter than heartbeat_interval {}",
+                self.max_heartbeat_age, self.heartbeat_interval
+            ));
+        }
+        if self.failure_timeout < self.max_heartbeat_age {
+            return Err(format!(
+                "failure_timeout {} is shorter than max_heartbeat_age {}",
+                self.failure_timeout, self.max_heartbeat_age
+            ));
+        }
+        if self.message_timeout_ms == 0 {
+            return Err("message_timeout_ms must be positive".to_string());
+        }
+        if self.max_retries > 0 && self.retry_backoff_ms == 0 {
+            return Err("retry_backoff_ms must be positive".to_string());
+        }
+        if self.max_retry_backoff_ms < self.retry_backoff_ms {
+            return Err(format!(
+                "max_retry_backoff_ms {} is shorter than retry_backoff_ms {}",
+                self.max_retry_backoff_ms, self.retry_backoff_ms
+            ));
+        }
+        if self.max_reconnect_attempts > 1 && self.reconnect_backoff_ms == 0 {
+            return Err("reconnect_backoff_ms must be positive".to_string());
+        }
+        self.flow_control().validate(self.queue_capacity)?;
+        self.protocol_versions().validate()?;
+        if let Some(identity) = &self.identity {
+            identity.validate().map_err(|e| format!("identity.{}", e))?;
+        }
+        if self.max_message_bytes == 0 || self.max_message_bytes >= u32::MAX as usize {
+            return Err(format!(
+                "max_message_bytes must be between 1 and {}",
+                u32::MAX - 1
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Framing of messages with the configured codec, size limit and compression
+    pub fn framing(&self) -> Framing {
+        Framing::new(self.codec, self.max_message_bytes).with_compression(self.compression)
+    }
+
+    /// Protocol versions this server offers its peers
+    pub fn protocol_versions(&self) -> VersionRange {
+        VersionRange {
+            min: self.min_protocol_version,
+            max: self.max_protocol_version,
+        }
+    }
+
+    /// Failure detector suspecting servers after `max_heartbeat_age` and declaring them failed after `failure_timeout`
+    pub fn failure_detector(&self) -> FailureDetector {
+        FailureDetector {
+            suspect_after: Duration::from_secs(self.max_heartbeat_age),
+            fail_after: Duration::from_secs(self.failure_timeout),
+        }
+    }
+
+    /// Resends of unacknowledged messages, unless `max_retries` is 0
+    pub fn retry_policy(&self) -> Option<RetryPolicy> {
+        (self.max_retries > 0).then(|| RetryPolicy {
+            max_retries: self.max_retries,
+            initial_backoff: Duration::from_millis(self.retry_backoff_ms),
+            max_backoff: Duration::from_millis(self.max_retry_backoff_ms),
+        })
+    }
+
+    /// Watermarks of the channels, which wait up to `message_timeout_ms` for a paused channel
+    pub fn flow_control(&self) -> FlowControl {
+        FlowControl {
+            high_watermark: self.high_watermark,
+            low_watermark: self.low_watermark,
+            max_wait: Duration::from_millis(self.message_timeout_ms),
+        }
+    }
+
+    /// Waits between attempts to reconnect a lost channel
+    pub fn reconnect_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_reconnect_attempts.saturating_sub(1),
+            initial_backoff: Duration::from_millis(self.reconnect_backoff_ms),
+            max_backoff: Duration::from_millis(self.max_retry_backoff_ms.max(self.reconnect_backoff_ms)),
+        }
+    }
+}
+
+/// Message handler trait
+pub trait MessageHandler {
+    /// Handle incoming message
+    fn handle(&self, message: &NetworkMessage) -> Result<(), ProtocolError>;
+}
+
+/// The two halves of a link to another server
+pub type Link = (Sender<NetworkMessage>, Receiver<NetworkMessage>);
+
+/// Opens links to other servers, to reconnect lost channels
+pub trait Connector: Send + Sync {
+    /// Open a new link to `target_id`
+    fn connect(&self, target_id: usize) -> BoxFuture<'_, Result<Link, ProtocolError>>;
+}
+
+impl NetworkManager {
+    /// Create a new network manager
+    pub fn new(server_id: usize, config: NetworkConfig) -> Self {
+        Self {
+            server_id,
+            channels: HashMap::new(),
+            handlers: HashMap::new(),
+            runtime: runtime::default_runtime(),
+            authenticator: None,
+            connector: None,
+            phase: ProtocolPhase::Setup,
+            outbox: config.outbox_path.clone().map(Outbox::new),
+            heartbeat_links: Default::default(),
+            liveness_callbacks: Vec::new(),
+            suspicion_callbacks: Vec::new(),
+            frame_counters: None,
+            broadcasts: HashMap::new(),
+            deferred: VecDeque::new(),
+            early: BTreeMap::new(),
+            cancellation: CancellationToken::new(),
+            transcript: None,
+            config,
+        }
+    }
+
+    /// Use another runtime for the heartbeat task
+    pub fn with_runtime(mut self, runtime: SharedRuntime) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    /// Reconnect lost channels with links opened by `connector`
+    pub fn with_connector(mut self, connector: Arc<dyn Connector>) -> Self {
+        self.connector = Some(connector);
+        self
+    }
+
+    /// Record every message sent and received with `recorder`
+    pub fn with_transcript(mut self, recorder: TranscriptRecorder) -> Self {
+        self.set_transcript(recorder);
+        self
+    }
+
+    /// Record every message sent and received with `recorder`
+    ///
+    /// Messages are recorded as they are handed to a channel, before
+    /// encryption, and as they are delivered by one, after duplicates and
+    /// messages that fail authentication were dropped. Recording fails the
+    /// send or receive if the transcript cannot be written.
+    pub fn set_transcript(&mut self, recorder: TranscriptRecorder) {
+        self.transcript = Some(Arc::new(recorder));
+    }
+
+    /// Recorder of the messages sent and received, if any
+    pub fn transcript(&self) -> Option<&TranscriptRecorder> {
+        self.transcript.as_deref()
+    }
+
+    /// Add a message sent to or received from `peer` to the transcript
+    fn record(&self, direction: Direction, peer: usize, message: &NetworkMessage) -> Result<(), ProtocolError> {
+        match &self.transcript {
+            Some(recorder) => recorder.record(self.runtime.now(), direction, peer, self.phase, message),
+            None => Ok(()),
+        }
+    }
+
+    /// Feed the messages `transcript` recorded as received to this manager, returning how many there were
+    ///
+    /// Meant for debugging and audit: the manager needs no channels, and
+    /// follows the phases the recording server went through, so phase gating,
+    /// broadcasts and the handlers see the messages as that server did.
+    /// Messages the recording server sent are not sent again. Fails if the
+    /// transcript was recorded by another server.
+    pub async fn replay(&mut self, transcript: &Transcript) -> Result<usize, ProtocolError> {
+        if transcript.header.server_id != self.server_id {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Transcript belongs to server {}, not {}",
+                transcript.header.server_id, self.server_id
+            )));
+        }
+
+        let mut replayed = 0;
+        for entry in &transcript.entries {
+            if entry.phase != self.phase {
+                self.set_phase(entry.phase)?;
+            }
+            while let Some((target_id, message)) = self.deferred.pop_front() {
+                self.handle_message(target_id, message).await;
+            }
+            if entry.direction == Direction::Received {
+                self.handle_message(entry.peer, entry.message.clone()).await;
+                replayed += 1;
+            }
+        }
+        while let Some((target_id, message)) = self.deferred.pop_front() {
+            self.handle_message(target_id, message).await;
+        }
+
+        log::info!("Replayed {} messages from server {}'s transcript", replayed, self.server_id);
+        Ok(replayed)
+    }
+
+    /// Abort the receive loops when `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.set_cancellation(token);
+        self
+    }
+
+    /// Abort the receive loops when `token` is cancelled
+    ///
+    /// `process_messages`, `deliver_broadcast` and `reconnect` then fail with
+    /// `Cancelled` at their next await point. The manager stops the heartbeats,
+    /// closes its channels and drops the messages it buffered, and the session
+    /// in the outbox is left as it was, so a later run can resume it.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+
+    /// Token that aborts the receive loops when cancelled
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Release the channels and buffers of a cancelled run
+    fn cancelled(&mut self) -> ProtocolError {
+        log::info!("Server {} releasing its channels after cancellation in phase {:?}", self.server_id, self.phase);
+        self.heartbeat_links.lock().unwrap().clear();
+        for channel in self.channels.values_mut() {
+            channel.receiver.close();
+            channel.disconnect();
+        }
+        self.broadcasts.clear();
+        self.deferred.clear();
+        self.early.clear();
+        ProtocolError::Cancelled
+    }
+
+    /// Enter a phase of the protocol, saving the session to the outbox
+    ///
+    /// Messages of the phase that arrived early are handled by the next
+    /// `process_messages`, and those of phases skipped over are dropped. The
+    /// outbox is deleted once the protocol is completed.
+    pub fn set_phase(&mut self, phase: ProtocolPhase) -> Result<(), ProtocolError> {
+        self.phase = phase;
+        let later = self.early.split_off(&phase);
+        for (skipped, messages) in std::mem::replace(&mut self.early, later) {
+            log::warn!("Dropping {} messages of phase {:?}, which this server skipped", messages.len(), skipped);
+        }
+        if let Some(messages) = self.early.remove(&phase) {
+            self.deferred.extend(messages);
+        }
+        match (&self.outbox, phase) {
+            (Some(outbox), ProtocolPhase::Completed) => outbox.clear(),
+            _ => self.checkpoint(),
+        }
+    }
+
+    /// Save the phase and the unacknowledged messages to the outbox
+    ///
+    /// Messages sent after the last checkpoint are not replayed after a restart.
+    /// `set_phase` and `maintain` save the session too.
+    pub fn checkpoint(&self) -> Result<(), ProtocolError> {
+        let Some(outbox) = &self.outbox else {
+            return Ok(());
+        };
+
+        let state = SessionState {
+            server_id: self.server_id,
+            phase: self.phase,
+            channels: self
+                .channels
+                .iter()
+                .map(|(target_id, channel)| (*target_id, channel.state()))
+                .collect::<BTreeMap<_, _>>(),
+        };
+        outbox.save(&state)
+    }
+
+    /// Continue the session saved in the outbox, returning its phase
+    ///
+    /// Channels must be added before, and the unacknowledged messages are
+    /// replayed once they connect. Returns `None` if no session was saved.
+    pub fn resume(&mut self) -> Result<Option<ProtocolPhase>, ProtocolError> {
+        let Some(state) = self.outbox.as_ref().map(Outbox::load).transpose()?.flatten() else {
+            return Ok(None);
+        };
+        if state.server_id != self.server_id {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Outbox belongs to server {}, not {}",
+                state.server_id, self.server_id
+            )));
+        }
+
+        for (target_id, channel_state) in state.channels {
+            let channel = self
+                .channels
+                .get_mut(&target_id)
+                .ok_or_else(|| ProtocolError::network_error(format!("No channel to server {}", target_id)))?;
+            channel.restore(channel_state);
+        }
+        self.phase = state.phase;
+        log::info!("Resuming session in phase {:?}", self.phase);
+        Ok(Some(self.phase))
+    }
+
+    /// Open a channel to every other server of `topology` over TCP and connect them
+    ///
+    /// The public keys of the topology are added to the identity, and the
+    /// transport becomes the connector that reopens lost channels. Servers
+    /// that are not listening yet are dialed again with the backoff of
+    /// `reconnect_backoff_ms`.
+    pub async fn bootstrap(&mut self, topology: Topology) -> Result<(), ProtocolError> {
+        topology.validate().map_err(ProtocolError::InvalidConfiguration)?;
+        if let Some(identity) = &mut self.config.identity {
+            topology.add_keys(identity).map_err(ProtocolError::InvalidConfiguration)?;
+        }
+
+        let peers = topology.peers(self.server_id).iter().map(|peer| peer.id).collect::<Vec<_>>();
+        let accept_timeout = Duration::from_millis(self.config.message_timeout_ms);
+        let transport = TcpTransport::bind(
+            self.server_id,
+            topology,
+            self.config.framing(),
+            self.config.queue_capacity,
+            accept_timeout,
+        )
+        .await?;
+        self.frame_counters = Some(transport.counters());
+        for target_id in peers {
+            let (sender, receiver) = self.open_link(&transport, target_id).await?;
+            let channel = CommunicationChannel::new(target_id, sender, receiver).with_runtime(self.runtime.clone());
+            self.add_channel(target_id, channel);
+        }
+
+        self.connector = Some(Arc::new(transport));
+        self.connect_all().await
+    }
+
+    /// Open a link with `connector`, trying again with the backoff of `reconnect_policy`
+    async fn open_link(&self, connector: &dyn Connector, target_id: usize) -> Result<Link, ProtocolError> {
+        let policy = self.config.reconnect_policy();
+        let mut attempt = 0;
+        loop {
+            match connector.connect(target_id).await {
+                Ok(link) => return Ok(link),
+                Err(e) if attempt < policy.max_retries => {
+                    log::debug!("Failed to open a link to server {} (attempt {}): {}", target_id, attempt + 1, e);
+                    self.runtime.sleep(policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Check if lost channels are reconnected
+    pub fn can_reconnect(&self) -> bool {
+        self.connector.is_some() && self.config.max_reconnect_attempts > 0
+    }
+
+    /// Open a new link to `target_id` and replay the messages it did not acknowledge
+    ///
+    /// Links are opened with the connector up to `max_reconnect_attempts` times,
+    /// with exponential backoff between attempts. Returns the number of
+    /// messages replayed.
+    pub async fn reconnect(&mut self, target_id: usize) -> Result<usize, ProtocolError> {
+        let connector = self.connector.clone().ok_or_else(|| {
+            ProtocolError::network_error(format!("No connector to reconnect to server {}", target_id))
+        })?;
+        let policy = self.config.reconnect_policy();
+        let handshake_timeout = Duration::from_millis(self.config.message_timeout_ms);
+
+        let cancellation = self.cancellation.clone();
+        let mut attempt = 0;
+        loop {
+            if cancellation.is_cancelled() {
+                return Err(self.cancelled());
+            }
+            let channel = self
+                .channels
+                .get_mut(&target_id)
+                .ok_or_else(|| ProtocolError::network_error(format!("No channel to server {}", target_id)))?;
+            self.heartbeat_links.lock().unwrap().remove(&target_id);
+            let result = match cancellation.run(connector.connect(target_id)).await {
+                Ok((sender, receiver)) => {
+                    channel.replace_link(sender, receiver);
+                    cancellation.run(channel.connect_timeout(handshake_timeout)).await
+                }
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(()) => break,
+                Err(ProtocolError::Cancelled) => return Err(self.cancelled()),
+                Err(e) if attempt < policy.max_retries => {
+                    log::warn!("Failed to reconnect to server {} (attempt {}): {}", target_id, attempt + 1, e);
+                    self.runtime.sleep(policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.publish_heartbeat(target_id);
+        let replayed = self.channels[&target_id].replay_unacked().await;
+        log::info!(
+            "Reconnected to server {} in phase {:?}, replayed {} messages",
+            target_id, self.phase, replayed
+        );
+        Ok(replayed)
+    }
+
+    /// Let the heartbeat task send on the current link and session of a channel
+    fn publish_heartbeat(&self, target_id: usize) {
+        if let Some(channel) = self.channels.get(&target_id) {
+            let sender = channel.heartbeat_sender(self.server_id);
+            self.heartbeat_links.lock().unwrap().insert(target_id, sender);
+        }
+    }
+
+    /// Call `callback` with the ID of a server and whether it is alive whenever that changes
+    ///
+    /// A server is alive while it was heard from within `max_heartbeat_age`.
+    /// Callbacks registered after `start` are not called.
+    pub fn on_liveness(&mut self, callback: impl Fn(usize, bool) + Send + Sync + 'static) {
+        self.liveness_callbacks.push(Arc::new(callback));
+    }
+
+    /// Call `callback` with the ID of a server and its suspicion whenever the failure detector changes it
+    ///
+    /// A server is suspected once silent for `max_heartbeat_age`, declared
+    /// failed once silent for `failure_timeout`, and trusted again once heard
+    /// from. Callbacks registered after `start` are not called.
+    pub fn on_suspicion(&mut self, callback: impl Fn(usize, Suspicion) + Send + Sync + 'static) {
+        self.suspicion_callbacks.push(Arc::new(callback));
+    }
+
+    /// Servers the failure detector declared failed, in order of their IDs
+    pub fn failed_servers(&self) -> Vec<usize> {
+        let mut failed = self
+            .channels
+            .iter()
+            .filter(|(_, channel)| channel.heartbeat().suspicion == Suspicion::Failed)
+            .map(|(target_id, _)| *target_id)
+            .collect::<Vec<_>>();
+        failed.sort_unstable();
+        failed
+    }
+
+    /// Heartbeat state of every channel, by server ID
+    pub fn heartbeats(&self) -> BTreeMap<usize, HeartbeatState> {
+        self.channels
+            .iter()
+            .map(|(target_id, channel)| (*target_id, channel.heartbeat()))
+            .collect()
+    }
+
+    /// Queue depths and backpressure of every channel, by server ID
+    pub fn flow_metrics(&self) -> BTreeMap<usize, FlowMetrics> {
+        self.channels
+            .iter()
+            .map(|(target_id, channel)| (*target_id, channel.flow_metrics()))
+            .collect()
+    }
+
+    /// Frames sent and received over TCP before and after compression
+    ///
+    /// All zero unless the channels were set up by `bootstrap`.
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.frame_counters
+            .as_ref()
+            .map(|counters| counters.stats())
+            .unwrap_or_default()
+    }
+
+    /// Messages sent to and received from each server, by type and by phase
+    pub fn traffic(&self) -> BTreeMap<usize, TrafficStats> {
+        self.channels
+            .iter()
+            .map(|(target_id, channel)| (*target_id, channel.traffic()))
+            .collect()
+    }
+
+    /// Messages sent to and received from all servers, by type and by phase
+    pub fn total_traffic(&self) -> TrafficStats {
+        let mut total = TrafficStats::default();
+        for channel in self.channels.values() {
+            total.merge(&channel.traffic());
+        }
+        total
+    }
+
+    /// Heartbeat state of the channel to `target_id`
+    pub fn heartbeat(&self, target_id: usize) -> Option<HeartbeatState> {
+        self.channels.get(&target_id).map(CommunicationChannel::heartbeat)
+    }
+
+    /// Add communication channel
+    pub fn add_channel(&mut self, target_id: usize, channel: CommunicationChannel) {
+        self.channels.insert(target_id, channel);
+    }
 
     /// Register message handler
     pub fn register_handler(&mut self, message_type: MessageType, handler: Box<dyn MessageHandler + Send + Sync>) {
@@ -332,8 +1884,12 @@ impl NetworkManager {
     }
 
     /// Send message to target server
-    pub async fn send_message(&self, target_id: usize, message: NetworkMessage) -> Result<(), ProtocolError> {
+    ///
+    /// A message without a phase is sent as part of the phase this server is in.
+    pub async fn send_message(&self, target_id: usize, mut message: NetworkMessage) -> Result<(), ProtocolError> {
+        message.phase.get_or_insert(self.phase);
         if let Some(channel) = self.channels.get(&target_id) {
+            self.record(Direction::Sent, target_id, &message)?;
             channel.send(message).await
         } else {
             Err(ProtocolError::network_error(format!("No channel to server {}", target_id)))
@@ -341,12 +1897,22 @@ impl NetworkManager {
     }
 
     /// Broadcast message to all servers
-    pub async fn broadcast(&self, message: NetworkMessage) -> Result<(), ProtocolError> {
+    ///
+    /// Each copy is addressed to its target, so it can be authenticated. A
+    /// message without a phase is sent as part of the phase this server is in.
+    pub async fn broadcast(&self, mut message: NetworkMessage) -> Result<(), ProtocolError> {
+        message.phase.get_or_insert(self.phase);
         let mut errors = Vec::new();
 
         for (target_id, channel) in &self.channels {
             if *target_id != self.server_id {
-                if let Err(e) = channel.send(message.clone()).await {
+                let mut message = message.clone();
+                message.target_id = *target_id;
+                let sent = match self.record(Direction::Sent, *target_id, &message) {
+                    Ok(()) => channel.send(message).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = sent {
                     errors.push(format!("Failed to send to server {}: {}", target_id, e));
                 }
             }
@@ -359,63 +1925,368 @@ impl NetworkManager {
         Ok(())
     }
 
-    /// Start network manager
-    pub async fn start(&mut self) -> Result<(), ProtocolError> {
-        // Establish connections with all servers
-        for (target_id, channel) in &mut self.channels {
-            channel.connect().await?;
-            log::info!("Connected to server {}", target_id);
+    /// Reliably broadcast `value` as instance `instance`, returning it once delivered
+    ///
+    /// Unlike `broadcast`, all honest servers that deliver the instance with
+    /// `deliver_broadcast` get the same value even if a server equivocates,
+    /// which matters for e.g. commitments to permutations and public parameters.
+    /// A server caught sending conflicting values fails the broadcast with
+    /// `CheatDetected`, and a broadcast not delivered within
+    /// `message_timeout_ms` times out.
+    pub async fn reliable_broadcast(&mut self, instance: u64, value: Vec<u8>) -> Result<Vec<u8>, ProtocolError> {
+        let server_id = self.server_id;
+        for step in self.broadcast_instance(server_id, instance)?.start(value)? {
+            self.send_broadcast(&step).await?;
+            self.handle_broadcast(server_id, step).await?;
+        }
+        self.deliver_broadcast(server_id, instance).await
+    }
+
+    /// Wait for instance `instance` of the reliable broadcast from `sender` and return its value
+    ///
+    /// Other messages received meanwhile are handled by the next `process_messages`.
+    pub async fn deliver_broadcast(&mut self, sender: usize, instance: u64) -> Result<Vec<u8>, ProtocolError> {
+        // Steps released by `set_phase` from the messages that arrived early
+        let (steps, deferred) = std::mem::take(&mut self.deferred)
+            .into_iter()
+            .partition::<VecDeque<_>, _>(|(_, message)| matches!(message.payload, MessagePayload::Broadcast(_)));
+        self.deferred = deferred;
+        for (from, message) in steps {
+            if let MessagePayload::Broadcast(step) = message.payload {
+                self.handle_broadcast(from, step).await?;
+            }
+        }
+
+        let timeout_duration = Duration::from_millis(self.config.message_timeout_ms);
+        let deadline = self.runtime.now() + timeout_duration;
+        let cancellation = self.cancellation.clone();
+        loop {
+            if let Some(value) = self.broadcast_instance(sender, instance)?.delivered() {
+                return Ok(value.to_vec());
+            }
+
+            let now = self.runtime.now();
+            if now >= deadline {
+                return Err(ProtocolError::timeout(timeout_duration.as_millis() as u64));
+            }
+            let received = match cancellation.run(self.receive_any(deadline - now)).await {
+                Err(ProtocolError::Cancelled) => return Err(self.cancelled()),
+                received => received?,
+            };
+            match received.and_then(|(from, message)| Some((from, self.gate(from, message)?))) {
+                Some((from, NetworkMessage { payload: MessagePayload::Broadcast(step), .. })) => {
+                    self.handle_broadcast(from, step).await?
+                }
+                Some(received) => self.deferred.push_back(received),
+                None => {}
+            }
         }
+    }
 
-        // Start heartbeat loop
-        self.start_heartbeat_loop().await?;
+    /// Instance `instance` of the broadcast from `sender`, created by its first step
+    fn broadcast_instance(&mut self, sender: usize, instance: u64) -> Result<&mut ReliableBroadcast, ProtocolError> {
+        let num_servers = self.channels.keys().filter(|&&target_id| target_id != self.server_id).count() + 1;
+        match self.broadcasts.entry((sender, instance)) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let faults = broadcast::max_faults(num_servers);
+                Ok(entry.insert(ReliableBroadcast::new(self.server_id, num_servers, sender, instance, faults)?))
+            }
+        }
+    }
 
+    /// Handle a broadcast step from `from`, sending the steps it leads to
+    ///
+    /// Steps of this server go to the other servers and to itself.
+    async fn handle_broadcast(&mut self, from: usize, step: BroadcastMessage) -> Result<(), ProtocolError> {
+        let mut pending = vec![(from, step)];
+        while let Some((from, step)) = pending.pop() {
+            let outgoing = self.broadcast_instance(step.sender, step.instance)?.handle(from, step)?;
+            for step in outgoing {
+                self.send_broadcast(&step).await?;
+                pending.push((self.server_id, step));
+            }
+        }
         Ok(())
     }
 
-    /// Start heartbeat loop
-    async fn start_heartbeat_loop(&mut self) -> Result<(), ProtocolError> {
-        let heartbeat_interval = Duration::from_secs(self.config.heartbeat_interval);
+    /// Send a broadcast step to every other server
+    async fn send_broadcast(&mut self, step: &BroadcastMessage) -> Result<(), ProtocolError> {
+        let targets = self.channels.keys().copied().filter(|&target_id| target_id != self.server_id).collect::<Vec<_>>();
+        for target_id in targets {
+            let sequence = self.channels.get_mut(&target_id).expect("target of the manager").next_sequence();
+            let mut message = NetworkMessage::broadcast(self.server_id, target_id, sequence, step.clone());
+            message.phase = Some(self.phase);
+            self.record(Direction::Sent, target_id, &message)?;
+            self.channels[&target_id].send(message).await?;
+        }
+        Ok(())
+    }
 
-        loop {
-            tokio::time::sleep(heartbeat_interval).await;
+    /// Receive the next message from any server within `timeout_duration`, with the ID of the server
+    ///
+    /// Returns `None` on timeout, and for acknowledgements, duplicates,
+    /// messages held back on an ordered channel and messages that fail
+    /// authentication.
+    async fn receive_any(&mut self, timeout_duration: Duration) -> Result<Option<(usize, NetworkMessage)>, ProtocolError> {
+        let released = self
+            .channels
+            .iter_mut()
+            .find_map(|(target_id, channel)| Some((*target_id, channel.take_released()?)));
+        if let Some((target_id, message)) = &released {
+            self.record(Direction::Received, *target_id, message)?;
+            return Ok(released);
+        }
 
-            // Send heartbeats to all servers
-            for (target_id, channel) in &mut self.channels {
-                if let Err(e) = channel.send_heartbeat(self.server_id).await {
-                    log::warn!("Failed to send heartbeat to server {}: {}", target_id, e);
-                }
+        let received = {
+            let receives = self
+                .channels
+                .iter_mut()
+                .map(|(target_id, channel)| Box::pin(async move { (*target_id, channel.receiver.recv().await) }))
+                .collect::<Vec<_>>();
+            if receives.is_empty() {
+                return Err(ProtocolError::network_error("No channels to receive from"));
             }
+            runtime::timeout(&*self.runtime, timeout_duration, future::select_all(receives))
+                .await
+                .map(|(received, _, _)| received)
+        };
 
-            // Check health of all channels
-            for (target_id, channel) in &self.channels {
-                if !channel.is_healthy(self.config.max_heartbeat_age) {
-                    log::warn!("Channel to server {} is unhealthy", target_id);
+        let (target_id, message) = match received {
+            Err(_) => return Ok(None),
+            Ok((target_id, Some(message))) => (target_id, message),
+            Ok((target_id, None)) => {
+                return Err(ProtocolError::network_error(format!("Channel to server {} closed", target_id)))
+            }
+        };
+        let channel = self.channels.get_mut(&target_id).expect("received on a channel of the manager");
+        channel.count_received(&message);
+        match channel.accept(message).await {
+            Ok(Some(message)) => {
+                self.record(Direction::Received, target_id, &message)?;
+                Ok(Some((target_id, message)))
+            }
+            Ok(None) => Ok(None),
+            Err(ProtocolError::Security(e)) => {
+                log::warn!("Dropping message from server {}: {}", target_id, e);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Start network manager
+    ///
+    /// With `enable_encryption`, every channel is encrypted, and with
+    /// `authenticate_messages`, every message is authenticated, with the keys in
+    /// the `identity` configuration. Starting fails if they are missing.
+    /// Channels already connected, e.g. by `bootstrap`, are not connected again.
+    ///
+    /// Heartbeats are then sent every `heartbeat_interval` by a task on the
+    /// runtime until the returned handle is shut down or dropped. Heartbeats
+    /// from the other servers are seen as messages are received, the task
+    /// reports the servers the failure detector suspects to `on_suspicion`,
+    /// and `maintain` acts on the servers that stopped answering.
+    pub async fn start(&mut self) -> Result<HeartbeatHandle, ProtocolError> {
+        if !self.channels.values().all(|channel| channel.connected) {
+            self.connect_all().await?;
+        }
+
+        let task = HeartbeatTask {
+            links: self.heartbeat_links.clone(),
+            interval: Duration::from_secs(self.config.heartbeat_interval),
+            max_age: Duration::from_secs(self.config.max_heartbeat_age),
+            detector: self.config.failure_detector(),
+            runtime: self.runtime.clone(),
+            callbacks: self.liveness_callbacks.clone(),
+            suspicion_callbacks: self.suspicion_callbacks.clone(),
+        };
+        Ok(task.spawn())
+    }
+
+    /// Establish connections with all servers
+    async fn connect_all(&mut self) -> Result<(), ProtocolError> {
+        let needs_identity = self.config.enable_encryption || self.config.authenticate_messages;
+        let identity = match (&self.config.identity, needs_identity) {
+            (Some(identity), true) => Some((identity, identity.keys()?)),
+            (None, true) => {
+                return Err(ProtocolError::Security(
+                    "Encryption or message authentication is enabled but no identity is configured".to_string(),
+                ))
+            }
+            (_, false) => None,
+        };
+        self.authenticator = match &identity {
+            Some((identity, _)) if self.config.authenticate_messages => {
+                Some(MessageAuthenticator::from_identity(self.server_id, identity)?)
+            }
+            _ => None,
+        };
+        let handshake_timeout = Duration::from_millis(self.config.message_timeout_ms);
+
+        // Every server handshakes with its peers in the order of their IDs, so
+        // no two servers wait for each other
+        let mut channels = self.channels.iter_mut().collect::<Vec<_>>();
+        channels.sort_by_key(|(target_id, _)| **target_id);
+        for (target_id, channel) in channels {
+            if let Some((identity, keys)) = identity.as_ref().filter(|_| self.config.enable_encryption) {
+                channel.set_encryption(self.server_id, keys.clone(), identity.peer_key(*target_id)?);
+            }
+            channel.set_authenticator(self.authenticator.clone());
+            if let Some(policy) = self.config.retry_policy() {
+                channel.set_retry(policy);
+            }
+            channel.set_flow_control(self.config.flow_control());
+            channel.set_versions(self.server_id, self.config.protocol_versions());
+            channel.set_codec(self.config.codec);
+            if self.config.ordered_delivery {
+                channel.set_ordering();
+            }
+            channel.connect_timeout(handshake_timeout).await?;
+            log::info!(
+                "Connected to server {} (encrypted: {}, protocol version: {:?})",
+                target_id,
+                channel.is_encrypted(),
+                channel.version()
+            );
+            self.heartbeat_links
+                .lock()
+                .unwrap()
+                .insert(*target_id, channel.heartbeat_sender(self.server_id));
+
+            // Messages restored by `resume`
+            let replayed = channel.replay_unacked().await;
+            if replayed > 0 {
+                log::info!("Replayed {} messages to server {}", replayed, target_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resend overdue messages, reconnect unhealthy channels and save the session
+    ///
+    /// Meant to be called between the rounds of the protocol, which owns the
+    /// channels while the heartbeat task runs. Returns the IDs of the unhealthy
+    /// channels, whether or not they were reconnected.
+    pub async fn maintain(&mut self) -> Vec<usize> {
+        for (target_id, channel) in &self.channels {
+            if let Err(e) = channel.resend_due().await {
+                log::warn!("Channel to server {}: {}", target_id, e);
+            }
+        }
+
+        let mut unhealthy = self
+            .channels
+            .iter()
+            .filter(|(_, channel)| !channel.is_healthy(self.config.max_heartbeat_age))
+            .map(|(target_id, _)| *target_id)
+            .collect::<Vec<_>>();
+        unhealthy.sort_unstable();
+        for &target_id in &unhealthy {
+            log::warn!("Channel to server {} is unhealthy", target_id);
+            if self.can_reconnect() {
+                if let Err(e) = self.reconnect(target_id).await {
+                    log::error!("Failed to reconnect to server {}: {}", target_id, e);
                 }
             }
         }
+
+        if let Err(e) = self.checkpoint() {
+            log::warn!("Failed to save the session: {}", e);
+        }
+        unhealthy
     }
 
     /// Process incoming messages
-    pub async fn process_messages(&self) -> Result<(), ProtocolError> {
-        for (target_id, channel) in &mut self.channels {
-            while let Ok(Some(message)) = channel.receive_timeout(
-                Duration::from_millis(self.config.message_timeout_ms)
-            ).await {
-                // Handle message based on type
-                if let Some(handler) = self.handlers.get(&message.message_type) {
-                    if let Err(e) = handler.handle(&message) {
-                        log::error!("Failed to handle message: {}", e);
+    ///
+    /// Messages that fail authentication are dropped before any handler sees them.
+    /// A lost channel is reconnected if the manager has a connector. Fails with
+    /// `Cancelled` once the token of `set_cancellation` is cancelled.
+    pub async fn process_messages(&mut self) -> Result<(), ProtocolError> {
+        let cancellation = self.cancellation.clone();
+        if cancellation.is_cancelled() {
+            return Err(self.cancelled());
+        }
+        while let Some((target_id, message)) = self.deferred.pop_front() {
+            self.handle_message(target_id, message).await;
+        }
+
+        let timeout_duration = Duration::from_millis(self.config.message_timeout_ms);
+        let targets = self.channels.keys().copied().collect::<Vec<_>>();
+        for target_id in targets {
+            loop {
+                let Some(channel) = self.channels.get_mut(&target_id) else {
+                    break;
+                };
+                let message = match cancellation.run(channel.receive_timeout(timeout_duration)).await {
+                    Ok(Some(message)) => message,
+                    Err(ProtocolError::Cancelled) => return Err(self.cancelled()),
+                    Err(ProtocolError::Security(e)) => {
+                        log::warn!("Dropping message from server {}: {}", target_id, e);
+                        continue;
                     }
-                } else {
-                    log::warn!("No handler for message type: {:?}", message.message_type);
-                }
+                    Err(ProtocolError::NetworkError { message }) if self.can_reconnect() => {
+                        log::warn!("Lost channel to server {}: {}", target_id, message);
+                        self.reconnect(target_id).await?;
+                        continue;
+                    }
+                    _ => break,
+                };
+                self.record(Direction::Received, target_id, &message)?;
+                self.handle_message(target_id, message).await;
             }
         }
 
         Ok(())
     }
 
+    /// Hand a message from `target_id` to the broadcast it belongs to or the handler of its type
+    async fn handle_message(&mut self, target_id: usize, message: NetworkMessage) {
+        let Some(message) = self.gate(target_id, message) else {
+            return;
+        };
+        if let MessagePayload::Broadcast(step) = message.payload {
+            if let Err(e) = self.handle_broadcast(target_id, step).await {
+                log::error!("Failed to handle broadcast from server {}: {}", target_id, e);
+            }
+        } else if let Some(handler) = self.handlers.get(&message.message_type) {
+            if let Err(e) = handler.handle(&message) {
+                log::error!("Failed to handle message: {}", e);
+            }
+        } else {
+            log::warn!("No handler for message type: {:?}", message.message_type);
+        }
+    }
+
+    /// Let through a message from `target_id` of the phase this server is in, or of no phase
+    ///
+    /// A message of a later phase is kept until `set_phase` enters it, and a
+    /// message of an earlier phase is dropped, so that a late or early
+    /// message is never taken for one of the current round.
+    fn gate(&mut self, target_id: usize, message: NetworkMessage) -> Option<NetworkMessage> {
+        match message.phase {
+            Some(phase) if phase > self.phase => {
+                log::debug!("Keeping message {} from server {} until phase {:?}", message.sequence, target_id, phase);
+                self.early.entry(phase).or_default().push((target_id, message));
+                None
+            }
+            Some(phase) if phase < self.phase => {
+                log::warn!(
+                    "Dropping message {} from server {} of phase {:?} in phase {:?}",
+                    message.sequence, target_id, phase, self.phase
+                );
+                None
+            }
+            _ => Some(message),
+        }
+    }
+
+    /// Number of messages kept until this server enters their phase
+    pub fn early_messages(&self) -> usize {
+        self.early.values().map(Vec::len).sum()
+    }
+
     /// Get channel to specific server
     pub fn get_channel(&self, target_id: usize) -> Option<&CommunicationChannel> {
         self.channels.get(&target_id)
@@ -435,6 +2306,10 @@ impl NetworkManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::multi_party::broadcast::BroadcastStep;
+    use crate::multi_party::server::ServerRole;
+    use crate::multi_party::topology::PeerConfig;
+    use crate::multi_party::traffic::TrafficCounts;
     use crate::schema::QueryType;
 
     #[test]
@@ -452,15 +2327,715 @@ mod tests {
         assert!(!message.is_expired(60)); // Should not be expired after 1 second
     }
 
+    #[test]
+    fn test_network_message_decoding() {
+        let message = NetworkMessage::shuffle(0, 1, 7, vec![2, 0, 1]);
+        let decoded = NetworkMessage::from_bytes(&message.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.sequence, 7);
+        assert!(matches!(decoded.payload, MessagePayload::Permutation(ref p) if p == &[2, 0, 1]));
+
+        let malformed: [&[u8]; 3] = [b"", b"{\"message_type\":", &[0xff, 0x00]];
+        for bytes in malformed {
+            assert!(matches!(NetworkMessage::from_bytes(bytes), Err(ProtocolError::MalformedMessage(_))));
+        }
+
+        let mut message = NetworkMessage::shuffle(0, 1, 7, vec![0, 0, 3]);
+        assert!(NetworkMessage::from_bytes(&message.to_bytes().unwrap()).is_err());
+        message.payload = MessagePayload::Heartbeat;
+        assert!(NetworkMessage::from_bytes(&message.to_bytes().unwrap()).is_err());
+
+        // A timestamp from the future does not underflow the age
+        let mut message = NetworkMessage::heartbeat(0, 1, 1);
+        message.timestamp = u64::MAX;
+        assert_eq!(message.age_seconds(), 0);
+        assert!(!message.is_expired(60));
+    }
+
     #[tokio::test]
     async fn test_communication_channel() {
-        let (tx, rx) = mpsc::channel(10);
+        let (tx, rx) = runtime::channel(10);
         let mut channel = CommunicationChannel::new(1, tx, rx);
         
         channel.connect().await.unwrap();
         assert!(channel.connected);
     }
 
+    #[tokio::test]
+    async fn test_encrypted_channel() {
+        let keys = [ServerKeys::generate(&mut rand::rngs::OsRng), ServerKeys::generate(&mut rand::rngs::OsRng)];
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut channel_0 = CommunicationChannel::new(1, tx_01, rx_10).with_encryption(0, keys[0].clone(), keys[1].public_key());
+        let mut channel_1 = CommunicationChannel::new(0, tx_10, rx_01).with_encryption(1, keys[1].clone(), keys[0].public_key());
+
+        assert!(channel_0.send(NetworkMessage::ack(0, 1, 0)).await.is_err());
+        let (connected_0, connected_1) = tokio::join!(channel_0.connect(), channel_1.connect());
+        connected_0.unwrap();
+        connected_1.unwrap();
+        assert!(channel_0.is_encrypted() && channel_1.is_encrypted());
+
+        channel_0.send(NetworkMessage::share(0, 1, 5, vec![vec![1, 2, 3]])).await.unwrap();
+        let received = channel_1.receive().await.unwrap().unwrap();
+        assert_eq!(received.sequence, 5);
+        assert!(matches!(received.payload, MessagePayload::Shares(ref shares) if shares == &[vec![1, 2, 3]]));
+
+        // Plaintext is rejected once the channel is encrypted
+        channel_0.send_raw(NetworkMessage::share(0, 1, 6, vec![vec![4]])).await.unwrap();
+        assert!(matches!(channel_1.receive().await, Err(ProtocolError::Security(_))));
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_channel() {
+        let authenticator = |local_id, peer_id| {
+            let mut authenticator = MessageAuthenticator::new(local_id);
+            authenticator.add_peer(peer_id, [7; KEY_BYTES]);
+            Some(authenticator)
+        };
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut channel_0 = CommunicationChannel::new(1, tx_01, rx_10);
+        let mut channel_1 = CommunicationChannel::new(0, tx_10, rx_01);
+        channel_0.set_authenticator(authenticator(0, 1));
+        channel_1.set_authenticator(authenticator(1, 0));
+        channel_0.connect().await.unwrap();
+        channel_1.connect().await.unwrap();
+
+        channel_0.send(NetworkMessage::share(0, 1, 5, vec![vec![1, 2, 3]])).await.unwrap();
+        let received = channel_1.receive().await.unwrap().unwrap();
+        assert_eq!(received.sequence, 5);
+
+        // A signed message sent again is rejected as a replay
+        channel_0.send_raw(received).await.unwrap();
+        assert!(matches!(channel_1.receive().await, Err(ProtocolError::Security(_))));
+    }
+
+    #[tokio::test]
+    async fn test_version_negotiation() {
+        let channels = |versions_0: VersionRange, versions_1: VersionRange| {
+            let (tx_01, rx_01) = runtime::channel(10);
+            let (tx_10, rx_10) = runtime::channel(10);
+            (
+                CommunicationChannel::new(1, tx_01, rx_10).with_versions(0, versions_0),
+                CommunicationChannel::new(0, tx_10, rx_01).with_versions(1, versions_1),
+            )
+        };
+
+        // A server upgraded to version 2 keeps speaking version 1 to the others
+        let (mut channel_0, mut channel_1) = channels(VersionRange { min: 1, max: 2 }, VersionRange { min: 1, max: 1 });
+        let (connected_0, connected_1) = tokio::join!(channel_0.connect(), channel_1.connect());
+        connected_0.unwrap();
+        connected_1.unwrap();
+        assert_eq!((channel_0.version(), channel_1.version()), (Some(1), Some(1)));
+
+        channel_0.send(NetworkMessage::share(0, 1, 5, vec![vec![1]])).await.unwrap();
+        assert_eq!(channel_1.receive().await.unwrap().unwrap().version, 1);
+        let mut message = NetworkMessage::share(0, 1, 6, vec![vec![2]]);
+        message.version = 2;
+        channel_0.send_raw(message).await.unwrap();
+        assert!(matches!(channel_1.receive().await, Err(ProtocolError::MalformedMessage(_))));
+
+        let (mut channel_0, mut channel_1) = channels(VersionRange { min: 2, max: 3 }, VersionRange { min: 1, max: 1 });
+        let (connected_0, connected_1) = tokio::join!(channel_0.connect(), channel_1.connect());
+        for connected in [connected_0, connected_1] {
+            assert!(matches!(connected, Err(ProtocolError::IncompatibleVersion { .. })));
+        }
+        assert!(!channel_0.connected && !channel_1.connected);
+    }
+
+    #[test]
+    fn test_reliable_channel() {
+        let runtime = Arc::new(runtime::ManualRuntime::new());
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+        };
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut channel_0 = CommunicationChannel::new(1, tx_01, rx_10).with_runtime(runtime.clone()).with_retry(policy);
+        let mut channel_1 = CommunicationChannel::new(0, tx_10, rx_01).with_runtime(runtime.clone()).with_retry(policy);
+
+        runtime.block_on(async {
+            channel_0.connect().await.unwrap();
+            channel_1.connect().await.unwrap();
+
+            // The first copy is lost, and two more are sent at 10 and 30 ms
+            channel_0.send(NetworkMessage::share(0, 1, 0, vec![vec![1]])).await.unwrap();
+            assert!(channel_1.receiver.recv().await.is_some());
+            assert_eq!(channel_0.pending_acks(), 1);
+            let waited = channel_0.receive_timeout(Duration::from_millis(35)).await;
+            assert!(matches!(waited, Err(ProtocolError::Timeout { duration_ms: 35, .. })));
+
+            // The second copy is dropped as a duplicate, and both are acknowledged
+            let received = channel_1.receive().await.unwrap().unwrap();
+            assert!(matches!(received.payload, MessagePayload::Shares(ref shares) if shares == &[vec![1]]));
+            assert!(channel_1.receive_timeout(Duration::from_millis(5)).await.is_err());
+            assert!(channel_0.receive_timeout(Duration::from_millis(5)).await.is_err());
+            assert_eq!(channel_0.pending_acks(), 0);
+
+            // A message that is never acknowledged is given up after the retries
+            channel_0.send(NetworkMessage::share(0, 1, 1, vec![vec![2]])).await.unwrap();
+            let waited = channel_0.receive_timeout(Duration::from_secs(1)).await;
+            assert!(matches!(waited, Err(ProtocolError::NetworkError { .. })));
+            assert_eq!(channel_0.pending_acks(), 0);
+        });
+    }
+
+    #[test]
+    fn test_ordered_channel() {
+        let runtime = Arc::new(runtime::ManualRuntime::new());
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+        };
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut channel_0 = CommunicationChannel::new(1, tx_01, rx_10)
+            .with_runtime(runtime.clone())
+            .with_retry(policy)
+            .with_ordering();
+        let mut channel_1 = CommunicationChannel::new(0, tx_10, rx_01)
+            .with_runtime(runtime.clone())
+            .with_retry(policy)
+            .with_ordering();
+
+        runtime.block_on(async {
+            channel_0.connect().await.unwrap();
+            channel_1.connect().await.unwrap();
+
+            // The channel numbers the messages whatever their sequence numbers
+            channel_0.send(NetworkMessage::share(0, 1, 9, vec![vec![1]])).await.unwrap();
+            channel_0.send(NetworkMessage::share(0, 1, 3, vec![vec![2]])).await.unwrap();
+            let first = channel_1.receiver.recv().await.unwrap();
+            let second = channel_1.receiver.recv().await.unwrap();
+            assert_eq!((first.sequence, second.sequence), (0, 1));
+
+            // The second message overtakes the first and is held back until it arrives
+            channel_0.sender.send(second).await.unwrap();
+            channel_0.sender.send(first).await.unwrap();
+            let received = channel_1.receive().await.unwrap().unwrap();
+            assert!(matches!(received.payload, MessagePayload::Shares(ref shares) if shares == &[vec![1]]));
+            assert_eq!(channel_1.held_messages(), 0);
+            let state = channel_1.state();
+            assert_eq!((state.next_delivery, state.held.len()), (1, 1));
+            let received = channel_1.receive().await.unwrap().unwrap();
+            assert!(matches!(received.payload, MessagePayload::Shares(ref shares) if shares == &[vec![2]]));
+
+            // Both are acknowledged, and nothing is delivered twice
+            assert!(channel_0.receive_timeout(Duration::from_millis(5)).await.is_err());
+            assert_eq!(channel_0.pending_acks(), 0);
+            assert!(channel_1.receive_timeout(Duration::from_millis(5)).await.is_err());
+            assert_eq!(channel_0.state().next_ordered, 2);
+            assert_eq!(channel_1.state().next_delivery, 2);
+        });
+    }
+
+    /// Hands out the links prepared by a test
+    struct QueuedConnector(std::sync::Mutex<std::collections::VecDeque<Link>>);
+
+    impl Connector for QueuedConnector {
+        fn connect(&self, target_id: usize) -> BoxFuture<'_, Result<Link, ProtocolError>> {
+            let link = self.0.lock().unwrap().pop_front();
+            Box::pin(async move { link.ok_or_else(|| ProtocolError::network_error(format!("No link to server {}", target_id))) })
+        }
+    }
+
+    #[test]
+    fn test_flow_control() {
+        let runtime = Arc::new(runtime::ManualRuntime::new());
+        let control = FlowControl {
+            high_watermark: 3,
+            low_watermark: 1,
+            max_wait: Duration::from_millis(100),
+        };
+        let (tx_01, mut rx_01) = runtime::channel(10);
+        let (_tx_10, rx_10) = runtime::channel(10);
+        let mut channel = CommunicationChannel::new(1, tx_01, rx_10)
+            .with_runtime(runtime.clone())
+            .with_flow_control(control);
+        let share = |sequence| NetworkMessage::share(0, 1, sequence, vec![vec![1]]);
+
+        runtime.block_on(async {
+            channel.connect().await.unwrap();
+            for sequence in 0..3 {
+                channel.try_send(share(sequence)).await.unwrap();
+            }
+            assert!(matches!(
+                channel.try_send(share(3)).await,
+                Err(ProtocolError::Backpressure { target_id: 1, depth: 3 })
+            ));
+            assert!(channel.is_paused());
+
+            // A send waits until the target drains the queue to the low watermark
+            let drain = async {
+                rx_01.recv().await.unwrap();
+                rx_01.recv().await.unwrap();
+            };
+            let (sent, ()) = futures::join!(channel.send(share(3)), drain);
+            sent.unwrap();
+            assert!(!channel.is_paused());
+
+            // and gives up if it does not
+            channel.send(share(4)).await.unwrap();
+            let sent = channel.send(share(5)).await;
+            assert!(matches!(sent, Err(ProtocolError::Backpressure { depth: 3, .. })));
+            assert_eq!(runtime.now(), Duration::from_millis(100));
+        });
+
+        let metrics = channel.flow_metrics();
+        assert_eq!((metrics.outbound_depth, metrics.inbound_depth), (3, 0));
+        assert_eq!((metrics.pauses, metrics.rejected, metrics.max_outbound_depth), (2, 2, 3));
+        assert_eq!(metrics.waited, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_heartbeat_task() {
+        let runtime = Arc::new(runtime::ManualRuntime::new());
+        let config = NetworkConfig {
+            heartbeat_interval: 1,
+            max_heartbeat_age: 2,
+            enable_encryption: false,
+            authenticate_messages: false,
+            ..Default::default()
+        };
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut manager = NetworkManager::new(0, config).with_runtime(runtime.clone());
+        manager.add_channel(1, CommunicationChannel::new(1, tx_01, rx_10).with_runtime(runtime.clone()));
+        let mut peer = CommunicationChannel::new(0, tx_10, rx_01).with_runtime(runtime.clone());
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        manager.on_liveness(move |server_id, alive| recorded.lock().unwrap().push((server_id, alive)));
+
+        runtime.block_on(async {
+            peer.connect().await.unwrap();
+            let handle = manager.start().await.unwrap();
+
+            // Heartbeats keep coming while the peer stays silent, which it is
+            // declared dead for after the maximum age
+            for second in 1..=3 {
+                let heartbeat = peer.receive().await.unwrap().unwrap();
+                assert!(matches!(heartbeat.message_type, MessageType::Heartbeat));
+                assert_eq!(runtime.now(), Duration::from_secs(second));
+            }
+            assert!(!manager.heartbeat(1).unwrap().alive);
+            assert_eq!(manager.maintain().await, [1]);
+
+            // A heartbeat from the peer revives it, and does not take a sequence number
+            peer.send(NetworkMessage::heartbeat(1, 0, 0)).await.unwrap();
+            let channel = manager.get_channel_mut(1).unwrap();
+            assert!(channel.receive_timeout(Duration::from_millis(10)).await.is_ok());
+            peer.send(NetworkMessage::share(1, 0, 0, vec![vec![1]])).await.unwrap();
+            assert!(channel.receive_timeout(Duration::from_millis(10)).await.is_ok());
+            assert!(manager.maintain().await.is_empty());
+            peer.receive().await.unwrap();
+            peer.receive().await.unwrap();
+
+            let state = manager.heartbeats()[&1];
+            assert!(state.alive);
+            assert_eq!(state.sent, 4);
+            assert_eq!(state.last_seen, Some(Duration::from_secs(3)));
+
+            handle.shutdown().await;
+            assert!(peer.receive_timeout(Duration::from_secs(5)).await.is_err());
+        });
+        assert_eq!(*changes.lock().unwrap(), [(1, true), (1, false), (1, true)]);
+    }
+
+    #[test]
+    fn test_failure_detector() {
+        let runtime = Arc::new(runtime::ManualRuntime::new());
+        let config = NetworkConfig {
+            heartbeat_interval: 1,
+            max_heartbeat_age: 2,
+            failure_timeout: 4,
+            enable_encryption: false,
+            authenticate_messages: false,
+            ..Default::default()
+        };
+        assert!(NetworkConfig { failure_timeout: 1, ..config.clone() }.validate().is_err());
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut manager = NetworkManager::new(0, config).with_runtime(runtime.clone());
+        manager.add_channel(1, CommunicationChannel::new(1, tx_01, rx_10).with_runtime(runtime.clone()));
+        let mut peer = CommunicationChannel::new(0, tx_10, rx_01).with_runtime(runtime.clone());
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        manager.on_suspicion(move |server_id, suspicion| recorded.lock().unwrap().push((server_id, suspicion)));
+
+        runtime.block_on(async {
+            peer.connect().await.unwrap();
+            let handle = manager.start().await.unwrap();
+
+            // The silent peer is suspected after 3 seconds and declared failed after 5
+            for _ in 1..=5 {
+                peer.receive().await.unwrap();
+            }
+            assert_eq!(manager.heartbeat(1).unwrap().suspicion, Suspicion::Failed);
+            assert_eq!(manager.failed_servers(), [1]);
+
+            // Hearing from it again restores trust on the next heartbeat
+            peer.send(NetworkMessage::heartbeat(1, 0, 0)).await.unwrap();
+            let channel = manager.get_channel_mut(1).unwrap();
+            assert!(channel.receive_timeout(Duration::from_millis(10)).await.is_ok());
+            peer.receive().await.unwrap();
+            handle.shutdown().await;
+        });
+        assert!(manager.failed_servers().is_empty());
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [(1, Suspicion::Suspected), (1, Suspicion::Failed), (1, Suspicion::Trusted)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_and_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = NetworkConfig {
+            message_timeout_ms: 10,
+            reconnect_backoff_ms: 1,
+            outbox_path: Some(dir.path().join("outbox.json")),
+            ..NetworkConfig::default()
+        };
+        let policy = config.retry_policy().unwrap();
+        let (tx_01, mut rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let (new_tx_01, mut new_rx_01) = runtime::channel(10);
+        let (_new_tx_10, new_rx_10) = runtime::channel(10);
+        let connector = QueuedConnector(std::sync::Mutex::new([(new_tx_01, new_rx_10)].into()));
+
+        let mut manager = NetworkManager::new(0, config.clone()).with_connector(Arc::new(connector));
+        let mut channel = CommunicationChannel::new(1, tx_01, rx_10).with_retry(policy);
+        channel.connect().await.unwrap();
+        manager.add_channel(1, channel);
+        manager.set_phase(ProtocolPhase::Shuffling).unwrap();
+        manager.send_message(1, NetworkMessage::shuffle(0, 1, 0, vec![1, 0])).await.unwrap();
+        assert!(rx_01.recv().await.is_some());
+        manager.checkpoint().unwrap();
+
+        // The link drops before server 1 acknowledges the shuffle, which is replayed on a new link
+        drop(tx_10);
+        manager.process_messages().await.unwrap();
+        let replayed = new_rx_01.recv().await.unwrap();
+        assert_eq!(replayed.sequence, 0);
+        assert!(matches!(replayed.payload, MessagePayload::Permutation(_)));
+
+        // A restarted server resumes the phase with the shuffle still unacknowledged
+        let (tx, rx) = runtime::channel(10);
+        let mut restarted = NetworkManager::new(0, config);
+        restarted.add_channel(1, CommunicationChannel::new(1, tx, rx).with_retry(policy));
+        assert_eq!(restarted.resume().unwrap(), Some(ProtocolPhase::Shuffling));
+        assert_eq!(restarted.channels[&1].pending_acks(), 1);
+
+        restarted.set_phase(ProtocolPhase::Completed).unwrap();
+        assert!(restarted.resume().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap() {
+        let keys = (0..3).map(|_| ServerKeys::generate(&mut rand::rngs::OsRng)).collect::<Vec<_>>();
+        let ports = (0..3)
+            .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port())
+            .collect::<Vec<_>>();
+        let roles = [ServerRole::First, ServerRole::Second, ServerRole::Third];
+        let topology = Topology {
+            servers: (0..3)
+                .map(|id| PeerConfig {
+                    id,
+                    address: format!("127.0.0.1:{}", ports[id]),
+                    role: roles[id].clone(),
+                    public_key: keys[id].public_key_hex(),
+                })
+                .collect(),
+        };
+
+        let mut managers = (0..3)
+            .map(|id| {
+                let config = NetworkConfig {
+                    identity: Some(IdentityConfig {
+                        private_key: keys[id].private_key_hex(),
+                        peers: Default::default(),
+                    }),
+                    reconnect_backoff_ms: 20,
+                    compression: Compression::Zstd,
+                    ..NetworkConfig::default()
+                };
+                NetworkManager::new(id, config)
+            })
+            .collect::<Vec<_>>();
+        let [manager_0, manager_1, manager_2] = &mut managers[..] else {
+            unreachable!()
+        };
+        let (bootstrapped_0, bootstrapped_1, bootstrapped_2) = tokio::join!(
+            manager_0.bootstrap(topology.clone()),
+            manager_1.bootstrap(topology.clone()),
+            manager_2.bootstrap(topology.clone()),
+        );
+        bootstrapped_0.unwrap();
+        bootstrapped_1.unwrap();
+        bootstrapped_2.unwrap();
+
+        for manager in &managers {
+            assert_eq!(manager.channels.len(), 2);
+            assert!(manager.channels.values().all(|channel| channel.connected && channel.is_encrypted()));
+            assert!(manager.can_reconnect());
+        }
+
+        managers[2].send_message(0, NetworkMessage::shuffle(2, 0, 0, vec![1, 0])).await.unwrap();
+        let received = managers[0].channels.get_mut(&2).unwrap().receive().await.unwrap().unwrap();
+        assert_eq!(received.source_id, 2);
+        assert!(matches!(received.payload, MessagePayload::Permutation(ref p) if p == &[1, 0]));
+
+        let sent = managers[2].compression_stats();
+        assert!(sent.frames_sent > 0);
+        assert!(sent.wire_bytes_sent <= sent.raw_bytes_sent);
+        assert!(managers[0].compression_stats().frames_received > 0);
+    }
+
+    #[tokio::test]
+    async fn test_reliable_broadcast() {
+        let mut managers = (0..3).map(|id| NetworkManager::new(id, NetworkConfig::default())).collect::<Vec<_>>();
+        for i in 0..3 {
+            for j in i + 1..3 {
+                let (tx_ij, rx_ij) = runtime::channel(16);
+                let (tx_ji, rx_ji) = runtime::channel(16);
+                let mut channel_ij = CommunicationChannel::new(j, tx_ij, rx_ji);
+                let mut channel_ji = CommunicationChannel::new(i, tx_ji, rx_ij);
+                channel_ij.connect().await.unwrap();
+                channel_ji.connect().await.unwrap();
+                managers[i].add_channel(j, channel_ij);
+                managers[j].add_channel(i, channel_ji);
+            }
+        }
+        let [manager_0, manager_1, manager_2] = &mut managers[..] else {
+            unreachable!()
+        };
+
+        let (delivered_0, delivered_1, delivered_2) = tokio::join!(
+            manager_0.reliable_broadcast(4, b"params".to_vec()),
+            manager_1.deliver_broadcast(0, 4),
+            manager_2.deliver_broadcast(0, 4),
+        );
+        for delivered in [delivered_0, delivered_1, delivered_2] {
+            assert_eq!(delivered.unwrap(), b"params");
+        }
+
+        // Server 0 sends and echoes one value to server 1 and another to server 2
+        for (target_id, value) in [(1, b"a"), (2, b"b")] {
+            let channel = manager_0.get_channel_mut(target_id).unwrap();
+            for step in [BroadcastStep::Send, BroadcastStep::Echo] {
+                let step = BroadcastMessage {
+                    sender: 0,
+                    instance: 5,
+                    step,
+                    value: value.to_vec(),
+                };
+                let sequence = channel.next_sequence();
+                channel.send(NetworkMessage::broadcast(0, target_id, sequence, step)).await.unwrap();
+            }
+        }
+        let (delivered_1, delivered_2) = tokio::join!(manager_1.deliver_broadcast(0, 5), manager_2.deliver_broadcast(0, 5));
+        for delivered in [delivered_1, delivered_2] {
+            assert!(matches!(delivered, Err(ProtocolError::CheatDetected { index: 5, ref servers }) if servers.contains(&0)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_network_manager() {
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut channel_0 = CommunicationChannel::new(1, tx_01, rx_10);
+        let mut channel_1 = CommunicationChannel::new(0, tx_10, rx_01);
+        channel_0.connect().await.unwrap();
+        channel_1.connect().await.unwrap();
+        let token = CancellationToken::new();
+        let mut manager = NetworkManager::new(1, NetworkConfig::default()).with_cancellation(token.clone());
+        manager.add_channel(0, channel_1);
+
+        // Server 0 never broadcasts, so only the token ends the wait
+        let started = std::time::Instant::now();
+        let canceller = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            token.cancel();
+        });
+        let waiting = manager.deliver_broadcast(0, 9).await;
+        canceller.await.unwrap();
+        assert!(matches!(waiting, Err(ProtocolError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_millis(manager.config.message_timeout_ms));
+
+        // The channels were released, and the loops stay cancelled
+        assert!(!manager.get_channel(0).unwrap().connected);
+        assert!(channel_0.send(NetworkMessage::heartbeat(0, 1, 0)).await.is_err());
+        assert!(matches!(manager.process_messages().await, Err(ProtocolError::Cancelled)));
+    }
+
+    /// Records the phase of every message it handles
+    struct PhaseRecorder(Arc<Mutex<Vec<Option<ProtocolPhase>>>>);
+
+    impl MessageHandler for PhaseRecorder {
+        fn handle(&self, message: &NetworkMessage) -> Result<(), ProtocolError> {
+            self.0.lock().unwrap().push(message.phase);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_phase_gating() {
+        let config = NetworkConfig {
+            message_timeout_ms: 20,
+            ..NetworkConfig::default()
+        };
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut channel_0 = CommunicationChannel::new(1, tx_01, rx_10);
+        let mut channel_1 = CommunicationChannel::new(0, tx_10, rx_01);
+        channel_0.connect().await.unwrap();
+        channel_1.connect().await.unwrap();
+        let mut manager_0 = NetworkManager::new(0, config.clone());
+        let mut manager_1 = NetworkManager::new(1, config);
+        manager_0.add_channel(1, channel_0);
+        manager_1.add_channel(0, channel_1);
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        manager_1.register_handler(MessageType::Shuffle, Box::new(PhaseRecorder(handled.clone())));
+        manager_1.set_phase(ProtocolPhase::Sharing).unwrap();
+
+        // Messages are sent as part of the phase of the sender unless they have one
+        manager_0.set_phase(ProtocolPhase::Shuffling).unwrap();
+        manager_0.send_message(1, NetworkMessage::shuffle(0, 1, 0, vec![1, 0])).await.unwrap();
+        for phase in [ProtocolPhase::Setup, ProtocolPhase::Sharing, ProtocolPhase::Query, ProtocolPhase::Completed] {
+            let mut message = NetworkMessage::shuffle(0, 1, 0, vec![0, 1]);
+            message.phase = Some(phase);
+            manager_0.send_message(1, message).await.unwrap();
+        }
+
+        // The stale message is dropped and the early ones are kept for their phases
+        manager_1.process_messages().await.unwrap();
+        assert_eq!(*handled.lock().unwrap(), [Some(ProtocolPhase::Sharing)]);
+        assert_eq!(manager_1.early_messages(), 3);
+
+        manager_1.set_phase(ProtocolPhase::Shuffling).unwrap();
+        manager_1.process_messages().await.unwrap();
+        assert_eq!(handled.lock().unwrap()[1..], [Some(ProtocolPhase::Shuffling)]);
+        assert_eq!(manager_1.early_messages(), 2);
+
+        // Skipping a phase drops its messages
+        manager_1.set_phase(ProtocolPhase::Completed).unwrap();
+        manager_1.process_messages().await.unwrap();
+        assert_eq!(handled.lock().unwrap()[2..], [Some(ProtocolPhase::Completed)]);
+        assert_eq!(manager_1.early_messages(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_traffic_accounting() {
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut channel_0 = CommunicationChannel::new(1, tx_01, rx_10);
+        let mut channel_1 = CommunicationChannel::new(0, tx_10, rx_01);
+        channel_0.connect().await.unwrap();
+        channel_1.connect().await.unwrap();
+        let mut manager_0 = NetworkManager::new(0, NetworkConfig::default());
+        let mut manager_1 = NetworkManager::new(1, NetworkConfig::default());
+        manager_0.add_channel(1, channel_0);
+        manager_1.add_channel(0, channel_1);
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        manager_1.register_handler(MessageType::Shuffle, Box::new(PhaseRecorder(handled.clone())));
+
+        for phase in [ProtocolPhase::Sharing, ProtocolPhase::Shuffling] {
+            manager_0.set_phase(phase).unwrap();
+            manager_1.set_phase(phase).unwrap();
+            manager_0.send_message(1, NetworkMessage::shuffle(0, 1, 0, vec![2, 0, 1])).await.unwrap();
+            manager_1.process_messages().await.unwrap();
+        }
+        assert_eq!(handled.lock().unwrap().len(), 2);
+
+        let sent = &manager_0.traffic()[&1].sent;
+        assert_eq!(sent.total.messages, 2);
+        assert_eq!(sent.by_type["shuffle"].messages, 2);
+        assert!(sent.phase_bytes(ProtocolPhase::Shuffling) > 0);
+        assert_eq!(sent.phase_bytes(ProtocolPhase::Sharing) + sent.phase_bytes(ProtocolPhase::Shuffling), sent.type_bytes("shuffle"));
+        assert_eq!(sent.phase_bytes(ProtocolPhase::Query), 0);
+
+        // Each side counts the same messages
+        assert_eq!(manager_1.total_traffic().received, manager_0.total_traffic().sent);
+        assert_eq!(manager_1.total_traffic().sent, TrafficCounts::default());
+    }
+
+    #[tokio::test]
+    async fn test_transcript_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server-1.jsonl");
+        let config = NetworkConfig {
+            message_timeout_ms: 20,
+            ..NetworkConfig::default()
+        };
+        let (tx_01, rx_01) = runtime::channel(10);
+        let (tx_10, rx_10) = runtime::channel(10);
+        let mut channel_0 = CommunicationChannel::new(1, tx_01, rx_10);
+        let mut channel_1 = CommunicationChannel::new(0, tx_10, rx_01);
+        channel_0.connect().await.unwrap();
+        channel_1.connect().await.unwrap();
+        let mut manager_0 = NetworkManager::new(0, config.clone());
+        let mut manager_1 = NetworkManager::new(1, config.clone())
+            .with_transcript(TranscriptRecorder::create(&path, 1).unwrap());
+        manager_0.add_channel(1, channel_0);
+        manager_1.add_channel(0, channel_1);
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        manager_1.register_handler(MessageType::Shuffle, Box::new(PhaseRecorder(handled.clone())));
+
+        // One message on time, one early and kept until server 1 catches up
+        manager_1.set_phase(ProtocolPhase::Sharing).unwrap();
+        manager_0.set_phase(ProtocolPhase::Sharing).unwrap();
+        manager_0.send_message(1, NetworkMessage::shuffle(0, 1, 0, vec![1, 0])).await.unwrap();
+        manager_0.set_phase(ProtocolPhase::Shuffling).unwrap();
+        manager_0.send_message(1, NetworkMessage::shuffle(0, 1, 0, vec![0, 1])).await.unwrap();
+        manager_1.process_messages().await.unwrap();
+        manager_1.set_phase(ProtocolPhase::Shuffling).unwrap();
+        manager_1.send_message(0, NetworkMessage::shuffle(1, 0, 0, vec![1, 0])).await.unwrap();
+        manager_1.process_messages().await.unwrap();
+        let live = handled.lock().unwrap().clone();
+        assert_eq!(live, [Some(ProtocolPhase::Sharing), Some(ProtocolPhase::Shuffling)]);
+        assert_eq!(manager_1.transcript().unwrap().entries(), 3);
+
+        let transcript = Transcript::load(&path).unwrap();
+        assert_eq!(transcript.sent().count(), 1);
+        assert_eq!(transcript.received().map(|entry| entry.phase).collect::<Vec<_>>(), [ProtocolPhase::Sharing; 2]);
+
+        // A lone server without channels handles the messages as server 1 did
+        let replayed = Arc::new(Mutex::new(Vec::new()));
+        let mut replica = NetworkManager::new(1, config.clone());
+        replica.register_handler(MessageType::Shuffle, Box::new(PhaseRecorder(replayed.clone())));
+        assert_eq!(replica.replay(&transcript).await.unwrap(), 2);
+        assert_eq!(*replayed.lock().unwrap(), live);
+
+        let mut other = NetworkManager::new(2, config);
+        assert!(matches!(other.replay(&transcript).await, Err(ProtocolError::InvalidConfiguration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_network_manager_requires_identity() {
+        let (tx, rx) = runtime::channel(10);
+        let mut manager = NetworkManager::new(0, NetworkConfig::default());
+        manager.add_channel(1, CommunicationChannel::new(1, tx, rx));
+        assert!(matches!(manager.connect_all().await, Err(ProtocolError::Security(_))));
+
+        let keys = ServerKeys::generate(&mut rand::rngs::OsRng);
+        manager.config.identity = Some(IdentityConfig {
+            private_key: keys.private_key_hex(),
+            peers: Default::default(),
+        });
+        assert!(manager.config.validate().is_ok());
+        assert!(matches!(manager.connect_all().await, Err(ProtocolError::Security(_))));
+
+        manager.config.enable_encryption = false;
+        manager.config.authenticate_messages = false;
+        manager.connect_all().await.unwrap();
+        assert!(!manager.channels[&1].is_encrypted());
+        assert!(manager.authenticator.is_none());
+    }
+
     #[tokio::test]
     async fn test_network_manager() {
         let config = NetworkConfig::default();
@@ -0,0 +1,291 @@
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::share::DataShare;
+use crate::random::prf::{PRFKey, PRFValue, PRF};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Configuration for redundant cross-checking of share computations
+///
+/// Each output index is independently included in the audited sample with
+/// probability `sample_rate`. A server that corrupts `k` outputs therefore
+/// escapes detection with probability `(1 - sample_rate)^k`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrossCheckConfig {
+    /// Probability that a given output index is recomputed by a second server
+    pub sample_rate: f64,
+}
+
+impl CrossCheckConfig {
+    /// Create a new cross-check configuration
+    pub fn new(sample_rate: f64) -> Result<Self, ProtocolError> {
+        if !(sample_rate > 0.0 && sample_rate <= 1.0) {
+            return Err(ProtocolError::InvalidConfiguration(
+                "Cross-check sample rate must be in (0, 1]".to_string(),
+            ));
+        }
+
+        Ok(Self { sample_rate })
+    }
+
+    /// Choose the sample rate needed to catch a server that corrupts at least
+    /// `corrupted` outputs with probability at least `target`
+    pub fn for_detection_probability(target: f64, corrupted: usize) -> Result<Self, ProtocolError> {
+        if !(target > 0.0 && target < 1.0) || corrupted == 0 {
+            return Err(ProtocolError::InvalidConfiguration(
+                "Detection target must be in (0, 1) with at least one corrupted output".to_string(),
+            ));
+        }
+
+        let sample_rate = 1.0 - (1.0 - target).powf(1.0 / corrupted as f64);
+        Self::new(sample_rate)
+    }
+
+    /// Probability of catching a server that corrupts `corrupted` outputs
+    pub fn detection_probability(&self, corrupted: usize) -> f64 {
+        1.0 - (1.0 - self.sample_rate).powi(corrupted as i32)
+    }
+}
+
+impl Default for CrossCheckConfig {
+    fn default() -> Self {
+        Self { sample_rate: 0.05 }
+    }
+}
+
+/// Commitment to the values a server computed for one output index
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComputationCommitment {
+    /// Server that produced the commitment
+    pub server_id: usize,
+    /// Output index being committed to
+    pub index: usize,
+    /// Commitment digest
+    pub digest: PRFValue,
+}
+
+/// Opening of a `ComputationCommitment`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitmentOpening {
+    /// Server that produced the commitment
+    pub server_id: usize,
+    /// Output index being opened
+    pub index: usize,
+    /// Committed share values
+    pub values: Vec<u64>,
+    /// Commitment randomness
+    pub nonce: PRFKey,
+}
+
+impl CommitmentOpening {
+    /// Compute the digest this opening commits to
+    fn digest(&self) -> PRFValue {
+        PRF::new(&self.nonce).eval(&commitment_input(self.index, &self.values))
+    }
+
+    /// Check the opening against a commitment
+    pub fn verify(&self, commitment: &ComputationCommitment) -> bool {
+        self.server_id == commitment.server_id
+            && self.index == commitment.index
+            && self.digest() == commitment.digest
+    }
+}
+
+/// Encode the committed index and values as PRF input
+fn commitment_input(index: usize, values: &[u64]) -> Vec<u8> {
+    let mut input = Vec::with_capacity(8 * (values.len() + 1));
+    input.extend_from_slice(&(index as u64).to_le_bytes());
+    for value in values {
+        input.extend_from_slice(&value.to_le_bytes());
+    }
+    input
+}
+
+/// Commit to the values computed by `server_id` for output `index`
+pub fn commit(server_id: usize, index: usize, values: Vec<u64>) -> (ComputationCommitment, CommitmentOpening) {
+    let nonce: PRFKey = rand::thread_rng().gen();
+    let opening = CommitmentOpening {
+        server_id,
+        index,
+        values,
+        nonce,
+    };
+    let commitment = ComputationCommitment {
+        server_id,
+        index,
+        digest: opening.digest(),
+    };
+
+    (commitment, opening)
+}
+
+/// Commit to the sampled entries of a server's shuffle output
+pub fn commit_outputs(
+    server_id: usize,
+    outputs: &[Vec<DataShare>],
+    sample: &[usize],
+) -> Result<(Vec<ComputationCommitment>, Vec<CommitmentOpening>), ProtocolError> {
+    let mut commitments = Vec::with_capacity(sample.len());
+    let mut openings = Vec::with_capacity(sample.len());
+
+    for &index in sample {
+        let shares = outputs.get(index).ok_or(ProtocolError::InternalError {
+            message: format!("Sampled index {} out of range", index),
+        })?;
+        let values = shares.iter().map(|share| share.value).collect();
+        let (commitment, opening) = commit(server_id, index, values);
+        commitments.push(commitment);
+        openings.push(opening);
+    }
+
+    Ok((commitments, openings))
+}
+
+/// Coordinates sampling and comparison of redundant computations
+pub struct CrossChecker {
+    /// Cross-check configuration
+    pub config: CrossCheckConfig,
+}
+
+impl CrossChecker {
+    /// Create a new cross checker
+    pub fn new(config: CrossCheckConfig) -> Self {
+        Self { config }
+    }
+
+    /// Sample the output indices that will be recomputed by a second server
+    pub fn sample_indices(&self, total: usize) -> Vec<usize> {
+        let mut rng = rand::thread_rng();
+        (0..total)
+            .filter(|_| rng.gen_bool(self.config.sample_rate))
+            .collect()
+    }
+
+    /// Compare the committed results of two servers on the sampled indices
+    ///
+    /// Both servers must first send their commitments and only afterwards the
+    /// openings, so neither can adapt its answer to the other's.
+    pub fn verify(
+        &self,
+        sample: &[usize],
+        first: (&[ComputationCommitment], &[CommitmentOpening]),
+        second: (&[ComputationCommitment], &[CommitmentOpening]),
+    ) -> Result<(), ProtocolError> {
+        let first_values = Self::open_all(sample, first.0, first.1)?;
+        let second_values = Self::open_all(sample, second.0, second.1)?;
+
+        for &index in sample {
+            let (first_id, a) = &first_values[&index];
+            let (second_id, b) = &second_values[&index];
+            if a != b {
+                return Err(ProtocolError::cheat_detected(index, vec![*first_id, *second_id]));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify every opening against its commitment and index the values
+    fn open_all(
+        sample: &[usize],
+        commitments: &[ComputationCommitment],
+        openings: &[CommitmentOpening],
+    ) -> Result<HashMap<usize, (usize, Vec<u64>)>, ProtocolError> {
+        let commitments: HashMap<usize, &ComputationCommitment> =
+            commitments.iter().map(|c| (c.index, c)).collect();
+        let mut values = HashMap::with_capacity(openings.len());
+
+        for opening in openings {
+            match commitments.get(&opening.index) {
+                Some(commitment) if opening.verify(commitment) => {
+                    values.insert(opening.index, (opening.server_id, opening.values.clone()));
+                }
+                _ => {
+                    return Err(ProtocolError::cheat_detected(opening.index, vec![opening.server_id]));
+                }
+            }
+        }
+
+        for &index in sample {
+            if !values.contains_key(&index) {
+                let server_id = commitments.get(&index).map(|c| c.server_id);
+                return Err(ProtocolError::cheat_detected(index, server_id.into_iter().collect()));
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs(values: &[u64]) -> Vec<Vec<DataShare>> {
+        values
+            .iter()
+            .map(|&v| vec![DataShare::feature(0, 0, v, 97)])
+            .collect()
+    }
+
+    #[test]
+    fn test_commitment_roundtrip() {
+        let (commitment, opening) = commit(1, 3, vec![5, 7]);
+        assert!(opening.verify(&commitment));
+
+        let mut forged = opening.clone();
+        forged.values[0] = 6;
+        assert!(!forged.verify(&commitment));
+    }
+
+    #[test]
+    fn test_detection_probability() {
+        let config = CrossCheckConfig::for_detection_probability(0.99, 10).unwrap();
+        assert!(config.detection_probability(10) >= 0.99 - 1e-9);
+        assert!(CrossCheckConfig::new(0.0).is_err());
+    }
+
+    #[test]
+    fn test_honest_servers_pass() {
+        let checker = CrossChecker::new(CrossCheckConfig::new(1.0).unwrap());
+        let data = outputs(&[1, 2, 3, 4]);
+        let sample = checker.sample_indices(data.len());
+        assert_eq!(sample.len(), 4);
+
+        let first = commit_outputs(1, &data, &sample).unwrap();
+        let second = commit_outputs(2, &data, &sample).unwrap();
+        assert!(checker
+            .verify(&sample, (&first.0, &first.1), (&second.0, &second.1))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_cheating_server_detected() {
+        let checker = CrossChecker::new(CrossCheckConfig::new(1.0).unwrap());
+        let honest = outputs(&[1, 2, 3, 4]);
+        let cheating = outputs(&[1, 2, 9, 4]);
+        let sample = checker.sample_indices(honest.len());
+
+        let first = commit_outputs(1, &honest, &sample).unwrap();
+        let second = commit_outputs(2, &cheating, &sample).unwrap();
+        let err = checker
+            .verify(&sample, (&first.0, &first.1), (&second.0, &second.1))
+            .unwrap_err();
+        assert!(matches!(err, ProtocolError::CheatDetected { index: 2, .. }));
+    }
+
+    #[test]
+    fn test_equivocating_opening_detected() {
+        let checker = CrossChecker::new(CrossCheckConfig::new(1.0).unwrap());
+        let data = outputs(&[1, 2]);
+        let sample = vec![0, 1];
+
+        let first = commit_outputs(1, &data, &sample).unwrap();
+        let (commitments, mut openings) = commit_outputs(2, &data, &sample).unwrap();
+        openings[1].values[0] = 42;
+        let err = checker
+            .verify(&sample, (&first.0, &first.1), (&commitments, &openings))
+            .unwrap_err();
+        assert!(matches!(err, ProtocolError::CheatDetected { index: 1, ref servers } if servers == &vec![2]));
+    }
+}
@@ -1,48 +1,34 @@
+use crate::field::shamir::{self, Reconstructor};
+use crate::field::{FieldError, PrimeField};
 use crate::schema::DataPoint;
 use crate::arith::PrivacyBudget;
+use crate::multi_party::mac::{MacCheck, MacDealer};
+use crate::multi_party::pool::SharePools;
 use crate::multi_party::protocol::ProtocolError;
 use crate::multi_party::share::{DataShare, ShareType};
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use rand::Rng;
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use rand::{CryptoRng, Rng};
+use rayon::prelude::*;
 
-/// Secret share for Shamir's secret sharing
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SecretShare {
-    /// Share ID
-    pub id: usize,
-    /// Share value
-    pub value: u64,
-    /// Share polynomial coefficient
-    pub coefficient: u64,
-    /// Prime modulus
-    pub modulus: u64,
-}
+pub use crate::field::SecretShare;
 
-impl SecretShare {
-    /// Create a new secret share
-    pub fn new(id: usize, value: u64, coefficient: u64, modulus: u64) -> Self {
-        Self {
-            id,
-            value,
-            coefficient,
-            modulus,
-        }
-    }
+/// Prime modulus of the shares of `ThresholdEncryption`, 2^64 - 59
+pub const SHARE_MODULUS: u64 = 0xFFFFFFFFFFFFFFC5;
 
-    /// Evaluate the share at a given point
-    pub fn evaluate(&self, x: u64) -> u64 {
-        let mut result = self.value;
-        let mut power = 1;
-        
-        for _ in 0..self.coefficient {
-            power = (power * x) % self.modulus;
-            result = (result + power) % self.modulus;
-        }
-        
-        result
-    }
-}
+/// Largest message `ThresholdEncryption::decrypt` recovers, 2^40
+///
+/// Bounds the baby-step table of the discrete logarithm to 2^20 points.
+pub const MAX_PLAINTEXT: u64 = 1 << 40;
+
+/// Smallest number of secrets `reconstruct_many` hands to one parallel task
+const RECONSTRUCT_CHUNK: usize = 4096;
 
 /// Shamir's secret sharing implementation
 pub struct ShamirSecretSharing {
@@ -71,6 +57,12 @@ impl ShamirSecretSharing {
             ));
         }
 
+        if PrimeField::new(modulus).is_err() {
+            return Err(ProtocolError::InvalidConfiguration(
+                "Modulus must be at least 2".to_string(),
+            ));
+        }
+
         Ok(Self {
             threshold,
             num_shares,
@@ -80,6 +72,9 @@ impl ShamirSecretSharing {
     }
 
     /// Share a secret value
+    ///
+    /// The secret is the constant term of a random polynomial of degree
+    /// `threshold - 1`; share `i` is its evaluation at `x = i + 1`.
     pub fn share_secret(&self, secret: u64) -> Result<Vec<SecretShare>, ProtocolError> {
         if secret >= self.modulus {
             return Err(ProtocolError::InvalidConfiguration(
@@ -87,19 +82,45 @@ impl ShamirSecretSharing {
             ));
         }
 
-        let mut shares = Vec::new();
-        let mut rng = rand::thread_rng();
+        Ok(shamir::share(
+            &self.field(),
+            secret,
+            self.threshold,
+            self.num_shares,
+            &mut rand::thread_rng(),
+        )?)
+    }
 
-        for i in 0..self.num_shares {
-            let coefficient = rng.gen_range(0..self.modulus);
-            let share = SecretShare::new(i, secret, coefficient, self.modulus);
-            shares.push(share);
+    /// Share a secret value into caller-provided buffers
+    ///
+    /// Same as `share_secret`, but reuses `coefficients` as scratch space and
+    /// writes the shares into `shares`, so sharing many secrets allocates once.
+    pub fn share_secret_into(
+        &self,
+        secret: u64,
+        coefficients: &mut Vec<u64>,
+        shares: &mut Vec<SecretShare>,
+    ) -> Result<(), ProtocolError> {
+        if secret >= self.modulus {
+            return Err(ProtocolError::InvalidConfiguration(
+                "Secret must be less than modulus".to_string(),
+            ));
         }
 
-        Ok(shares)
+        Ok(shamir::share_into(
+            &self.field(),
+            secret,
+            self.threshold,
+            self.num_shares,
+            &mut rand::thread_rng(),
+            coefficients,
+            shares,
+        )?)
     }
 
     /// Reconstruct secret from shares
+    ///
+    /// Any `threshold` shares with distinct IDs suffice; extra shares are ignored.
     pub fn reconstruct_secret(&self, shares: &[SecretShare]) -> Result<u64, ProtocolError> {
         if shares.len() < self.threshold {
             return Err(ProtocolError::InsufficientServers {
@@ -108,95 +129,592 @@ impl ShamirSecretSharing {
             });
         }
 
-        // Use Lagrange interpolation to reconstruct the secret
-        let mut secret = 0u64;
-        let n = shares.len() as u64;
+        Ok(shamir::reconstruct(&self.field(), shares, self.threshold)?)
+    }
 
-        for i in 0..shares.len() {
-            let mut numerator = 1u64;
-            let mut denominator = 1u64;
+    /// Reconstruct many secrets, each from its own shares
+    ///
+    /// The Lagrange coefficients are computed once from the share IDs of the
+    /// first secret and the secrets are reconstructed in parallel chunks.
+    /// Secrets whose shares have other IDs are interpolated on their own.
+    pub fn reconstruct_many(&self, shares: &[Vec<SecretShare>]) -> Result<Vec<u64>, ProtocolError> {
+        let Some(first) = shares.first() else {
+            return Ok(Vec::new());
+        };
+
+        let reconstructor = Reconstructor::for_shares(self.field(), first, self.threshold)?;
+        Ok(shares
+            .par_iter()
+            .with_min_len(RECONSTRUCT_CHUNK)
+            .map(|shares| reconstructor.reconstruct(shares))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
 
-            for j in 0..shares.len() {
-                if i != j {
-                    numerator = (numerator * (n - j as u64)) % self.modulus;
-                    denominator = (denominator * ((i as u64 + 1) - (j as u64 + 1))) % self.modulus;
+    /// Lagrange coefficients for interpolating at zero from the given share IDs
+    pub fn lagrange_coefficients(&self, ids: &[usize]) -> Result<Vec<u64>, ProtocolError> {
+        Ok(shamir::lagrange_coefficients(&self.field(), ids)?)
+    }
+
+    /// The field the shares live in
+    fn field(&self) -> PrimeField {
+        PrimeField::new(self.modulus).expect("ShamirSecretSharing::new checks the modulus")
+    }
+
+    /// Modular addition
+    fn add_mod(&self, a: u64, b: u64) -> u64 {
+        self.field().add(a, b)
+    }
+
+    /// Modular subtraction
+    fn sub_mod(&self, a: u64, b: u64) -> u64 {
+        self.field().sub(a, b)
+    }
+
+    /// Modular multiplication
+    fn mul_mod(&self, a: u64, b: u64) -> u64 {
+        self.field().mul(a, b)
+    }
+
+    /// Modular multiplicative inverse, if one exists
+    fn mod_inverse(&self, a: u64) -> Option<u64> {
+        self.field().inv(a)
+    }
+}
+
+impl From<FieldError> for ProtocolError {
+    fn from(error: FieldError) -> Self {
+        match error {
+            FieldError::InsufficientShares {
+                available,
+                required,
+            } => ProtocolError::InsufficientServers {
+                available,
+                required,
+            },
+            error => ProtocolError::InvalidConfiguration(error.to_string()),
+        }
+    }
+}
+
+/// Secret sharing scheme the servers share data with, see `ProtocolConfig::sharing`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SharingScheme {
+    /// Shamir sharing over a prime field, reconstructed by any `threshold` servers
+    #[default]
+    Shamir,
+    /// Additive sharing modulo 2^64, reconstructed by all servers together
+    Additive,
+}
+
+/// Modulus recorded in the `DataShare`s of `AdditiveSecretSharing`
+///
+/// 0 stands for 2^64, which does not fit in a `u64`, see `DataShare::normalized_value`.
+pub const WRAPPING_MODULUS: u64 = 0;
+
+/// A server's share of a secret under `AdditiveSecretSharing`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdditiveShare {
+    /// Share ID, the index of the server holding it
+    pub id: usize,
+    /// Share value
+    pub value: u64,
+}
+
+impl AdditiveShare {
+    /// Create a new additive share
+    pub fn new(id: usize, value: u64) -> Self {
+        Self { id, value }
+    }
+}
+
+/// Additive secret sharing with wrapping arithmetic modulo 2^64
+///
+/// The shares of a secret sum to it modulo 2^64, and all `num_shares` of them
+/// are needed to reconstruct it. Without a prime field there is no
+/// interpolation and no modular reduction, so sums and histograms, which
+/// only add shares and scale them by public constants, are cheaper than
+/// under Shamir. Secrets and their sums wrap around modulo 2^64.
+pub struct AdditiveSecretSharing {
+    /// Number of shares
+    pub num_shares: usize,
+}
+
+impl AdditiveSecretSharing {
+    /// Create a new additive secret sharing scheme
+    pub fn new(num_shares: usize) -> Result<Self, ProtocolError> {
+        if num_shares < 2 {
+            return Err(ProtocolError::InvalidConfiguration(
+                "Additive sharing needs at least 2 shares".to_string(),
+            ));
+        }
+
+        Ok(Self { num_shares })
+    }
+
+    /// Share a secret value
+    ///
+    /// All shares but the last are uniformly random, and the last makes the sum the secret.
+    pub fn share_secret(&self, secret: u64) -> Vec<AdditiveShare> {
+        let mut rng = rand::thread_rng();
+        let mut last = secret;
+        let mut shares: Vec<AdditiveShare> = (0..self.num_shares - 1)
+            .map(|id| {
+                let value: u64 = rng.gen();
+                last = last.wrapping_sub(value);
+                AdditiveShare::new(id, value)
+            })
+            .collect();
+        shares.push(AdditiveShare::new(self.num_shares - 1, last));
+        shares
+    }
+
+    /// Reconstruct secret from the shares of all servers
+    pub fn reconstruct_secret(&self, shares: &[AdditiveShare]) -> Result<u64, ProtocolError> {
+        let mut seen = vec![false; self.num_shares];
+        for share in shares {
+            match seen.get_mut(share.id) {
+                Some(seen) if !*seen => *seen = true,
+                Some(_) => return Err(FieldError::DuplicateShareId(share.id).into()),
+                None => {
+                    return Err(ProtocolError::InvalidConfiguration(format!(
+                        "Share ID {} out of range for {} shares",
+                        share.id, self.num_shares
+                    )))
                 }
             }
+        }
+        if shares.len() < self.num_shares {
+            return Err(ProtocolError::InsufficientServers {
+                available: shares.len(),
+                required: self.num_shares,
+            });
+        }
 
-            let lagrange_coeff = (numerator * self.mod_inverse(denominator)) % self.modulus;
-            secret = (secret + (shares[i].value * lagrange_coeff) % self.modulus) % self.modulus;
+        Ok(shares.iter().fold(0u64, |sum, share| sum.wrapping_add(share.value)))
+    }
+
+    /// Add two shares with the same ID, giving a share of the sum of their secrets
+    pub fn add(&self, a: &AdditiveShare, b: &AdditiveShare) -> Result<AdditiveShare, ProtocolError> {
+        if a.id != b.id {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Shares {} and {} cannot be added",
+                a.id, b.id
+            )));
         }
 
-        Ok(secret)
+        Ok(AdditiveShare::new(a.id, a.value.wrapping_add(b.value)))
     }
 
-    /// Modular multiplicative inverse
-    fn mod_inverse(&self, a: u64) -> u64 {
-        let mut t = 0u64;
-        let mut new_t = 1u64;
-        let mut r = self.modulus;
-        let mut new_r = a;
+    /// Multiply a share by a public scalar, giving a share of the scaled secret
+    pub fn multiply_scalar(&self, share: &AdditiveShare, scalar: u64) -> AdditiveShare {
+        AdditiveShare::new(share.id, share.value.wrapping_mul(scalar))
+    }
+}
 
-        while new_r != 0 {
-            let quotient = r / new_r;
-            let temp_t = t;
-            t = new_t;
-            new_t = temp_t - quotient * new_t;
-            let temp_r = r;
-            r = new_r;
-            new_r = temp_r - quotient * new_r;
+/// Number of servers of a replicated secret sharing
+pub const REPLICATED_SERVERS: usize = 3;
+
+/// A server's share of a secret under replicated (2,3) secret sharing
+///
+/// The secret is split into three additive components `x₀ + x₁ + x₂`, and
+/// server `i` holds `xᵢ` and `xᵢ₊₁`, indices mod 3. Any two servers together
+/// hold every component, and each component is held by two servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicatedShare {
+    /// Server ID that holds this share, from 0 to 2
+    pub server_id: usize,
+    /// Component `xᵢ`
+    pub first: u64,
+    /// Component `xᵢ₊₁`
+    pub second: u64,
+}
+
+/// Replicated secret sharing among three servers, secure against one corrupted server
+///
+/// Much cheaper than Shamir for the three-server topology: addition is
+/// local, and multiplication costs each server one field element sent to one
+/// other server, with no interpolation.
+pub struct ReplicatedSecretSharing {
+    /// Prime modulus
+    pub modulus: u64,
+}
+
+impl ReplicatedSecretSharing {
+    /// Create a new replicated secret sharing scheme
+    pub fn new(modulus: u64) -> Result<Self, ProtocolError> {
+        if PrimeField::new(modulus).is_err() || modulus <= REPLICATED_SERVERS as u64 {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Modulus must exceed {}",
+                REPLICATED_SERVERS
+            )));
         }
 
-        if r > 1 {
-            return 0; // No inverse exists
+        Ok(Self { modulus })
+    }
+
+    /// Share a secret value, returning the share of server `i` at index `i`
+    pub fn share_secret(&self, secret: u64) -> Result<Vec<ReplicatedShare>, ProtocolError> {
+        let field = self.field();
+        let secret = field.check(secret)?;
+        let mut rng = rand::thread_rng();
+        let first = field.random(&mut rng);
+        let second = field.random(&mut rng);
+        let components = [first, second, field.sub(field.sub(secret, first), second)];
+
+        Ok((0..REPLICATED_SERVERS)
+            .map(|server_id| ReplicatedShare {
+                server_id,
+                first: components[server_id],
+                second: components[(server_id + 1) % REPLICATED_SERVERS],
+            })
+            .collect())
+    }
+
+    /// Reconstruct the secret from the shares of at least two servers
+    ///
+    /// A component held by two servers must be the same in both shares, so a
+    /// single corrupted server is caught with `CheatDetected`, naming the
+    /// component and the two servers that disagree on it.
+    pub fn reconstruct_secret(&self, shares: &[ReplicatedShare]) -> Result<u64, ProtocolError> {
+        let mut components: [Option<(usize, u64)>; REPLICATED_SERVERS] = [None; REPLICATED_SERVERS];
+        for share in shares {
+            self.check_share(share)?;
+            let held = [
+                (share.server_id, share.first),
+                ((share.server_id + 1) % REPLICATED_SERVERS, share.second),
+            ];
+            for (index, value) in held {
+                match components[index] {
+                    Some((holder, known)) if known != value => {
+                        return Err(ProtocolError::cheat_detected(index, vec![holder, share.server_id]));
+                    }
+                    Some(_) => {}
+                    None => components[index] = Some((share.server_id, value)),
+                }
+            }
         }
 
-        if t < 0 {
-            t += self.modulus;
+        let field = self.field();
+        components.iter().try_fold(0, |sum, component| match component {
+            Some((_, value)) => Ok(field.add(sum, *value)),
+            None => Err(ProtocolError::InsufficientServers {
+                available: shares.len(),
+                required: 2,
+            }),
+        })
+    }
+
+    /// Add two shares held by the same server, locally
+    pub fn add(&self, a: &ReplicatedShare, b: &ReplicatedShare) -> Result<ReplicatedShare, ProtocolError> {
+        self.check_pair(a, b)?;
+        let field = self.field();
+        Ok(ReplicatedShare {
+            server_id: a.server_id,
+            first: field.add(a.first, b.first),
+            second: field.add(a.second, b.second),
+        })
+    }
+
+    /// First step of a multiplication: this server's additive share of `a · b`
+    ///
+    /// `zero` is the server's next share of zero from its `ZeroSharing`,
+    /// which hides the result from the server it is sent to. The result goes
+    /// to the previous server, `server_id + 2 mod 3`, and `reshare` completes
+    /// the product with the value received from the next server.
+    pub fn multiply_local(&self, a: &ReplicatedShare, b: &ReplicatedShare, zero: u64) -> Result<u64, ProtocolError> {
+        self.check_pair(a, b)?;
+        let field = self.field();
+        let cross = field.add(field.mul(a.first, b.second), field.mul(a.second, b.first));
+        Ok(field.add(field.add(field.mul(a.first, b.first), cross), zero))
+    }
+
+    /// Turn this server's additive share `own` back into a replicated share
+    ///
+    /// `next` is the additive share the next server, `server_id + 1 mod 3`,
+    /// sent after `multiply_local` or `from_data_share`.
+    pub fn reshare(&self, server_id: usize, own: u64, next: u64) -> Result<ReplicatedShare, ProtocolError> {
+        let share = ReplicatedShare {
+            server_id,
+            first: own,
+            second: next,
+        };
+        self.check_share(&share)?;
+        Ok(share)
+    }
+
+    /// Convert a share into a Shamir share of threshold 2, locally
+    ///
+    /// The result is a `DataShare` of the feature at `feature_index`, which
+    /// `ShamirSecretSharing` and `ThresholdEncryption` reconstruct together
+    /// with the converted share of any other server.
+    pub fn to_data_share(&self, share: &ReplicatedShare, feature_index: usize) -> Result<DataShare, ProtocolError> {
+        self.check_share(share)?;
+        // Component k times the line through (0, 1) and (x, 0), where x is the
+        // evaluation point of server k + 1, the one server without it
+        let field = self.field();
+        let x = shamir::evaluation_point(&field, share.server_id);
+        let weight = |component: usize| {
+            let missing = shamir::evaluation_point(&field, (component + 1) % REPLICATED_SERVERS);
+            let inverse = field.inv(missing).expect("evaluation points are nonzero");
+            field.sub(1, field.mul(x, inverse))
+        };
+        let next = (share.server_id + 1) % REPLICATED_SERVERS;
+        let value = field.add(
+            field.mul(share.first, weight(share.server_id)),
+            field.mul(share.second, weight(next)),
+        );
+
+        Ok(DataShare::feature(share.server_id, feature_index, value, self.modulus))
+    }
+
+    /// First step of converting a Shamir share of threshold 2 to a replicated share
+    ///
+    /// Returns the server's additive share of the secret, masked with its
+    /// share of zero `zero`, to send to the previous server and complete
+    /// with `reshare` like a multiplication. All three servers take part.
+    pub fn from_data_share(&self, share: &DataShare, zero: u64) -> Result<u64, ProtocolError> {
+        if share.server_id >= REPLICATED_SERVERS || share.modulus != self.modulus {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Share of server {} modulo {} is not a share of this scheme",
+                share.server_id, share.modulus
+            )));
+        }
+
+        let field = self.field();
+        let coefficients = shamir::lagrange_coefficients(&field, &[0, 1, 2])?;
+        let value = field.check(share.value)?;
+        Ok(field.add(field.mul(coefficients[share.server_id], value), zero))
+    }
+
+    /// Check that a share belongs to one of the three servers and lies in the field
+    fn check_share(&self, share: &ReplicatedShare) -> Result<(), ProtocolError> {
+        if share.server_id >= REPLICATED_SERVERS {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Replicated shares are held by servers 0 to {}, not {}",
+                REPLICATED_SERVERS - 1,
+                share.server_id
+            )));
+        }
+        let field = self.field();
+        field.check(share.first)?;
+        field.check(share.second)?;
+        Ok(())
+    }
+
+    /// Check that two shares are valid and held by the same server
+    fn check_pair(&self, a: &ReplicatedShare, b: &ReplicatedShare) -> Result<(), ProtocolError> {
+        self.check_share(a)?;
+        self.check_share(b)?;
+        if a.server_id != b.server_id {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Shares of servers {} and {} cannot be combined locally",
+                a.server_id, b.server_id
+            )));
         }
+        Ok(())
+    }
+
+    /// The field the shares live in
+    fn field(&self) -> PrimeField {
+        PrimeField::new(self.modulus).expect("ReplicatedSecretSharing::new checks the modulus")
+    }
+}
+
+/// Shares of zero for a server of a replicated sharing, derived without interaction
+///
+/// Every server draws a key with `generate_key` and sends it to the previous
+/// server once. Server `i` then derives its `n`-th share of zero as
+/// `F(kᵢ, n) - F(kᵢ₊₁, n)` with HMAC-SHA256 as `F`, so the shares of the three
+/// servers sum to zero while each looks random to the others.
+pub struct ZeroSharing {
+    /// Key of this server
+    own_key: [u8; 32],
+    /// Key of the next server
+    next_key: [u8; 32],
+    /// Shares derived so far
+    counter: u64,
+    /// Field the shares live in
+    field: PrimeField,
+}
 
-        t
+impl ZeroSharing {
+    /// Derive shares of zero from this server's key and the one the next server sent
+    pub fn new(own_key: [u8; 32], next_key: [u8; 32], modulus: u64) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            own_key,
+            next_key,
+            counter: 0,
+            field: PrimeField::new(modulus)?,
+        })
+    }
+
+    /// Draw a fresh key for this server
+    pub fn generate_key() -> [u8; 32] {
+        rand::thread_rng().gen()
+    }
+
+    /// The next share of zero, in step with the other servers
+    pub fn next_share(&mut self) -> u64 {
+        let counter = self.counter;
+        self.counter += 1;
+        self.field.sub(self.derive(&self.own_key, counter), self.derive(&self.next_key, counter))
     }
+
+    /// Pseudorandom field element for `counter` under `key`
+    fn derive(&self, key: &[u8; 32], counter: u64) -> u64 {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any size");
+        mac.update(&counter.to_le_bytes());
+        let bytes = mac.finalize().into_bytes();
+        // 128 bits reduced modulo a 64-bit prime are close enough to uniform
+        let wide = u128::from_le_bytes(bytes[..16].try_into().expect("SHA-256 output has 32 bytes"));
+        (wide % self.field.modulus() as u128) as u64
+    }
+}
+
+/// ElGamal ciphertext `(r·G, m·G + r·Y)` of a message `m` in the exponent
+///
+/// Ciphertexts under one key combine with `ThresholdEncryption::add` into a
+/// ciphertext of the sum of their messages, and `rerandomize` turns one into
+/// a ciphertext of the same message that cannot be linked to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "[[u8; 32]; 2]", try_from = "[[u8; 32]; 2]")]
+pub struct ElGamalCiphertext {
+    c1: RistrettoPoint,
+    c2: RistrettoPoint,
+}
+
+impl From<ElGamalCiphertext> for [[u8; 32]; 2] {
+    fn from(ciphertext: ElGamalCiphertext) -> Self {
+        [ciphertext.c1.compress().to_bytes(), ciphertext.c2.compress().to_bytes()]
+    }
+}
+
+impl TryFrom<[[u8; 32]; 2]> for ElGamalCiphertext {
+    type Error = ProtocolError;
+
+    fn try_from([c1, c2]: [[u8; 32]; 2]) -> Result<Self, Self::Error> {
+        Ok(Self {
+            c1: decompress(&c1)?,
+            c2: decompress(&c2)?,
+        })
+    }
+}
+
+/// A server's share `x_i·c1` of the decryption of an `ElGamalCiphertext`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "(usize, [u8; 32])", try_from = "(usize, [u8; 32])")]
+pub struct DecryptionShare {
+    /// Server whose key share produced it
+    pub server_id: usize,
+    value: RistrettoPoint,
+}
+
+impl From<DecryptionShare> for (usize, [u8; 32]) {
+    fn from(share: DecryptionShare) -> Self {
+        (share.server_id, share.value.compress().to_bytes())
+    }
+}
+
+impl TryFrom<(usize, [u8; 32])> for DecryptionShare {
+    type Error = ProtocolError;
+
+    fn try_from((server_id, value): (usize, [u8; 32])) -> Result<Self, Self::Error> {
+        Ok(Self {
+            server_id,
+            value: decompress(&value)?,
+        })
+    }
+}
+
+/// Decode a point of the Ristretto group; every valid encoding is a group element
+fn decompress(bytes: &[u8; 32]) -> Result<RistrettoPoint, ProtocolError> {
+    CompressedRistretto(*bytes)
+        .decompress()
+        .ok_or_else(|| ProtocolError::MalformedMessage("Invalid Ristretto point in ElGamal message".to_string()))
 }
 
 /// Threshold encryption implementation
+///
+/// ElGamal over the Ristretto group, whose order is a 252-bit prime. The
+/// private key is Shamir-shared among the servers over the scalars of the
+/// group, so any `threshold` decryption shares combine by Lagrange
+/// interpolation in the exponent. Messages are encrypted in the exponent,
+/// which makes ciphertexts additively homomorphic, and decryption takes a
+/// discrete logarithm, so it only recovers messages up to `MAX_PLAINTEXT`.
+/// Decryption shares carry no proof, so a server that sends a wrong one makes
+/// decryption fail or give a wrong value.
 pub struct ThresholdEncryption {
     /// Shamir secret sharing scheme
     pub shamir: ShamirSecretSharing,
-    /// Public key
-    pub public_key: u64,
-    /// Private key shares
-    pub private_key_shares: Vec<u64>,
+    /// Additive secret sharing among all servers
+    pub additive: AdditiveSecretSharing,
+    /// Scheme data is shared with
+    pub scheme: SharingScheme,
+    /// Public key `x·G`
+    pub public_key: RistrettoPoint,
+    /// Shamir shares of the private key `x` over the scalars of the group
+    pub private_key_shares: Vec<Scalar>,
+    /// Whether shares carry SPDZ-style MACs, checked on reconstruction
+    pub authenticated: bool,
+    /// Dealer of the MAC key, set up by `initialize` for authenticated shares
+    pub mac_dealer: Option<MacDealer>,
     /// Initialized flag
     pub initialized: bool,
+    /// Buffer pools for sharing
+    pub pools: Arc<SharePools>,
 }
 
 impl ThresholdEncryption {
     /// Create a new threshold encryption scheme
     pub fn new(threshold: usize, num_servers: usize) -> Result<Self, ProtocolError> {
-        let modulus = 0xFFFFFFFFFFFFFFC5; // 2^64 - 59
-        let shamir = ShamirSecretSharing::new(threshold, num_servers, modulus)?;
+        let shamir = ShamirSecretSharing::new(threshold, num_servers, SHARE_MODULUS)?;
+        let additive = AdditiveSecretSharing::new(num_servers)?;
 
         Ok(Self {
             shamir,
-            public_key: 0,
+            additive,
+            scheme: SharingScheme::Shamir,
+            public_key: RistrettoPoint::default(),
             private_key_shares: Vec::new(),
+            authenticated: false,
+            mac_dealer: None,
             initialized: false,
+            pools: Arc::new(SharePools::disabled()),
         })
     }
 
+    /// Share data with `scheme` instead of Shamir
+    pub fn with_scheme(mut self, scheme: SharingScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Attach MACs to shares and check them before returning reconstructed data
+    pub fn with_authentication(mut self, authenticated: bool) -> Self {
+        self.authenticated = authenticated;
+        self
+    }
+
+    /// Share buffers through the given pools, e.g. ones shared by all dealers
+    pub fn with_pools(mut self, pools: Arc<SharePools>) -> Self {
+        self.pools = pools;
+        self
+    }
+
     /// Initialize the threshold encryption scheme
     pub async fn initialize(&mut self) -> Result<(), ProtocolError> {
-        // Generate a random private key
+        // Generate a random private key and share it over the scalars
         let mut rng = rand::thread_rng();
-        let private_key = rng.gen_range(1..self.shamir.modulus);
+        let private_key = random_scalar(&mut rng);
+        self.private_key_shares = share_scalar(private_key, self.shamir.threshold, self.shamir.num_shares, &mut rng);
 
-        // Share the private key
-        let shares = self.shamir.share_secret(private_key)?;
-        self.private_key_shares = shares.iter().map(|s| s.value).collect();
+        // Compute public key x·G
+        self.public_key = RistrettoPoint::mul_base(&private_key);
 
-        // Compute public key (g^private_key mod p)
-        self.public_key = self.modular_exponentiation(self.shamir.generator, private_key);
+        if self.authenticated {
+            self.mac_dealer = Some(MacDealer::new(self.shamir.threshold, self.shamir.num_shares, self.scheme)?);
+        }
 
         self.initialized = true;
         Ok(())
@@ -210,13 +728,31 @@ impl ThresholdEncryption {
             });
         }
 
-        let mut shares = Vec::new();
+        let features = data.features();
+        if self.scheme == SharingScheme::Additive {
+            let mut shares = features
+                .iter()
+                .enumerate()
+                .flat_map(|(i, &feature)| {
+                    self.additive.share_secret(feature as u64).into_iter().map(move |share| {
+                        DataShare::new(share.id, i, ShareType::Feature, share.value, WRAPPING_MODULUS)
+                    })
+                })
+                .collect::<Vec<_>>();
+            self.authenticate(features, &mut shares)?;
+            return Ok(shares);
+        }
 
-        // Share each feature
-        for (i, &feature) in data.features().iter().enumerate() {
+        let mut shares = Vec::with_capacity(features.len() * self.shamir.num_shares);
+
+        // Share each feature, reusing the scratch buffers across features
+        let mut coefficients = self.pools.field_elements.take();
+        let mut feature_shares = self.pools.secret_shares.take();
+        let result = features.iter().enumerate().try_for_each(|(i, &feature)| {
             let feature_u64 = feature as u64;
-            let feature_shares = self.shamir.share_secret(feature_u64)?;
-            
+            self.shamir
+                .share_secret_into(feature_u64, &mut coefficients, &mut feature_shares)?;
+
             for (j, share) in feature_shares.iter().enumerate() {
                 let data_share = DataShare::new(
                     j,
@@ -227,11 +763,26 @@ impl ThresholdEncryption {
                 );
                 shares.push(data_share);
             }
-        }
+            Ok::<_, ProtocolError>(())
+        });
+        self.pools.field_elements.give(coefficients);
+        self.pools.secret_shares.give(feature_shares);
 
+        result?;
+        self.authenticate(features, &mut shares)?;
         Ok(shares)
     }
 
+    /// Attach MACs to the shares of `features`, `num_shares` per feature in order
+    fn authenticate(&self, features: &[f64], shares: &mut [DataShare]) -> Result<(), ProtocolError> {
+        if let Some(dealer) = &self.mac_dealer {
+            for (&feature, feature_shares) in features.iter().zip(shares.chunks_mut(self.shamir.num_shares)) {
+                dealer.authenticate(feature as u64, feature_shares)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Reconstruct data from shares
     pub async fn reconstruct_data(&self, shares: Vec<DataShare>) -> Result<DataPoint, ProtocolError> {
         if !self.initialized {
@@ -240,36 +791,135 @@ impl ThresholdEncryption {
             });
         }
 
-        // Group shares by feature index
-        let mut feature_shares: HashMap<usize, Vec<u64>> = HashMap::new();
+        if self.mac_dealer.is_some() {
+            let mut points = self.reconstruct_data_many(vec![shares]).await?;
+            return Ok(points.remove(0));
+        }
 
-        for share in shares {
-            if let ShareType::Feature = share.share_type {
-                feature_shares.entry(share.feature_index).or_insert_with(Vec::new).push(share.value);
+        let features = self
+            .group_feature_shares(shares)
+            .into_iter()
+            .map(|secret_shares| match secret_shares {
+                Some(secret_shares) => Ok(self.reconstruct_secret(&secret_shares)? as f64),
+                None => Ok(0.0),
+            })
+            .collect::<Result<Vec<_>, ProtocolError>>()?;
+
+        Ok(DataPoint::new(features))
+    }
+
+    /// Reconstruct many data points, each from its own shares
+    ///
+    /// All features of all points are reconstructed in one batch, with
+    /// `ShamirSecretSharing::reconstruct_many` for Shamir shares. With
+    /// authenticated shares, every reconstructed feature is MAC-checked
+    /// against its shares first, and a mismatch fails with `CheatDetected`.
+    pub async fn reconstruct_data_many(&self, shares: Vec<Vec<DataShare>>) -> Result<Vec<DataPoint>, ProtocolError> {
+        if !self.initialized {
+            return Err(ProtocolError::InternalError {
+                message: "Threshold encryption not initialized".to_string(),
+            });
+        }
+
+        // Keep the authenticated shares of every feature, in batch order, for the MAC check
+        let authenticated: Vec<Vec<DataShare>> = match self.mac_dealer {
+            Some(_) => shares.iter().flat_map(|point_shares| Self::feature_shares(point_shares)).collect(),
+            None => Vec::new(),
+        };
+
+        // Move the shares of every feature into one batch, remembering which
+        // features of each point have shares
+        let mut batch = Vec::new();
+        let layouts: Vec<Vec<bool>> = shares
+            .into_iter()
+            .map(|point_shares| {
+                self.group_feature_shares(point_shares)
+                    .into_iter()
+                    .map(|secret_shares| {
+                        let present = secret_shares.is_some();
+                        batch.extend(secret_shares);
+                        present
+                    })
+                    .collect()
+            })
+            .collect();
+        let secrets = match self.scheme {
+            SharingScheme::Shamir => self.shamir.reconstruct_many(&batch)?,
+            SharingScheme::Additive => batch
+                .iter()
+                .map(|secret_shares| self.reconstruct_secret(secret_shares))
+                .collect::<Result<_, _>>()?,
+        };
+        if let Some(dealer) = &self.mac_dealer {
+            let check = MacCheck::new(self.shamir.threshold, self.shamir.num_shares, self.scheme)?;
+            for (round, (feature_shares, &secret)) in authenticated.iter().zip(&secrets).enumerate() {
+                check.check(round, dealer.key_shares(), feature_shares, secret)?;
             }
         }
+        let mut secrets = secrets.into_iter();
+
+        Ok(layouts
+            .iter()
+            .map(|layout| {
+                let features = layout
+                    .iter()
+                    .map(|&present| {
+                        if present {
+                            secrets.next().expect("one secret per feature") as f64
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect();
+                DataPoint::new(features)
+            })
+            .collect())
+    }
 
-        // Reconstruct each feature
-        let mut features = Vec::new();
-        let num_features = feature_shares.keys().max().unwrap_or(&0) + 1;
+    /// Reconstruct one secret with the scheme data is shared with
+    fn reconstruct_secret(&self, shares: &[SecretShare]) -> Result<u64, ProtocolError> {
+        match self.scheme {
+            SharingScheme::Shamir => self.shamir.reconstruct_secret(shares),
+            SharingScheme::Additive => {
+                let shares: Vec<AdditiveShare> = shares
+                    .iter()
+                    .map(|share| AdditiveShare::new(share.id, share.value))
+                    .collect();
+                self.additive.reconstruct_secret(&shares)
+            }
+        }
+    }
 
-        for i in 0..num_features {
-            if let Some(share_values) = feature_shares.get(&i) {
-                // Convert back to SecretShare format for reconstruction
-                let mut secret_shares = Vec::new();
-                for (j, &value) in share_values.iter().enumerate() {
-                    let share = SecretShare::new(j, value, 0, self.shamir.modulus);
-                    secret_shares.push(share);
-                }
+    /// The feature shares of a data point by feature index, skipping features without shares
+    fn feature_shares(shares: &[DataShare]) -> Vec<Vec<DataShare>> {
+        let mut features: BTreeMap<usize, Vec<DataShare>> = BTreeMap::new();
+        for share in shares.iter().filter(|share| share.is_feature()) {
+            features.entry(share.feature_index).or_default().push(share.clone());
+        }
+        features.into_values().collect()
+    }
 
-                let reconstructed_value = self.shamir.reconstruct_secret(&secret_shares)?;
-                features.push(reconstructed_value as f64);
-            } else {
-                features.push(0.0);
+    /// Group the feature shares of a data point by feature index
+    ///
+    /// Features without shares are `None`.
+    fn group_feature_shares(&self, shares: Vec<DataShare>) -> Vec<Option<Vec<SecretShare>>> {
+        let num_features = shares
+            .iter()
+            .filter(|share| matches!(share.share_type, ShareType::Feature))
+            .map(|share| share.feature_index)
+            .max()
+            .unwrap_or(0)
+            + 1;
+
+        let mut feature_shares = vec![None; num_features];
+        for share in shares {
+            if let ShareType::Feature = share.share_type {
+                feature_shares[share.feature_index]
+                    .get_or_insert_with(Vec::new)
+                    .push(SecretShare::new(share.server_id, share.value, 0, self.shamir.modulus));
             }
         }
-
-        Ok(DataPoint::new(features))
+        feature_shares
     }
 
     /// Generate noise for differential privacy
@@ -292,76 +942,111 @@ impl ThresholdEncryption {
         Ok(noise)
     }
 
-    /// Encrypt a value using threshold encryption
-    pub async fn encrypt(&self, value: u64) -> Result<u64, ProtocolError> {
+    /// Encrypt a value in the exponent under the shared public key
+    pub async fn encrypt(&self, value: u64) -> Result<ElGamalCiphertext, ProtocolError> {
         if !self.initialized {
             return Err(ProtocolError::InternalError {
                 message: "Threshold encryption not initialized".to_string(),
             });
         }
 
-        // Simple ElGamal encryption: (g^r, m * y^r)
-        let mut rng = rand::thread_rng();
-        let r = rng.gen_range(1..self.shamir.modulus);
-        
-        let c1 = self.modular_exponentiation(self.shamir.generator, r);
-        let c2 = (value * self.modular_exponentiation(self.public_key, r)) % self.shamir.modulus;
-        
-        Ok(c2) // Return only the second component for simplicity
+        // ElGamal encryption: (r·G, m·G + r·Y)
+        let r = random_scalar(&mut rand::thread_rng());
+        Ok(ElGamalCiphertext {
+            c1: RistrettoPoint::mul_base(&r),
+            c2: RistrettoPoint::mul_base(&Scalar::from(value)) + r * self.public_key,
+        })
+    }
+
+    /// Ciphertext of the sum of the messages of two ciphertexts
+    pub fn add(&self, a: &ElGamalCiphertext, b: &ElGamalCiphertext) -> ElGamalCiphertext {
+        ElGamalCiphertext {
+            c1: a.c1 + b.c1,
+            c2: a.c2 + b.c2,
+        }
+    }
+
+    /// Ciphertext of the same message with fresh randomness, unlinkable to the original
+    pub fn rerandomize(&self, ciphertext: &ElGamalCiphertext) -> ElGamalCiphertext {
+        let s = random_scalar(&mut rand::thread_rng());
+        ElGamalCiphertext {
+            c1: ciphertext.c1 + RistrettoPoint::mul_base(&s),
+            c2: ciphertext.c2 + s * self.public_key,
+        }
+    }
+
+    /// Share of the decryption of a ciphertext, computed with the key share of `server_id`
+    pub async fn partial_decrypt(
+        &self,
+        server_id: usize,
+        ciphertext: &ElGamalCiphertext,
+    ) -> Result<DecryptionShare, ProtocolError> {
+        if !self.initialized {
+            return Err(ProtocolError::InternalError {
+                message: "Threshold encryption not initialized".to_string(),
+            });
+        }
+
+        let key_share = self.private_key_shares.get(server_id).ok_or_else(|| {
+            ProtocolError::InvalidConfiguration(format!("No key share for server {}", server_id))
+        })?;
+
+        Ok(DecryptionShare {
+            server_id,
+            value: key_share * ciphertext.c1,
+        })
     }
 
-    /// Decrypt a value using threshold decryption
-    pub async fn decrypt(&self, encrypted_value: u64, shares: &[u64]) -> Result<u64, ProtocolError> {
+    /// Decrypt a value from the decryption shares of at least `threshold` servers
+    ///
+    /// The message must be at most `max_plaintext`, itself at most
+    /// `MAX_PLAINTEXT`; finding it takes time and memory proportional to the
+    /// square root of the bound.
+    pub async fn decrypt(
+        &self,
+        ciphertext: &ElGamalCiphertext,
+        shares: &[DecryptionShare],
+        max_plaintext: u64,
+    ) -> Result<u64, ProtocolError> {
         if !self.initialized {
             return Err(ProtocolError::InternalError {
                 message: "Threshold encryption not initialized".to_string(),
             });
         }
 
+        if max_plaintext > MAX_PLAINTEXT {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Decryption searches messages up to {}, not {}",
+                MAX_PLAINTEXT, max_plaintext
+            )));
+        }
         if shares.len() < self.shamir.threshold {
             return Err(ProtocolError::InsufficientServers {
                 available: shares.len(),
                 required: self.shamir.threshold,
             });
         }
-
-        // Threshold decryption using Lagrange interpolation
-        let mut decryption_share = 0u64;
-        let n = shares.len() as u64;
-
-        for i in 0..shares.len() {
-            let mut numerator = 1u64;
-            let mut denominator = 1u64;
-
-            for j in 0..shares.len() {
-                if i != j {
-                    numerator = (numerator * (n - j as u64)) % self.shamir.modulus;
-                    denominator = (denominator * ((i as u64 + 1) - (j as u64 + 1))) % self.shamir.modulus;
-                }
-            }
-
-            let lagrange_coeff = (numerator * self.shamir.mod_inverse(denominator)) % self.shamir.modulus;
-            decryption_share = (decryption_share + (shares[i] * lagrange_coeff) % self.shamir.modulus) % self.shamir.modulus;
+        if let Some(share) = shares.iter().find(|share| share.server_id >= self.shamir.num_shares) {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "Decryption share of unknown server {}",
+                share.server_id
+            )));
         }
 
-        let decrypted_value = (encrypted_value * self.modular_exponentiation(decryption_share, -1)) % self.shamir.modulus;
-        Ok(decrypted_value)
-    }
-
-    /// Modular exponentiation (g^e mod p)
-    fn modular_exponentiation(&self, mut base: u64, mut exponent: u64) -> u64 {
-        let mut result = 1u64;
-        base = base % self.shamir.modulus;
-
-        while exponent > 0 {
-            if exponent % 2 == 1 {
-                result = (result * base) % self.shamir.modulus;
+        // Threshold decryption using Lagrange interpolation of x·c1 in the exponent
+        let ids: Vec<usize> = shares.iter().map(|share| share.server_id).collect();
+        let coefficients = scalar_lagrange_coefficients(&ids)?;
+        let mask: RistrettoPoint = shares
+            .iter()
+            .zip(coefficients)
+            .map(|(share, coeff)| coeff * share.value)
+            .sum();
+
+        discrete_log(&(ciphertext.c2 - mask), max_plaintext).ok_or_else(|| {
+            ProtocolError::InternalError {
+                message: format!("Decrypted message exceeds {} or a decryption share is wrong", max_plaintext),
             }
-            exponent = exponent >> 1;
-            base = (base * base) % self.shamir.modulus;
-        }
-
-        result
+        })
     }
 
     /// Get threshold
@@ -380,86 +1065,72 @@ impl ThresholdEncryption {
     }
 }
 
-/// Homomorphic encryption for secure computation
-pub struct HomomorphicEncryption {
-    /// Public key
-    pub public_key: u64,
-    /// Private key
-    pub private_key: u64,
-    /// Modulus
-    pub modulus: u64,
-}
-
-impl HomomorphicEncryption {
-    /// Create a new homomorphic encryption scheme
-    pub fn new() -> Self {
-        let modulus = 0xFFFFFFFFFFFFFFC5;
-        let mut rng = rand::thread_rng();
-        let private_key = rng.gen_range(1..modulus);
-        let public_key = 5u64.pow(private_key as u32) % modulus;
-
-        Self {
-            public_key,
-            private_key,
-            modulus,
-        }
-    }
-
-    /// Encrypt a value
-    pub fn encrypt(&self, value: u64) -> u64 {
-        let mut rng = rand::thread_rng();
-        let r = rng.gen_range(1..self.modulus);
-        
-        let c1 = 5u64.pow(r as u32) % self.modulus;
-        let c2 = (value * self.public_key.pow(r as u32)) % self.modulus;
-        
-        c2 // Return only the second component for simplicity
-    }
-
-    /// Decrypt a value
-    pub fn decrypt(&self, encrypted_value: u64) -> u64 {
-        let decryption_key = self.public_key.pow(self.private_key as u32) % self.modulus;
-        let inverse = self.modular_inverse(decryption_key);
-        (encrypted_value * inverse) % self.modulus
-    }
-
-    /// Add two encrypted values
-    pub fn add(&self, a: u64, b: u64) -> u64 {
-        (a * b) % self.modulus
+/// Find `m <= max` with `m·G = target` by baby-step giant-step
+///
+/// `max` is at most `MAX_PLAINTEXT`, so the table holds at most 2^20 points.
+fn discrete_log(target: &RistrettoPoint, max: u64) -> Option<u64> {
+    let step = max.isqrt() + 1;
+    let mut baby_steps = HashMap::with_capacity(step as usize);
+    let mut power = RistrettoPoint::default();
+    for j in 0..step {
+        baby_steps.entry(power.compress()).or_insert(j);
+        power += RISTRETTO_BASEPOINT_POINT;
     }
 
-    /// Multiply encrypted value by plaintext
-    pub fn multiply(&self, encrypted: u64, plaintext: u64) -> u64 {
-        encrypted.pow(plaintext as u32) % self.modulus
+    let giant_step = -RistrettoPoint::mul_base(&Scalar::from(step));
+    let mut gamma = *target;
+    for i in 0..=max / step {
+        if let Some(&j) = baby_steps.get(&gamma.compress()) {
+            return Some(i * step + j).filter(|&m| m <= max);
+        }
+        gamma += giant_step;
     }
 
-    /// Modular multiplicative inverse
-    fn modular_inverse(&self, a: u64) -> u64 {
-        let mut t = 0i64;
-        let mut new_t = 1i64;
-        let mut r = self.modulus as i64;
-        let mut new_r = a as i64;
+    None
+}
 
-        while new_r != 0 {
-            let quotient = r / new_r;
-            let temp_t = t;
-            t = new_t;
-            new_t = temp_t - quotient * new_t;
-            let temp_r = r;
-            r = new_r;
-            new_r = temp_r - quotient * new_r;
-        }
+/// Shamir shares of `secret` over the scalars, share `id` evaluated at `id + 1`
+fn share_scalar<R: Rng + CryptoRng + ?Sized>(secret: Scalar, threshold: usize, num_shares: usize, rng: &mut R) -> Vec<Scalar> {
+    let coefficients: Vec<Scalar> = std::iter::once(secret)
+        .chain((1..threshold).map(|_| random_scalar(rng)))
+        .collect();
 
-        if r > 1 {
-            return 0; // No inverse exists
-        }
+    (0..num_shares)
+        .map(|id| {
+            let x = Scalar::from(id as u64 + 1);
+            coefficients.iter().rev().fold(Scalar::ZERO, |acc, coefficient| acc * x + coefficient)
+        })
+        .collect()
+}
 
-        if t < 0 {
-            t += self.modulus as i64;
-        }
+/// Lagrange coefficients over the scalars for interpolating at zero from the given share IDs
+fn scalar_lagrange_coefficients(ids: &[usize]) -> Result<Vec<Scalar>, ProtocolError> {
+    ids.iter()
+        .enumerate()
+        .map(|(i, &id_i)| {
+            let x_i = Scalar::from(id_i as u64 + 1);
+            let mut numerator = Scalar::ONE;
+            let mut denominator = Scalar::ONE;
+            for (j, &id_j) in ids.iter().enumerate() {
+                if i != j {
+                    if id_i == id_j {
+                        return Err(FieldError::DuplicateShareId(id_i).into());
+                    }
+                    let x_j = Scalar::from(id_j as u64 + 1);
+                    numerator *= x_j;
+                    denominator *= x_j - x_i;
+                }
+            }
+            Ok(numerator * denominator.invert())
+        })
+        .collect()
+}
 
-        t as u64
-    }
+/// Sample a uniformly random scalar
+pub(crate) fn random_scalar<R: Rng + CryptoRng + ?Sized>(rng: &mut R) -> Scalar {
+    let mut bytes = [0u8; 64];
+    rng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
 }
 
 #[cfg(test)]
@@ -479,6 +1150,196 @@ mod tests {
         assert_eq!(reconstructed, secret);
     }
 
+    #[test]
+    fn test_shamir_five_servers_threshold_three() {
+        let shamir = ShamirSecretSharing::new(3, 5, 0xFFFFFFFFFFFFFFC5).unwrap();
+        let secret = 123_456_789u64;
+
+        let shares = shamir.share_secret(secret).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        // Every 3-subset of the 5 shares reconstructs the secret
+        for a in 0..5 {
+            for b in (a + 1)..5 {
+                for c in (b + 1)..5 {
+                    let subset = vec![shares[a].clone(), shares[b].clone(), shares[c].clone()];
+                    assert_eq!(shamir.reconstruct_secret(&subset).unwrap(), secret);
+                }
+            }
+        }
+
+        assert!(shamir.reconstruct_secret(&shares[0..2]).is_err());
+    }
+
+    #[test]
+    fn test_reconstruct_many() {
+        let shamir = ShamirSecretSharing::new(3, 5, 0xFFFFFFFFFFFFFFC5).unwrap();
+        let secrets: Vec<u64> = (0..10_000).collect();
+        let shares: Vec<Vec<SecretShare>> = secrets
+            .iter()
+            .map(|&secret| shamir.share_secret(secret).unwrap()[1..4].to_vec())
+            .collect();
+
+        assert_eq!(shamir.reconstruct_many(&shares).unwrap(), secrets);
+        assert!(shamir.reconstruct_many(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_additive_sharing() {
+        let additive = AdditiveSecretSharing::new(3).unwrap();
+        let a = additive.share_secret(u64::MAX - 1);
+        let b = additive.share_secret(5);
+        assert_eq!(additive.reconstruct_secret(&a).unwrap(), u64::MAX - 1);
+
+        // Sums wrap around modulo 2^64
+        let sums: Vec<_> = a.iter().zip(&b).map(|(a, b)| additive.add(a, b).unwrap()).collect();
+        assert_eq!(additive.reconstruct_secret(&sums).unwrap(), 3);
+        let scaled: Vec<_> = b.iter().map(|share| additive.multiply_scalar(share, 7)).collect();
+        assert_eq!(additive.reconstruct_secret(&scaled).unwrap(), 35);
+        assert!(additive.add(&a[0], &b[1]).is_err());
+
+        assert!(matches!(
+            additive.reconstruct_secret(&a[..2]),
+            Err(ProtocolError::InsufficientServers { available: 2, required: 3 })
+        ));
+        assert!(additive.reconstruct_secret(&[a[0], a[0], a[1]]).is_err());
+        assert!(AdditiveSecretSharing::new(1).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_additive_data_sharing() {
+        let mut crypto = ThresholdEncryption::new(2, 3).unwrap().with_scheme(SharingScheme::Additive);
+        crypto.initialize().await.unwrap();
+
+        let shares = crypto.share_data(DataPoint::new(vec![3.0, 40.0])).await.unwrap();
+        assert_eq!(shares.len(), 6);
+        assert!(shares.iter().all(|share| share.modulus == WRAPPING_MODULUS));
+        let points = crypto.reconstruct_data_many(vec![shares.clone()]).await.unwrap();
+        assert_eq!(points[0].features(), &[3.0, 40.0]);
+
+        // Unlike Shamir, every server's share is needed
+        let subset: Vec<DataShare> = shares.into_iter().filter(|share| share.server_id < 2).collect();
+        assert!(crypto.reconstruct_data(subset).await.is_err());
+    }
+
+    #[test]
+    fn test_replicated_sharing() {
+        let replicated = ReplicatedSecretSharing::new(97).unwrap();
+        let shares = replicated.share_secret(42).unwrap();
+        assert_eq!(shares.len(), 3);
+
+        // Any two servers reconstruct, one alone does not
+        for (a, b) in [(0, 1), (1, 2), (0, 2)] {
+            assert_eq!(replicated.reconstruct_secret(&[shares[a], shares[b]]).unwrap(), 42);
+        }
+        assert!(matches!(
+            replicated.reconstruct_secret(&shares[..1]),
+            Err(ProtocolError::InsufficientServers { .. })
+        ));
+
+        let others = replicated.share_secret(60).unwrap();
+        let sums: Vec<_> = shares.iter().zip(&others).map(|(a, b)| replicated.add(a, b).unwrap()).collect();
+        assert_eq!(replicated.reconstruct_secret(&sums).unwrap(), 5);
+        assert!(replicated.add(&shares[0], &others[1]).is_err());
+
+        // A server changing a component it shares with another is caught
+        let mut tampered = shares.clone();
+        tampered[1].second = (tampered[1].second + 1) % 97;
+        assert!(matches!(
+            replicated.reconstruct_secret(&tampered[1..]),
+            Err(ProtocolError::CheatDetected { index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_replicated_multiplication_and_conversion() {
+        let modulus = 0xFFFFFFFFFFFFFFC5;
+        let replicated = ReplicatedSecretSharing::new(modulus).unwrap();
+        let keys: Vec<[u8; 32]> = (0..3).map(|_| ZeroSharing::generate_key()).collect();
+        let mut zeros: Vec<ZeroSharing> = (0..3)
+            .map(|i| ZeroSharing::new(keys[i], keys[(i + 1) % 3], modulus).unwrap())
+            .collect();
+        // Each server sends its additive share to the previous one
+        let exchange = |additive: [u64; 3]| -> Vec<ReplicatedShare> {
+            (0..3).map(|i| replicated.reshare(i, additive[i], additive[(i + 1) % 3]).unwrap()).collect()
+        };
+
+        let x = replicated.share_secret(1 << 40).unwrap();
+        let y = replicated.share_secret(12_345).unwrap();
+        let products: [u64; 3] =
+            std::array::from_fn(|i| replicated.multiply_local(&x[i], &y[i], zeros[i].next_share()).unwrap());
+        let product = exchange(products);
+        let expected = ((1u128 << 40) * 12_345 % modulus as u128) as u64;
+        assert_eq!(replicated.reconstruct_secret(&product[1..]).unwrap(), expected);
+
+        // To Shamir shares of threshold 2, and back
+        let shamir = ShamirSecretSharing::new(2, 3, modulus).unwrap();
+        let data_shares: Vec<DataShare> = product.iter().map(|share| replicated.to_data_share(share, 4).unwrap()).collect();
+        let secret_shares: Vec<SecretShare> = data_shares
+            .iter()
+            .map(|share| SecretShare::new(share.server_id, share.value, 0, modulus))
+            .collect();
+        assert_eq!(shamir.reconstruct_secret(&secret_shares[1..]).unwrap(), expected);
+        assert_eq!(shamir.reconstruct_secret(&[secret_shares[2].clone(), secret_shares[0].clone()]).unwrap(), expected);
+
+        let additive: [u64; 3] =
+            std::array::from_fn(|i| replicated.from_data_share(&data_shares[i], zeros[i].next_share()).unwrap());
+        assert_eq!(replicated.reconstruct_secret(&exchange(additive)).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_data_reconstruction_many() {
+        let mut crypto = ThresholdEncryption::new(3, 5).unwrap();
+        crypto.initialize().await.unwrap();
+
+        let mut records = Vec::new();
+        for features in [vec![1.0, 2.0], vec![3.0], vec![5.0, 8.0, 13.0]] {
+            let shares = crypto.share_data(DataPoint::new(features)).await.unwrap();
+            records.push(shares.into_iter().filter(|share| share.server_id < 3).collect());
+        }
+
+        let points = crypto.reconstruct_data_many(records).await.unwrap();
+        let features: Vec<&[f64]> = points.iter().map(|point| point.features()).collect();
+        assert_eq!(features, vec![&[1.0, 2.0][..], &[3.0][..], &[5.0, 8.0, 13.0][..]]);
+    }
+
+    #[tokio::test]
+    async fn test_data_reconstruction_from_any_threshold_servers() {
+        let mut crypto = ThresholdEncryption::new(3, 5).unwrap();
+        crypto.initialize().await.unwrap();
+
+        let data = DataPoint::new(vec![4.0, 8.0, 15.0]);
+        let shares = crypto.share_data(data).await.unwrap();
+
+        let subset: Vec<DataShare> = shares
+            .into_iter()
+            .filter(|share| [1, 3, 4].contains(&share.server_id))
+            .collect();
+        let reconstructed = crypto.reconstruct_data(subset).await.unwrap();
+        assert_eq!(reconstructed.features(), &[4.0, 8.0, 15.0]);
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_reconstruction() {
+        let mut crypto = ThresholdEncryption::new(2, 3).unwrap().with_authentication(true);
+        crypto.initialize().await.unwrap();
+
+        let shares = crypto.share_data(DataPoint::new(vec![4.0, 8.0])).await.unwrap();
+        assert!(shares.iter().all(DataShare::is_authenticated));
+        let points = crypto.reconstruct_data_many(vec![shares.clone(), shares.clone()]).await.unwrap();
+        assert_eq!(points[1].features(), &[4.0, 8.0]);
+
+        let mut tampered = shares.clone();
+        let share = tampered.iter_mut().find(|share| share.server_id == 0 && share.feature_index == 1).unwrap();
+        share.value ^= 1;
+        let error = crypto.reconstruct_data(tampered).await.unwrap_err();
+        assert!(error.is_cheating());
+
+        // Additive shares cannot be authenticated
+        let mut additive = ThresholdEncryption::new(2, 3).unwrap().with_scheme(SharingScheme::Additive).with_authentication(true);
+        assert!(additive.initialize().await.is_err());
+    }
+
     #[tokio::test]
     async fn test_threshold_encryption() {
         let mut crypto = ThresholdEncryption::new(2, 3).unwrap();
@@ -500,6 +1361,54 @@ mod tests {
         assert!(!shares.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_threshold_decryption() {
+        let mut crypto = ThresholdEncryption::new(3, 5).unwrap();
+        crypto.initialize().await.unwrap();
+
+        let a = crypto.encrypt(1200).await.unwrap();
+        let b = crypto.encrypt(34).await.unwrap();
+        let sum = crypto.rerandomize(&crypto.add(&a, &b));
+        assert_ne!(crypto.rerandomize(&a), a);
+
+        // Any three servers decrypt
+        let mut shares = Vec::new();
+        for server_id in [4, 1, 2] {
+            shares.push(crypto.partial_decrypt(server_id, &sum).await.unwrap());
+        }
+        assert_eq!(crypto.decrypt(&sum, &shares, 10_000).await.unwrap(), 1234);
+
+        // Ciphertexts and shares survive serialization
+        let json = serde_json::to_string(&(&sum, &shares)).unwrap();
+        let (sum, shares): (ElGamalCiphertext, Vec<DecryptionShare>) = serde_json::from_str(&json).unwrap();
+        assert_eq!(crypto.decrypt(&sum, &shares, 10_000).await.unwrap(), 1234);
+
+        assert!(crypto.decrypt(&sum, &shares, 1000).await.is_err());
+        assert!(matches!(
+            crypto.decrypt(&sum, &shares[..2], 10_000).await,
+            Err(ProtocolError::InsufficientServers { available: 2, required: 3 })
+        ));
+        let other = crypto.partial_decrypt(0, &a).await.unwrap();
+        assert!(crypto.decrypt(&sum, &[other, shares[1].clone(), shares[2].clone()], 10_000).await.is_err());
+
+        // The baby-step table is bounded, so larger searches are refused
+        assert!(matches!(
+            crypto.decrypt(&sum, &shares, MAX_PLAINTEXT + 1).await,
+            Err(ProtocolError::InvalidConfiguration(_))
+        ));
+        let large = crypto.encrypt((1 << 24) - 1).await.unwrap();
+        let mut shares = Vec::new();
+        for server_id in [0, 3, 2] {
+            shares.push(crypto.partial_decrypt(server_id, &large).await.unwrap());
+        }
+        assert_eq!(crypto.decrypt(&large, &shares, 1 << 24).await.unwrap(), (1 << 24) - 1);
+
+        // Encodings of anything but a group element are rejected
+        let json = serde_json::to_string(&[[0xFFu8; 32], [0; 32]]).unwrap();
+        assert!(serde_json::from_str::<ElGamalCiphertext>(&json).is_err());
+        assert!(serde_json::from_str::<DecryptionShare>(&format!("[1,{:?}]", [0xFFu8; 32])).is_err());
+    }
+
     #[tokio::test]
     async fn test_noise_generation() {
         let mut crypto = ThresholdEncryption::new(2, 3).unwrap();
@@ -510,15 +1419,4 @@ mod tests {
         
         assert!(noise.is_finite());
     }
-
-    #[test]
-    fn test_homomorphic_encryption() {
-        let crypto = HomomorphicEncryption::new();
-        
-        let value = 42u64;
-        let encrypted = crypto.encrypt(value);
-        let decrypted = crypto.decrypt(encrypted);
-        
-        assert_eq!(decrypted, value);
-    }
 } 
\ No newline at end of file
@@ -0,0 +1,262 @@
+use crate::multi_party::cancel::CancellationToken;
+use crate::multi_party::protocol::{ProtocolConfig, ProtocolError, ProtocolPhase};
+use crate::multi_party::runtime::{self, SharedRuntime};
+use std::future::Future;
+use std::time::Duration;
+
+/// Why a run was aborted
+#[derive(Debug, Clone)]
+enum Abort {
+    /// A phase took longer than its timeout
+    TimedOut { phase: ProtocolPhase, duration_ms: u64 },
+    /// The caller gave up on the run
+    Requested { phase: ProtocolPhase, reason: String },
+    /// The cancellation token of the run was cancelled
+    Cancelled,
+}
+
+impl Abort {
+    fn error(&self) -> ProtocolError {
+        match self {
+            Abort::TimedOut { phase, duration_ms } => ProtocolError::phase_timeout(*phase, *duration_ms),
+            Abort::Requested { phase, reason } => {
+                ProtocolError::server_error(format!("Protocol aborted in phase {:?}: {}", phase, reason))
+            }
+            Abort::Cancelled => ProtocolError::Cancelled,
+        }
+    }
+}
+
+/// Drives a run of the protocol through its phases, each within its timeout
+///
+/// `advance` only moves to a phase that may follow the current one, see
+/// `ProtocolPhase::successors`, and starts the clock of the new phase. A step
+/// run with `run` fails with a `Timeout` naming the phase once the phase has
+/// taken longer than `ProtocolConfig::phase_timeout`. The run is then aborted,
+/// as it is by `abort` or by cancelling the token given to `with_cancellation`,
+/// and every later step and transition fails with the same error.
+#[derive(Debug)]
+pub struct ProtocolDriver {
+    config: ProtocolConfig,
+    runtime: SharedRuntime,
+    phase: ProtocolPhase,
+    /// Time on the runtime clock the current phase was entered
+    entered_at: Duration,
+    /// Phases left so far and the time spent in each
+    history: Vec<(ProtocolPhase, Duration)>,
+    cancellation: CancellationToken,
+    aborted: Option<Abort>,
+}
+
+impl ProtocolDriver {
+    /// Start a run in the `Setup` phase on the default runtime
+    pub fn new(config: ProtocolConfig) -> Self {
+        let runtime = runtime::default_runtime();
+        Self {
+            config,
+            entered_at: runtime.now(),
+            runtime,
+            phase: ProtocolPhase::Setup,
+            history: Vec::new(),
+            cancellation: CancellationToken::new(),
+            aborted: None,
+        }
+    }
+
+    /// Use another runtime for the clock, restarting the clock of the current phase
+    pub fn with_runtime(mut self, runtime: SharedRuntime) -> Self {
+        self.entered_at = runtime.now();
+        self.runtime = runtime;
+        self
+    }
+
+    /// Abort the run when `token` is cancelled, stopping the step that is running
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Phase the run is in
+    pub fn phase(&self) -> ProtocolPhase {
+        self.phase
+    }
+
+    /// Time spent in the current phase
+    pub fn elapsed(&self) -> Duration {
+        self.runtime.now().saturating_sub(self.entered_at)
+    }
+
+    /// Time left before the current phase times out
+    pub fn remaining(&self) -> Duration {
+        self.config.phase_timeout(self.phase).saturating_sub(self.elapsed())
+    }
+
+    /// Phases left so far and the time spent in each, in order
+    pub fn history(&self) -> &[(ProtocolPhase, Duration)] {
+        &self.history
+    }
+
+    /// Check if the run was aborted, by a timeout or by `abort`
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.is_some()
+    }
+
+    /// Fail if the run was aborted or cancelled, or the current phase timed out
+    pub fn check(&mut self) -> Result<(), ProtocolError> {
+        if let Some(abort) = &self.aborted {
+            return Err(abort.error());
+        }
+        if self.cancellation.is_cancelled() {
+            return Err(self.cancel());
+        }
+        if self.remaining().is_zero() {
+            return Err(self.time_out());
+        }
+        Ok(())
+    }
+
+    /// Move to phase `next`, failing if it may not follow the current one
+    pub fn advance(&mut self, next: ProtocolPhase) -> Result<(), ProtocolError> {
+        self.check()?;
+        if !self.phase.can_advance_to(next) {
+            return Err(ProtocolError::InvalidTransition {
+                from: self.phase,
+                to: next,
+            });
+        }
+
+        let elapsed = self.elapsed();
+        log::debug!("Leaving phase {:?} after {:?} for {:?}", self.phase, elapsed, next);
+        self.history.push((self.phase, elapsed));
+        self.phase = next;
+        self.entered_at = self.runtime.now();
+        Ok(())
+    }
+
+    /// Run a step of the current phase, giving up once the phase times out
+    ///
+    /// Errors of the step itself are returned as they are and do not abort the run.
+    pub async fn run<T, F>(&mut self, step: F) -> Result<T, ProtocolError>
+    where
+        F: Future<Output = Result<T, ProtocolError>>,
+    {
+        self.check()?;
+        let cancellation = self.cancellation.clone();
+        match runtime::timeout(&*self.runtime, self.remaining(), cancellation.run(step)).await {
+            Ok(Err(ProtocolError::Cancelled)) if cancellation.is_cancelled() => Err(self.cancel()),
+            Ok(result) => result,
+            Err(_) => Err(self.time_out()),
+        }
+    }
+
+    /// Abort the run, so that every later step and transition fails
+    pub fn abort(&mut self, reason: impl Into<String>) {
+        let reason = reason.into();
+        log::warn!("Aborting the protocol in phase {:?}: {}", self.phase, reason);
+        self.aborted.get_or_insert(Abort::Requested {
+            phase: self.phase,
+            reason,
+        });
+    }
+
+    /// Abort the run because its token was cancelled
+    fn cancel(&mut self) -> ProtocolError {
+        log::warn!("Protocol cancelled in phase {:?}", self.phase);
+        self.aborted.get_or_insert(Abort::Cancelled).error()
+    }
+
+    /// Abort the run because the current phase timed out
+    fn time_out(&mut self) -> ProtocolError {
+        let duration_ms = self.config.phase_timeout(self.phase).as_millis() as u64;
+        log::error!("Phase {:?} timed out after {}ms", self.phase, duration_ms);
+        self.aborted
+            .get_or_insert(Abort::TimedOut {
+                phase: self.phase,
+                duration_ms,
+            })
+            .error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::runtime::{ManualRuntime, Runtime};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_driver_transitions_and_timeouts() {
+        let runtime = Arc::new(ManualRuntime::new());
+        let config = ProtocolConfig {
+            timeout_ms: 100,
+            phase_timeouts: BTreeMap::from([(ProtocolPhase::Shuffling, 500)]),
+            ..ProtocolConfig::default()
+        };
+        let mut driver = ProtocolDriver::new(config).with_runtime(runtime.clone());
+
+        runtime.block_on(async {
+            let value = driver.run(async { Ok(7) }).await.unwrap();
+            assert_eq!(value, 7);
+            assert!(matches!(
+                driver.advance(ProtocolPhase::Shuffling),
+                Err(ProtocolError::InvalidTransition {
+                    from: ProtocolPhase::Setup,
+                    to: ProtocolPhase::Shuffling
+                })
+            ));
+            driver.advance(ProtocolPhase::Sharing).unwrap();
+            runtime.sleep(Duration::from_millis(60)).await;
+            driver.advance(ProtocolPhase::Shuffling).unwrap();
+
+            // The shuffle has its own timeout, and a step that overruns it aborts the run
+            let sleeper = runtime.clone();
+            let stuck = driver
+                .run(async move {
+                    sleeper.sleep(Duration::from_secs(1)).await;
+                    Ok(())
+                })
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                stuck,
+                ProtocolError::Timeout {
+                    duration_ms: 500,
+                    phase: Some(ProtocolPhase::Shuffling)
+                }
+            ));
+            assert_eq!(stuck.to_string(), "Operation timed out after 500ms in phase Shuffling");
+        });
+
+        assert!(driver.is_aborted());
+        assert!(matches!(driver.advance(ProtocolPhase::Noise), Err(ProtocolError::Timeout { .. })));
+        assert_eq!(driver.phase(), ProtocolPhase::Shuffling);
+        assert_eq!(
+            driver.history(),
+            [(ProtocolPhase::Setup, Duration::ZERO), (ProtocolPhase::Sharing, Duration::from_millis(60))]
+        );
+    }
+
+    #[test]
+    fn test_driver_cancellation() {
+        let runtime = Arc::new(ManualRuntime::new());
+        let token = CancellationToken::new();
+        let mut driver = ProtocolDriver::new(ProtocolConfig::default())
+            .with_runtime(runtime.clone())
+            .with_cancellation(token.clone());
+
+        // Cancelling from another task stops the running step long before its timeout
+        let cancelled = runtime.block_on(async {
+            let sleeper = runtime.clone();
+            runtime.spawn(Box::pin(async move {
+                sleeper.sleep(Duration::from_millis(10)).await;
+                token.cancel();
+            }));
+            driver.run(futures::future::pending::<Result<(), ProtocolError>>()).await
+        });
+        assert!(matches!(cancelled, Err(ProtocolError::Cancelled)));
+        assert_eq!(runtime.now(), Duration::from_millis(10));
+        assert!(driver.is_aborted());
+        assert!(matches!(driver.advance(ProtocolPhase::Sharing), Err(ProtocolError::Cancelled)));
+    }
+}
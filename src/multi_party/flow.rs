@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+/// Messages a channel to another server can queue
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// Watermarks of the queue of messages a channel sends to its target
+///
+/// Once `high_watermark` messages are queued and not taken by the target yet,
+/// the channel is paused: `CommunicationChannel::try_send` refuses messages
+/// with a `Backpressure` error, and `CommunicationChannel::send` waits up to
+/// `max_wait` for the queue to drain to `low_watermark`. The channel stays
+/// paused until then, so it does not flip between states with every message.
+///
+/// Heartbeats, acknowledgements and resends are never held back, so a paused
+/// channel still tells its target what it received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowControl {
+    /// Queued messages that pause the channel
+    pub high_watermark: usize,
+    /// Queued messages that resume a paused channel
+    pub low_watermark: usize,
+    /// Longest a send waits for a paused channel to resume
+    pub max_wait: Duration,
+}
+
+impl Default for FlowControl {
+    fn default() -> Self {
+        Self {
+            high_watermark: DEFAULT_QUEUE_CAPACITY / 4,
+            low_watermark: DEFAULT_QUEUE_CAPACITY / 16,
+            max_wait: Duration::from_secs(5),
+        }
+    }
+}
+
+impl FlowControl {
+    /// Check that the watermarks fit in a queue of `capacity` messages
+    pub fn validate(&self, capacity: usize) -> Result<(), String> {
+        if self.low_watermark >= self.high_watermark {
+            return Err(format!(
+                "low_watermark {} must be below high_watermark {}",
+                self.low_watermark, self.high_watermark
+            ));
+        }
+        if self.high_watermark > capacity {
+            return Err(format!(
+                "high_watermark {} exceeds queue_capacity {}",
+                self.high_watermark, capacity
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Queue depths and backpressure of a channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlowMetrics {
+    /// Messages queued to the target and not taken by it yet
+    pub outbound_depth: usize,
+    /// Messages from the target not received yet
+    pub inbound_depth: usize,
+    /// Deepest outbound queue a send saw
+    pub max_outbound_depth: usize,
+    /// Times the channel was paused
+    pub pauses: u64,
+    /// Messages refused because the channel stayed paused
+    pub rejected: u64,
+    /// Time sends spent waiting for the channel to resume
+    pub waited: Duration,
+}
+
+/// Whether a channel is paused, and its counters
+#[derive(Debug)]
+pub(crate) struct FlowState {
+    pub(crate) control: FlowControl,
+    paused: bool,
+    metrics: FlowMetrics,
+}
+
+impl FlowState {
+    pub(crate) fn new(control: FlowControl) -> Self {
+        Self {
+            control,
+            paused: false,
+            metrics: FlowMetrics::default(),
+        }
+    }
+
+    /// Check if a message may be sent with `depth` messages queued, pausing or resuming the channel
+    pub(crate) fn admit(&mut self, depth: usize) -> bool {
+        self.metrics.max_outbound_depth = self.metrics.max_outbound_depth.max(depth);
+        if self.paused && depth <= self.control.low_watermark {
+            self.paused = false;
+        } else if !self.paused && depth >= self.control.high_watermark {
+            self.paused = true;
+            self.metrics.pauses += 1;
+        }
+        !self.paused
+    }
+
+    /// Resume the channel after the queue drained in `waited`
+    pub(crate) fn resume(&mut self, waited: Duration) {
+        self.paused = false;
+        self.metrics.waited += waited;
+    }
+
+    /// Count a message refused after waiting `waited`
+    pub(crate) fn reject(&mut self, waited: Duration) {
+        self.metrics.rejected += 1;
+        self.metrics.waited += waited;
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub(crate) fn metrics(&self) -> FlowMetrics {
+        self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermarks() {
+        let control = FlowControl {
+            high_watermark: 4,
+            low_watermark: 1,
+            max_wait: Duration::from_secs(1),
+        };
+        assert!(control.validate(4).is_ok());
+        assert!(control.validate(3).is_err());
+        assert!(FlowControl { low_watermark: 4, ..control }.validate(8).is_err());
+
+        let mut flow = FlowState::new(control);
+        assert!(flow.admit(3));
+        assert!(!flow.admit(4));
+        // Still paused until the queue drains to the low watermark
+        assert!(!flow.admit(2));
+        assert!(flow.admit(1));
+        assert!(flow.admit(3));
+
+        assert!(!flow.admit(5));
+        flow.reject(Duration::from_millis(10));
+        flow.resume(Duration::from_millis(5));
+        assert!(!flow.is_paused());
+        let metrics = flow.metrics();
+        assert_eq!((metrics.pauses, metrics.rejected, metrics.max_outbound_depth), (2, 1, 5));
+        assert_eq!(metrics.waited, Duration::from_millis(15));
+    }
+}
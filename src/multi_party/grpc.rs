@@ -0,0 +1,495 @@
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::server::MultiPartyServer;
+use crate::multi_party::share::{DataShare, ShareType};
+use crate::schema::{DataPoint, Query, QueryResult, QueryType};
+use prost::Message;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tonic::{Request, Response, Status};
+
+/// Client and server generated from `proto/mpc.proto` by `build.rs`
+#[allow(clippy::all, missing_docs)]
+mod generated {
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/doppio.mpc.v1.MultiPartyService.rs"
+    ));
+}
+
+pub use generated::multi_party_service_client::MultiPartyServiceClient;
+pub use generated::multi_party_service_server::{MultiPartyService, MultiPartyServiceServer};
+
+/// Kind of a share in `DataShareProto`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum ShareKind {
+    Feature = 0,
+    Metadata = 1,
+    Noise = 2,
+    Permutation = 3,
+    QueryResult = 4,
+    Triple = 5,
+}
+
+/// Type of a query in `QueryRequest`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum QueryKind {
+    Mean = 0,
+    Variance = 1,
+    Histogram = 2,
+    Range = 3,
+    Count = 4,
+    Sum = 5,
+}
+
+/// Wire message for a `DataShare`
+#[derive(Clone, PartialEq, Message)]
+pub struct DataShareProto {
+    #[prost(uint64, tag = "1")]
+    pub server_id: u64,
+    #[prost(uint64, tag = "2")]
+    pub feature_index: u64,
+    #[prost(enumeration = "ShareKind", tag = "3")]
+    pub share_type: i32,
+    #[prost(uint64, tag = "4")]
+    pub value: u64,
+    #[prost(uint64, tag = "5")]
+    pub modulus: u64,
+    #[prost(map = "string, string", tag = "6")]
+    pub metadata: HashMap<String, String>,
+    #[prost(uint64, optional, tag = "7")]
+    pub mac: Option<u64>,
+}
+
+/// Wire message for the shares of one record
+#[derive(Clone, PartialEq, Message)]
+pub struct RecordProto {
+    #[prost(message, repeated, tag = "1")]
+    pub shares: Vec<DataShareProto>,
+}
+
+/// Wire message for the features of a `DataPoint`
+#[derive(Clone, PartialEq, Message)]
+pub struct DataPointProto {
+    #[prost(double, repeated, tag = "1")]
+    pub features: Vec<f64>,
+}
+
+/// Request of the `Share` RPC
+#[derive(Clone, PartialEq, Message)]
+pub struct ShareRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub shares: Vec<DataShareProto>,
+}
+
+/// Response of the `Share` RPC
+#[derive(Clone, PartialEq, Message)]
+pub struct ShareResponse {
+    #[prost(uint64, tag = "1")]
+    pub share_count: u64,
+}
+
+/// Request of the `Shuffle` RPC
+#[derive(Clone, PartialEq, Message)]
+pub struct ShuffleRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub records: Vec<RecordProto>,
+}
+
+/// Response of the `Shuffle` RPC
+#[derive(Clone, PartialEq, Message)]
+pub struct ShuffleResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub records: Vec<RecordProto>,
+}
+
+/// Request of the `Reconstruct` RPC
+#[derive(Clone, PartialEq, Message)]
+pub struct ReconstructRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub records: Vec<RecordProto>,
+}
+
+/// Response of the `Reconstruct` RPC
+#[derive(Clone, PartialEq, Message)]
+pub struct ReconstructResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub points: Vec<DataPointProto>,
+}
+
+/// Request of the `Query` RPC
+#[derive(Clone, PartialEq, Message)]
+pub struct QueryRequest {
+    #[prost(enumeration = "QueryKind", tag = "1")]
+    pub query_type: i32,
+    #[prost(string, repeated, tag = "2")]
+    pub features: Vec<String>,
+    #[prost(map = "string, double", tag = "3")]
+    pub parameters: HashMap<String, f64>,
+    #[prost(message, repeated, tag = "4")]
+    pub points: Vec<DataPointProto>,
+}
+
+/// Response of the `Query` RPC
+#[derive(Clone, PartialEq, Message)]
+pub struct QueryResponse {
+    #[prost(double, repeated, tag = "1")]
+    pub values: Vec<f64>,
+    #[prost(bool, tag = "2")]
+    pub has_noise: bool,
+    #[prost(double, tag = "3")]
+    pub privacy_budget_used: f64,
+}
+
+impl From<&DataShare> for DataShareProto {
+    fn from(share: &DataShare) -> Self {
+        let kind = match share.share_type {
+            ShareType::Feature => ShareKind::Feature,
+            ShareType::Metadata => ShareKind::Metadata,
+            ShareType::Noise => ShareKind::Noise,
+            ShareType::Permutation => ShareKind::Permutation,
+            ShareType::QueryResult => ShareKind::QueryResult,
+            ShareType::Triple => ShareKind::Triple,
+        };
+
+        Self {
+            server_id: share.server_id as u64,
+            feature_index: share.feature_index as u64,
+            share_type: kind as i32,
+            value: share.value,
+            modulus: share.modulus,
+            metadata: share.metadata.clone(),
+            mac: share.mac,
+        }
+    }
+}
+
+impl TryFrom<DataShareProto> for DataShare {
+    type Error = Status;
+
+    fn try_from(proto: DataShareProto) -> Result<Self, Self::Error> {
+        let share_type = match ShareKind::try_from(proto.share_type) {
+            Ok(ShareKind::Feature) => ShareType::Feature,
+            Ok(ShareKind::Metadata) => ShareType::Metadata,
+            Ok(ShareKind::Noise) => ShareType::Noise,
+            Ok(ShareKind::Permutation) => ShareType::Permutation,
+            Ok(ShareKind::QueryResult) => ShareType::QueryResult,
+            Ok(ShareKind::Triple) => ShareType::Triple,
+            Err(_) => {
+                return Err(Status::invalid_argument(format!(
+                    "Unknown share type {}",
+                    proto.share_type
+                )))
+            }
+        };
+
+        let mut share = DataShare::new(
+            index(proto.server_id, "server_id")?,
+            index(proto.feature_index, "feature_index")?,
+            share_type,
+            proto.value,
+            proto.modulus,
+        );
+        share.metadata = proto.metadata;
+        share.mac = proto.mac;
+        Ok(share)
+    }
+}
+
+impl From<&[DataShare]> for RecordProto {
+    fn from(shares: &[DataShare]) -> Self {
+        Self {
+            shares: shares.iter().map(DataShareProto::from).collect(),
+        }
+    }
+}
+
+impl TryFrom<RecordProto> for Vec<DataShare> {
+    type Error = Status;
+
+    fn try_from(proto: RecordProto) -> Result<Self, Self::Error> {
+        proto.shares.into_iter().map(DataShare::try_from).collect()
+    }
+}
+
+impl From<&DataPoint> for DataPointProto {
+    fn from(point: &DataPoint) -> Self {
+        Self {
+            features: point.features().to_vec(),
+        }
+    }
+}
+
+impl From<DataPointProto> for DataPoint {
+    fn from(proto: DataPointProto) -> Self {
+        DataPoint::new(proto.features)
+    }
+}
+
+impl From<QueryType> for QueryKind {
+    fn from(query_type: QueryType) -> Self {
+        match query_type {
+            QueryType::Mean => QueryKind::Mean,
+            QueryType::Variance => QueryKind::Variance,
+            QueryType::Histogram => QueryKind::Histogram,
+            QueryType::Range => QueryKind::Range,
+            QueryType::Count => QueryKind::Count,
+            QueryType::Sum => QueryKind::Sum,
+        }
+    }
+}
+
+impl From<QueryKind> for QueryType {
+    fn from(kind: QueryKind) -> Self {
+        match kind {
+            QueryKind::Mean => QueryType::Mean,
+            QueryKind::Variance => QueryType::Variance,
+            QueryKind::Histogram => QueryType::Histogram,
+            QueryKind::Range => QueryType::Range,
+            QueryKind::Count => QueryType::Count,
+            QueryKind::Sum => QueryType::Sum,
+        }
+    }
+}
+
+impl From<&QueryResult> for QueryResponse {
+    fn from(result: &QueryResult) -> Self {
+        Self {
+            values: result.values().to_vec(),
+            has_noise: result.has_noise(),
+            privacy_budget_used: result.privacy_budget_used(),
+        }
+    }
+}
+
+/// Convert an index received on the wire
+fn index(value: u64, field: &str) -> Result<usize, Status> {
+    usize::try_from(value)
+        .map_err(|_| Status::invalid_argument(format!("{} {} is out of range", field, value)))
+}
+
+/// Convert records received on the wire
+fn records(records: Vec<RecordProto>) -> Result<Vec<Vec<DataShare>>, Status> {
+    records
+        .into_iter()
+        .map(Vec::<DataShare>::try_from)
+        .collect()
+}
+
+/// gRPC status of a protocol error
+fn status(error: ProtocolError) -> Status {
+    let message = error.to_string();
+    match error {
+        ProtocolError::InvalidConfiguration(_)
+        | ProtocolError::UnsupportedQuery(_)
+        | ProtocolError::MalformedMessage(_)
+        | ProtocolError::InvalidShare { .. } => Status::invalid_argument(message),
+        ProtocolError::ServerError { .. }
+        | ProtocolError::IncompatibleVersion { .. }
+        | ProtocolError::InvalidTransition { .. } => Status::failed_precondition(message),
+        ProtocolError::InsufficientServers { .. } | ProtocolError::NetworkError { .. } => {
+            Status::unavailable(message)
+        }
+        ProtocolError::Timeout { .. } => Status::deadline_exceeded(message),
+        ProtocolError::Cancelled => Status::cancelled(message),
+        ProtocolError::Backpressure { .. } => Status::resource_exhausted(message),
+        ProtocolError::CheatDetected { .. } => Status::aborted(message),
+        ProtocolError::Security(_) => Status::unauthenticated(message),
+        ProtocolError::InternalError { .. } => Status::internal(message),
+    }
+}
+
+/// gRPC service wrapping a `MultiPartyServer`
+///
+/// Requests are served one at a time, in the order they take the server lock.
+#[derive(Debug, Clone)]
+pub struct MpcService {
+    server: Arc<Mutex<MultiPartyServer>>,
+}
+
+impl MpcService {
+    /// Serve the given server
+    pub fn new(server: MultiPartyServer) -> Self {
+        Self::from_shared(Arc::new(Mutex::new(server)))
+    }
+
+    /// Serve a server that is also used outside the service
+    pub fn from_shared(server: Arc<Mutex<MultiPartyServer>>) -> Self {
+        Self { server }
+    }
+
+    /// Get the served server
+    pub fn server(&self) -> Arc<Mutex<MultiPartyServer>> {
+        self.server.clone()
+    }
+
+    /// Wrap the service for a `tonic::transport::Server`
+    pub fn into_service(self) -> MultiPartyServiceServer<Self> {
+        MultiPartyServiceServer::new(self)
+    }
+
+    /// Serve the service on `addr` until the transport fails
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_service())
+            .serve(addr)
+            .await
+    }
+}
+
+#[tonic::async_trait]
+impl MultiPartyService for MpcService {
+    async fn share(
+        &self,
+        request: Request<ShareRequest>,
+    ) -> Result<Response<ShareResponse>, Status> {
+        let shares = request
+            .into_inner()
+            .shares
+            .into_iter()
+            .map(DataShare::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut server = self.server.lock().await;
+        server.receive_shares(shares).await.map_err(status)?;
+        Ok(Response::new(ShareResponse {
+            share_count: server.share_count() as u64,
+        }))
+    }
+
+    async fn shuffle(
+        &self,
+        request: Request<ShuffleRequest>,
+    ) -> Result<Response<ShuffleResponse>, Status> {
+        let records = records(request.into_inner().records)?;
+
+        let shuffled = self
+            .server
+            .lock()
+            .await
+            .participate_in_shuffle(records)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(ShuffleResponse {
+            records: shuffled
+                .iter()
+                .map(|record| RecordProto::from(record.as_slice()))
+                .collect(),
+        }))
+    }
+
+    async fn reconstruct(
+        &self,
+        request: Request<ReconstructRequest>,
+    ) -> Result<Response<ReconstructResponse>, Status> {
+        let records = records(request.into_inner().records)?;
+
+        let points = self
+            .server
+            .lock()
+            .await
+            .reconstruct_data(records)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(ReconstructResponse {
+            points: points.iter().map(DataPointProto::from).collect(),
+        }))
+    }
+
+    async fn query(
+        &self,
+        request: Request<QueryRequest>,
+    ) -> Result<Response<QueryResponse>, Status> {
+        let request = request.into_inner();
+        let kind = QueryKind::try_from(request.query_type).map_err(|_| {
+            Status::invalid_argument(format!("Unknown query type {}", request.query_type))
+        })?;
+        let query = Query::with_parameters(kind.into(), request.features, request.parameters);
+        let points = request.points.into_iter().map(DataPoint::from).collect();
+
+        let result = self
+            .server
+            .lock()
+            .await
+            .process_query(query, points)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(QueryResponse::from(&result)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::crypto::ThresholdEncryption;
+    use crate::multi_party::protocol::ProtocolConfig;
+    use crate::multi_party::server::ServerRole;
+
+    #[tokio::test]
+    async fn test_mpc_service() {
+        let mut dealer = ThresholdEncryption::new(2, 3).unwrap();
+        dealer.initialize().await.unwrap();
+        let mut records = Vec::new();
+        for features in [vec![1.0, 10.0], vec![2.0, 20.0]] {
+            records.push(dealer.share_data(DataPoint::new(features)).await.unwrap());
+        }
+
+        let mut server = MultiPartyServer::new(0, ServerRole::First, ProtocolConfig::default());
+        server.initialize().await.unwrap();
+        let service = MpcService::new(server);
+
+        let shares = records[0].iter().map(DataShareProto::from).collect();
+        let response = service
+            .share(Request::new(ShareRequest { shares }))
+            .await
+            .unwrap();
+        assert_eq!(response.into_inner().share_count, records[0].len() as u64);
+
+        let request = ShuffleRequest {
+            records: records
+                .iter()
+                .map(|r| RecordProto::from(r.as_slice()))
+                .collect(),
+        };
+        let shuffled = service.shuffle(Request::new(request)).await.unwrap();
+        let request = ReconstructRequest {
+            records: shuffled.into_inner().records,
+        };
+        let points = service.reconstruct(Request::new(request)).await.unwrap();
+        let mut features: Vec<Vec<f64>> = points
+            .into_inner()
+            .points
+            .into_iter()
+            .map(|p| p.features)
+            .collect();
+        features.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+        assert_eq!(features, vec![vec![1.0, 10.0], vec![2.0, 20.0]]);
+
+        let request = QueryRequest {
+            query_type: QueryKind::Mean as i32,
+            features: vec!["feature1".to_string()],
+            parameters: HashMap::new(),
+            points: vec![DataPointProto {
+                features: vec![1.0, 2.0],
+            }],
+        };
+        assert!(
+            service
+                .query(Request::new(request))
+                .await
+                .unwrap()
+                .into_inner()
+                .has_noise
+        );
+
+        let request = QueryRequest {
+            query_type: 99,
+            ..Default::default()
+        };
+        let error = service.query(Request::new(request)).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+    }
+}
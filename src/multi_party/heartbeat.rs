@@ -0,0 +1,236 @@
+use crate::multi_party::auth::MessageAuthenticator;
+use crate::multi_party::communication::{seal_message, NetworkMessage};
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::runtime::{self, Receiver, Sender, SharedRuntime};
+use crate::multi_party::secure::SecureSession;
+use futures::future::{self, Either};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Liveness of the channel to one server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeartbeatState {
+    /// Time on the runtime clock a message from the server last arrived
+    pub last_seen: Option<Duration>,
+    /// Time on the runtime clock a heartbeat was last sent to the server
+    pub last_sent: Option<Duration>,
+    /// Heartbeats sent to the server
+    pub sent: u64,
+    /// Whether the server was heard from within the maximum heartbeat age
+    pub alive: bool,
+    /// How strongly the server is suspected of having failed
+    pub suspicion: Suspicion,
+}
+
+/// How strongly the failure detector suspects a server of having failed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Suspicion {
+    /// The server was heard from recently
+    #[default]
+    Trusted,
+    /// The server missed its heartbeats, and may only be slow
+    Suspected,
+    /// The server was silent for too long and is taken to have failed
+    Failed,
+}
+
+/// Silences after which the failure detector suspects a server and declares it failed
+///
+/// A server declared failed is trusted again once it is heard from, so a
+/// server that was only cut off rejoins after a reconnect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailureDetector {
+    /// Silence after which a server is suspected
+    pub suspect_after: Duration,
+    /// Silence after which a server is declared failed
+    pub fail_after: Duration,
+}
+
+impl FailureDetector {
+    /// Suspicion of a server not heard from for `silence`
+    pub fn suspicion(&self, silence: Duration) -> Suspicion {
+        if silence > self.fail_after {
+            Suspicion::Failed
+        } else if silence > self.suspect_after {
+            Suspicion::Suspected
+        } else {
+            Suspicion::Trusted
+        }
+    }
+}
+
+impl HeartbeatState {
+    /// Record a message from the server at `now`
+    pub(crate) fn seen(&mut self, now: Duration) {
+        self.last_seen = Some(now);
+    }
+}
+
+/// Heartbeat state shared by a channel, which records what it receives, and the heartbeat task
+pub type SharedHeartbeat = Arc<Mutex<HeartbeatState>>;
+
+/// Called with the ID of a server and whether it is alive whenever that changes
+pub type LivenessCallback = Arc<dyn Fn(usize, bool) + Send + Sync>;
+
+/// Called with the ID of a server and its suspicion whenever that changes
+pub type SuspicionCallback = Arc<dyn Fn(usize, Suspicion) + Send + Sync>;
+
+/// Sends heartbeats on a channel without borrowing it
+#[derive(Clone)]
+pub(crate) struct HeartbeatSender {
+    pub(crate) local_id: usize,
+    pub(crate) target_id: usize,
+    pub(crate) sender: Sender<NetworkMessage>,
+    /// Session of an encrypted channel, shared with the channel
+    pub(crate) session: Option<Arc<futures::lock::Mutex<SecureSession>>>,
+    pub(crate) authenticator: Option<MessageAuthenticator>,
+    pub(crate) state: SharedHeartbeat,
+    /// Protocol version agreed on by the channel
+    pub(crate) version: u16,
+}
+
+impl HeartbeatSender {
+    async fn send(&self, sequence: u64) -> Result<(), ProtocolError> {
+        let mut message = NetworkMessage::heartbeat(self.local_id, self.target_id, sequence);
+        message.version = self.version;
+        if let Some(authenticator) = &self.authenticator {
+            authenticator.sign(&mut message)?;
+        }
+
+        // The session stays locked until the message is sent, to keep the
+        // messages of the channel in the order they were encrypted
+        let mut session = match &self.session {
+            Some(session) => Some(session.lock().await),
+            None => None,
+        };
+        if let Some(session) = &mut session {
+            message = seal_message(session, &message)?;
+        }
+        self.sender
+            .send(message)
+            .await
+            .map_err(|e| ProtocolError::network_error(format!("Failed to send heartbeat: {}", e)))
+    }
+}
+
+/// Stops the heartbeat task started by `NetworkManager::start`
+///
+/// Dropping the handle stops the task too, without waiting for it.
+pub struct HeartbeatHandle {
+    stop: Option<Sender<()>>,
+    stopped: Receiver<()>,
+}
+
+impl fmt::Debug for HeartbeatHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeartbeatHandle").finish_non_exhaustive()
+    }
+}
+
+impl HeartbeatHandle {
+    /// Stop the task and wait until it has stopped
+    pub async fn shutdown(mut self) {
+        self.stop.take();
+        self.stopped.recv().await;
+    }
+}
+
+/// Sends heartbeats to every linked server and tracks which ones are alive
+pub(crate) struct HeartbeatTask {
+    /// Channels to heartbeat, updated when they connect
+    pub(crate) links: Arc<Mutex<HashMap<usize, HeartbeatSender>>>,
+    pub(crate) interval: Duration,
+    pub(crate) max_age: Duration,
+    pub(crate) detector: FailureDetector,
+    pub(crate) runtime: SharedRuntime,
+    pub(crate) callbacks: Vec<LivenessCallback>,
+    pub(crate) suspicion_callbacks: Vec<SuspicionCallback>,
+}
+
+impl HeartbeatTask {
+    /// Run the task in the background until the returned handle stops it
+    pub(crate) fn spawn(self) -> HeartbeatHandle {
+        let (stop, mut stop_requested) = runtime::channel::<()>(1);
+        let (stopped_sender, stopped) = runtime::channel(1);
+        let runtime = self.runtime.clone();
+
+        runtime.spawn(Box::pin(async move {
+            // Servers never heard from are silent since the task started
+            let started = self.runtime.now();
+            let mut sequence = 0;
+            loop {
+                let stop = pin!(stop_requested.recv());
+                if let Either::Left(_) = future::select(stop, self.runtime.sleep(self.interval)).await {
+                    break;
+                }
+                self.beat(sequence, started).await;
+                sequence += 1;
+            }
+            // The handle may be gone already
+            let _ = stopped_sender.send(()).await;
+        }));
+
+        HeartbeatHandle {
+            stop: Some(stop),
+            stopped,
+        }
+    }
+
+    /// Send a heartbeat to every server and report changes of liveness and suspicion
+    async fn beat(&self, sequence: u64, started: Duration) {
+        let links = self.links.lock().unwrap().values().cloned().collect::<Vec<_>>();
+        for link in links {
+            let sent = link.send(sequence).await;
+            let now = self.runtime.now();
+
+            let (alive, suspicion) = {
+                let mut state = link.state.lock().unwrap();
+                match &sent {
+                    Ok(()) => {
+                        state.last_sent = Some(now);
+                        state.sent += 1;
+                    }
+                    Err(e) => log::warn!("Failed to send heartbeat to server {}: {}", link.target_id, e),
+                }
+                let alive = state.last_seen.is_some_and(|seen| now.saturating_sub(seen) <= self.max_age);
+                let suspicion = self.detector.suspicion(now.saturating_sub(state.last_seen.unwrap_or(started)));
+                (
+                    (alive != state.alive).then(|| {
+                        state.alive = alive;
+                        alive
+                    }),
+                    (suspicion != state.suspicion).then(|| {
+                        state.suspicion = suspicion;
+                        suspicion
+                    }),
+                )
+            };
+
+            if let Some(alive) = alive {
+                if alive {
+                    log::info!("Server {} is alive again", link.target_id);
+                } else {
+                    log::warn!("Server {} missed its heartbeats", link.target_id);
+                }
+                for callback in &self.callbacks {
+                    callback(link.target_id, alive);
+                }
+            }
+
+            if let Some(suspicion) = suspicion {
+                match suspicion {
+                    Suspicion::Trusted => log::info!("Server {} is trusted again", link.target_id),
+                    Suspicion::Suspected => log::warn!("Server {} is suspected of having failed", link.target_id),
+                    Suspicion::Failed => log::error!("Server {} is declared failed", link.target_id),
+                }
+                for callback in &self.suspicion_callbacks {
+                    callback(link.target_id, suspicion);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use crate::field::{shamir, PrimeField};
+use crate::multi_party::beaver::Ring;
+use crate::multi_party::crypto::{ShamirSecretSharing, SharingScheme, SHARE_MODULUS};
+use crate::multi_party::cross_check::{self, CommitmentOpening, ComputationCommitment};
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::share::DataShare;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// A server's share of the global MAC key `alpha`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacKeyShare {
+    /// Server holding the share
+    pub server_id: usize,
+    /// Share of `alpha`
+    pub value: u64,
+    /// Modulus of the share, always `SHARE_MODULUS`
+    pub modulus: u64,
+}
+
+/// Check that shares of `scheme` can be authenticated
+///
+/// Only Shamir shares can. Over Z_2^64, `alpha * 2^63` only depends on the
+/// parity of `alpha`, so a server that adds 2^63 to both its share and its
+/// MAC share passes the check half the time with a uniform key and always
+/// with an odd one. Additive shares would need a SPDZ2k-style MAC over a
+/// larger ring.
+fn check_scheme(scheme: SharingScheme) -> Result<(), ProtocolError> {
+    match scheme {
+        SharingScheme::Shamir => Ok(()),
+        SharingScheme::Additive => Err(ProtocolError::InvalidConfiguration(
+            "Additive shares modulo 2^64 cannot be authenticated".to_string(),
+        )),
+    }
+}
+
+/// MAC share carried by a share that must be authenticated
+fn mac_of(share: &DataShare) -> Result<u64, ProtocolError> {
+    share.mac.ok_or(ProtocolError::InvalidShare {
+        server_id: share.server_id,
+        feature_index: share.feature_index,
+    })
+}
+
+/// Dealer of the global MAC key and of SPDZ-style authenticated shares
+///
+/// Every share of a secret `x` carries a share of `alpha * x`, where `alpha`
+/// is shared among the servers and known to nobody but the dealer. A server
+/// that alters its share of `x` by `e` would also have to alter its MAC share
+/// by `alpha * e`, so `MacCheck` catches it unless it guesses `alpha`, which
+/// happens with probability 2^-64 over the Shamir field. Additive shares are
+/// rejected, see `check_scheme`.
+pub struct MacDealer {
+    shamir: ShamirSecretSharing,
+    key: u64,
+    key_shares: Vec<MacKeyShare>,
+}
+
+impl MacDealer {
+    /// Draw a MAC key and share it among `num_servers` servers with `scheme`, which must be `Shamir`
+    pub fn new(threshold: usize, num_servers: usize, scheme: SharingScheme) -> Result<Self, ProtocolError> {
+        check_scheme(scheme)?;
+        let mut dealer = Self {
+            shamir: ShamirSecretSharing::new(threshold, num_servers, SHARE_MODULUS)?,
+            key: rand::thread_rng().gen_range(1..SHARE_MODULUS),
+            key_shares: Vec::new(),
+        };
+
+        let modulus = dealer.modulus();
+        dealer.key_shares = dealer
+            .share(dealer.key)?
+            .into_iter()
+            .enumerate()
+            .map(|(server_id, value)| MacKeyShare {
+                server_id,
+                value,
+                modulus,
+            })
+            .collect();
+
+        Ok(dealer)
+    }
+
+    /// Modulus of the shares the dealer authenticates
+    pub fn modulus(&self) -> u64 {
+        SHARE_MODULUS
+    }
+
+    /// Shares of the MAC key, by server ID
+    pub fn key_shares(&self) -> &[MacKeyShare] {
+        &self.key_shares
+    }
+
+    /// Attach shares of the MAC of `secret` to its shares, one per server
+    pub fn authenticate(&self, secret: u64, shares: &mut [DataShare]) -> Result<(), ProtocolError> {
+        let modulus = self.modulus();
+        if let Some(share) = shares.iter().find(|share| share.modulus != modulus) {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Share modulo {} cannot be authenticated with a MAC key modulo {}",
+                share.modulus, modulus
+            )));
+        }
+
+        let field = PrimeField::new(SHARE_MODULUS)?;
+        let mac = field.mul(self.key, field.reduce(secret));
+        let mac_shares = self.share(mac)?;
+        for share in shares {
+            let mac = mac_shares.get(share.server_id).ok_or(ProtocolError::InvalidConfiguration(format!(
+                "No server {} among {} servers",
+                share.server_id,
+                mac_shares.len()
+            )))?;
+            share.mac = Some(*mac);
+        }
+        Ok(())
+    }
+
+    /// Share `secret` as authenticated feature shares of `feature_index`
+    pub fn share_secret(&self, secret: u64, feature_index: usize) -> Result<Vec<DataShare>, ProtocolError> {
+        let modulus = self.modulus();
+        let mut shares: Vec<DataShare> = self
+            .share(secret)?
+            .into_iter()
+            .enumerate()
+            .map(|(server_id, value)| DataShare::feature(server_id, feature_index, value, modulus))
+            .collect();
+        self.authenticate(secret, &mut shares)?;
+        Ok(shares)
+    }
+
+    /// Share a value among the servers, by server ID
+    fn share(&self, secret: u64) -> Result<Vec<u64>, ProtocolError> {
+        Ok(self.shamir.share_secret(secret)?.into_iter().map(|share| share.value).collect())
+    }
+}
+
+/// Add two authenticated shares of one server
+pub fn add(a: &DataShare, b: &DataShare) -> Result<DataShare, ProtocolError> {
+    if a.server_id != b.server_id || a.modulus != b.modulus {
+        return Err(ProtocolError::InvalidConfiguration(format!(
+            "Shares of server {} modulo {} cannot be added to shares of server {} modulo {}",
+            a.server_id, a.modulus, b.server_id, b.modulus
+        )));
+    }
+
+    let ring = Ring::of(a.modulus)?;
+    let mut sum = a.clone();
+    sum.value = ring.add(a.value, b.value);
+    sum.mac = Some(ring.add(mac_of(a)?, mac_of(b)?));
+    Ok(sum)
+}
+
+/// Multiply an authenticated share by a public constant
+pub fn multiply_public(share: &DataShare, constant: u64) -> Result<DataShare, ProtocolError> {
+    let ring = Ring::of(share.modulus)?;
+    let mut product = share.clone();
+    product.value = ring.mul(share.value, constant);
+    product.mac = Some(ring.mul(mac_of(share)?, constant));
+    Ok(product)
+}
+
+/// Add a public constant to an authenticated share, given the server's MAC key share
+///
+/// The constant is added to every Shamir share, and its MAC `alpha *
+/// constant` is added through the key share.
+pub fn add_public(share: &DataShare, constant: u64, key: &MacKeyShare) -> Result<DataShare, ProtocolError> {
+    if key.server_id != share.server_id || key.modulus != share.modulus {
+        return Err(ProtocolError::InvalidConfiguration(format!(
+            "MAC key share of server {} modulo {} does not match a share of server {} modulo {}",
+            key.server_id, key.modulus, share.server_id, share.modulus
+        )));
+    }
+
+    let ring = Ring::of(share.modulus)?;
+    let mut sum = share.clone();
+    sum.value = ring.add(share.value, constant);
+    sum.mac = Some(ring.add(mac_of(share)?, ring.mul(key.value, constant)));
+    Ok(sum)
+}
+
+/// MAC check of opened values before they are trusted
+///
+/// For each opened value `x'`, server `i` computes `sigma_i = gamma_i -
+/// alpha_i * x'` from its MAC share `gamma_i` and key share `alpha_i`. The
+/// `sigma_i` are shares of `alpha * (x - x')`, which is zero exactly when `x'`
+/// is the value that was authenticated. Servers `commit` to their `sigma_i`
+/// before opening them, so nobody can choose theirs after seeing the others,
+/// and `verify` checks that they combine to zero.
+pub struct MacCheck {
+    threshold: usize,
+}
+
+impl MacCheck {
+    /// Create a check of shares of `scheme`, which must be `Shamir`, among `num_servers` servers, any `threshold` of which reconstruct them
+    pub fn new(threshold: usize, num_servers: usize, scheme: SharingScheme) -> Result<Self, ProtocolError> {
+        check_scheme(scheme)?;
+        if threshold < 2 || threshold > num_servers {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Threshold {} must be between 2 and the number of servers {}",
+                threshold, num_servers
+            )));
+        }
+
+        Ok(Self { threshold })
+    }
+
+    /// Commit to this server's `sigma_i` for the values opened in `round`
+    ///
+    /// `shares` holds the server's shares of the opened values, in the order of `opened`.
+    pub fn commit(
+        &self,
+        round: usize,
+        key: &MacKeyShare,
+        shares: &[DataShare],
+        opened: &[u64],
+    ) -> Result<(ComputationCommitment, CommitmentOpening), ProtocolError> {
+        if shares.len() != opened.len() {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "{} shares do not match {} opened values",
+                shares.len(),
+                opened.len()
+            )));
+        }
+
+        let sigmas = shares
+            .iter()
+            .zip(opened)
+            .map(|(share, &value)| self.sigma(key, share, value))
+            .collect::<Result<_, _>>()?;
+        Ok(cross_check::commit(key.server_id, round, sigmas))
+    }
+
+    /// Check the openings of the servers' commitments for `round`
+    ///
+    /// Every failure is reported for `round`. A server whose opening does not
+    /// match its commitment is reported alone, and a MAC that does not match
+    /// an opened value is reported with every participating server, since any
+    /// of them may have altered it.
+    pub fn verify(
+        &self,
+        round: usize,
+        commitments: &[ComputationCommitment],
+        openings: &[CommitmentOpening],
+    ) -> Result<(), ProtocolError> {
+        for opening in openings {
+            let committed = commitments
+                .iter()
+                .any(|commitment| commitment.server_id == opening.server_id && opening.verify(commitment));
+            if opening.index != round || !committed {
+                return Err(ProtocolError::cheat_detected(round, vec![opening.server_id]));
+            }
+        }
+
+        let ids: Vec<usize> = openings.iter().map(|opening| opening.server_id).collect();
+        let count = openings.first().map_or(0, |opening| opening.values.len());
+        if let Some(opening) = openings.iter().find(|opening| opening.values.len() != count) {
+            return Err(ProtocolError::cheat_detected(round, vec![opening.server_id]));
+        }
+
+        for index in 0..count {
+            let sigmas: Vec<u64> = openings.iter().map(|opening| opening.values[index]).collect();
+            if self.combine(&ids, &sigmas)? != 0 {
+                log::warn!("MAC check of round {} failed on opened value {}", round, index);
+                return Err(ProtocolError::cheat_detected(round, ids));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the MAC of one opened value against all of its shares at once
+    ///
+    /// This runs both halves of the check in one process, e.g. in simulations
+    /// where one party holds every server's shares and key shares. A failure
+    /// is reported for `round`, as in `verify`.
+    pub fn check(&self, round: usize, keys: &[MacKeyShare], shares: &[DataShare], opened: u64) -> Result<(), ProtocolError> {
+        let mut ids = Vec::with_capacity(shares.len());
+        let mut sigmas = Vec::with_capacity(shares.len());
+        for share in shares {
+            let key = keys
+                .iter()
+                .find(|key| key.server_id == share.server_id)
+                .ok_or(ProtocolError::InvalidConfiguration(format!(
+                    "No MAC key share for server {}",
+                    share.server_id
+                )))?;
+            ids.push(share.server_id);
+            sigmas.push(self.sigma(key, share, opened)?);
+        }
+
+        if self.combine(&ids, &sigmas)? != 0 {
+            let feature_index = shares.first().map_or(0, |share| share.feature_index);
+            log::warn!("MAC check of round {} failed on feature {}", round, feature_index);
+            return Err(ProtocolError::cheat_detected(round, ids));
+        }
+        Ok(())
+    }
+
+    /// A server's share of `alpha * (x - opened)`
+    fn sigma(&self, key: &MacKeyShare, share: &DataShare, opened: u64) -> Result<u64, ProtocolError> {
+        if key.server_id != share.server_id || key.modulus != SHARE_MODULUS || share.modulus != SHARE_MODULUS {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "MAC key share of server {} modulo {} does not match a share of server {} modulo {}",
+                key.server_id, key.modulus, share.server_id, share.modulus
+            )));
+        }
+
+        let ring = Ring::of(SHARE_MODULUS)?;
+        Ok(ring.sub(mac_of(share)?, ring.mul(key.value, opened)))
+    }
+
+    /// Combine the `sigma_i` of the servers `ids` into `alpha * (x - opened)`
+    fn combine(&self, ids: &[usize], sigmas: &[u64]) -> Result<u64, ProtocolError> {
+        if ids.len() < self.threshold {
+            return Err(ProtocolError::InsufficientServers {
+                available: ids.len(),
+                required: self.threshold,
+            });
+        }
+
+        let field = PrimeField::new(SHARE_MODULUS)?;
+        let coefficients = shamir::lagrange_coefficients(&field, ids)?;
+        Ok(coefficients
+            .iter()
+            .zip(sigmas)
+            .fold(0, |sum, (&coefficient, &sigma)| field.add(sum, field.mul(coefficient, sigma))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::beaver::MultiplicationGate;
+
+    #[test]
+    fn test_mac_check() {
+        let gate = MultiplicationGate::new(2, 3).unwrap();
+        let dealer = MacDealer::new(2, 3, SharingScheme::Shamir).unwrap();
+        let keys = dealer.key_shares();
+        let check = MacCheck::new(2, 3, SharingScheme::Shamir).unwrap();
+
+        // 3 * x + y + 5, computed locally on authenticated shares
+        let x = dealer.share_secret(7, 0).unwrap();
+        let y = dealer.share_secret(11, 1).unwrap();
+        assert!(x.iter().all(DataShare::is_authenticated));
+        let z: Vec<DataShare> = (0..3)
+            .map(|i| add_public(&add(&multiply_public(&x[i], 3).unwrap(), &y[i]).unwrap(), 5, &keys[i]).unwrap())
+            .collect();
+        let opened = gate.open(&z).unwrap();
+        assert_eq!(opened, 37);
+        check.check(0, keys, &z, opened).unwrap();
+
+        // The servers commit to their sigmas before opening them
+        let (commitments, openings): (Vec<_>, Vec<_>) = (0..3)
+            .map(|i| check.commit(4, &keys[i], &[x[i].clone(), z[i].clone()], &[7, opened]).unwrap())
+            .unzip();
+        check.verify(4, &commitments, &openings).unwrap();
+        assert!(check.verify(5, &commitments, &openings).is_err());
+
+        // A server that alters its share is caught, even if the value it opens is plausible
+        let mut tampered = z.clone();
+        tampered[1].value = Ring::of(tampered[1].modulus).unwrap().add(tampered[1].value, 1);
+        let opened = gate.open(&tampered).unwrap();
+        let error = check.check(3, keys, &tampered, opened).unwrap_err();
+        assert_eq!(error.to_string(), ProtocolError::cheat_detected(3, vec![0, 1, 2]).to_string());
+
+        // The round is reported, not the position of the failing value
+        let (commitments, openings): (Vec<_>, Vec<_>) = (0..3)
+            .map(|i| check.commit(6, &keys[i], &[x[i].clone(), tampered[i].clone()], &[7, opened]).unwrap())
+            .unzip();
+        assert_eq!(
+            check.verify(6, &commitments, &openings).unwrap_err().to_string(),
+            ProtocolError::cheat_detected(6, vec![0, 1, 2]).to_string()
+        );
+
+        // So is one that changes its sigma after committing
+        let (commitments, mut openings): (Vec<_>, Vec<_>) =
+            (0..3).map(|i| check.commit(0, &keys[i], &z[i..=i], &[37]).unwrap()).unzip();
+        openings[2].values[0] ^= 1;
+        assert_eq!(
+            check.verify(0, &commitments, &openings).unwrap_err().to_string(),
+            ProtocolError::cheat_detected(0, vec![2]).to_string()
+        );
+
+        let mut bare = x.clone();
+        bare[0].mac = None;
+        assert!(check.check(0, keys, &bare, 7).unwrap_err().is_cheating());
+    }
+
+    #[test]
+    fn test_mac_check_catches_high_bit_errors() {
+        // Over Z_2^64 an odd key maps 2^63 to itself, so additive shares are not authenticated
+        assert!(MacDealer::new(2, 3, SharingScheme::Additive).is_err());
+        assert!(MacCheck::new(2, 3, SharingScheme::Additive).is_err());
+
+        let gate = MultiplicationGate::new(2, 3).unwrap();
+        let dealer = MacDealer::new(2, 3, SharingScheme::Shamir).unwrap();
+        let keys = dealer.key_shares();
+        let check = MacCheck::new(2, 3, SharingScheme::Shamir).unwrap();
+
+        // A server adds 2^63 to both its share and its MAC share
+        let mut shares = dealer.share_secret(7, 0).unwrap();
+        let ring = Ring::of(shares[1].modulus).unwrap();
+        shares[1].value = ring.add(shares[1].value, 1 << 63);
+        shares[1].mac = Some(ring.add(shares[1].mac.unwrap(), 1 << 63));
+        let opened = gate.open(&shares).unwrap();
+        assert!(check.check(0, keys, &shares, opened).unwrap_err().is_cheating());
+    }
+}
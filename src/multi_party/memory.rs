@@ -0,0 +1,283 @@
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::runtime::{self, Runtime};
+use crate::multi_party::share::DataShare;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// What to do when buffered shares would exceed the memory budget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until buffered shares are released
+    Backpressure,
+    /// Write the shares to files in the given directory
+    Spill(PathBuf),
+}
+
+/// Memory budget for shares buffered by a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareMemoryConfig {
+    /// Maximum number of bytes of shares held in memory
+    pub max_bytes: usize,
+    /// Behaviour once the budget is exhausted
+    pub overflow: OverflowPolicy,
+}
+
+impl ShareMemoryConfig {
+    /// Create a budget that applies backpressure
+    pub fn backpressure(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            overflow: OverflowPolicy::Backpressure,
+        }
+    }
+
+    /// Create a budget that spills to `dir`
+    pub fn spill(max_bytes: usize, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            max_bytes,
+            overflow: OverflowPolicy::Spill(dir.into()),
+        }
+    }
+}
+
+/// Estimate the in-memory size of a share in bytes
+pub fn share_size(share: &DataShare) -> usize {
+    let metadata: usize = share
+        .metadata
+        .iter()
+        .map(|(key, value)| key.capacity() + value.capacity())
+        .sum();
+
+    std::mem::size_of::<DataShare>() + metadata
+}
+
+/// Reservation of part of the memory budget, released on drop
+#[derive(Debug, Default)]
+pub struct MemoryReservation {
+    /// Number of bytes reserved
+    bytes: usize,
+    /// Permits backing the reservation
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl MemoryReservation {
+    /// Number of bytes reserved
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Combine another reservation into this one
+    pub fn merge(&mut self, other: MemoryReservation) {
+        self.bytes += other.bytes;
+        match (&mut self.permit, other.permit) {
+            (Some(permit), Some(other)) => permit.merge(other),
+            (slot @ None, other) => *slot = other,
+            (Some(_), None) => {}
+        }
+    }
+}
+
+/// Shares taken out of a server's buffer
+///
+/// The memory they occupy counts against the budget until this is dropped.
+#[derive(Debug)]
+pub struct BufferedShares {
+    /// Shares that were held in memory
+    pub shares: Vec<DataShare>,
+    /// Files holding shares that were spilled to disk
+    pub spilled: Vec<PathBuf>,
+    /// Reservation released when the shares are dropped
+    pub reservation: MemoryReservation,
+}
+
+impl BufferedShares {
+    /// Read back every share that was spilled to disk
+    pub fn load_spilled(&self) -> Result<Vec<DataShare>, ProtocolError> {
+        let mut shares = Vec::new();
+        for path in &self.spilled {
+            let file = File::open(path).map_err(|e| spill_error(path, e))?;
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|e| spill_error(path, e))?;
+                let share = serde_json::from_str(&line).map_err(|e| spill_error(path, e))?;
+                shares.push(share);
+            }
+        }
+
+        Ok(shares)
+    }
+}
+
+/// Memory accounting for buffered shares
+#[derive(Debug)]
+pub struct ShareMemory {
+    /// Memory budget configuration
+    config: ShareMemoryConfig,
+    /// Free budget in bytes
+    available: Arc<Semaphore>,
+    /// Prefix for spill file names
+    spill_prefix: String,
+    /// Number of spill files written
+    spill_count: AtomicUsize,
+}
+
+impl ShareMemory {
+    /// Create memory accounting for the server with the given ID
+    pub fn new(server_id: usize, config: ShareMemoryConfig) -> Result<Self, ProtocolError> {
+        if config.max_bytes == 0 || config.max_bytes > Semaphore::MAX_PERMITS {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Share memory budget must be between 1 and {} bytes",
+                Semaphore::MAX_PERMITS
+            )));
+        }
+
+        Ok(Self {
+            available: Arc::new(Semaphore::new(config.max_bytes)),
+            spill_prefix: format!("server-{}-{}", server_id, std::process::id()),
+            spill_count: AtomicUsize::new(0),
+            config,
+        })
+    }
+
+    /// Get the budget configuration
+    pub fn config(&self) -> &ShareMemoryConfig {
+        &self.config
+    }
+
+    /// Number of bytes currently free
+    pub fn available_bytes(&self) -> usize {
+        self.available.available_permits()
+    }
+
+    /// Reserve memory for `bytes`, waiting up to `timeout` on the runtime's
+    /// clock under backpressure
+    ///
+    /// Returns `None` if the shares should be spilled instead.
+    pub async fn reserve(
+        &self,
+        bytes: usize,
+        timeout: Duration,
+        runtime: &dyn Runtime,
+    ) -> Result<Option<MemoryReservation>, ProtocolError> {
+        if bytes == 0 {
+            return Ok(Some(MemoryReservation::default()));
+        }
+
+        if let Some(reservation) = self.try_reserve(bytes) {
+            return Ok(Some(reservation));
+        }
+
+        match &self.config.overflow {
+            OverflowPolicy::Spill(_) => Ok(None),
+            OverflowPolicy::Backpressure => {
+                if bytes > self.config.max_bytes {
+                    return Err(ProtocolError::server_error(format!(
+                        "Batch of {} bytes exceeds share memory budget of {} bytes",
+                        bytes, self.config.max_bytes
+                    )));
+                }
+
+                let permits = u32::try_from(bytes).map_err(|_| {
+                    ProtocolError::server_error(format!("Batch of {} bytes is too large to reserve", bytes))
+                })?;
+                let acquire = self.available.clone().acquire_many_owned(permits);
+                let permit = runtime::timeout(runtime, timeout, acquire)
+                    .await
+                    .map_err(|_| ProtocolError::timeout(timeout.as_millis() as u64))?
+                    .map_err(|_| ProtocolError::server_error("Share memory budget closed".to_string()))?;
+
+                Ok(Some(MemoryReservation {
+                    bytes,
+                    permit: Some(permit),
+                }))
+            }
+        }
+    }
+
+    /// Reserve memory for `bytes` without waiting
+    fn try_reserve(&self, bytes: usize) -> Option<MemoryReservation> {
+        let permits = u32::try_from(bytes).ok()?;
+        let permit = self.available.clone().try_acquire_many_owned(permits).ok()?;
+
+        Some(MemoryReservation {
+            bytes,
+            permit: Some(permit),
+        })
+    }
+
+    /// Write shares to a new spill file
+    pub fn spill(&self, shares: &[DataShare]) -> Result<PathBuf, ProtocolError> {
+        let OverflowPolicy::Spill(dir) = &self.config.overflow else {
+            return Err(ProtocolError::server_error("Spilling is not enabled".to_string()));
+        };
+
+        let index = self.spill_count.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("{}-{}.jsonl", self.spill_prefix, index));
+
+        let file = File::create(&path).map_err(|e| spill_error(&path, e))?;
+        let mut writer = BufWriter::new(file);
+        for share in shares {
+            serde_json::to_writer(&mut writer, share).map_err(|e| spill_error(&path, e))?;
+            writer.write_all(b"\n").map_err(|e| spill_error(&path, e))?;
+        }
+        writer.flush().map_err(|e| spill_error(&path, e))?;
+
+        Ok(path)
+    }
+}
+
+/// Create an error for a failed spill file operation
+fn spill_error(path: &std::path::Path, error: impl std::fmt::Display) -> ProtocolError {
+    ProtocolError::server_error(format!("Spill file {}: {}", path.display(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::runtime::{ManualRuntime, TokioRuntime};
+
+    fn shares(count: usize) -> Vec<DataShare> {
+        (0..count).map(|i| DataShare::feature(0, i, i as u64, 97)).collect()
+    }
+
+    #[test]
+    fn test_backpressure_waits_for_release() {
+        let runtime = ManualRuntime::new();
+        let size = share_size(&shares(1)[0]);
+        let memory = ShareMemory::new(0, ShareMemoryConfig::backpressure(2 * size)).unwrap();
+        let timeout = Duration::from_millis(20);
+
+        runtime.block_on(async {
+            let first = memory.reserve(2 * size, timeout, &runtime).await.unwrap().unwrap();
+            assert_eq!(memory.available_bytes(), 0);
+            assert!(memory.reserve(size, timeout, &runtime).await.unwrap_err().to_string().contains("timed out"));
+            assert_eq!(runtime.now(), timeout);
+
+            drop(first);
+            assert!(memory.reserve(size, timeout, &runtime).await.unwrap().is_some());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_spill_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = ShareMemory::new(1, ShareMemoryConfig::spill(1, dir.path())).unwrap();
+        let batch = shares(3);
+
+        let bytes: usize = batch.iter().map(share_size).sum();
+        assert!(memory.reserve(bytes, Duration::ZERO, &TokioRuntime).await.unwrap().is_none());
+
+        let buffered = BufferedShares {
+            shares: Vec::new(),
+            spilled: vec![memory.spill(&batch).unwrap()],
+            reservation: MemoryReservation::default(),
+        };
+        let loaded = buffered.load_spilled().unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded[2].value, 2);
+    }
+}
@@ -1 +1,66 @@
- 
\ No newline at end of file
+pub mod auth;
+pub mod beaver;
+pub mod broadcast;
+pub mod cancel;
+pub mod codec;
+pub mod communication;
+pub mod cross_check;
+pub mod crypto;
+pub mod driver;
+pub mod flow;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod heartbeat;
+pub mod mac;
+pub mod memory;
+pub mod outbox;
+pub mod paillier;
+pub mod pool;
+pub mod protocol;
+pub mod rebalance;
+pub mod reliable;
+pub mod runtime;
+pub mod secure;
+pub mod server;
+pub mod share;
+pub mod stream;
+pub mod timing;
+pub mod topology;
+pub mod traffic;
+pub mod transcript;
+pub mod transport;
+pub mod version;
+pub mod vss;
+pub mod waksman;
+pub mod zero_copy;
+
+pub use auth::MessageAuthenticator;
+pub use beaver::{BeaverTriple, MultiplicationGate, TripleDealer};
+pub use broadcast::{BroadcastMessage, ReliableBroadcast};
+pub use cancel::CancellationToken;
+pub use codec::{CodecKind, Compression, CompressionStats, Framing, MessageCodec};
+pub use cross_check::{CrossCheckConfig, CrossChecker};
+pub use driver::ProtocolDriver;
+pub use flow::{FlowControl, FlowMetrics};
+pub use heartbeat::{FailureDetector, HeartbeatHandle, HeartbeatState, Suspicion};
+pub use mac::{MacCheck, MacDealer, MacKeyShare};
+pub use memory::{OverflowPolicy, ShareMemoryConfig};
+pub use outbox::{Outbox, SessionState};
+pub use paillier::{Ciphertext, EncryptedAggregation, PaillierPrivateKey, PaillierPublicKey};
+pub use pool::{SharePools, VecPool};
+pub use protocol::{ProtocolConfig, ProtocolError, ProtocolPhase, ServerState};
+pub use rebalance::{MembershipChange, RebalancePlan};
+pub use reliable::RetryPolicy;
+pub use runtime::{ManualRuntime, Runtime, SharedRuntime, TokioRuntime};
+pub use secure::{IdentityConfig, ServerKeys};
+pub use server::{MultiPartyServer, ServerRole};
+pub use stream::{ShareStreamReader, ShareStreamWriter};
+pub use timing::{PhaseRecorder, PhaseTimings};
+pub use topology::{PeerConfig, Topology};
+pub use traffic::{MessageCount, TrafficCounts, TrafficStats};
+pub use transcript::{Transcript, TranscriptRecorder};
+pub use transport::TcpTransport;
+pub use version::VersionRange;
+pub use vss::{PedersenVss, ShareCommitment};
+pub use waksman::{ObliviousShuffle, WaksmanNetwork};
+pub use zero_copy::MessageView;
@@ -0,0 +1,134 @@
+use crate::multi_party::communication::NetworkMessage;
+use crate::multi_party::protocol::{ProtocolError, ProtocolPhase};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// State of one channel in a `SessionState`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChannelState {
+    /// Sequence number of the next message sent
+    pub sequence_counter: u64,
+    /// Messages sent and not acknowledged yet
+    pub unacked: Vec<NetworkMessage>,
+    /// Source and sequence number of the most recently delivered messages, oldest first
+    pub delivered: Vec<(usize, u64)>,
+    /// Sequence number of the next message sent on an ordered channel
+    #[serde(default)]
+    pub next_ordered: u64,
+    /// Sequence number of the next message delivered on an ordered channel
+    #[serde(default)]
+    pub next_delivery: u64,
+    /// Messages received on an ordered channel and not delivered yet
+    #[serde(default)]
+    pub held: Vec<NetworkMessage>,
+}
+
+/// Where a server is in the protocol and what its peers have not acknowledged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionState {
+    /// Server the state belongs to
+    pub server_id: usize,
+    /// Phase the server was in
+    pub phase: ProtocolPhase,
+    /// State of the channel to each server
+    pub channels: BTreeMap<usize, ChannelState>,
+}
+
+/// File a `NetworkManager` saves its `SessionState` to, to resume after a restart
+///
+/// Messages are saved as they were before encryption, so the file must be
+/// protected like the shares themselves.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    path: PathBuf,
+}
+
+fn outbox_error(path: &Path, error: impl std::fmt::Display) -> ProtocolError {
+    ProtocolError::server_error(format!("Outbox {}: {}", path.display(), error))
+}
+
+impl Outbox {
+    /// Save the state to `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Path of the state file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the saved state, if there is one
+    pub fn load(&self) -> Result<Option<SessionState>, ProtocolError> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(outbox_error(&self.path, e)),
+        };
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| outbox_error(&self.path, e))
+    }
+
+    /// Replace the saved state
+    ///
+    /// The state is written to a temporary file first, so a crash leaves
+    /// either the old or the new state.
+    pub fn save(&self, state: &SessionState) -> Result<(), ProtocolError> {
+        let bytes = serde_json::to_vec(state).map_err(|e| outbox_error(&self.path, e))?;
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, bytes).map_err(|e| outbox_error(&self.path, e))?;
+        fs::rename(&temporary, &self.path).map_err(|e| outbox_error(&self.path, e))
+    }
+
+    /// Delete the saved state
+    pub fn clear(&self) -> Result<(), ProtocolError> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(outbox_error(&self.path, e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outbox_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let outbox = Outbox::new(dir.path().join("session.json"));
+        assert!(outbox.load().unwrap().is_none());
+
+        let channel = ChannelState {
+            sequence_counter: 8,
+            unacked: vec![NetworkMessage::shuffle(0, 1, 7, vec![1, 0])],
+            delivered: vec![(1, 3), (1, 4)],
+            next_ordered: 2,
+            next_delivery: 5,
+            held: vec![NetworkMessage::shuffle(1, 0, 6, vec![0, 1])],
+        };
+        let state = SessionState {
+            server_id: 0,
+            phase: ProtocolPhase::Shuffling,
+            channels: BTreeMap::from([(1, channel)]),
+        };
+        outbox.save(&state).unwrap();
+
+        let loaded = outbox.load().unwrap().unwrap();
+        assert_eq!(loaded.phase, ProtocolPhase::Shuffling);
+        assert_eq!(loaded.channels[&1].sequence_counter, 8);
+        assert_eq!(loaded.channels[&1].unacked[0].sequence, 7);
+        assert_eq!(loaded.channels[&1].delivered, [(1, 3), (1, 4)]);
+        assert_eq!(loaded.channels[&1].next_delivery, 5);
+        assert_eq!(loaded.channels[&1].held[0].sequence, 6);
+
+        outbox.clear().unwrap();
+        outbox.clear().unwrap();
+        assert!(outbox.load().unwrap().is_none());
+    }
+}
@@ -0,0 +1,407 @@
+use crate::multi_party::protocol::ProtocolError;
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Bits of the modulus of keys generated for production use
+pub const DEFAULT_KEY_BITS: usize = 2048;
+
+/// Smallest modulus `PaillierPrivateKey::generate` accepts, so that sums of
+/// `u64` plaintexts decrypt without wrapping
+pub const MIN_KEY_BITS: usize = 256;
+
+/// Largest modulus a received public key may have, bounding the work its
+/// operations and the ciphertexts under it can cause
+pub const MAX_KEY_BITS: usize = 8192;
+
+/// Miller-Rabin rounds for the primes of a key
+const PRIMALITY_ROUNDS: usize = 40;
+
+/// Small primes dividing out most candidates before Miller-Rabin
+const SMALL_PRIMES: [u32; 24] = [3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97];
+
+/// Paillier ciphertext, an integer modulo `n^2`
+///
+/// A received ciphertext is only known to be positive and below the square
+/// of the largest modulus; `PaillierPublicKey::check` checks it against `n^2`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "Vec<u8>", try_from = "Vec<u8>")]
+pub struct Ciphertext(BigUint);
+
+impl From<Ciphertext> for Vec<u8> {
+    fn from(ciphertext: Ciphertext) -> Self {
+        ciphertext.0.to_bytes_be()
+    }
+}
+
+impl TryFrom<Vec<u8>> for Ciphertext {
+    type Error = ProtocolError;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        let value = BigUint::from_bytes_be(&bytes);
+        if value.is_zero() || value.bits() as usize > 2 * MAX_KEY_BITS {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "Paillier ciphertext must be positive and below 2^{}",
+                2 * MAX_KEY_BITS
+            )));
+        }
+
+        Ok(Self(value))
+    }
+}
+
+/// Public key of the Paillier cryptosystem, with generator `n + 1`
+///
+/// Anyone holding it can encrypt, add ciphertexts and multiply them by
+/// plaintexts; only the holder of the matching `PaillierPrivateKey` can
+/// decrypt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "Vec<u8>", try_from = "Vec<u8>")]
+pub struct PaillierPublicKey {
+    n: BigUint,
+    n_squared: BigUint,
+}
+
+impl From<PaillierPublicKey> for Vec<u8> {
+    fn from(key: PaillierPublicKey) -> Self {
+        key.n.to_bytes_be()
+    }
+}
+
+impl TryFrom<Vec<u8>> for PaillierPublicKey {
+    type Error = ProtocolError;
+
+    /// Decode a received key, refusing even moduli and moduli of fewer than
+    /// `MIN_KEY_BITS` or more than `MAX_KEY_BITS` bits
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        let n = BigUint::from_bytes_be(&bytes);
+        let bits = n.bits() as usize;
+        if n.is_even() || !(MIN_KEY_BITS..=MAX_KEY_BITS).contains(&bits) {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "Paillier modulus must be odd and have between {} and {} bits",
+                MIN_KEY_BITS, MAX_KEY_BITS
+            )));
+        }
+
+        Ok(Self::new(n))
+    }
+}
+
+impl PaillierPublicKey {
+    fn new(n: BigUint) -> Self {
+        let n_squared = &n * &n;
+        Self { n, n_squared }
+    }
+
+    /// Bits of the modulus `n`
+    pub fn bits(&self) -> usize {
+        self.n.bits() as usize
+    }
+
+    /// Encrypt `plaintext` with fresh randomness
+    pub fn encrypt(&self, plaintext: u64) -> Ciphertext {
+        // (1 + n)^m = 1 + m * n modulo n^2
+        let g_m = (BigUint::one() + BigUint::from(plaintext) * &self.n) % &self.n_squared;
+        Ciphertext(g_m * self.randomizer() % &self.n_squared)
+    }
+
+    /// Encryption of zero that hides nothing, the neutral element of `add`
+    pub fn zero(&self) -> Ciphertext {
+        Ciphertext(BigUint::one())
+    }
+
+    /// Add the plaintexts of two ciphertexts
+    pub fn add(&self, a: &Ciphertext, b: &Ciphertext) -> Ciphertext {
+        Ciphertext(&a.0 * &b.0 % &self.n_squared)
+    }
+
+    /// Multiply the plaintext of a ciphertext by `plaintext`
+    pub fn multiply(&self, ciphertext: &Ciphertext, plaintext: u64) -> Ciphertext {
+        Ciphertext(ciphertext.0.modpow(&BigUint::from(plaintext), &self.n_squared))
+    }
+
+    /// Add the plaintexts of many ciphertexts
+    pub fn sum<'a>(&self, ciphertexts: impl IntoIterator<Item = &'a Ciphertext>) -> Ciphertext {
+        ciphertexts
+            .into_iter()
+            .fold(self.zero(), |sum, ciphertext| self.add(&sum, ciphertext))
+    }
+
+    /// Rerandomize a ciphertext, so it cannot be linked to the original
+    pub fn rerandomize(&self, ciphertext: &Ciphertext) -> Ciphertext {
+        Ciphertext(&ciphertext.0 * self.randomizer() % &self.n_squared)
+    }
+
+    /// Check that a ciphertext received from elsewhere is a unit modulo `n^2`
+    pub fn check(&self, ciphertext: &Ciphertext) -> Result<(), ProtocolError> {
+        if ciphertext.0.is_zero() || ciphertext.0 >= self.n_squared || !ciphertext.0.gcd(&self.n).is_one() {
+            return Err(ProtocolError::MalformedMessage(
+                "Paillier ciphertext is not a unit modulo n^2".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// `r^n` modulo `n^2` for a random unit `r` modulo `n`
+    fn randomizer(&self) -> BigUint {
+        let mut rng = rand::thread_rng();
+        loop {
+            let r = random_below(&mut rng, &self.n);
+            if !r.is_zero() && r.gcd(&self.n).is_one() {
+                return r.modpow(&self.n, &self.n_squared);
+            }
+        }
+    }
+}
+
+/// Private key of the Paillier cryptosystem
+pub struct PaillierPrivateKey {
+    public_key: PaillierPublicKey,
+    lambda: BigUint,
+    mu: BigUint,
+}
+
+impl PaillierPrivateKey {
+    /// Generate a key whose modulus has `bits` bits, `DEFAULT_KEY_BITS` for production use
+    pub fn generate(bits: usize) -> Result<Self, ProtocolError> {
+        if bits < MIN_KEY_BITS || !bits.is_multiple_of(2) {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Paillier keys need an even number of at least {} bits, not {}",
+                MIN_KEY_BITS, bits
+            )));
+        }
+
+        let mut rng = rand::thread_rng();
+        let p = random_prime(&mut rng, bits / 2);
+        let q = loop {
+            let q = random_prime(&mut rng, bits / 2);
+            if q != p {
+                break q;
+            }
+        };
+        Self::from_primes(p, q)
+    }
+
+    /// Key with modulus `p * q`, for primes of equal length
+    fn from_primes(p: BigUint, q: BigUint) -> Result<Self, ProtocolError> {
+        let n = &p * &q;
+        let lambda = (&p - 1u32).lcm(&(&q - 1u32));
+        let mu = lambda.modinv(&n).ok_or_else(|| {
+            ProtocolError::InvalidConfiguration("Paillier primes must not divide each other's predecessor".to_string())
+        })?;
+
+        Ok(Self {
+            public_key: PaillierPublicKey::new(n),
+            lambda,
+            mu,
+        })
+    }
+
+    /// Public key to hand to the encrypting parties
+    pub fn public_key(&self) -> &PaillierPublicKey {
+        &self.public_key
+    }
+
+    /// Decrypt a ciphertext
+    ///
+    /// Fails if the ciphertext is malformed or its plaintext, e.g. a sum of
+    /// too many large values, does not fit in a `u128`.
+    pub fn decrypt(&self, ciphertext: &Ciphertext) -> Result<u128, ProtocolError> {
+        let key = &self.public_key;
+        key.check(ciphertext)?;
+
+        // m = L(c^lambda mod n^2) * mu mod n, with L(x) = (x - 1) / n
+        let x = ciphertext.0.modpow(&self.lambda, &key.n_squared);
+        let plaintext = (x - 1u32) / &key.n * &self.mu % &key.n;
+        let digits = plaintext.to_u64_digits();
+        match digits.as_slice() {
+            [] => Ok(0),
+            [low] => Ok(*low as u128),
+            [low, high] => Ok((*high as u128) << 64 | *low as u128),
+            _ => Err(ProtocolError::MalformedMessage(
+                "Paillier plaintext does not fit in 128 bits".to_string(),
+            )),
+        }
+    }
+}
+
+/// Uniformly random integer below `bound`
+fn random_below<R: RngCore>(rng: &mut R, bound: &BigUint) -> BigUint {
+    let bits = bound.bits();
+    let mut bytes = vec![0u8; bits.div_ceil(8) as usize];
+    loop {
+        rng.fill_bytes(&mut bytes);
+        let excess = bytes.len() as u64 * 8 - bits;
+        bytes[0] &= 0xFF >> excess;
+        let candidate = BigUint::from_bytes_be(&bytes);
+        if &candidate < bound {
+            return candidate;
+        }
+    }
+}
+
+/// Random prime of exactly `bits` bits, with the top two set so products of two have twice as many
+fn random_prime<R: RngCore>(rng: &mut R, bits: usize) -> BigUint {
+    let mut bytes = vec![0u8; bits.div_ceil(8)];
+    loop {
+        rng.fill_bytes(&mut bytes);
+        let mut candidate = BigUint::from_bytes_be(&bytes) >> (bytes.len() * 8 - bits);
+        candidate.set_bit(bits as u64 - 1, true);
+        candidate.set_bit(bits as u64 - 2, true);
+        candidate.set_bit(0, true);
+        if is_probable_prime(rng, &candidate) {
+            return candidate;
+        }
+    }
+}
+
+/// Miller-Rabin test of an odd candidate above the small primes
+fn is_probable_prime<R: RngCore>(rng: &mut R, candidate: &BigUint) -> bool {
+    if SMALL_PRIMES.iter().any(|&prime| (candidate % prime).is_zero()) {
+        return false;
+    }
+
+    let minus_one = candidate - 1u32;
+    let shift = minus_one.trailing_zeros().expect("candidate is odd and above one");
+    let odd = &minus_one >> shift;
+    let two = BigUint::from(2u32);
+    'witness: for _ in 0..PRIMALITY_ROUNDS {
+        let base = random_below(rng, &(candidate - 3u32)) + 2u32;
+        let mut x = base.modpow(&odd, candidate);
+        if x.is_one() || x == minus_one {
+            continue;
+        }
+        for _ in 1..shift {
+            x = x.modpow(&two, candidate);
+            if x == minus_one {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+
+    true
+}
+
+/// Sums of encrypted rows, feature by feature, under one public key
+///
+/// Servers aggregate client values they cannot read, and only the holder of
+/// the private key decrypts the totals.
+pub struct EncryptedAggregation {
+    public_key: PaillierPublicKey,
+    sums: Vec<Ciphertext>,
+    count: usize,
+}
+
+impl EncryptedAggregation {
+    /// Start aggregating rows of `num_features` ciphertexts
+    pub fn new(public_key: PaillierPublicKey, num_features: usize) -> Self {
+        let sums = vec![public_key.zero(); num_features];
+        Self {
+            public_key,
+            sums,
+            count: 0,
+        }
+    }
+
+    /// Add a row of ciphertexts, one per feature
+    pub fn push(&mut self, row: &[Ciphertext]) -> Result<(), ProtocolError> {
+        if row.len() != self.sums.len() {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "Encrypted row has {} features instead of {}",
+                row.len(),
+                self.sums.len()
+            )));
+        }
+        for ciphertext in row {
+            self.public_key.check(ciphertext)?;
+        }
+
+        for (sum, ciphertext) in self.sums.iter_mut().zip(row) {
+            *sum = self.public_key.add(sum, ciphertext);
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Number of rows aggregated so far
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether no rows were aggregated
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Encrypted sums of the features, rerandomized so they cannot be linked to the rows
+    pub fn finish(self) -> Vec<Ciphertext> {
+        self.sums
+            .iter()
+            .map(|sum| self.public_key.rerandomize(sum))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paillier_encryption() {
+        let key = PaillierPrivateKey::generate(MIN_KEY_BITS).unwrap();
+        let public_key = key.public_key();
+        assert_eq!(public_key.bits(), MIN_KEY_BITS);
+
+        let value = 42u64;
+        let encrypted = public_key.encrypt(value);
+        assert_ne!(encrypted, public_key.encrypt(value));
+        assert_eq!(key.decrypt(&encrypted).unwrap(), 42);
+
+        let large = public_key.encrypt(u64::MAX);
+        assert_eq!(key.decrypt(&public_key.add(&encrypted, &large)).unwrap(), u64::MAX as u128 + 42);
+        assert_eq!(key.decrypt(&public_key.multiply(&large, 4)).unwrap(), u64::MAX as u128 * 4);
+        assert_eq!(key.decrypt(&public_key.rerandomize(&encrypted)).unwrap(), 42);
+
+        // Keys and ciphertexts survive serialization
+        let public_key: PaillierPublicKey = serde_json::from_str(&serde_json::to_string(public_key).unwrap()).unwrap();
+        let encrypted: Ciphertext = serde_json::from_str(&serde_json::to_string(&public_key.encrypt(7)).unwrap()).unwrap();
+        assert_eq!(key.decrypt(&encrypted).unwrap(), 7);
+
+        assert!(key.decrypt(&Ciphertext(BigUint::zero())).is_err());
+
+        // Malformed keys and ciphertexts from a peer are refused when decoded
+        let decode_key = |n: BigUint| serde_json::from_str::<PaillierPublicKey>(&serde_json::to_string(&n.to_bytes_be()).unwrap());
+        assert!(decode_key(BigUint::zero()).is_err());
+        assert!(decode_key(BigUint::one()).is_err());
+        assert!(decode_key(BigUint::one() << MIN_KEY_BITS).is_err());
+        assert!(decode_key((BigUint::one() << (MIN_KEY_BITS - 2)) + 1u32).is_err());
+        assert!(decode_key((BigUint::one() << MAX_KEY_BITS) + 1u32).is_err());
+        let decode_ciphertext = |c: BigUint| serde_json::from_str::<Ciphertext>(&serde_json::to_string(&c.to_bytes_be()).unwrap());
+        assert!(decode_ciphertext(BigUint::zero()).is_err());
+        assert!(decode_ciphertext(BigUint::one() << (2 * MAX_KEY_BITS)).is_err());
+        let too_large = decode_ciphertext(public_key.n_squared.clone()).unwrap();
+        assert!(key.decrypt(&too_large).is_err());
+        assert!(PaillierPrivateKey::generate(128).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_aggregation() {
+        let key = PaillierPrivateKey::generate(MIN_KEY_BITS).unwrap();
+        let public_key = key.public_key();
+
+        let mut aggregation = EncryptedAggregation::new(public_key.clone(), 2);
+        for row in [[1u64, 10], [2, 20], [3, 30]] {
+            let row: Vec<Ciphertext> = row.iter().map(|&value| public_key.encrypt(value)).collect();
+            aggregation.push(&row).unwrap();
+        }
+        assert!(aggregation.push(&[public_key.encrypt(1)]).is_err());
+        assert_eq!(aggregation.len(), 3);
+
+        let sums: Vec<u128> = aggregation.finish().iter().map(|sum| key.decrypt(sum).unwrap()).collect();
+        assert_eq!(sums, vec![6, 60]);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::multi_party::crypto::SecretShare;
+use crate::multi_party::share::DataShare;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Counters of a `VecPool`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Vectors handed out from the pool
+    pub hits: usize,
+    /// Vectors allocated because the pool was empty
+    pub misses: usize,
+    /// Vectors currently held by the pool
+    pub pooled: usize,
+}
+
+/// Pool of reusable vectors
+///
+/// The sharing and shuffle hot paths allocate a vector per secret, record, and
+/// round. Taking vectors from a pool and giving them back once consumed keeps
+/// their capacity, so after warm-up these paths stop hitting the allocator. A
+/// pool holding at most zero vectors allocates and frees as usual.
+#[derive(Debug)]
+pub struct VecPool<T> {
+    /// Cleared vectors ready for reuse
+    free: Mutex<Vec<Vec<T>>>,
+    /// Maximum number of vectors kept
+    max_pooled: usize,
+    /// Vectors handed out from the pool
+    hits: AtomicUsize,
+    /// Vectors allocated because the pool was empty
+    misses: AtomicUsize,
+}
+
+impl<T> VecPool<T> {
+    /// Create a pool that keeps at most `max_pooled` vectors
+    pub fn new(max_pooled: usize) -> Self {
+        Self {
+            free: Mutex::new(Vec::new()),
+            max_pooled,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Take an empty vector, reusing a pooled one if there is any
+    pub fn take(&self) -> Vec<T> {
+        match self.free.lock().unwrap().pop() {
+            Some(vec) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                vec
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Take an empty vector with room for at least `capacity` elements
+    pub fn take_with_capacity(&self, capacity: usize) -> Vec<T> {
+        let mut vec = self.take();
+        vec.reserve(capacity);
+        vec
+    }
+
+    /// Give a vector back to the pool, dropping its elements
+    ///
+    /// The vector is freed instead if the pool is full or it never allocated.
+    pub fn give(&self, mut vec: Vec<T>) {
+        if vec.capacity() == 0 {
+            return;
+        }
+        vec.clear();
+
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.max_pooled {
+            free.push(vec);
+        }
+    }
+
+    /// Get the pool counters
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            pooled: self.free.lock().unwrap().len(),
+        }
+    }
+}
+
+/// Pools for the vectors of the sharing and shuffle hot paths
+///
+/// One set of pools can be shared by the servers and dealers of a process.
+#[derive(Debug)]
+pub struct SharePools {
+    /// Shares of a single secret
+    pub secret_shares: VecPool<SecretShare>,
+    /// Field elements, e.g. polynomial coefficients
+    pub field_elements: VecPool<u64>,
+    /// Records of shares being shuffled
+    pub records: VecPool<Vec<DataShare>>,
+    /// Permutations and other index vectors
+    pub indices: VecPool<usize>,
+}
+
+impl SharePools {
+    /// Create pools that each keep at most `max_pooled` vectors
+    pub fn new(max_pooled: usize) -> Self {
+        Self {
+            secret_shares: VecPool::new(max_pooled),
+            field_elements: VecPool::new(max_pooled),
+            records: VecPool::new(max_pooled),
+            indices: VecPool::new(max_pooled),
+        }
+    }
+
+    /// Create pools that keep nothing, so every vector is allocated as usual
+    pub fn disabled() -> Self {
+        Self::new(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vec_pool() {
+        let pool = VecPool::<u64>::new(1);
+        let mut first = pool.take_with_capacity(100);
+        first.extend(0..100);
+        let capacity = first.capacity();
+        pool.give(first);
+        pool.give(Vec::with_capacity(10));
+
+        let reused = pool.take();
+        assert!(reused.is_empty());
+        assert_eq!(reused.capacity(), capacity);
+        assert_eq!(pool.stats(), PoolStats { hits: 1, misses: 1, pooled: 0 });
+
+        let disabled = SharePools::disabled();
+        disabled.indices.give(vec![1, 2, 3]);
+        assert_eq!(disabled.indices.stats().pooled, 0);
+    }
+}
@@ -1 +1,407 @@
- 
\ No newline at end of file
+use crate::arith::PrivacyBudget;
+use crate::config::{self, ConfigError};
+use crate::multi_party::cross_check::CrossCheckConfig;
+use crate::multi_party::crypto::SharingScheme;
+use crate::multi_party::memory::ShareMemoryConfig;
+use crate::multi_party::version::VersionRange;
+use crate::schema::QueryType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Configuration for the multi-party protocol
+#[derive(Debug, Clone)]
+pub struct ProtocolConfig {
+    /// Number of servers
+    pub num_servers: usize,
+    /// Threshold for secret sharing
+    pub threshold: usize,
+    /// Privacy budget for noise generation
+    pub privacy_budget: PrivacyBudget,
+    /// Timeout for a single protocol step in milliseconds
+    pub timeout_ms: u64,
+    /// Timeouts of whole phases in milliseconds, for the phases not timed by `timeout_ms`
+    pub phase_timeouts: BTreeMap<ProtocolPhase, u64>,
+    /// Redundant cross-checking of computations (disabled when `None`)
+    pub cross_check: Option<CrossCheckConfig>,
+    /// Memory budget for buffered shares (unbounded when `None`)
+    pub share_memory: Option<ShareMemoryConfig>,
+    /// Scheme data is shared with, e.g. `Additive` for sums and histograms
+    pub sharing: SharingScheme,
+    /// Attach SPDZ-style MACs to shares and check them before reconstruction (Shamir sharing only)
+    pub authenticated_shares: bool,
+}
+
+impl ProtocolConfig {
+    /// Create a configuration for `num_servers` servers with reconstruction threshold `threshold`
+    pub fn with_servers(num_servers: usize, threshold: usize) -> Result<Self, ProtocolError> {
+        let config = Self {
+            num_servers,
+            threshold,
+            ..Self::default()
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Load a configuration from a TOML or YAML file
+    ///
+    /// Missing fields take their default values. Cross-checking and the share
+    /// memory budget are enabled by their own tables, e.g.
+    ///
+    /// ```toml
+    /// num_servers = 5
+    /// threshold = 3
+    /// epsilon = 0.5
+    /// authenticated_shares = true
+    ///
+    /// [phase_timeouts]
+    /// Shuffling = 120000
+    ///
+    /// [cross_check]
+    /// sample_rate = 0.1
+    ///
+    /// [share_memory]
+    /// max_bytes = 1073741824
+    /// spill_dir = "/var/tmp/doppio"
+    /// ```
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let config = config::load(path.as_ref(), |file: &ProtocolConfigFile| {
+            file.to_config().map(|_| ())
+        })?;
+        Ok(config.to_config().expect("validated by load"))
+    }
+
+    /// Validate the server count and threshold
+    pub fn validate(&self) -> Result<(), ProtocolError> {
+        if self.threshold < 2 {
+            return Err(ProtocolError::InvalidConfiguration(
+                "Threshold must be at least 2".to_string(),
+            ));
+        }
+
+        if self.threshold > self.num_servers {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Threshold {} exceeds number of servers {}",
+                self.threshold, self.num_servers
+            )));
+        }
+
+        if self.authenticated_shares && self.sharing == SharingScheme::Additive {
+            return Err(ProtocolError::InvalidConfiguration(
+                "Additive shares cannot be authenticated".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Time a protocol run may spend in `phase`, `timeout_ms` unless set in `phase_timeouts`
+    pub fn phase_timeout(&self, phase: ProtocolPhase) -> Duration {
+        Duration::from_millis(self.phase_timeouts.get(&phase).copied().unwrap_or(self.timeout_ms))
+    }
+
+    /// Number of shuffle rounds
+    ///
+    /// Up to `threshold - 1` servers may collude, so `threshold` rounds with
+    /// distinct leaders guarantee at least one honestly generated permutation.
+    pub fn shuffle_rounds(&self) -> usize {
+        self.threshold
+    }
+
+    /// Server that chooses the permutation in the given shuffle round
+    pub fn round_leader(&self, round: usize) -> usize {
+        round % self.num_servers
+    }
+}
+
+/// `ProtocolConfig` as written in a configuration file
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ProtocolConfigFile {
+    num_servers: usize,
+    threshold: usize,
+    epsilon: f64,
+    delta: f64,
+    timeout_ms: u64,
+    phase_timeouts: BTreeMap<ProtocolPhase, u64>,
+    cross_check: Option<CrossCheckFile>,
+    share_memory: Option<ShareMemoryFile>,
+    sharing: SharingScheme,
+    authenticated_shares: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CrossCheckFile {
+    sample_rate: f64,
+}
+
+/// Shares spill to `spill_dir` once `max_bytes` are buffered, or apply
+/// backpressure if it is not set
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShareMemoryFile {
+    max_bytes: usize,
+    spill_dir: Option<PathBuf>,
+}
+
+impl Default for ProtocolConfigFile {
+    fn default() -> Self {
+        let config = ProtocolConfig::default();
+        Self {
+            num_servers: config.num_servers,
+            threshold: config.threshold,
+            epsilon: config.privacy_budget.epsilon(),
+            delta: config.privacy_budget.delta(),
+            timeout_ms: config.timeout_ms,
+            phase_timeouts: config.phase_timeouts,
+            cross_check: None,
+            share_memory: None,
+            sharing: config.sharing,
+            authenticated_shares: config.authenticated_shares,
+        }
+    }
+}
+
+impl ProtocolConfigFile {
+    /// Build and validate the configuration
+    fn to_config(&self) -> Result<ProtocolConfig, String> {
+        config::validate_budget(self.epsilon, self.delta)?;
+        let cross_check = self
+            .cross_check
+            .as_ref()
+            .map(|cross_check| CrossCheckConfig::new(cross_check.sample_rate))
+            .transpose()
+            .map_err(|e| e.to_string())?;
+        let share_memory = self.share_memory.as_ref().map(|share_memory| {
+            match &share_memory.spill_dir {
+                Some(spill_dir) => ShareMemoryConfig::spill(share_memory.max_bytes, spill_dir.clone()),
+                None => ShareMemoryConfig::backpressure(share_memory.max_bytes),
+            }
+        });
+
+        let config = ProtocolConfig {
+            num_servers: self.num_servers,
+            threshold: self.threshold,
+            privacy_budget: PrivacyBudget::new(self.epsilon, self.delta),
+            timeout_ms: self.timeout_ms,
+            phase_timeouts: self.phase_timeouts.clone(),
+            cross_check,
+            share_memory,
+            sharing: self.sharing,
+            authenticated_shares: self.authenticated_shares,
+        };
+        config.validate().map_err(|e| e.to_string())?;
+        Ok(config)
+    }
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            num_servers: 3,
+            threshold: 2,
+            privacy_budget: PrivacyBudget::new(1.0, 1e-5),
+            timeout_ms: 30000,
+            phase_timeouts: BTreeMap::new(),
+            cross_check: None,
+            share_memory: None,
+            sharing: SharingScheme::default(),
+            authenticated_shares: false,
+        }
+    }
+}
+
+/// Phases of the multi-party protocol, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ProtocolPhase {
+    /// Servers are establishing connections and key material
+    Setup,
+    /// Clients' data shares are being distributed
+    Sharing,
+    /// Servers are running the oblivious shuffle
+    Shuffling,
+    /// Servers are adding differential privacy noise
+    Noise,
+    /// Shuffled data is being reconstructed
+    Reconstruction,
+    /// Queries are being answered
+    Query,
+    /// Protocol has finished
+    Completed,
+}
+
+impl ProtocolPhase {
+    /// Every phase, in order
+    pub const ALL: [ProtocolPhase; 7] = [
+        ProtocolPhase::Setup,
+        ProtocolPhase::Sharing,
+        ProtocolPhase::Shuffling,
+        ProtocolPhase::Noise,
+        ProtocolPhase::Reconstruction,
+        ProtocolPhase::Query,
+        ProtocolPhase::Completed,
+    ];
+
+    /// Tag of the phase on the wire
+    pub(crate) fn tag(self) -> u8 {
+        self as u8
+    }
+
+    /// Phase with the tag `tag`
+    pub(crate) fn from_tag(tag: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(tag)).copied()
+    }
+
+    /// Phases a run may move to from this one
+    ///
+    /// Noise may be left out after the shuffle, and queries after the
+    /// reconstruction.
+    pub fn successors(self) -> &'static [ProtocolPhase] {
+        match self {
+            ProtocolPhase::Setup => &[ProtocolPhase::Sharing],
+            ProtocolPhase::Sharing => &[ProtocolPhase::Shuffling],
+            ProtocolPhase::Shuffling => &[ProtocolPhase::Noise, ProtocolPhase::Reconstruction],
+            ProtocolPhase::Noise => &[ProtocolPhase::Reconstruction],
+            ProtocolPhase::Reconstruction => &[ProtocolPhase::Query, ProtocolPhase::Completed],
+            ProtocolPhase::Query => &[ProtocolPhase::Completed],
+            ProtocolPhase::Completed => &[],
+        }
+    }
+
+    /// Check if a run may move from this phase to `next`
+    pub fn can_advance_to(self, next: ProtocolPhase) -> bool {
+        self.successors().contains(&next)
+    }
+}
+
+/// States of a multi-party server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServerState {
+    /// Server is offline
+    Offline,
+    /// Server is online and ready
+    Online,
+    /// Server is participating in protocol
+    Participating,
+    /// Server has completed its part
+    Completed,
+    /// Server carries on without the servers declared failed
+    Degraded { failed: Vec<usize> },
+    /// Server has failed
+    Failed(String),
+}
+
+impl ServerState {
+    /// Check if server is available
+    pub fn is_available(&self) -> bool {
+        matches!(
+            self,
+            ServerState::Online | ServerState::Participating | ServerState::Completed | ServerState::Degraded { .. }
+        )
+    }
+
+    /// Check if server carries on without some of the others
+    pub fn is_degraded(&self) -> bool {
+        matches!(self, ServerState::Degraded { .. })
+    }
+
+    /// Check if server has failed
+    pub fn is_failed(&self) -> bool {
+        matches!(self, ServerState::Failed(_))
+    }
+}
+
+/// Errors that can occur during the multi-party protocol
+#[derive(Error, Debug)]
+pub enum ProtocolError {
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
+
+    #[error("Insufficient servers: {available} available, {required} required")]
+    InsufficientServers { available: usize, required: usize },
+
+    #[error("Unsupported query type: {0:?}")]
+    UnsupportedQuery(QueryType),
+
+    #[error("Server error: {message}")]
+    ServerError { message: String },
+
+    #[error("Network error: {message}")]
+    NetworkError { message: String },
+
+    #[error(
+        "Operation timed out after {duration_ms}ms{}",
+        .phase.map_or(String::new(), |phase| format!(" in phase {:?}", phase))
+    )]
+    Timeout { duration_ms: u64, phase: Option<ProtocolPhase> },
+
+    #[error("Cheating detected at share {index} between servers {servers:?}")]
+    CheatDetected { index: usize, servers: Vec<usize> },
+
+    #[error("Share {feature_index} of server {server_id} does not match the dealer's commitment")]
+    InvalidShare { server_id: usize, feature_index: usize },
+
+    #[error("Internal error: {message}")]
+    InternalError { message: String },
+
+    #[error("Malformed message: {0}")]
+    MalformedMessage(String),
+
+    #[error("Channel security error: {0}")]
+    Security(String),
+
+    #[error("Channel to server {target_id} is paused with {depth} messages queued")]
+    Backpressure { target_id: usize, depth: usize },
+
+    #[error("Server {target_id} speaks protocol version {peer}, incompatible with {local} on this server")]
+    IncompatibleVersion { target_id: usize, local: VersionRange, peer: VersionRange },
+
+    #[error("Cannot move from phase {from:?} to phase {to:?}")]
+    InvalidTransition { from: ProtocolPhase, to: ProtocolPhase },
+
+    #[error("Protocol run was cancelled")]
+    Cancelled,
+}
+
+impl ProtocolError {
+    /// Create a server error
+    pub fn server_error(message: impl Into<String>) -> Self {
+        Self::ServerError {
+            message: message.into(),
+        }
+    }
+
+    /// Create a network error
+    pub fn network_error(message: impl Into<String>) -> Self {
+        Self::NetworkError {
+            message: message.into(),
+        }
+    }
+
+    /// Create a timeout error
+    pub fn timeout(duration_ms: u64) -> Self {
+        Self::Timeout { duration_ms, phase: None }
+    }
+
+    /// Create a timeout error for a phase that took too long
+    pub fn phase_timeout(phase: ProtocolPhase, duration_ms: u64) -> Self {
+        Self::Timeout {
+            duration_ms,
+            phase: Some(phase),
+        }
+    }
+
+    /// Create a cheat detected error
+    pub fn cheat_detected(index: usize, servers: Vec<usize>) -> Self {
+        Self::CheatDetected { index, servers }
+    }
+
+    /// Check if this error was caused by a misbehaving server or dealer
+    pub fn is_cheating(&self) -> bool {
+        matches!(self, ProtocolError::CheatDetected { .. } | ProtocolError::InvalidShare { .. })
+    }
+}
@@ -0,0 +1,228 @@
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::share::{DataShare, ShareManager};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Change in the set of servers holding shares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MembershipChange {
+    /// A server joined
+    Joined(usize),
+    /// A server left
+    Left(usize),
+}
+
+impl MembershipChange {
+    /// Apply the change to a list of server IDs
+    pub fn apply(&self, members: &[usize]) -> Vec<usize> {
+        let mut updated: Vec<usize> = members.to_vec();
+        match *self {
+            MembershipChange::Joined(id) => {
+                if !updated.contains(&id) {
+                    updated.push(id);
+                }
+            }
+            MembershipChange::Left(id) => updated.retain(|&member| member != id),
+        }
+        updated.sort_unstable();
+        updated
+    }
+}
+
+/// Move of a share from one live server to another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareTransfer {
+    /// Server currently holding the share
+    pub from: usize,
+    /// Server that should hold the share
+    pub to: usize,
+    /// Share being moved
+    pub share: DataShare,
+}
+
+/// Share held by a departed server that must be re-dealt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareReshare {
+    /// Server that held the share
+    pub lost_from: usize,
+    /// Server that should receive the re-dealt share
+    pub to: usize,
+    /// Last known copy of the share
+    pub share: DataShare,
+}
+
+/// Transfers needed to reach the target distribution after a membership change
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RebalancePlan {
+    /// Shares moved between live servers
+    pub transfers: Vec<ShareTransfer>,
+    /// Shares of departed servers that must be re-shared
+    pub reshares: Vec<ShareReshare>,
+}
+
+impl RebalancePlan {
+    /// Check if nothing needs to move
+    pub fn is_empty(&self) -> bool {
+        self.transfers.is_empty() && self.reshares.is_empty()
+    }
+
+    /// Total number of shares that change server
+    pub fn len(&self) -> usize {
+        self.transfers.len() + self.reshares.len()
+    }
+
+    /// Transfers sent by the given server
+    pub fn outgoing(&self, server_id: usize) -> impl Iterator<Item = &ShareTransfer> {
+        self.transfers.iter().filter(move |transfer| transfer.from == server_id)
+    }
+
+    /// Apply the plan to a share assignment in place
+    pub fn apply(&self, assignment: &mut HashMap<usize, Vec<DataShare>>, members: &[usize]) {
+        for transfer in &self.transfers {
+            if let Some(shares) = assignment.get_mut(&transfer.from) {
+                if let Some(pos) = shares.iter().position(|share| same_share(share, &transfer.share)) {
+                    shares.swap_remove(pos);
+                }
+            }
+            assignment.entry(transfer.to).or_default().push(transfer.share.clone());
+        }
+
+        for reshare in &self.reshares {
+            assignment.entry(reshare.to).or_default().push(reshare.share.clone());
+        }
+
+        assignment.retain(|id, _| members.contains(id));
+    }
+}
+
+/// Compare two shares by content
+pub(crate) fn same_share(a: &DataShare, b: &DataShare) -> bool {
+    a.feature_index == b.feature_index && a.share_type == b.share_type && a.value == b.value && a.modulus == b.modulus && a.mac == b.mac
+}
+
+impl ShareManager {
+    /// Compute the fewest share moves that bring `current` to this manager's
+    /// distribution over `members`
+    ///
+    /// The manager must already be configured for `members.len()` servers.
+    /// Slot `i` of the distribution strategy maps to `members[i]`.
+    pub fn plan_rebalance(
+        &self,
+        current: &HashMap<usize, Vec<DataShare>>,
+        members: &[usize],
+    ) -> Result<RebalancePlan, ProtocolError> {
+        if members.len() != self.num_servers {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Share manager configured for {} servers but membership has {}",
+                self.num_servers,
+                members.len()
+            )));
+        }
+
+        let ordered: BTreeMap<usize, &Vec<DataShare>> = current.iter().map(|(id, shares)| (*id, shares)).collect();
+        let all_shares: Vec<DataShare> = ordered.values().flat_map(|shares| shares.iter().cloned()).collect();
+        let total = all_shares.len();
+
+        // Target count per member under the distribution strategy
+        let target_distribution = self.distribute_shares(all_shares);
+        let mut targets: BTreeMap<usize, usize> = members.iter().map(|&id| (id, 0)).collect();
+        for (slot, shares) in &target_distribution {
+            let id = members.get(*slot).ok_or(ProtocolError::InternalError {
+                message: format!("Distribution assigned shares to unknown slot {}", slot),
+            })?;
+            *targets.entry(*id).or_default() += shares.len();
+        }
+
+        if targets.values().sum::<usize>() != total {
+            return Err(ProtocolError::InvalidConfiguration(
+                "Rebalancing requires a distribution that preserves the share count".to_string(),
+            ));
+        }
+
+        // Shares each server must give up: everything on departed servers, the
+        // surplus over target on live ones
+        let mut surplus: Vec<(usize, bool, DataShare)> = Vec::new();
+        for (&id, shares) in &ordered {
+            let keep = targets.get(&id).copied().unwrap_or(0).min(shares.len());
+            let live = targets.contains_key(&id);
+            surplus.extend(shares[keep..].iter().map(|share| (id, live, share.clone())));
+        }
+
+        let mut plan = RebalancePlan::default();
+        let mut surplus = surplus.into_iter();
+        for (&id, &target) in &targets {
+            let held = ordered.get(&id).map_or(0, |shares| shares.len());
+            for _ in held..target {
+                let (from, live, share) = surplus.next().ok_or(ProtocolError::InternalError {
+                    message: "Ran out of shares while rebalancing".to_string(),
+                })?;
+                if live {
+                    plan.transfers.push(ShareTransfer { from, to: id, share });
+                } else {
+                    plan.reshares.push(ShareReshare {
+                        lost_from: from,
+                        to: id,
+                        share,
+                    });
+                }
+            }
+        }
+
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::share::ShareDistribution;
+
+    fn assignment(counts: &[(usize, usize)]) -> HashMap<usize, Vec<DataShare>> {
+        let mut next = 0;
+        counts
+            .iter()
+            .map(|&(id, count)| {
+                let shares = (0..count)
+                    .map(|_| {
+                        next += 1;
+                        DataShare::feature(id, 0, next, 1_000_003)
+                    })
+                    .collect();
+                (id, shares)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_join_moves_minimal_shares() {
+        let current = assignment(&[(0, 4), (1, 4), (2, 4)]);
+        let members = MembershipChange::Joined(3).apply(&[0, 1, 2]);
+        let manager = ShareManager::new(ShareDistribution::Even, members.len(), 2);
+
+        let plan = manager.plan_rebalance(&current, &members).unwrap();
+        assert_eq!(plan.transfers.len(), 3);
+        assert!(plan.reshares.is_empty());
+        assert!(plan.transfers.iter().all(|t| t.to == 3));
+
+        let mut updated = current.clone();
+        plan.apply(&mut updated, &members);
+        assert!(updated.values().all(|shares| shares.len() == 3));
+    }
+
+    #[test]
+    fn test_leave_reshares_lost_shares() {
+        let current = assignment(&[(0, 3), (1, 3), (2, 3)]);
+        let members = MembershipChange::Left(1).apply(&[0, 1, 2]);
+        let manager = ShareManager::new(ShareDistribution::Even, members.len(), 2);
+
+        let plan = manager.plan_rebalance(&current, &members).unwrap();
+        assert!(plan.transfers.is_empty());
+        assert_eq!(plan.reshares.len(), 3);
+        assert!(plan.reshares.iter().all(|r| r.lost_from == 1));
+
+        let mut updated = current.clone();
+        plan.apply(&mut updated, &members);
+        assert_eq!(updated.len(), 2);
+        assert_eq!(updated.values().map(|shares| shares.len()).sum::<usize>(), 9);
+    }
+}
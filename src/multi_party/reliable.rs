@@ -0,0 +1,222 @@
+use crate::multi_party::communication::NetworkMessage;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::time::Duration;
+
+/// Number of delivered messages a channel remembers to drop duplicates
+pub const DEDUP_WINDOW: usize = 4096;
+
+/// Retransmission of unacknowledged messages
+///
+/// A message is resent if no acknowledgement arrives within `initial_backoff`
+/// of sending it. The wait doubles with every resend up to `max_backoff`, and
+/// the message is given up after `max_retries` resends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Resends before a message is given up
+    pub max_retries: usize,
+    /// Wait for the acknowledgement of the first send
+    pub initial_backoff: Duration,
+    /// Longest wait between two resends
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait for an acknowledgement after the send numbered `attempt`, the first being 0
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let factor = 1u32 << attempt.min(31);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// A sent message waiting for its acknowledgement
+struct Pending {
+    message: NetworkMessage,
+    /// Resends so far
+    attempts: usize,
+    /// Time on the runtime clock of the next resend
+    retry_at: Duration,
+}
+
+/// Messages sent on a channel and not acknowledged yet, by sequence number
+#[derive(Default)]
+pub(crate) struct ResendBuffer {
+    pending: BTreeMap<u64, Pending>,
+}
+
+impl ResendBuffer {
+    /// Keep a sent message until it is acknowledged, resending it at `retry_at`
+    pub(crate) fn track(&mut self, message: NetworkMessage, retry_at: Duration) {
+        let pending = Pending {
+            message,
+            attempts: 0,
+            retry_at,
+        };
+        self.pending.insert(pending.message.sequence, pending);
+    }
+
+    /// Drop an acknowledged message, returning whether it was pending
+    pub(crate) fn acknowledge(&mut self, sequence: u64) -> bool {
+        self.pending.remove(&sequence).is_some()
+    }
+
+    /// Number of messages waiting for an acknowledgement
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The messages waiting for an acknowledgement, by sequence number
+    pub(crate) fn messages(&self) -> Vec<NetworkMessage> {
+        self.pending.values().map(|pending| pending.message.clone()).collect()
+    }
+
+    /// Take every message to send again at `now`, as on a new link, restarting its retries
+    pub(crate) fn restart(&mut self, now: Duration, policy: &RetryPolicy) -> Vec<NetworkMessage> {
+        self.pending
+            .values_mut()
+            .map(|pending| {
+                pending.attempts = 0;
+                pending.retry_at = now + policy.backoff(0);
+                pending.message.clone()
+            })
+            .collect()
+    }
+
+    /// Time of the earliest resend
+    pub(crate) fn next_retry(&self) -> Option<Duration> {
+        self.pending.values().map(|pending| pending.retry_at).min()
+    }
+
+    /// Take the messages to resend at `now` and schedule their next resend
+    ///
+    /// Messages out of retries are dropped, and their sequence numbers are
+    /// returned alongside the messages to resend.
+    pub(crate) fn take_due(&mut self, now: Duration, policy: &RetryPolicy) -> (Vec<NetworkMessage>, Vec<u64>) {
+        let mut due = Vec::new();
+        let mut given_up = Vec::new();
+        for (&sequence, pending) in self.pending.iter_mut().filter(|(_, pending)| pending.retry_at <= now) {
+            if pending.attempts >= policy.max_retries {
+                given_up.push(sequence);
+            } else {
+                pending.attempts += 1;
+                pending.retry_at = now + policy.backoff(pending.attempts);
+                due.push(pending.message.clone());
+            }
+        }
+        for sequence in &given_up {
+            self.pending.remove(sequence);
+        }
+        (due, given_up)
+    }
+}
+
+/// The most recently delivered messages, by source and sequence number
+pub(crate) struct RecentMessages {
+    seen: HashSet<(usize, u64)>,
+    order: VecDeque<(usize, u64)>,
+    capacity: usize,
+}
+
+impl RecentMessages {
+    /// Remember up to `capacity` messages
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            seen: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record a delivered message, returning false if it is a duplicate
+    pub(crate) fn insert(&mut self, source_id: usize, sequence: u64) -> bool {
+        if !self.seen.insert((source_id, sequence)) {
+            return false;
+        }
+        self.order.push_back((source_id, sequence));
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /// The remembered messages, oldest first
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.order.iter().copied()
+    }
+
+    /// Forget every message
+    pub(crate) fn clear(&mut self) {
+        self.seen.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resend_buffer() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(15),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(10));
+        assert_eq!(policy.backoff(1), Duration::from_millis(15));
+        assert_eq!(policy.backoff(usize::MAX), Duration::from_millis(15));
+
+        let mut buffer = ResendBuffer::default();
+        buffer.track(NetworkMessage::ack(0, 1, 4), Duration::from_millis(10));
+        buffer.track(NetworkMessage::ack(0, 1, 5), Duration::from_millis(20));
+        assert_eq!(buffer.next_retry(), Some(Duration::from_millis(10)));
+        assert!(buffer.acknowledge(5));
+        assert!(!buffer.acknowledge(5));
+
+        let (due, given_up) = buffer.take_due(Duration::from_millis(9), &policy);
+        assert!(due.is_empty() && given_up.is_empty());
+        let (due, _) = buffer.take_due(Duration::from_millis(10), &policy);
+        assert_eq!(due.iter().map(|message| message.sequence).collect::<Vec<_>>(), [4]);
+        assert_eq!(buffer.next_retry(), Some(Duration::from_millis(25)));
+        let (due, _) = buffer.take_due(Duration::from_millis(25), &policy);
+        assert_eq!(due.len(), 1);
+        let (due, given_up) = buffer.take_due(Duration::from_millis(40), &policy);
+        assert!(due.is_empty());
+        assert_eq!(given_up, [4]);
+        assert_eq!(buffer.len(), 0);
+
+        // A new link restarts the retries
+        buffer.track(NetworkMessage::ack(0, 1, 6), Duration::from_millis(50));
+        buffer.take_due(Duration::from_millis(50), &policy);
+        let replayed = buffer.restart(Duration::from_millis(60), &policy);
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(buffer.next_retry(), Some(Duration::from_millis(70)));
+        assert_eq!(buffer.messages()[0].sequence, 6);
+    }
+
+    #[test]
+    fn test_recent_messages() {
+        let mut recent = RecentMessages::new(2);
+        assert!(recent.insert(0, 1));
+        assert!(!recent.insert(0, 1));
+        assert!(recent.insert(1, 1));
+        assert!(recent.insert(0, 2));
+
+        // The oldest message was forgotten
+        assert!(recent.insert(0, 1));
+        assert_eq!(recent.iter().collect::<Vec<_>>(), [(0, 2), (0, 1)]);
+        recent.clear();
+        assert!(recent.insert(0, 2));
+    }
+}
@@ -0,0 +1,504 @@
+use futures::channel::mpsc;
+use futures::executor::LocalPool;
+use futures::future::{self, BoxFuture, Either};
+use futures::task::{self, ArcWake, SpawnExt};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{ready, Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+pub use futures::channel::mpsc::SendError;
+
+/// Spawning and timers of an async executor
+///
+/// The multi-party layer only needs these operations and the
+/// executor-independent channels below, so it runs under tokio, async-std, or
+/// the single-threaded `ManualRuntime` used in tests and WASM. Other executors
+/// only need to implement this trait.
+pub trait Runtime: fmt::Debug + Send + Sync {
+    /// Run a task in the background
+    fn spawn(&self, task: BoxFuture<'static, ()>);
+
+    /// Complete once `duration` has passed
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Time since a fixed point in the past, on the clock `sleep` waits on
+    fn now(&self) -> Duration {
+        static EPOCH: OnceLock<Instant> = OnceLock::new();
+        EPOCH.get_or_init(Instant::now).elapsed()
+    }
+}
+
+/// Runtime shared by the servers and channels of one deployment
+pub type SharedRuntime = Arc<dyn Runtime>;
+
+/// The tokio runtime, which servers and channels use unless given another one
+pub fn default_runtime() -> SharedRuntime {
+    Arc::new(TokioRuntime)
+}
+
+/// Error returned by `timeout` when the duration passes first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// Wait for `future`, giving up after `duration` on the runtime's clock
+pub async fn timeout<F: Future>(
+    runtime: &dyn Runtime,
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    let future = pin!(future);
+    match future::select(future, runtime.sleep(duration)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(((), _)) => Err(Elapsed),
+    }
+}
+
+/// Create a bounded channel that works under any runtime
+///
+/// Both halves know how many values are queued, so senders can hold back
+/// before the channel is full.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    let queue = Arc::new(Queue::default());
+    (
+        Sender {
+            inner: sender,
+            queue: queue.clone(),
+        },
+        Receiver { inner: receiver, queue },
+    )
+}
+
+/// Values queued in a `channel`, and the senders waiting for it to drain
+#[derive(Debug, Default)]
+struct Queue {
+    depth: AtomicUsize,
+    closed: AtomicBool,
+    waiters: Mutex<Vec<Waker>>,
+}
+
+impl Queue {
+    fn is_drained(&self, level: usize) -> bool {
+        self.depth.load(Ordering::SeqCst) <= level || self.closed.load(Ordering::SeqCst)
+    }
+
+    fn wake(&self) {
+        let waiters = std::mem::take(&mut *self.waiters.lock().unwrap());
+        waiters.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Counts a value as queued until it turns out not to be sent
+struct Enqueued<'a> {
+    queue: &'a Queue,
+    sent: bool,
+}
+
+impl Drop for Enqueued<'_> {
+    fn drop(&mut self) {
+        if !self.sent {
+            self.queue.depth.fetch_sub(1, Ordering::SeqCst);
+            self.queue.wake();
+        }
+    }
+}
+
+/// Sending half of a `channel`
+#[derive(Debug)]
+pub struct Sender<T> {
+    inner: mpsc::Sender<T>,
+    queue: Arc<Queue>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Send a value, waiting while the channel is full
+    pub async fn send(&self, value: T) -> Result<(), SendError> {
+        // Counted before it is sent, so the receiver never sees more values than the depth
+        self.queue.depth.fetch_add(1, Ordering::SeqCst);
+        let mut enqueued = Enqueued {
+            queue: &self.queue,
+            sent: false,
+        };
+        let mut inner = self.inner.clone();
+        let mut value = Some(value);
+        let result = future::poll_fn(|cx| {
+            ready!(inner.poll_ready(cx))?;
+            Poll::Ready(inner.start_send(value.take().expect("polled after completion")))
+        })
+        .await;
+        enqueued.sent = result.is_ok();
+        result
+    }
+
+    /// Check whether the receiver was dropped
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    /// Number of values sent and not received yet
+    pub fn depth(&self) -> usize {
+        self.queue.depth.load(Ordering::SeqCst)
+    }
+
+    /// Complete once at most `level` values are queued, or the receiver is closed
+    pub async fn drained(&self, level: usize) {
+        future::poll_fn(|cx| {
+            if self.queue.is_drained(level) {
+                return Poll::Ready(());
+            }
+            self.queue.waiters.lock().unwrap().push(cx.waker().clone());
+            // The receiver may have taken a value before the waker was registered
+            if self.queue.is_drained(level) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// Receiving half of a `channel`
+#[derive(Debug)]
+pub struct Receiver<T> {
+    inner: mpsc::Receiver<T>,
+    queue: Arc<Queue>,
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next value, or `None` once every sender is dropped
+    pub async fn recv(&mut self) -> Option<T> {
+        let value = self.inner.next().await;
+        if value.is_some() {
+            self.queue.depth.fetch_sub(1, Ordering::SeqCst);
+            self.queue.wake();
+        }
+        value
+    }
+
+    /// Stop accepting values, keeping those already sent
+    pub fn close(&mut self) {
+        self.inner.close();
+        self.queue.closed.store(true, Ordering::SeqCst);
+        self.queue.wake();
+    }
+
+    /// Number of values sent and not received yet
+    pub fn depth(&self) -> usize {
+        self.queue.depth.load(Ordering::SeqCst)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.queue.closed.store(true, Ordering::SeqCst);
+        self.queue.wake();
+    }
+}
+
+/// Runtime backed by the ambient tokio runtime
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        tokio::spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    /// Follows the paused clock of tokio tests
+    fn now(&self) -> Duration {
+        static EPOCH: OnceLock<tokio::time::Instant> = OnceLock::new();
+        EPOCH.get_or_init(tokio::time::Instant::now).elapsed()
+    }
+}
+
+/// Runtime backed by the async-std global executor
+#[cfg(feature = "async-std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        async_std::task::spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+#[derive(Default)]
+struct ManualState {
+    /// Time on the virtual clock
+    now: Duration,
+    /// ID of the next timer
+    next_timer: u64,
+    /// Deadline and waker of every pending sleep
+    timers: HashMap<u64, (Duration, Option<Waker>)>,
+    /// Tasks spawned since the executor last ran
+    spawned: Vec<BoxFuture<'static, ()>>,
+}
+
+impl ManualState {
+    /// Move the clock to `now` and take the wakers of the expired timers
+    fn advance_to(&mut self, now: Duration) -> Vec<Waker> {
+        self.now = self.now.max(now);
+        let now = self.now;
+        self.timers
+            .values_mut()
+            .filter(|(deadline, _)| *deadline <= now)
+            .filter_map(|(_, waker)| waker.take())
+            .collect()
+    }
+}
+
+/// Single-threaded runtime with a virtual clock
+///
+/// Tasks run on the calling thread inside `block_on`. Time only moves when
+/// `advance` is called or when every task is waiting on a timer, in which case
+/// the clock jumps to the earliest deadline. Timeouts therefore fire instantly
+/// and deterministically, which suits tests and single-threaded WASM hosts.
+#[derive(Clone, Default)]
+pub struct ManualRuntime {
+    state: Arc<Mutex<ManualState>>,
+}
+
+impl fmt::Debug for ManualRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("ManualRuntime")
+            .field("now", &state.now)
+            .field("timers", &state.timers.len())
+            .field("spawned", &state.spawned.len())
+            .finish()
+    }
+}
+
+impl ManualRuntime {
+    /// Create a runtime whose clock starts at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time on the virtual clock
+    pub fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    /// Move the virtual clock forward, waking the sleeps that expire
+    pub fn advance(&self, duration: Duration) {
+        let wakers = {
+            let mut state = self.state.lock().unwrap();
+            let now = state.now + duration;
+            state.advance_to(now)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    /// Move the clock to the earliest pending deadline, if there is one
+    fn advance_to_next_timer(&self) -> bool {
+        let wakers = {
+            let mut state = self.state.lock().unwrap();
+            let Some(deadline) = state.timers.values().map(|(deadline, _)| *deadline).min() else {
+                return false;
+            };
+            state.advance_to(deadline)
+        };
+        wakers.into_iter().for_each(Waker::wake);
+        true
+    }
+
+    /// Run `future` and the spawned tasks on this thread until `future` completes
+    ///
+    /// # Panics
+    ///
+    /// Panics if `future` can never complete because every task is blocked and
+    /// no timer is pending.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let mut pool = LocalPool::new();
+        let spawner = pool.spawner();
+        let woken = Arc::new(Flag(AtomicBool::new(true)));
+        let waker = task::waker(woken.clone());
+        let mut context = Context::from_waker(&waker);
+        let mut future = pin!(future);
+
+        loop {
+            let spawned = std::mem::take(&mut self.state.lock().unwrap().spawned);
+            let spawned_any = !spawned.is_empty();
+            for task in spawned {
+                spawner.spawn(task).expect("the local pool is running");
+            }
+            pool.run_until_stalled();
+
+            if woken.0.swap(false, Ordering::SeqCst) {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                    return output;
+                }
+                continue;
+            }
+            if spawned_any || !self.state.lock().unwrap().spawned.is_empty() {
+                continue;
+            }
+            assert!(
+                self.advance_to_next_timer(),
+                "ManualRuntime::block_on: every task is blocked and no timer is pending"
+            );
+        }
+    }
+}
+
+impl Runtime for ManualRuntime {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        self.state.lock().unwrap().spawned.push(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_timer;
+        state.next_timer += 1;
+        Box::pin(ManualSleep {
+            state: self.state.clone(),
+            id,
+            deadline: state.now + duration,
+        })
+    }
+
+    fn now(&self) -> Duration {
+        ManualRuntime::now(self)
+    }
+}
+
+/// Wake flag of the future driven by `ManualRuntime::block_on`
+struct Flag(AtomicBool);
+
+impl ArcWake for Flag {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Sleep on the virtual clock of a `ManualRuntime`
+struct ManualSleep {
+    state: Arc<Mutex<ManualState>>,
+    id: u64,
+    deadline: Duration,
+}
+
+impl Future for ManualSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.now >= self.deadline {
+            state.timers.remove(&self.id);
+            Poll::Ready(())
+        } else {
+            state
+                .timers
+                .insert(self.id, (self.deadline, Some(cx.waker().clone())));
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for ManualSleep {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.timers.remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_runtime_timeout() {
+        let runtime = ManualRuntime::new();
+        let (sender, mut receiver) = channel::<u32>(1);
+
+        let result = runtime.block_on(timeout(&runtime, Duration::from_secs(5), receiver.recv()));
+        assert_eq!(result, Err(Elapsed));
+        assert_eq!(runtime.now(), Duration::from_secs(5));
+
+        let background = runtime.clone();
+        runtime.spawn(Box::pin(async move {
+            background.sleep(Duration::from_secs(1)).await;
+            sender.send(7).await.unwrap();
+        }));
+        let result = runtime.block_on(timeout(&runtime, Duration::from_secs(5), receiver.recv()));
+        assert_eq!(result, Ok(Some(7)));
+        assert_eq!(runtime.now(), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn test_channel_depth() {
+        let runtime = ManualRuntime::new();
+        let (sender, mut receiver) = channel::<u32>(4);
+
+        runtime.block_on(async {
+            sender.send(1).await.unwrap();
+            sender.send(2).await.unwrap();
+            assert_eq!((sender.depth(), receiver.depth()), (2, 2));
+
+            let received = async {
+                assert_eq!(receiver.recv().await, Some(1));
+                assert_eq!(receiver.recv().await, Some(2));
+            };
+            future::join(sender.drained(0), received).await;
+            assert_eq!(sender.depth(), 0);
+
+            // A closed receiver never drains, so waiting stops
+            sender.send(3).await.unwrap();
+            receiver.close();
+            sender.drained(0).await;
+            assert_eq!(sender.depth(), 1);
+        });
+    }
+
+    #[tokio::test]
+    async fn test_tokio_runtime() {
+        let runtime = TokioRuntime;
+        let (sender, mut receiver) = channel::<u32>(1);
+
+        let background = sender.clone();
+        runtime.spawn(Box::pin(async move {
+            background.send(1).await.unwrap();
+        }));
+        assert_eq!(receiver.recv().await, Some(1));
+
+        let result = timeout(&runtime, Duration::from_millis(10), receiver.recv()).await;
+        assert_eq!(result, Err(Elapsed));
+        drop(sender);
+        assert_eq!(receiver.recv().await, None);
+    }
+}
@@ -0,0 +1,407 @@
+use crate::multi_party::protocol::ProtocolError;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snow::params::NoiseParams;
+use snow::{Builder, HandshakeState, TransportState};
+use std::collections::BTreeMap;
+use std::fmt;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Noise protocol of the channels between servers
+///
+/// In the KK pattern both servers know each other's static key in advance, so
+/// the handshake authenticates both ends in a single round trip.
+const NOISE_PARAMS: &str = "Noise_KK_25519_ChaChaPoly_SHA256";
+
+/// Domain separator mixed into every handshake
+const PROLOGUE_DOMAIN: &[u8] = b"doppio-channel-v1";
+
+/// Largest Noise message
+const MAX_NOISE_MESSAGE: usize = 65535;
+
+/// Size of the authentication tag of every Noise message
+const TAG_BYTES: usize = 16;
+
+/// Largest plaintext carried by one Noise message
+const MAX_CHUNK: usize = MAX_NOISE_MESSAGE - TAG_BYTES;
+
+/// Size of an X25519 key
+pub const KEY_BYTES: usize = 32;
+
+/// Domain separator of the keys that authenticate messages
+const PAIR_KEY_DOMAIN: &[u8] = b"doppio-message-mac-v1";
+
+fn security_error(error: impl fmt::Display) -> ProtocolError {
+    ProtocolError::Security(error.to_string())
+}
+
+/// Static X25519 key pair identifying a server
+#[derive(Clone)]
+pub struct ServerKeys {
+    private_key: [u8; KEY_BYTES],
+    public_key: [u8; KEY_BYTES],
+}
+
+impl ServerKeys {
+    /// Generate a new key pair
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        Self::from_private_key(StaticSecret::random_from_rng(rng).to_bytes())
+    }
+
+    /// Restore a key pair from its private key
+    pub fn from_private_key(private_key: [u8; KEY_BYTES]) -> Self {
+        let public_key = PublicKey::from(&StaticSecret::from(private_key)).to_bytes();
+        Self {
+            private_key,
+            public_key,
+        }
+    }
+
+    /// Public key, to be configured on the peers
+    pub fn public_key(&self) -> [u8; KEY_BYTES] {
+        self.public_key
+    }
+
+    /// Key shared with a peer for authenticating messages
+    ///
+    /// Both servers derive the same key from their static keys, bound to the
+    /// pair of server IDs.
+    pub fn pair_key(
+        &self,
+        local_id: usize,
+        peer_id: usize,
+        peer_public_key: &[u8; KEY_BYTES],
+    ) -> Result<[u8; KEY_BYTES], ProtocolError> {
+        let shared =
+            StaticSecret::from(self.private_key).diffie_hellman(&PublicKey::from(*peer_public_key));
+        if !shared.was_contributory() {
+            return Err(security_error(format!(
+                "Public key of server {} is a low-order point",
+                peer_id
+            )));
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(PAIR_KEY_DOMAIN);
+        hasher.update((local_id.min(peer_id) as u64).to_le_bytes());
+        hasher.update((local_id.max(peer_id) as u64).to_le_bytes());
+        hasher.update(shared.as_bytes());
+        Ok(hasher.finalize().into())
+    }
+
+    /// Private key, hex encoded for an `IdentityConfig`
+    pub fn private_key_hex(&self) -> String {
+        encode_hex(&self.private_key)
+    }
+
+    /// Public key, hex encoded for an `IdentityConfig`
+    pub fn public_key_hex(&self) -> String {
+        encode_hex(&self.public_key)
+    }
+}
+
+impl fmt::Debug for ServerKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerKeys")
+            .field("public_key", &self.public_key_hex())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Identity of a server and the public keys of its peers, hex encoded
+///
+/// Every server has its own private key and lists the public key of every
+/// server it connects to by server ID. A channel to a server without a listed
+/// key cannot be established.
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityConfig {
+    /// Private X25519 key of this server
+    pub private_key: String,
+    /// Public X25519 keys of the other servers, by server ID
+    pub peers: BTreeMap<usize, String>,
+}
+
+impl IdentityConfig {
+    /// Key pair of this server
+    pub fn keys(&self) -> Result<ServerKeys, ProtocolError> {
+        Ok(ServerKeys::from_private_key(decode_key(&self.private_key)?))
+    }
+
+    /// Public key of the server with the given ID
+    pub fn peer_key(&self, server_id: usize) -> Result<[u8; KEY_BYTES], ProtocolError> {
+        let key = self
+            .peers
+            .get(&server_id)
+            .ok_or_else(|| security_error(format!("No public key for server {}", server_id)))?;
+        decode_key(key)
+    }
+
+    /// Check that all keys are well formed
+    pub fn validate(&self) -> Result<(), String> {
+        decode_key(&self.private_key).map_err(|e| format!("private_key: {}", e))?;
+        for (server_id, key) in &self.peers {
+            decode_key(key).map_err(|e| format!("peers.{}: {}", server_id, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for IdentityConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdentityConfig")
+            .field("private_key", &"<redacted>")
+            .field("peers", &self.peers)
+            .finish()
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn decode_key(hex: &str) -> Result<[u8; KEY_BYTES], ProtocolError> {
+    let hex = hex.trim();
+    if hex.len() != 2 * KEY_BYTES || !hex.is_ascii() {
+        return Err(security_error(format!(
+            "Expected a key of {} hex digits",
+            2 * KEY_BYTES
+        )));
+    }
+
+    let mut key = [0; KEY_BYTES];
+    for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digits = std::str::from_utf8(digits).map_err(security_error)?;
+        *byte = u8::from_str_radix(digits, 16).map_err(security_error)?;
+    }
+    Ok(key)
+}
+
+/// Noise handshake with one peer
+///
+/// The server with the lower ID initiates. Both server IDs are bound to the
+/// handshake, so a key cannot be replayed on a channel between other servers.
+pub struct Handshake {
+    state: HandshakeState,
+    initiator: bool,
+}
+
+impl Handshake {
+    /// Start a handshake between `local_id` and `peer_id`
+    pub fn new(
+        local_id: usize,
+        peer_id: usize,
+        keys: &ServerKeys,
+        peer_public_key: &[u8; KEY_BYTES],
+    ) -> Result<Self, ProtocolError> {
+        if local_id == peer_id {
+            return Err(security_error("A server cannot open a channel to itself"));
+        }
+
+        let params: NoiseParams = NOISE_PARAMS.parse().map_err(security_error)?;
+        let mut prologue = PROLOGUE_DOMAIN.to_vec();
+        prologue.extend_from_slice(&(local_id.min(peer_id) as u64).to_le_bytes());
+        prologue.extend_from_slice(&(local_id.max(peer_id) as u64).to_le_bytes());
+
+        let initiator = local_id < peer_id;
+        let builder = Builder::new(params)
+            .local_private_key(&keys.private_key)
+            .remote_public_key(peer_public_key)
+            .prologue(&prologue);
+        let state = if initiator {
+            builder.build_initiator()
+        } else {
+            builder.build_responder()
+        }
+        .map_err(security_error)?;
+
+        Ok(Self { state, initiator })
+    }
+
+    /// Whether this side sends the first handshake message
+    pub fn is_initiator(&self) -> bool {
+        self.initiator
+    }
+
+    /// Whether this side sends the next handshake message
+    pub fn is_my_turn(&self) -> bool {
+        self.state.is_my_turn()
+    }
+
+    /// Whether the handshake is complete
+    pub fn is_finished(&self) -> bool {
+        self.state.is_handshake_finished()
+    }
+
+    /// Write the next handshake message
+    pub fn write_message(&mut self) -> Result<Vec<u8>, ProtocolError> {
+        let mut message = vec![0; MAX_NOISE_MESSAGE];
+        let len = self
+            .state
+            .write_message(&[], &mut message)
+            .map_err(security_error)?;
+        message.truncate(len);
+        Ok(message)
+    }
+
+    /// Read a handshake message from the peer
+    pub fn read_message(&mut self, message: &[u8]) -> Result<(), ProtocolError> {
+        let mut payload = vec![0; MAX_NOISE_MESSAGE];
+        self.state
+            .read_message(message, &mut payload)
+            .map_err(security_error)?;
+        Ok(())
+    }
+
+    /// Finish the handshake
+    pub fn into_session(self) -> Result<SecureSession, ProtocolError> {
+        let transport = self.state.into_transport_mode().map_err(security_error)?;
+        Ok(SecureSession { transport })
+    }
+}
+
+impl fmt::Debug for Handshake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handshake")
+            .field("initiator", &self.initiator)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// Encrypted session with one peer after the handshake
+///
+/// Messages must be decrypted in the order they were encrypted, which the
+/// channels between servers guarantee. A plaintext longer than one Noise
+/// message is split into length-prefixed chunks.
+pub struct SecureSession {
+    transport: TransportState,
+}
+
+impl SecureSession {
+    /// Encrypt a plaintext for the peer
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+        let chunks = plaintext.len().div_ceil(MAX_CHUNK).max(1);
+        let mut ciphertext = Vec::with_capacity(plaintext.len() + chunks * (TAG_BYTES + 2));
+        let mut message = vec![0; MAX_NOISE_MESSAGE];
+
+        for chunk in plaintext
+            .chunks(MAX_CHUNK)
+            .chain(plaintext.is_empty().then_some(&[][..]))
+        {
+            let len = self
+                .transport
+                .write_message(chunk, &mut message)
+                .map_err(security_error)?;
+            ciphertext.extend_from_slice(&(len as u16).to_be_bytes());
+            ciphertext.extend_from_slice(&message[..len]);
+        }
+        Ok(ciphertext)
+    }
+
+    /// Decrypt a ciphertext from the peer
+    pub fn decrypt(&mut self, mut ciphertext: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+        let mut plaintext = Vec::with_capacity(ciphertext.len());
+        let mut chunk = vec![0; MAX_NOISE_MESSAGE];
+
+        while !ciphertext.is_empty() {
+            if ciphertext.len() < 2 {
+                return Err(security_error("Truncated ciphertext"));
+            }
+            let len = u16::from_be_bytes([ciphertext[0], ciphertext[1]]) as usize;
+            let message = ciphertext
+                .get(2..2 + len)
+                .ok_or_else(|| security_error("Truncated ciphertext"))?;
+
+            let read = self
+                .transport
+                .read_message(message, &mut chunk)
+                .map_err(security_error)?;
+            plaintext.extend_from_slice(&chunk[..read]);
+            ciphertext = &ciphertext[2 + len..];
+        }
+        Ok(plaintext)
+    }
+}
+
+impl fmt::Debug for SecureSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureSession").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    /// Run a handshake between two servers in memory and return both sessions
+    fn session_pair(
+        first: (usize, &ServerKeys),
+        second: (usize, &ServerKeys),
+    ) -> Result<(SecureSession, SecureSession), ProtocolError> {
+        let mut a = Handshake::new(first.0, second.0, first.1, &second.1.public_key())?;
+        let mut b = Handshake::new(second.0, first.0, second.1, &first.1.public_key())?;
+
+        while !(a.is_finished() && b.is_finished()) {
+            if a.is_my_turn() {
+                b.read_message(&a.write_message()?)?;
+            } else {
+                a.read_message(&b.write_message()?)?;
+            }
+        }
+        Ok((a.into_session()?, b.into_session()?))
+    }
+
+    #[test]
+    fn test_secure_session() {
+        let (keys_0, keys_1) = (
+            ServerKeys::generate(&mut OsRng),
+            ServerKeys::generate(&mut OsRng),
+        );
+        let (mut session_0, mut session_1) = session_pair((0, &keys_0), (1, &keys_1)).unwrap();
+
+        let share = vec![7u8; 3 * MAX_CHUNK + 10];
+        let ciphertext = session_0.encrypt(&share).unwrap();
+        assert!(!ciphertext.windows(64).any(|window| window == &share[..64]));
+        assert_eq!(session_1.decrypt(&ciphertext).unwrap(), share);
+
+        let empty = session_1.encrypt(&[]).unwrap();
+        assert!(session_0.decrypt(&empty).unwrap().is_empty());
+
+        let mut tampered = session_0.encrypt(b"shares").unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(session_1.decrypt(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_handshake_rejects_unknown_keys() {
+        let (keys_0, keys_1) = (
+            ServerKeys::generate(&mut OsRng),
+            ServerKeys::generate(&mut OsRng),
+        );
+        let impostor = ServerKeys::generate(&mut OsRng);
+        assert!(session_pair((0, &keys_0), (1, &keys_1)).is_ok());
+
+        // Server 0 expects keys_1 for server 1, but the impostor answers
+        let mut a = Handshake::new(0, 1, &keys_0, &keys_1.public_key()).unwrap();
+        let mut b = Handshake::new(1, 0, &impostor, &keys_0.public_key()).unwrap();
+        let first = a.write_message().unwrap();
+        let result = b
+            .read_message(&first)
+            .and_then(|()| b.write_message())
+            .and_then(|second| a.read_message(&second));
+        assert!(result.is_err());
+
+        let config = IdentityConfig {
+            private_key: keys_0.private_key_hex(),
+            peers: BTreeMap::from([(1, keys_1.public_key_hex())]),
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.keys().unwrap().public_key(), keys_0.public_key());
+        assert_eq!(config.peer_key(1).unwrap(), keys_1.public_key());
+        assert!(config.peer_key(2).is_err());
+        assert!(!format!("{:?}", config).contains(&config.private_key));
+    }
+}
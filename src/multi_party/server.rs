@@ -1,13 +1,28 @@
 use crate::schema::{DataPoint, Query, QueryResult};
 use crate::arith::PrivacyBudget;
 use crate::multi_party::protocol::{ProtocolConfig, ProtocolError, ServerState, ProtocolPhase};
-use crate::multi_party::communication::{NetworkMessage, MessageType, CommunicationChannel};
-use crate::multi_party::crypto::{SecretShare, ShamirSecretSharing, ThresholdEncryption};
+use crate::multi_party::beaver::BeaverTriple;
+use crate::multi_party::communication::{NetworkMessage, MessagePayload, MessageType, CommunicationChannel};
+use crate::multi_party::cross_check::{self, CommitmentOpening, ComputationCommitment};
+use crate::multi_party::crypto::{ElGamalCiphertext, SecretShare, ShamirSecretSharing, ThresholdEncryption};
+use crate::multi_party::flow::DEFAULT_QUEUE_CAPACITY;
+use crate::multi_party::heartbeat::Suspicion;
+use crate::multi_party::memory::{self, BufferedShares, MemoryReservation, ShareMemory};
+use crate::multi_party::paillier::{Ciphertext, EncryptedAggregation, PaillierPublicKey};
+use crate::multi_party::pool::SharePools;
+use crate::multi_party::rebalance::{self, RebalancePlan};
+use crate::multi_party::runtime::{self, Receiver, Sender, SharedRuntime};
 use crate::multi_party::share::{DataShare, ShareType};
+use crate::multi_party::timing::{PhaseRecorder, PhaseTimings};
+use crate::multi_party::vss::{PedersenVss, ShareCommitment};
+use crate::multi_party::waksman::{ObliviousShuffle, WaksmanNetwork};
+use crate::telemetry::Operation;
+use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use std::time::Duration;
 
 /// Role of a server in the multi-party protocol
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -20,6 +35,8 @@ pub enum ServerRole {
     Third,
     /// Helper server (for future expansion)
     Helper,
+    /// One of `n` interchangeable computational servers
+    Computational(usize),
 }
 
 impl ServerRole {
@@ -30,17 +47,34 @@ impl ServerRole {
             ServerRole::Second => ServerRole::Third,
             ServerRole::Third => ServerRole::Second,
             ServerRole::Helper => ServerRole::Helper,
+            ServerRole::Computational(index) => ServerRole::Computational(*index),
+        }
+    }
+
+    /// Role of the server at `index` in a deployment of `num_servers` servers
+    ///
+    /// Three-server deployments keep the fixed first/second/third roles; any
+    /// other size uses symmetric computational servers.
+    pub fn for_index(index: usize, num_servers: usize) -> Self {
+        match (num_servers, index) {
+            (3, 0) => ServerRole::First,
+            (3, 1) => ServerRole::Second,
+            (3, 2) => ServerRole::Third,
+            _ => ServerRole::Computational(index),
         }
     }
 
     /// Check if this role participates in data holding
     pub fn holds_data(&self) -> bool {
-        matches!(self, ServerRole::First | ServerRole::Second)
+        matches!(self, ServerRole::First | ServerRole::Second | ServerRole::Computational(_))
     }
 
     /// Check if this role participates in oblivious shuffle
     pub fn participates_in_shuffle(&self) -> bool {
-        matches!(self, ServerRole::First | ServerRole::Second | ServerRole::Third)
+        matches!(
+            self,
+            ServerRole::First | ServerRole::Second | ServerRole::Third | ServerRole::Computational(_)
+        )
     }
 }
 
@@ -62,20 +96,40 @@ pub struct MultiPartyServer {
     /// Cryptographic components
     pub crypto: ThresholdEncryption,
     /// Message receiver
-    pub message_receiver: Option<mpsc::Receiver<NetworkMessage>>,
+    pub message_receiver: Option<Receiver<NetworkMessage>>,
     /// Message sender
-    pub message_sender: Option<mpsc::Sender<NetworkMessage>>,
+    pub message_sender: Option<Sender<NetworkMessage>>,
     /// Round number
     pub round_number: usize,
     /// Permutation for oblivious shuffle
     pub permutation: Option<Vec<usize>>,
+    /// Beaver triples dealt for the oblivious shuffle, used in order
+    pub triples: Vec<BeaverTriple>,
+    /// Memory budget for buffered shares
+    pub memory: Option<Arc<ShareMemory>>,
+    /// Budget reserved by the shares currently held
+    pub reservation: MemoryReservation,
+    /// Files holding shares spilled to disk
+    pub spilled: Vec<PathBuf>,
+    /// Time spent in each protocol phase
+    pub timings: PhaseRecorder,
+    /// Runtime for channels and timeouts
+    pub runtime: SharedRuntime,
+    /// Buffer pools for sharing and shuffling
+    pub pools: Arc<SharePools>,
 }
 
 impl MultiPartyServer {
     /// Create a new multi-party server
     pub fn new(id: usize, role: ServerRole, config: ProtocolConfig) -> Self {
+        config.validate().expect("Invalid protocol configuration");
         let crypto = ThresholdEncryption::new(config.threshold, config.num_servers)
-            .expect("Failed to create threshold encryption");
+            .expect("Failed to create threshold encryption")
+            .with_scheme(config.sharing)
+            .with_authentication(config.authenticated_shares);
+        let memory = config.share_memory.clone().map(|budget| {
+            Arc::new(ShareMemory::new(id, budget).expect("Invalid share memory budget"))
+        });
 
         Self {
             id,
@@ -89,31 +143,65 @@ impl MultiPartyServer {
             message_sender: None,
             round_number: 0,
             permutation: None,
+            triples: Vec::new(),
+            memory,
+            reservation: MemoryReservation::default(),
+            spilled: Vec::new(),
+            timings: PhaseRecorder::default(),
+            runtime: runtime::default_runtime(),
+            pools: Arc::new(SharePools::disabled()),
         }
     }
 
+    /// Use another runtime for channels and timeouts
+    pub fn with_runtime(mut self, runtime: SharedRuntime) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    /// Reuse share and permutation buffers through the given pools
+    ///
+    /// Without pools, every shuffle round allocates a fresh permutation and
+    /// record vector; servers of one process can share a single set of pools.
+    pub fn with_pools(mut self, pools: Arc<SharePools>) -> Self {
+        self.crypto = self.crypto.with_pools(pools.clone());
+        self.pools = pools;
+        self
+    }
+
     /// Initialize the server
     pub async fn initialize(&mut self) -> Result<(), ProtocolError> {
-        self.state = ServerState::Online;
-        self.round_number = 0;
-        self.shares.clear();
-        self.channels.clear();
-
-        // Initialize cryptographic components
-        self.crypto.initialize().await?;
+        let _phase = self.timings.scope(ProtocolPhase::Setup);
+        let operation = Operation::start("multi_party", "setup");
+        let items = 0;
+        let result = async {
+            self.state = ServerState::Online;
+            self.round_number = 0;
+            self.shares.clear();
+            self.reservation = MemoryReservation::default();
+            self.spilled.clear();
+            self.channels.clear();
+
+            // Initialize cryptographic components
+            self.crypto.initialize().await?;
+
+            // Initialize communication channels
+            self.initialize_communication().await?;
 
-        // Initialize communication channels
-        self.initialize_communication().await?;
+            Ok(())
+        }
+        .await;
 
-        Ok(())
+        operation.finish(&result, items);
+        result
     }
 
     /// Initialize communication channels
     async fn initialize_communication(&mut self) -> Result<(), ProtocolError> {
         for server_id in 0..self.config.num_servers {
             if server_id != self.id {
-                let (tx, rx) = mpsc::channel(100);
-                let channel = CommunicationChannel::new(server_id, tx, rx);
+                let (tx, rx) = runtime::channel(DEFAULT_QUEUE_CAPACITY);
+                let channel = CommunicationChannel::new(server_id, tx, rx).with_runtime(self.runtime.clone());
                 self.channels.insert(server_id, channel);
             }
         }
@@ -139,17 +227,142 @@ impl MultiPartyServer {
     }
 
     /// Receive and process data shares
+    ///
+    /// With a memory budget configured, this waits for buffered shares taken
+    /// with `take_buffered_shares` to be released, or spills the batch to disk,
+    /// depending on the overflow policy.
     pub async fn receive_shares(&mut self, shares: Vec<DataShare>) -> Result<(), ProtocolError> {
-        if !self.role.holds_data() {
-            return Err(ProtocolError::server_error(
-                "Server does not hold data shares".to_string(),
-            ));
+        let _phase = self.timings.scope(ProtocolPhase::Sharing);
+        let operation = Operation::start("multi_party", "sharing");
+        let items = shares.len();
+        let result = async {
+            if !self.role.holds_data() {
+                return Err(ProtocolError::server_error(
+                    "Server does not hold data shares".to_string(),
+                ));
+            }
+
+            if let Some(budget) = &self.memory {
+                let bytes = shares.iter().map(memory::share_size).sum();
+                let timeout = Duration::from_millis(self.config.timeout_ms);
+
+                match budget.reserve(bytes, timeout, &*self.runtime).await? {
+                    Some(reservation) => {
+                        self.reservation.merge(reservation);
+                        self.shares.extend(shares);
+                    }
+                    None => {
+                        let path = budget.spill(&shares)?;
+                        log::debug!("Server {} spilled {} shares to {}", self.id, shares.len(), path.display());
+                        self.spilled.push(path);
+                    }
+                }
+            } else {
+                self.shares.extend(shares);
+            }
+
+            self.state = ServerState::Participating;
+
+            Ok(())
         }
+        .await;
 
-        self.shares.extend(shares);
-        self.state = ServerState::Participating;
+        operation.finish(&result, items);
+        result
+    }
 
-        Ok(())
+    /// Check shares against their dealer's commitments, then receive them
+    ///
+    /// Nothing is received if any share fails the check, and the error names
+    /// the first inconsistent share.
+    pub async fn receive_verified_shares(
+        &mut self,
+        shares: Vec<DataShare>,
+        commitments: &[ShareCommitment],
+    ) -> Result<(), ProtocolError> {
+        PedersenVss::new(self.config.threshold, self.config.num_servers)?.verify_all(&shares, commitments)?;
+        self.receive_shares(shares).await
+    }
+
+    /// Receive Beaver triples dealt in the offline phase for the oblivious shuffle
+    ///
+    /// Every server must receive its triples of the same deal in the same
+    /// order, since each shuffle takes the next ones.
+    pub fn receive_triples(&mut self, triples: Vec<BeaverTriple>) {
+        self.triples.extend(triples);
+    }
+
+    /// Number of Beaver triples `participate_in_shuffle` takes for `records`
+    pub fn shuffle_triples_needed(&self, records: &[Vec<DataShare>]) -> usize {
+        ObliviousShuffle::triples_needed(records) * self.config.shuffle_rounds()
+    }
+
+    /// Take all buffered shares, including the paths of spilled shares
+    ///
+    /// Their memory budget is released when the returned value is dropped.
+    pub fn take_buffered_shares(&mut self) -> BufferedShares {
+        BufferedShares {
+            shares: std::mem::take(&mut self.shares),
+            spilled: std::mem::take(&mut self.spilled),
+            reservation: std::mem::take(&mut self.reservation),
+        }
+    }
+
+    /// Send this server's outgoing rebalance transfers to their new holders
+    ///
+    /// Moved shares are removed from this server. Returns the number sent.
+    pub async fn execute_rebalance(&mut self, plan: &RebalancePlan) -> Result<usize, ProtocolError> {
+        let mut outgoing: HashMap<usize, Vec<DataShare>> = HashMap::new();
+        for transfer in plan.outgoing(self.id) {
+            let position = self
+                .shares
+                .iter()
+                .position(|share| rebalance::same_share(share, &transfer.share))
+                .ok_or_else(|| ProtocolError::server_error(format!(
+                    "Server {} does not hold share planned for transfer to {}",
+                    self.id, transfer.to
+                )))?;
+            outgoing.entry(transfer.to).or_default().push(self.shares.swap_remove(position));
+        }
+
+        let mut sent = 0;
+        for (target_id, shares) in outgoing {
+            let payload = shares
+                .iter()
+                .map(serde_json::to_vec)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| ProtocolError::server_error(format!("Failed to encode shares: {}", e)))?;
+            let sequence = self
+                .channels
+                .get_mut(&target_id)
+                .map(|channel| channel.next_sequence())
+                .ok_or_else(|| ProtocolError::network_error(format!("No channel to server {}", target_id)))?;
+
+            self.send_message(target_id, NetworkMessage::share(self.id, target_id, sequence, payload)).await?;
+            sent += shares.len();
+        }
+
+        Ok(sent)
+    }
+
+    /// Accept shares moved to this server by a rebalance
+    pub async fn receive_rebalanced(&mut self, message: NetworkMessage) -> Result<usize, ProtocolError> {
+        let MessagePayload::Shares(encoded) = message.payload else {
+            return Err(ProtocolError::server_error(format!(
+                "Expected share payload from server {}",
+                message.source_id
+            )));
+        };
+
+        let shares = encoded
+            .iter()
+            .map(|bytes| serde_json::from_slice::<DataShare>(bytes))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ProtocolError::server_error(format!("Failed to decode shares: {}", e)))?;
+        let count = shares.len();
+        self.receive_shares(shares).await?;
+
+        Ok(count)
     }
 
     /// Generate permutation for oblivious shuffle
@@ -174,10 +387,38 @@ impl MultiPartyServer {
         Ok(permutation)
     }
 
+    /// Generate the permutation for a shuffle round over `len` records
+    ///
+    /// The permutation is seeded by the round's leader rather than by this
+    /// server, so every server applies the same permutation to its shares and
+    /// knows it. `participate_in_shuffle` permutes records obliviously instead.
+    pub fn generate_round_permutation(&mut self, round: usize, len: usize) -> Result<Vec<usize>, ProtocolError> {
+        if !self.role.participates_in_shuffle() {
+            return Err(ProtocolError::server_error(
+                "Server does not participate in shuffle".to_string(),
+            ));
+        }
+
+        let leader = self.config.round_leader(round);
+        let mut permutation = self.pools.indices.take_with_capacity(len);
+        permutation.extend(0..len);
+        let seed = (leader as u64) * 1000 + (round as u64);
+        self.shuffle_permutation(&mut permutation, seed);
+
+        // Keep a copy in the previous round's buffer rather than a new one
+        let mut stored = self.permutation.take().unwrap_or_else(|| self.pools.indices.take());
+        stored.clear();
+        stored.extend_from_slice(&permutation);
+        self.permutation = Some(stored);
+        self.round_number = round;
+
+        Ok(permutation)
+    }
+
     /// Apply permutation to shares
     pub async fn apply_permutation(
         &mut self,
-        shares: Vec<Vec<DataShare>>,
+        mut shares: Vec<Vec<DataShare>>,
         permutation: Vec<usize>,
     ) -> Result<Vec<Vec<DataShare>>, ProtocolError> {
         if !self.role.participates_in_shuffle() {
@@ -186,15 +427,25 @@ impl MultiPartyServer {
             ));
         }
 
-        let mut permuted_shares = shares;
+        if permutation.len() != shares.len() {
+            return Err(ProtocolError::InternalError {
+                message: format!(
+                    "Permutation of length {} applied to {} records",
+                    permutation.len(),
+                    shares.len()
+                ),
+            });
+        }
 
-        // Apply permutation to each set of shares
-        for share_set in &mut permuted_shares {
-            let mut temp = share_set.clone();
-            for (i, &new_pos) in permutation.iter().enumerate() {
-                share_set[new_pos] = temp[i].clone();
-            }
+        // Move each record's shares to its new position, then hand the emptied
+        // input and the permutation back to the pools for the next round
+        let mut permuted_shares = self.pools.records.take_with_capacity(shares.len());
+        permuted_shares.resize_with(shares.len(), Vec::new);
+        for (share_set, &new_pos) in shares.drain(..).zip(permutation.iter()) {
+            permuted_shares[new_pos] = share_set;
         }
+        self.pools.records.give(shares);
+        self.pools.indices.give(permutation);
 
         Ok(permuted_shares)
     }
@@ -215,26 +466,199 @@ impl MultiPartyServer {
         }
     }
 
+    /// Shuffle ElGamal ciphertexts with a secret permutation and rerandomize them
+    ///
+    /// Each server applies its own permutation in turn, as in a re-encryption
+    /// mix-net, so the output cannot be linked to the input unless every
+    /// server colludes. Unlike `participate_in_shuffle`, the permutation is
+    /// drawn from this server's own randomness and never shared.
+    pub async fn shuffle_ciphertexts(
+        &mut self,
+        ciphertexts: Vec<ElGamalCiphertext>,
+    ) -> Result<Vec<ElGamalCiphertext>, ProtocolError> {
+        let _phase = self.timings.scope(ProtocolPhase::Shuffling);
+        let operation = Operation::start("multi_party", "reencryption_shuffle");
+        let items = ciphertexts.len();
+        let result = async {
+            if !self.role.participates_in_shuffle() {
+                return Err(ProtocolError::server_error(
+                    "Server does not participate in shuffle".to_string(),
+                ));
+            }
+
+            let mut permutation: Vec<usize> = (0..ciphertexts.len()).collect();
+            permutation.shuffle(&mut rand::thread_rng());
+            Ok(permutation
+                .into_iter()
+                .map(|index| self.crypto.rerandomize(&ciphertexts[index]))
+                .collect())
+        }
+        .await;
+
+        operation.finish(&result, items);
+        result
+    }
+
     /// Participate in oblivious shuffle protocol
+    ///
+    /// Every server holding shares of the records calls this at the same
+    /// time, over channels connected to each other. In each round, the
+    /// round's leader draws a secret permutation and deals shares of the
+    /// control bits of its `WaksmanNetwork`, and the servers evaluate the
+    /// network on their shares with `ObliviousShuffle`, spending triples from
+    /// `receive_triples`. A leader knows only its own permutation, so no
+    /// server learns the composed one.
     pub async fn participate_in_shuffle(
         &mut self,
         shares: Vec<Vec<DataShare>>,
     ) -> Result<Vec<Vec<DataShare>>, ProtocolError> {
-        if !self.role.participates_in_shuffle() {
-            return Err(ProtocolError::server_error(
-                "Server does not participate in shuffle".to_string(),
-            ));
+        let _phase = self.timings.scope(ProtocolPhase::Shuffling);
+        let operation = Operation::start("multi_party", "shuffling");
+        let items = shares.len();
+        let result = async {
+            if !self.role.participates_in_shuffle() {
+                return Err(ProtocolError::server_error(
+                    "Server does not participate in shuffle".to_string(),
+                ));
+            }
+
+            let needed = self.shuffle_triples_needed(&shares);
+            if self.triples.len() < needed {
+                return Err(ProtocolError::server_error(format!(
+                    "Shuffle of {} records needs {} Beaver triples, server {} holds {}",
+                    shares.len(),
+                    needed,
+                    self.id,
+                    self.triples.len()
+                )));
+            }
+
+            let mut current_shares = shares;
+
+            // Run one round per leader, enough that some round is led by an honest server
+            for round in 0..self.config.shuffle_rounds() {
+                current_shares = self.shuffle_round(round, current_shares).await?;
+            }
+
+            Ok(current_shares)
+        }
+        .await;
+
+        operation.finish(&result, items);
+        result
+    }
+
+    /// Shuffle records through the Waksman network of one round's leader
+    async fn shuffle_round(
+        &mut self,
+        round: usize,
+        records: Vec<Vec<DataShare>>,
+    ) -> Result<Vec<Vec<DataShare>>, ProtocolError> {
+        let network = WaksmanNetwork::new(records.len());
+        if network.switch_count() == 0 {
+            return Ok(records);
+        }
+        let peers: Vec<usize> = (0..self.config.num_servers).filter(|&id| id != self.id).collect();
+
+        let leader = self.config.round_leader(round);
+        let bits = if leader == self.id {
+            let mut permutation = self.pools.indices.take_with_capacity(records.len());
+            permutation.extend(0..records.len());
+            permutation.shuffle(&mut rand::thread_rng());
+            let dealt = network.share_control_bits(
+                &permutation,
+                self.config.threshold,
+                self.config.num_servers,
+                self.crypto.scheme,
+            );
+            self.pools.indices.give(permutation);
+
+            let mut dealt = dealt?;
+            for &peer in &peers {
+                self.send_shuffle_shares(peer, &dealt[peer]).await?;
+            }
+            std::mem::take(&mut dealt[self.id])
+        } else {
+            self.receive_shuffle_shares(leader).await?
+        };
+
+        let triples = self.triples.drain(..ObliviousShuffle::triples_needed(&records)).collect();
+        let mut shuffle = ObliviousShuffle::new(self.config.threshold, self.config.num_servers, records, bits, triples)?;
+        while !shuffle.is_finished() {
+            let masked = shuffle.mask()?;
+            for &peer in &peers {
+                self.send_shuffle_shares(peer, &masked).await?;
+            }
+
+            let mut opened = vec![Vec::new(); self.config.num_servers];
+            for &peer in &peers {
+                opened[peer] = self.receive_shuffle_shares(peer).await?;
+            }
+            opened[self.id] = masked;
+            shuffle.finish_layer(&opened)?;
         }
+        self.round_number = round;
+
+        Ok(shuffle.into_records())
+    }
+
+    /// Send shares of control bits or masked values of the shuffle to another server
+    async fn send_shuffle_shares(&mut self, target_id: usize, shares: &[DataShare]) -> Result<(), ProtocolError> {
+        let payload = shares
+            .iter()
+            .map(serde_json::to_vec)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ProtocolError::server_error(format!("Failed to encode shares: {}", e)))?;
+        let sequence = self
+            .channels
+            .get_mut(&target_id)
+            .map(|channel| channel.next_sequence())
+            .ok_or_else(|| ProtocolError::network_error(format!("No channel to server {}", target_id)))?;
+
+        let message = NetworkMessage::new(MessageType::Shuffle, self.id, target_id, sequence, MessagePayload::Shares(payload));
+        self.send_message(target_id, message).await
+    }
+
+    /// Receive shares of the shuffle from another server, waiting up to `timeout_ms`
+    async fn receive_shuffle_shares(&mut self, source_id: usize) -> Result<Vec<DataShare>, ProtocolError> {
+        let timeout = Duration::from_millis(self.config.timeout_ms);
+        let message = self
+            .channels
+            .get_mut(&source_id)
+            .ok_or_else(|| ProtocolError::network_error(format!("No channel to server {}", source_id)))?
+            .receive_timeout(timeout)
+            .await?
+            .ok_or_else(|| ProtocolError::network_error("Channel closed".to_string()))?;
+
+        let (MessageType::Shuffle, MessagePayload::Shares(encoded)) = (&message.message_type, message.payload) else {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "Expected shuffle shares from server {}, received a {} message",
+                source_id,
+                message.message_type.name()
+            )));
+        };
 
-        let mut current_shares = shares;
+        encoded
+            .iter()
+            .map(|bytes| serde_json::from_slice::<DataShare>(bytes))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ProtocolError::MalformedMessage(format!("Failed to decode shuffle shares: {}", e)))
+    }
 
-        // Apply multiple rounds of permutation
-        for round in 0..self.config.num_servers {
-            let permutation = self.generate_permutation(round).await?;
-            current_shares = self.apply_permutation(current_shares, permutation).await?;
+    /// Commit to the sampled entries of a computation's output so a peer can
+    /// recompute them and the results can be cross-checked
+    pub fn commit_for_cross_check(
+        &self,
+        outputs: &[Vec<DataShare>],
+        sample: &[usize],
+    ) -> Result<(Vec<ComputationCommitment>, Vec<CommitmentOpening>), ProtocolError> {
+        if self.config.cross_check.is_none() {
+            return Err(ProtocolError::server_error(
+                "Cross-checking is not enabled".to_string(),
+            ));
         }
 
-        Ok(current_shares)
+        cross_check::commit_outputs(self.id, outputs, sample)
     }
 
     /// Reconstruct data from shares
@@ -242,34 +666,45 @@ impl MultiPartyServer {
         &self,
         shares: Vec<Vec<DataShare>>,
     ) -> Result<Vec<DataPoint>, ProtocolError> {
-        if !self.role.holds_data() {
-            return Err(ProtocolError::server_error(
-                "Server does not hold data shares".to_string(),
-            ));
-        }
-
-        let mut reconstructed_data = Vec::new();
+        let _phase = self.timings.scope(ProtocolPhase::Reconstruction);
+        let operation = Operation::start("multi_party", "reconstruction");
+        let items = shares.len();
+        let result = async {
+            if !self.role.holds_data() {
+                return Err(ProtocolError::server_error(
+                    "Server does not hold data shares".to_string(),
+                ));
+            }
 
-        for point_shares in shares {
-            let data_point = self.crypto.reconstruct_data(point_shares).await?;
-            reconstructed_data.push(data_point);
+            self.crypto.reconstruct_data_many(shares).await
         }
+        .await;
 
-        Ok(reconstructed_data)
+        operation.finish(&result, items);
+        result
     }
 
     /// Add noise for differential privacy
     pub async fn add_noise(&self, data: Vec<DataPoint>) -> Result<Vec<DataPoint>, ProtocolError> {
-        let mut noisy_data = data;
-
-        for point in &mut noisy_data {
-            for feature in point.features_mut() {
-                let noise = self.crypto.generate_noise(&self.config.privacy_budget).await?;
-                *feature += noise;
+        let _phase = self.timings.scope(ProtocolPhase::Noise);
+        let operation = Operation::start("multi_party", "noise");
+        let items = data.len();
+        let result = async {
+            let mut noisy_data = data;
+
+            for point in &mut noisy_data {
+                for feature in point.features_mut() {
+                    let noise = self.crypto.generate_noise(&self.config.privacy_budget).await?;
+                    *feature += noise;
+                }
             }
+
+            Ok(noisy_data)
         }
+        .await;
 
-        Ok(noisy_data)
+        operation.finish(&result, items);
+        result
     }
 
     /// Process a query on the server's data
@@ -278,18 +713,66 @@ impl MultiPartyServer {
         query: Query,
         data: Vec<DataPoint>,
     ) -> Result<QueryResult, ProtocolError> {
-        let result = match query.query_type {
-            crate::schema::QueryType::Mean => self.compute_mean(&data, &query),
-            crate::schema::QueryType::Variance => self.compute_variance(&data, &query),
-            crate::schema::QueryType::Histogram => self.compute_histogram(&data, &query),
-            crate::schema::QueryType::Range => self.compute_range(&data, &query),
-            _ => return Err(ProtocolError::UnsupportedQuery(query.query_type)),
-        };
+        let _phase = self.timings.scope(ProtocolPhase::Query);
+        let operation = Operation::start("multi_party", "query");
+        let items = data.len();
+        let result = async {
+            let result = match query.query_type {
+                crate::schema::QueryType::Mean => self.compute_mean(&data, &query),
+                crate::schema::QueryType::Variance => self.compute_variance(&data, &query),
+                crate::schema::QueryType::Histogram => self.compute_histogram(&data, &query),
+                crate::schema::QueryType::Range => self.compute_range(&data, &query),
+                _ => return Err(ProtocolError::UnsupportedQuery(query.query_type)),
+            };
+
+            // Add noise for query privacy
+            let noisy_result = self.add_query_noise(result).await?;
+
+            Ok(noisy_result)
+        }
+        .await;
 
-        // Add noise for query privacy
-        let noisy_result = self.add_query_noise(result).await?;
+        operation.finish(&result, items);
+        result
+    }
+
+    /// Sum rows of Paillier ciphertexts feature by feature without decrypting them
+    ///
+    /// Clients encrypt their values under the public key of an analyst, and
+    /// the server returns only the rerandomized encrypted sums for the analyst
+    /// to decrypt, e.g. to compute means with the number of rows.
+    pub async fn aggregate_encrypted(
+        &self,
+        public_key: &PaillierPublicKey,
+        rows: &[Vec<Ciphertext>],
+    ) -> Result<Vec<Ciphertext>, ProtocolError> {
+        let _phase = self.timings.scope(ProtocolPhase::Query);
+        let operation = Operation::start("multi_party", "encrypted_aggregation");
+        let items = rows.len();
+        let result = async {
+            let num_features = rows.first().map_or(0, Vec::len);
+            let mut aggregation = EncryptedAggregation::new(public_key.clone(), num_features);
+            for row in rows {
+                aggregation.push(row)?;
+            }
 
-        Ok(noisy_result)
+            Ok(aggregation.finish())
+        }
+        .await;
+
+        operation.finish(&result, items);
+        result
+    }
+
+    /// Process a query and return the result with the time this server spent in
+    /// each protocol phase, including this query
+    pub async fn process_query_with_timings(
+        &self,
+        query: Query,
+        data: Vec<DataPoint>,
+    ) -> Result<(QueryResult, PhaseTimings), ProtocolError> {
+        let result = self.process_query(query, data).await?;
+        Ok((result, self.phase_timings()))
     }
 
     /// Compute mean query
@@ -443,6 +926,16 @@ impl MultiPartyServer {
         self.state = state;
     }
 
+    /// Get the time spent in each protocol phase so far
+    pub fn phase_timings(&self) -> PhaseTimings {
+        self.timings.snapshot()
+    }
+
+    /// Take the time spent in each protocol phase and reset the timings
+    pub fn take_phase_timings(&mut self) -> PhaseTimings {
+        self.timings.take()
+    }
+
     /// Get number of shares held
     pub fn share_count(&self) -> usize {
         self.shares.len()
@@ -462,13 +955,99 @@ impl MultiPartyServer {
     pub fn is_failed(&self) -> bool {
         self.state.is_failed()
     }
+
+    /// Act on the failure detector declaring server `server_id` failed or trusting it again
+    ///
+    /// Meant to be called from `NetworkManager::on_suspicion`. While at least
+    /// `threshold` servers remain, the server carries on in the `Degraded`
+    /// state, and leaves it once every failed server is trusted again. With
+    /// fewer, it fails instead of waiting on servers that do not answer.
+    /// Suspected servers may only be slow, and change nothing.
+    pub fn update_suspicion(&mut self, server_id: usize, suspicion: Suspicion) {
+        let mut failed = match &self.state {
+            ServerState::Degraded { failed } => failed.clone(),
+            ServerState::Online | ServerState::Participating => Vec::new(),
+            ServerState::Offline | ServerState::Completed | ServerState::Failed(_) => return,
+        };
+        match suspicion {
+            Suspicion::Failed if !failed.contains(&server_id) => {
+                failed.push(server_id);
+                failed.sort_unstable();
+            }
+            Suspicion::Trusted => failed.retain(|&id| id != server_id),
+            _ => return,
+        }
+
+        let remaining = self.config.num_servers.saturating_sub(failed.len());
+        self.state = if remaining < self.config.threshold {
+            log::error!(
+                "Server {} gives up: servers {:?} failed, leaving {} of the {} needed",
+                self.id, failed, remaining, self.config.threshold
+            );
+            ServerState::Failed(format!(
+                "Servers {:?} failed, leaving {} of the {} servers needed",
+                failed, remaining, self.config.threshold
+            ))
+        } else if failed.is_empty() {
+            log::info!("Server {} leaves degraded mode", self.id);
+            if self.shares.is_empty() && self.spilled.is_empty() {
+                ServerState::Online
+            } else {
+                ServerState::Participating
+            }
+        } else {
+            log::warn!("Server {} carries on without servers {:?}", self.id, failed);
+            ServerState::Degraded { failed }
+        };
+    }
+
+    /// Drop everything held for a cancelled run
+    ///
+    /// Buffered shares and their memory budget are released, spilled shares
+    /// are deleted and the channels are closed. The server is `Offline` until
+    /// it is initialized again.
+    pub fn release(&mut self) {
+        log::info!("Server {} releasing the resources of its run", self.id);
+        let buffered = self.take_buffered_shares();
+        for path in &buffered.spilled {
+            if let Err(e) = std::fs::remove_file(path) {
+                log::warn!("Failed to remove spilled shares {}: {}", path.display(), e);
+            }
+        }
+        self.channels.clear();
+        self.permutation = None;
+        self.triples.clear();
+        self.round_number = 0;
+        self.state = ServerState::Offline;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::multi_party::beaver::TripleDealer;
+    use crate::multi_party::crypto::SharingScheme;
+    use crate::multi_party::memory::ShareMemoryConfig;
+    use crate::multi_party::paillier::{self, PaillierPrivateKey};
     use crate::schema::QueryType;
 
+    /// Connect the channels of every pair of servers to each other
+    async fn connect_servers(servers: &mut [MultiPartyServer]) {
+        for i in 0..servers.len() {
+            for j in i + 1..servers.len() {
+                let (tx_ij, rx_ij) = runtime::channel(DEFAULT_QUEUE_CAPACITY);
+                let (tx_ji, rx_ji) = runtime::channel(DEFAULT_QUEUE_CAPACITY);
+                servers[i].channels.insert(j, CommunicationChannel::new(j, tx_ij, rx_ji));
+                servers[j].channels.insert(i, CommunicationChannel::new(i, tx_ji, rx_ij));
+            }
+        }
+        for server in servers.iter_mut() {
+            for channel in server.channels.values_mut() {
+                channel.connect().await.unwrap();
+            }
+        }
+    }
+
     #[test]
     fn test_server_role() {
         let first = ServerRole::First;
@@ -478,6 +1057,98 @@ mod tests {
         let third = ServerRole::Third;
         assert!(!third.holds_data());
         assert!(third.participates_in_shuffle());
+
+        let computational = ServerRole::for_index(4, 5);
+        assert_eq!(computational, ServerRole::Computational(4));
+        assert!(computational.holds_data());
+        assert!(computational.participates_in_shuffle());
+    }
+
+    #[tokio::test]
+    async fn test_five_server_shuffle_and_reconstruction() {
+        let config = ProtocolConfig::with_servers(5, 3).unwrap();
+        let mut dealer = ThresholdEncryption::new(3, 5).unwrap();
+        dealer.initialize().await.unwrap();
+
+        let points: Vec<Vec<f64>> = vec![vec![1.0, 10.0], vec![2.0, 20.0], vec![3.0, 30.0], vec![4.0, 40.0]];
+        let mut per_server: Vec<Vec<Vec<DataShare>>> = vec![Vec::new(); 5];
+        for features in &points {
+            let mut record = vec![Vec::new(); 5];
+            for share in dealer.share_data(DataPoint::new(features.clone())).await.unwrap() {
+                record[share.server_id].push(share);
+            }
+            for (server_id, shares) in record.into_iter().enumerate() {
+                per_server[server_id].push(shares);
+            }
+        }
+
+        let mut servers: Vec<MultiPartyServer> = (0..5)
+            .map(|id| MultiPartyServer::new(id, ServerRole::for_index(id, 5), config.clone()))
+            .collect();
+        for server in &mut servers {
+            server.initialize().await.unwrap();
+        }
+        connect_servers(&mut servers).await;
+
+        // Without triples the shuffle is refused before any message is sent
+        assert!(servers[0].participate_in_shuffle(per_server[0].clone()).await.is_err());
+
+        let needed = servers[0].shuffle_triples_needed(&per_server[0]);
+        let triples = TripleDealer::new(3, 5, SharingScheme::Shamir).unwrap().deal(needed).unwrap();
+        for (server, triples) in servers.iter_mut().zip(triples) {
+            server.receive_triples(triples);
+        }
+
+        let shuffled: Vec<Vec<Vec<DataShare>>> = futures::future::join_all(
+            servers.iter_mut().zip(per_server).map(|(server, records)| server.participate_in_shuffle(records)),
+        )
+        .await
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+        assert!(servers.iter().all(|server| server.triples.is_empty()));
+
+        // Any 3 of the 5 servers can reconstruct the shuffled records
+        let quorum = [0, 2, 4];
+        let combined: Vec<Vec<DataShare>> = (0..points.len())
+            .map(|i| quorum.iter().flat_map(|&id| shuffled[id][i].clone()).collect())
+            .collect();
+        let reconstructed = servers[0].reconstruct_data(combined).await.unwrap();
+
+        let mut result: Vec<Vec<f64>> = reconstructed.iter().map(|p| p.features().to_vec()).collect();
+        result.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+        assert_eq!(result, points);
+    }
+
+    #[tokio::test]
+    async fn test_pooled_shuffle() {
+        let config = ProtocolConfig::with_servers(5, 3).unwrap();
+        let records: Vec<Vec<DataShare>> = (0..8)
+            .map(|i| vec![DataShare::new(0, 0, ShareType::Feature, i, 97)])
+            .collect();
+
+        async fn shuffle(server: &mut MultiPartyServer, mut records: Vec<Vec<DataShare>>) -> Vec<Vec<DataShare>> {
+            for round in 0..server.config.shuffle_rounds() {
+                let permutation = server.generate_round_permutation(round, records.len()).unwrap();
+                records = server.apply_permutation(records, permutation).await.unwrap();
+            }
+            records
+        }
+
+        let mut plain = MultiPartyServer::new(0, ServerRole::for_index(0, 5), config.clone());
+        plain.initialize().await.unwrap();
+        let expected = shuffle(&mut plain, records.clone()).await;
+
+        let pools = Arc::new(SharePools::new(4));
+        let mut pooled = MultiPartyServer::new(0, ServerRole::for_index(0, 5), config)
+            .with_pools(pools.clone());
+        pooled.initialize().await.unwrap();
+        let shuffled = shuffle(&mut pooled, records).await;
+
+        let values = |records: &[Vec<DataShare>]| records.iter().map(|r| r[0].value).collect::<Vec<_>>();
+        assert_eq!(values(&shuffled), values(&expected));
+        assert!(pools.records.stats().hits > 0);
+        assert!(pools.indices.stats().hits > 0);
     }
 
     #[tokio::test]
@@ -499,6 +1170,155 @@ mod tests {
         assert_eq!(server.state(), &ServerState::Online);
     }
 
+    #[tokio::test]
+    async fn test_receive_shares_backpressure() {
+        let share = DataShare::feature(0, 0, 1, 97);
+        let size = memory::share_size(&share);
+        let config = ProtocolConfig {
+            timeout_ms: 20,
+            share_memory: Some(ShareMemoryConfig::backpressure(size)),
+            ..ProtocolConfig::default()
+        };
+        let mut server = MultiPartyServer::new(0, ServerRole::First, config);
+
+        server.receive_shares(vec![share.clone()]).await.unwrap();
+        let err = server.receive_shares(vec![share.clone()]).await.unwrap_err();
+        assert!(matches!(err, ProtocolError::Timeout { .. }));
+
+        let buffered = server.take_buffered_shares();
+        assert_eq!(buffered.shares.len(), 1);
+        drop(buffered);
+        server.receive_shares(vec![share]).await.unwrap();
+        assert_eq!(server.share_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_receive_verified_shares() {
+        let config = ProtocolConfig::default();
+        let vss = PedersenVss::new(config.threshold, config.num_servers).unwrap();
+        let (shares, commitments) = vss.share_data(&[3, 9]).unwrap();
+        let mut own: Vec<DataShare> = shares.into_iter().filter(|share| share.server_id == 0).collect();
+        let mut server = MultiPartyServer::new(0, ServerRole::First, config);
+
+        own[1].value += 1;
+        let err = server.receive_verified_shares(own.clone(), &commitments).await.unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidShare { server_id: 0, feature_index: 1 }));
+        assert_eq!(server.share_count(), 0);
+
+        own[1].value -= 1;
+        server.receive_verified_shares(own, &commitments).await.unwrap();
+        assert_eq!(server.share_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_encrypted() {
+        let key = PaillierPrivateKey::generate(paillier::MIN_KEY_BITS).unwrap();
+        let public_key = key.public_key();
+        let server = MultiPartyServer::new(0, ServerRole::First, ProtocolConfig::default());
+
+        let rows: Vec<Vec<Ciphertext>> = [[4u64, 1], [6, 0]]
+            .iter()
+            .map(|row| row.iter().map(|&value| public_key.encrypt(value)).collect())
+            .collect();
+        let sums = server.aggregate_encrypted(public_key, &rows).await.unwrap();
+        assert_eq!(key.decrypt(&sums[0]).unwrap(), 10);
+        assert_eq!(key.decrypt(&sums[1]).unwrap(), 1);
+
+        let ragged = vec![rows[0].clone(), rows[1][..1].to_vec()];
+        assert!(server.aggregate_encrypted(public_key, &ragged).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shuffle_ciphertexts() {
+        let mut server = MultiPartyServer::new(0, ServerRole::First, ProtocolConfig::default());
+        server.initialize().await.unwrap();
+        let crypto = &server.crypto;
+
+        let mut ciphertexts = Vec::new();
+        for value in 0..4 {
+            ciphertexts.push(crypto.encrypt(value).await.unwrap());
+        }
+        let shuffled = server.shuffle_ciphertexts(ciphertexts.clone()).await.unwrap();
+        assert!(shuffled.iter().all(|ciphertext| !ciphertexts.contains(ciphertext)));
+
+        let crypto = &server.crypto;
+        let ids: Vec<usize> = (0..crypto.threshold()).collect();
+        let mut values = Vec::new();
+        for ciphertext in &shuffled {
+            let mut shares = Vec::new();
+            for &id in &ids {
+                shares.push(crypto.partial_decrypt(id, ciphertext).await.unwrap());
+            }
+            values.push(crypto.decrypt(ciphertext, &shares, 3).await.unwrap());
+        }
+        values.sort();
+        assert_eq!(values, vec![0, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_receive_shares_spills_over_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ProtocolConfig {
+            share_memory: Some(ShareMemoryConfig::spill(1, dir.path())),
+            ..ProtocolConfig::default()
+        };
+        let mut server = MultiPartyServer::new(1, ServerRole::Second, config);
+
+        let shares: Vec<DataShare> = (0..4).map(|i| DataShare::feature(1, i, i as u64, 97)).collect();
+        server.receive_shares(shares).await.unwrap();
+        assert_eq!(server.share_count(), 0);
+
+        let buffered = server.take_buffered_shares();
+        assert_eq!(buffered.load_spilled().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_release_after_cancellation() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ProtocolConfig {
+            share_memory: Some(ShareMemoryConfig::spill(1, dir.path())),
+            ..ProtocolConfig::default()
+        };
+        let mut server = MultiPartyServer::new(1, ServerRole::Second, config);
+        server.initialize().await.unwrap();
+        let shares: Vec<DataShare> = (0..4).map(|i| DataShare::feature(1, i, i as u64, 97)).collect();
+        server.receive_shares(shares).await.unwrap();
+
+        server.release();
+        assert_eq!(server.state(), &ServerState::Offline);
+        assert!(server.channels.is_empty());
+        assert!(server.take_buffered_shares().spilled.is_empty());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_degraded_mode() {
+        let config = ProtocolConfig::with_servers(4, 2).unwrap();
+        let mut server = MultiPartyServer::new(0, ServerRole::for_index(0, 4), config);
+        server.update_suspicion(1, Suspicion::Failed);
+        assert_eq!(server.state(), &ServerState::Offline);
+
+        server.initialize().await.unwrap();
+        server.update_suspicion(1, Suspicion::Suspected);
+        assert_eq!(server.state(), &ServerState::Online);
+        server.update_suspicion(3, Suspicion::Failed);
+        server.update_suspicion(1, Suspicion::Failed);
+        assert_eq!(server.state(), &ServerState::Degraded { failed: vec![1, 3] });
+        assert!(server.is_available());
+
+        server.update_suspicion(1, Suspicion::Trusted);
+        server.update_suspicion(3, Suspicion::Trusted);
+        assert_eq!(server.state(), &ServerState::Online);
+
+        // Two servers are needed, and only one would be left
+        for id in 1..4 {
+            server.update_suspicion(id, Suspicion::Failed);
+        }
+        assert!(server.is_failed());
+        server.update_suspicion(1, Suspicion::Trusted);
+        assert!(server.is_failed());
+    }
+
     #[tokio::test]
     async fn test_permutation_generation() {
         let config = ProtocolConfig::default();
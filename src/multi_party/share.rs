@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
 /// Types of data shares
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -14,6 +15,8 @@ pub enum ShareType {
     Permutation,
     /// Query result share
     QueryResult,
+    /// Share of a component of a Beaver triple
+    Triple,
 }
 
 /// Data share structure
@@ -31,6 +34,9 @@ pub struct DataShare {
     pub modulus: u64,
     /// Share metadata
     pub metadata: HashMap<String, String>,
+    /// Share of the MAC of the secret under the global MAC key, if authenticated
+    #[serde(default)]
+    pub mac: Option<u64>,
 }
 
 impl DataShare {
@@ -49,6 +55,7 @@ impl DataShare {
             value,
             modulus,
             metadata: HashMap::new(),
+            mac: None,
         }
     }
 
@@ -72,6 +79,11 @@ impl DataShare {
         Self::new(server_id, feature_index, ShareType::Permutation, value, modulus)
     }
 
+    /// Create a share of a component of a Beaver triple
+    pub fn triple(server_id: usize, triple_index: usize, value: u64, modulus: u64) -> Self {
+        Self::new(server_id, triple_index, ShareType::Triple, value, modulus)
+    }
+
     /// Add metadata to the share
     pub fn add_metadata(&mut self, key: impl Into<String>, value: impl Into<String>) {
         self.metadata.insert(key.into(), value.into());
@@ -102,14 +114,32 @@ impl DataShare {
         matches!(self.share_type, ShareType::Permutation)
     }
 
+    /// Check if this is a Beaver triple share
+    pub fn is_triple(&self) -> bool {
+        matches!(self.share_type, ShareType::Triple)
+    }
+
+    /// Check if this share carries a MAC share
+    pub fn is_authenticated(&self) -> bool {
+        self.mac.is_some()
+    }
+
     /// Get the normalized value (0.0 to 1.0)
     pub fn normalized_value(&self) -> f64 {
-        self.value as f64 / self.modulus as f64
+        self.value as f64 / self.modulus_f64()
     }
 
     /// Set the normalized value
     pub fn set_normalized_value(&mut self, normalized: f64) {
-        self.value = (normalized * self.modulus as f64) as u64;
+        self.value = (normalized * self.modulus_f64()) as u64;
+    }
+
+    /// Modulus as a float, where 0 stands for 2^64 as in additive shares
+    fn modulus_f64(&self) -> f64 {
+        match self.modulus {
+            0 => 2f64.powi(64),
+            modulus => modulus as f64,
+        }
     }
 }
 
@@ -215,6 +245,76 @@ impl Default for ShareDistribution {
     }
 }
 
+/// Expiry policy for the share cache
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Entries older than this are expired
+    pub ttl: Option<Duration>,
+    /// Entries cached more than this many epochs ago are expired
+    pub max_epochs: Option<u64>,
+    /// Maximum number of entries; least recently used entries are evicted beyond it
+    pub max_entries: Option<usize>,
+}
+
+/// Counters for share cache evictions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheMetrics {
+    /// Entries expired by TTL
+    pub ttl_evictions: u64,
+    /// Entries expired by epoch
+    pub epoch_evictions: u64,
+    /// Entries evicted to respect the entry bound
+    pub capacity_evictions: u64,
+    /// Entries removed explicitly
+    pub explicit_evictions: u64,
+}
+
+impl CacheMetrics {
+    /// Total number of evicted entries
+    pub fn total_evictions(&self) -> u64 {
+        self.ttl_evictions + self.epoch_evictions + self.capacity_evictions + self.explicit_evictions
+    }
+}
+
+/// Reason a cache entry expired
+#[derive(Debug, Clone, Copy)]
+enum Expiry {
+    Ttl,
+    Epoch,
+}
+
+/// Cached shares with bookkeeping for expiry
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    /// Cached shares
+    pub shares: DataPointShares,
+    /// Time the entry was cached
+    pub inserted_at: Instant,
+    /// Epoch in which the entry was cached
+    pub epoch: u64,
+    /// Logical time of the last access, for LRU ordering
+    last_access: u64,
+}
+
+/// Resources a server contributes to the protocol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServerCapacity {
+    /// Number of CPU cores
+    pub cpu_cores: f64,
+    /// Available memory in bytes
+    pub memory_bytes: f64,
+}
+
+impl ServerCapacity {
+    /// Create a new server capacity
+    pub fn new(cpu_cores: f64, memory_bytes: f64) -> Self {
+        Self {
+            cpu_cores,
+            memory_bytes,
+        }
+    }
+}
+
 /// Share manager for coordinating share operations
 pub struct ShareManager {
     /// Distribution strategy
@@ -224,7 +324,17 @@ pub struct ShareManager {
     /// Threshold for reconstruction
     pub threshold: usize,
     /// Share cache
-    pub cache: HashMap<String, DataPointShares>,
+    pub cache: HashMap<String, CacheEntry>,
+    /// Cache expiry policy
+    pub cache_policy: CachePolicy,
+    /// Cache eviction counters
+    pub cache_metrics: CacheMetrics,
+    /// Current cache epoch
+    current_epoch: u64,
+    /// Logical clock for LRU ordering
+    access_clock: u64,
+    /// Per-server capacities used by weighted distribution
+    pub capacities: Vec<ServerCapacity>,
 }
 
 impl ShareManager {
@@ -235,7 +345,46 @@ impl ShareManager {
             num_servers,
             threshold,
             cache: HashMap::new(),
+            cache_policy: CachePolicy::default(),
+            cache_metrics: CacheMetrics::default(),
+            current_epoch: 0,
+            access_clock: 0,
+            capacities: Vec::new(),
+        }
+    }
+
+    /// Set per-server capacities for weighted distribution
+    pub fn with_capacities(mut self, capacities: Vec<ServerCapacity>) -> Self {
+        self.capacities = capacities;
+        self
+    }
+
+    /// Target fraction of shares for each server
+    ///
+    /// Each server's CPU and memory fractions of the cluster total are
+    /// averaged. Without valid capacities every server gets an equal fraction.
+    pub fn target_ratios(&self) -> Vec<f64> {
+        let even = vec![1.0 / self.num_servers as f64; self.num_servers];
+        if !matches!(self.distribution, ShareDistribution::Weighted) || self.capacities.len() != self.num_servers {
+            return even;
         }
+
+        let total_cpu: f64 = self.capacities.iter().map(|c| c.cpu_cores.max(0.0)).sum();
+        let total_memory: f64 = self.capacities.iter().map(|c| c.memory_bytes.max(0.0)).sum();
+        if total_cpu <= 0.0 || total_memory <= 0.0 {
+            return even;
+        }
+
+        self.capacities
+            .iter()
+            .map(|c| (c.cpu_cores.max(0.0) / total_cpu + c.memory_bytes.max(0.0) / total_memory) / 2.0)
+            .collect()
+    }
+
+    /// Set the cache expiry policy
+    pub fn with_cache_policy(mut self, policy: CachePolicy) -> Self {
+        self.cache_policy = policy;
+        self
     }
 
     /// Distribute shares according to the strategy
@@ -250,9 +399,21 @@ impl ShareManager {
                 }
             }
             ShareDistribution::Weighted => {
-                // Simple weighted distribution based on server ID
-                for (i, share) in shares.into_iter().enumerate() {
-                    let server_id = (i * 2) % self.num_servers; // Simple weighting
+                // Smooth weighted round robin keeps every prefix close to the target ratios
+                let weights = self.target_ratios();
+                let mut current = vec![0.0; self.num_servers];
+
+                for share in shares {
+                    for (credit, weight) in current.iter_mut().zip(&weights) {
+                        *credit += weight;
+                    }
+                    let server_id = current
+                        .iter()
+                        .enumerate()
+                        .max_by(|a, b| a.1.total_cmp(b.1))
+                        .map(|(id, _)| id)
+                        .unwrap_or(0);
+                    current[server_id] -= 1.0;
                     distribution.entry(server_id).or_insert_with(Vec::new).push(share);
                 }
             }
@@ -286,21 +447,82 @@ impl ShareManager {
 
     /// Cache shares for a data point
     pub fn cache_shares(&mut self, data_point_id: String, shares: DataPointShares) {
-        self.cache.insert(data_point_id, shares);
+        let entry = CacheEntry {
+            shares,
+            inserted_at: Instant::now(),
+            epoch: self.current_epoch,
+            last_access: self.next_access(),
+        };
+        self.cache.insert(data_point_id, entry);
+        self.enforce_capacity();
     }
 
     /// Get cached shares for a data point
-    pub fn get_cached_shares(&self, data_point_id: &str) -> Option<&DataPointShares> {
-        self.cache.get(data_point_id)
+    ///
+    /// Expired entries are not returned. A hit marks the entry as recently used.
+    pub fn get_cached_shares(&mut self, data_point_id: &str) -> Option<&DataPointShares> {
+        let now = Instant::now();
+        if let Some(expiry) = self.cache.get(data_point_id).and_then(|entry| self.expiry(entry, now)) {
+            self.cache.remove(data_point_id);
+            self.record_expiry(expiry);
+            return None;
+        }
+
+        let access = self.next_access();
+        self.cache.get_mut(data_point_id).map(|entry| {
+            entry.last_access = access;
+            &entry.shares
+        })
     }
 
     /// Remove cached shares for a data point
     pub fn remove_cached_shares(&mut self, data_point_id: &str) {
-        self.cache.remove(data_point_id);
+        if self.cache.remove(data_point_id).is_some() {
+            self.cache_metrics.explicit_evictions += 1;
+        }
+    }
+
+    /// Remove cached shares for every data point in `data_point_ids`
+    pub fn evict_cached_shares(&mut self, data_point_ids: &HashSet<String>) -> usize {
+        let before = self.cache.len();
+        self.cache.retain(|id, _| !data_point_ids.contains(id));
+        let evicted = before - self.cache.len();
+        self.cache_metrics.explicit_evictions += evicted as u64;
+        evicted
+    }
+
+    /// Advance the cache epoch and expire entries that are too old
+    pub fn advance_epoch(&mut self) -> u64 {
+        self.current_epoch += 1;
+        self.expire_cached_shares();
+        self.current_epoch
+    }
+
+    /// Get the current cache epoch
+    pub fn current_epoch(&self) -> u64 {
+        self.current_epoch
+    }
+
+    /// Remove all expired entries, returning how many were removed
+    pub fn expire_cached_shares(&mut self) -> usize {
+        let now = Instant::now();
+        let expired: Vec<(String, Expiry)> = self
+            .cache
+            .iter()
+            .filter_map(|(id, entry)| self.expiry(entry, now).map(|expiry| (id.clone(), expiry)))
+            .collect();
+
+        for (id, expiry) in &expired {
+            self.cache.remove(id);
+            self.record_expiry(*expiry);
+        }
+
+        expired.len()
     }
 
     /// Clear all cached shares
     pub fn clear_cache(&mut self) {
+        self.cache_metrics.explicit_evictions += self.cache.len() as u64;
         self.cache.clear();
     }
 
@@ -309,6 +531,67 @@ impl ShareManager {
         self.cache.len()
     }
 
+    /// Get cache eviction counters
+    pub fn cache_metrics(&self) -> &CacheMetrics {
+        &self.cache_metrics
+    }
+
+    /// Check whether an entry has expired under the cache policy
+    fn expiry(&self, entry: &CacheEntry, now: Instant) -> Option<Expiry> {
+        if self
+            .cache_policy
+            .max_epochs
+            .is_some_and(|max| self.current_epoch - entry.epoch > max)
+        {
+            Some(Expiry::Epoch)
+        } else if self
+            .cache_policy
+            .ttl
+            .is_some_and(|ttl| now.duration_since(entry.inserted_at) > ttl)
+        {
+            Some(Expiry::Ttl)
+        } else {
+            None
+        }
+    }
+
+    /// Count an expired entry in the eviction metrics
+    fn record_expiry(&mut self, expiry: Expiry) {
+        match expiry {
+            Expiry::Ttl => self.cache_metrics.ttl_evictions += 1,
+            Expiry::Epoch => self.cache_metrics.epoch_evictions += 1,
+        }
+    }
+
+    /// Evict least recently used entries until the entry bound is respected
+    fn enforce_capacity(&mut self) {
+        let Some(max_entries) = self.cache_policy.max_entries else {
+            return;
+        };
+
+        while self.cache.len() > max_entries {
+            let oldest = self
+                .cache
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(id, _)| id.clone());
+
+            match oldest {
+                Some(id) => {
+                    self.cache.remove(&id);
+                    self.cache_metrics.capacity_evictions += 1;
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Advance the logical access clock
+    fn next_access(&mut self) -> u64 {
+        self.access_clock += 1;
+        self.access_clock
+    }
+
     /// Validate share distribution
     pub fn validate_distribution(&self, distribution: &HashMap<usize, Vec<DataShare>>) -> bool {
         // Check that all servers have shares
@@ -336,12 +619,22 @@ impl ShareManager {
         let max_shares = distribution.values().map(|v| v.len()).max().unwrap_or(0);
         let avg_shares = if distribution.is_empty() { 0.0 } else { total_shares as f64 / distribution.len() as f64 };
 
+        let target_ratios = self.target_ratios();
+        let realized_ratios = (0..self.num_servers)
+            .map(|server_id| {
+                let count = distribution.get(&server_id).map_or(0, |v| v.len());
+                if total_shares == 0 { 0.0 } else { count as f64 / total_shares as f64 }
+            })
+            .collect();
+
         ShareStats {
             total_shares,
             min_shares,
             max_shares,
             avg_shares,
             num_servers: distribution.len(),
+            target_ratios,
+            realized_ratios,
         }
     }
 }
@@ -359,9 +652,22 @@ pub struct ShareStats {
     pub avg_shares: f64,
     /// Number of servers
     pub num_servers: usize,
+    /// Target fraction of shares per server
+    pub target_ratios: Vec<f64>,
+    /// Realized fraction of shares per server
+    pub realized_ratios: Vec<f64>,
 }
 
 impl ShareStats {
+    /// Largest absolute difference between realized and target ratios
+    pub fn max_ratio_deviation(&self) -> f64 {
+        self.target_ratios
+            .iter()
+            .zip(&self.realized_ratios)
+            .map(|(target, realized)| (target - realized).abs())
+            .fold(0.0, f64::max)
+    }
+
     /// Check if distribution is balanced
     pub fn is_balanced(&self) -> bool {
         self.max_shares - self.min_shares <= 1
@@ -431,6 +737,80 @@ mod tests {
         assert!(manager.validate_distribution(&distribution));
     }
 
+    #[test]
+    fn test_weighted_distribution() {
+        let capacities = vec![
+            ServerCapacity::new(8.0, 32.0),
+            ServerCapacity::new(4.0, 16.0),
+            ServerCapacity::new(4.0, 16.0),
+        ];
+        let manager = ShareManager::new(ShareDistribution::Weighted, 3, 2).with_capacities(capacities);
+        assert_eq!(manager.target_ratios(), vec![0.5, 0.25, 0.25]);
+
+        let shares: Vec<DataShare> = (0..100).map(|i| DataShare::feature(0, i, i as u64, 97)).collect();
+        let distribution = manager.distribute_shares(shares);
+        assert_eq!(distribution[&0].len(), 50);
+        assert_eq!(distribution[&1].len(), 25);
+
+        let stats = manager.get_distribution_stats(&distribution);
+        assert!(stats.max_ratio_deviation() < 1e-9);
+    }
+
+    #[test]
+    fn test_cache_lru_eviction() {
+        let policy = CachePolicy {
+            max_entries: Some(2),
+            ..CachePolicy::default()
+        };
+        let mut manager = ShareManager::new(ShareDistribution::Even, 3, 2).with_cache_policy(policy);
+
+        manager.cache_shares("a".to_string(), DataPointShares::new("a"));
+        manager.cache_shares("b".to_string(), DataPointShares::new("b"));
+        assert!(manager.get_cached_shares("a").is_some());
+        manager.cache_shares("c".to_string(), DataPointShares::new("c"));
+
+        assert_eq!(manager.cache_size(), 2);
+        assert!(manager.get_cached_shares("b").is_none());
+        assert!(manager.get_cached_shares("a").is_some());
+        assert_eq!(manager.cache_metrics().capacity_evictions, 1);
+    }
+
+    #[test]
+    fn test_cache_epoch_and_explicit_eviction() {
+        let policy = CachePolicy {
+            max_epochs: Some(1),
+            ..CachePolicy::default()
+        };
+        let mut manager = ShareManager::new(ShareDistribution::Even, 3, 2).with_cache_policy(policy);
+
+        manager.cache_shares("old".to_string(), DataPointShares::new("old"));
+        manager.advance_epoch();
+        manager.cache_shares("x".to_string(), DataPointShares::new("x"));
+        manager.cache_shares("y".to_string(), DataPointShares::new("y"));
+        manager.advance_epoch();
+        assert!(manager.get_cached_shares("old").is_none());
+        assert_eq!(manager.cache_metrics().epoch_evictions, 1);
+
+        let ids: HashSet<String> = ["x".to_string()].into_iter().collect();
+        assert_eq!(manager.evict_cached_shares(&ids), 1);
+        assert_eq!(manager.cache_size(), 1);
+        assert_eq!(manager.cache_metrics().explicit_evictions, 1);
+    }
+
+    #[test]
+    fn test_cache_ttl_expiry() {
+        let policy = CachePolicy {
+            ttl: Some(Duration::ZERO),
+            ..CachePolicy::default()
+        };
+        let mut manager = ShareManager::new(ShareDistribution::Even, 3, 2).with_cache_policy(policy);
+
+        manager.cache_shares("a".to_string(), DataPointShares::new("a"));
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(manager.expire_cached_shares(), 1);
+        assert_eq!(manager.cache_metrics().ttl_evictions, 1);
+    }
+
     #[test]
     fn test_share_stats() {
         let mut distribution = HashMap::new();
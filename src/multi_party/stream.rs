@@ -0,0 +1,247 @@
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::share::DataPointShares;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+/// Magic bytes at the start of a share stream
+const STREAM_MAGIC: &[u8; 4] = b"DPSS";
+
+/// Version of the share stream format
+const STREAM_VERSION: u8 = 1;
+
+/// Default number of data points per chunk
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// Largest chunk accepted when reading, in bytes
+pub const MAX_CHUNK_BYTES: usize = 256 * 1024 * 1024;
+
+/// Writes `DataPointShares` as a sequence of length-prefixed chunks
+///
+/// Only one chunk is buffered at a time, so arbitrarily many points can be
+/// written to a file or socket with bounded memory.
+pub struct ShareStreamWriter<W: Write> {
+    /// Underlying writer
+    writer: W,
+    /// Points waiting to be written
+    pending: Vec<DataPointShares>,
+    /// Maximum points per chunk
+    chunk_size: usize,
+    /// Number of points written
+    points_written: usize,
+    /// Whether the header has been written
+    header_written: bool,
+}
+
+impl<W: Write> ShareStreamWriter<W> {
+    /// Create a new stream writer
+    pub fn new(writer: W) -> Self {
+        Self::with_chunk_size(writer, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Create a new stream writer with the given chunk size
+    pub fn with_chunk_size(writer: W, chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Self {
+            writer,
+            pending: Vec::with_capacity(chunk_size),
+            chunk_size,
+            points_written: 0,
+            header_written: false,
+        }
+    }
+
+    /// Queue a data point, writing a chunk when it is full
+    pub fn write(&mut self, shares: DataPointShares) -> Result<(), ProtocolError> {
+        self.pending.push(shares);
+        if self.pending.len() >= self.chunk_size {
+            self.write_chunk()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write every data point produced by an iterator
+    pub fn write_all(&mut self, shares: impl IntoIterator<Item = DataPointShares>) -> Result<(), ProtocolError> {
+        for point in shares {
+            self.write(point)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write any pending chunk and the end marker, returning the writer
+    pub fn finish(mut self) -> Result<W, ProtocolError> {
+        self.write_chunk()?;
+        self.write_header()?;
+        self.writer.write_all(&0u32.to_le_bytes()).map_err(stream_error)?;
+        self.writer.flush().map_err(stream_error)?;
+
+        Ok(self.writer)
+    }
+
+    /// Number of points written so far
+    pub fn points_written(&self) -> usize {
+        self.points_written
+    }
+
+    /// Write the stream header if it has not been written yet
+    fn write_header(&mut self) -> Result<(), ProtocolError> {
+        if !self.header_written {
+            self.writer.write_all(STREAM_MAGIC).map_err(stream_error)?;
+            self.writer.write_all(&[STREAM_VERSION]).map_err(stream_error)?;
+            self.header_written = true;
+        }
+
+        Ok(())
+    }
+
+    /// Serialize and write the pending points as one chunk
+    fn write_chunk(&mut self) -> Result<(), ProtocolError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        self.write_header()?;
+        let bytes = serde_json::to_vec(&self.pending)
+            .map_err(|e| ProtocolError::server_error(format!("Failed to encode share chunk: {}", e)))?;
+        let length = u32::try_from(bytes.len())
+            .ok()
+            .filter(|&length| length > 0 && length as usize <= MAX_CHUNK_BYTES)
+            .ok_or_else(|| ProtocolError::server_error(format!("Share chunk of {} bytes is too large", bytes.len())))?;
+
+        self.writer.write_all(&length.to_le_bytes()).map_err(stream_error)?;
+        self.writer.write_all(&bytes).map_err(stream_error)?;
+        self.points_written += self.pending.len();
+        self.pending.clear();
+
+        Ok(())
+    }
+}
+
+/// Reads `DataPointShares` written by `ShareStreamWriter`, one chunk at a time
+pub struct ShareStreamReader<R: Read> {
+    /// Underlying reader
+    reader: R,
+    /// Decoded points not yet returned
+    buffered: VecDeque<DataPointShares>,
+    /// Whether the header has been read
+    header_read: bool,
+    /// Whether the end marker has been reached
+    finished: bool,
+}
+
+impl<R: Read> ShareStreamReader<R> {
+    /// Create a new stream reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffered: VecDeque::new(),
+            header_read: false,
+            finished: false,
+        }
+    }
+
+    /// Read and check the stream header
+    fn read_header(&mut self) -> Result<(), ProtocolError> {
+        let mut header = [0u8; 5];
+        self.reader.read_exact(&mut header).map_err(stream_error)?;
+        if &header[..4] != STREAM_MAGIC {
+            return Err(ProtocolError::server_error("Not a share stream".to_string()));
+        }
+        if header[4] != STREAM_VERSION {
+            return Err(ProtocolError::server_error(format!(
+                "Unsupported share stream version {}",
+                header[4]
+            )));
+        }
+
+        self.header_read = true;
+        Ok(())
+    }
+
+    /// Read the next chunk into the buffer
+    fn read_chunk(&mut self) -> Result<(), ProtocolError> {
+        if !self.header_read {
+            self.read_header()?;
+        }
+
+        let mut length = [0u8; 4];
+        self.reader.read_exact(&mut length).map_err(stream_error)?;
+        let length = u32::from_le_bytes(length) as usize;
+        if length == 0 {
+            self.finished = true;
+            return Ok(());
+        }
+        if length > MAX_CHUNK_BYTES {
+            return Err(ProtocolError::server_error(format!(
+                "Share chunk of {} bytes exceeds limit",
+                length
+            )));
+        }
+
+        let mut bytes = vec![0u8; length];
+        self.reader.read_exact(&mut bytes).map_err(stream_error)?;
+        let points: Vec<DataPointShares> = serde_json::from_slice(&bytes)
+            .map_err(|e| ProtocolError::server_error(format!("Failed to decode share chunk: {}", e)))?;
+        self.buffered.extend(points);
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for ShareStreamReader<R> {
+    type Item = Result<DataPointShares, ProtocolError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffered.is_empty() && !self.finished {
+            if let Err(e) = self.read_chunk() {
+                self.finished = true;
+                return Some(Err(e));
+            }
+        }
+
+        self.buffered.pop_front().map(Ok)
+    }
+}
+
+/// Convert an I/O error into a protocol error
+fn stream_error(error: io::Error) -> ProtocolError {
+    ProtocolError::network_error(format!("Share stream I/O failed: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::share::DataShare;
+
+    fn point(id: usize) -> DataPointShares {
+        let mut shares = DataPointShares::new(format!("point-{}", id));
+        shares.add_feature_share(0, DataShare::feature(0, 0, id as u64, 97));
+        shares
+    }
+
+    #[test]
+    fn test_stream_roundtrip() {
+        let mut writer = ShareStreamWriter::with_chunk_size(Vec::new(), 3);
+        writer.write_all((0..10).map(point)).unwrap();
+        assert_eq!(writer.points_written(), 9);
+        let bytes = writer.finish().unwrap();
+
+        let points: Vec<DataPointShares> = ShareStreamReader::new(bytes.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(points.len(), 10);
+        assert_eq!(points[7].data_point_id, "point-7");
+    }
+
+    #[test]
+    fn test_truncated_stream_errors() {
+        let mut writer = ShareStreamWriter::with_chunk_size(Vec::new(), 2);
+        writer.write_all((0..4).map(point)).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let truncated = &bytes[..bytes.len() - 6];
+        let results: Vec<_> = ShareStreamReader::new(truncated).collect();
+        assert!(results.last().unwrap().is_err());
+    }
+}
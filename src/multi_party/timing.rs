@@ -0,0 +1,174 @@
+use crate::multi_party::protocol::ProtocolPhase;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Time spent in each phase of the multi-party protocol
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    /// Connection and key setup
+    pub setup: Duration,
+    /// Receiving data shares
+    pub sharing: Duration,
+    /// Oblivious shuffle rounds
+    pub shuffling: Duration,
+    /// Adding differential privacy noise
+    pub noise: Duration,
+    /// Reconstructing the shuffled data
+    pub reconstruction: Duration,
+    /// Answering queries
+    pub query: Duration,
+}
+
+impl PhaseTimings {
+    /// Time spent in a phase; always zero for `Completed`
+    pub fn get(&self, phase: ProtocolPhase) -> Duration {
+        match phase {
+            ProtocolPhase::Setup => self.setup,
+            ProtocolPhase::Sharing => self.sharing,
+            ProtocolPhase::Shuffling => self.shuffling,
+            ProtocolPhase::Noise => self.noise,
+            ProtocolPhase::Reconstruction => self.reconstruction,
+            ProtocolPhase::Query => self.query,
+            ProtocolPhase::Completed => Duration::ZERO,
+        }
+    }
+
+    /// Add time to a phase; time in `Completed` is not recorded
+    pub fn add(&mut self, phase: ProtocolPhase, elapsed: Duration) {
+        let slot = match phase {
+            ProtocolPhase::Setup => &mut self.setup,
+            ProtocolPhase::Sharing => &mut self.sharing,
+            ProtocolPhase::Shuffling => &mut self.shuffling,
+            ProtocolPhase::Noise => &mut self.noise,
+            ProtocolPhase::Reconstruction => &mut self.reconstruction,
+            ProtocolPhase::Query => &mut self.query,
+            ProtocolPhase::Completed => return,
+        };
+        *slot += elapsed;
+    }
+
+    /// Total time over all phases
+    pub fn total(&self) -> Duration {
+        self.setup + self.sharing + self.shuffling + self.noise + self.reconstruction + self.query
+    }
+
+    /// Add another server's timings, e.g. for the total work of all servers
+    pub fn merge(&mut self, other: &PhaseTimings) {
+        for phase in Self::PHASES {
+            self.add(phase, other.get(phase));
+        }
+    }
+
+    /// Per-phase maximum with another server's timings, i.e. the wall-clock time
+    /// of phases the servers run concurrently
+    pub fn max(&self, other: &PhaseTimings) -> PhaseTimings {
+        let mut timings = PhaseTimings::default();
+        for phase in Self::PHASES {
+            timings.add(phase, self.get(phase).max(other.get(phase)));
+        }
+        timings
+    }
+
+    /// Phases that are timed
+    const PHASES: [ProtocolPhase; 6] = [
+        ProtocolPhase::Setup,
+        ProtocolPhase::Sharing,
+        ProtocolPhase::Shuffling,
+        ProtocolPhase::Noise,
+        ProtocolPhase::Reconstruction,
+        ProtocolPhase::Query,
+    ];
+}
+
+/// Accumulates the `PhaseTimings` of a server from timing scopes
+///
+/// Cloning takes a snapshot, so a cloned server keeps its own timings.
+#[derive(Debug, Default)]
+pub struct PhaseRecorder(Arc<Mutex<PhaseTimings>>);
+
+impl PhaseRecorder {
+    /// Start timing a phase; the time is recorded when the scope is dropped
+    pub fn scope(&self, phase: ProtocolPhase) -> PhaseScope {
+        PhaseScope {
+            timings: self.0.clone(),
+            phase,
+            start: Instant::now(),
+        }
+    }
+
+    /// Timings recorded so far
+    pub fn snapshot(&self) -> PhaseTimings {
+        *self.0.lock().unwrap()
+    }
+
+    /// Take the timings recorded so far and start over
+    pub fn take(&self) -> PhaseTimings {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Clone for PhaseRecorder {
+    fn clone(&self) -> Self {
+        Self(Arc::new(Mutex::new(self.snapshot())))
+    }
+}
+
+/// Timing scope of one phase, created by `PhaseRecorder::scope`
+#[derive(Debug)]
+pub struct PhaseScope {
+    timings: Arc<Mutex<PhaseTimings>>,
+    phase: ProtocolPhase,
+    start: Instant,
+}
+
+impl Drop for PhaseScope {
+    fn drop(&mut self) {
+        if let Ok(mut timings) = self.timings.lock() {
+            timings.add(self.phase, self.start.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_recorder() {
+        let recorder = PhaseRecorder::default();
+        {
+            let _scope = recorder.scope(ProtocolPhase::Shuffling);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        drop(recorder.scope(ProtocolPhase::Completed));
+
+        let timings = recorder.snapshot();
+        assert!(timings.shuffling >= Duration::from_millis(5));
+        assert_eq!(timings.total(), timings.shuffling);
+
+        let copy = recorder.clone();
+        drop(copy.scope(ProtocolPhase::Query));
+        assert_eq!(recorder.snapshot().query, Duration::ZERO);
+
+        assert_eq!(recorder.take(), timings);
+        assert_eq!(recorder.snapshot(), PhaseTimings::default());
+    }
+
+    #[test]
+    fn test_merge_and_max() {
+        let mut first = PhaseTimings::default();
+        first.add(ProtocolPhase::Sharing, Duration::from_millis(3));
+        first.add(ProtocolPhase::Noise, Duration::from_millis(1));
+        let mut second = PhaseTimings::default();
+        second.add(ProtocolPhase::Sharing, Duration::from_millis(2));
+        second.add(ProtocolPhase::Noise, Duration::from_millis(4));
+
+        let wall_clock = first.max(&second);
+        assert_eq!(wall_clock.sharing, Duration::from_millis(3));
+        assert_eq!(wall_clock.noise, Duration::from_millis(4));
+
+        first.merge(&second);
+        assert_eq!(first.total(), Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::config::{self, ConfigError};
+use crate::multi_party::secure::{decode_key, IdentityConfig};
+use crate::multi_party::server::ServerRole;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// A server of a `Topology`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PeerConfig {
+    /// Server ID
+    pub id: usize,
+    /// Address the server listens on, as `host:port`
+    pub address: String,
+    /// Role of the server in the protocol
+    pub role: ServerRole,
+    /// X25519 public key of the server, as 64 hex digits
+    pub public_key: String,
+}
+
+/// Addresses, roles and keys of the servers of a deployment
+///
+/// Every server loads the same topology, e.g.
+///
+/// ```toml
+/// [[servers]]
+/// id = 0
+/// address = "10.0.0.1:7100"
+/// role = "First"
+/// public_key = "8f40c5adb68f25624ae5b214ea767a6ec94d829d3d7b5e1ad1ba6f3e2138285f"
+///
+/// [[servers]]
+/// id = 1
+/// address = "10.0.0.2:7100"
+/// role = "Second"
+/// public_key = "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Topology {
+    /// Every server, including this one
+    pub servers: Vec<PeerConfig>,
+}
+
+impl Topology {
+    /// Load a topology from a TOML or YAML file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        config::load(path.as_ref(), Self::validate)
+    }
+
+    /// Check that IDs and addresses are unique, and that addresses and keys are well formed
+    pub fn validate(&self) -> Result<(), String> {
+        if self.servers.is_empty() {
+            return Err("servers must not be empty".to_string());
+        }
+
+        let mut ids = HashSet::new();
+        let mut addresses = HashSet::new();
+        for server in &self.servers {
+            if !ids.insert(server.id) {
+                return Err(format!("server {} appears twice", server.id));
+            }
+            if !addresses.insert(&server.address) {
+                return Err(format!("servers.{}.address {} is used twice", server.id, server.address));
+            }
+            match server.address.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+                _ => return Err(format!("servers.{}.address must be host:port, got {}", server.id, server.address)),
+            }
+            decode_key(&server.public_key).map_err(|e| format!("servers.{}.public_key: {}", server.id, e))?;
+        }
+        Ok(())
+    }
+
+    /// The server with the given ID
+    pub fn server(&self, id: usize) -> Option<&PeerConfig> {
+        self.servers.iter().find(|server| server.id == id)
+    }
+
+    /// Every server except `local_id`, by ID
+    pub fn peers(&self, local_id: usize) -> Vec<&PeerConfig> {
+        let mut peers = self.servers.iter().filter(|server| server.id != local_id).collect::<Vec<_>>();
+        peers.sort_by_key(|server| server.id);
+        peers
+    }
+
+    /// Add the public key of every server to `identity`
+    ///
+    /// Fails if the identity already has a different key for a server.
+    pub fn add_keys(&self, identity: &mut IdentityConfig) -> Result<(), String> {
+        for server in &self.servers {
+            match identity.peers.get(&server.id) {
+                Some(key) if !key.trim().eq_ignore_ascii_case(server.public_key.trim()) => {
+                    return Err(format!(
+                        "identity.peers.{} does not match the public key in the topology",
+                        server.id
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    identity.peers.insert(server.id, server.public_key.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::secure::ServerKeys;
+    use rand::rngs::OsRng;
+
+    fn topology() -> Topology {
+        let servers = [ServerRole::First, ServerRole::Second, ServerRole::Third]
+            .into_iter()
+            .enumerate()
+            .map(|(id, role)| PeerConfig {
+                id,
+                address: format!("127.0.0.1:{}", 7100 + id),
+                role,
+                public_key: ServerKeys::generate(&mut OsRng).public_key_hex(),
+            })
+            .collect();
+        Topology { servers }
+    }
+
+    #[test]
+    fn test_topology_from_file() {
+        let topology = topology();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("topology.toml");
+        std::fs::write(&path, toml::to_string(&topology).unwrap()).unwrap();
+
+        let loaded = Topology::from_file(&path).unwrap();
+        assert_eq!(loaded.server(2).unwrap().role, ServerRole::Third);
+        assert_eq!(loaded.peers(1).iter().map(|peer| peer.id).collect::<Vec<_>>(), [0, 2]);
+
+        let mut identity = IdentityConfig {
+            private_key: ServerKeys::generate(&mut OsRng).private_key_hex(),
+            peers: Default::default(),
+        };
+        loaded.add_keys(&mut identity).unwrap();
+        assert_eq!(identity.peers.len(), 3);
+        identity.peers.insert(0, topology.servers[1].public_key.clone());
+        assert!(loaded.add_keys(&mut identity).is_err());
+    }
+
+    #[test]
+    fn test_invalid_topology() {
+        let mut duplicate = topology();
+        duplicate.servers[2].id = 0;
+        assert!(duplicate.validate().is_err());
+
+        let mut unreachable = topology();
+        unreachable.servers[1].address = "127.0.0.1".to_string();
+        assert!(unreachable.validate().is_err());
+
+        let mut bad_key = topology();
+        bad_key.servers[0].public_key = "00".to_string();
+        assert!(bad_key.validate().is_err());
+
+        assert!(Topology::default().validate().is_err());
+    }
+}
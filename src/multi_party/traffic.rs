@@ -0,0 +1,121 @@
+use crate::multi_party::codec::CodecKind;
+use crate::multi_party::communication::NetworkMessage;
+use crate::multi_party::protocol::ProtocolPhase;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Number of messages and the bytes they took on the wire
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MessageCount {
+    /// Messages
+    pub messages: u64,
+    /// Encoded bytes of the messages
+    pub bytes: u64,
+}
+
+impl MessageCount {
+    fn add(&mut self, other: MessageCount) {
+        self.messages += other.messages;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Messages of one direction of a channel, in total, by message type and by phase
+///
+/// Messages without a phase, such as acknowledgements and heartbeats, only
+/// count towards `total` and `by_type`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TrafficCounts {
+    /// Every message
+    pub total: MessageCount,
+    /// Messages by the name of their type, see `MessageType::name`
+    pub by_type: BTreeMap<&'static str, MessageCount>,
+    /// Messages by the phase they were sent in
+    pub by_phase: BTreeMap<ProtocolPhase, MessageCount>,
+}
+
+impl TrafficCounts {
+    /// Count a message
+    pub(crate) fn record(&mut self, message: Measured) {
+        let count = MessageCount {
+            messages: 1,
+            bytes: message.bytes as u64,
+        };
+        self.total.add(count);
+        self.by_type.entry(message.name).or_default().add(count);
+        if let Some(phase) = message.phase {
+            self.by_phase.entry(phase).or_default().add(count);
+        }
+    }
+
+    /// Add the counts of another channel
+    pub fn merge(&mut self, other: &TrafficCounts) {
+        self.total.add(other.total);
+        for (name, count) in &other.by_type {
+            self.by_type.entry(*name).or_default().add(*count);
+        }
+        for (phase, count) in &other.by_phase {
+            self.by_phase.entry(*phase).or_default().add(*count);
+        }
+    }
+
+    /// Bytes of the messages of type `name`
+    pub fn type_bytes(&self, name: &str) -> u64 {
+        self.by_type.get(name).map_or(0, |count| count.bytes)
+    }
+
+    /// Bytes of the messages sent in `phase`
+    pub fn phase_bytes(&self, phase: ProtocolPhase) -> u64 {
+        self.by_phase.get(&phase).map_or(0, |count| count.bytes)
+    }
+}
+
+/// Messages a channel sent and received
+///
+/// Sizes are those of the messages as sent, after signing and encryption,
+/// encoded with the codec of the network configuration but before framing
+/// and compression, which `CompressionStats` accounts for.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TrafficStats {
+    /// Messages sent, including acknowledgements and resends
+    pub sent: TrafficCounts,
+    /// Messages received, including duplicates the channel dropped
+    pub received: TrafficCounts,
+}
+
+impl TrafficStats {
+    /// Add the traffic of another channel
+    pub fn merge(&mut self, other: &TrafficStats) {
+        self.sent.merge(&other.sent);
+        self.received.merge(&other.received);
+    }
+
+    /// Bytes sent and received
+    pub fn total_bytes(&self) -> u64 {
+        self.sent.total.bytes + self.received.total.bytes
+    }
+}
+
+/// What is counted of a message: its type, its phase and its size
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Measured {
+    name: &'static str,
+    phase: Option<ProtocolPhase>,
+    bytes: usize,
+}
+
+/// Measure `message` as encoded by `codec`, with a size of 0 if it cannot be encoded
+pub(crate) fn measure(codec: CodecKind, message: &NetworkMessage) -> Measured {
+    let bytes = match codec.codec().encode(message) {
+        Ok(bytes) => bytes.len(),
+        Err(e) => {
+            log::debug!("Not counting the size of message {}: {}", message.sequence, e);
+            0
+        }
+    };
+    Measured {
+        name: message.message_type.name(),
+        phase: message.phase,
+        bytes,
+    }
+}
@@ -0,0 +1,226 @@
+use crate::multi_party::communication::NetworkMessage;
+use crate::multi_party::protocol::{ProtocolError, ProtocolPhase};
+use crate::multi_party::version::PROTOCOL_VERSION;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// First line of a transcript file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptHeader {
+    /// Server whose messages were recorded
+    pub server_id: usize,
+    /// Version of the wire protocol of the recording server
+    pub protocol_version: u16,
+    /// Wall clock time the recording started, in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+}
+
+/// Whether a recorded message was sent or received
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Sent by the recording server
+    Sent,
+    /// Received by the recording server
+    Received,
+}
+
+/// One message of a transcript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// Time since the first recorded message, in milliseconds of the runtime clock
+    pub at_ms: u64,
+    /// Whether the message was sent or received
+    pub direction: Direction,
+    /// Server the message was sent to or received from
+    pub peer: usize,
+    /// Phase the recording server was in
+    pub phase: ProtocolPhase,
+    /// The message, before encryption and authentication
+    pub message: NetworkMessage,
+}
+
+fn transcript_error(path: &Path, error: impl std::fmt::Display) -> ProtocolError {
+    ProtocolError::server_error(format!("Transcript {}: {}", path.display(), error))
+}
+
+/// Writes every message a `NetworkManager` sends and receives to a file
+///
+/// The file holds a `TranscriptHeader` and then one `TranscriptEntry` per
+/// line, as JSON. Each line is flushed as it is written, so the transcript of
+/// a server that crashed is complete up to the crash. Messages are recorded
+/// as they were before encryption, so the file must be protected like the
+/// shares themselves.
+#[derive(Debug)]
+pub struct TranscriptRecorder {
+    path: PathBuf,
+    state: Mutex<RecorderState>,
+}
+
+#[derive(Debug)]
+struct RecorderState {
+    writer: BufWriter<File>,
+    /// Runtime clock at the first recorded message
+    origin: Option<Duration>,
+    entries: usize,
+}
+
+impl TranscriptRecorder {
+    /// Record the messages of `server_id` to `path`, replacing any file there
+    pub fn create(path: impl Into<PathBuf>, server_id: usize) -> Result<Self, ProtocolError> {
+        let path = path.into();
+        let file = File::create(&path).map_err(|e| transcript_error(&path, e))?;
+        let header = TranscriptHeader {
+            server_id,
+            protocol_version: PROTOCOL_VERSION,
+            started_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64),
+        };
+        let mut writer = BufWriter::new(file);
+        write_line(&mut writer, &header).map_err(|e| transcript_error(&path, e))?;
+
+        Ok(Self {
+            path,
+            state: Mutex::new(RecorderState {
+                writer,
+                origin: None,
+                entries: 0,
+            }),
+        })
+    }
+
+    /// Path of the transcript file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of messages recorded so far
+    pub fn entries(&self) -> usize {
+        self.state.lock().unwrap().entries
+    }
+
+    /// Record `message`, sent to or received from `peer` at `now` on the runtime clock
+    pub(crate) fn record(
+        &self,
+        now: Duration,
+        direction: Direction,
+        peer: usize,
+        phase: ProtocolPhase,
+        message: &NetworkMessage,
+    ) -> Result<(), ProtocolError> {
+        let mut state = self.state.lock().unwrap();
+        let origin = *state.origin.get_or_insert(now);
+        let entry = TranscriptEntry {
+            at_ms: now.saturating_sub(origin).as_millis() as u64,
+            direction,
+            peer,
+            phase,
+            message: message.clone(),
+        };
+        write_line(&mut state.writer, &entry).map_err(|e| transcript_error(&self.path, e))?;
+        state.entries += 1;
+        Ok(())
+    }
+}
+
+/// Write `value` as a line of JSON and flush it
+fn write_line(writer: &mut BufWriter<File>, value: &impl Serialize) -> Result<(), std::io::Error> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+/// Transcript read back from a file written by `TranscriptRecorder`
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    /// Recording server and time
+    pub header: TranscriptHeader,
+    /// Messages in the order they were sent and received
+    pub entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    /// Load the transcript at `path`
+    ///
+    /// A truncated last line, as left by a server that crashed while writing
+    /// it, is skipped.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProtocolError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| transcript_error(path, e))?;
+        let mut lines = BufReader::new(file).lines().enumerate().peekable();
+
+        let header = match lines.next() {
+            Some((_, line)) => {
+                let line = line.map_err(|e| transcript_error(path, e))?;
+                serde_json::from_str(&line).map_err(|e| transcript_error(path, format!("line 1: {}", e)))?
+            }
+            None => return Err(transcript_error(path, "empty file")),
+        };
+
+        let mut entries = Vec::new();
+        while let Some((index, line)) = lines.next() {
+            let line = line.map_err(|e| transcript_error(path, e))?;
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) if lines.peek().is_none() && e.is_eof() => {
+                    log::warn!("Skipping truncated last line of transcript {}", path.display());
+                }
+                Err(e) => return Err(transcript_error(path, format!("line {}: {}", index + 1, e))),
+            }
+        }
+
+        Ok(Self { header, entries })
+    }
+
+    /// Messages the recording server received, with the server they came from
+    pub fn received(&self) -> impl Iterator<Item = &TranscriptEntry> {
+        self.entries.iter().filter(|entry| entry.direction == Direction::Received)
+    }
+
+    /// Messages the recording server sent, with the server they went to
+    pub fn sent(&self) -> impl Iterator<Item = &TranscriptEntry> {
+        self.entries.iter().filter(|entry| entry.direction == Direction::Sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcript_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("transcript.jsonl");
+        let recorder = TranscriptRecorder::create(&path, 1).unwrap();
+
+        let sent = NetworkMessage::shuffle(1, 2, 0, vec![1, 0]);
+        let received = NetworkMessage::shuffle(0, 1, 4, vec![0, 1]);
+        recorder.record(Duration::from_millis(500), Direction::Sent, 2, ProtocolPhase::Sharing, &sent).unwrap();
+        recorder.record(Duration::from_millis(520), Direction::Received, 0, ProtocolPhase::Shuffling, &received).unwrap();
+        assert_eq!(recorder.entries(), 2);
+
+        // A crash in the middle of a line loses only that line
+        File::options().append(true).open(&path).unwrap().write_all(b"{\"at_ms\":3").unwrap();
+
+        let transcript = Transcript::load(&path).unwrap();
+        assert_eq!(transcript.header.server_id, 1);
+        assert_eq!(transcript.header.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(transcript.entries.len(), 2);
+        assert_eq!(transcript.entries[1].at_ms, 20);
+        assert_eq!(transcript.entries[1].phase, ProtocolPhase::Shuffling);
+
+        let sent = transcript.sent().collect::<Vec<_>>();
+        assert_eq!((sent[0].at_ms, sent[0].peer, sent[0].message.target_id), (0, 2, 2));
+        let received = transcript.received().collect::<Vec<_>>();
+        assert_eq!((received[0].peer, received[0].message.sequence), (0, 4));
+
+        // Anything but the last line must parse
+        std::fs::write(&path, "{\"server_id\":1,\"protocol_version\":1,\"started_at_ms\":0}\nnot json\n{}\n").unwrap();
+        assert!(Transcript::load(&path).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use crate::multi_party::codec::{Compression, CompressionStats, FrameCounters, Framing};
+use crate::multi_party::communication::{Connector, Link, MessagePayload, MessageType, NetworkMessage};
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::runtime::{self, Receiver, Sender};
+use crate::multi_party::topology::Topology;
+use futures::future::BoxFuture;
+use futures::lock::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn link_error(target_id: usize, error: impl std::fmt::Display) -> ProtocolError {
+    ProtocolError::network_error(format!("Link to server {}: {}", target_id, error))
+}
+
+/// Links between the servers of a `Topology` over TCP
+///
+/// Every server listens on its address in the topology. Of two servers, the one
+/// with the higher ID dials the other and starts the connection with an `Init`
+/// message from its ID, so a link to a lower ID is dialed and a link to a
+/// higher ID is accepted. Messages are framed with the configured codec, and
+/// up to the queue capacity are buffered in each direction of a link.
+///
+/// The `Init` message of the dialing server is framed with its compression,
+/// and the accepting server answers with an `Init` message framed with the
+/// compression of the link: the same one if both servers use it, no
+/// compression otherwise.
+///
+/// The transport runs its connections on tokio.
+pub struct TcpTransport {
+    local_id: usize,
+    topology: Topology,
+    settings: LinkSettings,
+    /// How long `connect` waits for a server with a higher ID to dial in
+    accept_timeout: Duration,
+    /// Connections accepted from each server with a higher ID
+    accepted: HashMap<usize, Mutex<Receiver<Link>>>,
+}
+
+impl TcpTransport {
+    /// Listen on the address of `local_id` in the topology
+    pub async fn bind(
+        local_id: usize,
+        topology: Topology,
+        framing: Framing,
+        capacity: usize,
+        accept_timeout: Duration,
+    ) -> Result<Self, ProtocolError> {
+        let address = &topology
+            .server(local_id)
+            .ok_or_else(|| ProtocolError::InvalidConfiguration(format!("Server {} is not in the topology", local_id)))?
+            .address;
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| ProtocolError::network_error(format!("Failed to listen on {}: {}", address, e)))?;
+
+        let settings = LinkSettings {
+            framing,
+            capacity,
+            counters: Arc::new(FrameCounters::default()),
+        };
+        let mut senders = HashMap::new();
+        let mut accepted = HashMap::new();
+        for peer in topology.peers(local_id).into_iter().filter(|peer| peer.id > local_id) {
+            let (sender, receiver) = runtime::channel(1);
+            senders.insert(peer.id, sender);
+            accepted.insert(peer.id, Mutex::new(receiver));
+        }
+        tokio::spawn(accept_loop(listener, local_id, settings.clone(), senders));
+
+        Ok(Self {
+            local_id,
+            topology,
+            settings,
+            accept_timeout,
+            accepted,
+        })
+    }
+
+    /// Counters of the frames sent and received on every link
+    pub fn counters(&self) -> Arc<FrameCounters> {
+        self.settings.counters.clone()
+    }
+
+    /// Frames sent and received on every link, before and after compression
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.settings.counters.stats()
+    }
+
+    /// Dial a server with a lower ID
+    async fn dial(&self, target_id: usize) -> Result<Link, ProtocolError> {
+        let peer = self
+            .topology
+            .server(target_id)
+            .ok_or_else(|| link_error(target_id, "not in the topology"))?;
+        let mut stream = TcpStream::connect(&peer.address)
+            .await
+            .map_err(|e| link_error(target_id, e))?;
+        stream.set_nodelay(true).map_err(|e| link_error(target_id, e))?;
+
+        let framing = self.settings.framing;
+        let hello = framing.encode_hello(&NetworkMessage::init(self.local_id, target_id, 0))?;
+        stream.write_all(&hello).await.map_err(|e| link_error(target_id, e))?;
+
+        let mut buffer = Vec::new();
+        let (reply, compression) = tokio::time::timeout(self.accept_timeout, read_hello(&mut stream, framing, &mut buffer))
+            .await
+            .map_err(|_| link_error(target_id, format!("no answer to the hello within {:?}", self.accept_timeout)))??;
+        check_hello(&reply, self.local_id)?;
+        if reply.source_id != target_id {
+            return Err(link_error(target_id, format!("answered by server {}", reply.source_id)));
+        }
+        if compression != Compression::None && compression != framing.compression {
+            return Err(link_error(target_id, format!("answered with {:?} compression", compression)));
+        }
+
+        let settings = self.settings.with_compression(compression);
+        Ok(spawn_link(stream, target_id, settings, buffer))
+    }
+
+    /// Wait for a server with a higher ID to dial in
+    async fn accept(&self, target_id: usize) -> Result<Link, ProtocolError> {
+        let accepted = self
+            .accepted
+            .get(&target_id)
+            .ok_or_else(|| link_error(target_id, "not in the topology"))?;
+        let mut accepted = accepted.lock().await;
+        match tokio::time::timeout(self.accept_timeout, accepted.recv()).await {
+            Ok(Some(link)) => Ok(link),
+            Ok(None) => Err(link_error(target_id, "the listener stopped")),
+            Err(_) => Err(link_error(target_id, format!("not dialed within {:?}", self.accept_timeout))),
+        }
+    }
+}
+
+impl Connector for TcpTransport {
+    fn connect(&self, target_id: usize) -> BoxFuture<'_, Result<Link, ProtocolError>> {
+        Box::pin(async move {
+            if target_id < self.local_id {
+                self.dial(target_id).await
+            } else {
+                self.accept(target_id).await
+            }
+        })
+    }
+}
+
+/// Accept connections and hand each to the waiting `TcpTransport::accept` once it said hello
+async fn accept_loop(listener: TcpListener, local_id: usize, settings: LinkSettings, senders: HashMap<usize, Sender<Link>>) {
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                log::warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+
+        let senders = senders.clone();
+        let settings = settings.clone();
+        tokio::spawn(async move {
+            match hello(stream, local_id, settings, &senders).await {
+                Ok(source_id) => log::debug!("Accepted a connection from server {} at {}", source_id, address),
+                Err(e) => log::warn!("Rejected a connection from {}: {}", address, e),
+            }
+        });
+    }
+}
+
+/// Read the `Init` message that starts a dialed connection, answer it and hand over the link
+async fn hello(
+    mut stream: TcpStream,
+    local_id: usize,
+    settings: LinkSettings,
+    senders: &HashMap<usize, Sender<Link>>,
+) -> Result<usize, ProtocolError> {
+    let mut buffer = Vec::new();
+    let (message, offered) = read_hello(&mut stream, settings.framing, &mut buffer).await?;
+    check_hello(&message, local_id)?;
+    let source_id = message.source_id;
+    let sender = senders
+        .get(&source_id)
+        .ok_or_else(|| ProtocolError::Security(format!("Server {} may not dial server {}", source_id, local_id)))?;
+
+    let compression = if offered == settings.framing.compression {
+        offered
+    } else {
+        Compression::None
+    };
+    let settings = settings.with_compression(compression);
+    let reply = settings.framing.encode_hello(&NetworkMessage::init(local_id, source_id, 0))?;
+    stream.write_all(&reply).await.map_err(|e| link_error(source_id, e))?;
+
+    stream.set_nodelay(true).map_err(|e| link_error(source_id, e))?;
+    let link = spawn_link(stream, source_id, settings, buffer);
+    sender.send(link).await.map_err(|e| link_error(source_id, e))?;
+    Ok(source_id)
+}
+
+/// Read a hello frame and the compression it is framed with
+///
+/// Bytes read past the frame stay in `buffer`.
+async fn read_hello(
+    stream: &mut TcpStream,
+    framing: Framing,
+    buffer: &mut Vec<u8>,
+) -> Result<(NetworkMessage, Compression), ProtocolError> {
+    loop {
+        if let Some(hello) = framing.decode_hello(buffer)? {
+            return Ok(hello);
+        }
+        let read = stream
+            .read_buf(buffer)
+            .await
+            .map_err(|e| ProtocolError::network_error(e.to_string()))?;
+        if read == 0 {
+            return Err(ProtocolError::network_error("Connection closed before the hello"));
+        }
+    }
+}
+
+/// Check that a message is a hello for `local_id`
+fn check_hello(message: &NetworkMessage, local_id: usize) -> Result<(), ProtocolError> {
+    let is_hello = matches!((&message.message_type, &message.payload), (MessageType::Init, MessagePayload::Empty));
+    if !is_hello || message.target_id != local_id {
+        return Err(ProtocolError::MalformedMessage(format!(
+            "Expected a hello for server {}, got {:?} for server {}",
+            local_id, message.message_type, message.target_id
+        )));
+    }
+    Ok(())
+}
+
+/// How links frame and buffer their messages
+#[derive(Clone)]
+struct LinkSettings {
+    framing: Framing,
+    /// Messages buffered in each direction of a link
+    capacity: usize,
+    /// Shared by all links of the transport
+    counters: Arc<FrameCounters>,
+}
+
+impl LinkSettings {
+    fn with_compression(&self, compression: Compression) -> Self {
+        Self {
+            framing: self.framing.with_compression(compression),
+            ..self.clone()
+        }
+    }
+}
+
+/// Pump messages between a connection and a `Link` until either side closes
+///
+/// `buffer` holds bytes already read from the connection. The depth of the
+/// outgoing queue grows once the target stops reading, which is what pauses
+/// a channel under flow control.
+fn spawn_link(stream: TcpStream, target_id: usize, settings: LinkSettings, mut buffer: Vec<u8>) -> Link {
+    let LinkSettings {
+        framing,
+        capacity,
+        counters,
+    } = settings;
+    let (mut reader, mut writer) = stream.into_split();
+    let received = counters.clone();
+    let (sender, mut outgoing) = runtime::channel::<NetworkMessage>(capacity);
+    let (incoming, receiver) = runtime::channel(capacity);
+
+    tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            let written = match framing.encode_counted(&message, &counters) {
+                Ok(frame) => writer.write_all(&frame).await.map_err(|e| link_error(target_id, e)),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                log::warn!("Closing the link to server {}: {}", target_id, e);
+                break;
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        loop {
+            match framing.decode_counted(&mut buffer, &received) {
+                Ok(Some(message)) => {
+                    if incoming.send(message).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log::warn!("Closing the link to server {}: {}", target_id, e);
+                    break;
+                }
+            }
+            match reader.read_buf(&mut buffer).await {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!("Closing the link to server {}: {}", target_id, e);
+                    break;
+                }
+            }
+        }
+    });
+
+    (sender, receiver)
+}
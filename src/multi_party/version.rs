@@ -0,0 +1,110 @@
+use crate::multi_party::protocol::ProtocolError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Newest version of the wire protocol this server speaks
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Oldest version of the wire protocol this server still speaks
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Versions of the wire protocol a server is willing to speak
+///
+/// Two servers speak the highest version in both of their ranges. During a
+/// rolling upgrade, upgraded servers keep the old version in their range until
+/// every server is upgraded, and may then raise `min`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRange {
+    /// Oldest version
+    pub min: u16,
+    /// Newest version
+    pub max: u16,
+}
+
+impl Default for VersionRange {
+    fn default() -> Self {
+        Self::supported()
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{} to {}", self.min, self.max)
+        }
+    }
+}
+
+impl VersionRange {
+    /// Every version this server speaks
+    pub fn supported() -> Self {
+        Self {
+            min: MIN_PROTOCOL_VERSION,
+            max: PROTOCOL_VERSION,
+        }
+    }
+
+    /// Check that the range is not empty and this server speaks all of it
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min > self.max {
+            return Err(format!(
+                "min_protocol_version {} exceeds max_protocol_version {}",
+                self.min, self.max
+            ));
+        }
+        let supported = Self::supported();
+        if self.min < supported.min || self.max > supported.max {
+            return Err(format!("Protocol versions {} are not all in the supported {}", self, supported));
+        }
+        Ok(())
+    }
+
+    /// Check if `version` is in the range
+    pub fn contains(&self, version: u16) -> bool {
+        (self.min..=self.max).contains(&version)
+    }
+
+    /// Highest version in both this range and the range of server `target_id`
+    pub fn negotiate(&self, target_id: usize, peer: VersionRange) -> Result<u16, ProtocolError> {
+        let version = self.max.min(peer.max);
+        if version < self.min.max(peer.min) {
+            return Err(ProtocolError::IncompatibleVersion {
+                target_id,
+                local: *self,
+                peer,
+            });
+        }
+        Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let old = VersionRange { min: 1, max: 1 };
+        let upgraded = VersionRange { min: 1, max: 3 };
+        let new = VersionRange { min: 3, max: 4 };
+
+        assert_eq!(upgraded.negotiate(1, old).unwrap(), 1);
+        assert_eq!(old.negotiate(1, upgraded).unwrap(), 1);
+        assert_eq!(upgraded.negotiate(1, new).unwrap(), 3);
+        assert_eq!(new.negotiate(1, upgraded).unwrap(), 3);
+
+        let error = new.negotiate(2, old).unwrap_err();
+        assert!(matches!(error, ProtocolError::IncompatibleVersion { target_id: 2, .. }));
+        assert_eq!(
+            error.to_string(),
+            "Server 2 speaks protocol version 1, incompatible with 3 to 4 on this server"
+        );
+        assert!(old.negotiate(2, new).is_err());
+
+        assert!(VersionRange::supported().validate().is_ok());
+        assert!(VersionRange { min: 2, max: 1 }.validate().is_err());
+        assert!(VersionRange { min: 1, max: PROTOCOL_VERSION + 1 }.validate().is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use crate::field::PrimeField;
+use crate::multi_party::crypto::{random_scalar, SHARE_MODULUS};
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::share::{DataShare, ShareType};
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use num_bigint::BigUint;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// Metadata key holding the blinding value of a share dealt by `PedersenVss`
+pub const BLINDING_KEY: &str = "vss_blinding";
+
+/// Metadata key holding the quotient `k` of a share `s` dealt by `PedersenVss`,
+/// whose sharing polynomial takes the integer value `s + k * SHARE_MODULUS`
+pub const QUOTIENT_KEY: &str = "vss_quotient";
+
+/// Bits of statistical hiding of the integer sharing polynomials
+const STATISTICAL_SECURITY: u64 = 64;
+
+/// Integer values of the sharing polynomials, times `2^threshold`, must stay below the 2^252 order of Ristretto
+const GROUP_ORDER_BITS: u64 = 252;
+
+/// Second generator `H` of the Ristretto group, hashed from a fixed label
+///
+/// Nobody knows its discrete logarithm to the base point, so commitments
+/// `a * G + b * H` bind the dealer to `a` unless it breaks discrete
+/// logarithms in a group of order about 2^252.
+fn blinding_generator() -> RistrettoPoint {
+    static GENERATOR: OnceLock<RistrettoPoint> = OnceLock::new();
+    *GENERATOR.get_or_init(|| {
+        let mut bytes = [0u8; 64];
+        for (half, chunk) in bytes.chunks_mut(32).enumerate() {
+            let mut hasher = Sha256::new();
+            hasher.update(b"doppio vss generator h");
+            hasher.update([half as u8]);
+            chunk.copy_from_slice(&hasher.finalize());
+        }
+        RistrettoPoint::from_uniform_bytes(&bytes)
+    })
+}
+
+/// Pedersen commitment `value * G + blinding * H`
+fn commit(value: Scalar, blinding: Scalar) -> RistrettoPoint {
+    RistrettoPoint::mul_base(&value) + blinding * blinding_generator()
+}
+
+/// Integer below 2^252 as a scalar
+fn to_scalar(value: &BigUint) -> Scalar {
+    let mut bytes = [0u8; 32];
+    let digits = value.to_bytes_le();
+    bytes[..digits.len()].copy_from_slice(&digits);
+    Scalar::from_bytes_mod_order(bytes)
+}
+
+/// Uniform integer below `2^bits`
+fn random_integer<R: RngCore + ?Sized>(rng: &mut R, bits: u64) -> BigUint {
+    let mut bytes = vec![0u8; bits.div_ceil(8) as usize];
+    rng.fill_bytes(&mut bytes);
+    if !bits.is_multiple_of(8) {
+        if let Some(last) = bytes.last_mut() {
+            *last &= (1u8 << (bits % 8)) - 1;
+        }
+    }
+    BigUint::from_bytes_le(&bytes)
+}
+
+/// Number of bits of `value`
+fn bit_length(value: u64) -> u64 {
+    (u64::BITS - value.leading_zeros()) as u64
+}
+
+/// Public commitments of a dealer to the sharing of one secret
+///
+/// Holds `a_j * G + b_j * H` in the Ristretto group for each coefficient
+/// `a_j` of the integer sharing polynomial and `b_j` of the blinding
+/// polynomial, constant term first. They reveal nothing about the secret,
+/// and a dealer cannot open them to shares of two different secrets without
+/// computing discrete logarithms.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareCommitment {
+    /// Feature index of the shared secret
+    pub feature_index: usize,
+    /// Commitments to the coefficients, as compressed Ristretto points
+    pub coefficients: Vec<[u8; 32]>,
+}
+
+/// Pedersen verifiable secret sharing of `DataShare`s
+///
+/// The dealer, a client or the auxiliary server, shares each secret `s` with
+/// a polynomial `f` over the integers with `f(0) = s + m * SHARE_MODULUS`,
+/// and a random blinding value with a second polynomial over the scalars,
+/// and publishes a `ShareCommitment` to both. Server `i` gets `f(i + 1)`
+/// reduced modulo `SHARE_MODULUS`, an ordinary Shamir share of `s`, along
+/// with the quotient and blinding value in the `QUOTIENT_KEY` and
+/// `BLINDING_KEY` metadata. It checks them against the commitment, so a
+/// dealer that hands out shares of no single polynomial is caught.
+/// Receivers must check against the same commitments, e.g. ones sent with
+/// `NetworkManager::reliable_broadcast`.
+///
+/// Commitments live in the Ristretto group, which has prime order about
+/// 2^252, so every decompressed point is in the group. Receivers also check
+/// that `f(i + 1)` is below the bound honest coefficients give: values that
+/// small cannot wrap around the group order, so shares that pass lie on one
+/// polynomial modulo `SHARE_MODULUS`. The coefficients are
+/// `STATISTICAL_SECURITY` bits wider than hiding `s` from `threshold - 1`
+/// servers needs, which limits the threshold and number of servers.
+#[derive(Debug, Clone)]
+pub struct PedersenVss {
+    /// Minimum shares needed to reconstruct
+    pub threshold: usize,
+    /// Number of shares
+    pub num_shares: usize,
+    /// Bits of the random coefficients of the sharing polynomial
+    coefficient_bits: u64,
+}
+
+impl PedersenVss {
+    /// Create a new scheme
+    pub fn new(threshold: usize, num_shares: usize) -> Result<Self, ProtocolError> {
+        if threshold < 2 || threshold > num_shares {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Threshold {} must be between 2 and the number of shares {}",
+                threshold, num_shares
+            )));
+        }
+
+        // Shifting the coefficients by up to 2q(n + 1)^(t - 1) maps any secret
+        // to any other without changing t - 1 shares
+        let (t, n) = (threshold as u64, num_shares as u64);
+        let coefficient_bits = STATISTICAL_SECURITY + bit_length(SHARE_MODULUS) + 1 + (t - 1) * bit_length(n + 1);
+        let value_bits = coefficient_bits + bit_length(t) + (t - 1) * bit_length(n);
+        if t + value_bits > GROUP_ORDER_BITS {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Threshold {} among {} shares is too large for verifiable sharing",
+                threshold, num_shares
+            )));
+        }
+
+        Ok(Self {
+            threshold,
+            num_shares,
+            coefficient_bits,
+        })
+    }
+
+    /// Modulus of the shares
+    pub fn modulus(&self) -> u64 {
+        SHARE_MODULUS
+    }
+
+    /// Share `secret` as feature `feature_index`, returning a share per server and the commitment
+    pub fn share_secret(
+        &self,
+        secret: u64,
+        feature_index: usize,
+    ) -> Result<(Vec<DataShare>, ShareCommitment), ProtocolError> {
+        let secret = PrimeField::new(SHARE_MODULUS)?.check(secret)?;
+        let modulus = BigUint::from(SHARE_MODULUS);
+        let mut rng = rand::thread_rng();
+
+        let offset = random_integer(&mut rng, self.coefficient_bits - bit_length(SHARE_MODULUS) - 1);
+        let coefficients: Vec<BigUint> = std::iter::once(BigUint::from(secret) + offset * &modulus)
+            .chain((1..self.threshold).map(|_| random_integer(&mut rng, self.coefficient_bits)))
+            .collect();
+        let blinding_coefficients: Vec<Scalar> = (0..self.threshold).map(|_| random_scalar(&mut rng)).collect();
+
+        let commitment = ShareCommitment {
+            feature_index,
+            coefficients: coefficients
+                .iter()
+                .zip(&blinding_coefficients)
+                .map(|(a, &b)| commit(to_scalar(a), b).compress().to_bytes())
+                .collect(),
+        };
+        let shares = (0..self.num_shares)
+            .map(|server_id| {
+                let x = server_id as u64 + 1;
+                let value = coefficients.iter().rev().fold(BigUint::ZERO, |acc, coefficient| acc * x + coefficient);
+                let blinding = blinding_coefficients
+                    .iter()
+                    .rev()
+                    .fold(Scalar::ZERO, |acc, coefficient| acc * Scalar::from(x) + coefficient);
+
+                let remainder = (&value % &modulus).iter_u64_digits().next().unwrap_or(0);
+                let mut share = DataShare::new(server_id, feature_index, ShareType::Feature, remainder, SHARE_MODULUS);
+                share.add_metadata(QUOTIENT_KEY, (value / &modulus).to_string());
+                share.add_metadata(BLINDING_KEY, BigUint::from_bytes_le(blinding.as_bytes()).to_string());
+                share
+            })
+            .collect();
+
+        Ok((shares, commitment))
+    }
+
+    /// Share every feature, returning the shares of all servers and a commitment per feature
+    pub fn share_data(&self, features: &[u64]) -> Result<(Vec<DataShare>, Vec<ShareCommitment>), ProtocolError> {
+        let mut shares = Vec::with_capacity(features.len() * self.num_shares);
+        let mut commitments = Vec::with_capacity(features.len());
+        for (feature_index, &feature) in features.iter().enumerate() {
+            let (feature_shares, commitment) = self.share_secret(feature, feature_index)?;
+            shares.extend(feature_shares);
+            commitments.push(commitment);
+        }
+
+        Ok((shares, commitments))
+    }
+
+    /// Check `share` against the dealer's `commitment`
+    ///
+    /// Fails with `InvalidShare` if the share is not the evaluation of the
+    /// committed polynomials at the point of its server, and with
+    /// `MalformedMessage` if the share or commitment cannot be checked.
+    pub fn verify(&self, share: &DataShare, commitment: &ShareCommitment) -> Result<(), ProtocolError> {
+        if share.feature_index != commitment.feature_index {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "Share of feature {} checked against the commitment to feature {}",
+                share.feature_index, commitment.feature_index
+            )));
+        }
+        if commitment.coefficients.len() != self.threshold {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "Commitment to feature {} has {} coefficients instead of {}",
+                commitment.feature_index,
+                commitment.coefficients.len(),
+                self.threshold
+            )));
+        }
+        let metadata = |key: &str| {
+            share.get_metadata(key).and_then(|value| value.parse::<BigUint>().ok()).ok_or_else(|| {
+                ProtocolError::MalformedMessage(format!(
+                    "Share of feature {} for server {} has no {}",
+                    share.feature_index, share.server_id, key
+                ))
+            })
+        };
+        let (quotient, blinding) = (metadata(QUOTIENT_KEY)?, metadata(BLINDING_KEY)?);
+
+        let invalid = || ProtocolError::InvalidShare {
+            server_id: share.server_id,
+            feature_index: share.feature_index,
+        };
+        if share.server_id >= self.num_shares || share.modulus != SHARE_MODULUS || share.value >= SHARE_MODULUS {
+            return Err(invalid());
+        }
+        let blinding = match blinding.to_bytes_le() {
+            digits if digits.len() <= 32 => {
+                let mut bytes = [0u8; 32];
+                bytes[..digits.len()].copy_from_slice(&digits);
+                Option::<Scalar>::from(Scalar::from_canonical_bytes(bytes)).ok_or_else(invalid)?
+            }
+            _ => return Err(invalid()),
+        };
+
+        // Honest coefficients keep f(x) below 2^coefficient_bits times the sum of the powers of x
+        let x = share.server_id as u64 + 1;
+        let value = BigUint::from(share.value) + quotient * SHARE_MODULUS;
+        let powers = (0..self.threshold as u32).fold(BigUint::ZERO, |sum, j| sum + BigUint::from(x).pow(j));
+        if value >= powers << self.coefficient_bits {
+            return Err(invalid());
+        }
+
+        // f(x) * G + r * H must equal the sum of x^j * C_j
+        let mut power = Scalar::ONE;
+        let mut expected = RistrettoPoint::default();
+        for coefficient in &commitment.coefficients {
+            let point = CompressedRistretto(*coefficient).decompress().ok_or_else(|| {
+                ProtocolError::MalformedMessage(format!(
+                    "Commitment to feature {} is not a Ristretto point",
+                    commitment.feature_index
+                ))
+            })?;
+            expected += power * point;
+            power *= Scalar::from(x);
+        }
+
+        if commit(to_scalar(&value), blinding) == expected {
+            Ok(())
+        } else {
+            Err(invalid())
+        }
+    }
+
+    /// Check every share against the commitment to its feature
+    pub fn verify_all(&self, shares: &[DataShare], commitments: &[ShareCommitment]) -> Result<(), ProtocolError> {
+        for share in shares {
+            let commitment = commitments
+                .iter()
+                .find(|commitment| commitment.feature_index == share.feature_index)
+                .ok_or_else(|| {
+                    ProtocolError::MalformedMessage(format!("No commitment to feature {}", share.feature_index))
+                })?;
+            self.verify(share, commitment)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::crypto::ShamirSecretSharing;
+    use crate::multi_party::crypto::SecretShare;
+
+    #[test]
+    fn test_pedersen_vss() {
+        let vss = PedersenVss::new(2, 3).unwrap();
+        let (shares, commitments) = vss.share_data(&[42, 7]).unwrap();
+        assert_eq!(shares.len(), 6);
+        vss.verify_all(&shares, &commitments).unwrap();
+
+        // The shares are ordinary Shamir shares of the features
+        let shamir = ShamirSecretSharing::new(2, 3, vss.modulus()).unwrap();
+        let first: Vec<SecretShare> = shares
+            .iter()
+            .filter(|share| share.feature_index == 0 && share.server_id != 1)
+            .map(|share| SecretShare::new(share.server_id, share.value, 0, share.modulus))
+            .collect();
+        assert_eq!(shamir.reconstruct_secret(&first).unwrap(), 42);
+
+        // A dealer that hands one server an inconsistent share is caught
+        let mut altered = shares[1].clone();
+        altered.value = (altered.value + 1) % vss.modulus();
+        let err = vss.verify(&altered, &commitments[0]).unwrap_err();
+        assert!(matches!(err, ProtocolError::InvalidShare { server_id: 1, feature_index: 0 }));
+        assert!(err.is_cheating());
+
+        let mut moved = shares[1].clone();
+        moved.server_id = 2;
+        assert!(vss.verify(&moved, &commitments[0]).is_err());
+
+        let mut unblinded = shares[0].clone();
+        unblinded.metadata.clear();
+        assert!(matches!(vss.verify(&unblinded, &commitments[0]), Err(ProtocolError::MalformedMessage(_))));
+        assert!(vss.verify(&shares[0], &commitments[1]).is_err());
+    }
+
+    #[test]
+    fn test_integer_sharing_checks() {
+        let vss = PedersenVss::new(3, 5).unwrap();
+        let (shares, commitments) = vss.share_data(&[SHARE_MODULUS - 1]).unwrap();
+        vss.verify_all(&shares, &commitments).unwrap();
+
+        // Moving a share by the modulus keeps its residue but not its commitment
+        let mut shifted = shares[2].clone();
+        let quotient: BigUint = shifted.get_metadata(QUOTIENT_KEY).unwrap().parse().unwrap();
+        shifted.add_metadata(QUOTIENT_KEY, (quotient + 1u32).to_string());
+        assert!(matches!(vss.verify(&shifted, &commitments[0]), Err(ProtocolError::InvalidShare { .. })));
+
+        // Integer values past the bound could wrap around the group order
+        let mut oversized = shares[2].clone();
+        oversized.add_metadata(QUOTIENT_KEY, (BigUint::from(1u32) << 250usize).to_string());
+        assert!(matches!(vss.verify(&oversized, &commitments[0]), Err(ProtocolError::InvalidShare { .. })));
+
+        let mut unreduced = shares[2].clone();
+        unreduced.add_metadata(BLINDING_KEY, (BigUint::from(1u32) << 253usize).to_string());
+        assert!(matches!(vss.verify(&unreduced, &commitments[0]), Err(ProtocolError::InvalidShare { .. })));
+
+        // Commitments must be encodings of Ristretto points
+        let mut malformed = commitments[0].clone();
+        malformed.coefficients[1] = [0xff; 32];
+        assert!(matches!(vss.verify(&shares[0], &malformed), Err(ProtocolError::MalformedMessage(_))));
+
+        assert!(PedersenVss::new(4, 3).is_err());
+        assert!(PedersenVss::new(2, 100).is_ok());
+        assert!(PedersenVss::new(10, 100).is_err());
+    }
+}
@@ -0,0 +1,491 @@
+use crate::multi_party::beaver::{BeaverTriple, MultiplicationGate, Ring};
+use crate::multi_party::crypto::{AdditiveSecretSharing, ShamirSecretSharing, SharingScheme, SHARE_MODULUS, WRAPPING_MODULUS};
+use crate::multi_party::protocol::ProtocolError;
+use crate::multi_party::share::DataShare;
+
+/// Waksman permutation network on `size` wires
+///
+/// The network is a Beneš network of 2-by-2 switches on the next power of
+/// two wires, built recursively from a column of input switches, an upper and
+/// a lower half-size network and a column of output switches. As in
+/// Waksman's construction, the first output switch of every subnetwork is
+/// always straight and left out, which leaves `n log n - n + 1` switches for
+/// `n` wires. Any permutation is routed by setting each switch to straight or
+/// swapped, and the switches of one layer act on distinct wires.
+#[derive(Debug, Clone)]
+pub struct WaksmanNetwork {
+    size: usize,
+    width: usize,
+    layers: Vec<Vec<(usize, usize)>>,
+}
+
+impl WaksmanNetwork {
+    /// Create the network permuting `size` wires
+    ///
+    /// Wires beyond `size` pad the network to a power of two and are routed
+    /// to themselves.
+    pub fn new(size: usize) -> Self {
+        let width = if size < 2 { size } else { size.next_power_of_two() };
+        let identity: Vec<usize> = (0..width).collect();
+        let layers = Self::switches(&identity)
+            .into_iter()
+            .map(|layer| layer.into_iter().map(|(upper, lower, _)| (upper, lower)).collect())
+            .collect();
+
+        Self { size, width, layers }
+    }
+
+    /// Number of wires permuted
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of wires including the padding
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Pairs of wires of the switches of each layer, in the order of their control bits
+    pub fn layers(&self) -> &[Vec<(usize, usize)>] {
+        &self.layers
+    }
+
+    /// Total number of switches
+    pub fn switch_count(&self) -> usize {
+        self.layers.iter().map(Vec::len).sum()
+    }
+
+    /// Control bits that move the item on wire `i` to wire `permutation[i]`, layer by layer
+    ///
+    /// A bit is set for a swapped switch.
+    pub fn route(&self, permutation: &[usize]) -> Result<Vec<bool>, ProtocolError> {
+        let mut seen = vec![false; self.size];
+        if permutation.len() != self.size
+            || !permutation.iter().all(|&i| i < seen.len() && !std::mem::replace(&mut seen[i], true))
+        {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Not a permutation of {} wires",
+                self.size
+            )));
+        }
+
+        let extended: Vec<usize> = permutation.iter().copied().chain(self.size..self.width).collect();
+        Ok(Self::switches(&extended)
+            .into_iter()
+            .flatten()
+            .map(|(_, _, swapped)| swapped)
+            .collect())
+    }
+
+    /// Share the control bits routing `permutation` among `num_servers` servers with `scheme`
+    ///
+    /// Returns the shares of each server by server ID, one `Permutation`
+    /// share per switch indexed by its position in `layers`. Whoever deals
+    /// the bits knows the permutation.
+    pub fn share_control_bits(
+        &self,
+        permutation: &[usize],
+        threshold: usize,
+        num_servers: usize,
+        scheme: SharingScheme,
+    ) -> Result<Vec<Vec<DataShare>>, ProtocolError> {
+        let bits = self.route(permutation)?;
+        let mut shares: Vec<Vec<DataShare>> = (0..num_servers).map(|_| Vec::with_capacity(bits.len())).collect();
+
+        match scheme {
+            SharingScheme::Shamir => {
+                let shamir = ShamirSecretSharing::new(threshold, num_servers, SHARE_MODULUS)?;
+                for (index, &bit) in bits.iter().enumerate() {
+                    for share in shamir.share_secret(bit as u64)? {
+                        shares[share.id].push(DataShare::permutation(share.id, index, share.value, SHARE_MODULUS));
+                    }
+                }
+            }
+            SharingScheme::Additive => {
+                let additive = AdditiveSecretSharing::new(num_servers)?;
+                for (index, &bit) in bits.iter().enumerate() {
+                    for share in additive.share_secret(bit as u64) {
+                        shares[share.id].push(DataShare::permutation(share.id, index, share.value, WRAPPING_MODULUS));
+                    }
+                }
+            }
+        }
+
+        Ok(shares)
+    }
+
+    /// Switches of each layer with their setting for `permutation` of a power of two wires
+    fn switches(permutation: &[usize]) -> Vec<Vec<(usize, usize, bool)>> {
+        let width = permutation.len();
+        if width < 2 {
+            return Vec::new();
+        }
+
+        let depth = 2 * width.trailing_zeros() as usize - 1;
+        let mut layers = vec![Vec::new(); depth];
+        let wires: Vec<usize> = (0..width).collect();
+        Self::route_into(permutation, &wires, 0, &mut layers);
+        layers
+    }
+
+    /// Route `permutation` of the subnetwork on `wires`, whose layers start at `first`
+    ///
+    /// Switches act in place: input switch `j` and output switch `j` both
+    /// join wires `2j` and `2j + 1`, the upper subnetwork runs on the even
+    /// wires and the lower one on the odd wires.
+    fn route_into(permutation: &[usize], wires: &[usize], first: usize, layers: &mut [Vec<(usize, usize, bool)>]) {
+        let width = wires.len();
+        if width == 2 {
+            layers[first].push((wires[0], wires[1], permutation[0] == 1));
+            return;
+        }
+
+        let mut inverse = vec![0; width];
+        for (input, &output) in permutation.iter().enumerate() {
+            inverse[output] = input;
+        }
+
+        // Whether each input goes through the lower subnetwork. Inputs of one
+        // switch take different subnetworks, and so do the inputs bound for
+        // one output switch; each cycle of these constraints starts with the
+        // input bound for an even output in the upper subnetwork, which keeps
+        // the first output switch straight.
+        let mut lower: Vec<Option<bool>> = vec![None; width];
+        for output in (0..width).step_by(2) {
+            let mut input = inverse[output];
+            while lower[input].is_none() {
+                lower[input] = Some(false);
+                lower[input ^ 1] = Some(true);
+                input = inverse[permutation[input ^ 1] ^ 1];
+            }
+        }
+
+        let half = width / 2;
+        let mut upper_permutation = Vec::with_capacity(half);
+        let mut lower_permutation = Vec::with_capacity(half);
+        for j in 0..half {
+            let swapped = lower[2 * j] == Some(true);
+            layers[first].push((wires[2 * j], wires[2 * j + 1], swapped));
+            let (up, down) = if swapped { (2 * j + 1, 2 * j) } else { (2 * j, 2 * j + 1) };
+            upper_permutation.push(permutation[up] / 2);
+            lower_permutation.push(permutation[down] / 2);
+        }
+
+        let even: Vec<usize> = wires.iter().step_by(2).copied().collect();
+        let odd: Vec<usize> = wires.iter().skip(1).step_by(2).copied().collect();
+        Self::route_into(&upper_permutation, &even, first + 1, layers);
+        Self::route_into(&lower_permutation, &odd, first + 1, layers);
+
+        let last = first + 2 * width.trailing_zeros() as usize - 2;
+        for j in 1..half {
+            let swapped = lower[inverse[2 * j]] == Some(true);
+            layers[last].push((wires[2 * j], wires[2 * j + 1], swapped));
+        }
+    }
+}
+
+/// One server's evaluation of a `WaksmanNetwork` on shared records with shared control bits
+///
+/// A switch with bit `b` between records `x` and `y` outputs `x + b * (y - x)`
+/// and `y - b * (y - x)`, with one Beaver multiplication per share of the
+/// record and one more per MAC share, so it reveals neither `b` nor the
+/// records. The servers evaluate one layer per round: each sends every other
+/// server its masked values from `mask`, and `finish_layer` opens them and
+/// applies the layer. Records are padded with shares of zero, which are
+/// dropped again by `into_records`. The bits are assumed to be 0 or 1, so the
+/// dealer of the bits must be semi-honest.
+pub struct ObliviousShuffle {
+    network: WaksmanNetwork,
+    gate: MultiplicationGate,
+    records: Vec<Vec<DataShare>>,
+    authenticated: Vec<bool>,
+    bits: Vec<DataShare>,
+    triples: Vec<BeaverTriple>,
+    layer: usize,
+    switch_offset: usize,
+}
+
+impl ObliviousShuffle {
+    /// Prepare to shuffle this server's `records` with its shares of the control `bits`
+    ///
+    /// `triples` must hold `triples_needed(&records)` triples, the same ones
+    /// by index on every server.
+    pub fn new(
+        threshold: usize,
+        num_servers: usize,
+        mut records: Vec<Vec<DataShare>>,
+        bits: Vec<DataShare>,
+        triples: Vec<BeaverTriple>,
+    ) -> Result<Self, ProtocolError> {
+        let network = WaksmanNetwork::new(records.len());
+        let authenticated = Self::authenticated(&records)?;
+
+        if bits.len() != network.switch_count() {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Network of {} switches given {} control bits",
+                network.switch_count(),
+                bits.len()
+            )));
+        }
+        let needed = Self::triples_needed(&records);
+        if triples.len() != needed {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Shuffle of {} records needs {} Beaver triples, given {}",
+                records.len(),
+                needed,
+                triples.len()
+            )));
+        }
+
+        if let Some(first) = records.first() {
+            let zero: Vec<DataShare> = first
+                .iter()
+                .map(|share| {
+                    let mut zero = share.clone();
+                    zero.value = 0;
+                    zero.mac = share.mac.map(|_| 0);
+                    zero
+                })
+                .collect();
+            records.resize(network.width(), zero);
+        }
+
+        Ok(Self {
+            gate: MultiplicationGate::new(threshold, num_servers)?,
+            network,
+            records,
+            authenticated,
+            bits,
+            triples,
+            layer: 0,
+            switch_offset: 0,
+        })
+    }
+
+    /// Number of Beaver triples needed to shuffle `records`
+    pub fn triples_needed(records: &[Vec<DataShare>]) -> usize {
+        let multiplications = records
+            .first()
+            .map_or(0, |record| record.iter().map(|share| 1 + share.mac.is_some() as usize).sum());
+        WaksmanNetwork::new(records.len()).switch_count() * multiplications
+    }
+
+    /// Number of layers, i.e. rounds of communication
+    pub fn layers(&self) -> usize {
+        self.network.layers().len()
+    }
+
+    /// Check whether every layer has been applied
+    pub fn is_finished(&self) -> bool {
+        self.layer == self.layers()
+    }
+
+    /// This server's shares of the masked values `d` and `e` of every
+    /// multiplication of the current layer, to send to the other servers
+    pub fn mask(&self) -> Result<Vec<DataShare>, ProtocolError> {
+        let mut masked = Vec::new();
+        for (index, difference) in self.differences()?.into_iter().enumerate() {
+            let (switch, triple) = self.operands(index);
+            let (d, e) = self.gate.mask(switch, &difference, triple)?;
+            masked.push(d);
+            masked.push(e);
+        }
+        Ok(masked)
+    }
+
+    /// Open the masked values of the current layer from every server's `mask`
+    /// and apply the layer's switches
+    pub fn finish_layer(&mut self, masked: &[Vec<DataShare>]) -> Result<(), ProtocolError> {
+        let differences = self.differences()?;
+        if let Some(shares) = masked.iter().find(|shares| shares.len() != 2 * differences.len()) {
+            return Err(ProtocolError::MalformedMessage(format!(
+                "Expected {} masked shares for layer {}, received {}",
+                2 * differences.len(),
+                self.layer,
+                shares.len()
+            )));
+        }
+
+        let mut products = Vec::with_capacity(differences.len());
+        for index in 0..differences.len() {
+            let d: Vec<DataShare> = masked.iter().map(|shares| shares[2 * index].clone()).collect();
+            let e: Vec<DataShare> = masked.iter().map(|shares| shares[2 * index + 1].clone()).collect();
+            let (switch, triple) = self.operands(index);
+            products.push(self.gate.finish(switch, triple, self.gate.open(&d)?, self.gate.open(&e)?)?.value);
+        }
+
+        let layer = &self.network.layers()[self.layer];
+        let mut products = products.into_iter();
+        for &(upper, lower) in layer {
+            for feature in 0..self.authenticated.len() {
+                let ring = Ring::of(self.records[upper][feature].modulus)?;
+                let product = products.next().unwrap_or_default();
+                let (x, y) = Self::pair(&mut self.records, upper, lower, feature);
+                x.value = ring.add(x.value, product);
+                y.value = ring.sub(y.value, product);
+
+                if self.authenticated[feature] {
+                    let product = products.next().unwrap_or_default();
+                    if let (Some(x), Some(y)) = (x.mac.as_mut(), y.mac.as_mut()) {
+                        *x = ring.add(*x, product);
+                        *y = ring.sub(*y, product);
+                    }
+                }
+            }
+        }
+
+        self.switch_offset += layer.len();
+        self.layer += 1;
+        Ok(())
+    }
+
+    /// The shuffled records, without the padding
+    pub fn into_records(mut self) -> Vec<Vec<DataShare>> {
+        self.records.truncate(self.network.size());
+        self.records
+    }
+
+    /// Shares of `y - x`, and of the MAC of `y - x`, for every switch of the current layer
+    fn differences(&self) -> Result<Vec<DataShare>, ProtocolError> {
+        let layer = self.network.layers().get(self.layer).ok_or(ProtocolError::InternalError {
+            message: "Oblivious shuffle has no layer left".to_string(),
+        })?;
+
+        let mut differences = Vec::new();
+        for &(upper, lower) in layer {
+            for feature in 0..self.authenticated.len() {
+                let (x, y) = (&self.records[upper][feature], &self.records[lower][feature]);
+                let ring = Ring::of(x.modulus)?;
+                let mut difference = y.clone();
+                difference.value = ring.sub(y.value, x.value);
+                difference.mac = None;
+                differences.push(difference.clone());
+
+                if self.authenticated[feature] {
+                    difference.value = ring.sub(y.mac.unwrap_or_default(), x.mac.unwrap_or_default());
+                    differences.push(difference);
+                }
+            }
+        }
+        Ok(differences)
+    }
+
+    /// Control bit and triple of multiplication `index` of the current layer
+    fn operands(&self, index: usize) -> (&DataShare, &BeaverTriple) {
+        let multiplications: usize = self.authenticated.iter().map(|&mac| 1 + mac as usize).sum();
+        let switch = self.switch_offset + index / multiplications;
+        (&self.bits[switch], &self.triples[self.switch_offset * multiplications + index])
+    }
+
+    /// Shares of `feature` of the records on wires `upper` and `lower`
+    fn pair(records: &mut [Vec<DataShare>], upper: usize, lower: usize, feature: usize) -> (&mut DataShare, &mut DataShare) {
+        let (head, tail) = records.split_at_mut(lower);
+        (&mut head[upper][feature], &mut tail[0][feature])
+    }
+
+    /// Which shares of the records carry MACs, the same for every record
+    fn authenticated(records: &[Vec<DataShare>]) -> Result<Vec<bool>, ProtocolError> {
+        let authenticated: Vec<bool> = records.first().map_or(Vec::new(), |record| {
+            record.iter().map(DataShare::is_authenticated).collect()
+        });
+
+        for record in records {
+            if record.len() != authenticated.len()
+                || record.iter().zip(&authenticated).any(|(share, &mac)| share.is_authenticated() != mac)
+            {
+                return Err(ProtocolError::InvalidConfiguration(
+                    "Records of an oblivious shuffle must have the same shares".to_string(),
+                ));
+            }
+        }
+        Ok(authenticated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::beaver::TripleDealer;
+    use crate::multi_party::crypto::ThresholdEncryption;
+    use crate::schema::DataPoint;
+    use rand::seq::SliceRandom;
+
+    #[test]
+    fn test_waksman_routing() {
+        let mut rng = rand::thread_rng();
+        assert_eq!(WaksmanNetwork::new(8).switch_count(), 17);
+        assert_eq!(WaksmanNetwork::new(8).layers().len(), 5);
+
+        for size in 0..=12 {
+            let network = WaksmanNetwork::new(size);
+            for _ in 0..20 {
+                let mut permutation: Vec<usize> = (0..size).collect();
+                permutation.shuffle(&mut rng);
+                let bits = network.route(&permutation).unwrap();
+                assert_eq!(bits.len(), network.switch_count());
+
+                // Apply the switches in the clear
+                let mut wires: Vec<usize> = (0..network.width()).collect();
+                let switches = network.layers().iter().flatten();
+                for (&(upper, lower), &swapped) in switches.zip(&bits) {
+                    if swapped {
+                        wires.swap(upper, lower);
+                    }
+                }
+                for (input, &output) in permutation.iter().enumerate() {
+                    assert_eq!(wires[output], input, "size {}", size);
+                }
+            }
+        }
+
+        assert!(WaksmanNetwork::new(3).route(&[0, 0, 1]).is_err());
+        assert!(WaksmanNetwork::new(3).route(&[0, 1]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oblivious_shuffle() {
+        for (scheme, authenticated) in [(SharingScheme::Shamir, false), (SharingScheme::Additive, false), (SharingScheme::Shamir, true)] {
+            let mut crypto = ThresholdEncryption::new(2, 3).unwrap().with_scheme(scheme).with_authentication(authenticated);
+            crypto.initialize().await.unwrap();
+
+            let points: Vec<Vec<f64>> = (0..5).map(|i| vec![i as f64, 10.0 * i as f64]).collect();
+            let mut records: Vec<Vec<Vec<DataShare>>> = vec![Vec::new(); 3];
+            for features in &points {
+                let mut record = vec![Vec::new(); 3];
+                for share in crypto.share_data(DataPoint::new(features.clone())).await.unwrap() {
+                    record[share.server_id].push(share);
+                }
+                for (server_id, shares) in record.into_iter().enumerate() {
+                    records[server_id].push(shares);
+                }
+            }
+
+            let network = WaksmanNetwork::new(points.len());
+            let permutation = vec![3, 0, 4, 1, 2];
+            let bits = network.share_control_bits(&permutation, 2, 3, scheme).unwrap();
+            let triples = TripleDealer::new(2, 3, scheme).unwrap().deal(ObliviousShuffle::triples_needed(&records[0])).unwrap();
+            let mut shuffles: Vec<ObliviousShuffle> = records
+                .into_iter()
+                .zip(bits)
+                .zip(triples)
+                .map(|((records, bits), triples)| ObliviousShuffle::new(2, 3, records, bits, triples).unwrap())
+                .collect();
+
+            while !shuffles[0].is_finished() {
+                let masked: Vec<Vec<DataShare>> = shuffles.iter().map(|shuffle| shuffle.mask().unwrap()).collect();
+                for shuffle in &mut shuffles {
+                    shuffle.finish_layer(&masked).unwrap();
+                }
+            }
+
+            let shuffled: Vec<Vec<Vec<DataShare>>> = shuffles.into_iter().map(ObliviousShuffle::into_records).collect();
+            let combined: Vec<Vec<DataShare>> = (0..points.len())
+                .map(|i| shuffled.iter().flat_map(|records| records[i].clone()).collect())
+                .collect();
+            let reconstructed = crypto.reconstruct_data_many(combined).await.unwrap();
+            for (input, &output) in permutation.iter().enumerate() {
+                assert_eq!(reconstructed[output].features(), points[input].as_slice(), "{:?}", scheme);
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use crate::multi_party::communication::{MessagePayload, MessageType, NetworkMessage, MAX_MESSAGE_BYTES};
+use crate::multi_party::protocol::{ProtocolError, ProtocolPhase};
+use rkyv::{AlignedVec, Archive, Deserialize, Serialize};
+
+/// Zero-copy wire frame of a `NetworkMessage`
+///
+/// Share vectors and permutations are archived with rkyv, so a receiver can
+/// validate the frame once and then read them in place. Payloads off the hot
+/// path, such as queries and results, are carried as JSON.
+#[derive(Archive, Serialize, Deserialize, Debug)]
+#[archive(check_bytes)]
+struct Frame {
+    message_type: u8,
+    error: Option<String>,
+    source_id: u64,
+    target_id: u64,
+    sequence: u64,
+    timestamp: u64,
+    payload: FramePayload,
+    mac: Option<Vec<u8>>,
+    version: u16,
+    phase: Option<u8>,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug)]
+#[archive(check_bytes)]
+enum FramePayload {
+    Empty,
+    Heartbeat,
+    Shares(Vec<Vec<u8>>),
+    Permutation(Vec<u64>),
+    Json(Vec<u8>),
+}
+
+fn malformed(message: impl Into<String>) -> ProtocolError {
+    ProtocolError::MalformedMessage(message.into())
+}
+
+pub(crate) fn message_type_tag(message_type: &MessageType) -> u8 {
+    match message_type {
+        MessageType::Init => 0,
+        MessageType::Share => 1,
+        MessageType::Shuffle => 2,
+        MessageType::Reconstruct => 3,
+        MessageType::Query => 4,
+        MessageType::QueryResponse => 5,
+        MessageType::Heartbeat => 6,
+        MessageType::Error(_) => 7,
+        MessageType::Ack => 8,
+        MessageType::Broadcast => 9,
+        MessageType::ObliviousTransfer => 10,
+    }
+}
+
+impl NetworkMessage {
+    /// Encode the message as a zero-copy frame, read with `MessageView::decode`
+    pub fn to_zero_copy_bytes(&self) -> Result<AlignedVec, ProtocolError> {
+        let payload = match &self.payload {
+            MessagePayload::Empty => FramePayload::Empty,
+            MessagePayload::Heartbeat => FramePayload::Heartbeat,
+            MessagePayload::Shares(shares) => FramePayload::Shares(shares.clone()),
+            MessagePayload::Permutation(permutation) => {
+                FramePayload::Permutation(permutation.iter().map(|&i| i as u64).collect())
+            }
+            payload => FramePayload::Json(serde_json::to_vec(payload).map_err(|e| malformed(e.to_string()))?),
+        };
+        let error = match &self.message_type {
+            MessageType::Error(error) => Some(error.clone()),
+            _ => None,
+        };
+
+        let frame = Frame {
+            message_type: message_type_tag(&self.message_type),
+            error,
+            source_id: self.source_id as u64,
+            target_id: self.target_id as u64,
+            sequence: self.sequence,
+            timestamp: self.timestamp,
+            payload,
+            mac: self.mac.clone(),
+            version: self.version,
+            phase: self.phase.map(ProtocolPhase::tag),
+        };
+        rkyv::to_bytes::<_, 1024>(&frame).map_err(|e| malformed(e.to_string()))
+    }
+}
+
+/// Validated view of a zero-copy frame, borrowing the received buffer
+///
+/// Shares and permutations are read directly from the buffer, so a server can
+/// process a large share vector without deserializing it into owned vectors.
+#[derive(Clone, Copy)]
+pub struct MessageView<'a> {
+    frame: &'a ArchivedFrame,
+}
+
+impl<'a> MessageView<'a> {
+    /// Validate a frame produced by `NetworkMessage::to_zero_copy_bytes`
+    ///
+    /// The buffer must be 16-byte aligned, e.g. received into an `AlignedVec`.
+    /// As with `NetworkMessage::from_bytes`, malformed input is rejected with
+    /// `ProtocolError::MalformedMessage`: the payload must match the message type
+    /// and permutations must be bijections.
+    pub fn decode(bytes: &'a [u8]) -> Result<Self, ProtocolError> {
+        if bytes.len() > MAX_MESSAGE_BYTES {
+            return Err(malformed(format!(
+                "{} bytes exceed the limit of {} bytes",
+                bytes.len(),
+                MAX_MESSAGE_BYTES
+            )));
+        }
+        if bytes.as_ptr() as usize % 16 != 0 {
+            return Err(malformed("Zero-copy frame is not 16-byte aligned"));
+        }
+
+        let frame = rkyv::check_archived_root::<Frame>(bytes).map_err(|e| malformed(e.to_string()))?;
+        let view = Self { frame };
+        view.validate()?;
+        Ok(view)
+    }
+
+    fn validate(&self) -> Result<(), ProtocolError> {
+        let message_type = self.message_type()?;
+        let consistent = match (&message_type, &self.frame.payload) {
+            (MessageType::Init | MessageType::Ack | MessageType::Reconstruct, ArchivedFramePayload::Empty) => true,
+            (MessageType::Share | MessageType::Reconstruct | MessageType::Shuffle, ArchivedFramePayload::Shares(_)) => true,
+            (MessageType::Shuffle, ArchivedFramePayload::Permutation(permutation)) => {
+                let mut seen = vec![false; permutation.len()];
+                if !permutation.iter().all(|&i| {
+                    let i = i as usize;
+                    i < seen.len() && !std::mem::replace(&mut seen[i], true)
+                }) {
+                    return Err(malformed("Shuffle permutation is not a bijection"));
+                }
+                true
+            }
+            (MessageType::Heartbeat, ArchivedFramePayload::Heartbeat) => true,
+            // Payloads carried as JSON, including handshakes and encrypted
+            // messages of any type, are checked when they are decoded
+            (_, ArchivedFramePayload::Json(_)) => true,
+            _ => false,
+        };
+
+        if !consistent {
+            return Err(malformed(format!("{:?} frame with a mismatched payload", message_type)));
+        }
+        Ok(())
+    }
+
+    /// Message type
+    pub fn message_type(&self) -> Result<MessageType, ProtocolError> {
+        Ok(match self.frame.message_type {
+            0 => MessageType::Init,
+            1 => MessageType::Share,
+            2 => MessageType::Shuffle,
+            3 => MessageType::Reconstruct,
+            4 => MessageType::Query,
+            5 => MessageType::QueryResponse,
+            6 => MessageType::Heartbeat,
+            7 => MessageType::Error(
+                self.frame
+                    .error
+                    .as_ref()
+                    .map(|error| error.as_str().to_string())
+                    .unwrap_or_default(),
+            ),
+            8 => MessageType::Ack,
+            9 => MessageType::Broadcast,
+            10 => MessageType::ObliviousTransfer,
+            tag => return Err(malformed(format!("Unknown message type {}", tag))),
+        })
+    }
+
+    /// Source server ID
+    pub fn source_id(&self) -> usize {
+        self.frame.source_id as usize
+    }
+
+    /// Target server ID
+    pub fn target_id(&self) -> usize {
+        self.frame.target_id as usize
+    }
+
+    /// Message sequence number
+    pub fn sequence(&self) -> u64 {
+        self.frame.sequence
+    }
+
+    /// Version of the wire protocol
+    pub fn version(&self) -> u16 {
+        self.frame.version
+    }
+
+    /// Phase the message belongs to
+    pub fn phase(&self) -> Result<Option<ProtocolPhase>, ProtocolError> {
+        self.frame
+            .phase
+            .as_ref()
+            .map(|&tag| ProtocolPhase::from_tag(tag).ok_or_else(|| malformed(format!("Unknown phase {}", tag))))
+            .transpose()
+    }
+
+    /// Authentication tag, borrowed from the buffer
+    pub fn mac(&self) -> Option<&'a [u8]> {
+        let frame: &'a ArchivedFrame = self.frame;
+        frame.mac.as_ref().map(|mac| mac.as_slice())
+    }
+
+    /// Shares carried by the message, borrowed from the buffer
+    pub fn shares(&self) -> Option<impl ExactSizeIterator<Item = &'a [u8]> + 'a> {
+        let frame: &'a ArchivedFrame = self.frame;
+        match &frame.payload {
+            ArchivedFramePayload::Shares(shares) => Some(shares.iter().map(|share| share.as_slice())),
+            _ => None,
+        }
+    }
+
+    /// Number of shares carried by the message
+    pub fn share_count(&self) -> usize {
+        match &self.frame.payload {
+            ArchivedFramePayload::Shares(shares) => shares.len(),
+            _ => 0,
+        }
+    }
+
+    /// Permutation carried by the message, read from the buffer
+    pub fn permutation(&self) -> Option<impl ExactSizeIterator<Item = usize> + 'a> {
+        let frame: &'a ArchivedFrame = self.frame;
+        match &frame.payload {
+            ArchivedFramePayload::Permutation(permutation) => Some(permutation.iter().map(|&i| i as usize)),
+            _ => None,
+        }
+    }
+
+    /// Deserialize into an owned `NetworkMessage`, copying the payload
+    pub fn to_message(&self) -> Result<NetworkMessage, ProtocolError> {
+        let payload = match &self.frame.payload {
+            ArchivedFramePayload::Empty => MessagePayload::Empty,
+            ArchivedFramePayload::Heartbeat => MessagePayload::Heartbeat,
+            ArchivedFramePayload::Shares(shares) => {
+                MessagePayload::Shares(shares.iter().map(|share| share.to_vec()).collect())
+            }
+            ArchivedFramePayload::Permutation(permutation) => {
+                MessagePayload::Permutation(permutation.iter().map(|&i| i as usize).collect())
+            }
+            ArchivedFramePayload::Json(json) => {
+                serde_json::from_slice(json).map_err(|e| malformed(e.to_string()))?
+            }
+        };
+
+        let message = NetworkMessage {
+            message_type: self.message_type()?,
+            source_id: self.source_id(),
+            target_id: self.target_id(),
+            sequence: self.sequence(),
+            payload,
+            timestamp: self.frame.timestamp,
+            mac: self.frame.mac.as_ref().map(|mac| mac.to_vec()),
+            version: self.frame.version,
+            phase: self.phase()?,
+        };
+        message.validate()?;
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{Query, QueryType};
+
+    #[test]
+    fn test_zero_copy_roundtrip() {
+        let shares = vec![vec![1, 2, 3], vec![], vec![4; 100]];
+        let bytes = NetworkMessage::share(0, 2, 9, shares.clone()).to_zero_copy_bytes().unwrap();
+        let view = MessageView::decode(&bytes).unwrap();
+        assert_eq!((view.source_id(), view.target_id(), view.sequence()), (0, 2, 9));
+        assert_eq!(view.share_count(), 3);
+        assert_eq!(view.shares().unwrap().collect::<Vec<_>>(), vec![&[1u8, 2, 3][..], &[][..], &[4; 100][..]]);
+
+        let bytes = NetworkMessage::shuffle(1, 0, 1, vec![2, 0, 1]).to_zero_copy_bytes().unwrap();
+        let view = MessageView::decode(&bytes).unwrap();
+        assert_eq!(view.permutation().unwrap().collect::<Vec<_>>(), vec![2, 0, 1]);
+        assert!(view.shares().is_none());
+
+        let query = Query::new(QueryType::Mean, vec!["feature1".to_string()]);
+        let bytes = NetworkMessage::query(1, 0, 2, query).to_zero_copy_bytes().unwrap();
+        let message = MessageView::decode(&bytes).unwrap().to_message().unwrap();
+        assert!(matches!(message.payload, MessagePayload::Query(_)));
+
+        let bytes = NetworkMessage::error(1, 0, 3, "failed".to_string()).to_zero_copy_bytes().unwrap();
+        let message = MessageView::decode(&bytes).unwrap().to_message().unwrap();
+        assert!(matches!(message.message_type, MessageType::Error(ref error) if error == "failed"));
+    }
+
+    #[test]
+    fn test_zero_copy_rejects_malformed_frames() {
+        let bytes = NetworkMessage::shuffle(0, 1, 7, vec![0, 0, 3]).to_zero_copy_bytes().unwrap();
+        assert!(MessageView::decode(&bytes).is_err());
+
+        let bytes = NetworkMessage::share(0, 1, 7, vec![vec![1; 64]]).to_zero_copy_bytes().unwrap();
+        let mut truncated = AlignedVec::new();
+        truncated.extend_from_slice(&bytes[..bytes.len() / 2]);
+        assert!(matches!(MessageView::decode(&truncated), Err(ProtocolError::MalformedMessage(_))));
+
+        let mut unaligned = AlignedVec::new();
+        unaligned.push(0);
+        unaligned.extend_from_slice(&bytes);
+        assert!(MessageView::decode(&unaligned[1..]).is_err());
+    }
+}
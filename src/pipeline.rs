@@ -0,0 +1,330 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! One configured pipeline from client data to released query results.
+//!
+//! An `MpsdpPipeline` wires the stages that callers otherwise glue together by
+//! hand. Client encoding checks every `DataPoint` against the schema and attaches
+//! its `Attribute`s, and resolves attribute names in the query to the positional
+//! feature names the mechanisms use. The `Shuffler` then permutes the encoded
+//! points, the `DPMechanism` answers the query, and the server release marks the
+//! result with the budget it spent and charges it to the pipeline's total budget.
+
+use crate::arith::PrivacyBudget;
+use crate::dp::{DPConfig, DPError, DPMechanism, MechanismType};
+use crate::schema::{attr_from_attr_value, DataPoint, Query, QueryResult, Schema};
+use crate::shuffle::{ShuffleConfig, ShuffleError, Shuffler};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("Invalid pipeline configuration: {0}")]
+    InvalidConfig(String),
+    #[error("Data point {index} does not match the schema: {message}")]
+    Encoding { index: usize, message: String },
+    #[error("Unknown attribute {0}")]
+    UnknownAttribute(String),
+    #[error("Releasing epsilon {requested} would exceed the total budget {total}, of which {spent} is spent")]
+    BudgetExceeded {
+        requested: f64,
+        spent: f64,
+        total: f64,
+    },
+    #[error("Shuffle error: {0}")]
+    Shuffle(#[from] ShuffleError),
+    #[error("DP error: {0}")]
+    DP(#[from] DPError),
+}
+
+/// A configured client → shuffler → DP mechanism → release pipeline.
+pub struct MpsdpPipeline {
+    schema: Option<Schema>,
+    shuffler: Shuffler,
+    dp_mechanism: DPMechanism,
+    privacy_budget: PrivacyBudget,
+    total_epsilon: Option<f64>,
+    epsilon_spent: f64,
+}
+
+impl MpsdpPipeline {
+    /// Create a builder starting from the default configuration
+    pub fn builder() -> MpsdpPipelineBuilder {
+        MpsdpPipelineBuilder::default()
+    }
+
+    /// Return the schema the data is encoded with, if any.
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    /// Return the budget each run spends.
+    pub fn privacy_budget(&self) -> &PrivacyBudget {
+        &self.privacy_budget
+    }
+
+    /// Return the epsilon spent by all runs so far.
+    pub fn epsilon_spent(&self) -> f64 {
+        self.epsilon_spent
+    }
+
+    /// Return the epsilon left for further runs, if the total budget is limited.
+    pub fn epsilon_remaining(&self) -> Option<f64> {
+        self.total_epsilon
+            .map(|total| (total - self.epsilon_spent).max(0.0))
+    }
+
+    /// Encode, shuffle, and answer `query` on `data`, and release the result.
+    ///
+    /// Nothing is spent if any stage fails.
+    pub fn run(
+        &mut self,
+        data: Vec<DataPoint>,
+        query: Query,
+    ) -> Result<QueryResult, PipelineError> {
+        let epsilon = self.privacy_budget.epsilon();
+        if let Some(total) = self.total_epsilon {
+            if self.epsilon_spent + epsilon > total {
+                return Err(PipelineError::BudgetExceeded {
+                    requested: epsilon,
+                    spent: self.epsilon_spent,
+                    total,
+                });
+            }
+        }
+
+        let mut data = self.encode(data)?;
+        let query = self.resolve_query(query)?;
+        // The DP stage borrows the shuffled points rather than taking a copy
+        self.shuffler.shuffle_in_place(&mut data)?;
+        let mut result = self
+            .dp_mechanism
+            .apply_mechanism_batches(std::iter::once(data.as_slice()), query)?;
+
+        self.epsilon_spent += epsilon;
+        result.set_privacy_budget_used(epsilon);
+        Ok(result)
+    }
+
+    /// Check every data point against the schema and attach its attributes.
+    fn encode(&self, data: Vec<DataPoint>) -> Result<Vec<DataPoint>, PipelineError> {
+        let schema = match &self.schema {
+            Some(schema) => schema,
+            None => return Ok(data),
+        };
+        let attr_types = schema.get_attr_types();
+
+        data.into_iter()
+            .enumerate()
+            .map(|(index, point)| {
+                if !point.attributes().is_empty() {
+                    return Ok(point);
+                }
+                if point.features().len() != attr_types.len() {
+                    return Err(PipelineError::Encoding {
+                        index,
+                        message: format!(
+                            "expected {} features, found {}",
+                            attr_types.len(),
+                            point.features().len()
+                        ),
+                    });
+                }
+
+                let attributes = attr_types
+                    .iter()
+                    .zip(point.features())
+                    .map(|(&attr_type, &value)| {
+                        if value < 0.0 || value.fract() != 0.0 || value > u32::MAX as f64 {
+                            return Err(format!("{} is not a valid attribute value", value));
+                        }
+                        attr_from_attr_value(attr_type, value as u32)
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|message| PipelineError::Encoding { index, message })?;
+                Ok(DataPoint::with_attributes(
+                    point.features().to_vec(),
+                    attributes,
+                ))
+            })
+            .collect()
+    }
+
+    /// Replace attribute names in the query with the positional feature names.
+    fn resolve_query(&self, mut query: Query) -> Result<Query, PipelineError> {
+        let schema = match &self.schema {
+            Some(schema) => schema,
+            None => return Ok(query),
+        };
+
+        for feature in query.features.iter_mut() {
+            if let Some(index) = schema.get_attr_index(feature) {
+                *feature = format!("feature{}", index + 1);
+            } else if !is_positional(feature, schema.len()) {
+                return Err(PipelineError::UnknownAttribute(feature.clone()));
+            }
+        }
+
+        Ok(query)
+    }
+}
+
+/// Whether a feature name is `featureN` for `N` in `1..=len`.
+fn is_positional(feature: &str, len: usize) -> bool {
+    feature
+        .strip_prefix("feature")
+        .and_then(|index| index.parse::<usize>().ok())
+        .map_or(false, |index| (1..=len).contains(&index))
+}
+
+/// Builder for `MpsdpPipeline`
+pub struct MpsdpPipelineBuilder {
+    schema: Option<Schema>,
+    shuffle_rounds: usize,
+    privacy_budget: PrivacyBudget,
+    mechanism_type: MechanismType,
+    total_epsilon: Option<f64>,
+}
+
+impl Default for MpsdpPipelineBuilder {
+    fn default() -> Self {
+        let dp_config = DPConfig::default();
+        Self {
+            schema: None,
+            shuffle_rounds: 1,
+            privacy_budget: dp_config.privacy_budget,
+            mechanism_type: dp_config.mechanism_type,
+            total_epsilon: None,
+        }
+    }
+}
+
+impl MpsdpPipelineBuilder {
+    /// Encode and validate data with a schema
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Set the number of shuffle rounds
+    pub fn shuffle_rounds(mut self, shuffle_rounds: usize) -> Self {
+        self.shuffle_rounds = shuffle_rounds;
+        self
+    }
+
+    /// Set the privacy budget each run spends
+    pub fn privacy_budget(mut self, privacy_budget: PrivacyBudget) -> Self {
+        self.privacy_budget = privacy_budget;
+        self
+    }
+
+    /// Set the DP mechanism
+    pub fn mechanism(mut self, mechanism_type: MechanismType) -> Self {
+        self.mechanism_type = mechanism_type;
+        self
+    }
+
+    /// Limit the epsilon all runs may spend together
+    pub fn total_epsilon(mut self, total_epsilon: f64) -> Self {
+        self.total_epsilon = Some(total_epsilon);
+        self
+    }
+
+    /// Build the pipeline
+    pub fn build(self) -> Result<MpsdpPipeline, PipelineError> {
+        if self.shuffle_rounds == 0 {
+            return Err(PipelineError::InvalidConfig(
+                "shuffle_rounds must be at least 1".to_string(),
+            ));
+        }
+        if let Some(schema) = &self.schema {
+            if !schema.is_valid() {
+                return Err(PipelineError::InvalidConfig(
+                    "schema is invalid".to_string(),
+                ));
+            }
+        }
+        if let Some(total) = self.total_epsilon {
+            if total.is_nan() || total < 0.0 {
+                return Err(PipelineError::InvalidConfig(
+                    "total_epsilon must be non-negative".to_string(),
+                ));
+            }
+        }
+
+        let mut shuffle_config = ShuffleConfig::builder()
+            .shuffle_rounds(self.shuffle_rounds)
+            .privacy_budget(self.privacy_budget.clone());
+        if let Some(schema) = &self.schema {
+            shuffle_config = shuffle_config.schema(schema.clone());
+        }
+
+        Ok(MpsdpPipeline {
+            shuffler: Shuffler::new(shuffle_config.build()),
+            dp_mechanism: DPMechanism::new(DPConfig {
+                privacy_budget: self.privacy_budget.clone(),
+                mechanism_type: self.mechanism_type,
+            }),
+            schema: self.schema,
+            privacy_budget: self.privacy_budget,
+            total_epsilon: self.total_epsilon,
+            epsilon_spent: 0.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::QueryType;
+
+    fn pipeline() -> MpsdpPipeline {
+        MpsdpPipeline::builder()
+            .schema(Schema::try_from(r#"[["age","c7"],["income",{"n10":1000}]]"#).unwrap())
+            .privacy_budget(PrivacyBudget::new(0.5, 1e-6))
+            .total_epsilon(1.0)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_run() {
+        let mut pipeline = pipeline();
+        let data = vec![
+            DataPoint::new(vec![1.0, 200.0]),
+            DataPoint::new(vec![3.0, 400.0]),
+        ];
+        let query = Query::new(QueryType::Mean, vec!["income".to_string()]);
+
+        let result = pipeline.run(data.clone(), query.clone()).unwrap();
+        assert!(result.has_noise());
+        assert_eq!(result.privacy_budget_used(), 0.5);
+        pipeline.run(data.clone(), query.clone()).unwrap();
+        assert_eq!(pipeline.epsilon_remaining(), Some(0.0));
+        assert!(matches!(
+            pipeline.run(data, query),
+            Err(PipelineError::BudgetExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_encoding_errors() {
+        let mut pipeline = pipeline();
+        let query = Query::new(QueryType::Mean, vec!["feature1".to_string()]);
+        assert!(matches!(
+            pipeline.run(vec![DataPoint::new(vec![1.0, 2.5])], query.clone()),
+            Err(PipelineError::Encoding { index: 0, .. })
+        ));
+        assert!(matches!(
+            pipeline.run(vec![DataPoint::new(vec![1.0])], query),
+            Err(PipelineError::Encoding { .. })
+        ));
+
+        let unknown = Query::new(QueryType::Mean, vec!["height".to_string()]);
+        assert!(matches!(
+            pipeline.run(vec![DataPoint::new(vec![1.0, 2.0])], unknown),
+            Err(PipelineError::UnknownAttribute(_))
+        ));
+        assert_eq!(pipeline.epsilon_spent(), 0.0);
+        assert!(MpsdpPipeline::builder().shuffle_rounds(0).build().is_err());
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::report::Report;
+use super::report_handler::ReportHandler;
+use super::report_vector::{read_report_bits, write_report_bits, ReportVector};
+use crate::schema::AttributeType;
+use serde::{Deserialize, Serialize};
+use std::mem::size_of;
+use thiserror::Error;
+
+/// Magic bytes that start every compressed batch.
+const BATCH_MAGIC: &[u8; 4] = b"DPRB";
+
+/// Version of the batch format.
+const BATCH_VERSION: u8 = 1;
+
+/// Flag bit for XOR delta coding between consecutive reports.
+const FLAG_DELTA: u8 = 1 << 0;
+
+/// Flag bit for a zstd-compressed payload.
+const FLAG_ZSTD: u8 = 1 << 1;
+
+/// Size of the fixed part of the batch header: magic, version, flags, and the length
+/// of the batch description.
+const FIXED_HEADER_SIZE: usize = BATCH_MAGIC.len() + 2 + size_of::<u32>();
+
+/// Errors that can occur when decompressing a report batch.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CompressionError {
+    /// The bytes do not start with a batch header
+    #[error("The report batch header is malformed")]
+    Malformed,
+
+    /// The batch was written by an unknown version of the format
+    #[error("Unsupported report batch version {0}")]
+    UnsupportedVersion(u8),
+
+    /// The attribute types in the header are not valid for this report size
+    #[error("The report batch attribute types are invalid")]
+    InvalidAttrTypes,
+
+    /// The payload could not be decompressed or is too short
+    #[error("The report batch payload is corrupted")]
+    Corrupted,
+
+    /// A decoded `Report` does not match the attribute types
+    #[error("The report batch contains an invalid report")]
+    InvalidReport,
+}
+
+/// How a `ReportVector` is encoded into a batch. Every batch is bit-packed by the
+/// attribute widths; delta coding and zstd are applied on top.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchCodec {
+    /// XOR every report with the previous one before packing; this pays off when
+    /// consecutive reports are similar, e.g. for sorted plaintext reports
+    pub delta: bool,
+
+    /// Compress the packed bits with zstd at this level
+    pub zstd_level: Option<i32>,
+}
+
+impl BatchCodec {
+    /// Bit-packing only. This is the right choice for secret shares, which are
+    /// uniformly random and do not compress further.
+    pub fn packed() -> Self {
+        Self {
+            delta: false,
+            zstd_level: None,
+        }
+    }
+
+    /// Bit-packing followed by zstd at the default level.
+    pub fn zstd() -> Self {
+        Self {
+            delta: false,
+            zstd_level: Some(zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+
+    /// Delta coding and bit-packing followed by zstd at the default level.
+    pub fn delta_zstd() -> Self {
+        Self {
+            delta: true,
+            zstd_level: Some(zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.delta {
+            flags |= FLAG_DELTA;
+        }
+        if self.zstd_level.is_some() {
+            flags |= FLAG_ZSTD;
+        }
+        flags
+    }
+}
+
+impl Default for BatchCodec {
+    fn default() -> Self {
+        Self::zstd()
+    }
+}
+
+/// The self-describing part of the batch header, so a batch can be decoded without
+/// knowing the `Schema` it was created with.
+#[derive(Debug, Serialize, Deserialize)]
+struct BatchDescription {
+    attr_types: Vec<AttributeType>,
+    report_count: usize,
+}
+
+impl<const U32_SIZE: usize> ReportVector<U32_SIZE> {
+    /// Encode the `ReportVector` into a compressed batch with a self-describing header.
+    pub fn compress(&self, codec: BatchCodec) -> Vec<u8> {
+        let description = serde_json::to_vec(&BatchDescription {
+            attr_types: self.report_handler().get_attr_types().to_vec(),
+            report_count: self.len(),
+        })
+        .expect("compress: Failed to serialize the batch description.");
+
+        let mut bytes = BATCH_MAGIC.to_vec();
+        bytes.push(BATCH_VERSION);
+        bytes.push(codec.flags());
+        bytes.extend_from_slice(&(description.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&description);
+
+        let payload = self
+            .pack_reports(codec.delta)
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        match codec.zstd_level {
+            Some(level) => bytes.extend_from_slice(
+                &zstd::encode_all(payload.as_slice(), level)
+                    .expect("compress: zstd compression failed."),
+            ),
+            None => bytes.extend_from_slice(&payload),
+        }
+
+        bytes
+    }
+
+    /// Decode a batch created by `compress`.
+    pub fn decompress(bytes: &[u8]) -> Result<Self, CompressionError> {
+        if bytes.len() < FIXED_HEADER_SIZE || &bytes[..BATCH_MAGIC.len()] != BATCH_MAGIC {
+            return Err(CompressionError::Malformed);
+        }
+
+        let version = bytes[BATCH_MAGIC.len()];
+        if version != BATCH_VERSION {
+            return Err(CompressionError::UnsupportedVersion(version));
+        }
+
+        let flags = bytes[BATCH_MAGIC.len() + 1];
+        let description_size = u32::from_le_bytes(
+            bytes[BATCH_MAGIC.len() + 2..FIXED_HEADER_SIZE]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        let description = bytes
+            .get(FIXED_HEADER_SIZE..FIXED_HEADER_SIZE + description_size)
+            .ok_or(CompressionError::Malformed)?;
+        let description: BatchDescription =
+            serde_json::from_slice(description).map_err(|_| CompressionError::Malformed)?;
+        if !is_valid_batch_attr_types::<U32_SIZE>(&description.attr_types) {
+            return Err(CompressionError::InvalidAttrTypes);
+        }
+
+        let payload = &bytes[FIXED_HEADER_SIZE + description_size..];
+        let payload = if flags & FLAG_ZSTD != 0 {
+            zstd::decode_all(payload).map_err(|_| CompressionError::Corrupted)?
+        } else {
+            payload.to_vec()
+        };
+        if payload.len() % size_of::<u32>() != 0 {
+            return Err(CompressionError::Corrupted);
+        }
+        let packed = payload
+            .chunks_exact(size_of::<u32>())
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        Self::unpack_reports(
+            &description.attr_types,
+            &packed,
+            description.report_count,
+            flags & FLAG_DELTA != 0,
+        )
+    }
+
+    /// Pack the `Report`s, optionally XOR-ing each with its predecessor first.
+    fn pack_reports(&self, delta: bool) -> Vec<u32> {
+        if !delta {
+            return self.pack();
+        }
+
+        let report_bit_size = self.report_handler().get_report_bit_size();
+        let mut packed = vec![0u32; (self.len() * report_bit_size + 31) / 32];
+        let mut previous = Report::<U32_SIZE>::new();
+        for (index, report) in self.iter().enumerate() {
+            let delta_report = *report ^ previous;
+            write_report_bits(
+                &mut packed,
+                index * report_bit_size,
+                &delta_report,
+                report_bit_size,
+            );
+            previous = *report;
+        }
+
+        packed
+    }
+
+    /// Unpack `count` many `Report`s, undoing the delta coding if necessary.
+    fn unpack_reports(
+        attr_types: &[AttributeType],
+        packed: &[u32],
+        count: usize,
+        delta: bool,
+    ) -> Result<Self, CompressionError> {
+        let mut report_vector = Self::new(attr_types);
+        let report_bit_size = report_vector.report_handler().get_report_bit_size();
+        if (packed.len() * 32) < count.saturating_mul(report_bit_size) {
+            return Err(CompressionError::Corrupted);
+        }
+
+        let mut previous = Report::<U32_SIZE>::new();
+        for index in 0..count {
+            let mut report =
+                read_report_bits::<U32_SIZE>(packed, index * report_bit_size, report_bit_size);
+            if delta {
+                report ^= previous;
+                previous = report;
+            }
+
+            if !report_vector.report_handler().is_valid_report(&report) {
+                return Err(CompressionError::InvalidReport);
+            }
+            report_vector.push(report);
+        }
+
+        Ok(report_vector)
+    }
+}
+
+/// Return whether the attribute types from a batch header fit into `U32_SIZE` many u32s.
+fn is_valid_batch_attr_types<const U32_SIZE: usize>(attr_types: &[AttributeType]) -> bool {
+    ReportHandler::<U32_SIZE>::is_valid_attr_types(attr_types)
+        && attr_types
+            .iter()
+            .map(|attr_type| attr_type.get_size())
+            .sum::<usize>()
+            <= U32_SIZE * 32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::{OsRng, StdRng};
+    use AttributeType::*;
+
+    const CODECS: [BatchCodec; 4] = [
+        BatchCodec {
+            delta: false,
+            zstd_level: None,
+        },
+        BatchCodec {
+            delta: true,
+            zstd_level: None,
+        },
+        BatchCodec {
+            delta: false,
+            zstd_level: Some(3),
+        },
+        BatchCodec {
+            delta: true,
+            zstd_level: Some(3),
+        },
+    ];
+
+    #[test]
+    fn test_compress_decompress() {
+        let mut rng = OsRng;
+        let attr_types = [C10, N20(65537), C5, N3(7)];
+        let report_vector = ReportVector::<2>::random(&attr_types, &mut rng, 100);
+
+        for codec in CODECS {
+            let bytes = report_vector.compress(codec);
+            assert_eq!(
+                ReportVector::<2>::decompress(&bytes).unwrap(),
+                report_vector
+            );
+        }
+
+        let empty = ReportVector::<1>::new(&[C4]);
+        assert_eq!(
+            ReportVector::<1>::decompress(&empty.compress(BatchCodec::default())).unwrap(),
+            empty
+        );
+    }
+
+    #[test]
+    fn test_compression_ratio() {
+        // Skewed plaintext reports compress well beyond the bit-packing.
+        let mut report_vector = ReportVector::<1>::new(&[C8, C8, C8]);
+        report_vector.push_many_zipf::<StdRng>(10000, 1.5, false);
+
+        let packed = report_vector.compress(BatchCodec::packed()).len();
+        let compressed = report_vector.compress(BatchCodec::zstd()).len();
+        assert!(packed < 10000 * 4);
+        assert!(compressed < packed);
+    }
+
+    #[test]
+    fn test_decompress_errors() {
+        let mut rng = OsRng;
+        let report_vector = ReportVector::<1>::random(&[C10, C10], &mut rng, 10);
+        let bytes = report_vector.compress(BatchCodec::zstd());
+
+        assert_eq!(
+            ReportVector::<1>::decompress(&bytes[..3]),
+            Err(CompressionError::Malformed)
+        );
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = BATCH_VERSION + 1;
+        assert_eq!(
+            ReportVector::<1>::decompress(&wrong_version),
+            Err(CompressionError::UnsupportedVersion(BATCH_VERSION + 1))
+        );
+
+        assert_eq!(
+            ReportVector::<1>::decompress(&bytes[..bytes.len() - 2]),
+            Err(CompressionError::Corrupted)
+        );
+
+        // The header describes 48 bits, which do not fit into a single u32.
+        let wide = ReportVector::<2>::random(&[C32, C16], &mut rng, 1);
+        assert_eq!(
+            ReportVector::<1>::decompress(&wide.compress(BatchCodec::packed())),
+            Err(CompressionError::InvalidAttrTypes)
+        );
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::report_vector::ReportVector;
+use super::store::ReportStore;
+use crate::schema::AttributeType;
+use futures::{Stream, StreamExt};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::pin::pin;
+use thiserror::Error;
+
+/// Errors that can occur when ingesting reports from a message queue.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum IngestError {
+    /// The message source failed
+    #[error("Reading from the report source failed: {0}")]
+    Source(String),
+
+    /// The store rejected a batch
+    #[error("Storing a report batch failed: {0}")]
+    Store(String),
+}
+
+/// An encoded report read from a partitioned log, such as a Kafka topic.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IngestRecord<B> {
+    /// Partition the record was read from
+    pub partition: u32,
+
+    /// Offset of the record in its partition
+    pub offset: u64,
+
+    /// A `ReportProto` message, as produced by `ReportVector::encode_wire_report`
+    pub payload: B,
+}
+
+/// Number the payloads of a plain byte stream as consecutive records of one
+/// partition, starting at `first_offset`.
+pub fn records_from_bytes<S: Stream>(
+    partition: u32,
+    first_offset: u64,
+    payloads: S,
+) -> impl Stream<Item = Result<IngestRecord<S::Item>, Infallible>> {
+    payloads.enumerate().map(move |(index, payload)| {
+        Ok(IngestRecord {
+            partition,
+            offset: first_offset + index as u64,
+            payload,
+        })
+    })
+}
+
+/// Read the records of a Kafka consumer subscribed to a single topic.
+///
+/// The consumer must not commit offsets to Kafka itself; instead assign it the
+/// partitions at the offsets in `ReportStore::committed_offsets`.
+#[cfg(feature = "kafka")]
+pub fn kafka_records<C>(
+    consumer: &rdkafka::consumer::StreamConsumer<C>,
+) -> impl Stream<Item = Result<IngestRecord<Vec<u8>>, rdkafka::error::KafkaError>> + '_
+where
+    C: rdkafka::consumer::ConsumerContext + 'static,
+{
+    use rdkafka::Message;
+
+    consumer.stream().map(|message| {
+        message.map(|message| IngestRecord {
+            partition: message.partition() as u32,
+            offset: message.offset() as u64,
+            payload: message.payload().unwrap_or_default().to_vec(),
+        })
+    })
+}
+
+/// Batching parameters of a `ReportIngestor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IngestConfig {
+    /// Number of records handed off to the store at once
+    pub batch_size: usize,
+
+    /// Largest payload accepted, in bytes
+    pub max_record_bytes: usize,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 1024,
+            max_record_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Counts of the records an `ReportIngestor` has consumed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    /// Valid reports handed off to the store
+    pub accepted: usize,
+
+    /// Records that were too large or did not decode to a valid report
+    pub rejected: usize,
+
+    /// Redelivered records at offsets that were already consumed
+    pub duplicates: usize,
+
+    /// Batches handed off to the store
+    pub batches: usize,
+}
+
+/// A `ReportIngestor` consumes encoded reports from a partitioned log, validates
+/// them against the attribute types, and hands them off to a `ReportStore` in
+/// batches, from which the server takes each epoch for shuffling and aggregation.
+///
+/// Every batch is ingested together with the offsets it was read up to, and
+/// records below the store's committed offsets are skipped as redeliveries. The
+/// handoff is therefore exactly once: after a crash or an error, create a new
+/// ingestor and resume the log from `ReportStore::committed_offsets`. Invalid
+/// records are counted and skipped so they cannot block their partition. Flush
+/// before moving the store to a new epoch so the pending reports are tagged with
+/// the epoch they arrived in.
+pub struct ReportIngestor<const U32_SIZE: usize> {
+    config: IngestConfig,
+    pending: ReportVector<U32_SIZE>,
+    pending_offsets: BTreeMap<u32, u64>,
+    stats: IngestStats,
+}
+
+impl<const U32_SIZE: usize> ReportIngestor<U32_SIZE> {
+    /// Create an ingestor for reports with the given attribute types. Panics if
+    /// the batch size is zero.
+    pub fn new(attr_types: &[AttributeType], config: IngestConfig) -> Self {
+        assert!(
+            config.batch_size > 0,
+            "ReportIngestor::new: The batch size must be positive."
+        );
+        Self {
+            config,
+            pending: ReportVector::new(attr_types),
+            pending_offsets: BTreeMap::new(),
+            stats: IngestStats::default(),
+        }
+    }
+
+    /// Return the counts of the records consumed so far.
+    pub fn stats(&self) -> IngestStats {
+        self.stats
+    }
+
+    /// Return the number of valid reports not yet handed off.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Return the offset of the next new record of a partition.
+    fn next_offset(&self, partition: u32, store: &ReportStore<U32_SIZE>) -> u64 {
+        self.pending_offsets
+            .get(&partition)
+            .copied()
+            .or_else(|| store.committed_offset(partition))
+            .unwrap_or(0)
+    }
+
+    /// Consume one record, handing off the batch once it is full. Returns the
+    /// epoch of the batch if one was handed off.
+    pub fn push<B: AsRef<[u8]>>(
+        &mut self,
+        record: IngestRecord<B>,
+        store: &mut ReportStore<U32_SIZE>,
+    ) -> Result<Option<u64>, IngestError> {
+        if record.offset < self.next_offset(record.partition, store) {
+            self.stats.duplicates += 1;
+            return Ok(None);
+        }
+
+        let payload = record.payload.as_ref();
+        let valid = payload.len() <= self.config.max_record_bytes
+            && self.pending.push_wire_report(payload).is_ok();
+        if valid {
+            self.stats.accepted += 1;
+        } else {
+            self.stats.rejected += 1;
+            log::debug!(
+                "Rejected report at offset {} of partition {}",
+                record.offset,
+                record.partition
+            );
+        }
+        self.pending_offsets
+            .insert(record.partition, record.offset + 1);
+
+        if self.pending.len() >= self.config.batch_size {
+            self.flush(store)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Hand off the pending reports and commit their offsets, even if every
+    /// pending record was invalid. Returns the epoch of the batch, or `None` if
+    /// nothing was consumed since the last handoff.
+    pub fn flush(&mut self, store: &mut ReportStore<U32_SIZE>) -> Result<Option<u64>, IngestError> {
+        if self.pending_offsets.is_empty() {
+            return Ok(None);
+        }
+
+        let reports = std::mem::replace(&mut self.pending, ReportVector::new(store.attr_types()));
+        let offsets = std::mem::take(&mut self.pending_offsets);
+        let epoch = store
+            .ingest_with_offsets(reports, &offsets)
+            .map_err(IngestError::Store)?;
+        self.stats.batches += 1;
+        Ok(Some(epoch))
+    }
+
+    /// Consume a stream of records until it ends, then flush. Returns the counts
+    /// of all records consumed by this ingestor.
+    pub async fn run<S, B, E>(
+        &mut self,
+        records: S,
+        store: &mut ReportStore<U32_SIZE>,
+    ) -> Result<IngestStats, IngestError>
+    where
+        S: Stream<Item = Result<IngestRecord<B>, E>>,
+        B: AsRef<[u8]>,
+        E: Display,
+    {
+        let mut records = pin!(records);
+        while let Some(record) = records.next().await {
+            let record = record.map_err(|e| IngestError::Source(e.to_string()))?;
+            self.push(record, store)?;
+        }
+
+        self.flush(store)?;
+        Ok(self.stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::store::RetentionPolicy;
+    use futures::executor::block_on;
+    use futures::stream;
+    use rand::rngs::OsRng;
+    use AttributeType::*;
+
+    #[test]
+    fn test_exactly_once_handoff() {
+        let attr_types = [C4, N5(31)];
+        let reports = ReportVector::<1>::random(&attr_types, &mut OsRng, 5);
+        let mut payloads = reports
+            .iter()
+            .map(|report| reports.encode_wire_report(report))
+            .collect::<Vec<_>>();
+        payloads.insert(2, b"not a report".to_vec());
+
+        let mut store = ReportStore::<1>::new(&attr_types, RetentionPolicy::KeepAll, 3);
+        let config = IngestConfig {
+            batch_size: 2,
+            ..IngestConfig::default()
+        };
+
+        // The first four records are redelivered after they were consumed.
+        let record = |offset: usize| {
+            Ok::<_, Infallible>(IngestRecord {
+                partition: 0,
+                offset: offset as u64,
+                payload: payloads[offset].clone(),
+            })
+        };
+        let records = (0..4).chain(0..6).map(record).collect::<Vec<_>>();
+        let mut ingestor = ReportIngestor::new(&attr_types, config);
+        let stats = block_on(ingestor.run(stream::iter(records), &mut store)).unwrap();
+        assert_eq!(
+            stats,
+            IngestStats {
+                accepted: 5,
+                rejected: 1,
+                duplicates: 4,
+                batches: 3,
+            }
+        );
+        assert_eq!(store.get_epoch(3), Some(&reports));
+        assert_eq!(store.committed_offset(0), Some(6));
+
+        // A restarted consumer replaying the partition adds nothing.
+        let mut restarted = ReportIngestor::new(&attr_types, config);
+        let stats =
+            block_on(restarted.run(records_from_bytes(0, 0, stream::iter(payloads)), &mut store))
+                .unwrap();
+        assert_eq!(stats.duplicates, 6);
+        assert_eq!(store.len(), 5);
+
+        let mut offsets = BTreeMap::new();
+        offsets.insert(0, 1);
+        assert!(store
+            .ingest_with_offsets(ReportVector::new(&attr_types), &offsets)
+            .is_err());
+    }
+}
@@ -5,3 +5,11 @@ pub(crate) mod attr;
 pub(crate) mod report;
 pub(crate) mod report_handler;
 pub(crate) mod report_vector;
+pub mod compress;
+pub mod ingest;
+pub mod nullifier;
+pub mod seal;
+pub mod sign;
+pub mod store;
+pub mod stream;
+pub mod wire;
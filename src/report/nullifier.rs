@@ -0,0 +1,221 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::seal::SealedReport;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+
+/// Domain separator for the nullifier PRF.
+const NULLIFIER_DOMAIN: &[u8] = b"doppio-report-nullifier-v1";
+
+/// Size of a device key and of a nullifier.
+const NULLIFIER_SIZE: usize = 32;
+
+/// A `DeviceKey` is a secret key that stays on the client. It is only used to derive
+/// nullifiers, so it never leaves the device.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DeviceKey([u8; NULLIFIER_SIZE]);
+
+/// A `Nullifier` is a pseudorandom tag derived from a `DeviceKey` and an epoch. It is
+/// the same for every report a device submits in one epoch, but nullifiers from
+/// different devices or different epochs cannot be linked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Nullifier([u8; NULLIFIER_SIZE]);
+
+/// A `NullifiedReport` is a `SealedReport` with the epoch and nullifier it was
+/// submitted under.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NullifiedReport {
+    epoch: u64,
+    nullifier: Nullifier,
+    sealed_report: SealedReport,
+}
+
+impl DeviceKey {
+    /// Create a new random `DeviceKey`.
+    pub fn random<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut key = [0u8; NULLIFIER_SIZE];
+        rng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Create a `DeviceKey` from stored bytes.
+    pub fn from_bytes(bytes: [u8; NULLIFIER_SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    /// Return the key bytes for storage on the device.
+    pub fn as_bytes(&self) -> &[u8; NULLIFIER_SIZE] {
+        &self.0
+    }
+
+    /// Derive the nullifier for the given epoch.
+    pub fn nullifier(&self, epoch: u64) -> Nullifier {
+        let digest = Sha256::new()
+            .chain_update(NULLIFIER_DOMAIN)
+            .chain_update(self.0)
+            .chain_update(epoch.to_le_bytes())
+            .finalize();
+        Nullifier(digest.into())
+    }
+}
+
+impl std::fmt::Debug for DeviceKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DeviceKey(..)")
+    }
+}
+
+impl Nullifier {
+    /// Return the nullifier bytes.
+    pub fn as_bytes(&self) -> &[u8; NULLIFIER_SIZE] {
+        &self.0
+    }
+}
+
+impl SealedReport {
+    /// Attach the device's nullifier for the given epoch to the sealed report.
+    pub fn with_nullifier(self, device_key: &DeviceKey, epoch: u64) -> NullifiedReport {
+        NullifiedReport {
+            epoch,
+            nullifier: device_key.nullifier(epoch),
+            sealed_report: self,
+        }
+    }
+}
+
+impl NullifiedReport {
+    /// Return the epoch the report was submitted in.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Return the nullifier.
+    pub fn nullifier(&self) -> Nullifier {
+        self.nullifier
+    }
+
+    /// Return the sealed report.
+    pub fn sealed_report(&self) -> &SealedReport {
+        &self.sealed_report
+    }
+}
+
+/// A `NullifierSet` holds the nullifiers the server has seen, per epoch, so a
+/// device that submits more than once in an epoch is counted once.
+#[derive(Clone, Debug, Default)]
+pub struct NullifierSet {
+    epochs: BTreeMap<u64, HashSet<Nullifier>>,
+    duplicates: usize,
+}
+
+impl NullifierSet {
+    /// Create a new empty `NullifierSet`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a nullifier. Return `false` if it was already seen in this epoch.
+    pub fn insert(&mut self, epoch: u64, nullifier: Nullifier) -> bool {
+        let is_new = self.epochs.entry(epoch).or_default().insert(nullifier);
+        if !is_new {
+            self.duplicates += 1;
+        }
+        is_new
+    }
+
+    /// Return whether a nullifier was already seen in this epoch.
+    pub fn contains(&self, epoch: u64, nullifier: &Nullifier) -> bool {
+        self.epochs
+            .get(&epoch)
+            .map_or(false, |nullifiers| nullifiers.contains(nullifier))
+    }
+
+    /// Return the number of distinct nullifiers seen in an epoch.
+    pub fn epoch_len(&self, epoch: u64) -> usize {
+        self.epochs.get(&epoch).map_or(0, HashSet::len)
+    }
+
+    /// Return the number of duplicate submissions rejected so far.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Keep the first submission for every nullifier and drop the rest. The
+    /// nullifiers are stripped, ready for `unseal_and_shuffle`.
+    pub fn filter(&mut self, nullified_reports: Vec<NullifiedReport>) -> Vec<SealedReport> {
+        nullified_reports
+            .into_iter()
+            .filter(|nullified_report| {
+                self.insert(nullified_report.epoch, nullified_report.nullifier)
+            })
+            .map(|nullified_report| nullified_report.sealed_report)
+            .collect()
+    }
+
+    /// Forget the nullifiers of all epochs before the given one. Returns the number
+    /// of epochs dropped.
+    pub fn drop_epochs_before(&mut self, epoch: u64) -> usize {
+        let kept = self.epochs.split_off(&epoch);
+        let dropped = self.epochs.len();
+        self.epochs = kept;
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_nullifier() {
+        let device_key = DeviceKey::random(&mut OsRng);
+        let other_key = DeviceKey::random(&mut OsRng);
+
+        assert_eq!(device_key.nullifier(7), device_key.nullifier(7));
+        assert_ne!(device_key.nullifier(7), device_key.nullifier(8));
+        assert_ne!(device_key.nullifier(7), other_key.nullifier(7));
+        assert_eq!(
+            DeviceKey::from_bytes(*device_key.as_bytes()).nullifier(7),
+            device_key.nullifier(7)
+        );
+    }
+
+    #[test]
+    fn test_nullifier_set_filter() {
+        let device_keys = (0..3)
+            .map(|_| DeviceKey::random(&mut OsRng))
+            .collect::<Vec<_>>();
+        let submit = |device_index: usize, epoch: u64, byte: u8| {
+            SealedReport::from_bytes(vec![byte; 40])
+                .with_nullifier(&device_keys[device_index], epoch)
+        };
+
+        // Device 0 resubmits in epoch 1 and submits again in epoch 2.
+        let mut nullifier_set = NullifierSet::new();
+        let accepted = nullifier_set.filter(vec![
+            submit(0, 1, 1),
+            submit(1, 1, 2),
+            submit(0, 1, 3),
+            submit(2, 1, 4),
+            submit(0, 2, 5),
+        ]);
+        assert_eq!(
+            accepted
+                .iter()
+                .map(|sealed_report| sealed_report.as_bytes()[0])
+                .collect::<Vec<_>>(),
+            vec![1, 2, 4, 5]
+        );
+        assert_eq!(nullifier_set.duplicates(), 1);
+        assert_eq!(nullifier_set.epoch_len(1), 3);
+        assert!(nullifier_set.contains(2, &device_keys[0].nullifier(2)));
+
+        assert_eq!(nullifier_set.drop_epochs_before(2), 1);
+        assert_eq!(nullifier_set.epoch_len(1), 0);
+        assert_eq!(nullifier_set.epoch_len(2), 1);
+    }
+}
@@ -10,6 +10,7 @@ use serde::{Deserialize, Serialize};
 use std::mem::size_of;
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "ReportHandlerData<U32_SIZE>")]
 pub struct ReportHandler<const U32_SIZE: usize> {
     attr_types: Vec<AttributeType>,
     attr_sizes: Vec<usize>,
@@ -19,6 +20,47 @@ pub struct ReportHandler<const U32_SIZE: usize> {
     report_filter_cat_only: Report<U32_SIZE>,
 }
 
+/// The serialized fields of a `ReportHandler`, validated before they are accepted.
+#[derive(Deserialize)]
+struct ReportHandlerData<const U32_SIZE: usize> {
+    attr_types: Vec<AttributeType>,
+    attr_sizes: Vec<usize>,
+    attr_offsets: Vec<usize>,
+    attr_filters: Vec<Report<U32_SIZE>>,
+    report_filter: Report<U32_SIZE>,
+    report_filter_cat_only: Report<U32_SIZE>,
+}
+
+impl<const U32_SIZE: usize> TryFrom<ReportHandlerData<U32_SIZE>> for ReportHandler<U32_SIZE> {
+    type Error = String;
+
+    fn try_from(data: ReportHandlerData<U32_SIZE>) -> Result<Self, Self::Error> {
+        // Deserialized data is untrusted: rebuild the `ReportHandler` from the attribute
+        // types, which must be valid, and check that the derived fields agree.
+        let attr_sizes = data
+            .attr_types
+            .iter()
+            .map(|attr_type| attr_type.get_size())
+            .collect::<Vec<_>>();
+        if !Self::is_valid_attr_types(&data.attr_types) || !Self::is_valid_attr_sizes(&attr_sizes)
+        {
+            return Err("ReportHandler: Invalid attribute types.".to_string());
+        }
+
+        let report_handler = Self::new(&data.attr_types);
+        if report_handler.attr_sizes != data.attr_sizes
+            || report_handler.attr_offsets != data.attr_offsets
+            || report_handler.attr_filters != data.attr_filters
+            || report_handler.report_filter != data.report_filter
+            || report_handler.report_filter_cat_only != data.report_filter_cat_only
+        {
+            return Err("ReportHandler: Inconsistent attribute layout.".to_string());
+        }
+
+        Ok(report_handler)
+    }
+}
+
 impl<const U32_SIZE: usize> ReportHandler<U32_SIZE> {
     fn compute_attr_start_positions(attr_sizes: &[usize]) -> Vec<usize> {
         // This function assumes the inputs are already validated.
@@ -138,6 +180,11 @@ impl<const U32_SIZE: usize> ReportHandler<U32_SIZE> {
         &self.attr_sizes
     }
 
+    /// Return the number of bits the attributes occupy in a `Report`.
+    pub(crate) fn get_report_bit_size(&self) -> usize {
+        self.attr_sizes.iter().sum()
+    }
+
     /// Return the attribute types.
     pub(crate) fn get_attr_types(&self) -> &[AttributeType] {
         &self.attr_types
@@ -440,7 +487,39 @@ impl<const U32_SIZE: usize> ReportHandler<U32_SIZE> {
         random_report
     }
 
-    /// Remove an attribute from the `ReportHandler`.
+    /// Remove an attribute's bits from a given `Report`. The bits of all later attributes
+    /// are shifted down to close the gap, so the result matches the layout of this
+    /// `ReportHandler` after `remove_attr` is called with the same index.
+    pub(crate) fn remove_attr_bits(&self, report: &mut Report<U32_SIZE>, attr_index: usize) {
+        self.panic_if_invalid_attr_index(attr_index);
+
+        let attr_offset = self.attr_offsets[attr_index];
+        let attr_end = attr_offset + self.attr_sizes[attr_index];
+
+        // Keep the bits below the attribute and move the bits above it down.
+        let low_filter = !(!Report::<U32_SIZE>::new() << attr_offset);
+        *report = (*report & low_filter) | ((*report >> attr_end) << attr_offset);
+    }
+
+    /// Return whether the attribute in a given `Report` has a given value. This compares
+    /// the attribute bits in place instead of extracting the attribute value.
+    pub(crate) fn has_attr_value(
+        &self,
+        report: &Report<U32_SIZE>,
+        attr_index: usize,
+        attr_value: AttrValueType,
+    ) -> bool {
+        self.panic_if_invalid_attr_index(attr_index);
+
+        let attr_offset = self.attr_offsets[attr_index];
+        let attr_filter = self.attr_filters[attr_index];
+        let attr_report = Report::<U32_SIZE>::from_u32(attr_value) << attr_offset;
+
+        (*report & attr_filter) == attr_report
+    }
+
+    /// Remove an attribute from the `ReportHandler`. The remaining attributes are
+    /// packed contiguously, as if the `ReportHandler` was created without it.
     pub(crate) fn remove_attr(&mut self, attr_index: usize) {
         self.panic_if_invalid_attr_index(attr_index);
 
@@ -450,11 +529,14 @@ impl<const U32_SIZE: usize> ReportHandler<U32_SIZE> {
         // Remove the attribute size.
         self.attr_sizes.remove(attr_index);
 
-        // Remove the attribute offset.
-        self.attr_offsets.remove(attr_index);
-
-        // Remove the attribute filter.
-        self.attr_filters.remove(attr_index);
+        // Recompute the attribute offsets and filters.
+        self.attr_offsets = Self::compute_attr_start_positions(&self.attr_sizes);
+        self.attr_filters = self
+            .attr_sizes
+            .iter()
+            .zip(&self.attr_offsets)
+            .map(|(attr_size, start_pos)| Self::make_attr_filter(*attr_size, *start_pos))
+            .collect::<Vec<_>>();
 
         // Update the report filter.
         self.report_filter = Self::make_report_filter(&self.attr_filters);
@@ -757,6 +839,48 @@ mod tests {
         assert_eq!(report, Report::<1>::from_u32(0b11010_1101_110_11));
     }
 
+    #[test]
+    fn test_remove_attr() {
+        let attr_types = vec![
+            AttributeType::C2,
+            AttributeType::C3,
+            AttributeType::C4,
+            AttributeType::C5,
+        ];
+        let mut report_handler = ReportHandler::<1>::new(&attr_types);
+
+        let mut report = Report::<1>::from_u32(0b01100_1011_011_10);
+        assert!(report_handler.has_attr_value(&report, 1, 0b011));
+        assert!(!report_handler.has_attr_value(&report, 1, 0b111));
+
+        report_handler.remove_attr_bits(&mut report, 1);
+        report_handler.remove_attr(1);
+        assert_eq!(report, Report::<1>::from_u32(0b01100_1011_10));
+        assert_eq!(report_handler.get_report_bit_size(), 11);
+        assert_eq!(
+            report_handler,
+            ReportHandler::<1>::new(&[AttributeType::C2, AttributeType::C4, AttributeType::C5])
+        );
+        assert_eq!(report_handler.get_attr(&report, 2), 0b01100);
+
+        // Attributes crossing a u32 boundary are shifted down as well.
+        let attr_types = vec![AttributeType::C10, AttributeType::C20, AttributeType::C30];
+        let mut report_handler = ReportHandler::<2>::new(&attr_types);
+
+        let mut report = Report::<2>::from_u32_slice(&[
+            0b10010010010010010010010101010101,
+            0b1101101101101101101101101101,
+        ]);
+        report_handler.remove_attr_bits(&mut report, 0);
+        report_handler.remove_attr(0);
+        assert_eq!(report_handler.get_attr(&report, 0), 0b01001001001001001001);
+        assert_eq!(
+            report_handler.get_attr(&report, 1),
+            0b110110110110110110110110110110
+        );
+        assert!(report_handler.is_valid_report(&report));
+    }
+
     #[test]
     fn test_serialize_deserialize() {
         let attr_types = vec![
@@ -778,6 +902,18 @@ mod tests {
             report_handler.attr_offsets,
             report_handler_copy.attr_offsets
         );
+
+        // A tampered layout is rejected instead of causing panics later.
+        let mut tampered = report_handler.clone();
+        tampered.attr_offsets[1] = 60;
+        let encoded = bincode::serialize(&tampered).unwrap();
+        assert!(bincode::deserialize::<ReportHandler<2>>(&encoded).is_err());
+
+        // So are attribute types that do not fit into the report.
+        let mut oversized = ReportHandler::<2>::new(&[AttributeType::C32, AttributeType::C32]);
+        oversized.attr_types.push(AttributeType::C8);
+        let encoded = bincode::serialize(&oversized).unwrap();
+        assert!(bincode::deserialize::<ReportHandler<2>>(&encoded).is_err());
     }
 
     #[test]
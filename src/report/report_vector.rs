@@ -8,15 +8,43 @@ use crate::schema::AttributeType;
 use rand::seq::SliceRandom;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::cmp::max;
+use std::cmp::{max, min};
 
 /// A `ReportVector` represents a collection of `Report`s.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "ReportVectorData<U32_SIZE>")]
 pub struct ReportVector<const U32_SIZE: usize> {
     report_handler: ReportHandler<U32_SIZE>,
     reports: Vec<Report<U32_SIZE>>,
 }
 
+/// The serialized fields of a `ReportVector`, validated before they are accepted.
+#[derive(Deserialize)]
+struct ReportVectorData<const U32_SIZE: usize> {
+    report_handler: ReportHandler<U32_SIZE>,
+    reports: Vec<Report<U32_SIZE>>,
+}
+
+impl<const U32_SIZE: usize> TryFrom<ReportVectorData<U32_SIZE>> for ReportVector<U32_SIZE> {
+    type Error = String;
+
+    fn try_from(data: ReportVectorData<U32_SIZE>) -> Result<Self, Self::Error> {
+        // Many functions panic on invalid `Report`s, so reject them when deserializing.
+        if let Some(index) = data
+            .reports
+            .iter()
+            .position(|report| !data.report_handler.is_valid_report(report))
+        {
+            return Err(format!("ReportVector: The report at index {} is invalid.", index));
+        }
+
+        Ok(Self {
+            report_handler: data.report_handler,
+            reports: data.reports,
+        })
+    }
+}
+
 pub struct ReportVectorIterator<'a, const U32_SIZE: usize> {
     inner: std::slice::Iter<'a, Report<U32_SIZE>>,
 }
@@ -227,30 +255,35 @@ impl<const U32_SIZE: usize> ReportVector<U32_SIZE> {
         old_len - new_len
     }
 
-    /// Remove an attribute from every `Report` in the `ReportVector`.
+    /// Remove an attribute from every `Report` in the `ReportVector`. The bits of the
+    /// remaining attributes are shifted down, so the `Report`s stay compactly packed.
     pub fn remove_attr(&mut self, attr_index: usize) {
-        // First set all of the attribute values to zero.
+        // First cut the attribute bits out of every report.
         self.reports
             .iter_mut()
-            .for_each(|report| self.report_handler.clear_attr(report, attr_index));
+            .for_each(|report| self.report_handler.remove_attr_bits(report, attr_index));
 
         // Next, update the `ReportHandler` to reflect the removal of the attribute.
         self.report_handler.remove_attr(attr_index);
     }
 
-    /// Split off all reports with the given categorical attribute value.
-    pub fn split_at(&mut self, attr_index: usize, attr_value: AttrValueType) -> Self {
+    /// Split off all reports with the given categorical attribute value. The attribute
+    /// is removed from the split-off reports. Reports are matched by comparing the
+    /// attribute bits in place, without decoding them.
+    pub fn split_at_attr(&mut self, attr_index: usize, attr_value: AttrValueType) -> Self {
         let attr_type = self.report_handler.get_attr_types()[attr_index];
         assert!(
             attr_type.is_categorical(),
-            "prune: The attribute must be categorical."
+            "split_at_attr: The attribute must be categorical."
         );
 
         // Separate all reports with the given attribute value.
         let mut split_reports = Vec::<Report<U32_SIZE>>::new();
         self.reports.retain(|report| {
-            let report_attr_value = self.report_handler.get_attr(report, attr_index);
-            if report_attr_value == attr_value {
+            if self
+                .report_handler
+                .has_attr_value(report, attr_index, attr_value)
+            {
                 split_reports.push(*report);
                 false
             } else {
@@ -270,12 +303,136 @@ impl<const U32_SIZE: usize> ReportVector<U32_SIZE> {
         split_report_vector
     }
 
+    /// Pack the `Report`s into a contiguous bit stream. Each `Report` takes up exactly
+    /// as many bits as its attributes, instead of a full `U32_SIZE` many u32s.
+    pub fn pack(&self) -> Vec<u32> {
+        let report_bit_size = self.report_handler.get_report_bit_size();
+        let mut packed = vec![0u32; (self.reports.len() * report_bit_size + 31) / 32];
+
+        let mut bit_pos = 0;
+        for report in &self.reports {
+            write_report_bits(&mut packed, bit_pos, report, report_bit_size);
+            bit_pos += report_bit_size;
+        }
+
+        packed
+    }
+
+    /// Unpack `count` many `Report`s with the given attribute types from a bit stream
+    /// created by `pack`. Panics if the bit stream is too short or a `Report` is invalid.
+    pub fn unpack(attr_types: &[AttributeType], packed: &[u32], count: usize) -> Self {
+        let mut report_vector = Self::new(attr_types);
+        let report_bit_size = report_vector.report_handler.get_report_bit_size();
+        assert!(
+            packed.len() * 32 >= count * report_bit_size,
+            "unpack: The packed data is too short."
+        );
+
+        let mut bit_pos = 0;
+        for _ in 0..count {
+            let report = read_report_bits(packed, bit_pos, report_bit_size);
+            report_vector.push(report);
+            bit_pos += report_bit_size;
+        }
+
+        report_vector
+    }
+
+    /// Unpack like `unpack`, but return `None` instead of panicking if the bit stream is
+    /// too short or a `Report` is invalid.
+    pub fn try_unpack(attr_types: &[AttributeType], packed: &[u32], count: usize) -> Option<Self> {
+        let mut report_vector = Self::new(attr_types);
+        let report_bit_size = report_vector.report_handler.get_report_bit_size();
+        if packed.len() * 32 < count.checked_mul(report_bit_size)? {
+            return None;
+        }
+
+        let mut bit_pos = 0;
+        for _ in 0..count {
+            let report = read_report_bits(packed, bit_pos, report_bit_size);
+            if !report_vector.report_handler.is_valid_report(&report) {
+                return None;
+            }
+            report_vector.reports.push(report);
+            bit_pos += report_bit_size;
+        }
+
+        Some(report_vector)
+    }
+
     /// Permute the `Report`s in this `ReportVector`.
     pub(crate) fn permute<R: Rng + ?Sized>(&mut self, rng: &mut R) {
         self.reports.as_mut_slice().shuffle(rng);
     }
 }
 
+/// Write the lowest `bit_size` bits of a `Report` to a bit stream at a given bit position.
+pub(super) fn write_report_bits<const U32_SIZE: usize>(
+    packed: &mut [u32],
+    bit_pos: usize,
+    report: &Report<U32_SIZE>,
+    bit_size: usize,
+) {
+    for (word_index, &word) in report.as_u32_slice().iter().enumerate() {
+        let word_bit_size = min(bit_size.saturating_sub(word_index * 32), 32);
+        if word_bit_size == 0 {
+            break;
+        }
+
+        write_bits(packed, bit_pos + word_index * 32, word, word_bit_size);
+    }
+}
+
+/// Read a `Report` of `bit_size` bits from a bit stream at a given bit position.
+pub(super) fn read_report_bits<const U32_SIZE: usize>(
+    packed: &[u32],
+    bit_pos: usize,
+    bit_size: usize,
+) -> Report<U32_SIZE> {
+    let words = (0..(bit_size + 31) / 32)
+        .map(|word_index| {
+            let word_bit_size = min(bit_size - word_index * 32, 32);
+            read_bits(packed, bit_pos + word_index * 32, word_bit_size)
+        })
+        .collect::<Vec<_>>();
+
+    Report::<U32_SIZE>::from_u32_slice(&words)
+}
+
+/// Write the lowest `bit_size` bits of a u32 to a bit stream at a given bit position.
+fn write_bits(packed: &mut [u32], bit_pos: usize, value: u32, bit_size: usize) {
+    let value = value & low_bits(bit_size);
+    let word_index = bit_pos / 32;
+    let shift = bit_pos % 32;
+
+    packed[word_index] |= value << shift;
+    if shift + bit_size > 32 {
+        packed[word_index + 1] |= value >> (32 - shift);
+    }
+}
+
+/// Read `bit_size` bits from a bit stream at a given bit position.
+fn read_bits(packed: &[u32], bit_pos: usize, bit_size: usize) -> u32 {
+    let word_index = bit_pos / 32;
+    let shift = bit_pos % 32;
+
+    let mut value = packed[word_index] >> shift;
+    if shift + bit_size > 32 {
+        value |= packed[word_index + 1] << (32 - shift);
+    }
+
+    value & low_bits(bit_size)
+}
+
+/// Return a u32 with the lowest `bit_size` bits set.
+fn low_bits(bit_size: usize) -> u32 {
+    if bit_size >= 32 {
+        !0
+    } else {
+        (1 << bit_size) - 1
+    }
+}
+
 pub mod test_distr {
     use super::*;
     use crate::random::zipf::ZipfDistribution;
@@ -489,6 +646,19 @@ mod tests {
         assert_eq!(report_vector_copy, report_vector);
     }
 
+    #[test]
+    fn test_deserialize_rejects_invalid_reports() {
+        let report_vector = ReportVector::<1> {
+            report_handler: ReportHandler::new(&[C2, N3(6)]),
+            reports: vec![Report::from_u32(!0)],
+        };
+        let encoded = bincode::serialize(&report_vector).unwrap();
+        assert!(bincode::deserialize::<ReportVector<1>>(&encoded).is_err());
+
+        // Truncated input is an error, not a panic.
+        assert!(bincode::deserialize::<ReportVector<1>>(&encoded[..encoded.len() / 2]).is_err());
+    }
+
     #[test]
     fn test_share_reveal() {
         use AttributeType::*;
@@ -540,4 +710,95 @@ mod tests {
         report_vector.prune(3, 100);
         assert_eq!(report_vector.len(), 0);
     }
+
+    #[test]
+    fn test_pack_unpack() {
+        let mut rng = OsRng;
+
+        // 65 bits per report, so the reports straddle u32 boundaries.
+        let attr_types = [C10, C20, N20(65537), C15];
+        let report_vector = ReportVector::<3>::random(&attr_types, &mut rng, 100);
+
+        let packed = report_vector.pack();
+        assert_eq!(packed.len(), (100 * 65 + 31) / 32);
+
+        let report_vector_copy = ReportVector::<3>::unpack(&attr_types, &packed, 100);
+        assert_eq!(report_vector_copy, report_vector);
+
+        // An empty vector packs into nothing.
+        let report_vector = ReportVector::<1>::new(&[C2, C3]);
+        assert!(report_vector.pack().is_empty());
+        assert_eq!(ReportVector::<1>::unpack(&[C2, C3], &[], 0), report_vector);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unpack_panic() {
+        ReportVector::<1>::unpack(&[C10, C20], &[0], 2);
+    }
+
+    #[test]
+    fn test_remove_attr_split_at_attr() {
+        let mut rng = OsRng;
+        let mut report_vector =
+            ReportVector::<2>::random(&[C4, N20(65537), C10, C20], &mut rng, 200);
+        let report_vector_copy = report_vector.clone();
+
+        // Split at the first attribute.
+        let split_report_vector = report_vector.split_at_attr(0, 5);
+        assert_eq!(
+            split_report_vector.len() + report_vector.len(),
+            report_vector_copy.len()
+        );
+        assert!(report_vector
+            .get_attr_iter(0)
+            .all(|attr_value| attr_value != 5));
+
+        // The split-off reports are packed as if created without the attribute.
+        assert_eq!(
+            split_report_vector.report_handler(),
+            ReportVector::<2>::new(&[N20(65537), C10, C20]).report_handler()
+        );
+        let expected = report_vector_copy
+            .iter()
+            .filter(|report| report_vector_copy.report_handler().get_attr(report, 0) == 5)
+            .map(|report| {
+                (1..4)
+                    .map(|attr_index| {
+                        report_vector_copy
+                            .report_handler()
+                            .get_attr(report, attr_index)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let split = split_report_vector
+            .iter()
+            .map(|report| {
+                (0..3)
+                    .map(|attr_index| {
+                        split_report_vector
+                            .report_handler()
+                            .get_attr(report, attr_index)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(split, expected);
+
+        // Removing an attribute keeps the others intact.
+        let mut report_vector = report_vector_copy.clone();
+        report_vector.remove_attr(2);
+        assert_eq!(report_vector.report_handler().get_report_bit_size(), 44);
+        for attr_index in 0..3 {
+            let original_index = if attr_index < 2 { attr_index } else { 3 };
+            assert!(report_vector
+                .get_attr_iter(attr_index)
+                .eq(report_vector_copy.get_attr_iter(original_index)));
+        }
+        assert_eq!(
+            ReportVector::<2>::unpack(&[C4, N20(65537), C20], &report_vector.pack(), 200),
+            report_vector
+        );
+    }
 }
@@ -0,0 +1,321 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::report::Report;
+use super::report_vector::ReportVector;
+use crate::schema::AttributeType;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::seq::SliceRandom;
+use rand::{CryptoRng, Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::mem::size_of;
+use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// Domain separator for the layer key derivation.
+const SEAL_DOMAIN: &[u8] = b"doppio-report-seal-v1";
+
+/// Size of the ephemeral public key that starts every layer.
+const PUBLIC_KEY_SIZE: usize = 32;
+
+/// Plaintext tag of a layer that wraps another layer.
+const LAYER_TAG: u8 = 0;
+
+/// Plaintext tag of the innermost layer, which wraps the `Report` itself.
+const REPORT_TAG: u8 = 1;
+
+/// Errors that can occur when unsealing a `SealedReport`.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SealError {
+    /// The bytes are too short or the plaintext has an unexpected format
+    #[error("The sealed report is malformed")]
+    Malformed,
+
+    /// The layer was not sealed to this key or was tampered with
+    #[error("The sealed report layer could not be decrypted")]
+    Decryption,
+
+    /// An intermediate hop reached the plaintext `Report`
+    #[error("The report was unsealed before its last hop")]
+    Premature,
+
+    /// The last hop expected the plaintext `Report` but found another layer
+    #[error("The report has layers left for further hops")]
+    LayersRemaining,
+
+    /// The unsealed `Report` does not match the attribute types
+    #[error("The unsealed report is invalid")]
+    InvalidReport,
+}
+
+/// A `SealedReport` holds a `Report` encrypted in layers, one for every hop on the way
+/// to the server that aggregates it. Each hop removes exactly one layer and learns
+/// nothing but the next layer, so only the last hop sees the `Report`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedReport {
+    bytes: Vec<u8>,
+}
+
+/// The result of removing one layer from a `SealedReport`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Unsealed<const U32_SIZE: usize> {
+    /// The layer for the next hop
+    Layer(SealedReport),
+
+    /// The plaintext `Report`; this was the last hop
+    Report(Report<U32_SIZE>),
+}
+
+impl<const U32_SIZE: usize> Report<U32_SIZE> {
+    /// Encrypt the `Report` in layers, one for every public key. The first key belongs
+    /// to the first hop, so its layer is the outermost one. Panics if there are no keys.
+    pub fn seal<R: RngCore + CryptoRng>(&self, layers: &[PublicKey], rng: &mut R) -> SealedReport {
+        assert!(!layers.is_empty(), "seal: At least one layer is required.");
+
+        // The innermost layer holds the report bytes.
+        let mut plaintext = vec![REPORT_TAG];
+        for value in self.as_u32_slice() {
+            plaintext.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // Wrap the layers from the last hop to the first.
+        let mut sealed = seal_layer(&layers[layers.len() - 1], &plaintext, rng);
+        for public_key in layers.iter().rev().skip(1) {
+            let mut plaintext = vec![LAYER_TAG];
+            plaintext.extend_from_slice(&sealed.bytes);
+            sealed = seal_layer(public_key, &plaintext, rng);
+        }
+
+        sealed
+    }
+}
+
+impl SealedReport {
+    /// Create a `SealedReport` from bytes received from the previous hop.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+
+    /// Return the sealed bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Remove the outermost layer with this hop's secret key.
+    pub fn unseal<const U32_SIZE: usize>(
+        &self,
+        secret_key: &StaticSecret,
+    ) -> Result<Unsealed<U32_SIZE>, SealError> {
+        let (tag, inner) = self.remove_layer(secret_key)?;
+
+        match tag {
+            LAYER_TAG => Ok(Unsealed::Layer(Self::from_bytes(inner))),
+            REPORT_TAG if inner.len() == U32_SIZE * size_of::<u32>() => {
+                let values = inner
+                    .chunks_exact(size_of::<u32>())
+                    .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+                    .collect::<Vec<_>>();
+                Ok(Unsealed::Report(Report::from_u32_slice(&values)))
+            }
+            _ => Err(SealError::Malformed),
+        }
+    }
+
+    /// Decrypt the outermost layer and return the plaintext tag and the remaining bytes.
+    fn remove_layer(&self, secret_key: &StaticSecret) -> Result<(u8, Vec<u8>), SealError> {
+        if self.bytes.len() < PUBLIC_KEY_SIZE {
+            return Err(SealError::Malformed);
+        }
+
+        // Recompute the layer key from the ephemeral public key.
+        let (ephemeral_public_key, ciphertext) = self.bytes.split_at(PUBLIC_KEY_SIZE);
+        let ephemeral_public_key =
+            PublicKey::from(<[u8; PUBLIC_KEY_SIZE]>::try_from(ephemeral_public_key).unwrap());
+        let shared_secret = secret_key.diffie_hellman(&ephemeral_public_key);
+        let cipher = layer_cipher(
+            shared_secret.as_bytes(),
+            &ephemeral_public_key,
+            &PublicKey::from(secret_key),
+        );
+
+        let mut plaintext = cipher
+            .decrypt(&Nonce::default(), ciphertext)
+            .map_err(|_| SealError::Decryption)?;
+        if plaintext.is_empty() {
+            return Err(SealError::Malformed);
+        }
+
+        let tag = plaintext.remove(0);
+        Ok((tag, plaintext))
+    }
+}
+
+/// Remove one layer from every `SealedReport` in a batch and shuffle the result. This is
+/// the work of an intermediate hop, so reaching a plaintext `Report` is an error.
+pub fn unseal_and_shuffle<R: Rng + ?Sized>(
+    sealed_reports: &[SealedReport],
+    secret_key: &StaticSecret,
+    rng: &mut R,
+) -> Result<Vec<SealedReport>, SealError> {
+    let mut unsealed = sealed_reports
+        .iter()
+        .map(
+            |sealed_report| match sealed_report.remove_layer(secret_key)? {
+                (LAYER_TAG, inner) => Ok(SealedReport::from_bytes(inner)),
+                (REPORT_TAG, _) => Err(SealError::Premature),
+                _ => Err(SealError::Malformed),
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+
+    unsealed.as_mut_slice().shuffle(rng);
+    Ok(unsealed)
+}
+
+/// Remove the last layer from every `SealedReport` in a batch and collect the `Report`s
+/// into a `ReportVector` with the given attribute types.
+pub fn open_reports<const U32_SIZE: usize>(
+    attr_types: &[AttributeType],
+    sealed_reports: &[SealedReport],
+    secret_key: &StaticSecret,
+) -> Result<ReportVector<U32_SIZE>, SealError> {
+    let mut report_vector = ReportVector::<U32_SIZE>::new(attr_types);
+    for sealed_report in sealed_reports {
+        let report = match sealed_report.unseal::<U32_SIZE>(secret_key)? {
+            Unsealed::Report(report) => report,
+            Unsealed::Layer(_) => return Err(SealError::LayersRemaining),
+        };
+
+        if !report_vector.report_handler().is_valid_report(&report) {
+            return Err(SealError::InvalidReport);
+        }
+        report_vector.push(report);
+    }
+
+    Ok(report_vector)
+}
+
+/// Encrypt a plaintext to a public key under a fresh ephemeral key.
+fn seal_layer<R: RngCore + CryptoRng>(
+    public_key: &PublicKey,
+    plaintext: &[u8],
+    rng: &mut R,
+) -> SealedReport {
+    let ephemeral_secret = EphemeralSecret::random_from_rng(rng);
+    let ephemeral_public_key = PublicKey::from(&ephemeral_secret);
+    let shared_secret = ephemeral_secret.diffie_hellman(public_key);
+    let cipher = layer_cipher(shared_secret.as_bytes(), &ephemeral_public_key, public_key);
+
+    // Every layer key is used exactly once, so a fixed nonce is safe.
+    let ciphertext = cipher
+        .encrypt(&Nonce::default(), plaintext)
+        .expect("seal_layer: Encryption failed.");
+
+    let mut bytes = ephemeral_public_key.as_bytes().to_vec();
+    bytes.extend_from_slice(&ciphertext);
+    SealedReport { bytes }
+}
+
+/// Derive the cipher of a layer from the shared secret and both public keys.
+fn layer_cipher(
+    shared_secret: &[u8; 32],
+    ephemeral_public_key: &PublicKey,
+    public_key: &PublicKey,
+) -> ChaCha20Poly1305 {
+    let key = Sha256::new()
+        .chain_update(SEAL_DOMAIN)
+        .chain_update(shared_secret)
+        .chain_update(ephemeral_public_key.as_bytes())
+        .chain_update(public_key.as_bytes())
+        .finalize();
+
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+    use AttributeType::*;
+
+    fn make_keys(count: usize) -> (Vec<StaticSecret>, Vec<PublicKey>) {
+        let secret_keys = (0..count)
+            .map(|_| StaticSecret::random_from_rng(OsRng))
+            .collect::<Vec<_>>();
+        let public_keys = secret_keys.iter().map(PublicKey::from).collect();
+        (secret_keys, public_keys)
+    }
+
+    #[test]
+    fn test_seal_unseal() {
+        let mut rng = OsRng;
+        let (secret_keys, public_keys) = make_keys(3);
+
+        let report_vector = ReportVector::<2>::random(&[C10, N20(65537), C20], &mut rng, 1);
+        let report = *report_vector.get(0).unwrap();
+        let mut sealed = report.seal(&public_keys, &mut rng);
+
+        // The hops must unseal in order.
+        assert_eq!(
+            sealed.unseal::<2>(&secret_keys[1]),
+            Err(SealError::Decryption)
+        );
+
+        for secret_key in &secret_keys[..2] {
+            sealed = match sealed.unseal::<2>(secret_key).unwrap() {
+                Unsealed::Layer(layer) => layer,
+                Unsealed::Report(_) => panic!("The report was revealed too early."),
+            };
+        }
+        assert_eq!(
+            sealed.unseal::<2>(&secret_keys[2]).unwrap(),
+            Unsealed::Report(report)
+        );
+
+        // Tampered layers are rejected.
+        let mut bytes = sealed.as_bytes().to_vec();
+        bytes[PUBLIC_KEY_SIZE] ^= 1;
+        assert_eq!(
+            SealedReport::from_bytes(bytes).unseal::<2>(&secret_keys[2]),
+            Err(SealError::Decryption)
+        );
+        assert_eq!(
+            SealedReport::from_bytes(vec![0; 8]).unseal::<2>(&secret_keys[2]),
+            Err(SealError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_shuffle_and_open() {
+        let mut rng = OsRng;
+        let (secret_keys, public_keys) = make_keys(2);
+
+        let attr_types = [C4, N5(31), C10];
+        let report_vector = ReportVector::<1>::random(&attr_types, &mut rng, 50);
+        let sealed_reports = report_vector
+            .iter()
+            .map(|report| report.seal(&public_keys, &mut rng))
+            .collect::<Vec<_>>();
+
+        // The last hop cannot open reports that still carry the shuffler's layer.
+        assert_eq!(
+            open_reports::<1>(&attr_types, &sealed_reports, &secret_keys[0]),
+            Err(SealError::LayersRemaining)
+        );
+
+        let shuffled = unseal_and_shuffle(&sealed_reports, &secret_keys[0], &mut rng).unwrap();
+        assert_eq!(
+            unseal_and_shuffle(&shuffled, &secret_keys[1], &mut rng),
+            Err(SealError::Premature)
+        );
+
+        let opened = open_reports::<1>(&attr_types, &shuffled, &secret_keys[1]).unwrap();
+        let mut expected = report_vector.iter().copied().collect::<Vec<_>>();
+        let mut actual = opened.iter().copied().collect::<Vec<_>>();
+        expected.sort_by_key(|report| report.as_u32_slice()[0]);
+        actual.sort_by_key(|report| report.as_u32_slice()[0]);
+        assert_eq!(actual, expected);
+    }
+}
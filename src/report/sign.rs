@@ -0,0 +1,296 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::seal::SealedReport;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Domain separator for report signatures.
+const SIGN_DOMAIN: &[u8] = b"doppio-report-sign-v1";
+
+/// Size of an Ed25519 verifying key.
+const VERIFYING_KEY_SIZE: usize = 32;
+
+/// Size of an Ed25519 signature.
+const SIGNATURE_SIZE: usize = 64;
+
+/// Errors that can occur when verifying a `SignedReport`.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The policy requires a signature but the report has none
+    #[error("The report is not signed")]
+    Unsigned,
+
+    /// The verifying key or the signature has an unexpected format
+    #[error("The report signature is malformed")]
+    Malformed,
+
+    /// The signature does not match the sealed report
+    #[error("The report signature is invalid")]
+    Invalid,
+
+    /// The report was signed with a key that is not in the trusted set
+    #[error("The report was signed with an untrusted key")]
+    UntrustedKey,
+}
+
+/// A `ReportSignature` is an Ed25519 signature on the sealed report bytes together
+/// with the client's verifying key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportSignature {
+    verifying_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl ReportSignature {
+    /// Return the verifying key bytes.
+    pub fn verifying_key(&self) -> &[u8] {
+        &self.verifying_key
+    }
+
+    /// Return the signature bytes.
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+}
+
+/// A `SignedReport` is a `SealedReport` as submitted by a client, with an optional
+/// signature. The first hop verifies and strips the signature before shuffling, so
+/// the signing key never travels further than the shuffler.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedReport {
+    sealed_report: SealedReport,
+    signature: Option<ReportSignature>,
+}
+
+impl SealedReport {
+    /// Sign the sealed report with the client's key.
+    pub fn sign(self, signing_key: &SigningKey) -> SignedReport {
+        let signature = signing_key.sign(&signed_message(&self));
+        SignedReport {
+            signature: Some(ReportSignature {
+                verifying_key: signing_key.verifying_key().to_bytes().to_vec(),
+                signature: signature.to_bytes().to_vec(),
+            }),
+            sealed_report: self,
+        }
+    }
+
+    /// Submit the sealed report without a signature.
+    pub fn unsigned(self) -> SignedReport {
+        SignedReport {
+            sealed_report: self,
+            signature: None,
+        }
+    }
+}
+
+impl SignedReport {
+    /// Return the sealed report.
+    pub fn sealed_report(&self) -> &SealedReport {
+        &self.sealed_report
+    }
+
+    /// Return the signature, if any.
+    pub fn signature(&self) -> Option<&ReportSignature> {
+        self.signature.as_ref()
+    }
+
+    /// Check the signature, if any, against the sealed report. Returns the verifying
+    /// key of a valid signature and `None` for an unsigned report.
+    pub fn verify(&self) -> Result<Option<VerifyingKey>, SignatureError> {
+        let Some(report_signature) = &self.signature else {
+            return Ok(None);
+        };
+
+        let verifying_key =
+            <[u8; VERIFYING_KEY_SIZE]>::try_from(report_signature.verifying_key.as_slice())
+                .map_err(|_| SignatureError::Malformed)?;
+        let verifying_key =
+            VerifyingKey::from_bytes(&verifying_key).map_err(|_| SignatureError::Malformed)?;
+        let signature = <[u8; SIGNATURE_SIZE]>::try_from(report_signature.signature.as_slice())
+            .map_err(|_| SignatureError::Malformed)?;
+
+        verifying_key
+            .verify_strict(
+                &signed_message(&self.sealed_report),
+                &Signature::from_bytes(&signature),
+            )
+            .map_err(|_| SignatureError::Invalid)?;
+        Ok(Some(verifying_key))
+    }
+}
+
+/// Which submissions a `ReportVerifier` accepts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    /// Accept unsigned reports, but reject reports with a bad signature
+    Optional,
+
+    /// Accept only reports with a valid signature under any key
+    Required,
+
+    /// Accept only reports with a valid signature under one of these keys, e.g. the
+    /// attested keys of enrolled devices
+    Trusted(HashSet<[u8; VERIFYING_KEY_SIZE]>),
+}
+
+/// Counts of the submissions a `ReportVerifier` has seen, by outcome.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RejectionCounters {
+    pub accepted: usize,
+    pub unsigned: usize,
+    pub malformed: usize,
+    pub invalid: usize,
+    pub untrusted_key: usize,
+}
+
+impl RejectionCounters {
+    /// Return the number of rejected submissions.
+    pub fn rejected(&self) -> usize {
+        self.unsigned + self.malformed + self.invalid + self.untrusted_key
+    }
+}
+
+/// A `ReportVerifier` filters submissions by their signatures before they reach the
+/// shuffle, and keeps count of what it rejected.
+#[derive(Clone, Debug)]
+pub struct ReportVerifier {
+    policy: SignaturePolicy,
+    counters: RejectionCounters,
+}
+
+impl ReportVerifier {
+    /// Create a new `ReportVerifier` with the given policy.
+    pub fn new(policy: SignaturePolicy) -> Self {
+        Self {
+            policy,
+            counters: RejectionCounters::default(),
+        }
+    }
+
+    /// Return the policy.
+    pub fn policy(&self) -> &SignaturePolicy {
+        &self.policy
+    }
+
+    /// Return the counters.
+    pub fn counters(&self) -> RejectionCounters {
+        self.counters
+    }
+
+    /// Check one submission against the policy and update the counters.
+    pub fn verify(&mut self, signed_report: &SignedReport) -> Result<(), SignatureError> {
+        let result =
+            signed_report
+                .verify()
+                .and_then(|verifying_key| match (&self.policy, verifying_key) {
+                    (SignaturePolicy::Optional, _) => Ok(()),
+                    (_, None) => Err(SignatureError::Unsigned),
+                    (SignaturePolicy::Required, Some(_)) => Ok(()),
+                    (SignaturePolicy::Trusted(keys), Some(verifying_key)) => {
+                        if keys.contains(verifying_key.as_bytes()) {
+                            Ok(())
+                        } else {
+                            Err(SignatureError::UntrustedKey)
+                        }
+                    }
+                });
+
+        match result {
+            Ok(()) => self.counters.accepted += 1,
+            Err(SignatureError::Unsigned) => self.counters.unsigned += 1,
+            Err(SignatureError::Malformed) => self.counters.malformed += 1,
+            Err(SignatureError::Invalid) => self.counters.invalid += 1,
+            Err(SignatureError::UntrustedKey) => self.counters.untrusted_key += 1,
+        }
+        result
+    }
+
+    /// Drop the submissions that fail the policy and strip the signatures from the
+    /// rest, ready for `unseal_and_shuffle`.
+    pub fn filter(&mut self, signed_reports: Vec<SignedReport>) -> Vec<SealedReport> {
+        signed_reports
+            .into_iter()
+            .filter(|signed_report| self.verify(signed_report).is_ok())
+            .map(|signed_report| signed_report.sealed_report)
+            .collect()
+    }
+}
+
+/// Return the message a client signs for a sealed report.
+fn signed_message(sealed_report: &SealedReport) -> Vec<u8> {
+    let mut message = SIGN_DOMAIN.to_vec();
+    message.extend_from_slice(sealed_report.as_bytes());
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    fn make_sealed_report(byte: u8) -> SealedReport {
+        SealedReport::from_bytes(vec![byte; 48])
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let signed = make_sealed_report(1).sign(&signing_key);
+        assert_eq!(signed.verify(), Ok(Some(signing_key.verifying_key())));
+        assert_eq!(make_sealed_report(1).unsigned().verify(), Ok(None));
+
+        // A signature does not carry over to another report.
+        let forged = SignedReport {
+            sealed_report: make_sealed_report(2),
+            signature: signed.signature.clone(),
+        };
+        assert_eq!(forged.verify(), Err(SignatureError::Invalid));
+
+        let mut truncated = signed;
+        truncated.signature.as_mut().unwrap().signature.pop();
+        assert_eq!(truncated.verify(), Err(SignatureError::Malformed));
+    }
+
+    #[test]
+    fn test_verifier_policies() {
+        let trusted_key = SigningKey::generate(&mut OsRng);
+        let other_key = SigningKey::generate(&mut OsRng);
+
+        let submissions = vec![
+            make_sealed_report(1).sign(&trusted_key),
+            make_sealed_report(2).sign(&other_key),
+            make_sealed_report(3).unsigned(),
+            SignedReport {
+                sealed_report: make_sealed_report(4),
+                signature: make_sealed_report(5).sign(&trusted_key).signature,
+            },
+        ];
+
+        let mut verifier = ReportVerifier::new(SignaturePolicy::Optional);
+        assert_eq!(verifier.filter(submissions.clone()).len(), 3);
+        assert_eq!(verifier.counters().invalid, 1);
+
+        let mut verifier = ReportVerifier::new(SignaturePolicy::Required);
+        assert_eq!(verifier.filter(submissions.clone()).len(), 2);
+        assert_eq!(verifier.counters().unsigned, 1);
+        assert_eq!(verifier.counters().rejected(), 2);
+
+        let trusted = HashSet::from([trusted_key.verifying_key().to_bytes()]);
+        let mut verifier = ReportVerifier::new(SignaturePolicy::Trusted(trusted));
+        assert_eq!(verifier.filter(submissions), vec![make_sealed_report(1)]);
+        assert_eq!(
+            verifier.counters(),
+            RejectionCounters {
+                accepted: 1,
+                unsigned: 1,
+                malformed: 0,
+                invalid: 1,
+                untrusted_key: 1,
+            }
+        );
+    }
+}
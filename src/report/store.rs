@@ -0,0 +1,263 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::compress::{BatchCodec, CompressionError};
+use super::report_vector::ReportVector;
+use crate::schema::AttributeType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How long a `ReportStore` keeps reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RetentionPolicy {
+    /// Keep reports until they are taken out explicitly
+    KeepAll,
+
+    /// Keep reports for this many epochs, including the current one. Older reports
+    /// are deleted when the epoch advances.
+    KeepEpochs(u64),
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self::KeepAll
+    }
+}
+
+/// A `ReportStore` holds the reports a server has ingested, bucketed by the epoch
+/// they arrived in. Every batch is tagged with the current epoch on ingestion, and
+/// the retention policy deletes whole epochs once they are too old, so a deletion
+/// deadline for client data can be enforced by the store itself.
+///
+/// Batches read from a partitioned log such as Kafka can be ingested together
+/// with the offsets they were read up to. The store keeps those offsets even
+/// after the epochs expire, so a consumer that resumes from them hands every
+/// report off exactly once.
+#[derive(Clone, Debug)]
+pub struct ReportStore<const U32_SIZE: usize> {
+    attr_types: Vec<AttributeType>,
+    retention: RetentionPolicy,
+    current_epoch: u64,
+    epochs: BTreeMap<u64, ReportVector<U32_SIZE>>,
+    expired_count: usize,
+    offsets: BTreeMap<u32, u64>,
+}
+
+impl<const U32_SIZE: usize> ReportStore<U32_SIZE> {
+    /// Create a new empty `ReportStore` starting at the given epoch.
+    pub fn new(attr_types: &[AttributeType], retention: RetentionPolicy, epoch: u64) -> Self {
+        Self {
+            attr_types: attr_types.to_vec(),
+            retention,
+            current_epoch: epoch,
+            epochs: BTreeMap::new(),
+            expired_count: 0,
+            offsets: BTreeMap::new(),
+        }
+    }
+
+    /// Return the current epoch.
+    pub fn current_epoch(&self) -> u64 {
+        self.current_epoch
+    }
+
+    /// Return the attribute types of the stored reports.
+    pub fn attr_types(&self) -> &[AttributeType] {
+        &self.attr_types
+    }
+
+    /// Return the retention policy.
+    pub fn retention(&self) -> RetentionPolicy {
+        self.retention
+    }
+
+    /// Return the oldest epoch the retention policy still keeps.
+    pub fn oldest_retained_epoch(&self) -> u64 {
+        match self.retention {
+            RetentionPolicy::KeepAll => 0,
+            RetentionPolicy::KeepEpochs(count) => {
+                (self.current_epoch + 1).saturating_sub(count.max(1))
+            }
+        }
+    }
+
+    /// Return the epochs that hold reports, in increasing order.
+    pub fn epochs(&self) -> impl Iterator<Item = u64> + '_ {
+        self.epochs.keys().copied()
+    }
+
+    /// Return the number of reports deleted by the retention policy so far.
+    pub fn expired_count(&self) -> usize {
+        self.expired_count
+    }
+
+    /// Return the total number of reports held.
+    pub fn len(&self) -> usize {
+        self.epochs.values().map(ReportVector::len).sum()
+    }
+
+    /// Add reports to the current epoch. Returns the epoch they were tagged with.
+    pub fn ingest(&mut self, reports: ReportVector<U32_SIZE>) -> Result<u64, String> {
+        if reports.report_handler().get_attr_types() != self.attr_types.as_slice() {
+            return Err(format!(
+                "ReportVector with attributes {:?} is incompatible with the store attributes {:?}.",
+                reports.report_handler().get_attr_types(),
+                self.attr_types
+            ));
+        }
+
+        let epoch_reports = self
+            .epochs
+            .entry(self.current_epoch)
+            .or_insert_with(|| ReportVector::new(&self.attr_types));
+        for report in reports.iter() {
+            epoch_reports.push(*report);
+        }
+        Ok(self.current_epoch)
+    }
+
+    /// Add reports read from a partitioned log to the current epoch, and commit
+    /// `offsets`, the offset after the last record read from each partition. The
+    /// offsets cannot move backwards. Returns the epoch the reports were tagged with.
+    pub fn ingest_with_offsets(
+        &mut self,
+        reports: ReportVector<U32_SIZE>,
+        offsets: &BTreeMap<u32, u64>,
+    ) -> Result<u64, String> {
+        for (&partition, &offset) in offsets {
+            if let Some(committed) = self.committed_offset(partition) {
+                if offset < committed {
+                    return Err(format!(
+                        "Offset {} of partition {} is behind the committed offset {}.",
+                        offset, partition, committed
+                    ));
+                }
+            }
+        }
+
+        let epoch = self.ingest(reports)?;
+        self.offsets.extend(offsets);
+        Ok(epoch)
+    }
+
+    /// Return the offset after the last record committed from a partition, i.e.
+    /// the offset to resume reading it from.
+    pub fn committed_offset(&self, partition: u32) -> Option<u64> {
+        self.offsets.get(&partition).copied()
+    }
+
+    /// Return the committed offsets of all partitions.
+    pub fn committed_offsets(&self) -> &BTreeMap<u32, u64> {
+        &self.offsets
+    }
+
+    /// Add reports from a compressed batch to the current epoch.
+    pub fn ingest_compressed(&mut self, bytes: &[u8]) -> Result<u64, String> {
+        let reports = ReportVector::<U32_SIZE>::decompress(bytes)
+            .map_err(|e: CompressionError| format!("Error decompressing reports: {}.", e))?;
+        self.ingest(reports)
+    }
+
+    /// Move to the next epoch and delete the epochs the retention policy no longer
+    /// keeps. Returns the number of reports deleted.
+    pub fn advance_epoch(&mut self) -> usize {
+        self.set_epoch(self.current_epoch + 1)
+    }
+
+    /// Move to a later epoch, e.g. one derived from the wall clock, and delete the
+    /// epochs the retention policy no longer keeps. Returns the number of reports
+    /// deleted. Panics if the epoch is earlier than the current one.
+    pub fn set_epoch(&mut self, epoch: u64) -> usize {
+        assert!(
+            epoch >= self.current_epoch,
+            "ReportStore::set_epoch: Epochs cannot move backwards."
+        );
+        self.current_epoch = epoch;
+
+        let kept = self.epochs.split_off(&self.oldest_retained_epoch());
+        let expired = std::mem::replace(&mut self.epochs, kept)
+            .values()
+            .map(ReportVector::len)
+            .sum::<usize>();
+        self.expired_count += expired;
+        expired
+    }
+
+    /// Return the reports of an epoch.
+    pub fn get_epoch(&self, epoch: u64) -> Option<&ReportVector<U32_SIZE>> {
+        self.epochs.get(&epoch)
+    }
+
+    /// Remove and return the reports of an epoch, e.g. to aggregate them.
+    pub fn take_epoch(&mut self, epoch: u64) -> Option<ReportVector<U32_SIZE>> {
+        self.epochs.remove(&epoch)
+    }
+
+    /// Encode the reports of an epoch as a compressed batch for persistent storage.
+    pub fn export_epoch(&self, epoch: u64, codec: BatchCodec) -> Option<Vec<u8>> {
+        self.epochs
+            .get(&epoch)
+            .map(|reports| reports.compress(codec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+    use AttributeType::*;
+
+    #[test]
+    fn test_ingest_and_expire() {
+        let mut rng = OsRng;
+        let attr_types = [C4, N5(31)];
+        let mut store = ReportStore::<1>::new(&attr_types, RetentionPolicy::KeepEpochs(2), 10);
+
+        assert_eq!(
+            store.ingest(ReportVector::random(&attr_types, &mut rng, 3)),
+            Ok(10)
+        );
+        assert_eq!(store.advance_epoch(), 0);
+        assert_eq!(
+            store.ingest(ReportVector::random(&attr_types, &mut rng, 4)),
+            Ok(11)
+        );
+        assert_eq!(
+            store.ingest(ReportVector::random(&attr_types, &mut rng, 1)),
+            Ok(11)
+        );
+        assert_eq!(store.len(), 8);
+        assert_eq!(store.epochs().collect::<Vec<_>>(), vec![10, 11]);
+
+        // Epoch 10 falls out of the two-epoch window.
+        assert_eq!(store.advance_epoch(), 3);
+        assert_eq!(store.oldest_retained_epoch(), 11);
+        assert_eq!(store.get_epoch(11).unwrap().len(), 5);
+
+        // Skipping ahead drops everything.
+        assert_eq!(store.set_epoch(20), 5);
+        assert_eq!(store.len(), 0);
+        assert_eq!(store.expired_count(), 8);
+
+        assert!(store
+            .ingest(ReportVector::random(&[C4], &mut rng, 1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_keep_all_and_export() {
+        let mut rng = OsRng;
+        let attr_types = [C10];
+        let mut store = ReportStore::<1>::new(&attr_types, RetentionPolicy::KeepAll, 0);
+        let reports = ReportVector::<1>::random(&attr_types, &mut rng, 10);
+        store
+            .ingest_compressed(&reports.compress(BatchCodec::packed()))
+            .unwrap();
+        assert_eq!(store.set_epoch(100), 0);
+
+        let bytes = store.export_epoch(0, BatchCodec::zstd()).unwrap();
+        assert_eq!(ReportVector::<1>::decompress(&bytes).unwrap(), reports);
+        assert_eq!(store.take_epoch(0), Some(reports));
+        assert_eq!(store.export_epoch(0, BatchCodec::zstd()), None);
+    }
+}
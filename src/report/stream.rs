@@ -0,0 +1,344 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::report::Report;
+use super::report_handler::ReportHandler;
+use super::report_vector::ReportVector;
+use crate::schema::AttributeType;
+use std::io::{self, Read, Write};
+use std::mem::{replace, size_of};
+use thiserror::Error;
+
+/// Magic bytes at the start of a report stream.
+const STREAM_MAGIC: &[u8; 4] = b"DPRS";
+
+/// Version of the report stream format.
+const STREAM_VERSION: u8 = 1;
+
+/// Default number of `Report`s per chunk.
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Largest chunk accepted when reading, in bytes.
+pub const MAX_CHUNK_BYTES: usize = 256 * 1024 * 1024;
+
+/// Largest attribute type description accepted when reading, in bytes.
+const MAX_DESCRIPTION_BYTES: usize = 64 * 1024;
+
+/// Errors that can occur when reading or writing a report stream.
+#[derive(Error, Debug)]
+pub enum StreamError {
+    /// The underlying reader or writer failed
+    #[error("Report stream I/O failed: {0}")]
+    Io(#[from] io::Error),
+
+    /// The bytes are not a report stream
+    #[error("The report stream header is malformed")]
+    Malformed,
+
+    /// The stream was written by an unknown version of the format
+    #[error("Unsupported report stream version {0}")]
+    UnsupportedVersion(u8),
+
+    /// The attribute types in the header are not valid for this report size
+    #[error("The report stream attribute types are invalid")]
+    InvalidAttrTypes,
+
+    /// A chunk is larger than `MAX_CHUNK_BYTES`
+    #[error("Report chunk of {0} bytes exceeds the limit")]
+    ChunkTooLarge(usize),
+
+    /// A decoded `Report` does not match the attribute types
+    #[error("The report stream contains an invalid report")]
+    InvalidReport,
+}
+
+/// A `ReportStreamWriter` writes `Report`s as a header with the attribute types,
+/// followed by bit-packed chunks of at most `chunk_size` many `Report`s. Only one
+/// chunk is buffered at a time, so a cohort of any size can be exported with
+/// bounded memory.
+pub struct ReportStreamWriter<W: Write, const U32_SIZE: usize> {
+    writer: W,
+    pending: ReportVector<U32_SIZE>,
+    chunk_size: usize,
+    reports_written: usize,
+    header_written: bool,
+}
+
+impl<W: Write, const U32_SIZE: usize> ReportStreamWriter<W, U32_SIZE> {
+    /// Create a new `ReportStreamWriter` for `Report`s with the given attribute types.
+    pub fn new(writer: W, attr_types: &[AttributeType]) -> Self {
+        Self::with_chunk_size(writer, attr_types, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Create a new `ReportStreamWriter` with the given chunk size.
+    pub fn with_chunk_size(writer: W, attr_types: &[AttributeType], chunk_size: usize) -> Self {
+        Self {
+            writer,
+            pending: ReportVector::new(attr_types),
+            chunk_size: chunk_size.max(1),
+            reports_written: 0,
+            header_written: false,
+        }
+    }
+
+    /// Queue a `Report`, writing a chunk when it is full. Panics if the `Report` is
+    /// invalid.
+    pub fn write(&mut self, report: Report<U32_SIZE>) -> Result<(), StreamError> {
+        self.pending.push(report);
+        if self.pending.len() >= self.chunk_size {
+            self.write_chunk()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write every `Report` produced by an iterator.
+    pub fn write_all<I>(&mut self, reports: I) -> Result<(), StreamError>
+    where
+        I: IntoIterator<Item = Report<U32_SIZE>>,
+    {
+        for report in reports {
+            self.write(report)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write any pending chunk and the end marker, and return the writer.
+    pub fn finish(mut self) -> Result<W, StreamError> {
+        self.write_chunk()?;
+        self.write_header()?;
+        self.writer.write_all(&0u32.to_le_bytes())?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    /// Return the number of `Report`s written so far.
+    pub fn reports_written(&self) -> usize {
+        self.reports_written
+    }
+
+    /// Write the stream header if it has not been written yet.
+    fn write_header(&mut self) -> Result<(), StreamError> {
+        if !self.header_written {
+            let description = serde_json::to_vec(self.pending.report_handler().get_attr_types())
+                .expect("write_header: Failed to serialize the attribute types.");
+            self.writer.write_all(STREAM_MAGIC)?;
+            self.writer.write_all(&[STREAM_VERSION])?;
+            self.writer
+                .write_all(&(description.len() as u32).to_le_bytes())?;
+            self.writer.write_all(&description)?;
+            self.header_written = true;
+        }
+
+        Ok(())
+    }
+
+    /// Pack and write the pending `Report`s as one chunk.
+    fn write_chunk(&mut self) -> Result<(), StreamError> {
+        if self.pending.len() == 0 {
+            return Ok(());
+        }
+
+        self.write_header()?;
+        let attr_types = self.pending.report_handler().get_attr_types().to_vec();
+        let chunk = replace(&mut self.pending, ReportVector::new(&attr_types));
+        let packed = chunk.pack();
+        if packed.len() * size_of::<u32>() > MAX_CHUNK_BYTES {
+            return Err(StreamError::ChunkTooLarge(packed.len() * size_of::<u32>()));
+        }
+
+        self.writer.write_all(&(chunk.len() as u32).to_le_bytes())?;
+        for word in packed {
+            self.writer.write_all(&word.to_le_bytes())?;
+        }
+        self.reports_written += chunk.len();
+
+        Ok(())
+    }
+}
+
+/// A `ReportStreamReader` reads a stream written by `ReportStreamWriter` and yields
+/// one chunk of `Report`s at a time as a `ReportVector`.
+pub struct ReportStreamReader<R: Read, const U32_SIZE: usize> {
+    reader: R,
+    attr_types: Vec<AttributeType>,
+    finished: bool,
+}
+
+impl<R: Read, const U32_SIZE: usize> ReportStreamReader<R, U32_SIZE> {
+    /// Create a new `ReportStreamReader` and read the stream header.
+    pub fn new(mut reader: R) -> Result<Self, StreamError> {
+        let mut header = [0u8; STREAM_MAGIC.len() + 1 + size_of::<u32>()];
+        reader.read_exact(&mut header)?;
+        if &header[..STREAM_MAGIC.len()] != STREAM_MAGIC {
+            return Err(StreamError::Malformed);
+        }
+        if header[STREAM_MAGIC.len()] != STREAM_VERSION {
+            return Err(StreamError::UnsupportedVersion(header[STREAM_MAGIC.len()]));
+        }
+
+        let description_size =
+            u32::from_le_bytes(header[STREAM_MAGIC.len() + 1..].try_into().unwrap()) as usize;
+        if description_size > MAX_DESCRIPTION_BYTES {
+            return Err(StreamError::Malformed);
+        }
+        let mut description = vec![0u8; description_size];
+        reader.read_exact(&mut description)?;
+        let attr_types: Vec<AttributeType> =
+            serde_json::from_slice(&description).map_err(|_| StreamError::Malformed)?;
+
+        let bit_size = attr_types
+            .iter()
+            .map(|attr_type| attr_type.get_size())
+            .sum::<usize>();
+        if attr_types.is_empty()
+            || bit_size > U32_SIZE * 32
+            || !ReportHandler::<U32_SIZE>::is_valid_attr_types(&attr_types)
+        {
+            return Err(StreamError::InvalidAttrTypes);
+        }
+
+        Ok(Self {
+            reader,
+            attr_types,
+            finished: false,
+        })
+    }
+
+    /// Return the attribute types of the `Report`s in the stream.
+    pub fn attr_types(&self) -> &[AttributeType] {
+        &self.attr_types
+    }
+
+    /// Read the next chunk. Returns `None` at the end marker.
+    fn read_chunk(&mut self) -> Result<Option<ReportVector<U32_SIZE>>, StreamError> {
+        let mut count = [0u8; size_of::<u32>()];
+        self.reader.read_exact(&mut count)?;
+        let count = u32::from_le_bytes(count) as usize;
+        if count == 0 {
+            return Ok(None);
+        }
+
+        let bit_size = self
+            .attr_types
+            .iter()
+            .map(|attr_type| attr_type.get_size())
+            .sum::<usize>();
+        let word_count = (count * bit_size + 31) / 32;
+        if word_count * size_of::<u32>() > MAX_CHUNK_BYTES {
+            return Err(StreamError::ChunkTooLarge(word_count * size_of::<u32>()));
+        }
+
+        let mut bytes = vec![0u8; word_count * size_of::<u32>()];
+        self.reader.read_exact(&mut bytes)?;
+        let packed = bytes
+            .chunks_exact(size_of::<u32>())
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        ReportVector::try_unpack(&self.attr_types, &packed, count)
+            .map(Some)
+            .ok_or(StreamError::InvalidReport)
+    }
+}
+
+impl<R: Read, const U32_SIZE: usize> Iterator for ReportStreamReader<R, U32_SIZE> {
+    type Item = Result<ReportVector<U32_SIZE>, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let chunk = self.read_chunk().transpose();
+        if !matches!(chunk, Some(Ok(_))) {
+            self.finished = true;
+        }
+        chunk
+    }
+}
+
+impl<const U32_SIZE: usize> ReportVector<U32_SIZE> {
+    /// Write the `ReportVector` as a report stream with the given chunk size.
+    pub fn write_stream<W: Write>(&self, writer: W, chunk_size: usize) -> Result<W, StreamError> {
+        let mut stream_writer = ReportStreamWriter::<W, U32_SIZE>::with_chunk_size(
+            writer,
+            self.report_handler().get_attr_types(),
+            chunk_size,
+        );
+        stream_writer.write_all(self.iter().copied())?;
+        stream_writer.finish()
+    }
+
+    /// Read a whole report stream into a `ReportVector`. Use `ReportStreamReader`
+    /// directly to process the chunks one at a time instead.
+    pub fn read_stream<R: Read>(reader: R) -> Result<Self, StreamError> {
+        let stream_reader = ReportStreamReader::<R, U32_SIZE>::new(reader)?;
+        let mut report_vector = Self::new(stream_reader.attr_types());
+        for chunk in stream_reader {
+            for report in chunk?.iter() {
+                report_vector.push(*report);
+            }
+        }
+
+        Ok(report_vector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+    use AttributeType::*;
+
+    #[test]
+    fn test_stream_roundtrip() {
+        let mut rng = OsRng;
+        let report_vector = ReportVector::<2>::random(&[C10, N20(65537), C7], &mut rng, 100);
+        let bytes = report_vector.write_stream(Vec::new(), 16).unwrap();
+
+        let chunk_sizes = ReportStreamReader::<_, 2>::new(bytes.as_slice())
+            .unwrap()
+            .map(|chunk| chunk.unwrap().len())
+            .collect::<Vec<_>>();
+        assert_eq!(chunk_sizes, vec![16, 16, 16, 16, 16, 16, 4]);
+        assert_eq!(
+            ReportVector::<2>::read_stream(bytes.as_slice()).unwrap(),
+            report_vector
+        );
+
+        let empty = ReportVector::<1>::new(&[C4]);
+        let bytes = empty.write_stream(Vec::new(), 16).unwrap();
+        assert_eq!(
+            ReportVector::<1>::read_stream(bytes.as_slice()).unwrap(),
+            empty
+        );
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        let mut rng = OsRng;
+        let report_vector = ReportVector::<1>::random(&[C10, C10], &mut rng, 10);
+        let bytes = report_vector.write_stream(Vec::new(), 4).unwrap();
+
+        // A truncated stream ends with an error.
+        let results = ReportStreamReader::<_, 1>::new(&bytes[..bytes.len() - 6])
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert!(matches!(results.last(), Some(Err(StreamError::Io(_)))));
+
+        assert!(matches!(
+            ReportStreamReader::<_, 1>::new(&b"DPSS\x01\0\0\0\0"[..]),
+            Err(StreamError::Malformed)
+        ));
+
+        let wide = ReportVector::<2>::random(&[C32, C16], &mut rng, 1);
+        let bytes = wide.write_stream(Vec::new(), 4).unwrap();
+        assert!(matches!(
+            ReportStreamReader::<_, 1>::new(bytes.as_slice()),
+            Err(StreamError::InvalidAttrTypes)
+        ));
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::attr::AttrValueType;
+use super::report::Report;
+use super::report_vector::ReportVector;
+use crate::schema::AttributeType;
+use prost::Message;
+use thiserror::Error;
+
+/// Errors that can occur when decoding reports from the wire format.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum WireError {
+    /// The bytes are not a valid protobuf message
+    #[error("The report message could not be decoded")]
+    Decode,
+
+    /// An attribute type has an unknown kind, size, or modulus
+    #[error("Invalid attribute type in report message")]
+    InvalidAttrType,
+
+    /// The attribute types do not fit into a `Report` of this size
+    #[error("The attribute types do not fit into the report size")]
+    ReportSizeExceeded,
+
+    /// A report has the wrong number of attribute values
+    #[error("Expected {expected} attribute values, found {found}")]
+    AttrCountMismatch { expected: usize, found: usize },
+
+    /// An attribute value is out of range for its type
+    #[error("Invalid value {value} for attribute {attr_index}")]
+    InvalidAttrValue {
+        attr_index: usize,
+        value: AttrValueType,
+    },
+}
+
+/// The kind of an attribute in `AttributeTypeProto`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum AttributeKind {
+    Categorical = 0,
+    Numerical = 1,
+}
+
+/// Wire message for `AttributeType`.
+#[derive(Clone, PartialEq, Message)]
+pub struct AttributeTypeProto {
+    #[prost(enumeration = "AttributeKind", tag = "1")]
+    pub kind: i32,
+    #[prost(uint32, tag = "2")]
+    pub bit_size: u32,
+    #[prost(uint32, tag = "3")]
+    pub modulus: u32,
+}
+
+/// Wire message for a single `Report`.
+#[derive(Clone, PartialEq, Message)]
+pub struct ReportProto {
+    #[prost(uint32, repeated, tag = "1")]
+    pub attr_values: Vec<u32>,
+}
+
+/// Wire message for a `ReportVector`. The messages in this file mirror
+/// `proto/report.proto`, so clients in other languages can generate their encoders
+/// from it and the server ingests their reports natively.
+#[derive(Clone, PartialEq, Message)]
+pub struct ReportVectorProto {
+    #[prost(message, repeated, tag = "1")]
+    pub attr_types: Vec<AttributeTypeProto>,
+    #[prost(message, repeated, tag = "2")]
+    pub reports: Vec<ReportProto>,
+}
+
+impl From<AttributeType> for AttributeTypeProto {
+    fn from(attr_type: AttributeType) -> Self {
+        let kind = if attr_type.is_categorical() {
+            AttributeKind::Categorical
+        } else {
+            AttributeKind::Numerical
+        };
+
+        Self {
+            kind: kind as i32,
+            bit_size: attr_type.get_size() as u32,
+            modulus: attr_type.get_modulus(),
+        }
+    }
+}
+
+impl TryFrom<&AttributeTypeProto> for AttributeType {
+    type Error = WireError;
+
+    fn try_from(proto: &AttributeTypeProto) -> Result<Self, Self::Error> {
+        // The serde names of `AttributeType` encode exactly the kind and bit size.
+        let value = match AttributeKind::try_from(proto.kind) {
+            Ok(AttributeKind::Categorical) => {
+                serde_json::Value::String(format!("c{}", proto.bit_size))
+            }
+            Ok(AttributeKind::Numerical) => {
+                let mut value = serde_json::Map::new();
+                value.insert(format!("n{}", proto.bit_size), proto.modulus.into());
+                serde_json::Value::Object(value)
+            }
+            Err(_) => return Err(WireError::InvalidAttrType),
+        };
+
+        let attr_type: AttributeType =
+            serde_json::from_value(value).map_err(|_| WireError::InvalidAttrType)?;
+        if !attr_type.is_valid()
+            || (attr_type.is_numerical() && attr_type.get_modulus() != proto.modulus)
+        {
+            return Err(WireError::InvalidAttrType);
+        }
+        Ok(attr_type)
+    }
+}
+
+impl<const U32_SIZE: usize> ReportVector<U32_SIZE> {
+    /// Encode the `ReportVector` as a `ReportVectorProto` message.
+    pub fn encode_wire(&self) -> Vec<u8> {
+        let report_handler = self.report_handler();
+        ReportVectorProto {
+            attr_types: report_handler
+                .get_attr_types()
+                .iter()
+                .map(|&attr_type| attr_type.into())
+                .collect(),
+            reports: self
+                .iter()
+                .map(|report| self.report_to_proto(report))
+                .collect(),
+        }
+        .encode_to_vec()
+    }
+
+    /// Decode a `ReportVectorProto` message into a `ReportVector`.
+    pub fn decode_wire(bytes: &[u8]) -> Result<Self, WireError> {
+        let proto = ReportVectorProto::decode(bytes).map_err(|_| WireError::Decode)?;
+        let attr_types = proto
+            .attr_types
+            .iter()
+            .map(AttributeType::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        if attr_types.is_empty() {
+            return Err(WireError::InvalidAttrType);
+        }
+        if attr_types
+            .iter()
+            .map(|attr_type| attr_type.get_size())
+            .sum::<usize>()
+            > U32_SIZE * 32
+        {
+            return Err(WireError::ReportSizeExceeded);
+        }
+
+        let mut report_vector = Self::new(&attr_types);
+        for report in &proto.reports {
+            let report = report_vector.report_from_proto(report)?;
+            report_vector.push(report);
+        }
+        Ok(report_vector)
+    }
+
+    /// Encode a single `Report` of this `ReportVector`'s type as a `ReportProto`
+    /// message.
+    pub fn encode_wire_report(&self, report: &Report<U32_SIZE>) -> Vec<u8> {
+        self.report_to_proto(report).encode_to_vec()
+    }
+
+    /// Decode a single `ReportProto` message, e.g. as submitted by a mobile or JS
+    /// client, and add it to the `ReportVector`.
+    pub fn push_wire_report(&mut self, bytes: &[u8]) -> Result<(), WireError> {
+        let proto = ReportProto::decode(bytes).map_err(|_| WireError::Decode)?;
+        let report = self.report_from_proto(&proto)?;
+        self.push(report);
+        Ok(())
+    }
+
+    fn report_to_proto(&self, report: &Report<U32_SIZE>) -> ReportProto {
+        let report_handler = self.report_handler();
+        ReportProto {
+            attr_values: (0..report_handler.get_attr_types().len())
+                .map(|attr_index| report_handler.get_attr(report, attr_index))
+                .collect(),
+        }
+    }
+
+    fn report_from_proto(&self, proto: &ReportProto) -> Result<Report<U32_SIZE>, WireError> {
+        let report_handler = self.report_handler();
+        let attr_types = report_handler.get_attr_types();
+        if proto.attr_values.len() != attr_types.len() {
+            return Err(WireError::AttrCountMismatch {
+                expected: attr_types.len(),
+                found: proto.attr_values.len(),
+            });
+        }
+
+        for (attr_index, (attr_type, &value)) in
+            attr_types.iter().zip(&proto.attr_values).enumerate()
+        {
+            if !attr_type.is_valid_value(value) {
+                return Err(WireError::InvalidAttrValue { attr_index, value });
+            }
+        }
+
+        Ok(report_handler.create_report(&proto.attr_values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+    use AttributeType::*;
+
+    #[test]
+    fn test_attr_type_round_trip() {
+        for attr_type in [C2, C10, C32, N2(3), N8(255), N16(65521), N31(2147483647)] {
+            let proto = AttributeTypeProto::from(attr_type);
+            assert_eq!(AttributeType::try_from(&proto), Ok(attr_type));
+        }
+
+        let invalid = [
+            AttributeTypeProto {
+                kind: 2,
+                bit_size: 10,
+                modulus: 0,
+            },
+            AttributeTypeProto {
+                kind: AttributeKind::Categorical as i32,
+                bit_size: 33,
+                modulus: 0,
+            },
+            AttributeTypeProto {
+                kind: AttributeKind::Numerical as i32,
+                bit_size: 4,
+                modulus: 17,
+            },
+        ];
+        for proto in &invalid {
+            assert_eq!(
+                AttributeType::try_from(proto),
+                Err(WireError::InvalidAttrType)
+            );
+        }
+    }
+
+    #[test]
+    fn test_report_vector_round_trip() {
+        let mut rng = OsRng;
+        let report_vector = ReportVector::<2>::random(&[C10, N20(65537), C20], &mut rng, 50);
+        let bytes = report_vector.encode_wire();
+        assert_eq!(ReportVector::<2>::decode_wire(&bytes), Ok(report_vector));
+
+        // 50 bits do not fit into a single u32.
+        assert_eq!(
+            ReportVector::<1>::decode_wire(&bytes),
+            Err(WireError::ReportSizeExceeded)
+        );
+        assert_eq!(
+            ReportVector::<2>::decode_wire(&[0xff, 0xff]),
+            Err(WireError::Decode)
+        );
+    }
+
+    #[test]
+    fn test_push_wire_report() {
+        let mut report_vector = ReportVector::<1>::new(&[C4, N5(31)]);
+
+        // A message as a client in another language would produce it.
+        let bytes = ReportProto {
+            attr_values: vec![9, 30],
+        }
+        .encode_to_vec();
+        report_vector.push_wire_report(&bytes).unwrap();
+        let expected = report_vector.report_handler().create_report(&[9, 30]);
+        assert_eq!(*report_vector.get(0).unwrap(), expected);
+        assert_eq!(report_vector.encode_wire_report(&expected), bytes);
+
+        let too_large = ReportProto {
+            attr_values: vec![9, 31],
+        }
+        .encode_to_vec();
+        assert_eq!(
+            report_vector.push_wire_report(&too_large),
+            Err(WireError::InvalidAttrValue {
+                attr_index: 1,
+                value: 31
+            })
+        );
+
+        let too_short = ReportProto {
+            attr_values: vec![9],
+        }
+        .encode_to_vec();
+        assert_eq!(
+            report_vector.push_wire_report(&too_short),
+            Err(WireError::AttrCountMismatch {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(report_vector.len(), 1);
+    }
+}
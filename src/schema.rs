@@ -344,6 +344,17 @@ impl AttributeType {
         }
     }
 
+    /// Return the number of values clients can report: the values below the
+    /// modulus for numerical attributes, and all values except the all-ones
+    /// dummy value for categorical attributes.
+    pub(crate) fn get_value_count(&self) -> u64 {
+        if self.is_numerical() {
+            self.get_modulus() as u64
+        } else {
+            (1u64 << self.get_size()) - 1
+        }
+    }
+
     /// Return the modulus for numerical attributes. For categorical
     /// attributes this function returns 0.
     pub(crate) fn get_modulus(&self) -> u32 {
@@ -811,12 +822,63 @@ impl TryFrom<&str> for Schema {
         // Load the input JSON.
         let schema_json: Result<Schema, _> = serde_json::from_str(input_json);
         match schema_json {
+            // Reject invalid schemas here, since many functions panic on them.
+            Ok(schema) if !schema.is_valid() => Err(
+                "Invalid schema: attribute names must be unique and attribute types valid."
+                    .to_string(),
+            ),
             Ok(schema) => Ok(schema),
             Err(e) => Err(format!("Error parsing input JSON: {}.", e)),
         }
     }
 }
 
+/// Parse CSV rows with a header row into `DataPoint`s, with one feature per schema
+/// attribute in schema order. The attribute names select the columns, and other
+/// columns are ignored. Returns an error, never panics, on malformed input,
+/// missing columns, and values that are not finite numbers.
+#[cfg(feature = "csv")]
+pub fn data_points_from_csv(
+    reader: impl std::io::Read,
+    schema: &Schema,
+) -> Result<Vec<DataPoint>, String> {
+    let mut csv = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let headers = csv.headers().map_err(|e| e.to_string())?.clone();
+    let columns = schema
+        .get_attr_names()
+        .iter()
+        .map(|attr_name| {
+            headers
+                .iter()
+                .position(|header| header == attr_name)
+                .ok_or_else(|| format!("Missing column {}.", attr_name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut data = Vec::new();
+    for (line, record) in csv.records().enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        // Line numbers count the header.
+        let row = line + 2;
+        let features = columns
+            .iter()
+            .map(|&column| {
+                let value = record.get(column).unwrap_or_default();
+                match value.parse::<f64>() {
+                    Ok(number) if number.is_finite() => Ok(number),
+                    Ok(_) => Err(format!("Row {}: {} is not finite.", row, value)),
+                    Err(e) => Err(format!("Row {}: {}: {}.", row, value, e)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        data.push(DataPoint::new(features));
+    }
+
+    Ok(data)
+}
+
 /// Get the attribute type for a named attribute.
 pub fn attr_type_from_attr_name(schema: &Schema, attr_name: &str) -> Result<AttributeType, String> {
     // Check that `attr_name` is a valid attribute name. Otherwise return an error.
@@ -837,7 +899,10 @@ pub fn attr_index_from_attr_name(schema: &Schema, attr_name: &str) -> Result<usi
 }
 
 // Query-related types for shuffle differential privacy
-#[derive(Debug, Clone, PartialEq, Eq)]
+
+/// Type of a query. Serialized in snake case, e.g. `"mean"` or `"histogram"`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum QueryType {
     Mean,
     Variance,
@@ -848,9 +913,13 @@ pub enum QueryType {
 }
 
 /// Represents a data point with features
-#[derive(Debug, Clone)]
+///
+/// Serialized as `{"features": [1.0, 2.0], "attributes": [...]}`, where
+/// `attributes` is omitted when empty.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DataPoint {
     features: Vec<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     attributes: Vec<Attribute>,
 }
 
@@ -931,10 +1000,15 @@ impl DataPoint {
 }
 
 /// Represents a query to be executed
-#[derive(Debug, Clone)]
+///
+/// Serialized as
+/// `{"query_type": "histogram", "features": ["feature1"], "parameters": {"bins": 10.0}}`,
+/// where `parameters` may be omitted when deserializing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Query {
     pub query_type: QueryType,
     pub features: Vec<String>,
+    #[serde(default)]
     pub parameters: std::collections::HashMap<String, f64>,
 }
 
@@ -973,7 +1047,11 @@ impl Query {
 }
 
 /// Represents the result of a query execution
-#[derive(Debug, Clone)]
+///
+/// Serialized with its noise metadata as
+/// `{"values": [3.2, 4.1], "has_noise": true, "privacy_budget_used": 0.5}`, so
+/// dashboards can tell noisy values from exact ones.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct QueryResult {
     values: Vec<f64>,
     has_noise: bool,
@@ -1079,6 +1157,31 @@ mod query_tests {
         assert!(noisy_result.has_noise());
         assert_eq!(noisy_result.privacy_budget_used(), 0.5);
     }
+
+    #[test]
+    fn test_query_json() {
+        let mut query = Query::new(QueryType::Histogram, vec!["feature1".to_string()]);
+        query.add_parameter("bins", 10.0);
+        assert_eq!(
+            serde_json::to_string(&query).unwrap(),
+            r#"{"query_type":"histogram","features":["feature1"],"parameters":{"bins":10.0}}"#
+        );
+        let query: Query =
+            serde_json::from_str(r#"{"query_type":"mean","features":["feature2"]}"#).unwrap();
+        assert_eq!(query.query_type, QueryType::Mean);
+        assert!(query.parameters.is_empty());
+
+        let result = QueryResult::with_noise(vec![3.5], 0.5);
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            r#"{"values":[3.5],"has_noise":true,"privacy_budget_used":0.5}"#
+        );
+
+        let data = DataPoint::new(vec![1.0, 2.0]);
+        assert_eq!(serde_json::to_string(&data).unwrap(), r#"{"features":[1.0,2.0]}"#);
+        let data: DataPoint = serde_json::from_str(r#"{"features":[4.0]}"#).unwrap();
+        assert_eq!(data.features(), &[4.0]);
+    }
 }
 
 #[cfg(test)]
@@ -1150,6 +1253,31 @@ mod tests {
         assert!(!schema.is_valid());
     }
 
+    #[test]
+    fn test_try_from_rejects_invalid_schemas() {
+        assert!(Schema::try_from(r#"[["attr1","c2"],["attr2",{"n5":20}]]"#).is_ok());
+        assert!(Schema::try_from(r#"[["attr1","c2"],["attr1","c3"]]"#).is_err());
+        assert!(Schema::try_from(r#"[["attr1",{"n5":33}]]"#).is_err());
+        assert!(Schema::try_from(r#"[["attr1","c33"]]"#).is_err());
+        assert!(Schema::try_from("[[").is_err());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_data_points_from_csv() {
+        let schema = Schema::try_from(r#"[["age","c7"],["income",{"n20":65537}]]"#).unwrap();
+        let csv = "id,income,age\n0, 52, 31\n1, 7, 45\n";
+        let data = data_points_from_csv(csv.as_bytes(), &schema).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[1].features(), &[45.0, 7.0]);
+
+        assert!(data_points_from_csv("age\n31\n".as_bytes(), &schema).is_err());
+        assert!(data_points_from_csv("age,income\n31,many\n".as_bytes(), &schema).is_err());
+        assert!(data_points_from_csv("age,income\n31,NaN\n".as_bytes(), &schema).is_err());
+        assert!(data_points_from_csv("age,income\n31\n".as_bytes(), &schema).is_err());
+        assert!(data_points_from_csv(&[0xff, 0xfe, b'\n'][..], &schema).is_err());
+    }
+
     #[test]
     fn test_is_compatible() {
         let schema = Schema(vec![
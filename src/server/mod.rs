@@ -2,7 +2,7 @@
 // Licensed under the MIT license.
 
 mod histogram;
-mod ot;
+pub mod ot;
 mod role;
 mod server;
 
@@ -51,6 +51,27 @@ impl Server {
         self.dp_mechanism.apply_mechanism(data, query)
             .map_err(|_| ServerError::QueryProcessingFailed)
     }
+
+    /// Shuffle an epoch in place and answer `query` on it in batches of
+    /// `batch_size` points.
+    ///
+    /// The DP stage reads the shuffled points through borrowed slices, so the
+    /// epoch is held in memory once instead of once per stage.
+    pub fn process_epoch(
+        &mut self,
+        data: &mut [DataPoint],
+        query: Query,
+        batch_size: usize,
+    ) -> Result<QueryResult, ServerError> {
+        if batch_size == 0 {
+            return Err(ServerError::InvalidInput);
+        }
+
+        self.shuffler.shuffle_in_place(data)
+            .map_err(|_| ServerError::QueryProcessingFailed)?;
+        self.dp_mechanism.apply_mechanism_batches(data.chunks(batch_size), query)
+            .map_err(|_| ServerError::QueryProcessingFailed)
+    }
 }
 
 pub use histogram::Histogram;
@@ -87,4 +108,16 @@ mod tests {
         let result = server.process_query(query, data).unwrap();
         assert!(result.has_noise());
     }
+
+    #[test]
+    fn test_server_process_epoch() {
+        let mut server = Server::new();
+        let mut data: Vec<DataPoint> = (0..100).map(|i| DataPoint::new(vec![i as f64])).collect();
+        let query = Query::new(QueryType::Mean, vec!["feature1".to_string()]);
+
+        let result = server.process_epoch(&mut data, query.clone(), 16).unwrap();
+        assert!(result.has_noise());
+        assert_eq!(data.len(), 100);
+        assert!(server.process_epoch(&mut data, query, 0).is_err());
+    }
 }
@@ -1,6 +1,15 @@
 // Copyright (c) Microsoft Corporation. All rights reserved.
 // Licensed under the MIT license.
 
+mod base;
+mod extension;
+
+pub use base::{base_ot_receive, BaseOtSender};
+pub use extension::{
+    deal_base_ots, send_base_ots, ExtensionMessage, Receiver, ReceiverSetup, Sender, SenderSetup,
+    BASE_OT_COUNT,
+};
+
 use super::role::Role;
 use crate::arith::Modulus;
 use crate::random::prf::*;
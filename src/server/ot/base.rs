@@ -0,0 +1,244 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::extension::{
+    receive, send, unexpected, ExtensionMessage, Receiver, ReceiverSetup, Sender, SenderSetup,
+    BASE_OT_COUNT,
+};
+use crate::multi_party::communication::CommunicationChannel;
+use crate::multi_party::protocol::ProtocolError;
+use crate::random::prf::PRFKey;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use rand::{CryptoRng, Rng};
+use sha2::{Digest, Sha256};
+
+/// The base OT sender of the simplest OT protocol of Chou and Orlandi on the Ristretto group
+/// of Curve25519. The extension `Receiver` plays this role for `BASE_OT_COUNT` base OTs at
+/// once, so the two parties of an OT extension need no dealer.
+///
+/// The sender sends `S = y * B` in an `ExtensionMessage::BaseSenderPoint`. For each base OT
+/// the receiver with choice bit `c` answers with `R = c * S + x * B`, and both parties hash
+/// the Diffie-Hellman keys: the sender's keys are `H(y * R)` and `H(y * (R - S))`, and the
+/// receiver's key is `H(x * S)`, which equals the one it chose. The protocol is secure
+/// against semi-honest parties.
+pub struct BaseOtSender {
+    /// The secret scalar `y`.
+    secret: Scalar,
+
+    /// The point `S = y * B`.
+    point: RistrettoPoint,
+}
+
+impl BaseOtSender {
+    /// Create a new base OT sender and the `ExtensionMessage::BaseSenderPoint` to send to the
+    /// base OT receiver.
+    pub fn new<R: Rng + CryptoRng + ?Sized>(rng: &mut R) -> (Self, ExtensionMessage) {
+        let secret = random_scalar(rng);
+        let point = RistrettoPoint::mul_base(&secret);
+        let msg = ExtensionMessage::BaseSenderPoint(point.compress().to_bytes());
+        (Self { secret, point }, msg)
+    }
+
+    /// Process the `ExtensionMessage::BaseReceiverPoints` of the base OT receiver to output
+    /// both keys of each base OT.
+    pub fn finish(self, msg: ExtensionMessage) -> Result<ReceiverSetup, String> {
+        let points = match msg {
+            ExtensionMessage::BaseReceiverPoints(points) => points,
+            msg => {
+                return Err(format!(
+                    "BaseOtSender::finish called with incorrect message type {:?}.",
+                    msg
+                ))
+            }
+        };
+
+        if points.len() != BASE_OT_COUNT {
+            return Err(format!(
+                "BaseOtSender::finish called with {} points (expected {}).",
+                points.len(),
+                BASE_OT_COUNT
+            ));
+        }
+
+        let mut keys = Vec::with_capacity(BASE_OT_COUNT);
+        for (index, bytes) in points.iter().enumerate() {
+            let point = decompress(bytes)?;
+            let key_0 = hash_key(index, &self.point, &point, &(self.secret * point));
+            let key_1 = hash_key(index, &self.point, &point, &(self.secret * (point - self.point)));
+            keys.push((key_0, key_1));
+        }
+
+        Ok(ReceiverSetup { keys })
+    }
+}
+
+/// Run the base OT receiver of the simplest OT protocol, see `BaseOtSender`, on the
+/// `ExtensionMessage::BaseSenderPoint` of the sender. This draws a random choice bit for each
+/// base OT and outputs the chosen keys, together with the `ExtensionMessage::BaseReceiverPoints`
+/// to send back.
+pub fn base_ot_receive<R: Rng + CryptoRng + ?Sized>(
+    rng: &mut R,
+    msg: ExtensionMessage,
+) -> Result<(SenderSetup, ExtensionMessage), String> {
+    let sender_point = match msg {
+        ExtensionMessage::BaseSenderPoint(bytes) => decompress(&bytes)?,
+        msg => {
+            return Err(format!(
+                "base_ot_receive called with incorrect message type {:?}.",
+                msg
+            ))
+        }
+    };
+
+    let mut choices = Vec::with_capacity(BASE_OT_COUNT);
+    let mut keys = Vec::with_capacity(BASE_OT_COUNT);
+    let mut points = Vec::with_capacity(BASE_OT_COUNT);
+    for index in 0..BASE_OT_COUNT {
+        let choice = rng.gen::<bool>();
+        let secret = random_scalar(rng);
+        let mut point = RistrettoPoint::mul_base(&secret);
+        if choice {
+            point += sender_point;
+        }
+
+        choices.push(choice);
+        keys.push(hash_key(index, &sender_point, &point, &(secret * sender_point)));
+        points.push(point.compress().to_bytes());
+    }
+
+    Ok((
+        SenderSetup { choices, keys },
+        ExtensionMessage::BaseReceiverPoints(points),
+    ))
+}
+
+impl Sender {
+    /// Run the base OTs with the receiver at the other end of `channel` and create a sender,
+    /// without a dealer. The receiver runs `Receiver::setup_over` at the same time.
+    pub async fn setup_over<R: Rng + CryptoRng + ?Sized>(
+        rng: &mut R,
+        channel: &mut CommunicationChannel,
+        local_id: usize,
+    ) -> Result<Self, ProtocolError> {
+        let (setup, points) =
+            base_ot_receive(rng, receive(channel).await?).map_err(ProtocolError::MalformedMessage)?;
+        send(channel, local_id, points).await?;
+        Self::new(setup).map_err(|message| ProtocolError::InternalError { message })
+    }
+}
+
+impl Receiver {
+    /// Run the base OTs with the sender at the other end of `channel` and create a receiver,
+    /// without a dealer. The sender runs `Sender::setup_over` at the same time.
+    pub async fn setup_over<R: Rng + CryptoRng + ?Sized>(
+        rng: &mut R,
+        channel: &mut CommunicationChannel,
+        local_id: usize,
+    ) -> Result<Self, ProtocolError> {
+        let (base_sender, point) = BaseOtSender::new(rng);
+        send(channel, local_id, point).await?;
+        let setup = match receive(channel).await? {
+            msg @ ExtensionMessage::BaseReceiverPoints(_) => {
+                base_sender.finish(msg).map_err(ProtocolError::MalformedMessage)?
+            }
+            msg => return Err(unexpected(&msg)),
+        };
+        Self::new(setup).map_err(|message| ProtocolError::InternalError { message })
+    }
+}
+
+/// Sample a uniformly random scalar.
+fn random_scalar<R: Rng + CryptoRng + ?Sized>(rng: &mut R) -> Scalar {
+    let mut bytes = [0u8; 64];
+    rng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+/// Decode a point sent by the other party.
+fn decompress(bytes: &[u8; 32]) -> Result<RistrettoPoint, String> {
+    CompressedRistretto(*bytes)
+        .decompress()
+        .ok_or("Received an invalid Ristretto point.".to_string())
+}
+
+/// Hash the Diffie-Hellman key of base OT `index` into a PRF key, binding it to the points
+/// of the transcript.
+fn hash_key(
+    index: usize,
+    sender_point: &RistrettoPoint,
+    receiver_point: &RistrettoPoint,
+    key: &RistrettoPoint,
+) -> PRFKey {
+    Sha256::new()
+        .chain_update(b"simplest ot")
+        .chain_update((index as u64).to_le_bytes())
+        .chain_update(sender_point.compress().as_bytes())
+        .chain_update(receiver_point.compress().as_bytes())
+        .chain_update(key.compress().as_bytes())
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::runtime;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_base_ot() {
+        let mut rng = OsRng;
+        let (base_sender, point) = BaseOtSender::new(&mut rng);
+        let (sender_setup, points) = base_ot_receive(&mut rng, point.clone()).unwrap();
+        let receiver_setup = base_sender.finish(points).unwrap();
+
+        for i in 0..BASE_OT_COUNT {
+            let (key_0, key_1) = receiver_setup.keys[i];
+            let (chosen, other) = if sender_setup.choices[i] { (key_1, key_0) } else { (key_0, key_1) };
+            assert_eq!(sender_setup.keys[i], chosen);
+            assert_ne!(sender_setup.keys[i], other);
+        }
+
+        // The base OTs bootstrap the OT extension.
+        let mut sender = Sender::new(sender_setup).unwrap();
+        let mut receiver = Receiver::new(receiver_setup).unwrap();
+        let messages = vec![(vec![1, 2], vec![3, 4]), (vec![5, 6], vec![7, 8])];
+        let columns = receiver.choose(&[true, false]).unwrap();
+        let payloads = sender.send(columns, &messages).unwrap();
+        assert_eq!(receiver.receive(payloads).unwrap(), vec![vec![3, 4], vec![5, 6]]);
+
+        // Invalid points and messages are rejected.
+        assert!(base_ot_receive(&mut rng, ExtensionMessage::BaseSenderPoint([0xff; 32])).is_err());
+        assert!(base_ot_receive(&mut rng, ExtensionMessage::BaseReceiverPoints(Vec::new())).is_err());
+        let (base_sender, _) = BaseOtSender::new(&mut rng);
+        assert!(base_sender.finish(point).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_base_ot_over_channels() {
+        let (tx_01, rx_01) = runtime::channel(16);
+        let (tx_10, rx_10) = runtime::channel(16);
+        let mut channel_0 = CommunicationChannel::new(1, tx_01, rx_10);
+        let mut channel_1 = CommunicationChannel::new(0, tx_10, rx_01);
+        channel_0.connect().await.unwrap();
+        channel_1.connect().await.unwrap();
+
+        let (mut rng_0, mut rng_1) = (OsRng, OsRng);
+        let (sender, receiver) = tokio::join!(
+            Sender::setup_over(&mut rng_0, &mut channel_0, 0),
+            Receiver::setup_over(&mut rng_1, &mut channel_1, 1)
+        );
+        let (mut sender, mut receiver) = (sender.unwrap(), receiver.unwrap());
+
+        let messages: Vec<(Vec<u8>, Vec<u8>)> = (0..50u8).map(|j| (vec![j], vec![j + 100])).collect();
+        let choices: Vec<bool> = (0..50).map(|j| j % 2 == 1).collect();
+        let (sent, received) = tokio::join!(
+            sender.send_over(&mut channel_0, 0, &messages),
+            receiver.receive_over(&mut channel_1, 1, &choices)
+        );
+        sent.unwrap();
+        let expected: Vec<Vec<u8>> = (0..50u8).map(|j| vec![if j % 2 == 1 { j + 100 } else { j }]).collect();
+        assert_eq!(received.unwrap(), expected);
+    }
+}
@@ -0,0 +1,532 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use crate::multi_party::communication::{CommunicationChannel, MessagePayload, NetworkMessage};
+use crate::multi_party::protocol::ProtocolError;
+use crate::random::prf::{PRFKey, PRF};
+use bincode::Options;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The number of base OTs, i.e., the computational security parameter.
+pub const BASE_OT_COUNT: usize = 128;
+
+/// The number of bytes in a row of the extension matrix.
+const ROW_BYTES: usize = BASE_OT_COUNT / 8;
+
+/// The base OT outputs of the extension `Sender`, who acts as the base OT receiver: a random
+/// choice bit for each base OT and the key it chose.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SenderSetup {
+    pub(super) choices: Vec<bool>,
+    pub(super) keys: Vec<PRFKey>,
+}
+
+/// The base OT inputs of the extension `Receiver`, who acts as the base OT sender: both keys
+/// of each base OT.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReceiverSetup {
+    pub(super) keys: Vec<(PRFKey, PRFKey)>,
+}
+
+/// Deal the base OTs for one pair of extension `Sender` and `Receiver`. This is done by
+/// `Role::Third`, who learns the base OTs but none of the transferred messages, and who must
+/// not collude with either party. Without a dealer, the two parties run the base OTs
+/// themselves with `Sender::setup_over` and `Receiver::setup_over`.
+pub fn deal_base_ots<R: Rng + ?Sized>(rng: &mut R) -> (SenderSetup, ReceiverSetup) {
+    let mut choices = Vec::with_capacity(BASE_OT_COUNT);
+    let mut chosen = Vec::with_capacity(BASE_OT_COUNT);
+    let mut keys = Vec::with_capacity(BASE_OT_COUNT);
+    for _ in 0..BASE_OT_COUNT {
+        let (key_0, key_1): (PRFKey, PRFKey) = (rng.gen(), rng.gen());
+        let choice = rng.gen::<bool>();
+        choices.push(choice);
+        chosen.push(if choice { key_1 } else { key_0 });
+        keys.push((key_0, key_1));
+    }
+
+    (SenderSetup { choices, keys: chosen }, ReceiverSetup { keys })
+}
+
+/// The messages of the OT extension protocol.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ExtensionMessage {
+    /// Base OT outputs dealt to the `Sender`.
+    SenderSetup(SenderSetup),
+    /// Base OT inputs dealt to the `Receiver`.
+    ReceiverSetup(ReceiverSetup),
+    /// The compressed point `S` of a `BaseOtSender`.
+    BaseSenderPoint([u8; 32]),
+    /// The compressed points `R` of the base OT receiver, one per base OT.
+    BaseReceiverPoints(Vec<[u8; 32]>),
+    /// The columns `u_i = G(k_i^0) ^ G(k_i^1) ^ r` of a batch, sent by the `Receiver`.
+    Columns(Vec<Vec<u8>>),
+    /// The message pairs of a batch masked with the hashed rows, sent by the `Sender`.
+    Payloads(Vec<(Vec<u8>, Vec<u8>)>),
+}
+
+impl ExtensionMessage {
+    /// Encode the message compactly, e.g. for the payload of a `NetworkMessage` in a binary
+    /// codec.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        bincode::DefaultOptions::new()
+            .serialize(self)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Decode a message encoded with `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        // The limit also bounds the lengths claimed inside the message.
+        bincode::DefaultOptions::new()
+            .with_limit(bytes.len() as u64)
+            .reject_trailing_bytes()
+            .deserialize(bytes)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// The sender of the IKNP OT extension. From `BASE_OT_COUNT` base OTs it transfers any number
+/// of message pairs, one message of each pair to the `Receiver`, with only symmetric-key
+/// operations per transfer.
+///
+/// Transfers happen in batches. For each batch the `Receiver` sends an
+/// `ExtensionMessage::Columns` from `Receiver::choose`, which `Sender::send` answers with an
+/// `ExtensionMessage::Payloads`. The protocol is secure against semi-honest parties; a
+/// malicious receiver can learn the base OT choices from inconsistent columns.
+pub struct Sender {
+    /// The base OT choice bits `s`, packed.
+    choices: [u8; ROW_BYTES],
+
+    /// The PRGs of the chosen base OT keys.
+    prgs: Vec<PRF>,
+
+    /// The index of the next batch.
+    batch: u64,
+}
+
+impl Sender {
+    /// Create a new sender from its base OT outputs.
+    pub fn new(setup: SenderSetup) -> Result<Self, String> {
+        if setup.choices.len() != BASE_OT_COUNT || setup.keys.len() != BASE_OT_COUNT {
+            return Err(format!(
+                "Sender::new called with {} choices and {} keys (expected {}).",
+                setup.choices.len(),
+                setup.keys.len(),
+                BASE_OT_COUNT
+            ));
+        }
+
+        let mut choices = [0u8; ROW_BYTES];
+        for (i, &choice) in setup.choices.iter().enumerate() {
+            set_bit(&mut choices, i, choice);
+        }
+
+        Ok(Self {
+            choices,
+            prgs: setup.keys.iter().map(PRF::new).collect(),
+            batch: 0,
+        })
+    }
+
+    /// Process the `ExtensionMessage::Columns` of the next batch and mask the message pairs
+    /// `messages`, one pair per choice of the `Receiver`. The two messages of a pair must have
+    /// the same length.
+    pub fn send(
+        &mut self,
+        msg: ExtensionMessage,
+        messages: &[(Vec<u8>, Vec<u8>)],
+    ) -> Result<ExtensionMessage, String> {
+        let columns = match msg {
+            ExtensionMessage::Columns(columns) => columns,
+            msg => {
+                return Err(format!(
+                    "Sender::send called with incorrect message type {:?}.",
+                    msg
+                ))
+            }
+        };
+
+        let count = messages.len();
+        let column_bytes = count.div_ceil(8);
+        if columns.len() != BASE_OT_COUNT || columns.iter().any(|column| column.len() != column_bytes) {
+            return Err(format!(
+                "Sender::send called with columns that do not match {} messages.",
+                count
+            ));
+        }
+
+        // Compute the columns q_i = G(k_i^{s_i}) ^ s_i * u_i.
+        let q: Vec<Vec<u8>> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let mut q = expand(&self.prgs[i], self.batch, column_bytes);
+                if bit(&self.choices, i) {
+                    xor_into(&mut q, column);
+                }
+                q
+            })
+            .collect();
+
+        // Each row is q_j = t_j ^ r_j * s, so the receiver knows the hash of exactly one of
+        // q_j and q_j ^ s.
+        let mut payloads = Vec::with_capacity(count);
+        for (j, (message_0, message_1)) in messages.iter().enumerate() {
+            if message_0.len() != message_1.len() {
+                return Err(format!(
+                    "Sender::send called with messages of different lengths {} and {} at index {}.",
+                    message_0.len(),
+                    message_1.len(),
+                    j
+                ));
+            }
+
+            let row_0 = row(&q, j);
+            let mut row_1 = row_0;
+            xor_into(&mut row_1, &self.choices);
+
+            let mut masked_0 = hash_row(self.batch, j, &row_0, message_0.len());
+            xor_into(&mut masked_0, message_0);
+            let mut masked_1 = hash_row(self.batch, j, &row_1, message_1.len());
+            xor_into(&mut masked_1, message_1);
+            payloads.push((masked_0, masked_1));
+        }
+
+        self.batch += 1;
+        Ok(ExtensionMessage::Payloads(payloads))
+    }
+
+    /// Wait for the base OT outputs dealt by `Role::Third` on `channel` and create a sender.
+    pub async fn receive_setup(channel: &mut CommunicationChannel) -> Result<Self, ProtocolError> {
+        match receive(channel).await? {
+            ExtensionMessage::SenderSetup(setup) => Self::new(setup).map_err(ProtocolError::MalformedMessage),
+            msg => Err(unexpected(&msg)),
+        }
+    }
+
+    /// Run one batch with the receiver at the other end of `channel`: wait for its columns and
+    /// answer with the masked `messages`.
+    pub async fn send_over(
+        &mut self,
+        channel: &mut CommunicationChannel,
+        local_id: usize,
+        messages: &[(Vec<u8>, Vec<u8>)],
+    ) -> Result<(), ProtocolError> {
+        let columns = receive(channel).await?;
+        let payloads = self.send(columns, messages).map_err(ProtocolError::MalformedMessage)?;
+        send(channel, local_id, payloads).await
+    }
+}
+
+/// The receiver of the IKNP OT extension, see `Sender`.
+pub struct Receiver {
+    /// The PRGs of both keys of each base OT.
+    prgs: Vec<(PRF, PRF)>,
+
+    /// The index of the next batch.
+    batch: u64,
+
+    /// The choices and rows t_j of the batch waiting for its payloads.
+    pending: Option<(Vec<bool>, Vec<[u8; ROW_BYTES]>)>,
+}
+
+impl Receiver {
+    /// Create a new receiver from its base OT inputs.
+    pub fn new(setup: ReceiverSetup) -> Result<Self, String> {
+        if setup.keys.len() != BASE_OT_COUNT {
+            return Err(format!(
+                "Receiver::new called with {} key pairs (expected {}).",
+                setup.keys.len(),
+                BASE_OT_COUNT
+            ));
+        }
+
+        Ok(Self {
+            prgs: setup
+                .keys
+                .iter()
+                .map(|(key_0, key_1)| (PRF::new(key_0), PRF::new(key_1)))
+                .collect(),
+            batch: 0,
+            pending: None,
+        })
+    }
+
+    /// Start the next batch with one choice bit per transfer, creating an
+    /// `ExtensionMessage::Columns` to send to the `Sender`.
+    pub fn choose(&mut self, choices: &[bool]) -> Result<ExtensionMessage, String> {
+        if self.pending.is_some() {
+            return Err("Receiver::receive must be called before the next Receiver::choose.".to_string());
+        }
+
+        let column_bytes = choices.len().div_ceil(8);
+        let mut packed = vec![0u8; column_bytes];
+        for (j, &choice) in choices.iter().enumerate() {
+            set_bit(&mut packed, j, choice);
+        }
+
+        // Compute the columns t_i = G(k_i^0) and u_i = t_i ^ G(k_i^1) ^ r.
+        let mut t = Vec::with_capacity(BASE_OT_COUNT);
+        let mut columns = Vec::with_capacity(BASE_OT_COUNT);
+        for (prg_0, prg_1) in &self.prgs {
+            let t_i = expand(prg_0, self.batch, column_bytes);
+            let mut u_i = expand(prg_1, self.batch, column_bytes);
+            xor_into(&mut u_i, &t_i);
+            xor_into(&mut u_i, &packed);
+            t.push(t_i);
+            columns.push(u_i);
+        }
+
+        let rows = (0..choices.len()).map(|j| row(&t, j)).collect();
+        self.pending = Some((choices.to_vec(), rows));
+        Ok(ExtensionMessage::Columns(columns))
+    }
+
+    /// Process the `ExtensionMessage::Payloads` of the pending batch to output the chosen
+    /// message of each pair.
+    pub fn receive(&mut self, msg: ExtensionMessage) -> Result<Vec<Vec<u8>>, String> {
+        let payloads = match msg {
+            ExtensionMessage::Payloads(payloads) => payloads,
+            msg => {
+                return Err(format!(
+                    "Receiver::receive called with incorrect message type {:?}.",
+                    msg
+                ))
+            }
+        };
+
+        let (choices, rows) = self
+            .pending
+            .take()
+            .ok_or("Receiver::choose must be called before Receiver::receive.".to_string())?;
+        if payloads.len() != choices.len() {
+            return Err(format!(
+                "Receiver::receive called with incorrect number of payloads ({} payloads, {} choices).",
+                payloads.len(),
+                choices.len()
+            ));
+        }
+
+        let batch = self.batch;
+        self.batch += 1;
+        Ok(payloads
+            .into_iter()
+            .zip(choices.iter().zip(&rows))
+            .enumerate()
+            .map(|(j, ((masked_0, masked_1), (&choice, row)))| {
+                let mut message = if choice { masked_1 } else { masked_0 };
+                let mask = hash_row(batch, j, row, message.len());
+                xor_into(&mut message, &mask);
+                message
+            })
+            .collect())
+    }
+
+    /// Wait for the base OT inputs dealt by `Role::Third` on `channel` and create a receiver.
+    pub async fn receive_setup(channel: &mut CommunicationChannel) -> Result<Self, ProtocolError> {
+        match receive(channel).await? {
+            ExtensionMessage::ReceiverSetup(setup) => Self::new(setup).map_err(ProtocolError::MalformedMessage),
+            msg => Err(unexpected(&msg)),
+        }
+    }
+
+    /// Run one batch with the sender at the other end of `channel`, returning the chosen
+    /// message of each pair.
+    pub async fn receive_over(
+        &mut self,
+        channel: &mut CommunicationChannel,
+        local_id: usize,
+        choices: &[bool],
+    ) -> Result<Vec<Vec<u8>>, ProtocolError> {
+        let columns = self.choose(choices).map_err(|message| ProtocolError::InternalError { message })?;
+        send(channel, local_id, columns).await?;
+        let payloads = receive(channel).await?;
+        self.receive(payloads).map_err(ProtocolError::MalformedMessage)
+    }
+}
+
+/// Deal base OTs as `Role::Third` to the sender and receiver at the other ends of
+/// `sender_channel` and `receiver_channel`.
+pub async fn send_base_ots<R: Rng + ?Sized>(
+    rng: &mut R,
+    sender_channel: &mut CommunicationChannel,
+    receiver_channel: &mut CommunicationChannel,
+    local_id: usize,
+) -> Result<(), ProtocolError> {
+    let (sender_setup, receiver_setup) = deal_base_ots(rng);
+    send(sender_channel, local_id, ExtensionMessage::SenderSetup(sender_setup)).await?;
+    send(receiver_channel, local_id, ExtensionMessage::ReceiverSetup(receiver_setup)).await
+}
+
+/// Send an OT extension message to the other end of `channel`.
+pub(super) async fn send(channel: &mut CommunicationChannel, local_id: usize, msg: ExtensionMessage) -> Result<(), ProtocolError> {
+    let sequence = channel.next_sequence();
+    let message = NetworkMessage::oblivious_transfer(local_id, channel.target_id, sequence, msg);
+    channel.send(message).await
+}
+
+/// Receive the next OT extension message from `channel`.
+pub(super) async fn receive(channel: &mut CommunicationChannel) -> Result<ExtensionMessage, ProtocolError> {
+    match channel.receive().await? {
+        Some(NetworkMessage {
+            payload: MessagePayload::ObliviousTransfer(msg),
+            ..
+        }) => Ok(msg),
+        Some(message) => Err(ProtocolError::MalformedMessage(format!(
+            "Expected an oblivious transfer message, received a {:?} message.",
+            message.message_type
+        ))),
+        None => Err(ProtocolError::network_error("Channel closed".to_string())),
+    }
+}
+
+/// The error for an OT extension message received out of turn.
+pub(super) fn unexpected(msg: &ExtensionMessage) -> ProtocolError {
+    ProtocolError::MalformedMessage(format!("Unexpected oblivious transfer message {:?}.", msg))
+}
+
+/// Expand a base OT key into `len` pseudo-random bytes for batch `batch`.
+fn expand(prg: &PRF, batch: u64, len: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(len.next_multiple_of(32));
+    let mut counter = 0u64;
+    while output.len() < len {
+        let mut input = [0u8; 16];
+        input[..8].copy_from_slice(&batch.to_le_bytes());
+        input[8..].copy_from_slice(&counter.to_le_bytes());
+        output.extend_from_slice(&prg.eval(&input));
+        counter += 1;
+    }
+    output.truncate(len);
+    output
+}
+
+/// Hash row `j` of batch `batch` into a mask of `len` bytes. This is the correlation-robust
+/// hash that breaks the correlation between q_j and q_j ^ s.
+fn hash_row(batch: u64, j: usize, row: &[u8; ROW_BYTES], len: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(len.next_multiple_of(32));
+    let mut counter = 0u64;
+    while output.len() < len {
+        let digest = Sha256::new()
+            .chain_update(b"iknp")
+            .chain_update(batch.to_le_bytes())
+            .chain_update((j as u64).to_le_bytes())
+            .chain_update(counter.to_le_bytes())
+            .chain_update(row)
+            .finalize();
+        output.extend_from_slice(&digest);
+        counter += 1;
+    }
+    output.truncate(len);
+    output
+}
+
+/// Read row `j` of a matrix given by its `BASE_OT_COUNT` columns.
+fn row(columns: &[Vec<u8>], j: usize) -> [u8; ROW_BYTES] {
+    let mut row = [0u8; ROW_BYTES];
+    for (i, column) in columns.iter().enumerate() {
+        set_bit(&mut row, i, bit(column, j));
+    }
+    row
+}
+
+fn bit(bytes: &[u8], index: usize) -> bool {
+    bytes[index / 8] >> (index % 8) & 1 == 1
+}
+
+fn set_bit(bytes: &mut [u8], index: usize, value: bool) {
+    bytes[index / 8] |= (value as u8) << (index % 8);
+}
+
+fn xor_into(target: &mut [u8], other: &[u8]) {
+    for (target, other) in target.iter_mut().zip(other) {
+        *target ^= other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multi_party::runtime;
+    use rand::rngs::OsRng;
+
+    fn random_pairs(count: usize, len: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut rng = OsRng;
+        (0..count)
+            .map(|_| {
+                let message_0 = (0..len).map(|_| rng.gen()).collect();
+                let message_1 = (0..len).map(|_| rng.gen()).collect();
+                (message_0, message_1)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ot_extension() {
+        let mut rng = OsRng;
+        let (sender_setup, receiver_setup) = deal_base_ots(&mut rng);
+        let mut sender = Sender::new(sender_setup).unwrap();
+        let mut receiver = Receiver::new(receiver_setup).unwrap();
+
+        // Several batches reuse the same base OTs.
+        for (count, len) in [(1000, 8), (13, 40), (0, 16)] {
+            let messages = random_pairs(count, len);
+            let choices: Vec<bool> = (0..count).map(|_| rng.gen()).collect();
+
+            let columns = receiver.choose(&choices).unwrap();
+            let payloads = sender.send(columns, &messages).unwrap();
+            let received = receiver.receive(payloads).unwrap();
+
+            assert_eq!(received.len(), count);
+            for j in 0..count {
+                let (chosen, other) = if choices[j] {
+                    (&messages[j].1, &messages[j].0)
+                } else {
+                    (&messages[j].0, &messages[j].1)
+                };
+                assert_eq!(&received[j], chosen);
+                assert_ne!(&received[j], other);
+            }
+        }
+
+        let columns = receiver.choose(&[true, false]).unwrap();
+        assert!(receiver.choose(&[true]).is_err());
+        assert!(sender.send(columns, &random_pairs(9, 8)).is_err());
+    }
+
+    /// Connect the ends of a channel between servers `a` and `b`.
+    async fn connect(a: usize, b: usize) -> (CommunicationChannel, CommunicationChannel) {
+        let (tx_ab, rx_ab) = runtime::channel(16);
+        let (tx_ba, rx_ba) = runtime::channel(16);
+        let mut channel_a = CommunicationChannel::new(b, tx_ab, rx_ba);
+        let mut channel_b = CommunicationChannel::new(a, tx_ba, rx_ab);
+        channel_a.connect().await.unwrap();
+        channel_b.connect().await.unwrap();
+        (channel_a, channel_b)
+    }
+
+    #[tokio::test]
+    async fn test_ot_extension_over_channels() {
+        // Server 2 deals the base OTs for sender 0 and receiver 1.
+        let (mut channel_0, mut channel_1) = connect(0, 1).await;
+        let (mut from_dealer_0, mut dealer_0) = connect(0, 2).await;
+        let (mut from_dealer_1, mut dealer_1) = connect(1, 2).await;
+
+        send_base_ots(&mut OsRng, &mut dealer_0, &mut dealer_1, 2).await.unwrap();
+        let mut sender = Sender::receive_setup(&mut from_dealer_0).await.unwrap();
+        let mut receiver = Receiver::receive_setup(&mut from_dealer_1).await.unwrap();
+
+        let messages = random_pairs(100, 8);
+        let choices: Vec<bool> = (0..100).map(|j| j % 3 == 0).collect();
+        let (sent, received) = tokio::join!(
+            sender.send_over(&mut channel_0, 0, &messages),
+            receiver.receive_over(&mut channel_1, 1, &choices)
+        );
+        sent.unwrap();
+        let received = received.unwrap();
+        for j in 0..100 {
+            let chosen = if choices[j] { &messages[j].1 } else { &messages[j].0 };
+            assert_eq!(&received[j], chosen);
+        }
+    }
+}
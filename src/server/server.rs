@@ -7,6 +7,7 @@ use super::role::Role;
 use crate::arith::Modulus;
 use crate::random::hist_noise::NoiseDistribution;
 use crate::report::attr::AttrValueType;
+use crate::report::compress::BatchCodec;
 use crate::report::report::Report;
 use crate::report::report_vector::ReportVector;
 use crate::schema::*;
@@ -189,6 +190,24 @@ impl<const REPORT_U32_SIZE: usize> Server<REPORT_U32_SIZE> {
         Ok(self.reports.take().unwrap())
     }
 
+    /// Add reports from a compressed batch, e.g. as received from a client or loaded
+    /// from storage.
+    pub fn add_compressed_reports(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let reports = ReportVector::<REPORT_U32_SIZE>::decompress(bytes)
+            .map_err(|e| format!("Error decompressing reports: {}.", e))?;
+        self.add_reports(reports)
+    }
+
+    /// Encode the current set of reports the `Server` holds as a compressed batch for
+    /// storage. The reports stay in place.
+    pub fn export_compressed_reports(&self, codec: BatchCodec) -> Result<Vec<u8>, String> {
+        if self.reports == None {
+            return Err("No report vector found.".to_string());
+        }
+
+        Ok(self.reports.as_ref().unwrap().compress(codec))
+    }
+
     /// Secret-share the entire `ReportVector`.
     pub(crate) fn share<T>(
         &mut self,
@@ -343,7 +362,7 @@ impl<const REPORT_U32_SIZE: usize> Server<REPORT_U32_SIZE> {
 
         // We have some reports. Split them and create a new `Server` with the split-off.
         let reports = self.reports.as_mut().unwrap();
-        let split_reports = reports.split_at(attr_index, attr_value);
+        let split_reports = reports.split_at_attr(attr_index, attr_value);
         let mut sub_server = Server::new(self.role, sub_schema.clone());
         sub_server.add_reports(split_reports)?;
 
@@ -844,4 +863,28 @@ mod tests {
         let mut server = Server::<1>::new(Role::First, schema.clone());
         assert!(server.add_reports(report_vector).is_err());
     }
+
+    #[test]
+    fn test_compressed_reports() {
+        let schema =
+            Schema::try_from(r#"[["attr1","c2"],["attr2","c10"],["attr3","c4"]]"#).unwrap();
+        let mut rng = rand::rngs::OsRng;
+        let report_vector = ReportVector::<1>::random(&schema.get_attr_types(), &mut rng, 20);
+
+        let mut server = Server::<1>::new(Role::First, schema.clone());
+        assert!(server
+            .export_compressed_reports(BatchCodec::zstd())
+            .is_err());
+        server
+            .add_compressed_reports(&report_vector.compress(BatchCodec::packed()))
+            .unwrap();
+
+        let stored = server
+            .export_compressed_reports(BatchCodec::zstd())
+            .unwrap();
+        let mut restored = Server::<1>::new(Role::First, schema);
+        restored.add_compressed_reports(&stored).unwrap();
+        assert_eq!(restored.extract_reports().unwrap(), report_vector);
+        assert!(restored.add_compressed_reports(&stored[..4]).is_err());
+    }
 }
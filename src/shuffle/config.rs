@@ -1 +1,113 @@
- 
\ No newline at end of file
+use crate::arith::PrivacyBudget;
+use crate::config::{self, ConfigError};
+use crate::schema::Schema;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Configuration for the shuffler
+#[derive(Debug, Clone)]
+pub struct ShuffleConfig {
+    /// Schema the data is validated against, if any
+    pub schema: Option<Schema>,
+    /// Number of shuffle rounds
+    pub shuffle_rounds: usize,
+    /// Privacy budget for the noise added to query results
+    pub privacy_budget: PrivacyBudget,
+}
+
+impl Default for ShuffleConfig {
+    fn default() -> Self {
+        Self {
+            schema: None,
+            shuffle_rounds: 1,
+            privacy_budget: PrivacyBudget::new(1.0, 1e-5),
+        }
+    }
+}
+
+impl ShuffleConfig {
+    /// Create a builder starting from the default configuration
+    pub fn builder() -> ShuffleConfigBuilder {
+        ShuffleConfigBuilder::default()
+    }
+
+    /// Load a configuration from a TOML or YAML file
+    ///
+    /// Missing fields take their default values, e.g.
+    /// `shuffle_rounds = 5`, `epsilon = 0.5`, `delta = 1e-6`, and
+    /// `schema = [["age", "c7"]]`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let file: ShuffleConfigFile = config::load(path.as_ref(), ShuffleConfigFile::validate)?;
+        Ok(Self {
+            schema: file.schema,
+            shuffle_rounds: file.shuffle_rounds,
+            privacy_budget: PrivacyBudget::new(file.epsilon, file.delta),
+        })
+    }
+}
+
+/// Builder for `ShuffleConfig`
+#[derive(Debug, Clone, Default)]
+pub struct ShuffleConfigBuilder {
+    config: ShuffleConfig,
+}
+
+impl ShuffleConfigBuilder {
+    /// Validate data against a schema
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.config.schema = Some(schema);
+        self
+    }
+
+    /// Set the number of shuffle rounds
+    pub fn shuffle_rounds(mut self, shuffle_rounds: usize) -> Self {
+        self.config.shuffle_rounds = shuffle_rounds;
+        self
+    }
+
+    /// Set the privacy budget
+    pub fn privacy_budget(mut self, privacy_budget: PrivacyBudget) -> Self {
+        self.config.privacy_budget = privacy_budget;
+        self
+    }
+
+    /// Build the configuration
+    pub fn build(self) -> ShuffleConfig {
+        self.config
+    }
+}
+
+/// `ShuffleConfig` as written in a configuration file
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ShuffleConfigFile {
+    schema: Option<Schema>,
+    shuffle_rounds: usize,
+    epsilon: f64,
+    delta: f64,
+}
+
+impl Default for ShuffleConfigFile {
+    fn default() -> Self {
+        Self {
+            schema: None,
+            shuffle_rounds: 1,
+            epsilon: 1.0,
+            delta: 1e-5,
+        }
+    }
+}
+
+impl ShuffleConfigFile {
+    fn validate(&self) -> Result<(), String> {
+        if self.shuffle_rounds == 0 {
+            return Err("shuffle_rounds must be at least 1".to_string());
+        }
+        if let Some(schema) = &self.schema {
+            if !schema.is_valid() {
+                return Err("schema is invalid".to_string());
+            }
+        }
+        config::validate_budget(self.epsilon, self.delta)
+    }
+}
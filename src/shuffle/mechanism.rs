@@ -17,6 +17,11 @@ impl ShuffleMechanism {
     }
 
     pub fn shuffle(&mut self, mut data: Vec<DataPoint>, rounds: usize) -> Result<Vec<DataPoint>, ShuffleError> {
+        self.shuffle_in_place(&mut data, rounds)?;
+        Ok(data)
+    }
+
+    pub fn shuffle_in_place(&mut self, data: &mut [DataPoint], rounds: usize) -> Result<(), ShuffleError> {
         if data.is_empty() {
             return Err(ShuffleError::InvalidInput);
         }
@@ -25,7 +30,7 @@ impl ShuffleMechanism {
             data.shuffle(&mut self.rng);
         }
 
-        Ok(data)
+        Ok(())
     }
 
     pub fn process_query(&self, query: Query, data: Vec<DataPoint>, config: &super::ShuffleConfig) -> Result<QueryResult, ShuffleError> {
@@ -3,13 +3,14 @@ mod config;
 mod types;
 mod error;
 
-pub use config::ShuffleConfig;
+pub use config::{ShuffleConfig, ShuffleConfigBuilder};
 pub use types::{ShuffleData, ShuffleResult};
 pub use error::ShuffleError;
 pub use mechanism::ShuffleMechanism;
 
 use crate::arith::PrivacyBudget;
 use crate::schema::{DataPoint, Query, QueryResult};
+use crate::telemetry;
 
 /// Main shuffler that orchestrates the shuffle differential privacy process
 pub struct Shuffler {
@@ -32,37 +33,44 @@ impl Shuffler {
     }
 
     /// Shuffle data with privacy guarantees
-    pub fn shuffle_data(&mut self, data: Vec<DataPoint>) -> Result<Vec<DataPoint>, ShuffleError> {
-        if data.is_empty() {
-            return Err(ShuffleError::EmptyInput);
-        }
+    pub fn shuffle_data(&mut self, mut data: Vec<DataPoint>) -> Result<Vec<DataPoint>, ShuffleError> {
+        self.shuffle_in_place(&mut data)?;
+        Ok(data)
+    }
 
-        // Validate data against schema if provided
-        if let Some(schema) = &self.config.schema {
-            self.validate_data_against_schema(&data, schema)?;
-        }
+    /// Shuffle data in place with privacy guarantees
+    ///
+    /// Later stages can read the shuffled data through borrowed batches, e.g.
+    /// `data.chunks(batch_size)`, instead of taking an owned copy.
+    pub fn shuffle_in_place(&mut self, data: &mut [DataPoint]) -> Result<(), ShuffleError> {
+        telemetry::record("shuffle", "shuffle_data", data.len(), || {
+            if data.is_empty() {
+                return Err(ShuffleError::EmptyInput);
+            }
 
-        // Apply shuffle mechanism
-        let shuffled_data = self.mechanism.shuffle(
-            data, 
-            self.config.shuffle_rounds,
-            &self.config.privacy_budget
-        )?;
+            // Validate data against schema if provided
+            if let Some(schema) = &self.config.schema {
+                self.validate_data_against_schema(data, schema)?;
+            }
 
-        Ok(shuffled_data)
+            // Apply shuffle mechanism
+            self.mechanism.shuffle_in_place(data, self.config.shuffle_rounds)
+        })
     }
 
     /// Process a query with shuffle differential privacy
     pub fn process_query(&self, query: Query, data: Vec<DataPoint>) -> Result<QueryResult, ShuffleError> {
-        if data.is_empty() {
-            return Err(ShuffleError::EmptyInput);
-        }
+        telemetry::record("shuffle", "process_query", data.len(), || {
+            if data.is_empty() {
+                return Err(ShuffleError::EmptyInput);
+            }
 
-        // Validate query
-        self.validate_query(&query)?;
+            // Validate query
+            self.validate_query(&query)?;
 
-        // Process query with shuffle mechanism
-        self.mechanism.process_query(query, data, &self.config)
+            // Process query with shuffle mechanism
+            self.mechanism.process_query(query, data, &self.config)
+        })
     }
 
     /// Get the current configuration
@@ -140,6 +148,12 @@ mod tests {
 
         let shuffled = shuffler.shuffle_data(data).unwrap();
         assert_eq!(shuffled.len(), 3);
+
+        let mut data = shuffled;
+        shuffler.shuffle_in_place(&mut data).unwrap();
+        let mut firsts: Vec<f64> = data.iter().map(|point| point.features()[0]).collect();
+        firsts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(firsts, vec![1.0, 3.0, 5.0]);
     }
 
     #[test]
@@ -0,0 +1,564 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! In-process simulation of the shuffle-model pipeline under attack.
+//!
+//! A `Simulation` runs synthetic clients, the shuffler, and three MPC servers in one
+//! process. Every client Shamir-shares its values 2-out-of-3, the shuffler permutes
+//! the submissions and forwards each server its shares as `NetworkMessage`s, and the
+//! servers sum their shares locally so that any two of them can open the sums. Each
+//! message passes through an `Adversary`, which may drop or modify it, and whose
+//! corrupted servers pool their views. A `SimulationReport` tells whether the result
+//! is correct or the attack was detected, and `Simulation::advantage` measures how
+//! well a distinguisher tells two input datasets apart from a coalition's view.
+
+use crate::multi_party::communication::{MessagePayload, NetworkMessage};
+use crate::multi_party::crypto::{SecretShare, ShamirSecretSharing};
+use crate::multi_party::share::DataShare;
+use crate::multi_party::ProtocolError;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+
+/// Number of MPC servers.
+pub const NUM_SERVERS: usize = 3;
+
+/// Number of servers needed to open a value.
+pub const THRESHOLD: usize = 2;
+
+/// Sender ID of the shuffler in the messages it forwards to the servers.
+pub const SHUFFLER_ID: usize = NUM_SERVERS;
+
+/// What happens to an intercepted message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intercept {
+    /// Deliver the message, possibly modified.
+    Deliver,
+    /// Drop the message.
+    Drop,
+}
+
+/// An `Adversary` controls the network between the shuffler and the servers, and
+/// sees everything its corrupted servers receive.
+pub trait Adversary {
+    /// Return the servers whose views the adversary pools.
+    fn corrupted_servers(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    /// Inspect a message from the shuffler to a server, and modify or drop it.
+    fn intercept(&mut self, _message: &mut NetworkMessage) -> Intercept {
+        Intercept::Deliver
+    }
+}
+
+/// An adversary that corrupts nothing and delivers every message.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Passive;
+
+impl Adversary for Passive {}
+
+/// A coalition of semi-honest servers that pool their views.
+#[derive(Debug, Clone)]
+pub struct Collusion {
+    servers: Vec<usize>,
+}
+
+impl Collusion {
+    /// Create a coalition of the given servers.
+    pub fn new(servers: Vec<usize>) -> Self {
+        Self { servers }
+    }
+}
+
+impl Adversary for Collusion {
+    fn corrupted_servers(&self) -> Vec<usize> {
+        self.servers.clone()
+    }
+}
+
+/// Drops each message to the target servers with a fixed probability.
+#[derive(Debug, Clone)]
+pub struct DropMessages {
+    targets: Vec<usize>,
+    probability: f64,
+    rng: StdRng,
+}
+
+impl DropMessages {
+    /// Drop messages to `targets` with the given probability, using a seeded RNG.
+    pub fn new(targets: Vec<usize>, probability: f64, seed: u64) -> Self {
+        Self {
+            targets,
+            probability,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Adversary for DropMessages {
+    fn intercept(&mut self, message: &mut NetworkMessage) -> Intercept {
+        if self.targets.contains(&message.target_id) && self.rng.gen_bool(self.probability) {
+            Intercept::Drop
+        } else {
+            Intercept::Deliver
+        }
+    }
+}
+
+/// Adds an offset to every share sent to the target server, as a malicious shuffler
+/// or network would to bias the result.
+#[derive(Debug, Clone)]
+pub struct SubstituteShares {
+    target: usize,
+    offset: u64,
+}
+
+impl SubstituteShares {
+    /// Add `offset` to the shares sent to `target`.
+    pub fn new(target: usize, offset: u64) -> Self {
+        Self { target, offset }
+    }
+}
+
+impl Adversary for SubstituteShares {
+    fn intercept(&mut self, message: &mut NetworkMessage) -> Intercept {
+        if message.target_id != self.target {
+            return Intercept::Deliver;
+        }
+
+        if let MessagePayload::Shares(encoded) = &mut message.payload {
+            for bytes in encoded.iter_mut() {
+                if let Some(mut share) = decode_share(bytes) {
+                    share.value = ((share.value as u128 + self.offset as u128)
+                        % share.modulus.max(1) as u128) as u64;
+                    *bytes = encode_share(&share);
+                }
+            }
+        }
+        Intercept::Deliver
+    }
+}
+
+/// Two adversaries acting together: the views are pooled, and a message is
+/// intercepted by the first and then, unless dropped, by the second.
+impl<A: Adversary, B: Adversary> Adversary for (A, B) {
+    fn corrupted_servers(&self) -> Vec<usize> {
+        let mut servers = self.0.corrupted_servers();
+        servers.extend(self.1.corrupted_servers());
+        servers.sort_unstable();
+        servers.dedup();
+        servers
+    }
+
+    fn intercept(&mut self, message: &mut NetworkMessage) -> Intercept {
+        match self.0.intercept(message) {
+            Intercept::Drop => Intercept::Drop,
+            Intercept::Deliver => self.1.intercept(message),
+        }
+    }
+}
+
+fn encode_share(share: &DataShare) -> Vec<u8> {
+    serde_json::to_vec(share).expect("DataShare serializes to JSON")
+}
+
+fn decode_share(bytes: &[u8]) -> Option<DataShare> {
+    serde_json::from_slice(bytes).ok()
+}
+
+/// A share received by a corrupted server.
+#[derive(Debug, Clone)]
+pub struct ObservedShare {
+    /// Position of the submission in the shuffled order.
+    pub submission: u64,
+    /// The share as received.
+    pub share: DataShare,
+}
+
+/// Parameters of a simulation.
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    /// Number of synthetic clients.
+    pub num_clients: usize,
+    /// Number of values each client submits.
+    pub num_features: usize,
+    /// Largest value a synthetic client submits.
+    pub max_value: u64,
+    /// Prime modulus of the secret sharing; must exceed every sum.
+    pub modulus: u64,
+    /// Seed for the synthetic inputs, the shuffle, and the adversary's randomness.
+    pub seed: u64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            num_clients: 100,
+            num_features: 2,
+            max_value: 100,
+            modulus: 2_147_483_647,
+            seed: 0,
+        }
+    }
+}
+
+/// The outcome of a simulation run.
+#[derive(Debug, Clone)]
+pub struct SimulationReport {
+    /// The values the clients submitted.
+    pub inputs: Vec<Vec<u64>>,
+    /// The true per-feature sums.
+    pub expected: Vec<u64>,
+    /// The per-feature sums opened by the servers, or why they could not be opened.
+    pub outcome: Result<Vec<u64>, ProtocolError>,
+    /// Number of messages delivered to the servers.
+    pub delivered: usize,
+    /// Number of messages dropped.
+    pub dropped: usize,
+    /// Everything the corrupted servers received.
+    pub coalition_view: Vec<ObservedShare>,
+}
+
+impl SimulationReport {
+    /// Return whether the servers opened the true sums.
+    pub fn is_correct(&self) -> bool {
+        matches!(&self.outcome, Ok(sums) if *sums == self.expected)
+    }
+
+    /// Return whether the servers detected inconsistent shares.
+    pub fn cheating_detected(&self) -> bool {
+        matches!(&self.outcome, Err(e) if e.is_cheating())
+    }
+}
+
+/// Runs the pipeline in one process, see the module documentation.
+pub struct Simulation {
+    config: SimulationConfig,
+    scheme: ShamirSecretSharing,
+    rng: StdRng,
+}
+
+impl Simulation {
+    /// Create a simulation. The modulus must be a prime larger than the sum of the
+    /// largest possible inputs.
+    pub fn new(config: SimulationConfig) -> Result<Self, ProtocolError> {
+        let max_sum = (config.num_clients as u128) * (config.max_value as u128);
+        if max_sum >= config.modulus as u128 {
+            return Err(ProtocolError::InvalidConfiguration(format!(
+                "Modulus {} does not exceed the largest sum {}",
+                config.modulus, max_sum
+            )));
+        }
+
+        let scheme = ShamirSecretSharing::new(THRESHOLD, NUM_SERVERS, config.modulus)?;
+        let rng = StdRng::seed_from_u64(config.seed);
+        Ok(Self {
+            config,
+            scheme,
+            rng,
+        })
+    }
+
+    /// Draw inputs for the synthetic clients.
+    pub fn random_inputs(&mut self) -> Vec<Vec<u64>> {
+        (0..self.config.num_clients)
+            .map(|_| {
+                (0..self.config.num_features)
+                    .map(|_| self.rng.gen_range(0..=self.config.max_value))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Run the pipeline with inputs drawn for the synthetic clients.
+    pub fn run(
+        &mut self,
+        adversary: &mut impl Adversary,
+    ) -> Result<SimulationReport, ProtocolError> {
+        let inputs = self.random_inputs();
+        self.run_with_inputs(inputs, adversary)
+    }
+
+    /// Run the pipeline with one input vector per client, each with `num_features`
+    /// values of at most `max_value`.
+    pub fn run_with_inputs(
+        &mut self,
+        inputs: Vec<Vec<u64>>,
+        adversary: &mut impl Adversary,
+    ) -> Result<SimulationReport, ProtocolError> {
+        let num_features = self.config.num_features;
+        if inputs.len() > self.config.num_clients
+            || inputs.iter().any(|values| {
+                values.len() != num_features || values.iter().any(|&v| v > self.config.max_value)
+            })
+        {
+            return Err(ProtocolError::InvalidConfiguration(
+                "Inputs do not match the simulation configuration".to_string(),
+            ));
+        }
+
+        let mut expected = vec![0u64; num_features];
+        for values in &inputs {
+            for (sum, value) in expected.iter_mut().zip(values) {
+                *sum += value;
+            }
+        }
+
+        // Clients: share every value, with share `i` destined for server `i`.
+        let mut submissions = Vec::with_capacity(inputs.len());
+        for values in &inputs {
+            let mut per_server = vec![Vec::with_capacity(num_features); NUM_SERVERS];
+            for (feature_index, &value) in values.iter().enumerate() {
+                for share in self.scheme.share_secret(value)? {
+                    per_server[share.id].push(DataShare::feature(
+                        share.id,
+                        feature_index,
+                        share.value,
+                        self.config.modulus,
+                    ));
+                }
+            }
+            submissions.push(per_server);
+        }
+
+        // Shuffler: permute the submissions, then forward each server its shares.
+        submissions.shuffle(&mut self.rng);
+        let corrupted = adversary.corrupted_servers();
+        let mut received: Vec<HashMap<u64, Vec<DataShare>>> = vec![HashMap::new(); NUM_SERVERS];
+        let mut coalition_view = Vec::new();
+        let (mut delivered, mut dropped) = (0, 0);
+        for (submission, per_server) in submissions.into_iter().enumerate() {
+            let submission = submission as u64;
+            for (server_id, shares) in per_server.into_iter().enumerate() {
+                let payload = shares.iter().map(encode_share).collect();
+                let mut message =
+                    NetworkMessage::share(SHUFFLER_ID, server_id, submission, payload);
+                if adversary.intercept(&mut message) == Intercept::Drop {
+                    dropped += 1;
+                    continue;
+                }
+                delivered += 1;
+
+                // Servers: honest servers ignore shares they cannot decode.
+                let target = message.target_id;
+                let MessagePayload::Shares(encoded) = message.payload else {
+                    continue;
+                };
+                let shares = encoded
+                    .iter()
+                    .filter_map(|bytes| decode_share(bytes))
+                    .collect::<Vec<_>>();
+                if target >= NUM_SERVERS {
+                    continue;
+                }
+                if corrupted.contains(&target) {
+                    coalition_view.extend(shares.iter().map(|share| ObservedShare {
+                        submission: message.sequence,
+                        share: share.clone(),
+                    }));
+                }
+                received[target].insert(message.sequence, shares);
+            }
+        }
+
+        let outcome = self.open_sums(&received, inputs.len() as u64);
+        Ok(SimulationReport {
+            inputs,
+            expected,
+            outcome,
+            delivered,
+            dropped,
+            coalition_view,
+        })
+    }
+
+    /// Open the per-feature sums. Submissions are grouped by the servers that hold
+    /// them; each group's holders sum their shares, and the group sum is opened from
+    /// every quorum of holders, which must agree.
+    fn open_sums(
+        &self,
+        received: &[HashMap<u64, Vec<DataShare>>],
+        num_submissions: u64,
+    ) -> Result<Vec<u64>, ProtocolError> {
+        let mut groups: BTreeMap<Vec<usize>, Vec<u64>> = BTreeMap::new();
+        for submission in 0..num_submissions {
+            let holders = (0..NUM_SERVERS)
+                .filter(|&server_id| received[server_id].contains_key(&submission))
+                .collect::<Vec<_>>();
+            if holders.len() < THRESHOLD {
+                return Err(ProtocolError::InsufficientServers {
+                    available: holders.len(),
+                    required: THRESHOLD,
+                });
+            }
+            groups.entry(holders).or_default().push(submission);
+        }
+
+        let num_features = self.config.num_features;
+        let mut sums = vec![0u64; num_features];
+        for (holders, submissions) in &groups {
+            let local_sums = holders
+                .iter()
+                .map(|&server_id| {
+                    let mut local = vec![0u64; num_features];
+                    for submission in submissions {
+                        for share in &received[server_id][submission] {
+                            if let Some(sum) = local.get_mut(share.feature_index) {
+                                *sum = ((*sum as u128 + share.value as u128)
+                                    % self.config.modulus as u128)
+                                    as u64;
+                            }
+                        }
+                    }
+                    local
+                })
+                .collect::<Vec<_>>();
+
+            for (feature_index, total) in sums.iter_mut().enumerate() {
+                let mut opened = None;
+                for first in 0..holders.len() {
+                    for second in first + 1..holders.len() {
+                        let quorum = [first, second].map(|i| {
+                            SecretShare::new(
+                                holders[i],
+                                local_sums[i][feature_index],
+                                0,
+                                self.config.modulus,
+                            )
+                        });
+                        let value = self.scheme.reconstruct_secret(&quorum)?;
+                        if opened.is_some_and(|opened| opened != value) {
+                            return Err(ProtocolError::cheat_detected(
+                                feature_index,
+                                holders.clone(),
+                            ));
+                        }
+                        opened = Some(value);
+                    }
+                }
+                *total += opened.unwrap_or_default();
+            }
+        }
+
+        Ok(sums)
+    }
+
+    /// Estimate the advantage of a distinguisher that sees a coalition's view in
+    /// telling two input datasets apart: the difference between how often it returns
+    /// `true` on views of `inputs_a` and of `inputs_b`, over `trials` runs each. A
+    /// coalition of fewer than `THRESHOLD` servers should have an advantage close to
+    /// zero for every distinguisher.
+    pub fn advantage<A: Adversary>(
+        &mut self,
+        inputs_a: &[Vec<u64>],
+        inputs_b: &[Vec<u64>],
+        trials: usize,
+        mut make_adversary: impl FnMut() -> A,
+        distinguisher: impl Fn(&[ObservedShare]) -> bool,
+    ) -> Result<f64, ProtocolError> {
+        let mut accepted = [0usize; 2];
+        for _ in 0..trials {
+            for (count, inputs) in accepted.iter_mut().zip([inputs_a, inputs_b]) {
+                let report = self.run_with_inputs(inputs.to_vec(), &mut make_adversary())?;
+                if distinguisher(&report.coalition_view) {
+                    *count += 1;
+                }
+            }
+        }
+
+        let trials = trials.max(1) as f64;
+        Ok((accepted[0] as f64 - accepted[1] as f64).abs() / trials)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulation() -> Simulation {
+        Simulation::new(SimulationConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn test_honest_run() {
+        let report = simulation().run(&mut Passive).unwrap();
+        assert!(report.is_correct());
+        assert_eq!(report.delivered, 300);
+        assert!(report.coalition_view.is_empty());
+    }
+
+    #[test]
+    fn test_dropped_messages() {
+        // All messages to one server: the other two still open the sums.
+        let report = simulation()
+            .run(&mut DropMessages::new(vec![2], 1.0, 1))
+            .unwrap();
+        assert!(report.is_correct());
+        assert_eq!(report.dropped, 100);
+
+        // Some messages to each server: submissions held by one server are lost.
+        let report = simulation()
+            .run(&mut DropMessages::new(vec![0, 1, 2], 0.5, 1))
+            .unwrap();
+        assert!(matches!(
+            report.outcome,
+            Err(ProtocolError::InsufficientServers { .. })
+        ));
+    }
+
+    #[test]
+    fn test_substituted_shares_are_detected() {
+        let report = simulation()
+            .run(&mut (Collusion::new(vec![1]), SubstituteShares::new(1, 5)))
+            .unwrap();
+        assert!(report.cheating_detected());
+        assert_eq!(report.coalition_view.len(), 200);
+    }
+
+    #[test]
+    fn test_coalition_views() {
+        let config = SimulationConfig {
+            num_clients: 1,
+            num_features: 1,
+            ..SimulationConfig::default()
+        };
+        let mut simulation = Simulation::new(config).unwrap();
+        let (inputs_a, inputs_b) = (vec![vec![0]], vec![vec![1]]);
+
+        // A single server sees a uniformly random share, whatever the input.
+        let advantage = simulation
+            .advantage(
+                &inputs_a,
+                &inputs_b,
+                500,
+                || Collusion::new(vec![0]),
+                |view| view[0].share.value % 2 == 0,
+            )
+            .unwrap();
+        assert!(advantage < 0.15);
+
+        // Two servers reach the threshold and recover the input.
+        let scheme = ShamirSecretSharing::new(THRESHOLD, NUM_SERVERS, 2_147_483_647).unwrap();
+        let advantage = simulation
+            .advantage(
+                &inputs_a,
+                &inputs_b,
+                20,
+                || Collusion::new(vec![0, 1]),
+                |view| {
+                    let shares = view
+                        .iter()
+                        .map(|observed| {
+                            let share = &observed.share;
+                            SecretShare::new(share.server_id, share.value, 0, share.modulus)
+                        })
+                        .collect::<Vec<_>>();
+                    scheme.reconstruct_secret(&shares).unwrap() == 0
+                },
+            )
+            .unwrap();
+        assert_eq!(advantage, 1.0);
+    }
+}
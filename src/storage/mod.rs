@@ -0,0 +1,361 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Persistent storage for shares, reports, and checkpoints.
+//!
+//! A `BlobStore` stores opaque byte blobs under string keys. The typed traits
+//! `ShareStorage`, `ReportStorage`, and `CheckpointStorage` are implemented for
+//! every `BlobStore` and fix the key layout, so a server only needs a blob backend
+//! to persist its state. With all state in an object store, such as the
+//! S3-compatible `ObjectStorage` behind the `object-store` feature, server nodes
+//! are stateless and can be replaced at any time.
+
+#[cfg(feature = "object-store")]
+pub mod object;
+
+#[cfg(feature = "object-store")]
+pub use object::{ObjectStorage, ObjectStorageConfig};
+
+use crate::multi_party::share::DataShare;
+use crate::report::compress::BatchCodec;
+use crate::report::report_vector::ReportVector;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Errors that can occur when storing or loading blobs.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// The backend failed
+    #[error("Storage backend error: {0}")]
+    Backend(String),
+
+    /// The backend configuration is invalid
+    #[error("Invalid storage configuration: {0}")]
+    InvalidConfig(String),
+
+    /// A stored blob could not be decoded
+    #[error("Blob {key} could not be decoded: {message}")]
+    Decode { key: String, message: String },
+}
+
+/// Asynchronous storage of byte blobs under string keys.
+pub trait BlobStore: Send + Sync {
+    /// Store a blob, replacing any blob with the same key.
+    fn put<'a>(&'a self, key: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    /// Load a blob, or `None` if there is no blob with the key.
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, StorageError>>;
+
+    /// Delete a blob. Deleting a missing blob is not an error.
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    /// Return the keys starting with `prefix`, in increasing order.
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<String>, StorageError>>;
+}
+
+/// Key of the shares a server holds for an epoch. Epochs are zero-padded so keys
+/// list in epoch order.
+fn share_key(server_id: usize, epoch: u64) -> String {
+    format!("shares/{}/{:020}", server_id, epoch)
+}
+
+fn report_key(epoch: u64) -> String {
+    format!("reports/{:020}", epoch)
+}
+
+fn checkpoint_key(name: &str) -> String {
+    format!("checkpoints/{}", name)
+}
+
+fn decode_error(key: &str, error: impl std::fmt::Display) -> StorageError {
+    StorageError::Decode {
+        key: key.to_string(),
+        message: error.to_string(),
+    }
+}
+
+/// Persistence of the shares each server holds, per epoch.
+pub trait ShareStorage {
+    /// Store the shares a server holds for an epoch.
+    fn save_shares<'a>(
+        &'a self,
+        server_id: usize,
+        epoch: u64,
+        shares: &'a [DataShare],
+    ) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    /// Load the shares a server holds for an epoch, or `None` if none were stored.
+    fn load_shares(
+        &self,
+        server_id: usize,
+        epoch: u64,
+    ) -> BoxFuture<'_, Result<Option<Vec<DataShare>>, StorageError>>;
+
+    /// Delete the shares a server holds for an epoch.
+    fn delete_shares(
+        &self,
+        server_id: usize,
+        epoch: u64,
+    ) -> BoxFuture<'_, Result<(), StorageError>>;
+}
+
+impl<S: BlobStore + ?Sized> ShareStorage for S {
+    fn save_shares<'a>(
+        &'a self,
+        server_id: usize,
+        epoch: u64,
+        shares: &'a [DataShare],
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            let key = share_key(server_id, epoch);
+            let bytes =
+                serde_json::to_vec(shares).map_err(|e| StorageError::Backend(e.to_string()))?;
+            self.put(&key, bytes).await
+        }
+        .boxed()
+    }
+
+    fn load_shares(
+        &self,
+        server_id: usize,
+        epoch: u64,
+    ) -> BoxFuture<'_, Result<Option<Vec<DataShare>>, StorageError>> {
+        async move {
+            let key = share_key(server_id, epoch);
+            match self.get(&key).await? {
+                Some(bytes) => serde_json::from_slice(&bytes)
+                    .map(Some)
+                    .map_err(|e| decode_error(&key, e)),
+                None => Ok(None),
+            }
+        }
+        .boxed()
+    }
+
+    fn delete_shares(
+        &self,
+        server_id: usize,
+        epoch: u64,
+    ) -> BoxFuture<'_, Result<(), StorageError>> {
+        async move { self.delete(&share_key(server_id, epoch)).await }.boxed()
+    }
+}
+
+/// Persistence of the reports ingested in each epoch, as compressed batches.
+pub trait ReportStorage {
+    /// Store the reports of an epoch.
+    fn save_reports<'a, const U32_SIZE: usize>(
+        &'a self,
+        epoch: u64,
+        reports: &ReportVector<U32_SIZE>,
+        codec: BatchCodec,
+    ) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    /// Load the reports of an epoch, or `None` if none were stored.
+    fn load_reports<const U32_SIZE: usize>(
+        &self,
+        epoch: u64,
+    ) -> BoxFuture<'_, Result<Option<ReportVector<U32_SIZE>>, StorageError>>;
+
+    /// Return the epochs with stored reports, in increasing order.
+    fn report_epochs(&self) -> BoxFuture<'_, Result<Vec<u64>, StorageError>>;
+}
+
+impl<S: BlobStore + ?Sized> ReportStorage for S {
+    fn save_reports<'a, const U32_SIZE: usize>(
+        &'a self,
+        epoch: u64,
+        reports: &ReportVector<U32_SIZE>,
+        codec: BatchCodec,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        let bytes = reports.compress(codec);
+        async move { self.put(&report_key(epoch), bytes).await }.boxed()
+    }
+
+    fn load_reports<const U32_SIZE: usize>(
+        &self,
+        epoch: u64,
+    ) -> BoxFuture<'_, Result<Option<ReportVector<U32_SIZE>>, StorageError>> {
+        async move {
+            let key = report_key(epoch);
+            match self.get(&key).await? {
+                Some(bytes) => ReportVector::decompress(&bytes)
+                    .map(Some)
+                    .map_err(|e| decode_error(&key, e)),
+                None => Ok(None),
+            }
+        }
+        .boxed()
+    }
+
+    fn report_epochs(&self) -> BoxFuture<'_, Result<Vec<u64>, StorageError>> {
+        async move {
+            let keys = self.list("reports/").await?;
+            keys.iter()
+                .map(|key| {
+                    key["reports/".len()..]
+                        .parse()
+                        .map_err(|e| decode_error(key, e))
+                })
+                .collect()
+        }
+        .boxed()
+    }
+}
+
+/// Persistence of named checkpoints of server state, e.g. the epoch coordinator
+/// or the privacy budget spent so far.
+pub trait CheckpointStorage {
+    /// Store a checkpoint, replacing any checkpoint with the same name.
+    fn save_checkpoint<'a, T: Serialize + ?Sized>(
+        &'a self,
+        name: &'a str,
+        state: &T,
+    ) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    /// Load a checkpoint, or `None` if there is none with the name.
+    fn load_checkpoint<'a, T: DeserializeOwned + Send + 'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<T>, StorageError>>;
+}
+
+impl<S: BlobStore + ?Sized> CheckpointStorage for S {
+    fn save_checkpoint<'a, T: Serialize + ?Sized>(
+        &'a self,
+        name: &'a str,
+        state: &T,
+    ) -> BoxFuture<'a, Result<(), StorageError>> {
+        let bytes = serde_json::to_vec(state).map_err(|e| StorageError::Backend(e.to_string()));
+        async move { self.put(&checkpoint_key(name), bytes?).await }.boxed()
+    }
+
+    fn load_checkpoint<'a, T: DeserializeOwned + Send + 'a>(
+        &'a self,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<Option<T>, StorageError>> {
+        async move {
+            let key = checkpoint_key(name);
+            match self.get(&key).await? {
+                Some(bytes) => serde_json::from_slice(&bytes)
+                    .map(Some)
+                    .map_err(|e| decode_error(&key, e)),
+                None => Ok(None),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// A `BlobStore` in memory, for tests and single-node deployments.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    blobs: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the number of blobs held.
+    pub fn len(&self) -> usize {
+        self.blobs.lock().unwrap().len()
+    }
+
+    /// Return whether the store holds no blobs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl BlobStore for MemoryStore {
+    fn put<'a>(&'a self, key: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<(), StorageError>> {
+        self.blobs.lock().unwrap().insert(key.to_string(), bytes);
+        futures::future::ready(Ok(())).boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, StorageError>> {
+        let blob = self.blobs.lock().unwrap().get(key).cloned();
+        futures::future::ready(Ok(blob)).boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        self.blobs.lock().unwrap().remove(key);
+        futures::future::ready(Ok(())).boxed()
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<String>, StorageError>> {
+        let keys = self
+            .blobs
+            .lock()
+            .unwrap()
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, _)| key.clone())
+            .collect();
+        futures::future::ready(Ok(keys)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::AttributeType::*;
+    use futures::executor::block_on;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_typed_storage() {
+        let store = MemoryStore::new();
+        block_on(async {
+            let shares = vec![
+                DataShare::feature(1, 0, 5, 97),
+                DataShare::feature(1, 1, 7, 97),
+            ];
+            store.save_shares(1, 4, &shares).await.unwrap();
+            let loaded = store.load_shares(1, 4).await.unwrap().unwrap();
+            assert_eq!(
+                loaded.iter().map(|s| s.value).collect::<Vec<_>>(),
+                vec![5, 7]
+            );
+            assert!(store.load_shares(0, 4).await.unwrap().is_none());
+            store.delete_shares(1, 4).await.unwrap();
+            assert!(store.load_shares(1, 4).await.unwrap().is_none());
+
+            let reports = ReportVector::<1>::random(&[C4, N5(31)], &mut OsRng, 10);
+            for epoch in [12, 3] {
+                store
+                    .save_reports(epoch, &reports, BatchCodec::zstd())
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(store.report_epochs().await.unwrap(), vec![3, 12]);
+            assert_eq!(store.load_reports::<1>(3).await.unwrap(), Some(reports));
+
+            store
+                .save_checkpoint("budget", &(7u64, 0.5f64))
+                .await
+                .unwrap();
+            assert_eq!(
+                store.load_checkpoint::<(u64, f64)>("budget").await.unwrap(),
+                Some((7, 0.5))
+            );
+            store
+                .put("checkpoints/broken", b"{".to_vec())
+                .await
+                .unwrap();
+            assert!(matches!(
+                store.load_checkpoint::<u64>("broken").await,
+                Err(StorageError::Decode { .. })
+            ));
+        });
+        assert_eq!(store.len(), 4);
+    }
+}
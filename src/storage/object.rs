@@ -0,0 +1,261 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+use super::{BlobStore, StorageError};
+use futures::future::{self, BoxFuture};
+use futures::{FutureExt, TryStreamExt};
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use std::sync::Arc;
+
+/// Smallest part S3 accepts in a multipart upload, except for the last part.
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// Layout and upload parameters of an `ObjectStorage`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectStorageConfig {
+    /// Path under which all blobs are stored, e.g. one per deployment
+    pub prefix: String,
+
+    /// Blobs larger than this many bytes are uploaded in parts
+    pub multipart_threshold: usize,
+
+    /// Size of each part of a multipart upload, at least `MIN_PART_SIZE`
+    pub part_size: usize,
+}
+
+impl Default for ObjectStorageConfig {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            multipart_threshold: 64 * 1024 * 1024,
+            part_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+fn backend_error(error: object_store::Error) -> StorageError {
+    StorageError::Backend(error.to_string())
+}
+
+/// A `BlobStore` in an object store, such as S3 or an S3-compatible service.
+///
+/// Large blobs, such as the reports of a busy epoch, are uploaded in parts, so
+/// neither a single request nor a retry has to carry the whole blob. A failed
+/// multipart upload is aborted so no parts are left behind.
+#[derive(Debug)]
+pub struct ObjectStorage {
+    store: Arc<dyn ObjectStore>,
+    config: ObjectStorageConfig,
+}
+
+impl ObjectStorage {
+    /// Create a storage backend on any `object_store` implementation.
+    pub fn new(
+        store: Arc<dyn ObjectStore>,
+        config: ObjectStorageConfig,
+    ) -> Result<Self, StorageError> {
+        if config.part_size < MIN_PART_SIZE {
+            return Err(StorageError::InvalidConfig(format!(
+                "Part size {} is smaller than {} bytes",
+                config.part_size, MIN_PART_SIZE
+            )));
+        }
+        Ok(Self { store, config })
+    }
+
+    /// Create a storage backend on an S3 bucket, configured from the standard
+    /// `AWS_*` environment variables. Set `AWS_ENDPOINT` for S3-compatible services.
+    pub fn s3_from_env(bucket: &str, config: ObjectStorageConfig) -> Result<Self, StorageError> {
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()
+            .map_err(backend_error)?;
+        Self::new(Arc::new(store), config)
+    }
+
+    /// Return the configuration.
+    pub fn config(&self) -> &ObjectStorageConfig {
+        &self.config
+    }
+
+    fn path(&self, key: &str) -> Path {
+        if self.config.prefix.is_empty() {
+            Path::from(key)
+        } else {
+            Path::from(format!("{}/{}", self.config.prefix, key))
+        }
+    }
+
+    /// Upload a blob in parts of `part_size` bytes.
+    async fn put_multipart(&self, path: &Path, bytes: Vec<u8>) -> Result<(), StorageError> {
+        let mut upload = self
+            .store
+            .put_multipart(path)
+            .await
+            .map_err(backend_error)?;
+        let parts = bytes
+            .chunks(self.config.part_size)
+            .map(|chunk| upload.put_part(PutPayload::from(chunk.to_vec())))
+            .collect::<Vec<_>>();
+
+        let result = match future::try_join_all(parts).await {
+            Ok(_) => upload.complete().await.map(|_| ()),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            if let Err(abort_error) = upload.abort().await {
+                log::warn!(
+                    "Failed to abort multipart upload of {}: {}",
+                    path,
+                    abort_error
+                );
+            }
+            return Err(backend_error(error));
+        }
+        Ok(())
+    }
+}
+
+impl BlobStore for ObjectStorage {
+    fn put<'a>(&'a self, key: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            let path = self.path(key);
+            if bytes.len() > self.config.multipart_threshold {
+                self.put_multipart(&path, bytes).await
+            } else {
+                self.store
+                    .put(&path, PutPayload::from(bytes))
+                    .await
+                    .map(|_| ())
+                    .map_err(backend_error)
+            }
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, StorageError>> {
+        async move {
+            let result = match self.store.get(&self.path(key)).await {
+                Ok(result) => result,
+                Err(object_store::Error::NotFound { .. }) => return Ok(None),
+                Err(error) => return Err(backend_error(error)),
+            };
+            let bytes = result.bytes().await.map_err(backend_error)?;
+            Ok(Some(bytes.to_vec()))
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        async move {
+            match self.store.delete(&self.path(key)).await {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+                Err(error) => Err(backend_error(error)),
+            }
+        }
+        .boxed()
+    }
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<String>, StorageError>> {
+        async move {
+            // Object stores list by directory, so list the directory of the prefix
+            // and filter the keys.
+            let directory = prefix
+                .rsplit_once('/')
+                .map_or("", |(directory, _)| directory);
+            let root = self.path("");
+            let objects = self
+                .store
+                .list(Some(&self.path(directory)))
+                .try_collect::<Vec<_>>()
+                .await
+                .map_err(backend_error)?;
+
+            let mut keys = objects
+                .into_iter()
+                .filter_map(|object| {
+                    let location = object.location.as_ref();
+                    let key = match root.as_ref() {
+                        "" => location,
+                        root => location.strip_prefix(root)?.strip_prefix('/')?,
+                    };
+                    key.starts_with(prefix).then(|| key.to_string())
+                })
+                .collect::<Vec<_>>();
+            keys.sort();
+            Ok(keys)
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::ReportStorage;
+    use futures::executor::block_on;
+    use object_store::memory::InMemory;
+
+    #[test]
+    fn test_object_storage() {
+        let store = Arc::new(InMemory::new());
+        let storage = ObjectStorage::new(
+            store.clone(),
+            ObjectStorageConfig {
+                prefix: "deployment".to_string(),
+                multipart_threshold: 1024,
+                part_size: MIN_PART_SIZE,
+            },
+        )
+        .unwrap();
+
+        block_on(async {
+            let blob = (0..MIN_PART_SIZE + 1000)
+                .map(|i| i as u8)
+                .collect::<Vec<_>>();
+            storage
+                .put("reports/00000000000000000001", blob.clone())
+                .await
+                .unwrap();
+            storage
+                .put("reports/00000000000000000000", vec![1])
+                .await
+                .unwrap();
+            storage
+                .put("shares/0/00000000000000000000", vec![2])
+                .await
+                .unwrap();
+
+            assert_eq!(
+                storage.get("reports/00000000000000000001").await.unwrap(),
+                Some(blob)
+            );
+            assert_eq!(storage.report_epochs().await.unwrap(), vec![0, 1]);
+            assert!(store
+                .head(&Path::from("deployment/shares/0/00000000000000000000"))
+                .await
+                .is_ok());
+
+            storage
+                .delete("reports/00000000000000000000")
+                .await
+                .unwrap();
+            storage
+                .delete("reports/00000000000000000000")
+                .await
+                .unwrap();
+            assert_eq!(
+                storage.get("reports/00000000000000000000").await.unwrap(),
+                None
+            );
+        });
+
+        let config = ObjectStorageConfig {
+            part_size: 1024,
+            ..ObjectStorageConfig::default()
+        };
+        assert!(ObjectStorage::new(Arc::new(InMemory::new()), config).is_err());
+    }
+}
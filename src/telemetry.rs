@@ -0,0 +1,154 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! OpenTelemetry instrumentation of the shuffler, the DP mechanisms, and the
+//! multi-party protocol phases.
+//!
+//! With the `telemetry` feature every instrumented operation emits a span named
+//! `<component>.<operation>` and records the `doppio.operation.duration` histogram
+//! and the `doppio.operation.items` counter, all through the global tracer and
+//! meter providers under the `doppio` resource name. Install providers built with
+//! `resource()` to export them. Without the feature the instrumentation compiles
+//! to nothing.
+
+use std::fmt::Display;
+
+/// Name of the service all telemetry is reported under.
+pub const SERVICE_NAME: &str = "doppio";
+
+/// Return the resource identifying this crate, for the tracer and meter providers.
+#[cfg(feature = "telemetry")]
+pub fn resource() -> opentelemetry_sdk::Resource {
+    use opentelemetry::KeyValue;
+
+    opentelemetry_sdk::Resource::new([
+        KeyValue::new("service.name", SERVICE_NAME),
+        KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+    ])
+}
+
+#[cfg(feature = "telemetry")]
+mod instruments {
+    use super::SERVICE_NAME;
+    use opentelemetry::global;
+    use opentelemetry::metrics::{Counter, Histogram, Unit};
+    use std::sync::OnceLock;
+
+    /// Metric instruments shared by all operations.
+    pub(super) struct Instruments {
+        pub(super) duration: Histogram<f64>,
+        pub(super) items: Counter<u64>,
+    }
+
+    pub(super) fn get() -> &'static Instruments {
+        static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+        INSTRUMENTS.get_or_init(|| {
+            let meter = global::meter(SERVICE_NAME);
+            Instruments {
+                duration: meter
+                    .f64_histogram("doppio.operation.duration")
+                    .with_description("Duration of pipeline operations")
+                    .with_unit(Unit::new("s"))
+                    .init(),
+                items: meter
+                    .u64_counter("doppio.operation.items")
+                    .with_description("Data points or shares processed by pipeline operations")
+                    .init(),
+            }
+        })
+    }
+}
+
+/// An `Operation` traces one instrumented call. It is started before the work and
+/// finished with its result; the span ends when the `Operation` is dropped.
+pub(crate) struct Operation {
+    #[cfg(feature = "telemetry")]
+    span: opentelemetry::global::BoxedSpan,
+    #[cfg(feature = "telemetry")]
+    attributes: [opentelemetry::KeyValue; 2],
+    #[cfg(feature = "telemetry")]
+    start: std::time::Instant,
+}
+
+impl Operation {
+    /// Start tracing an operation of a component, e.g. `("dp", "apply_mechanism")`.
+    #[cfg_attr(not(feature = "telemetry"), allow(unused_variables))]
+    pub(crate) fn start(component: &'static str, operation: &'static str) -> Self {
+        #[cfg(feature = "telemetry")]
+        {
+            use opentelemetry::trace::{Span, Tracer};
+            use opentelemetry::{global, KeyValue};
+
+            let attributes = [
+                KeyValue::new("doppio.component", component),
+                KeyValue::new("doppio.operation", operation),
+            ];
+            let mut span =
+                global::tracer(SERVICE_NAME).start(format!("{}.{}", component, operation));
+            span.set_attributes(attributes.iter().cloned());
+            Self {
+                span,
+                attributes,
+                start: std::time::Instant::now(),
+            }
+        }
+
+        #[cfg(not(feature = "telemetry"))]
+        Self {}
+    }
+
+    /// Record the outcome of the operation and the number of items it processed.
+    #[cfg_attr(not(feature = "telemetry"), allow(unused_variables, unused_mut))]
+    pub(crate) fn finish<T, E: Display>(mut self, result: &Result<T, E>, items: usize) {
+        #[cfg(feature = "telemetry")]
+        {
+            use opentelemetry::trace::{Span, Status};
+            use opentelemetry::KeyValue;
+
+            let outcome = match result {
+                Ok(_) => "ok",
+                Err(e) => {
+                    self.span.set_status(Status::error(e.to_string()));
+                    "error"
+                }
+            };
+            self.span
+                .set_attribute(KeyValue::new("doppio.items", items as i64));
+
+            let mut attributes = self.attributes.to_vec();
+            attributes.push(KeyValue::new("doppio.outcome", outcome));
+            let instruments = instruments::get();
+            instruments
+                .duration
+                .record(self.start.elapsed().as_secs_f64(), &attributes);
+            instruments.items.add(items as u64, &attributes);
+        }
+    }
+}
+
+/// Trace a synchronous operation processing `items` data points.
+pub(crate) fn record<T, E: Display>(
+    component: &'static str,
+    operation: &'static str,
+    items: usize,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let operation = Operation::start(component, operation);
+    let result = f();
+    operation.finish(&result, items);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_passes_result_through() {
+        assert_eq!(record("test", "ok", 3, || Ok::<_, String>(7)), Ok(7));
+        assert_eq!(
+            record("test", "error", 0, || Err::<(), _>("failed".to_string())),
+            Err("failed".to_string())
+        );
+    }
+}
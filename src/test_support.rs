@@ -0,0 +1,303 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! Data generators and invariant checkers for tests of code built on this crate.
+//!
+//! The generators produce `DataPoint`s that conform to a `Schema`, pairs of
+//! neighboring datasets for differential privacy tests, and random Shamir shares.
+//! The checkers verify that permutations are bijections, that a shuffle only
+//! reorders its input, and that every quorum of shares reconstructs the secret.
+//! Every generator has a `proptest` strategy counterpart in `strategy`.
+
+use crate::multi_party::crypto::{SecretShare, ShamirSecretSharing};
+use crate::multi_party::ProtocolError;
+use crate::report::attr::AttrValueType;
+use crate::schema::{attr_from_attr_value, DataPoint, Schema};
+use rand::Rng;
+
+/// Create a `DataPoint` from attribute values that are valid for the schema.
+fn data_point_from_values(schema: &Schema, values: &[AttrValueType]) -> DataPoint {
+    let attributes = schema
+        .get_attr_types()
+        .into_iter()
+        .zip(values)
+        .map(|(attr_type, value)| attr_from_attr_value(attr_type, *value).unwrap())
+        .collect();
+    let features = values.iter().map(|value| *value as f64).collect();
+    DataPoint::with_attributes(features, attributes)
+}
+
+/// Create a random `DataPoint` conforming to the schema, with one feature and one
+/// attribute per schema attribute.
+pub fn random_data_point<R: Rng + ?Sized>(schema: &Schema, rng: &mut R) -> DataPoint {
+    let values = schema
+        .get_attr_types()
+        .into_iter()
+        .map(|attr_type| rng.gen_range(0..attr_type.get_value_count()) as AttrValueType)
+        .collect::<Vec<_>>();
+    data_point_from_values(schema, &values)
+}
+
+/// Create `count` random `DataPoint`s conforming to the schema.
+pub fn random_data_points<R: Rng + ?Sized>(
+    schema: &Schema,
+    count: usize,
+    rng: &mut R,
+) -> Vec<DataPoint> {
+    (0..count).map(|_| random_data_point(schema, rng)).collect()
+}
+
+/// Return a dataset neighboring `data`: one random record is replaced by a fresh
+/// random record. The index of the replaced record is returned with the dataset.
+pub fn neighboring_dataset<R: Rng + ?Sized>(
+    data: &[DataPoint],
+    schema: &Schema,
+    rng: &mut R,
+) -> (Vec<DataPoint>, usize) {
+    assert!(
+        !data.is_empty(),
+        "An empty dataset has no substitution neighbors."
+    );
+
+    let index = rng.gen_range(0..data.len());
+    let mut neighbor = data.to_vec();
+    neighbor[index] = random_data_point(schema, rng);
+    (neighbor, index)
+}
+
+/// Create a pair of neighboring datasets of `count` records each, differing in
+/// exactly one record.
+pub fn neighboring_datasets<R: Rng + ?Sized>(
+    schema: &Schema,
+    count: usize,
+    rng: &mut R,
+) -> (Vec<DataPoint>, Vec<DataPoint>) {
+    let data = random_data_points(schema, count, rng);
+    let (neighbor, _) = neighboring_dataset(&data, schema, rng);
+    (data, neighbor)
+}
+
+/// Share a random secret with a `threshold`-out-of-`num_shares` Shamir scheme.
+/// Return the scheme, the secret, and its shares.
+pub fn random_shares<R: Rng + ?Sized>(
+    threshold: usize,
+    num_shares: usize,
+    modulus: u64,
+    rng: &mut R,
+) -> Result<(ShamirSecretSharing, u64, Vec<SecretShare>), ProtocolError> {
+    let scheme = ShamirSecretSharing::new(threshold, num_shares, modulus)?;
+    let secret = rng.gen_range(0..modulus);
+    let shares = scheme.share_secret(secret)?;
+    Ok((scheme, secret, shares))
+}
+
+/// Check that `permutation` is a bijection on `0..permutation.len()`.
+pub fn check_permutation(permutation: &[usize]) -> Result<(), String> {
+    let mut seen = vec![false; permutation.len()];
+    for (position, &target) in permutation.iter().enumerate() {
+        match seen.get_mut(target) {
+            None => {
+                return Err(format!(
+                    "Position {} maps to {}, which is out of range for length {}.",
+                    position,
+                    target,
+                    permutation.len()
+                ))
+            }
+            Some(true) => {
+                return Err(format!(
+                    "Position {} maps to {}, which is already taken.",
+                    position, target
+                ))
+            }
+            Some(slot) => *slot = true,
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that `shuffled` is a reordering of `original`: both contain the same
+/// records with the same multiplicities.
+pub fn check_shuffle(original: &[DataPoint], shuffled: &[DataPoint]) -> Result<(), String> {
+    if original.len() != shuffled.len() {
+        return Err(format!(
+            "The shuffle changed the number of records from {} to {}.",
+            original.len(),
+            shuffled.len()
+        ));
+    }
+
+    let sorted_features = |data: &[DataPoint]| {
+        let mut features = data
+            .iter()
+            .map(|point| point.features().iter().map(|f| f.to_bits()).collect())
+            .collect::<Vec<Vec<u64>>>();
+        features.sort_unstable();
+        features
+    };
+    if sorted_features(original) != sorted_features(shuffled) {
+        return Err("The shuffle added, removed, or modified records.".to_string());
+    }
+
+    Ok(())
+}
+
+/// Check that every quorum of `threshold` consecutive shares, wrapping around,
+/// reconstructs `secret`, and that the full set of shares does as well.
+pub fn check_reconstruction(
+    scheme: &ShamirSecretSharing,
+    secret: u64,
+    shares: &[SecretShare],
+) -> Result<(), String> {
+    if shares.len() < scheme.threshold {
+        return Err(format!(
+            "{} shares are fewer than the threshold {}.",
+            shares.len(),
+            scheme.threshold
+        ));
+    }
+
+    let quorums = (0..shares.len())
+        .map(|start| {
+            (0..scheme.threshold)
+                .map(|offset| shares[(start + offset) % shares.len()].clone())
+                .collect::<Vec<_>>()
+        })
+        .chain(std::iter::once(shares.to_vec()));
+    for quorum in quorums {
+        let ids = quorum.iter().map(|share| share.id).collect::<Vec<_>>();
+        let reconstructed = scheme
+            .reconstruct_secret(&quorum)
+            .map_err(|e| format!("Shares {:?} failed to reconstruct: {}", ids, e))?;
+        if reconstructed != secret {
+            return Err(format!(
+                "Shares {:?} reconstructed {} instead of {}.",
+                ids, reconstructed, secret
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// `proptest` strategies for the generators.
+pub mod strategy {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    /// Strategy for `DataPoint`s conforming to the schema.
+    pub fn data_point(schema: &Schema) -> impl Strategy<Value = DataPoint> {
+        let schema = schema.clone();
+        let ranges = schema
+            .get_attr_types()
+            .into_iter()
+            .map(|attr_type| 0..attr_type.get_value_count())
+            .collect::<Vec<_>>();
+        ranges.prop_map(move |values| {
+            let values = values
+                .into_iter()
+                .map(|value| value as AttrValueType)
+                .collect::<Vec<_>>();
+            data_point_from_values(&schema, &values)
+        })
+    }
+
+    /// Strategy for datasets with a number of records in `sizes`.
+    pub fn data_points(
+        schema: &Schema,
+        sizes: std::ops::Range<usize>,
+    ) -> impl Strategy<Value = Vec<DataPoint>> {
+        vec(data_point(schema), sizes)
+    }
+
+    /// Strategy for neighboring datasets with a number of records in `sizes`,
+    /// differing in exactly one record. `sizes` must not contain zero.
+    pub fn neighboring_datasets(
+        schema: &Schema,
+        sizes: std::ops::Range<usize>,
+    ) -> impl Strategy<Value = (Vec<DataPoint>, Vec<DataPoint>)> {
+        (
+            data_points(schema, sizes),
+            data_point(schema),
+            any::<prop::sample::Index>(),
+        )
+            .prop_map(|(data, replacement, index)| {
+                let mut neighbor = data.clone();
+                neighbor[index.index(data.len())] = replacement;
+                (data, neighbor)
+            })
+    }
+
+    /// Strategy for permutations of `0..len`.
+    pub fn permutation(len: usize) -> impl Strategy<Value = Vec<usize>> {
+        Just((0..len).collect::<Vec<_>>()).prop_shuffle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn schema() -> Schema {
+        Schema::try_from(r#"[["age_group","c3"],["income",{"n10":1000}],["flag","c32"]]"#).unwrap()
+    }
+
+    #[test]
+    fn test_generators() {
+        let schema = schema();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let data = random_data_points(&schema, 100, &mut rng);
+        for point in &data {
+            assert_eq!(point.features().len(), 3);
+            assert!(schema.is_compatible_attr_array(point.attributes()));
+            assert!(point.features()[0] < 7.0);
+            assert!(point.features()[1] < 1000.0);
+        }
+
+        let (data, neighbor) = neighboring_datasets(&schema, 10, &mut rng);
+        let differing = data
+            .iter()
+            .zip(&neighbor)
+            .filter(|(a, b)| a.features() != b.features())
+            .count();
+        assert!(differing <= 1);
+
+        let (scheme, secret, shares) = random_shares(3, 5, 65_537, &mut rng).unwrap();
+        check_reconstruction(&scheme, secret, &shares).unwrap();
+        assert!(check_reconstruction(&scheme, secret + 1, &shares).is_err());
+        assert!(check_reconstruction(&scheme, secret, &shares[..2]).is_err());
+    }
+
+    #[test]
+    fn test_checkers() {
+        assert!(check_permutation(&[2, 0, 1]).is_ok());
+        assert!(check_permutation(&[0, 0, 1]).is_err());
+        assert!(check_permutation(&[0, 3, 1]).is_err());
+
+        let data = vec![DataPoint::new(vec![1.0]), DataPoint::new(vec![2.0])];
+        let shuffled = vec![data[1].clone(), data[0].clone()];
+        assert!(check_shuffle(&data, &shuffled).is_ok());
+        assert!(check_shuffle(&data, &[data[0].clone(), data[0].clone()]).is_err());
+        assert!(check_shuffle(&data, &data[..1]).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_permutations_are_bijections(permutation in strategy::permutation(20)) {
+            prop_assert!(check_permutation(&permutation).is_ok());
+        }
+
+        #[test]
+        fn prop_data_points_conform(data in strategy::data_points(&schema(), 1..20)) {
+            for point in &data {
+                prop_assert!(schema().is_compatible_attr_array(point.attributes()));
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT license.
+
+//! JavaScript bindings for collecting reports in the browser, e.g. from an
+//! extension. They call the same randomization, encoding, and calibration code
+//! the server uses. Build with `wasm-pack build --features wasm`.
+
+use crate::dp::calibration;
+use crate::report::report_handler::ReportHandler;
+use crate::report::report_vector::ReportVector;
+use crate::report::wire::ReportProto;
+use crate::schema::Schema;
+use prost::Message;
+use wasm_bindgen::prelude::*;
+
+/// Number of u32s in the reports encoded by `encodeReport`. The wire encoding
+/// does not depend on it; it only bounds the total attribute size to 256 bits.
+const WASM_REPORT_U32_SIZE: usize = 8;
+
+/// Locally randomize a categorical value with k-ary randomized response.
+#[wasm_bindgen(js_name = randomizedResponse)]
+pub fn randomized_response(value: u32, domain_size: u32, epsilon: f64) -> Result<u32, JsError> {
+    if domain_size < 2 || value >= domain_size {
+        return Err(JsError::new(
+            "The value must be in a domain of at least two values",
+        ));
+    }
+    if epsilon.is_nan() || epsilon <= 0.0 {
+        return Err(JsError::new("epsilon must be positive"));
+    }
+
+    Ok(calibration::randomized_response(
+        value,
+        domain_size,
+        epsilon,
+        &mut rand::thread_rng(),
+    ))
+}
+
+/// Locally randomize a numerical value with Laplace noise calibrated to the
+/// sensitivity and epsilon.
+#[wasm_bindgen(js_name = localLaplace)]
+pub fn local_laplace(value: f64, sensitivity: f64, epsilon: f64) -> Result<f64, JsError> {
+    let scale = laplace_scale(sensitivity, epsilon)?;
+    Ok(value + calibration::sample_laplace(scale, &mut rand::thread_rng()))
+}
+
+/// Return the Laplace noise scale for the sensitivity and epsilon.
+#[wasm_bindgen(js_name = laplaceScale)]
+pub fn laplace_scale(sensitivity: f64, epsilon: f64) -> Result<f64, JsError> {
+    if epsilon.is_nan() || epsilon <= 0.0 {
+        return Err(JsError::new("epsilon must be positive"));
+    }
+    Ok(calibration::laplace_scale(sensitivity, epsilon))
+}
+
+/// Return the Gaussian noise standard deviation for the sensitivity, epsilon, and
+/// delta.
+#[wasm_bindgen(js_name = gaussianSigma)]
+pub fn gaussian_sigma(sensitivity: f64, epsilon: f64, delta: f64) -> Result<f64, JsError> {
+    if epsilon.is_nan() || epsilon <= 0.0 || delta.is_nan() || delta <= 0.0 || delta >= 1.0 {
+        return Err(JsError::new("epsilon must be positive and delta in (0, 1)"));
+    }
+    Ok(calibration::gaussian_sigma(sensitivity, epsilon, delta))
+}
+
+/// Encode attribute values as a report in the protobuf wire format, validated
+/// against a JSON schema such as `[["attr1","c2"],["attr2",{"n3":6}]]`.
+#[wasm_bindgen(js_name = encodeReport)]
+pub fn encode_report(schema_json: &str, attr_values: &[u32]) -> Result<Vec<u8>, JsError> {
+    let schema = Schema::try_from(schema_json).map_err(|e| JsError::new(&e))?;
+    let attr_types = schema.get_attr_types();
+    if !schema.is_valid()
+        || !ReportHandler::<WASM_REPORT_U32_SIZE>::is_valid_attr_types(&attr_types)
+        || schema.get_attr_sizes().iter().sum::<usize>() > WASM_REPORT_U32_SIZE * 32
+    {
+        return Err(JsError::new("Invalid schema"));
+    }
+
+    // Validate the values by decoding them into a report vector of this schema.
+    let mut report_vector = ReportVector::<WASM_REPORT_U32_SIZE>::new(&attr_types);
+    let bytes = ReportProto {
+        attr_values: attr_values.to_vec(),
+    }
+    .encode_to_vec();
+    report_vector
+        .push_wire_report(&bytes)
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(bytes)
+}
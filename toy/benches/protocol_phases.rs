@@ -0,0 +1,84 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::seq::SliceRandom;
+use toy_prototype::offline_phase::permute;
+use toy_prototype::{AdditiveSecretSharing, FieldElement, FiniteField, OnlinePhase, ToyConfig};
+
+const MODULUS: u64 = 0xFFFFFFFFFFFFFFC5;
+const USER_COUNTS: [usize; 3] = [100, 1_000, 10_000];
+const FEATURE_WIDTHS: [usize; 2] = [1, 16];
+
+/// Random `users × features` matrix of field elements
+fn random_rows(field: &FiniteField, users: usize, features: usize) -> Vec<Vec<FieldElement>> {
+    (0..users)
+        .map(|_| (0..features).map(|_| field.random_element()).collect())
+        .collect()
+}
+
+/// Run `bench` for every user count and feature width in its own group
+fn for_each_size(c: &mut Criterion, name: &str, mut bench: impl FnMut(&mut criterion::Bencher, usize, usize)) {
+    let mut group = c.benchmark_group(name);
+    for &features in &FEATURE_WIDTHS {
+        for &users in &USER_COUNTS {
+            group.throughput(Throughput::Elements((users * features) as u64));
+            let id = BenchmarkId::new(format!("{}_features", features), users);
+            group.bench_function(id, |b| bench(b, users, features));
+        }
+    }
+    group.finish();
+}
+
+fn bench_sharing(c: &mut Criterion) {
+    let field = FiniteField::new(MODULUS).unwrap();
+    let sharing = AdditiveSecretSharing::new(MODULUS).unwrap();
+
+    for_each_size(c, "sharing", |b, users, features| {
+        let rows = random_rows(&field, users, features);
+        b.iter(|| sharing.share_matrix(black_box(&rows)).unwrap())
+    });
+}
+
+fn bench_permutation(c: &mut Criterion) {
+    let field = FiniteField::new(MODULUS).unwrap();
+
+    for_each_size(c, "permutation", |b, users, features| {
+        let rows = random_rows(&field, users, features);
+        let mut permutation: Vec<usize> = (0..users).collect();
+        permutation.shuffle(&mut rand::thread_rng());
+        b.iter(|| permute(black_box(&permutation), black_box(&rows)).unwrap())
+    });
+}
+
+fn bench_noise_addition(c: &mut Criterion) {
+    let field = FiniteField::new(MODULUS).unwrap();
+
+    for_each_size(c, "noise_addition", |b, users, features| {
+        let rows = random_rows(&field, users, features);
+        let noise = random_rows(&field, users, features);
+        b.iter(|| {
+            rows.iter()
+                .zip(noise.iter())
+                .map(|(row, noise)| field.vector_add(row, noise).unwrap())
+                .collect::<Vec<_>>()
+        })
+    });
+}
+
+fn bench_reconstruction(c: &mut Criterion) {
+    let field = FiniteField::new(MODULUS).unwrap();
+
+    for_each_size(c, "reconstruction", |b, users, features| {
+        let config = ToyConfig { num_users: users, num_features: features, ..Default::default() };
+        let mut online_phase = OnlinePhase::new(config, field.clone()).unwrap();
+        let server_shares = vec![random_rows(&field, users, features), random_rows(&field, users, features)];
+        b.iter(|| online_phase.combine_server_results(black_box(&server_shares)).unwrap())
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_sharing,
+    bench_permutation,
+    bench_noise_addition,
+    bench_reconstruction
+);
+criterion_main!(benches);
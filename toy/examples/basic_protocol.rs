@@ -1,5 +1,6 @@
+use std::sync::Arc;
 use toy_prototype::{
-    ToyProtocol, ToyConfig, UserData, FieldElement, FiniteField
+    BudgetSplit, ConsoleObserver, NoiseKind, OutputMode, RecoveryPolicy, SecurityMode, ToyProtocol, ToyConfig, UserData
 };
 
 #[tokio::main]
@@ -10,9 +11,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Configuration
     let config = ToyConfig {
         num_users: 10,
+        num_features: 2,
         epsilon: 1.0,
         delta: 1e-5,
         noise_scale: 1.0,
+        noise: NoiseKind::Laplace,
+        budget: BudgetSplit::PerFeature,
+        fractional_bits: 16,
+        batch_size: None,
+        security: SecurityMode::SemiHonest,
+        verify: false,
+        schema: None,
+        recovery: RecoveryPolicy::Abort,
+        output: OutputMode::Rows,
+        seed: None,
         field_modulus: 0xFFFFFFFFFFFFFFC5, // 2^64 - 59
     };
 
@@ -25,19 +37,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Create protocol instance
     let mut protocol = ToyProtocol::new(config)?;
+    protocol.set_observer(Arc::new(ConsoleObserver));
     println!("✓ Protocol initialized");
 
     // Generate test user data
     let mut user_data = Vec::new();
-    let field = protocol.field();
+    let codec = protocol.codec()?;
     
     println!("Generating test user data...");
     for i in 0..10 {
-        let data = vec![
-            FieldElement::new(i as u64, field.modulus()),
-            FieldElement::new((i * 2) as u64, field.modulus()),
-        ];
-        user_data.push(UserData::new(i, data, i as u64));
+        let values = [i as f64, (i * 2) as f64];
+        user_data.push(UserData::from_values(i, &values, [i as u8; 32], &codec)?);
     }
     println!("✓ Generated {} user data points", user_data.len());
 
@@ -59,17 +69,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Performance statistics:");
     println!("  Offline phase time: {}ms", result.stats.offline_time_ms);
     println!("  Online phase time: {}ms", result.stats.online_time_ms);
+    println!("  Offline communication: {} bytes", result.stats.offline_communication_bytes);
+    println!("  Online communication: {} bytes", result.stats.online_communication_bytes);
     println!("  Total communication: {} bytes", result.stats.total_communication_bytes);
     println!("  Field operations: {}", result.stats.field_operations);
 
     // Show first few results
     println!();
     println!("First 5 result data points:");
-    for (i, data_point) in result.result.iter().take(5).enumerate() {
-        println!("  User {}: [{}, {}]", 
+    for (i, data_point) in result.decode(&codec).iter().take(5).enumerate() {
+        println!("  User {}: [{:.3}, {:.3}]", 
             i, 
-            data_point[0].value(), 
-            data_point[1].value()
+            data_point[0], 
+            data_point[1]
         );
     }
 
@@ -77,9 +89,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("✓ Protocol execution completed successfully!");
     println!();
     println!("Key features demonstrated:");
-    println!("  ✓ Zero communication in online phase");
+    println!("  ✓ One blinded O(n) message between servers in online phase");
     println!("  ✓ All computations in finite fields");
-    println!("  ✓ Secret sharing with 2-out-of-3 threshold");
+    println!("  ✓ Secret sharing with additive 2-of-2 shares between P₁ and P₂");
     println!("  ✓ Differential privacy with Laplace noise");
     println!("  ✓ Complete privacy preservation");
 
@@ -0,0 +1,110 @@
+use std::net::SocketAddr;
+use std::process::{Child, Command};
+use toy_prototype::network::{run_auxiliary_server, run_computational_server, run_curator};
+use toy_prototype::{CancellationToken, FieldElement, Topology, ToyConfig, UserData, UserRegistration};
+
+/// Number of users submitting data
+const NUM_USERS: usize = 100;
+
+/// Build the configuration every process agrees on
+fn config() -> ToyConfig {
+    ToyConfig {
+        num_users: NUM_USERS,
+        ..Default::default()
+    }
+}
+
+/// Build the test users; seeds stand in for the ones P₀ hands out at registration
+fn users(config: &ToyConfig) -> Vec<UserData> {
+    (0..config.num_users)
+        .map(|i| {
+            let data = vec![
+                FieldElement::new(i as u64, config.field_modulus),
+                FieldElement::new((i * 2) as u64, config.field_modulus),
+            ];
+            UserData::new(i, data, [i as u8; 32])
+        })
+        .collect()
+}
+
+/// Spawn this example again in the given role
+fn spawn(role: &str, topology: &Topology) -> std::io::Result<Child> {
+    Command::new(std::env::current_exe()?)
+        .arg(role)
+        .arg(topology.first.to_string())
+        .arg(topology.second.to_string())
+        .spawn()
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    let config = config();
+
+    // Ctrl-C stops every role cleanly instead of leaving it waiting on its peers
+    let cancel = CancellationToken::new();
+    let on_interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            on_interrupt.cancel();
+        }
+    });
+
+    // `networked_protocol <role> <p1-addr> <p2-addr>` runs a single process
+    if let [_, role, first, second] = args.as_slice() {
+        let topology = Topology {
+            first: first.parse()?,
+            second: second.parse()?,
+        };
+
+        match role.as_str() {
+            "p0" => {
+                let registrations: Vec<UserRegistration> = users(&config)
+                    .iter()
+                    .map(|user| UserRegistration::new(user.user_id, user.seed))
+                    .collect();
+                let traffic = run_auxiliary_server(config, topology, &registrations, &cancel).await?;
+                println!("[P₀] dealt correlations, sent {} bytes (offline)", traffic.bytes_sent);
+            }
+            "p1" | "p2" => {
+                let server_id = if role == "p1" { 1 } else { 2 };
+                let traffic = run_computational_server(server_id, config, topology, &cancel).await?;
+                println!(
+                    "[P{}] offline from P₀: {} bytes, online with users/curator: {} sent / {} received, online with peer: {} sent / {} received",
+                    server_id,
+                    traffic.auxiliary.bytes_received,
+                    traffic.clients.bytes_sent,
+                    traffic.clients.bytes_received,
+                    traffic.peer.bytes_sent,
+                    traffic.peer.bytes_received
+                );
+            }
+            _ => return Err(format!("Unknown role {}", role).into()),
+        }
+
+        return Ok(());
+    }
+
+    println!("=== Toy Prototype: Networked 3-Server Deployment ===");
+    let first: SocketAddr = args.get(1).map(String::as_str).unwrap_or("127.0.0.1:47001").parse()?;
+    let second: SocketAddr = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:47002").parse()?;
+    let topology = Topology { first, second };
+    println!("P₁ on {}, P₂ on {}", topology.first, topology.second);
+
+    let mut children = vec![spawn("p1", &topology)?, spawn("p2", &topology)?, spawn("p0", &topology)?];
+
+    // This process plays the users and the curator
+    let (result, traffic) = run_curator(config.clone(), topology, users(&config), &cancel).await?;
+    for child in &mut children {
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(format!("Server process exited with {}", status).into());
+        }
+    }
+
+    println!();
+    println!("✓ Reconstructed {} shuffled and noised rows", result.len());
+    println!("  Curator sent {} bytes and received {} bytes", traffic.bytes_sent, traffic.bytes_received);
+
+    Ok(())
+}
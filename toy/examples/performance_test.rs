@@ -1,5 +1,5 @@
 use toy_prototype::{
-    ToyProtocol, ToyConfig, UserData, FieldElement, FiniteField
+    BudgetSplit, NoiseKind, OutputMode, RecoveryPolicy, SecurityMode, ToyProtocol, ToyConfig, UserData, FieldElement
 };
 use std::time::Instant;
 
@@ -13,6 +13,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     println!("Testing protocol performance with different user counts:");
     println!("  User counts: {:?}", user_counts);
+    println!("  Parallel online phase: {}", toy_prototype::parallel::is_parallel());
     println!();
 
     for &num_users in &user_counts {
@@ -21,9 +22,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Configuration
         let config = ToyConfig {
             num_users,
+            num_features: 2,
             epsilon: 1.0,
             delta: 1e-5,
             noise_scale: 1.0,
+            noise: NoiseKind::Laplace,
+            budget: BudgetSplit::PerFeature,
+            fractional_bits: 16,
+            batch_size: None,
+            security: SecurityMode::SemiHonest,
+            verify: false,
+            schema: None,
+            recovery: RecoveryPolicy::Abort,
+            output: OutputMode::Rows,
+            seed: None,
             field_modulus: 0xFFFFFFFFFFFFFFC5,
         };
 
@@ -39,7 +51,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 FieldElement::new(i as u64, field.modulus()),
                 FieldElement::new((i * 2) as u64, field.modulus()),
             ];
-            user_data.push(UserData::new(i, data, i as u64));
+            user_data.push(UserData::new(i, data, [i as u8; 32]));
         }
 
         // Measure execution time
@@ -52,7 +64,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("  Offline phase time: {}ms", result.stats.offline_time_ms);
         println!("  Online phase time: {}ms", result.stats.online_time_ms);
         println!("  Field operations: {}", result.stats.field_operations);
-        println!("  Communication: {} bytes", result.stats.total_communication_bytes);
+        println!("  Communication: {} bytes offline, {} bytes online", result.stats.offline_communication_bytes, result.stats.online_communication_bytes);
+        let offline = &result.offline_stats;
+        println!(
+            "  Offline steps: permutation {}ms, masks {}ms, noise {}ms, sharing {}ms, distribution {}ms",
+            offline.permutation_time_ms, offline.mask_time_ms, offline.noise_time_ms, offline.sharing_time_ms, offline.distribution_time_ms
+        );
+        let online = &result.online_stats;
+        println!(
+            "  Online steps: submission {}ms, shuffle {}ms, randomization {}ms, reconstruction {}ms",
+            online.submission_time_ms, online.shuffle_time_ms, online.randomization_time_ms, online.reconstruction_time_ms
+        );
         
         // Calculate throughput
         let throughput = num_users as f64 / total_time.as_secs_f64();
@@ -65,15 +87,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!();
     }
 
+    // Compare sequential and pipelined batches on a continuous stream of users
+    let (num_users, batch_size) = (4000, 500);
+    println!("=== Batches of {} over {} users ===", batch_size, num_users);
+    let config = ToyConfig { num_users, ..Default::default() };
+    let mut protocol = ToyProtocol::new(config)?;
+    let modulus = protocol.field().modulus();
+    let users = || (0..num_users).map(move |i| UserData::new(i, vec![FieldElement::new(i as u64, modulus); 2], [i as u8; 32]));
+
+    let start_time = Instant::now();
+    protocol.execute_batched(users(), batch_size, |_| Ok(())).await?;
+    let sequential = start_time.elapsed();
+    let start_time = Instant::now();
+    protocol.execute_pipelined(users(), batch_size, |_| Ok(())).await?;
+    let pipelined = start_time.elapsed();
+    println!("  Sequential: {:?}", sequential);
+    println!("  Pipelined: {:?} ({:.2}x)", pipelined, sequential.as_secs_f64() / pipelined.as_secs_f64());
+    println!();
+
     println!("=== Performance Analysis ===");
     println!("Key observations:");
-    println!("  ✓ Online phase has zero communication");
+    println!("  ✓ Online communication is linear in user count");
     println!("  ✓ All computations are local");
     println!("  ✓ Field operations scale with user count");
     println!("  ✓ Protocol maintains privacy guarantees");
     println!();
     println!("This toy prototype demonstrates the efficiency of the");
-    println!("silent shuffle and silent randomization approach.");
+    println!("split shuffle and silent randomization approach.");
 
     Ok(())
 } 
\ No newline at end of file
@@ -0,0 +1,231 @@
+use crate::seed::{self, SeedTree};
+use crate::{PrivacyGuarantees, ProtocolError, ToyConfig, ToyProtocol, UserData};
+
+/// Empirical privacy audit of the full protocol on a pair of neighboring datasets
+///
+/// Runs the protocol `trials` times on each dataset and plays a
+/// membership-inference game: the adversary sees the decoded output,
+/// computes a statistic and guesses "neighbor" when it exceeds a threshold.
+/// Every threshold's false positive and false negative rates give a lower
+/// bound `ε ≥ ln((1 - δ - FNR) / FPR)`; the audit reports the largest one that
+/// holds at the configured confidence. A correct implementation never yields a
+/// lower bound above the claimed epsilon, except with probability `alpha`.
+#[derive(Debug, Clone)]
+pub struct PrivacyAudit {
+    /// Protocol configuration under audit; `num_users` is taken from the datasets
+    config: ToyConfig,
+    /// Protocol runs per dataset
+    trials: usize,
+    /// Probability that the reported bound exceeds the true epsilon
+    alpha: f64,
+    /// Number of thresholds the distinguisher tries
+    thresholds: usize,
+}
+
+/// Outcome of a privacy audit
+#[derive(Debug, Clone)]
+pub struct AuditReport {
+    /// Largest epsilon lower bound found, or 0 if no threshold beat chance
+    pub epsilon_lower_bound: f64,
+    /// Guarantees the protocol claimed for the audited runs
+    pub claimed: PrivacyGuarantees,
+    /// Threshold the bound was found at
+    pub threshold: f64,
+    /// Protocol runs per dataset
+    pub trials: usize,
+}
+
+impl AuditReport {
+    /// Whether the empirical bound is within the claimed epsilon
+    pub fn is_consistent(&self) -> bool {
+        self.epsilon_lower_bound <= self.claimed.epsilon
+    }
+}
+
+impl PrivacyAudit {
+    /// Create an audit with 99% confidence and 64 thresholds
+    pub fn new(config: ToyConfig, trials: usize) -> Self {
+        Self {
+            config,
+            trials,
+            alpha: 0.01,
+            thresholds: 64,
+        }
+    }
+
+    /// Set the probability that the reported bound is wrong
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Set the number of thresholds the distinguisher tries
+    pub fn with_thresholds(mut self, thresholds: usize) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// Audit the protocol on `dataset` against its neighbor `neighbor`
+    ///
+    /// Both datasets hold one row of real values per user and must have the
+    /// same number of users. `statistic` maps a decoded, shuffled output to
+    /// the value the adversary thresholds; it should be shuffle-invariant.
+    pub async fn run<S>(&self, dataset: &[Vec<f64>], neighbor: &[Vec<f64>], statistic: S) -> Result<AuditReport, ProtocolError>
+    where
+        S: Fn(&[Vec<f64>]) -> f64,
+    {
+        if dataset.len() != neighbor.len() {
+            return Err(ProtocolError::DimensionMismatch);
+        }
+        if self.trials == 0 || self.thresholds == 0 {
+            return Err(ProtocolError::invalid_configuration("An audit needs at least one trial and threshold"));
+        }
+        if !(self.alpha > 0.0 && self.alpha < 1.0) {
+            return Err(ProtocolError::invalid_configuration("Alpha must be in (0, 1)"));
+        }
+
+        let (mut original, claimed) = self.sample(dataset, &statistic).await?;
+        let (mut neighboring, _) = self.sample(neighbor, &statistic).await?;
+        original.sort_by(f64::total_cmp);
+        neighboring.sort_by(f64::total_cmp);
+
+        // Both error rates of every rule are bounded at once, so the confidence is split
+        let tests = 4.0 * self.thresholds as f64;
+        let slack = ((tests / self.alpha).ln() / (2.0 * self.trials as f64)).sqrt();
+        let delta = claimed.delta;
+
+        let mut report = AuditReport {
+            epsilon_lower_bound: 0.0,
+            claimed,
+            threshold: f64::NAN,
+            trials: self.trials,
+        };
+        for threshold in candidate_thresholds(&original, &neighboring, self.thresholds) {
+            // Rates of `statistic > threshold` on each dataset
+            let original_rate = tail_rate(&original, threshold);
+            let neighbor_rate = tail_rate(&neighboring, threshold);
+
+            // DP bounds the ratio both ways, for the rule and its complement
+            let bounds = [
+                epsilon_bound(neighbor_rate, original_rate, slack, delta),
+                epsilon_bound(original_rate, neighbor_rate, slack, delta),
+                epsilon_bound(1.0 - neighbor_rate, 1.0 - original_rate, slack, delta),
+                epsilon_bound(1.0 - original_rate, 1.0 - neighbor_rate, slack, delta),
+            ];
+            for bound in bounds.into_iter().flatten() {
+                if bound > report.epsilon_lower_bound {
+                    report.epsilon_lower_bound = bound;
+                    report.threshold = threshold;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Run the protocol `trials` times on `dataset` and collect the statistic of each output
+    async fn sample<S>(&self, dataset: &[Vec<f64>], statistic: &S) -> Result<(Vec<f64>, PrivacyGuarantees), ProtocolError>
+    where
+        S: Fn(&[Vec<f64>]) -> f64,
+    {
+        let config = ToyConfig {
+            num_users: dataset.len(),
+            num_features: dataset.first().map_or(self.config.num_features, Vec::len),
+            ..self.config.clone()
+        };
+
+        // A seeded audit is reproducible, with each trial on its own branch of the seed tree
+        let trials = config.seed.map(|master| SeedTree::new(master).child(seed::TRIALS));
+
+        let mut samples = Vec::with_capacity(self.trials);
+        let mut claimed = None;
+        for trial in 0..self.trials {
+            let config = ToyConfig {
+                seed: trials.as_ref().map(|tree| tree.derive_seed(trial as u64)),
+                ..config.clone()
+            };
+            let mut protocol = ToyProtocol::new(config.clone())?;
+            let codec = protocol.codec()?;
+            let users = dataset
+                .iter()
+                .enumerate()
+                .map(|(user_id, values)| UserData::from_values(user_id, values, config.user_seed(user_id), &codec))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let result = protocol.execute(users).await?;
+            samples.push(statistic(&result.decode(&codec)));
+            claimed.get_or_insert(result.privacy_guarantees);
+        }
+
+        Ok((samples, claimed.ok_or(ProtocolError::EmptyInput)?))
+    }
+}
+
+/// Evenly spaced quantiles of the pooled samples
+fn candidate_thresholds(first: &[f64], second: &[f64], count: usize) -> Vec<f64> {
+    let mut pooled: Vec<f64> = first.iter().chain(second.iter()).copied().collect();
+    pooled.sort_by(f64::total_cmp);
+
+    (1..=count)
+        .map(|k| pooled[(k * (pooled.len() - 1)) / (count + 1)])
+        .collect()
+}
+
+/// Fraction of sorted `samples` strictly above `threshold`
+fn tail_rate(samples: &[f64], threshold: f64) -> f64 {
+    let at_or_below = samples.partition_point(|&value| value <= threshold);
+    (samples.len() - at_or_below) as f64 / samples.len() as f64
+}
+
+/// Epsilon lower bound of a rule that fires with rate `hit` on one dataset and `miss` on the other
+///
+/// `slack` widens both rates to one-sided Hoeffding confidence bounds.
+fn epsilon_bound(hit: f64, miss: f64, slack: f64, delta: f64) -> Option<f64> {
+    let hit = hit - slack - delta;
+    let miss = (miss + slack).min(1.0);
+    (hit > 0.0 && hit > miss).then(|| (hit / miss).ln())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Largest decoded value of the first feature
+    fn max_value(output: &[Vec<f64>]) -> f64 {
+        output.iter().map(|row| row[0]).fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    #[tokio::test]
+    async fn test_empirical_epsilon_within_claim() {
+        let config = ToyConfig {
+            num_features: 1,
+            epsilon: 1.0,
+            ..Default::default()
+        };
+        // The neighbor moves one user by the full sensitivity
+        let dataset = vec![vec![0.0], vec![0.0]];
+        let neighbor = vec![vec![config.noise_scale], vec![0.0]];
+
+        let report = PrivacyAudit::new(config, 2_000)
+            .run(&dataset, &neighbor, max_value)
+            .await
+            .unwrap();
+        assert!(report.is_consistent(), "{:?}", report);
+        // The distinguisher learns something, so the bound is not vacuous
+        assert!(report.epsilon_lower_bound > 0.1, "{:?}", report);
+
+        // With little noise the same distinguisher certifies far more than ε = 1,
+        // so a protocol that under-noised its claim would fail the audit
+        let weak = ToyConfig {
+            num_features: 1,
+            epsilon: 50.0,
+            ..Default::default()
+        };
+        let report = PrivacyAudit::new(weak, 500)
+            .run(&dataset, &neighbor, max_value)
+            .await
+            .unwrap();
+        assert!(report.is_consistent(), "{:?}", report);
+        assert!(report.epsilon_lower_bound > 1.5, "{:?}", report);
+    }
+}
@@ -0,0 +1,242 @@
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toy_prototype::{
+    CancellationToken, ConsoleObserver, FixedPoint, OfflineStats, OnlineStats, PrivacyGuarantees, ProtocolResult,
+    MaskSeed, ProtocolStats, ToyConfig, ToyProtocol, UserData, UserRegistration,
+};
+
+/// Run the toy 3-server shuffle DP protocol on CSV user data
+///
+/// User CSVs have a header row and the columns `user_id,seed,<feature>...`,
+/// with each seed written as 64 hex digits.
+/// The number of users is taken from the CSV, overriding `num_users` in the config.
+#[derive(Parser)]
+#[command(name = "toy-cli", version)]
+struct Cli {
+    /// TOML protocol configuration; missing fields take their defaults
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Print phase progress to standard output
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Precompute the offline phase for the users of a CSV and save it
+    Offline {
+        /// Users to register; only the `user_id` and `seed` columns are read
+        #[arg(short, long)]
+        users: PathBuf,
+        /// Directory to save the offline phase to
+        #[arg(short, long)]
+        out: PathBuf,
+    },
+    /// Collect user data against a saved offline phase and run the online phase
+    Collect {
+        /// Directory written by `offline`
+        #[arg(long)]
+        offline: PathBuf,
+        /// User data to submit
+        #[arg(short, long)]
+        users: PathBuf,
+        /// JSON file for the output and statistics
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Run both phases end to end
+    Run {
+        /// User data to submit
+        #[arg(short, long)]
+        users: PathBuf,
+        /// JSON file for the output and statistics
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+/// Output file written by `collect` and `run`
+#[derive(Serialize)]
+struct Report {
+    /// Shuffled and noised rows, decoded to real values
+    output: Vec<Vec<f64>>,
+    /// Privacy guarantees of the run
+    privacy_guarantees: PrivacyGuarantees,
+    /// Protocol statistics
+    stats: ProtocolStats,
+    /// Per-step timing of the offline phase
+    offline_stats: OfflineStats,
+    /// Per-step timing of the online phase
+    online_stats: OnlineStats,
+}
+
+impl Report {
+    /// Decode a protocol result into a report
+    fn new(result: ProtocolResult, codec: &FixedPoint) -> Self {
+        Self {
+            output: result.decode(codec),
+            privacy_guarantees: result.privacy_guarantees,
+            stats: result.stats,
+            offline_stats: result.offline_stats,
+            online_stats: result.online_stats,
+        }
+    }
+}
+
+/// Read the protocol configuration, or the default one if no file is given
+fn read_config(path: Option<&Path>) -> Result<ToyConfig, Box<dyn Error>> {
+    match path {
+        Some(path) => Ok(ToyConfig::from_file(path)?),
+        None => Ok(ToyConfig::default()),
+    }
+}
+
+/// Parse user records from CSV, encoding feature values with `codec`
+fn parse_users(reader: impl std::io::Read, codec: &FixedPoint) -> Result<Vec<UserData>, Box<dyn Error>> {
+    let mut csv = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    let mut users = Vec::new();
+
+    for (line, record) in csv.records().enumerate() {
+        let record = record?;
+        // Line numbers count the header
+        let row = line + 2;
+        let field = |index: usize, name: &str| {
+            record
+                .get(index)
+                .ok_or_else(|| format!("row {}: missing {}", row, name))
+        };
+
+        let user_id = field(0, "user_id")?.parse().map_err(|e| format!("row {}: user_id: {}", row, e))?;
+        let seed = parse_seed(field(1, "seed")?).map_err(|e| format!("row {}: seed: {}", row, e))?;
+        let values = record
+            .iter()
+            .skip(2)
+            .map(|value| value.parse::<f64>().map_err(|e| format!("row {}: {}: {}", row, value, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        users.push(UserData::from_values(user_id, &values, seed, codec)?);
+    }
+
+    Ok(users)
+}
+
+/// Parse a 32-byte seed written as 64 hex digits
+fn parse_seed(hex: &str) -> Result<MaskSeed, String> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(format!("expected 64 hex digits, got {:?}", hex));
+    }
+
+    let mut seed = [0u8; 32];
+    for (byte, pair) in seed.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|e| e.to_string())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|e| format!("{:?}: {}", pair, e))?;
+    }
+
+    Ok(seed)
+}
+
+/// Read user records from a CSV file
+fn read_users(path: &Path, codec: &FixedPoint) -> Result<Vec<UserData>, Box<dyn Error>> {
+    let file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_users(file, codec).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// Build a protocol for `users` users
+fn protocol(mut config: ToyConfig, users: usize, verbose: bool) -> Result<ToyProtocol, Box<dyn Error>> {
+    config.num_users = users;
+    let mut protocol = ToyProtocol::new(config)?;
+    if verbose {
+        protocol.set_observer(Arc::new(ConsoleObserver));
+    }
+
+    // Ctrl-C aborts the run and releases the servers' correlated randomness
+    let cancel = CancellationToken::new();
+    protocol.set_cancellation(cancel.clone());
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancel.cancel();
+        }
+    });
+
+    Ok(protocol)
+}
+
+/// Write the report as pretty-printed JSON
+fn write_report(path: &Path, report: &Report) -> Result<(), Box<dyn Error>> {
+    let json = serde_json::to_string_pretty(report)?;
+    fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = read_config(cli.config.as_deref())?;
+    let codec = FixedPoint::new(config.field_modulus, config.fractional_bits)?;
+
+    match cli.command {
+        Command::Offline { users, out } => {
+            let users = read_users(&users, &codec)?;
+            let registrations = users
+                .iter()
+                .map(|user| UserRegistration::new(user.user_id, user.seed))
+                .collect();
+            let mut protocol = protocol(config, users.len(), cli.verbose)?;
+            protocol.precompute_offline(registrations, &out).await?;
+            println!("Saved the offline phase for {} users to {}", users.len(), out.display());
+        }
+        Command::Collect { offline, users, output } => {
+            let users = read_users(&users, &codec)?;
+            let mut protocol = protocol(config, users.len(), cli.verbose)?;
+            protocol.load_offline(&offline)?;
+            protocol.submit_chunk(users)?;
+            let result = protocol.finalize().await?;
+            write_report(&output, &Report::new(result, &codec))?;
+            println!("Wrote the output to {}", output.display());
+        }
+        Command::Run { users, output } => {
+            let users = read_users(&users, &codec)?;
+            let mut protocol = protocol(config, users.len(), cli.verbose)?;
+            let result = protocol.execute(users).await?;
+            write_report(&output, &Report::new(result, &codec))?;
+            println!("Wrote the output to {}", output.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_users_and_config() {
+        let config: ToyConfig = toml::from_str("num_features = 3\nepsilon = 0.5\n").unwrap();
+        assert_eq!(config.num_features, 3);
+        assert_eq!(config.epsilon, 0.5);
+        assert_eq!(config.field_modulus, ToyConfig::default().field_modulus);
+
+        let codec = FixedPoint::new(config.field_modulus, config.fractional_bits).unwrap();
+        let (seed0, seed1) = ("11".repeat(32), "9a".repeat(32));
+        let csv = format!("user_id,seed,age,income\n0, {}, 31, 52.5\n1, {}, 45, -3\n", seed0, seed1);
+        let users = parse_users(csv.as_bytes(), &codec).unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!((users[1].user_id, users[1].seed), (1, [0x9a; 32]));
+        assert_eq!(codec.decode(&users[0].data[1]), 52.5);
+
+        assert!(parse_users(format!("user_id,seed,age\n0,{},old\n", seed0).as_bytes(), &codec).is_err());
+        // Seeds are full 32-byte keys, not small integers
+        assert!(parse_users("user_id,seed,age\n0,1,30\n".as_bytes(), &codec).is_err());
+        assert!(parse_users(format!("user_id,seed,age\n0,{}zz,30\n", "00".repeat(31)).as_bytes(), &codec).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use crate::protocol::ProtocolError;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Lets a caller abort a protocol run from another task
+///
+/// Clones share their state: cancelling one cancels all of them, and every
+/// step run through `run` on any clone stops at its next await point with
+/// `ProtocolError::Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    /// Create a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the run, waking every task waiting on the token
+    pub fn cancel(&self) {
+        if !self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            tracing::info!("protocol run cancelled");
+        }
+        self.inner.notify.notify_waiters();
+    }
+
+    /// Check if the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fail with `Cancelled` if the token was cancelled
+    pub fn check(&self) -> Result<(), ProtocolError> {
+        if self.is_cancelled() {
+            return Err(ProtocolError::Cancelled);
+        }
+
+        Ok(())
+    }
+
+    /// Wait until the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // Register before checking, so a cancellation in between is not missed
+            let notified = self.inner.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Run `step` until it finishes or the token is cancelled, dropping it in the latter case
+    pub async fn run<T, F>(&self, step: F) -> Result<T, ProtocolError>
+    where
+        F: Future<Output = Result<T, ProtocolError>>,
+    {
+        self.check()?;
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => Err(ProtocolError::Cancelled),
+            result = step => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_wakes_running_step() {
+        let token = CancellationToken::new();
+        assert_eq!(token.run(async { Ok(3) }).await.unwrap(), 3);
+
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+        let stuck = token.run(std::future::pending::<Result<(), ProtocolError>>()).await;
+        assert!(matches!(stuck, Err(ProtocolError::Cancelled)));
+
+        // Once cancelled, later steps do not start
+        assert!(token.is_cancelled());
+        assert!(matches!(token.run(async { Ok(()) }).await, Err(ProtocolError::Cancelled)));
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Step of the online phase, in execution order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OnlineStep {
+    /// The computational servers receive the masked submissions
+    Submission,
+    /// P₁ and P₂ apply their permutation halves
+    Shuffle,
+    /// Each computational server adds its noise share locally
+    Randomization,
+    /// The servers send their output shares to the curator
+    Reconstruction,
+}
+
+impl OnlineStep {
+    /// Every step, in execution order
+    pub const ALL: [OnlineStep; 4] = [
+        OnlineStep::Submission,
+        OnlineStep::Shuffle,
+        OnlineStep::Randomization,
+        OnlineStep::Reconstruction,
+    ];
+}
+
+impl fmt::Display for OnlineStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OnlineStep::Submission => write!(f, "submission"),
+            OnlineStep::Shuffle => write!(f, "shuffle"),
+            OnlineStep::Randomization => write!(f, "randomization"),
+            OnlineStep::Reconstruction => write!(f, "reconstruction"),
+        }
+    }
+}
+
+/// What the protocol does when a computational server fails during the online phase
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecoveryPolicy {
+    /// Stop and return `ServerFailed` with the statistics gathered so far
+    #[default]
+    Abort,
+    /// Bring the failed server back, have P₀ deal fresh correlated randomness
+    /// and rerun the online phase on the same submissions
+    ///
+    /// User masks only depend on the users' seeds, so the buffered submissions
+    /// stay valid under the new deal.
+    Redeal {
+        /// Number of times the online phase is rerun before giving up
+        max_attempts: usize,
+    },
+}
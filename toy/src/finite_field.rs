@@ -56,12 +56,8 @@ impl FieldElement {
             return Err(FieldError::ModulusMismatch);
         }
 
-        let sum = self.value + other.value;
-        let result = if sum >= self.modulus {
-            sum - self.modulus
-        } else {
-            sum
-        };
+        let sum = (self.value as u128) + (other.value as u128);
+        let result = (sum % (self.modulus as u128)) as u64;
 
         Ok(FieldElement::new(result, self.modulus))
     }
@@ -113,10 +109,10 @@ impl FieldElement {
             return Err(FieldError::DivisionByZero);
         }
 
-        let mut t = 0i64;
-        let mut new_t = 1i64;
-        let mut r = self.modulus as i64;
-        let mut new_r = self.value as i64;
+        let mut t = 0i128;
+        let mut new_t = 1i128;
+        let mut r = self.modulus as i128;
+        let mut new_r = self.value as i128;
 
         while new_r != 0 {
             let quotient = r / new_r;
@@ -133,7 +129,7 @@ impl FieldElement {
         }
 
         if t < 0 {
-            t += self.modulus as i64;
+            t += self.modulus as i128;
         }
 
         Ok(FieldElement::new(t as u64, self.modulus))
@@ -149,10 +145,10 @@ impl FieldElement {
         let mut result = FieldElement::one(self.modulus);
 
         while exponent > 0 {
-            if exponent % 2 == 1 {
+            if exponent & 1 == 1 {
                 result = result.mul(&base)?;
             }
-            exponent = exponent >> 1;
+            exponent >>= 1;
             base = base.mul(&base)?;
         }
 
@@ -161,8 +157,11 @@ impl FieldElement {
 
     /// Random field element
     pub fn random(modulus: u64) -> Self {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
+        Self::random_with(&mut rand::thread_rng(), modulus)
+    }
+
+    /// Random field element drawn from `rng`
+    pub fn random_with<R: rand::Rng + ?Sized>(rng: &mut R, modulus: u64) -> Self {
         let value = rng.gen_range(0..modulus);
         Self::new(value, modulus)
     }
@@ -218,25 +217,52 @@ impl FiniteField {
         })
     }
 
-    /// Check if a number is prime
+    /// Check if a number is prime (deterministic Miller-Rabin for 64-bit inputs)
     fn is_prime(n: u64) -> bool {
+        const WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
         if n < 2 {
             return false;
         }
-        if n == 2 {
-            return true;
-        }
-        if n % 2 == 0 {
-            return false;
+        for &p in &WITNESSES {
+            if n == p {
+                return true;
+            }
+            if n.is_multiple_of(p) {
+                return false;
+            }
         }
 
-        let mut i = 3;
-        while i * i <= n {
-            if n % i == 0 {
-                return false;
+        let mul_mod = |a: u64, b: u64| ((a as u128 * b as u128) % n as u128) as u64;
+        let pow_mod = |mut base: u64, mut exponent: u64| {
+            let mut result = 1u64;
+            while exponent > 0 {
+                if exponent & 1 == 1 {
+                    result = mul_mod(result, base);
+                }
+                base = mul_mod(base, base);
+                exponent >>= 1;
+            }
+            result
+        };
+
+        let s = (n - 1).trailing_zeros();
+        let d = (n - 1) >> s;
+
+        'witness: for &a in &WITNESSES {
+            let mut x = pow_mod(a, d);
+            if x == 1 || x == n - 1 {
+                continue;
             }
-            i += 2;
+            for _ in 1..s {
+                x = mul_mod(x, x);
+                if x == n - 1 {
+                    continue 'witness;
+                }
+            }
+            return false;
         }
+
         true
     }
 
@@ -276,6 +302,11 @@ impl FiniteField {
         FieldElement::random(self.modulus)
     }
 
+    /// Create random element drawn from `rng`
+    pub fn random_element_with<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> FieldElement {
+        FieldElement::random_with(rng, self.modulus)
+    }
+
     /// Create element from u64
     pub fn element(&self, value: u64) -> FieldElement {
         FieldElement::new(value, self.modulus)
@@ -323,6 +354,11 @@ impl FiniteField {
         Ok(result)
     }
 
+    /// Multiply every element of a vector by `scalar`
+    pub fn vector_scale(&self, a: &[FieldElement], scalar: &FieldElement) -> Result<Vec<FieldElement>, FieldError> {
+        a.iter().map(|x| x.mul(scalar)).collect()
+    }
+
     /// Matrix-vector multiplication
     pub fn matrix_vector_mul(&self, matrix: &[Vec<FieldElement>], vector: &[FieldElement]) -> Result<Vec<FieldElement>, FieldError> {
         if matrix.is_empty() || vector.is_empty() {
@@ -354,6 +390,11 @@ impl FiniteField {
         (0..length).map(|_| self.random_element()).collect()
     }
 
+    /// Generate random vector drawn from `rng`
+    pub fn random_vector_with<R: rand::Rng + ?Sized>(&self, rng: &mut R, length: usize) -> Vec<FieldElement> {
+        (0..length).map(|_| self.random_element_with(rng)).collect()
+    }
+
     /// Generate random matrix
     pub fn random_matrix(&self, rows: usize, cols: usize) -> Vec<Vec<FieldElement>> {
         (0..rows).map(|_| self.random_vector(cols)).collect()
@@ -375,6 +416,85 @@ pub enum FieldError {
     DimensionMismatch,
     #[error("Empty input")]
     EmptyInput,
+    #[error("Value out of range for the field encoding")]
+    OutOfRange,
+}
+
+/// Signed fixed-point encoding of real numbers into a prime field
+///
+/// A real `v` is encoded as `round(v · 2^fractional_bits)`, with negative
+/// integers `-x` stored as `modulus - x`. Field elements above `modulus / 2`
+/// decode as negative, so sums of encoded values decode correctly as long as
+/// they stay within `±modulus / 2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixedPoint {
+    /// Field modulus (prime)
+    modulus: u64,
+    /// Number of fractional bits
+    fractional_bits: u32,
+}
+
+impl FixedPoint {
+    /// Create a new encoding
+    pub fn new(modulus: u64, fractional_bits: u32) -> Result<Self, FieldError> {
+        if fractional_bits >= 62 || (1u64 << fractional_bits) >= modulus / 2 {
+            return Err(FieldError::OutOfRange);
+        }
+
+        Ok(Self {
+            modulus,
+            fractional_bits,
+        })
+    }
+
+    /// Get the scale factor `2^fractional_bits`
+    pub fn scale(&self) -> f64 {
+        (1u64 << self.fractional_bits) as f64
+    }
+
+    /// Get the number of fractional bits
+    pub fn fractional_bits(&self) -> u32 {
+        self.fractional_bits
+    }
+
+    /// Encode a signed integer, without scaling
+    pub fn encode_integer(&self, value: i64) -> Result<FieldElement, FieldError> {
+        if value.unsigned_abs() > self.modulus / 2 {
+            return Err(FieldError::OutOfRange);
+        }
+
+        let encoded = if value < 0 {
+            self.modulus - value.unsigned_abs()
+        } else {
+            value as u64
+        };
+        Ok(FieldElement::new(encoded, self.modulus))
+    }
+
+    /// Decode a field element as a signed integer, without scaling
+    pub fn decode_integer(&self, element: &FieldElement) -> i64 {
+        let value = element.value();
+        if value > self.modulus / 2 {
+            -((self.modulus - value) as i64)
+        } else {
+            value as i64
+        }
+    }
+
+    /// Encode a real number
+    pub fn encode(&self, value: f64) -> Result<FieldElement, FieldError> {
+        let scaled = (value * self.scale()).round();
+        if !scaled.is_finite() || scaled.abs() >= (self.modulus / 2) as f64 {
+            return Err(FieldError::OutOfRange);
+        }
+
+        self.encode_integer(scaled as i64)
+    }
+
+    /// Decode a field element as a real number
+    pub fn decode(&self, element: &FieldElement) -> f64 {
+        self.decode_integer(element) as f64 / self.scale()
+    }
 }
 
 #[cfg(test)]
@@ -441,4 +561,23 @@ mod tests {
         let result = field.matrix_vector_mul(&matrix, &vector).unwrap();
         assert_eq!(result.len(), 2);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_fixed_point_encoding() {
+        let codec = FixedPoint::new(0xFFFFFFFFFFFFFFC5, 16).unwrap();
+
+        for value in [0.0, 1.5, -1.5, -1234.25, 98765.0] {
+            assert_eq!(codec.decode(&codec.encode(value).unwrap()), value);
+        }
+
+        // Negative values wrap around the modulus and sums stay correct
+        let negative = codec.encode(-2.0).unwrap();
+        assert_eq!(negative.value(), 0xFFFFFFFFFFFFFFC5 - 2 * 65536);
+        let sum = negative.add(&codec.encode(0.5).unwrap()).unwrap();
+        assert_eq!(codec.decode(&sum), -1.5);
+
+        assert!(codec.encode(f64::NAN).is_err());
+        assert!(codec.encode(1e30).is_err());
+        assert!(FixedPoint::new(97, 8).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::finite_field::{FieldElement, FiniteField, FixedPoint};
+use crate::{ProtocolError, UserData};
+use serde::{Deserialize, Serialize};
+
+/// What the curator reconstructs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputMode {
+    /// One shuffled, noised row per user
+    #[default]
+    Rows,
+    /// A single noisy histogram over `num_features` buckets
+    ///
+    /// Every user submits a one-hot bucket vector. Each computational server
+    /// sums its shares of the shuffled contributions before sending anything,
+    /// so the curator only ever sees the aggregate, and P₀ deals noise for
+    /// that one row instead of every user's. With Laplace noise `noise_scale`
+    /// is the L1 sensitivity: 1 when a neighbor adds or removes a user, 2 when
+    /// it changes one user's bucket.
+    Histogram,
+}
+
+impl OutputMode {
+    /// Check if the output is a single histogram
+    pub fn is_histogram(&self) -> bool {
+        matches!(self, OutputMode::Histogram)
+    }
+}
+
+/// Encode `bucket` as a one-hot vector over `num_buckets` buckets
+pub fn one_hot(bucket: usize, num_buckets: usize, codec: &FixedPoint) -> Result<Vec<FieldElement>, ProtocolError> {
+    if bucket >= num_buckets {
+        return Err(ProtocolError::invalid_configuration(format!(
+            "Bucket {} is out of range for {} buckets",
+            bucket, num_buckets
+        )));
+    }
+
+    (0..num_buckets)
+        .map(|index| codec.encode(if index == bucket { 1.0 } else { 0.0 }).map_err(ProtocolError::from))
+        .collect()
+}
+
+/// Check that a user record is a one-hot bucket vector
+pub fn validate_one_hot(codec: &FixedPoint, user: &UserData) -> Result<(), ProtocolError> {
+    let one = codec.encode(1.0)?;
+    let mut ones = 0;
+    for value in &user.data {
+        if *value == one {
+            ones += 1;
+        } else if !value.is_zero() {
+            return Err(ProtocolError::NotOneHot { user_id: user.user_id });
+        }
+    }
+
+    if ones != 1 {
+        return Err(ProtocolError::NotOneHot { user_id: user.user_id });
+    }
+
+    Ok(())
+}
+
+/// Sum rows element-wise into a single row
+pub fn aggregate(field: &FiniteField, rows: &[Vec<FieldElement>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+    let (first, rest) = rows.split_first().ok_or(ProtocolError::EmptyInput)?;
+
+    let mut total = first.clone();
+    for row in rest {
+        total = field.vector_add(&total, row)?;
+    }
+
+    Ok(vec![total])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_hot_validation_and_aggregate() {
+        let modulus = 0xFFFFFFFFFFFFFFC5;
+        let codec = FixedPoint::new(modulus, 16).unwrap();
+        let field = FiniteField::new(modulus).unwrap();
+
+        let rows: Vec<Vec<FieldElement>> = [2, 0, 2].iter().map(|&bucket| one_hot(bucket, 3, &codec).unwrap()).collect();
+        for (user_id, row) in rows.iter().enumerate() {
+            assert!(validate_one_hot(&codec, &UserData::new(user_id, row.clone(), [0; 32])).is_ok());
+        }
+        assert!(one_hot(3, 3, &codec).is_err());
+
+        let histogram = aggregate(&field, &rows).unwrap();
+        let counts: Vec<f64> = histogram[0].iter().map(|count| codec.decode(count)).collect();
+        assert_eq!(counts, vec![1.0, 0.0, 2.0]);
+
+        let two_hot = UserData::from_values(4, &[1.0, 1.0, 0.0], [0; 32], &codec).unwrap();
+        let fractional = UserData::from_values(5, &[0.5, 0.5, 0.0], [0; 32], &codec).unwrap();
+        assert!(matches!(validate_one_hot(&codec, &two_hot), Err(ProtocolError::NotOneHot { user_id: 4 })));
+        assert!(matches!(validate_one_hot(&codec, &fractional), Err(ProtocolError::NotOneHot { user_id: 5 })));
+    }
+}
@@ -7,30 +7,100 @@ pub mod offline_phase;
 pub mod online_phase;
 pub mod protocol;
 pub mod server;
+pub mod noise;
+pub mod mac;
+pub mod mask;
+pub mod message;
+#[cfg(feature = "runtime")]
+pub mod network;
+pub mod simulation;
+pub mod progress;
+pub mod schema;
+pub mod parallel;
+pub mod fault;
+pub mod histogram;
+pub mod seed;
+pub mod user;
+#[cfg(feature = "runtime")]
+pub mod audit;
+#[cfg(feature = "runtime")]
+pub mod cancel;
 
-pub use finite_field::{FieldElement, FiniteField, FieldError};
-pub use secret_sharing::{SecretShare, ShamirSecretSharing, ShareDistributor};
-pub use offline_phase::OfflinePhase;
-pub use online_phase::OnlinePhase;
+pub use finite_field::{FieldElement, FiniteField, FieldError, FixedPoint};
+pub use secret_sharing::{AdditiveSecretSharing, SecretShare, ShamirSecretSharing, ShareDistributor};
+pub use offline_phase::{OfflineManifest, OfflinePhase, OfflineStats, ServerCorrelation, UserRegistration};
+pub use online_phase::{OnlinePhase, OnlineStats};
 pub use protocol::{ProtocolConfig, ProtocolError};
 pub use server::{Server, ServerRole, ServerState, ServerStats};
+pub use noise::{BudgetSplit, NoiseKind};
+pub use mac::{AuthenticatedShare, MacShares, SecurityMode};
+pub use mask::{MaskDerivation, MaskSeed};
+pub use message::{MessageBytes, ToyMessage};
+pub use simulation::PlaintextSimulation;
+pub use schema::{DataSchema, FeatureRange};
+pub use progress::{ConsoleObserver, Phase, ProgressObserver, SharedObserver, SilentObserver};
+pub use user::UserClient;
+pub use fault::{OnlineStep, RecoveryPolicy};
+pub use histogram::OutputMode;
+pub use seed::SeedTree;
+#[cfg(feature = "runtime")]
+pub use network::{Connection, ServerTraffic, Topology, TrafficStats};
+#[cfg(feature = "runtime")]
+pub use audit::{AuditReport, PrivacyAudit};
+#[cfg(feature = "runtime")]
+pub use cancel::CancellationToken;
 
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "runtime")]
 use std::collections::HashMap;
+#[cfg(feature = "runtime")]
+use std::path::Path;
+#[cfg(feature = "runtime")]
+use tokio::task::JoinHandle;
+#[cfg(feature = "runtime")]
+use tracing::{field::Empty, info_span};
 
 /// Configuration for the 3-server protocol
+///
+/// Fields missing from a serialized configuration take their default values.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ToyConfig {
     /// Field modulus (prime)
     pub field_modulus: u64,
     /// Number of users
     pub num_users: usize,
+    /// Number of features per user
+    pub num_features: usize,
     /// Privacy budget epsilon
     pub epsilon: f64,
     /// Privacy budget delta
     pub delta: f64,
-    /// Noise scale for differential privacy
+    /// Noise scale for differential privacy (the sensitivity of each value)
     pub noise_scale: f64,
+    /// Distribution the noise is sampled from
+    pub noise: NoiseKind,
+    /// How the privacy budget is divided between features
+    pub budget: BudgetSplit,
+    /// Fractional bits of the fixed-point encoding of data and noise
+    pub fractional_bits: u32,
+    /// Process users in batches of this size, each with its own correlated randomness
+    pub batch_size: Option<usize>,
+    /// Whether shares are authenticated against a malicious computational server
+    pub security: SecurityMode,
+    /// Check every output against a trusted plaintext simulation (testing only)
+    pub verify: bool,
+    /// Declared value ranges that every submitted record is validated against
+    pub schema: Option<DataSchema>,
+    /// What to do when a computational server fails during the online phase
+    pub recovery: RecoveryPolicy,
+    /// Whether the curator reconstructs per-user rows or a single histogram
+    pub output: OutputMode,
+    /// Master seed all of P₀'s randomness is derived from, for reproducible runs
+    ///
+    /// Two runs with the same seed and inputs produce identical outputs.
+    /// Only for debugging and CI: anyone who knows the seed can unmask the data.
+    pub seed: Option<u64>,
 }
 
 impl Default for ToyConfig {
@@ -38,9 +108,74 @@ impl Default for ToyConfig {
         Self {
             field_modulus: 0xFFFFFFFFFFFFFFC5, // 2^64 - 59
             num_users: 1000,
+            num_features: 2,
             epsilon: 1.0,
             delta: 1e-5,
             noise_scale: 1.0,
+            noise: NoiseKind::Laplace,
+            budget: BudgetSplit::PerFeature,
+            fractional_bits: 16,
+            batch_size: None,
+            security: SecurityMode::SemiHonest,
+            verify: false,
+            schema: None,
+            recovery: RecoveryPolicy::Abort,
+            output: OutputMode::Rows,
+            seed: None,
+        }
+    }
+}
+
+impl ToyConfig {
+    /// Load a configuration from a TOML file
+    ///
+    /// Missing fields take their default values. Parse errors name the line and
+    /// column of the offending value.
+    #[cfg(feature = "config")]
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, ProtocolError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ProtocolError::invalid_configuration(format!("{}: {}", path.display(), e)))?;
+        let config: Self = toml::from_str(&text).map_err(|e| {
+            let (line, column) = e.span().map_or((0, 0), |span| {
+                let before = &text[..span.start];
+                let line = before.matches('\n').count() + 1;
+                (line, before.chars().rev().take_while(|&c| c != '\n').count() + 1)
+            });
+            ProtocolError::invalid_configuration(format!("{}:{}:{}: {}", path.display(), line, column, e.message()))
+        })?;
+
+        config
+            .validate()
+            .map_err(|e| ProtocolError::invalid_configuration(format!("{}: {}", path.display(), e)))?;
+        Ok(config)
+    }
+
+    /// Check the parameters `ToyProtocol::new` and the fixed-point encoding rely on
+    pub fn validate(&self) -> Result<(), ProtocolError> {
+        if !(self.epsilon.is_finite() && self.epsilon > 0.0) {
+            return Err(ProtocolError::invalid_configuration("epsilon must be positive"));
+        }
+        if !(0.0..1.0).contains(&self.delta) {
+            return Err(ProtocolError::invalid_configuration("delta must be in [0, 1)"));
+        }
+        if let Some(schema) = &self.schema {
+            schema.check(self)?;
+        }
+        self.budget.fractions(self.num_features)?;
+        FiniteField::new(self.field_modulus)?;
+        FixedPoint::new(self.field_modulus, self.fractional_bits)?;
+
+        Ok(())
+    }
+
+    /// Seed for `user_id` to derive its mask from
+    ///
+    /// Derived from the master seed in seeded runs, random otherwise.
+    pub fn user_seed(&self, user_id: usize) -> MaskSeed {
+        match self.seed {
+            Some(master) => SeedTree::new(master).child(seed::USERS).derive_key(user_id as u64),
+            None => MaskDerivation::generate_seed(),
         }
     }
 }
@@ -53,12 +188,12 @@ pub struct UserData {
     /// User's private data
     pub data: Vec<FieldElement>,
     /// User's seed for mask generation
-    pub seed: u64,
+    pub seed: MaskSeed,
 }
 
 impl UserData {
     /// Create new user data
-    pub fn new(user_id: usize, data: Vec<FieldElement>, seed: u64) -> Self {
+    pub fn new(user_id: usize, data: Vec<FieldElement>, seed: MaskSeed) -> Self {
         Self {
             user_id,
             data,
@@ -66,6 +201,21 @@ impl UserData {
         }
     }
 
+    /// Create user data from real values, encoded with `codec`
+    pub fn from_values(user_id: usize, values: &[f64], seed: MaskSeed, codec: &FixedPoint) -> Result<Self, ProtocolError> {
+        let data = values
+            .iter()
+            .map(|&value| codec.encode(value))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::new(user_id, data, seed))
+    }
+
+    /// Create a histogram contribution: a one-hot vector over `num_buckets` buckets
+    pub fn one_hot(user_id: usize, bucket: usize, num_buckets: usize, seed: MaskSeed, codec: &FixedPoint) -> Result<Self, ProtocolError> {
+        Ok(Self::new(user_id, histogram::one_hot(bucket, num_buckets, codec)?, seed))
+    }
+
     /// Get data length
     pub fn len(&self) -> usize {
         self.data.len()
@@ -86,6 +236,20 @@ pub struct ProtocolResult {
     pub privacy_guarantees: PrivacyGuarantees,
     /// Protocol statistics
     pub stats: ProtocolStats,
+    /// Per-step timing of the offline phase
+    pub offline_stats: OfflineStats,
+    /// Per-step timing of the online phase
+    pub online_stats: OnlineStats,
+}
+
+impl ProtocolResult {
+    /// Decode the result rows as real values
+    pub fn decode(&self, codec: &FixedPoint) -> Vec<Vec<f64>> {
+        self.result
+            .iter()
+            .map(|row| row.iter().map(|value| codec.decode(value)).collect())
+            .collect()
+    }
 }
 
 /// Privacy guarantees
@@ -97,56 +261,117 @@ pub struct PrivacyGuarantees {
     pub delta: f64,
     /// Whether guarantees are proven
     pub is_proven: bool,
+    /// Epsilon spent on each feature, in order
+    #[serde(default)]
+    pub feature_epsilons: Vec<f64>,
 }
 
 /// Protocol statistics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProtocolStats {
     /// Offline phase time (ms)
     pub offline_time_ms: u64,
     /// Online phase time (ms)
     pub online_time_ms: u64,
+    /// Offline communication from P₀ to the computational servers (bytes)
+    pub offline_communication_bytes: usize,
+    /// Online communication from users and between servers (bytes)
+    pub online_communication_bytes: usize,
     /// Total communication (bytes)
     pub total_communication_bytes: usize,
+    /// Online communication between the computational servers (bytes)
+    ///
+    /// Only the blinded rows P₁ sends P₂ during the shuffle, and their tags in
+    /// malicious mode: the rest of the online phase is silent between servers.
+    #[serde(default)]
+    pub online_server_bytes: usize,
+    /// Communication in both phases by message type (bytes)
+    #[serde(default)]
+    pub communication_by_type: MessageBytes,
     /// Number of field operations
     pub field_operations: usize,
 }
 
-impl Default for ProtocolStats {
-    fn default() -> Self {
-        Self {
-            offline_time_ms: 0,
-            online_time_ms: 0,
-            total_communication_bytes: 0,
-            field_operations: 0,
-        }
+/// Output of an offline phase dealt for a collection window
+#[cfg(feature = "runtime")]
+struct DealtOffline {
+    /// Correlation for each computational server
+    correlations: HashMap<usize, ServerCorrelation>,
+    /// P₀'s plaintext view, kept only when verifying
+    simulation: Option<PlaintextSimulation>,
+    /// Per-step timing of the dealing
+    stats: OfflineStats,
+    /// Time the whole offline phase took (ms)
+    time_ms: u64,
+}
+
+/// Offline phase running in the background
+#[cfg(feature = "runtime")]
+type PendingOffline = JoinHandle<Result<DealtOffline, ProtocolError>>;
+
+/// Collection window opened by `ToyProtocol::start_collection`
+#[cfg(feature = "runtime")]
+struct Collection {
+    /// Registered users, in the order the offline phase dealt for them
+    registrations: Vec<UserRegistration>,
+    /// Position of each registered user
+    positions: HashMap<usize, usize>,
+    /// Masked submissions received so far, by position
+    submissions: Vec<Option<Vec<FieldElement>>>,
+    /// Unmasked inputs, by position, kept only when verifying
+    inputs: Vec<Option<Vec<FieldElement>>>,
+    /// Offline phase still being computed
+    offline: PendingOffline,
+}
+
+impl ProtocolStats {
+    /// Add the statistics of another run
+    pub fn merge(&mut self, other: &ProtocolStats) {
+        self.offline_time_ms += other.offline_time_ms;
+        self.online_time_ms += other.online_time_ms;
+        self.offline_communication_bytes += other.offline_communication_bytes;
+        self.online_communication_bytes += other.online_communication_bytes;
+        self.total_communication_bytes += other.total_communication_bytes;
+        self.online_server_bytes += other.online_server_bytes;
+        self.communication_by_type.merge(&other.communication_by_type);
+        self.field_operations += other.field_operations;
     }
 }
 
 /// Main protocol implementation
+#[cfg(feature = "runtime")]
 pub struct ToyProtocol {
     /// Configuration
     config: ToyConfig,
     /// Finite field
     field: FiniteField,
     /// Secret sharing scheme
-    secret_sharing: ShamirSecretSharing,
+    secret_sharing: AdditiveSecretSharing,
     /// Offline phase
     offline_phase: OfflinePhase,
     /// Online phase
     online_phase: OnlinePhase,
     /// Servers
     servers: HashMap<usize, Server>,
+    /// Open collection window, if any
+    collection: Option<Collection>,
+    /// Receives progress events
+    observer: SharedObserver,
+    /// Aborts the run when cancelled
+    cancellation: CancellationToken,
 }
 
+#[cfg(feature = "runtime")]
 impl ToyProtocol {
     /// Create new protocol instance
     pub fn new(config: ToyConfig) -> Result<Self, ProtocolError> {
+        config.validate()?;
+
         let field = FiniteField::new(config.field_modulus)?;
-        let secret_sharing = ShamirSecretSharing::new(2, 3, config.field_modulus)?;
+        let secret_sharing = AdditiveSecretSharing::new(config.field_modulus)?;
         
         let offline_phase = OfflinePhase::new(config.clone(), field.clone(), secret_sharing.clone())?;
-        let online_phase = OnlinePhase::new(config.clone(), field.clone(), secret_sharing.clone())?;
+        let online_phase = OnlinePhase::new(config.clone(), field.clone())?;
 
         // Initialize servers
         let mut servers = HashMap::new();
@@ -161,49 +386,517 @@ impl ToyProtocol {
             offline_phase,
             online_phase,
             servers,
+            collection: None,
+            observer: progress::silent(),
+            cancellation: CancellationToken::new(),
         })
     }
 
+    /// Send progress events to `observer` instead of discarding them
+    pub fn set_observer(&mut self, observer: SharedObserver) {
+        self.offline_phase.set_observer(observer.clone());
+        self.online_phase.set_observer(observer.clone());
+        self.observer = observer;
+    }
+
+    /// Abort runs when `token` is cancelled
+    ///
+    /// A cancelled run fails with `ProtocolError::Cancelled` at its next await
+    /// point: the offline task is stopped, the open collection window is
+    /// discarded and the servers drop their correlated randomness.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+
+    /// Token that aborts runs of this protocol when cancelled
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
     /// Execute the complete protocol
+    ///
+    /// Runs in batches when `batch_size` is configured; in histogram mode the
+    /// batches' noisy histograms are summed into one.
     pub async fn execute(&mut self, user_data: Vec<UserData>) -> Result<ProtocolResult, ProtocolError> {
-        let start_time = std::time::Instant::now();
+        if let Some(batch_size) = self.config.batch_size {
+            let mut output = Vec::with_capacity(user_data.len());
+            let mut result = self
+                .execute_batched(user_data, batch_size, |batch| {
+                    output.extend(batch);
+                    Ok(())
+                })
+                .await?;
+            if self.config.output.is_histogram() {
+                output = histogram::aggregate(&self.field, &output)?;
+            }
+            result.result = output;
+            return Ok(result);
+        }
+
+        self.execute_single(user_data).await
+    }
+
+    /// Execute the protocol over all users at once
+    async fn execute_single(&mut self, user_data: Vec<UserData>) -> Result<ProtocolResult, ProtocolError> {
+        self.start_collection(registrations(&user_data))?;
+        self.submit_chunk(user_data)?;
+        self.finalize().await
+    }
+
+    /// Execute the protocol over users in batches of `batch_size`
+    ///
+    /// Only one batch of users is held in memory at a time. Each batch gets its
+    /// own correlated randomness and is shuffled on its own, so a batch is the
+    /// anonymity set. Each batch's output is passed to `sink` as soon as it is
+    /// reconstructed; the returned result carries the merged statistics and no rows.
+    pub async fn execute_batched<I, F>(
+        &mut self,
+        users: I,
+        batch_size: usize,
+        mut sink: F,
+    ) -> Result<ProtocolResult, ProtocolError>
+    where
+        I: IntoIterator<Item = UserData>,
+        F: FnMut(Vec<Vec<FieldElement>>) -> Result<(), ProtocolError>,
+    {
+        if batch_size == 0 {
+            return Err(ProtocolError::invalid_configuration("Batch size must be positive"));
+        }
+
+        let mut totals = self.batch_totals();
+        let mut users = users.into_iter().peekable();
+        let mut batch_index = 0;
+        while users.peek().is_some() {
+            self.cancellation.check()?;
+            let batch: Vec<UserData> = users.by_ref().take(batch_size).collect();
+            self.observer.batch_started(batch_index, batch.len());
+
+            let mut batch_protocol = self.batch_protocol(batch_index, batch.len())?;
+            let span = info_span!("batch", index = batch_index, users = batch.len(), elapsed_ms = Empty);
+            let batch_result = progress::timed(span, batch_protocol.execute_single(batch)).await?;
+
+            merge_batch(&mut totals, &batch_result);
+            sink(batch_result.result)?;
+            batch_index += 1;
+        }
+
+        Ok(totals)
+    }
+
+    /// Execute the protocol over users in batches of `batch_size`, overlapping
+    /// the offline phase of each batch with the online phase of the one before
+    ///
+    /// Each batch's collection window is opened, and its offline phase started
+    /// in the background, before the previous batch is finalized. With a
+    /// multi-threaded runtime, P₀ deals for batch `k + 1` while batch `k` is
+    /// shuffled and reconstructed, so end-to-end latency approaches the sum of
+    /// the online phases plus one offline phase. Outputs and statistics are as
+    /// for `execute_batched`, but up to two batches are held in memory.
+    pub async fn execute_pipelined<I, F>(
+        &mut self,
+        users: I,
+        batch_size: usize,
+        mut sink: F,
+    ) -> Result<ProtocolResult, ProtocolError>
+    where
+        I: IntoIterator<Item = UserData>,
+        F: FnMut(Vec<Vec<FieldElement>>) -> Result<(), ProtocolError>,
+    {
+        if batch_size == 0 {
+            return Err(ProtocolError::invalid_configuration("Batch size must be positive"));
+        }
+
+        let mut totals = self.batch_totals();
+        let mut users = users.into_iter().peekable();
+        let mut next = self.open_batch(&mut users, batch_size, 0)?;
+        let mut batch_index = 0;
+        while let Some((mut batch_protocol, batch)) = next.take() {
+            self.cancellation.check()?;
+            let batch_users = batch.len();
+            // Users submit while their batch's offline phase is still running
+            batch_protocol.submit_chunk(batch)?;
+
+            // Start dealing for the next batch before this one goes online
+            next = self.open_batch(&mut users, batch_size, batch_index + 1)?;
+
+            let span = info_span!("batch", index = batch_index, users = batch_users, pipelined = true, elapsed_ms = Empty);
+            let batch_result = progress::timed(span, batch_protocol.finalize()).await?;
+
+            merge_batch(&mut totals, &batch_result);
+            sink(batch_result.result)?;
+            batch_index += 1;
+        }
+
+        Ok(totals)
+    }
+
+    /// Take the next batch of users and open its collection window, starting its offline phase
+    fn open_batch<I>(
+        &self,
+        users: &mut std::iter::Peekable<I>,
+        batch_size: usize,
+        batch_index: usize,
+    ) -> Result<Option<(ToyProtocol, Vec<UserData>)>, ProtocolError>
+    where
+        I: Iterator<Item = UserData>,
+    {
+        if users.peek().is_none() {
+            return Ok(None);
+        }
+
+        let batch: Vec<UserData> = users.by_ref().take(batch_size).collect();
+        self.observer.batch_started(batch_index, batch.len());
+        let mut batch_protocol = self.batch_protocol(batch_index, batch.len())?;
+        batch_protocol.start_collection(registrations(&batch))?;
+
+        Ok(Some((batch_protocol, batch)))
+    }
+
+    /// Create the protocol instance that runs batch `batch_index` of `users` users
+    fn batch_protocol(&self, batch_index: usize, users: usize) -> Result<ToyProtocol, ProtocolError> {
+        // Every batch gets its own branch of the seed tree, so batches never share noise
+        let batch_config = ToyConfig {
+            num_users: users,
+            batch_size: None,
+            seed: self.config.seed.map(|master| {
+                SeedTree::new(master).child(seed::BATCHES).derive_seed(batch_index as u64)
+            }),
+            ..self.config.clone()
+        };
+        let mut batch_protocol = ToyProtocol::new(batch_config)?;
+        batch_protocol.set_observer(self.observer.clone());
+        batch_protocol.set_cancellation(self.cancellation.clone());
+
+        Ok(batch_protocol)
+    }
+
+    /// Empty result that batch statistics are merged into
+    fn batch_totals(&self) -> ProtocolResult {
+        ProtocolResult {
+            result: Vec::new(),
+            privacy_guarantees: self.privacy_guarantees(),
+            stats: ProtocolStats::default(),
+            offline_stats: OfflineStats::default(),
+            online_stats: OnlineStats::default(),
+        }
+    }
+
+    /// Privacy guarantees of this configuration
+    fn privacy_guarantees(&self) -> PrivacyGuarantees {
+        // Validated when the protocol was created
+        let fractions = self.config.budget.fractions(self.config.num_features).unwrap_or_default();
+        let (epsilon, feature_epsilons) = match self.config.noise {
+            NoiseKind::Laplace => (
+                self.config.epsilon,
+                fractions.iter().map(|fraction| fraction * self.config.epsilon).collect(),
+            ),
+            NoiseKind::DiscreteGaussian { sigma } => {
+                let epsilon = |sigma| noise::gaussian_epsilon(self.config.noise_scale, sigma, self.config.delta);
+                (
+                    epsilon(sigma),
+                    fractions.iter().map(|fraction| epsilon(sigma / fraction.sqrt())).collect(),
+                )
+            }
+        };
+
+        PrivacyGuarantees {
+            epsilon,
+            delta: self.config.delta,
+            is_proven: true,
+            feature_epsilons,
+        }
+    }
+
+    /// Open a collection window for the registered users
+    ///
+    /// The offline phase starts in the background, and users can `submit`
+    /// while it runs. Must be called from within a Tokio runtime.
+    pub fn start_collection(&mut self, registrations: Vec<UserRegistration>) -> Result<(), ProtocolError> {
+        if self.collection.is_some() {
+            return Err(ProtocolError::invalid_configuration("A collection window is already open"));
+        }
 
         // Phase 1: Offline preparation
-        println!("Starting offline phase...");
+        self.observer.phase_started(Phase::Offline);
+        let mut dealer = OfflinePhase::new(self.config.clone(), self.field.clone(), self.secret_sharing.clone())?;
+        dealer.set_observer(self.observer.clone());
+        let dealt_for = registrations.clone();
+        let verify = self.config.verify;
+        let cancellation = self.cancellation.clone();
+        let offline = tokio::spawn(async move {
+            let offline_start = std::time::Instant::now();
+            let span = info_span!("offline_phase", users = dealt_for.len(), elapsed_ms = Empty);
+            let dealing = progress::timed(span, dealer.deal_with_simulation(&dealt_for));
+            let (correlations, simulation, stats) = cancellation.run(dealing).await?;
+            Ok(DealtOffline {
+                correlations,
+                simulation: verify.then_some(simulation),
+                stats,
+                time_ms: offline_start.elapsed().as_millis() as u64,
+            })
+        });
+
+        self.open_collection(registrations, offline)
+    }
+
+    /// Run the offline phase ahead of time and save it to `dir`
+    pub async fn precompute_offline(&mut self, registrations: Vec<UserRegistration>, dir: impl AsRef<Path>) -> Result<(), ProtocolError> {
+        self.observer.phase_started(Phase::Offline);
         let offline_start = std::time::Instant::now();
-        self.offline_phase.execute(&mut self.servers).await?;
-        let offline_time = offline_start.elapsed().as_millis() as u64;
-        println!("✓ Offline phase completed in {}ms", offline_time);
+        let correlations = self.cancellation.run(self.offline_phase.deal(&registrations)).await?;
+        let manifest = OfflineManifest {
+            field_modulus: self.config.field_modulus,
+            num_features: self.config.num_features,
+            registrations,
+        };
+        offline_phase::save_offline(dir.as_ref(), &manifest, &correlations)?;
+        self.observer.progress(Phase::Offline, 100.0, &format!("Saved offline phase to {}", dir.as_ref().display()));
+        self.observer.phase_completed(Phase::Offline, offline_start.elapsed());
+
+        Ok(())
+    }
+
+    /// Load a precomputed offline phase from `dir` and open a collection window for its users
+    ///
+    /// Users then `submit` and the window is closed with `finalize`, as with
+    /// `start_collection`. Must be called from within a Tokio runtime.
+    pub fn load_offline(&mut self, dir: impl AsRef<Path>) -> Result<(), ProtocolError> {
+        if self.collection.is_some() {
+            return Err(ProtocolError::invalid_configuration("A collection window is already open"));
+        }
+
+        let (manifest, correlations) = offline_phase::load_offline(dir.as_ref())?;
+        manifest.check(&self.config)?;
+
+        // The material is already computed, so the pending offline phase resolves immediately
+        let offline = tokio::spawn(async move {
+            Ok(DealtOffline {
+                correlations,
+                simulation: None,
+                stats: OfflineStats::default(),
+                time_ms: 0,
+            })
+        });
+        self.open_collection(manifest.registrations, offline)
+    }
+
+    /// Open a collection window whose offline phase is `offline`
+    fn open_collection(&mut self, registrations: Vec<UserRegistration>, offline: PendingOffline) -> Result<(), ProtocolError> {
+        let mut positions = HashMap::with_capacity(registrations.len());
+        for (position, registration) in registrations.iter().enumerate() {
+            if positions.insert(registration.user_id, position).is_some() {
+                offline.abort();
+                return Err(ProtocolError::invalid_configuration(format!(
+                    "User {} is registered twice",
+                    registration.user_id
+                )));
+            }
+        }
+
+        self.collection = Some(Collection {
+            submissions: vec![None; registrations.len()],
+            inputs: vec![None; registrations.len()],
+            registrations,
+            positions,
+            offline,
+        });
+
+        Ok(())
+    }
+
+    /// Submit one user's data to the open collection window
+    ///
+    /// The record is validated against the configuration and schema, then the
+    /// user masks its data immediately, so only the masked submission is buffered.
+    pub fn submit(&mut self, user: UserData) -> Result<(), ProtocolError> {
+        let collection = self
+            .collection
+            .as_mut()
+            .ok_or_else(|| ProtocolError::invalid_configuration("No collection window is open"))?;
+
+        let user_id = user.user_id;
+        let position = *collection
+            .positions
+            .get(&user_id)
+            .ok_or(ProtocolError::UnregisteredUser { user_id })?;
+        if collection.registrations[position].seed != user.seed {
+            return Err(ProtocolError::UnregisteredUser { user_id });
+        }
+        if collection.submissions[position].is_some() {
+            return Err(ProtocolError::DuplicateSubmission { user_id });
+        }
+        let codec = FixedPoint::new(self.config.field_modulus, self.config.fractional_bits)?;
+        schema::validate_user(&self.config, &codec, &user)?;
+
+        if self.config.verify {
+            collection.inputs[position] = Some(user.data.clone());
+        }
+        let masked = self.online_phase.mask_submissions(vec![user])?;
+        collection.submissions[position] = masked.into_iter().next();
+
+        Ok(())
+    }
+
+    /// Submit a chunk of users to the open collection window
+    pub fn submit_chunk(&mut self, users: impl IntoIterator<Item = UserData>) -> Result<(), ProtocolError> {
+        for user in users {
+            self.submit(user)?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of users that have submitted to the open collection window
+    pub fn submitted(&self) -> usize {
+        self.collection
+            .as_ref()
+            .map_or(0, |collection| collection.submissions.iter().filter(|s| s.is_some()).count())
+    }
+
+    /// Close the collection window and run the online phase
+    ///
+    /// Waits for the offline phase if it is still running. Every registered
+    /// user must have submitted.
+    pub async fn finalize(&mut self) -> Result<ProtocolResult, ProtocolError> {
+        let result = self.finalize_collection().await;
+        if matches!(result, Err(ProtocolError::Cancelled)) {
+            self.release();
+        }
+        result
+    }
+
+    /// Close the collection window and run the online phase, until cancelled
+    async fn finalize_collection(&mut self) -> Result<ProtocolResult, ProtocolError> {
+        let collection = self
+            .collection
+            .take()
+            .ok_or_else(|| ProtocolError::invalid_configuration("No collection window is open"))?;
+
+        let missing = collection.submissions.iter().filter(|s| s.is_none()).count();
+        if missing > 0 {
+            collection.offline.abort();
+            return Err(ProtocolError::MissingSubmissions { missing });
+        }
+        let submissions: Vec<Vec<FieldElement>> = collection.submissions.into_iter().flatten().collect();
+
+        let mut offline = collection.offline;
+        let dealt = tokio::select! {
+            biased;
+            _ = self.cancellation.cancelled() => {
+                offline.abort();
+                return Err(ProtocolError::Cancelled);
+            }
+            dealt = &mut offline => dealt,
+        };
+        let dealt = dealt.map_err(|e| ProtocolError::internal_error(format!("Offline phase task failed: {}", e)))??;
+        let offline_time = dealt.time_ms;
+        let mut simulation = dealt.simulation;
+        self.offline_phase.distribute(&mut self.servers, dealt.correlations)?;
+        let mut offline_stats = dealt.stats;
+        self.observer.phase_completed(Phase::Offline, std::time::Duration::from_millis(offline_time));
 
         // Phase 2: Online execution
-        println!("Starting online phase...");
+        self.observer.phase_started(Phase::Online);
         let online_start = std::time::Instant::now();
-        let result = self.online_phase.execute(&mut self.servers, user_data).await?;
+        let mut redeals = 0;
+        let result = loop {
+            let span = info_span!("online_phase", users = submissions.len(), elapsed_ms = Empty);
+            let online = self.online_phase.execute_submissions(&mut self.servers, submissions.clone());
+            match self.cancellation.run(progress::timed(span, online)).await {
+                Ok(result) => break result,
+                Err(ProtocolError::ServerFailed { server_id, step, .. }) if self.may_redeal(redeals) => {
+                    redeals += 1;
+                    tracing::warn!(server_id, %step, redeals, "computational server failed, re-dealing");
+                    self.observer.progress(
+                        Phase::Online,
+                        0.0,
+                        &format!("Server {} failed during {}, re-dealing", server_id, step),
+                    );
+
+                    // P₀ deals fresh correlated randomness for the same users
+                    self.servers.get_mut(&server_id).ok_or(ProtocolError::ServerNotFound)?.recover();
+                    let redealing = self.offline_phase.deal_with_simulation(&collection.registrations);
+                    let (correlations, redealt, stats) = self.cancellation.run(redealing).await?;
+                    self.offline_phase.distribute(&mut self.servers, correlations)?;
+                    offline_stats.merge(&stats);
+                    if self.config.verify {
+                        simulation = Some(redealt);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        offline_stats.merge(&self.offline_phase.stats());
         let online_time = online_start.elapsed().as_millis() as u64;
-        println!("✓ Online phase completed in {}ms", online_time);
 
-        let total_time = start_time.elapsed().as_millis() as u64;
+        if self.config.verify {
+            let simulation = simulation.ok_or_else(|| {
+                ProtocolError::invalid_configuration("Verification needs an offline phase dealt by this protocol instance")
+            })?;
+            let inputs: Vec<Vec<FieldElement>> = collection.inputs.into_iter().flatten().collect();
+            if self.config.output.is_histogram() {
+                simulation.verify_histogram(&self.field, &inputs, &result)?;
+            } else {
+                simulation.verify(&self.field, &inputs, &result)?;
+            }
+            self.observer.progress(Phase::Online, 100.0, "Output matches the plaintext simulation");
+        }
+        self.observer.phase_completed(Phase::Online, std::time::Duration::from_millis(online_time));
 
+        let offline_communication_bytes = self.offline_phase.communication_bytes();
+        let online_communication_bytes = self.online_phase.communication_bytes();
+        let online_communication = self.online_phase.communication();
+        let mut communication_by_type = self.offline_phase.stats().communication;
+        communication_by_type.merge(online_communication);
         let stats = ProtocolStats {
             offline_time_ms: offline_time,
             online_time_ms: online_time,
-            total_communication_bytes: 0, // No communication in online phase
+            offline_communication_bytes,
+            online_communication_bytes,
+            total_communication_bytes: offline_communication_bytes + online_communication_bytes,
+            online_server_bytes: online_communication.get("blinded rows") + online_communication.get("blinded tags"),
+            communication_by_type,
             field_operations: self.online_phase.field_operations(),
         };
 
-        let privacy_guarantees = PrivacyGuarantees {
-            epsilon: self.config.epsilon,
-            delta: self.config.delta,
-            is_proven: true,
-        };
-
         Ok(ProtocolResult {
             result,
-            privacy_guarantees,
+            privacy_guarantees: self.privacy_guarantees(),
             stats,
+            offline_stats,
+            online_stats: self.online_phase.stats(),
         })
     }
 
+    /// Drop the correlated randomness and partial results of a cancelled run
+    fn release(&mut self) {
+        for server in self.servers.values_mut() {
+            server.recover();
+        }
+    }
+
+    /// Whether the recovery policy allows another re-deal after `redeals` of them
+    fn may_redeal(&self, redeals: usize) -> bool {
+        match self.config.recovery {
+            RecoveryPolicy::Abort => false,
+            RecoveryPolicy::Redeal { max_attempts } => redeals < max_attempts,
+        }
+    }
+
+    /// Make computational server `server_id` fail when the online phase reaches `step`
+    pub fn inject_failure(&mut self, server_id: usize, step: OnlineStep) -> Result<(), ProtocolError> {
+        let server = self.servers.get_mut(&server_id).ok_or(ProtocolError::ServerNotFound)?;
+        if !server.is_computational() {
+            return Err(ProtocolError::invalid_configuration(format!(
+                "Server {} is not a computational server",
+                server_id
+            )));
+        }
+        server.fail_at(step);
+
+        Ok(())
+    }
+
     /// Get server by ID
     pub fn get_server(&self, server_id: usize) -> Option<&Server> {
         self.servers.get(&server_id)
@@ -223,9 +916,36 @@ impl ToyProtocol {
     pub fn field(&self) -> &FiniteField {
         &self.field
     }
+
+    /// Get the fixed-point encoding for data and results
+    pub fn codec(&self) -> Result<FixedPoint, ProtocolError> {
+        Ok(FixedPoint::new(self.config.field_modulus, self.config.fractional_bits)?)
+    }
+
+    /// Get secret sharing scheme
+    pub fn secret_sharing(&self) -> &AdditiveSecretSharing {
+        &self.secret_sharing
+    }
+}
+
+/// Registrations of `users`, in order
+#[cfg(feature = "runtime")]
+fn registrations(users: &[UserData]) -> Vec<UserRegistration> {
+    users
+        .iter()
+        .map(|user| UserRegistration::new(user.user_id, user.seed))
+        .collect()
+}
+
+/// Add a batch's statistics to the running totals
+#[cfg(feature = "runtime")]
+fn merge_batch(totals: &mut ProtocolResult, batch: &ProtocolResult) {
+    totals.stats.merge(&batch.stats);
+    totals.offline_stats.merge(&batch.offline_stats);
+    totals.online_stats.merge(&batch.online_stats);
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "runtime"))]
 mod tests {
     use super::*;
 
@@ -251,7 +971,7 @@ mod tests {
                 FieldElement::new(i as u64, protocol.field().modulus()),
                 FieldElement::new((i * 2) as u64, protocol.field().modulus()),
             ];
-            user_data.push(UserData::new(i, data, i as u64));
+            user_data.push(UserData::new(i, data, [i as u8; 32]));
         }
 
         let result = protocol.execute(user_data).await;
@@ -260,6 +980,27 @@ mod tests {
         let result = result.unwrap();
         assert_eq!(result.result.len(), 10);
         assert!(result.privacy_guarantees.is_proven);
+        assert!(result.stats.offline_communication_bytes > result.stats.online_communication_bytes);
+        assert!(result.stats.online_communication_bytes > 0);
+        assert_eq!(
+            result.stats.total_communication_bytes,
+            result.stats.offline_communication_bytes + result.stats.online_communication_bytes
+        );
+        assert_eq!(result.offline_stats.total_communication_bytes, result.stats.offline_communication_bytes);
+        assert_eq!(result.online_stats.communication_bytes, result.stats.online_communication_bytes);
+        assert_eq!(result.online_stats.field_operations, result.stats.field_operations);
+
+        // Besides P₁'s blinded rows, the servers send each other nothing online
+        let by_type = &result.stats.communication_by_type;
+        assert_eq!(by_type.total(), result.stats.total_communication_bytes);
+        assert_eq!(by_type.get("correlation"), result.stats.offline_communication_bytes);
+        assert_eq!(by_type.get("blinded rows"), result.stats.online_server_bytes);
+        assert!(result.stats.online_server_bytes > 0);
+        assert_eq!(
+            result.stats.online_communication_bytes,
+            by_type.get("submissions") + by_type.get("blinded rows") + by_type.get("result share")
+        );
+        assert_eq!(result.online_stats.communication.total(), result.online_stats.communication_bytes);
     }
 
     #[test]
@@ -286,4 +1027,566 @@ mod tests {
         let reconstructed = shamir.reconstruct_secret(&shares[0..2]).unwrap();
         assert_eq!(reconstructed.value(), 5);
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_incremental_submission() {
+        let config = ToyConfig {
+            num_users: 6,
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let modulus = protocol.field().modulus();
+
+        let users: Vec<UserData> = (0..6)
+            .map(|i| UserData::new(i, vec![FieldElement::new(i as u64, modulus); 2], [3 * i as u8; 32]))
+            .collect();
+        let registrations = users.iter().map(|user| UserRegistration::new(user.user_id, user.seed)).collect();
+        protocol.start_collection(registrations).unwrap();
+
+        let mut users = users.into_iter();
+        protocol.submit(users.next().unwrap()).unwrap();
+        protocol.submit_chunk(users.by_ref().take(3)).unwrap();
+        assert_eq!(protocol.submitted(), 4);
+
+        // Unknown, duplicate and missing submissions are rejected
+        let stranger = UserData::new(42, vec![FieldElement::new(0, modulus); 2], [0; 32]);
+        assert!(matches!(protocol.submit(stranger), Err(ProtocolError::UnregisteredUser { user_id: 42 })));
+        let again = UserData::new(0, vec![FieldElement::new(0, modulus); 2], [0; 32]);
+        assert!(matches!(protocol.submit(again), Err(ProtocolError::DuplicateSubmission { user_id: 0 })));
+
+        protocol.submit_chunk(users).unwrap();
+        let result = protocol.finalize().await.unwrap();
+        assert_eq!(result.result.len(), 6);
+        assert!(protocol.finalize().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_finalize_requires_every_submission() {
+        let config = ToyConfig {
+            num_users: 3,
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+
+        let registrations = (0..3).map(|i| UserRegistration::new(i, [i as u8; 32])).collect();
+        protocol.start_collection(registrations).unwrap();
+        protocol
+            .submit(UserData::new(1, vec![protocol.field().element(5); 2], [1; 32]))
+            .unwrap();
+
+        assert!(matches!(protocol.finalize().await, Err(ProtocolError::MissingSubmissions { missing: 2 })));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_execution() {
+        let config = ToyConfig {
+            num_users: 8,
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let modulus = protocol.field().modulus();
+        let users = || (0..8).map(move |i| UserData::new(i, vec![FieldElement::new(i as u64, modulus); 2], [i as u8; 32]));
+
+        // Cancelling from the sink stops the run before the next batch
+        let token = CancellationToken::new();
+        protocol.set_cancellation(token.clone());
+        let mut batches = 0;
+        let cancelled = protocol
+            .execute_batched(users(), 3, |_| {
+                batches += 1;
+                token.cancel();
+                Ok(())
+            })
+            .await;
+        assert!(matches!(cancelled, Err(ProtocolError::Cancelled)));
+        assert_eq!(batches, 1);
+
+        // A window closed after cancellation discards the servers' correlations
+        protocol.set_cancellation(CancellationToken::new());
+        protocol.start_collection(registrations(&users().collect::<Vec<_>>())).unwrap();
+        protocol.submit_chunk(users()).unwrap();
+        protocol.cancellation().cancel();
+        assert!(matches!(protocol.finalize().await, Err(ProtocolError::Cancelled)));
+        assert!(protocol.get_server(1).unwrap().get_permutation().is_empty());
+        assert!(protocol.finalize().await.is_err());
+
+        protocol.set_cancellation(CancellationToken::new());
+        assert_eq!(protocol.execute(users().collect()).await.unwrap().result.len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_batched_execution() {
+        let config = ToyConfig {
+            num_users: 10,
+            batch_size: Some(4),
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let modulus = protocol.field().modulus();
+        let users = || (0..10).map(move |i| UserData::new(i, vec![FieldElement::new(i as u64, modulus); 2], [i as u8; 32]));
+
+        // Batches of 4, 4 and 2 are streamed to the sink
+        let mut batch_sizes = Vec::new();
+        let streamed = protocol
+            .execute_batched(users(), 4, |batch| {
+                batch_sizes.push(batch.len());
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(batch_sizes, vec![4, 4, 2]);
+        assert!(streamed.result.is_empty());
+        assert!(streamed.stats.offline_communication_bytes > 0);
+
+        // `execute` merges the batches when a batch size is configured
+        let result = protocol.execute(users().collect()).await.unwrap();
+        assert_eq!(result.result.len(), 10);
+        assert!(protocol.execute_batched(users(), 0, |_| Ok(())).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_pipelined_execution_matches_batched() {
+        let config = ToyConfig {
+            num_users: 10,
+            verify: true,
+            seed: Some(7),
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config.clone()).unwrap();
+        let modulus = protocol.field().modulus();
+        let seeds: Vec<MaskSeed> = (0..10).map(|i| config.user_seed(i)).collect();
+        let users = || seeds.iter().enumerate().map(move |(i, &seed)| UserData::new(i, vec![FieldElement::new(i as u64, modulus); 2], seed));
+
+        let mut pipelined = Vec::new();
+        let result = protocol
+            .execute_pipelined(users(), 4, |batch| {
+                pipelined.push(batch);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(pipelined.iter().map(Vec::len).collect::<Vec<_>>(), vec![4, 4, 2]);
+        assert!(result.result.is_empty());
+        assert!(result.stats.offline_communication_bytes > 0);
+
+        // Each batch deals from the same branch of the seed tree either way
+        let mut batched = Vec::new();
+        protocol
+            .execute_batched(users(), 4, |batch| {
+                batched.push(batch);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(pipelined, batched);
+        assert!(protocol.execute_pipelined(users(), 0, |_| Ok(())).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_persisted_offline_phase() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ToyConfig {
+            num_users: 5,
+            ..Default::default()
+        };
+        let registrations: Vec<UserRegistration> = (0..5).map(|i| UserRegistration::new(i, [11 * i as u8; 32])).collect();
+
+        let mut dealer = ToyProtocol::new(config.clone()).unwrap();
+        dealer.precompute_offline(registrations.clone(), dir.path()).await.unwrap();
+        assert!(offline_phase::correlation_path(dir.path(), 1).exists());
+        assert!(offline_phase::correlation_path(dir.path(), 2).exists());
+
+        // A fresh protocol instance collects against the saved material
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        protocol.load_offline(dir.path()).unwrap();
+        for registration in &registrations {
+            let data = vec![protocol.field().element(registration.user_id as u64); 2];
+            protocol.submit(UserData::new(registration.user_id, data, registration.seed)).unwrap();
+        }
+        let result = protocol.finalize().await.unwrap();
+        assert_eq!(result.result.len(), 5);
+        assert_eq!(result.stats.offline_time_ms, 0);
+
+        // Material dealt for a different configuration is rejected
+        let mut mismatched = ToyProtocol::new(ToyConfig { num_users: 6, ..Default::default() }).unwrap();
+        assert!(mismatched.load_offline(dir.path()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decoded_result_is_noised_data() {
+        let config = ToyConfig {
+            num_users: 20,
+            noise_scale: 0.5,
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let codec = protocol.codec().unwrap();
+
+        let user_data: Vec<UserData> = (0..20)
+            .map(|i| UserData::from_values(i, &[i as f64 - 10.0, 0.25], [i as u8; 32], &codec).unwrap())
+            .collect();
+        let result = protocol.execute(user_data).await.unwrap();
+
+        // Each decoded row is a shuffled input row plus Laplace noise, which may be negative
+        let decoded = result.decode(&codec);
+        let total: f64 = decoded.iter().map(|row| row[0]).sum();
+        assert!((total - (-10.0)).abs() < 20.0, "total {}", total);
+        assert!(decoded.iter().all(|row| (row[1] - 0.25).abs() < 20.0));
+    }
+
+    #[tokio::test]
+    async fn test_discrete_gaussian_guarantees() {
+        let config = ToyConfig {
+            num_users: 8,
+            noise: NoiseKind::DiscreteGaussian { sigma: 4.0 },
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let codec = protocol.codec().unwrap();
+
+        let user_data: Vec<UserData> = (0..8)
+            .map(|i| UserData::from_values(i, &[i as f64, 1.0], [i as u8; 32], &codec).unwrap())
+            .collect();
+        let result = protocol.execute(user_data).await.unwrap();
+
+        assert_eq!(result.result.len(), 8);
+        assert_eq!(result.privacy_guarantees.delta, 1e-5);
+        assert_eq!(result.privacy_guarantees.epsilon, noise::gaussian_epsilon(1.0, 4.0, 1e-5));
+    }
+
+    #[tokio::test]
+    async fn test_budget_split_between_features() {
+        let users = 2_000;
+        let config = ToyConfig {
+            num_users: users,
+            budget: BudgetSplit::Weighted { weights: vec![3.0, 1.0] },
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let codec = protocol.codec().unwrap();
+
+        let user_data: Vec<UserData> = (0..users)
+            .map(|i| UserData::from_values(i, &[0.0, 0.0], [i as u8; 32], &codec).unwrap())
+            .collect();
+        let result = protocol.execute(user_data).await.unwrap();
+        assert_eq!(result.privacy_guarantees.epsilon, 1.0);
+        assert_eq!(result.privacy_guarantees.feature_epsilons, vec![0.75, 0.25]);
+
+        // Each feature's Laplace noise has scale 1 / ε_j, and E|X| = b
+        let decoded = result.decode(&codec);
+        for (feature, epsilon) in [0.75, 0.25].into_iter().enumerate() {
+            let mean_magnitude = decoded.iter().map(|row| row[feature].abs()).sum::<f64>() / users as f64;
+            assert!((mean_magnitude * epsilon - 1.0).abs() < 0.1, "feature {} E|X| {}", feature, mean_magnitude);
+        }
+
+        // A Gaussian split gives each feature a share of ρ, so σ_j = σ·√2
+        let gaussian = ToyConfig {
+            num_users: 2,
+            noise: NoiseKind::DiscreteGaussian { sigma: 4.0 },
+            budget: BudgetSplit::Uniform,
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(gaussian).unwrap();
+        let user_data: Vec<UserData> = (0..2)
+            .map(|i| UserData::from_values(i, &[1.0, 2.0], [i as u8; 32], &codec).unwrap())
+            .collect();
+        let guarantees = protocol.execute(user_data).await.unwrap().privacy_guarantees;
+        let half = noise::gaussian_epsilon(1.0, 4.0 * 2f64.sqrt(), 1e-5);
+        assert_eq!(guarantees.feature_epsilons.len(), 2);
+        assert!(guarantees.feature_epsilons.iter().all(|epsilon| (epsilon - half).abs() < 1e-12));
+        assert_eq!(guarantees.epsilon, noise::gaussian_epsilon(1.0, 4.0, 1e-5));
+
+        let mismatched = ToyConfig {
+            budget: BudgetSplit::Weighted { weights: vec![1.0] },
+            ..Default::default()
+        };
+        assert!(ToyProtocol::new(mismatched).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_malicious_mode_matches_semi_honest_output() {
+        let config = ToyConfig {
+            num_users: 6,
+            noise_scale: 0.0,
+            security: SecurityMode::Malicious,
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let codec = protocol.codec().unwrap();
+
+        let user_data: Vec<UserData> = (0..6)
+            .map(|i| UserData::from_values(i, &[i as f64, 2.0 * i as f64], [i as u8; 32], &codec).unwrap())
+            .collect();
+        let result = protocol.execute(user_data).await.unwrap();
+
+        // Without noise the output is a permutation of the inputs
+        let mut decoded = result.decode(&codec);
+        decoded.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+        for (i, row) in decoded.iter().enumerate() {
+            assert_eq!(row, &vec![i as f64, 2.0 * i as f64]);
+        }
+        assert!(result.stats.online_communication_bytes > 0);
+    }
+
+    #[tokio::test]
+    async fn test_output_matches_plaintext_simulation() {
+        for (security, batch_size) in [
+            (SecurityMode::SemiHonest, None),
+            (SecurityMode::Malicious, None),
+            (SecurityMode::SemiHonest, Some(3)),
+        ] {
+            let config = ToyConfig {
+                num_users: 7,
+                security,
+                batch_size,
+                verify: true,
+                ..Default::default()
+            };
+            let mut protocol = ToyProtocol::new(config).unwrap();
+            let user_data: Vec<UserData> = (0..7)
+                .map(|i| UserData::new(i, vec![protocol.field().element(i as u64), protocol.field().element(3)], [i as u8; 32]))
+                .collect();
+
+            let result = protocol.execute(user_data).await.unwrap();
+            assert_eq!(result.result.len(), 7);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_histogram_mode_outputs_one_noisy_histogram() {
+        let buckets = [0, 2, 2, 1, 2, 0, 2];
+        for (security, batch_size) in [
+            (SecurityMode::SemiHonest, None),
+            (SecurityMode::Malicious, None),
+            (SecurityMode::SemiHonest, Some(3)),
+        ] {
+            let config = ToyConfig {
+                num_users: buckets.len(),
+                num_features: 3,
+                noise_scale: 0.0,
+                security,
+                batch_size,
+                verify: true,
+                output: OutputMode::Histogram,
+                ..Default::default()
+            };
+            let mut protocol = ToyProtocol::new(config).unwrap();
+            let codec = protocol.codec().unwrap();
+            let user_data: Vec<UserData> = buckets
+                .iter()
+                .enumerate()
+                .map(|(i, &bucket)| UserData::one_hot(i, bucket, 3, [i as u8; 32], &codec).unwrap())
+                .collect();
+
+            // Without noise the single output row is the exact histogram
+            let result = protocol.execute(user_data).await.unwrap();
+            assert_eq!(result.decode(&codec), vec![vec![2.0, 1.0, 4.0]]);
+        }
+
+        let mut protocol = ToyProtocol::new(ToyConfig {
+            num_users: 1,
+            num_features: 3,
+            output: OutputMode::Histogram,
+            ..Default::default()
+        })
+        .unwrap();
+        let codec = protocol.codec().unwrap();
+        protocol.start_collection(vec![UserRegistration::new(0, [1; 32])]).unwrap();
+        let error = protocol.submit(UserData::from_values(0, &[1.0, 1.0, 0.0], [1; 32], &codec).unwrap()).unwrap_err();
+        assert!(matches!(error, ProtocolError::NotOneHot { user_id: 0 }));
+    }
+
+    #[tokio::test]
+    async fn test_seeded_runs_are_reproducible() {
+        let run = |seed, security, batch_size| async move {
+            let config = ToyConfig {
+                num_users: 6,
+                security,
+                batch_size,
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let mut protocol = ToyProtocol::new(config.clone()).unwrap();
+            let codec = protocol.codec().unwrap();
+            let user_data: Vec<UserData> = (0..6)
+                .map(|i| UserData::from_values(i, &[i as f64, 1.0], config.user_seed(i), &codec).unwrap())
+                .collect();
+            protocol.execute(user_data).await.unwrap().result
+        };
+
+        for (security, batch_size) in [(SecurityMode::SemiHonest, None), (SecurityMode::Malicious, Some(4))] {
+            assert_eq!(run(42, security, batch_size).await, run(42, security, batch_size).await);
+            assert_ne!(run(42, security, batch_size).await, run(43, security, batch_size).await);
+        }
+
+        let config = ToyConfig { seed: Some(42), ..Default::default() };
+        assert_eq!(config.user_seed(3), config.user_seed(3));
+        assert_ne!(config.user_seed(3), config.user_seed(4));
+    }
+
+    #[tokio::test]
+    async fn test_server_failure_in_each_online_step() {
+        for step in OnlineStep::ALL {
+            // Aborting reports the failed step and the online statistics gathered so far
+            let mut protocol = ToyProtocol::new(ToyConfig { num_users: 6, ..Default::default() }).unwrap();
+            let user_data: Vec<UserData> = (0..6)
+                .map(|i| UserData::new(i, vec![protocol.field().element(i as u64), protocol.field().element(1)], [i as u8; 32]))
+                .collect();
+            protocol.inject_failure(2, step).unwrap();
+            match protocol.execute(user_data.clone()).await {
+                Err(ProtocolError::ServerFailed { server_id: 2, step: failed, partial }) => {
+                    assert_eq!(failed, step);
+                    assert_eq!(partial.communication_bytes > 0, step != OnlineStep::Submission);
+                }
+                other => panic!("expected a failure during {}, got {:?}", step, other.map(|r| r.result)),
+            }
+            assert!(protocol.get_server(2).unwrap().is_failed());
+
+            // Re-dealing recovers and still produces the expected output
+            let config = ToyConfig {
+                num_users: 6,
+                verify: true,
+                recovery: RecoveryPolicy::Redeal { max_attempts: 1 },
+                ..Default::default()
+            };
+            let mut protocol = ToyProtocol::new(config).unwrap();
+            protocol.inject_failure(1, step).unwrap();
+            let result = protocol.execute(user_data).await.unwrap();
+            assert_eq!(result.result.len(), 6);
+            assert!(!protocol.get_server(1).unwrap().is_failed());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress_observer_sees_both_phases() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Recorder {
+            events: Mutex<Vec<(Phase, Option<f64>)>>,
+        }
+
+        impl ProgressObserver for Recorder {
+            fn phase_started(&self, phase: Phase) {
+                self.events.lock().unwrap().push((phase, None));
+            }
+
+            fn progress(&self, phase: Phase, percent: f64, _step: &str) {
+                self.events.lock().unwrap().push((phase, Some(percent)));
+            }
+        }
+
+        let config = ToyConfig { num_users: 4, ..Default::default() };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let recorder = std::sync::Arc::new(Recorder::default());
+        protocol.set_observer(recorder.clone());
+
+        let user_data: Vec<UserData> = (0..4)
+            .map(|i| UserData::new(i, vec![protocol.field().element(i as u64), protocol.field().one()], [i as u8; 32]))
+            .collect();
+        protocol.execute(user_data).await.unwrap();
+
+        let events = recorder.events.lock().unwrap();
+        for phase in [Phase::Offline, Phase::Online] {
+            let percents: Vec<f64> = events.iter().filter(|(p, _)| *p == phase).filter_map(|(_, percent)| *percent).collect();
+            assert!(events.contains(&(phase, None)));
+            assert!(percents.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert_eq!(percents.last(), Some(&100.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_steps_are_traced_with_timing() {
+        use std::sync::{Arc, Mutex};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata};
+
+        /// Records each span's name and whether its timing was filled in
+        #[derive(Clone, Default)]
+        struct SpanRecorder {
+            spans: Arc<Mutex<Vec<(&'static str, bool)>>>,
+        }
+
+        impl tracing::Subscriber for SpanRecorder {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut spans = self.spans.lock().unwrap();
+                spans.push((span.metadata().name(), false));
+                Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, span: &Id, values: &Record<'_>) {
+                if !values.is_empty() {
+                    self.spans.lock().unwrap()[span.into_u64() as usize - 1].1 = true;
+                }
+            }
+
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, _event: &Event<'_>) {}
+            fn enter(&self, _span: &Id) {}
+            fn exit(&self, _span: &Id) {}
+        }
+
+        let recorder = SpanRecorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        let config = ToyConfig { num_users: 4, ..Default::default() };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let user_data: Vec<UserData> = (0..4)
+            .map(|i| UserData::new(i, vec![protocol.field().element(i as u64), protocol.field().one()], [i as u8; 32]))
+            .collect();
+        protocol.execute(user_data).await.unwrap();
+
+        let spans = recorder.spans.lock().unwrap();
+        for step in ["offline_phase", "submission", "shuffle", "randomization", "reconstruction", "online_phase"] {
+            assert!(spans.contains(&(step, true)), "missing timed span {}", step);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submit_rejects_records_outside_schema() {
+        let config = ToyConfig {
+            num_users: 2,
+            schema: Some(DataSchema::uniform(2, 0.0, 10.0)),
+            ..Default::default()
+        };
+        let mut protocol = ToyProtocol::new(config).unwrap();
+        let codec = protocol.codec().unwrap();
+
+        protocol
+            .start_collection(vec![UserRegistration::new(0, [1; 32]), UserRegistration::new(1, [2; 32])])
+            .unwrap();
+        protocol.submit(UserData::from_values(0, &[1.0, 2.0], [1; 32], &codec).unwrap()).unwrap();
+
+        let error = protocol.submit(UserData::from_values(1, &[1.0, 20.0], [2; 32], &codec).unwrap()).unwrap_err();
+        assert!(matches!(error, ProtocolError::ValueOutOfRange { user_id: 1, feature: 1, .. }));
+        assert_eq!(protocol.submitted(), 1);
+
+        let mismatched = ToyConfig {
+            schema: Some(DataSchema::uniform(3, 0.0, 10.0)),
+            ..Default::default()
+        };
+        assert!(ToyProtocol::new(mismatched).is_err());
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_config_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("toy.toml");
+
+        std::fs::write(&path, "num_features = 3\nepsilon = 0.5\n").unwrap();
+        let config = ToyConfig::from_file(&path).unwrap();
+        assert_eq!((config.num_features, config.epsilon), (3, 0.5));
+
+        std::fs::write(&path, "num_features = 3\nepsilon = \"high\"\n").unwrap();
+        let error = ToyConfig::from_file(&path).unwrap_err().to_string();
+        assert!(error.contains("toy.toml:2:11"), "{}", error);
+
+        std::fs::write(&path, "epsilon = -1.0\n").unwrap();
+        assert!(ToyConfig::from_file(&path).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::finite_field::FieldElement;
+use crate::secret_sharing::SecretShare;
+use crate::ProtocolError;
+use serde::{Deserialize, Serialize};
+
+/// Adversary the computational servers are protected against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SecurityMode {
+    /// Servers follow the protocol; shares are not authenticated
+    #[default]
+    SemiHonest,
+    /// A computational server may tamper with its shares; every shared value
+    /// carries a MAC that is checked before the output is reconstructed
+    Malicious,
+}
+
+/// MAC material dealt to one computational server
+///
+/// P₀ picks a global key `α` and shares both `α` and `α·(κ + r) − π₂(m)`
+/// for every shared output value `κ + r`, so neither server learns `α` on its
+/// own. P₁ sends `α₁·u + m` along with its blinded rows `u`, and once P₂ has
+/// permuted them, the tags cover the shuffled rows `π₂(u)` as well.
+///
+/// Since P₁ tags `u` itself, P₀ also deals a shuffle check that binds `u` to
+/// the submissions `d`: P₂ holds random keys `k` and masks `q`, and P₁ holds
+/// `−(k + π₁(q))`, so the servers' checks sum to `⟨k, u − π₁(d) − c⟩` per
+/// feature. That is zero for honest rows, and P₁ cannot shift it to match
+/// rows it altered without knowing `k`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacShares {
+    /// The server's share of the MAC key `α`
+    pub key_share: SecretShare,
+    /// The server's share of `α·(κ + r) − π₂(m)`, indexed `[row][feature]`
+    pub tag_shares: Vec<Vec<SecretShare>>,
+    /// Masks `m` hiding `α₁·u` in the tags sent with the blinded rows (first server only)
+    pub tag_masks: Vec<Vec<FieldElement>>,
+    /// Shuffle check keys for the rows the server permutes, `−(k + π₁(q))` or `k`
+    pub check_keys: Vec<Vec<FieldElement>>,
+    /// Shuffle check masks `q` for the submissions (second server only)
+    pub check_masks: Vec<Vec<FieldElement>>,
+    /// The server's share of `⟨k, c⟩`, one per feature
+    pub check_shares: Vec<SecretShare>,
+}
+
+/// A computational server's authenticated contribution to the output
+///
+/// All fields are additive shares, so the curator recovers each value by
+/// summing the two servers' contributions.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthenticatedShare {
+    /// Additive share of `κ + r`
+    pub share: Vec<Vec<FieldElement>>,
+    /// Additive share of `α·w` for the output `w = κ + r + π₂(π₁(d) + c)`
+    pub tag: Vec<Vec<FieldElement>>,
+    /// Additive share of the MAC key `α`, opened only after the shares are sent
+    pub key_share: Option<FieldElement>,
+    /// Shuffled rows `π₂(π₁(d) + c)` added in the clear (second server only)
+    pub public: Vec<Vec<FieldElement>>,
+    /// Additive share of the shuffle check, which sums to zero for every feature
+    pub shuffle_check: Vec<FieldElement>,
+}
+
+/// Check that every opened value `w` satisfies `α·w = tag`
+pub fn check_macs(key: &FieldElement, values: &[Vec<FieldElement>], tags: &[Vec<FieldElement>]) -> Result<(), ProtocolError> {
+    if values.len() != tags.len() {
+        return Err(ProtocolError::DimensionMismatch);
+    }
+
+    for (row, (value_row, tag_row)) in values.iter().zip(tags.iter()).enumerate() {
+        if value_row.len() != tag_row.len() {
+            return Err(ProtocolError::DimensionMismatch);
+        }
+
+        for (feature, (value, tag)) in value_row.iter().zip(tag_row.iter()).enumerate() {
+            let expected = key.mul(value).map_err(|_| ProtocolError::FieldOperationFailed)?;
+            if expected != *tag {
+                return Err(ProtocolError::MacCheckFailed { row, feature });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the servers' shuffle checks sum to zero for every feature
+///
+/// The check covers all of P₁'s blinded rows at once, so a failure is
+/// reported as `row`, one past the last output row.
+pub fn check_shuffle(row: usize, check: &[FieldElement]) -> Result<(), ProtocolError> {
+    match check.iter().position(|value| !value.is_zero()) {
+        Some(feature) => Err(ProtocolError::MacCheckFailed { row, feature }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finite_field::FiniteField;
+
+    #[test]
+    fn test_check_macs_detects_tampering() {
+        let field = FiniteField::new(0xFFFFFFFFFFFFFFC5).unwrap();
+        let key = field.element(123_456_789);
+        let values = vec![vec![field.element(5), field.element(7)], vec![field.element(11), field.element(13)]];
+        let mut tags: Vec<Vec<FieldElement>> = values
+            .iter()
+            .map(|row| row.iter().map(|value| key.mul(value).unwrap()).collect())
+            .collect();
+
+        assert!(check_macs(&key, &values, &tags).is_ok());
+
+        tags[1][0] = tags[1][0].add(&field.one()).unwrap();
+        assert!(matches!(
+            check_macs(&key, &values, &tags),
+            Err(ProtocolError::MacCheckFailed { row: 1, feature: 0 })
+        ));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::finite_field::{FieldElement, FiniteField};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+/// Stream of the seed's keystream the mask key is taken from
+const MASK_DOMAIN: u64 = u64::from_le_bytes(*b"toy-mask");
+
+/// Per-user seed, used as a 256-bit ChaCha20 key
+pub type MaskSeed = [u8; 32];
+
+/// Keyed PRF deriving each user's data mask `a_i`
+///
+/// The user's seed is a ChaCha20 key. The mask key is its keystream on the
+/// mask domain's stream, and the mask is the keystream of that key on the
+/// stream of the user's ID, reduced to field elements without bias. Users and
+/// the auxiliary server both derive masks through this type, so the mask a
+/// user subtracts is exactly the one the dealer accounted for.
+#[derive(Debug, Clone)]
+pub struct MaskDerivation {
+    /// Field the masks live in
+    field: FiniteField,
+    /// Number of features, one mask value each
+    num_features: usize,
+}
+
+impl MaskDerivation {
+    /// Create a mask derivation for `num_features`-feature records
+    pub fn new(field: FiniteField, num_features: usize) -> Self {
+        Self { field, num_features }
+    }
+
+    /// Draw a fresh per-user seed for the dealer to hand out
+    pub fn generate_seed() -> MaskSeed {
+        let mut seed = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut seed);
+        seed
+    }
+
+    /// Derive the mask of `user_id` from its `seed`
+    pub fn derive(&self, user_id: usize, seed: &MaskSeed) -> Vec<FieldElement> {
+        let mut rng = ChaCha20Rng::from_seed(mask_key(seed));
+        rng.set_stream(user_id as u64);
+        let modulus = self.field.modulus();
+        // Largest multiple of the modulus that fits, so accepted draws reduce uniformly
+        let limit = u64::MAX - u64::MAX % modulus;
+
+        (0..self.num_features)
+            .map(|_| loop {
+                let draw = rng.next_u64();
+                if draw < limit {
+                    break self.field.element(draw % modulus);
+                }
+            })
+            .collect()
+    }
+
+    /// Number of mask values per user
+    pub fn num_features(&self) -> usize {
+        self.num_features
+    }
+}
+
+/// Derive the mask key from a seed, separated from any other use of the seed
+fn mask_key(seed: &MaskSeed) -> [u8; 32] {
+    let mut stream = ChaCha20Rng::from_seed(*seed);
+    stream.set_stream(MASK_DOMAIN);
+    let mut key = [0u8; 32];
+    stream.fill_bytes(&mut key);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_are_deterministic_and_keyed() {
+        let field = FiniteField::new(0xFFFFFFFFFFFFFFC5).unwrap();
+        let derivation = MaskDerivation::new(field, 4);
+
+        let seed = MaskDerivation::generate_seed();
+        let mask = derivation.derive(3, &seed);
+        assert_eq!(mask.len(), 4);
+        assert!(mask.iter().all(|value| value.value() < derivation.field.modulus()));
+        assert_eq!(mask, derivation.derive(3, &seed));
+
+        // The seed and user ID are both part of the key
+        assert_ne!(mask, derivation.derive(3, &MaskDerivation::generate_seed()));
+        assert_ne!(mask, derivation.derive(4, &seed));
+        // The seed is the key itself, not the keystream under it
+        assert_ne!(mask_key(&seed), seed);
+    }
+}
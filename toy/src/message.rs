@@ -0,0 +1,80 @@
+use crate::finite_field::FieldElement;
+use crate::mac::AuthenticatedShare;
+use crate::offline_phase::ServerCorrelation;
+use crate::ProtocolError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Messages exchanged by networked toy processes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ToyMessage {
+    /// Correlated randomness dealt by P₀ (offline)
+    Correlation(ServerCorrelation),
+    /// Masked user submissions `d` sent by the users (online)
+    Submissions(Vec<Vec<FieldElement>>),
+    /// Blinded rows `π₁(d) + c` sent from P₁ to P₂ (online)
+    BlindedRows(Vec<Vec<FieldElement>>),
+    /// Tags `α₁·u + m` on the blinded rows `u`, sent from P₁ to P₂ (online, malicious mode)
+    BlindedTags(Vec<Vec<FieldElement>>),
+    /// A computational server's additive share of the output (online)
+    ResultShare(Vec<Vec<FieldElement>>),
+    /// A computational server's authenticated contribution to the output (online, malicious mode)
+    AuthenticatedResultShare(AuthenticatedShare),
+}
+
+impl ToyMessage {
+    /// Get message name for logging
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToyMessage::Correlation(_) => "correlation",
+            ToyMessage::Submissions(_) => "submissions",
+            ToyMessage::BlindedRows(_) => "blinded rows",
+            ToyMessage::BlindedTags(_) => "blinded tags",
+            ToyMessage::ResultShare(_) => "result share",
+            ToyMessage::AuthenticatedResultShare(_) => "authenticated result share",
+        }
+    }
+}
+
+/// Number of bytes `message` occupies on the wire, including its length prefix
+pub fn message_size(message: &ToyMessage) -> Result<usize, ProtocolError> {
+    serde_json::to_vec(message)
+        .map(|bytes| 4 + bytes.len())
+        .map_err(|e| ProtocolError::network_error(format!("Failed to encode {}: {}", message.name(), e)))
+}
+
+/// Bytes sent, by the name of the message type
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MessageBytes(BTreeMap<String, usize>);
+
+impl MessageBytes {
+    /// Count `copies` of `message`, returning the bytes they took
+    pub fn record(&mut self, message: &ToyMessage, copies: usize) -> Result<usize, ProtocolError> {
+        let bytes = copies * message_size(message)?;
+        *self.0.entry(message.name().to_string()).or_default() += bytes;
+        Ok(bytes)
+    }
+
+    /// Bytes sent as messages named `name`
+    pub fn get(&self, name: &str) -> usize {
+        self.0.get(name).copied().unwrap_or(0)
+    }
+
+    /// Bytes sent as messages of any type
+    pub fn total(&self) -> usize {
+        self.0.values().sum()
+    }
+
+    /// Add the bytes of another run
+    pub fn merge(&mut self, other: &MessageBytes) {
+        for (name, bytes) in &other.0 {
+            *self.0.entry(name.clone()).or_default() += bytes;
+        }
+    }
+
+    /// Message types and their bytes, by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.0.iter().map(|(name, bytes)| (name.as_str(), *bytes))
+    }
+}
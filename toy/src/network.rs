@@ -0,0 +1,399 @@
+use crate::cancel::CancellationToken;
+use crate::finite_field::{FieldElement, FiniteField, FixedPoint};
+use crate::mac::SecurityMode;
+use crate::offline_phase::{OfflinePhase, UserRegistration};
+use crate::online_phase::{OnlinePhase, TaggedRows};
+use crate::schema::validate_user;
+use crate::secret_sharing::AdditiveSecretSharing;
+use crate::server::{Server, ServerRole};
+use crate::{ProtocolError, ToyConfig, UserData};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub use crate::message::{message_size, ToyMessage};
+
+/// Largest message accepted from a peer, in bytes
+pub const MAX_MESSAGE_BYTES: usize = 1 << 30;
+
+/// How long to keep retrying a connection to a peer that is not listening yet
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bytes a process sent and received over the network
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficStats {
+    /// Bytes sent
+    pub bytes_sent: usize,
+    /// Bytes received
+    pub bytes_received: usize,
+}
+
+impl TrafficStats {
+    /// Add another connection's traffic
+    pub fn merge(&mut self, other: TrafficStats) {
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+    }
+}
+
+/// Traffic of one computational server, split by peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerTraffic {
+    /// Traffic with the auxiliary server (offline)
+    pub auxiliary: TrafficStats,
+    /// Traffic with users and the curator (online)
+    pub clients: TrafficStats,
+    /// Traffic with the other computational server (online)
+    pub peer: TrafficStats,
+}
+
+/// Length-prefixed JSON message connection
+pub struct Connection {
+    /// Underlying stream
+    stream: TcpStream,
+    /// Traffic on this connection
+    traffic: TrafficStats,
+}
+
+impl Connection {
+    /// Wrap an established stream
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            traffic: TrafficStats::default(),
+        }
+    }
+
+    /// Connect to a peer, retrying until it starts listening
+    pub async fn connect(addr: SocketAddr) -> Result<Self, ProtocolError> {
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        loop {
+            match TcpStream::connect(addr).await {
+                Ok(stream) => return Ok(Self::new(stream)),
+                Err(e) if Instant::now() >= deadline => {
+                    return Err(ProtocolError::network_error(format!("Failed to connect to {}: {}", addr, e)));
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    }
+
+    /// Send a message
+    pub async fn send(&mut self, message: &ToyMessage) -> Result<(), ProtocolError> {
+        let bytes = serde_json::to_vec(message)
+            .map_err(|e| ProtocolError::network_error(format!("Failed to encode {}: {}", message.name(), e)))?;
+        let length = u32::try_from(bytes.len())
+            .ok()
+            .filter(|&length| length as usize <= MAX_MESSAGE_BYTES)
+            .ok_or_else(|| ProtocolError::network_error(format!("{} of {} bytes is too large", message.name(), bytes.len())))?;
+
+        self.stream.write_all(&length.to_le_bytes()).await.map_err(io_error)?;
+        self.stream.write_all(&bytes).await.map_err(io_error)?;
+        self.stream.flush().await.map_err(io_error)?;
+        self.traffic.bytes_sent += 4 + bytes.len();
+        tracing::debug!(kind = message.name(), bytes = 4 + bytes.len(), "sent message");
+
+        Ok(())
+    }
+
+    /// Receive a message
+    pub async fn recv(&mut self) -> Result<ToyMessage, ProtocolError> {
+        let mut length = [0u8; 4];
+        self.stream.read_exact(&mut length).await.map_err(io_error)?;
+        let length = u32::from_le_bytes(length) as usize;
+        if length > MAX_MESSAGE_BYTES {
+            return Err(ProtocolError::network_error(format!("Message of {} bytes exceeds limit", length)));
+        }
+
+        let mut bytes = vec![0u8; length];
+        self.stream.read_exact(&mut bytes).await.map_err(io_error)?;
+        self.traffic.bytes_received += 4 + length;
+
+        let message: ToyMessage = serde_json::from_slice(&bytes)
+            .map_err(|e| ProtocolError::network_error(format!("Failed to decode message: {}", e)))?;
+        tracing::debug!(kind = message.name(), bytes = 4 + length, "received message");
+        Ok(message)
+    }
+
+    /// Get traffic on this connection
+    pub fn traffic(&self) -> TrafficStats {
+        self.traffic
+    }
+}
+
+/// Addresses of the two computational servers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Topology {
+    /// Address P₁ listens on
+    pub first: SocketAddr,
+    /// Address P₂ listens on
+    pub second: SocketAddr,
+}
+
+impl Topology {
+    /// Get the listen address of computational server `server_id`
+    pub fn address(&self, server_id: usize) -> Result<SocketAddr, ProtocolError> {
+        match server_id {
+            1 => Ok(self.first),
+            2 => Ok(self.second),
+            _ => Err(ProtocolError::ServerNotFound),
+        }
+    }
+}
+
+/// Run P₀: deal the offline correlation to both computational servers and exit
+///
+/// Stops with `ProtocolError::Cancelled` when `cancel` is cancelled.
+#[tracing::instrument(skip_all, fields(users = registrations.len()))]
+pub async fn run_auxiliary_server(
+    config: ToyConfig,
+    topology: Topology,
+    registrations: &[UserRegistration],
+    cancel: &CancellationToken,
+) -> Result<TrafficStats, ProtocolError> {
+    let field = FiniteField::new(config.field_modulus)?;
+    let secret_sharing = AdditiveSecretSharing::new(config.field_modulus)?;
+    let offline_phase = OfflinePhase::new(config, field, secret_sharing)?;
+
+    let mut correlations = cancel.run(offline_phase.deal(registrations)).await?;
+    let mut traffic = TrafficStats::default();
+    for server_id in 1..=2 {
+        let correlation = correlations.remove(&server_id).ok_or(ProtocolError::ServerNotFound)?;
+        let mut connection = cancel.run(Connection::connect(topology.address(server_id)?)).await?;
+        cancel.run(connection.send(&ToyMessage::Correlation(correlation))).await?;
+        traffic.merge(connection.traffic());
+    }
+
+    Ok(traffic)
+}
+
+/// Run computational server `server_id` until it has sent its result share
+///
+/// P₁ needs its correlation and the submissions; P₂ needs its correlation,
+/// the submissions (to know where to reply) and P₁'s blinded rows. Peers may
+/// connect in any order. When `cancel` is cancelled the server stops waiting
+/// for them, closes its listener and fails with `ProtocolError::Cancelled`.
+#[tracing::instrument(skip(config, topology, cancel))]
+pub async fn run_computational_server(
+    server_id: usize,
+    config: ToyConfig,
+    topology: Topology,
+    cancel: &CancellationToken,
+) -> Result<ServerTraffic, ProtocolError> {
+    let listener = TcpListener::bind(topology.address(server_id)?)
+        .await
+        .map_err(io_error)?;
+
+    let field = FiniteField::new(config.field_modulus)?;
+    let mut online_phase = OnlinePhase::new(config.clone(), field)?;
+    let mut server = Server::new(server_id, ServerRole::Computational, config.clone());
+    server.initialize();
+
+    let mut traffic = ServerTraffic::default();
+    let mut correlation = None;
+    let mut submissions = None;
+    let mut blinded_rows = None;
+    let mut blinded_tags = None;
+
+    while correlation.is_none() || submissions.is_none() || (server_id == 2 && blinded_rows.is_none()) {
+        let (stream, _) = cancel.run(async { listener.accept().await.map_err(io_error) }).await?;
+        let mut connection = Connection::new(stream);
+        match cancel.run(connection.recv()).await? {
+            ToyMessage::Correlation(received) => {
+                correlation = Some(received);
+                traffic.auxiliary.merge(connection.traffic());
+            }
+            ToyMessage::Submissions(received) => submissions = Some((received, connection)),
+            ToyMessage::BlindedRows(received) if server_id == 2 => {
+                // In malicious mode P₁'s tags on the rows follow on the same connection
+                if config.security == SecurityMode::Malicious {
+                    match cancel.run(connection.recv()).await? {
+                        ToyMessage::BlindedTags(tags) => blinded_tags = Some(tags),
+                        other => {
+                            return Err(ProtocolError::network_error(format!(
+                                "Server {} received {} instead of blinded tags",
+                                server_id,
+                                other.name()
+                            )));
+                        }
+                    }
+                }
+                blinded_rows = Some(received);
+                traffic.peer.merge(connection.traffic());
+            }
+            other => {
+                return Err(ProtocolError::network_error(format!(
+                    "Server {} received unexpected {}",
+                    server_id,
+                    other.name()
+                )));
+            }
+        }
+    }
+
+    let (Some(correlation), Some((submissions, mut client))) = (correlation, submissions) else {
+        return Err(ProtocolError::internal_error("Server loop ended without its inputs"));
+    };
+    server.receive_correlation(correlation);
+    server.set_state(crate::server::ServerState::Participating);
+
+    let shuffled = if server_id == 1 {
+        // Hand the blinded rows to P₂ and keep only the correction and noise shares
+        let blinded = online_phase.shuffle_step(&server, &submissions).await?;
+        let mut peer = cancel.run(Connection::connect(topology.second)).await?;
+        if config.security == SecurityMode::Malicious {
+            let tags = online_phase.tag_blinded_rows(&server, &blinded)?;
+            cancel.run(peer.send(&ToyMessage::BlindedRows(blinded))).await?;
+            cancel.run(peer.send(&ToyMessage::BlindedTags(tags))).await?;
+        } else {
+            cancel.run(peer.send(&ToyMessage::BlindedRows(blinded))).await?;
+        }
+        traffic.peer.merge(peer.traffic());
+
+        None
+    } else {
+        let blinded = blinded_rows.as_deref().ok_or(ProtocolError::EmptyInput)?;
+        Some(online_phase.shuffle_step(&server, blinded).await?)
+    };
+
+    let result_share = match config.security {
+        SecurityMode::SemiHonest => ToyMessage::ResultShare(
+            online_phase.compute_local_randomization(&mut server, shuffled.as_deref()).await?,
+        ),
+        SecurityMode::Malicious => {
+            let public = match (&blinded_rows, &blinded_tags, &shuffled) {
+                (Some(blinded), Some(tags), Some(shuffled)) => Some(TaggedRows { blinded, tags, shuffled }),
+                _ => None,
+            };
+            ToyMessage::AuthenticatedResultShare(
+                online_phase.compute_authenticated_share(&mut server, &submissions, public).await?,
+            )
+        }
+    };
+
+    cancel.run(client.send(&result_share)).await?;
+    traffic.clients.merge(client.traffic());
+    server.set_state(crate::server::ServerState::Completed);
+
+    Ok(traffic)
+}
+
+/// Run the users and the curator: submit masked data to both servers and reconstruct the output
+///
+/// Stops waiting for the servers with `ProtocolError::Cancelled` when `cancel` is cancelled.
+#[tracing::instrument(skip_all, fields(users = user_data.len()))]
+pub async fn run_curator(
+    config: ToyConfig,
+    topology: Topology,
+    user_data: Vec<UserData>,
+    cancel: &CancellationToken,
+) -> Result<(Vec<Vec<FieldElement>>, TrafficStats), ProtocolError> {
+    let field = FiniteField::new(config.field_modulus)?;
+    let mut online_phase = OnlinePhase::new(config.clone(), field)?;
+
+    let codec = FixedPoint::new(config.field_modulus, config.fractional_bits)?;
+    for user in &user_data {
+        validate_user(&config, &codec, user)?;
+    }
+    let submissions = online_phase.mask_submissions(user_data)?;
+
+    let mut connections = Vec::with_capacity(2);
+    for server_id in 1..=2 {
+        let mut connection = cancel.run(Connection::connect(topology.address(server_id)?)).await?;
+        cancel.run(connection.send(&ToyMessage::Submissions(submissions.clone()))).await?;
+        connections.push(connection);
+    }
+
+    let mut shares = Vec::with_capacity(2);
+    let mut authenticated = Vec::with_capacity(2);
+    let mut traffic = TrafficStats::default();
+    for connection in &mut connections {
+        match (cancel.run(connection.recv()).await?, config.security) {
+            (ToyMessage::ResultShare(share), SecurityMode::SemiHonest) => shares.push(share),
+            (ToyMessage::AuthenticatedResultShare(share), SecurityMode::Malicious) => authenticated.push(share),
+            (other, _) => {
+                return Err(ProtocolError::network_error(format!("Curator received unexpected {}", other.name())));
+            }
+        }
+        traffic.merge(connection.traffic());
+    }
+
+    let result = match config.security {
+        SecurityMode::SemiHonest => online_phase.combine_server_results(&shares)?,
+        SecurityMode::Malicious => online_phase.open_authenticated_results(&authenticated)?,
+    };
+    Ok((result, traffic))
+}
+
+/// Convert an I/O error into a protocol error
+fn io_error(error: std::io::Error) -> ProtocolError {
+    ProtocolError::network_error(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn free_address() -> SocketAddr {
+        std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_networked_protocol() {
+        for security in [SecurityMode::SemiHonest, SecurityMode::Malicious] {
+            let config = ToyConfig { num_users: 8, security, ..Default::default() };
+            let topology = Topology {
+                first: free_address(),
+                second: free_address(),
+            };
+
+            let user_data: Vec<UserData> = (0..8)
+                .map(|i| UserData::new(i, vec![FieldElement::new(i as u64, config.field_modulus); 2], [i as u8; 32]))
+                .collect();
+            let registrations: Vec<UserRegistration> = user_data
+                .iter()
+                .map(|user| UserRegistration::new(user.user_id, user.seed))
+                .collect();
+
+            let cancel = CancellationToken::new();
+            let (first_cancel, second_cancel) = (cancel.clone(), cancel.clone());
+            let first_config = config.clone();
+            let first = tokio::spawn(async move { run_computational_server(1, first_config, topology, &first_cancel).await });
+            let second_config = config.clone();
+            let second = tokio::spawn(async move { run_computational_server(2, second_config, topology, &second_cancel).await });
+            run_auxiliary_server(config.clone(), topology, &registrations, &cancel).await.unwrap();
+            let (result, _) = run_curator(config.clone(), topology, user_data, &cancel).await.unwrap();
+
+            let first = first.await.unwrap().unwrap();
+            let second = second.await.unwrap().unwrap();
+            assert_eq!(result.len(), 8);
+
+            // The only online server-to-server traffic is P₁'s blinded rows, and their tags in malicious mode
+            assert_eq!(first.peer.bytes_sent, second.peer.bytes_received);
+            assert_eq!(first.peer.bytes_received, 0);
+            assert!(first.auxiliary.bytes_received > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_waiting_server() {
+        let config = ToyConfig { num_users: 2, ..Default::default() };
+        let topology = Topology {
+            first: free_address(),
+            second: free_address(),
+        };
+
+        // P₁ never hears from P₀ or the curator, so it waits until cancelled
+        let cancel = CancellationToken::new();
+        let server_cancel = cancel.clone();
+        let server = tokio::spawn(async move { run_computational_server(1, config, topology, &server_cancel).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        cancel.cancel();
+        assert!(matches!(server.await.unwrap(), Err(ProtocolError::Cancelled)));
+
+        // The listener was closed, so the address can be bound again
+        TcpListener::bind(topology.first).await.unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+use crate::ProtocolError;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Distribution the auxiliary server samples DP noise from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum NoiseKind {
+    /// Continuous Laplace with scale `noise_scale / epsilon`, rounded to the fixed-point grid
+    #[default]
+    Laplace,
+    /// Discrete Gaussian over the fixed-point grid with standard deviation `sigma`
+    DiscreteGaussian {
+        /// Standard deviation, in data units
+        sigma: f64,
+    },
+}
+
+/// How the privacy budget is divided between features
+///
+/// Each feature's noise is drawn independently with its own parameter. With
+/// Laplace noise feature `j` gets `ε_j = f_j·ε`; with a discrete Gaussian
+/// it gets `f_j` of the zCDP budget `ρ`, i.e. `σ_j = σ / √f_j`. Either way
+/// the per-feature budgets compose to the configured one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum BudgetSplit {
+    /// Every feature gets the whole budget, so the budget holds per feature
+    #[default]
+    PerFeature,
+    /// The budget is split evenly between the features
+    Uniform,
+    /// The budget is split in proportion to positive per-feature weights
+    Weighted {
+        /// Relative weight of each feature, in order
+        weights: Vec<f64>,
+    },
+}
+
+impl BudgetSplit {
+    /// Fraction `f_j` of the budget each of `num_features` features gets
+    pub fn fractions(&self, num_features: usize) -> Result<Vec<f64>, ProtocolError> {
+        match self {
+            BudgetSplit::PerFeature => Ok(vec![1.0; num_features]),
+            BudgetSplit::Uniform => Ok(vec![1.0 / num_features as f64; num_features]),
+            BudgetSplit::Weighted { weights } => {
+                if weights.len() != num_features {
+                    return Err(ProtocolError::invalid_configuration(format!(
+                        "Budget split has {} weights but the configuration has {} features",
+                        weights.len(),
+                        num_features
+                    )));
+                }
+                if let Some(feature) = weights.iter().position(|w| !(w.is_finite() && *w > 0.0)) {
+                    return Err(ProtocolError::invalid_configuration(format!(
+                        "Budget weight of feature {} must be positive",
+                        feature
+                    )));
+                }
+
+                let total: f64 = weights.iter().sum();
+                Ok(weights.iter().map(|weight| weight / total).collect())
+            }
+        }
+    }
+}
+
+/// Epsilon of a discrete Gaussian with the given sensitivity and sigma, at `delta`
+///
+/// The discrete Gaussian satisfies `ρ`-zCDP with `ρ = Δ² / (2σ²)`, which
+/// implies `(ρ + 2√(ρ ln(1/δ)), δ)`-DP.
+pub fn gaussian_epsilon(sensitivity: f64, sigma: f64, delta: f64) -> f64 {
+    let rho = sensitivity * sensitivity / (2.0 * sigma * sigma);
+    rho + 2.0 * (rho * (1.0 / delta).ln()).sqrt()
+}
+
+/// Sample `Bernoulli(exp(-gamma))` for `gamma >= 0` without evaluating `exp`
+pub fn bernoulli_exp_neg<R: Rng + ?Sized>(rng: &mut R, gamma: f64) -> bool {
+    // exp(-gamma) = exp(-1)^floor(gamma) · exp(-(gamma - floor(gamma)))
+    let mut remaining = gamma;
+    while remaining > 1.0 {
+        if !bernoulli_exp_neg_unit(rng, 1.0) {
+            return false;
+        }
+        remaining -= 1.0;
+    }
+
+    bernoulli_exp_neg_unit(rng, remaining)
+}
+
+/// Sample `Bernoulli(exp(-gamma))` for `gamma` in `[0, 1]`
+fn bernoulli_exp_neg_unit<R: Rng + ?Sized>(rng: &mut R, gamma: f64) -> bool {
+    // The first k with Bernoulli(gamma / k) = 0 is odd with probability exp(-gamma)
+    let mut k = 1u64;
+    while rng.gen_bool((gamma / k as f64).min(1.0)) {
+        k += 1;
+    }
+
+    k % 2 == 1
+}
+
+/// Sample the discrete Laplace distribution `P(x) ∝ exp(-|x| / t)` over the integers
+pub fn sample_discrete_laplace<R: Rng + ?Sized>(rng: &mut R, t: u64) -> i64 {
+    loop {
+        // Fractional part, uniform on {0, ..., t - 1} and accepted with probability exp(-u / t)
+        let u = rng.gen_range(0..t);
+        if !bernoulli_exp_neg(rng, u as f64 / t as f64) {
+            continue;
+        }
+
+        // Integer part, geometric with parameter 1 - exp(-1)
+        let mut v = 0u64;
+        while bernoulli_exp_neg(rng, 1.0) {
+            v += 1;
+        }
+
+        let magnitude = (u + t * v) as i64;
+        let negative = rng.gen_bool(0.5);
+        if negative && magnitude == 0 {
+            continue;
+        }
+
+        return if negative { -magnitude } else { magnitude };
+    }
+}
+
+/// Sample the discrete Gaussian `P(x) ∝ exp(-x² / (2σ²))` over the integers
+///
+/// Uses rejection sampling from the discrete Laplace (Canonne, Kamath and
+/// Steinke, 2020), so the output is integer-valued with no rounding step.
+pub fn sample_discrete_gaussian<R: Rng + ?Sized>(rng: &mut R, sigma: f64) -> i64 {
+    let t = sigma.floor() as u64 + 1;
+    let sigma_squared = sigma * sigma;
+
+    loop {
+        let candidate = sample_discrete_laplace(rng, t);
+        let offset = candidate.unsigned_abs() as f64 - sigma_squared / t as f64;
+        if bernoulli_exp_neg(rng, offset * offset / (2.0 * sigma_squared)) {
+            return candidate;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bernoulli_exp_neg() {
+        let mut rng = rand::thread_rng();
+        let samples = 50_000;
+        for gamma in [0.0, 0.5, 2.5] {
+            let hits = (0..samples).filter(|_| bernoulli_exp_neg(&mut rng, gamma)).count();
+            let rate = hits as f64 / samples as f64;
+            assert!((rate - (-gamma).exp()).abs() < 0.01, "gamma {} rate {}", gamma, rate);
+        }
+    }
+
+    #[test]
+    fn test_discrete_gaussian_moments() {
+        let mut rng = rand::thread_rng();
+        let sigma = 4.0;
+        let samples = 40_000;
+        let draws: Vec<i64> = (0..samples).map(|_| sample_discrete_gaussian(&mut rng, sigma)).collect();
+
+        let mean = draws.iter().sum::<i64>() as f64 / samples as f64;
+        let variance = draws.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / samples as f64;
+        assert!(mean.abs() < 0.1, "mean {}", mean);
+        assert!((variance / (sigma * sigma) - 1.0).abs() < 0.05, "variance {}", variance);
+
+        // Symmetric around zero and decaying away from it
+        let frequency = |value: i64| draws.iter().filter(|&&x| x == value).count() as f64 / samples as f64;
+        assert!((frequency(1) - frequency(-1)).abs() < 0.01);
+        assert!(frequency(0) > 2.0 * frequency(8));
+    }
+
+    #[test]
+    fn test_budget_fractions() {
+        assert_eq!(BudgetSplit::PerFeature.fractions(3).unwrap(), vec![1.0; 3]);
+        assert_eq!(BudgetSplit::Uniform.fractions(4).unwrap(), vec![0.25; 4]);
+
+        let weighted = BudgetSplit::Weighted { weights: vec![3.0, 1.0] };
+        assert_eq!(weighted.fractions(2).unwrap(), vec![0.75, 0.25]);
+        assert!(weighted.fractions(3).is_err());
+        assert!(BudgetSplit::Weighted { weights: vec![1.0, 0.0] }.fractions(2).is_err());
+    }
+
+    #[test]
+    fn test_gaussian_epsilon() {
+        let loose = gaussian_epsilon(1.0, 1.0, 1e-5);
+        let tight = gaussian_epsilon(1.0, 10.0, 1e-5);
+        assert!(tight < loose);
+        assert!((tight - (0.005 + 2.0 * (0.005 * 1e5f64.ln()).sqrt())).abs() < 1e-12);
+    }
+}
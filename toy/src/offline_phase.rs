@@ -1,9 +1,202 @@
-use crate::finite_field::{FieldElement, FiniteField, FieldError};
-use crate::secret_sharing::{SecretShare, ShamirSecretSharing, ShareDistributor};
-use crate::server::{Server, ServerRole};
+use crate::finite_field::{FieldElement, FiniteField, FixedPoint};
+use crate::mac::{MacShares, SecurityMode};
+use crate::mask::{MaskDerivation, MaskSeed};
+use crate::secret_sharing::{AdditiveSecretSharing, SecretShare};
+use crate::message::{MessageBytes, ToyMessage};
+use crate::noise::{self, NoiseKind};
+use crate::parallel;
+use crate::progress::{self, timed, timed_blocking, Phase, SharedObserver};
+use crate::seed::{self, SeedTree};
+use crate::server::Server;
+use crate::simulation::PlaintextSimulation;
 use crate::{ToyConfig, ProtocolError};
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::time::Instant;
+use rand::Rng;
+use rand_chacha::ChaCha20Rng;
 use serde::{Deserialize, Serialize};
+use tracing::field::Empty;
+use tracing::info_span;
+
+/// Name of the manifest file in an offline directory
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Registration of a user with the auxiliary server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserRegistration {
+    /// User ID (the user's "mailbox" position)
+    pub user_id: usize,
+    /// Seed the user derives its mask from
+    pub seed: MaskSeed,
+}
+
+impl UserRegistration {
+    /// Create a new user registration
+    pub fn new(user_id: usize, seed: MaskSeed) -> Self {
+        Self { user_id, seed }
+    }
+}
+
+/// Apply a permutation vector: entry `i` of the result is `rows[permutation[i]]`
+pub fn permute<T: Clone + Send + Sync>(permutation: &[usize], rows: &[T]) -> Result<Vec<T>, ProtocolError> {
+    if permutation.len() != rows.len() {
+        return Err(ProtocolError::DimensionMismatch);
+    }
+
+    parallel::try_map(permutation, |_, &source| rows.get(source).cloned().ok_or(ProtocolError::DimensionMismatch))
+}
+
+/// Independent randomness streams of a deal, one per seed tree branch
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    /// P₁'s permutation half
+    FirstPermutation,
+    /// P₂'s permutation half
+    SecondPermutation,
+    /// P₁'s blinding `c`
+    Blinding,
+    /// Shares of the mask correction
+    CorrectionShares,
+    /// DP noise
+    Noise,
+    /// Shares of the noise
+    NoiseShares,
+    /// MAC key and tag shares
+    Mac,
+}
+
+/// Shuffle correlation dealt by the auxiliary server
+///
+/// The shuffle is `π = π₂ ∘ π₁`: `P₁` only learns `π₁` and `P₂` only learns
+/// `π₂`, so neither knows where a user's row ends up.
+struct ShuffleCorrelation {
+    /// Permutation applied by `P₁`
+    first_permutation: Vec<usize>,
+    /// Permutation applied by `P₂`
+    second_permutation: Vec<usize>,
+    /// Blinding `c` that `P₁` adds before handing its shuffled rows to `P₂`
+    blinding: Vec<Vec<FieldElement>>,
+    /// Shares of the correction `π(a) − π₂(c)`, indexed `[row][feature][share]`
+    correction: Vec<Vec<Vec<SecretShare>>>,
+}
+
+/// MAC correlation dealt by the auxiliary server in malicious mode
+struct MacCorrelation {
+    /// Shares of the global MAC key `α`
+    key: Vec<SecretShare>,
+    /// Shares of `α·(κ + r) − π₂(m)`, indexed `[row][feature][share]`
+    tags: Vec<Vec<Vec<SecretShare>>>,
+    /// Masks `m` P₁ hides its tags on the blinded rows with
+    tag_masks: Vec<Vec<FieldElement>>,
+    /// P₁'s shuffle check keys `−(k + π₁(q))`
+    first_check_keys: Vec<Vec<FieldElement>>,
+    /// P₂'s shuffle check keys `k`
+    second_check_keys: Vec<Vec<FieldElement>>,
+    /// P₂'s shuffle check masks `q`
+    check_masks: Vec<Vec<FieldElement>>,
+    /// Shares of `⟨k, c⟩`, indexed `[feature][share]`
+    check_shares: Vec<Vec<SecretShare>>,
+}
+
+/// Everything the auxiliary server deals to one computational server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerCorrelation {
+    /// The server's half of the permutation
+    pub permutation: Vec<usize>,
+    /// Blinding added before handing rows on (first server only)
+    pub blinding: Vec<Vec<FieldElement>>,
+    /// The server's share of the mask correction
+    pub mask_shares: Vec<Vec<SecretShare>>,
+    /// The server's share of the noise
+    pub noise_shares: Vec<Vec<SecretShare>>,
+    /// The server's MAC key and tag shares (malicious mode only)
+    #[serde(default)]
+    pub mac: Option<MacShares>,
+}
+
+/// Description of offline material saved to disk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OfflineManifest {
+    /// Field modulus the material was dealt in
+    pub field_modulus: u64,
+    /// Number of features per user
+    pub num_features: usize,
+    /// Users the material was dealt for, in order
+    pub registrations: Vec<UserRegistration>,
+}
+
+impl OfflineManifest {
+    /// Check the material matches `config`
+    pub fn check(&self, config: &ToyConfig) -> Result<(), ProtocolError> {
+        if self.field_modulus != config.field_modulus
+            || self.num_features != config.num_features
+            || self.registrations.len() != config.num_users
+        {
+            return Err(ProtocolError::invalid_configuration(format!(
+                "Offline material for {} users with {} features mod {} does not match configuration",
+                self.registrations.len(),
+                self.num_features,
+                self.field_modulus
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Path of computational server `server_id`'s correlation file in `dir`
+pub fn correlation_path(dir: &Path, server_id: usize) -> std::path::PathBuf {
+    dir.join(format!("server-{}.json", server_id))
+}
+
+/// Write the manifest and one correlation file per computational server to `dir`
+///
+/// Each server only needs its own file, so the files can be shipped to the
+/// servers separately.
+pub fn save_offline(
+    dir: &Path,
+    manifest: &OfflineManifest,
+    correlations: &HashMap<usize, ServerCorrelation>,
+) -> Result<(), ProtocolError> {
+    std::fs::create_dir_all(dir).map_err(|e| storage_error(dir, e))?;
+    write_json(&dir.join(MANIFEST_FILE), manifest)?;
+    for (server_id, correlation) in correlations {
+        write_json(&correlation_path(dir, *server_id), correlation)?;
+    }
+
+    Ok(())
+}
+
+/// Read the manifest and both computational servers' correlations from `dir`
+pub fn load_offline(dir: &Path) -> Result<(OfflineManifest, HashMap<usize, ServerCorrelation>), ProtocolError> {
+    let manifest: OfflineManifest = read_json(&dir.join(MANIFEST_FILE))?;
+    let mut correlations = HashMap::new();
+    for server_id in 1..=2 {
+        correlations.insert(server_id, read_json(&correlation_path(dir, server_id))?);
+    }
+
+    Ok((manifest, correlations))
+}
+
+/// Write a value as JSON
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), ProtocolError> {
+    let file = File::create(path).map_err(|e| storage_error(path, e))?;
+    serde_json::to_writer(BufWriter::new(file), value).map_err(|e| storage_error(path, e))
+}
+
+/// Read a value from JSON
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, ProtocolError> {
+    let file = File::open(path).map_err(|e| storage_error(path, e))?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| storage_error(path, e))
+}
+
+/// Create an error for a failed offline file operation
+fn storage_error(path: &Path, error: impl std::fmt::Display) -> ProtocolError {
+    ProtocolError::storage_error(format!("{}: {}", path.display(), error))
+}
 
 /// Offline phase implementation
 pub struct OfflinePhase {
@@ -12,9 +205,19 @@ pub struct OfflinePhase {
     /// Finite field
     field: FiniteField,
     /// Secret sharing scheme
-    secret_sharing: ShamirSecretSharing,
-    /// Share distributor
-    distributor: ShareDistributor,
+    secret_sharing: AdditiveSecretSharing,
+    /// Fixed-point encoding of the noise
+    codec: FixedPoint,
+    /// PRF the user masks are derived with
+    masks: MaskDerivation,
+    /// Fraction of the privacy budget each feature's noise is calibrated to
+    budget: Vec<f64>,
+    /// Branch of the seed tree this phase draws from, if the run is seeded
+    seed: Option<SeedTree>,
+    /// Timing and communication of this phase
+    stats: OfflineStats,
+    /// Receives progress events
+    observer: SharedObserver,
 }
 
 impl OfflinePhase {
@@ -22,163 +225,386 @@ impl OfflinePhase {
     pub fn new(
         config: ToyConfig,
         field: FiniteField,
-        secret_sharing: ShamirSecretSharing,
+        secret_sharing: AdditiveSecretSharing,
     ) -> Result<Self, ProtocolError> {
-        let distributor = ShareDistributor::new(secret_sharing.clone(), 3);
+        let codec = FixedPoint::new(config.field_modulus, config.fractional_bits)?;
+        let masks = MaskDerivation::new(field.clone(), config.num_features);
+        let budget = config.budget.fractions(config.num_features)?;
+        let seed = config.seed.map(|master| SeedTree::new(master).child(seed::OFFLINE));
 
         Ok(Self {
             config,
             field,
             secret_sharing,
-            distributor,
+            codec,
+            masks,
+            budget,
+            seed,
+            stats: OfflineStats::default(),
+            observer: progress::silent(),
         })
     }
 
-    /// Execute offline phase
-    pub async fn execute(&self, servers: &mut HashMap<usize, Server>) -> Result<(), ProtocolError> {
-        println!("  Generating shuffle correlation...");
-        self.generate_shuffle_correlation(servers).await?;
+    /// Send progress events to `observer`
+    pub fn set_observer(&mut self, observer: SharedObserver) {
+        self.observer = observer;
+    }
+
+    /// Random number generator of `stream`, derived from the seed in seeded runs
+    ///
+    /// A seeded phase deals the same correlations every time, so re-dealing
+    /// after a failure reuses the noise of the aborted attempt.
+    fn rng(&self, stream: Stream) -> ChaCha20Rng {
+        seed::rng(self.seed.as_ref(), stream as u64)
+    }
 
-        println!("  Generating DP correlation...");
-        self.generate_dp_correlation(servers).await?;
+    /// Report that a step of the offline phase finished
+    fn report(&self, percent: f64, step: &str) {
+        self.observer.progress(Phase::Offline, percent, step);
+    }
 
-        println!("  Distributing shares to computational servers...");
-        self.distribute_shares(servers).await?;
+    /// Execute offline phase for the registered users
+    pub async fn execute(
+        &mut self,
+        servers: &mut HashMap<usize, Server>,
+        registrations: &[UserRegistration],
+    ) -> Result<(), ProtocolError> {
+        let (correlations, _, stats) = self.deal_with_simulation(registrations).await?;
+        self.stats.merge(&stats);
+        self.distribute(servers, correlations)
+    }
+
+    /// Hand each computational server the correlation dealt for it
+    pub fn distribute(
+        &mut self,
+        servers: &mut HashMap<usize, Server>,
+        mut correlations: HashMap<usize, ServerCorrelation>,
+    ) -> Result<(), ProtocolError> {
+        let _span = info_span!("distribute").entered();
+        let start = Instant::now();
+        for server_id in 1..=2 {
+            let correlation = correlations.remove(&server_id).ok_or(ProtocolError::ServerNotFound)?;
+            let bytes = self.stats.communication.record(&ToyMessage::Correlation(correlation.clone()), 1)?;
+            self.stats.total_communication_bytes += bytes;
+            tracing::debug!(server_id, bytes, "distributed correlation");
+
+            let server = servers.get_mut(&server_id).ok_or(ProtocolError::ServerNotFound)?;
+            server.receive_correlation(correlation);
+            self.report(80.0 + 10.0 * server_id as f64, &format!("Distributed shares to server {}", server_id));
+        }
+        self.stats.distribution_time_ms += elapsed_ms(start);
 
         Ok(())
     }
 
-    /// Generate shuffle correlation (permutation matrix and masks)
-    async fn generate_shuffle_correlation(&self, servers: &mut HashMap<usize, Server>) -> Result<(), ProtocolError> {
-        let auxiliary_server = servers.get_mut(&0).ok_or(ProtocolError::ServerNotFound)?;
-        
-        // Generate random permutation matrix
-        let permutation_matrix = self.generate_permutation_matrix().await?;
-        println!("    ✓ Generated permutation matrix");
+    /// Get bytes sent to the computational servers
+    pub fn communication_bytes(&self) -> usize {
+        self.stats.total_communication_bytes
+    }
 
-        // Generate random masks for each user
-        let masks = self.generate_user_masks().await?;
-        println!("    ✓ Generated user masks");
+    /// Get timing and communication recorded by `execute` and `distribute`
+    pub fn stats(&self) -> OfflineStats {
+        self.stats.clone()
+    }
 
-        // Share permutation matrix
-        let permutation_shares = self.share_permutation_matrix(&permutation_matrix).await?;
-        auxiliary_server.store_permutation_shares(permutation_shares);
-        println!("    ✓ Shared permutation matrix");
+    /// Generate the correlation for each computational server, keyed by server ID
+    pub async fn deal(&self, registrations: &[UserRegistration]) -> Result<HashMap<usize, ServerCorrelation>, ProtocolError> {
+        self.deal_with_simulation(registrations).await.map(|(correlations, _, _)| correlations)
+    }
 
-        // Share user masks
-        let mask_shares = self.share_user_masks(&masks).await?;
-        auxiliary_server.store_mask_shares(mask_shares);
-        println!("    ✓ Shared user masks");
+    /// Generate the correlations along with P₀'s plaintext view of the shuffle
+    /// and noise, and the time each step took
+    pub async fn deal_with_simulation(
+        &self,
+        registrations: &[UserRegistration],
+    ) -> Result<(HashMap<usize, ServerCorrelation>, PlaintextSimulation, OfflineStats), ProtocolError> {
+        if registrations.len() != self.config.num_users {
+            return Err(ProtocolError::invalid_configuration(format!(
+                "Expected {} registered users, got {}",
+                self.config.num_users,
+                registrations.len()
+            )));
+        }
 
-        Ok(())
+        let users = registrations.len();
+        let shuffle_span = info_span!("shuffle_correlation", users, elapsed_ms = Empty);
+        let mut stats = OfflineStats::default();
+        let shuffle = timed(shuffle_span, self.generate_shuffle_correlation(registrations, &mut stats)).await?;
+
+        let noise_span = info_span!("dp_correlation", users, noise = ?self.config.noise, elapsed_ms = Empty);
+        let (noise_vector, noise) = timed(noise_span, self.generate_dp_correlation(&mut stats)).await?;
+
+        let mac = match self.config.security {
+            SecurityMode::SemiHonest => None,
+            SecurityMode::Malicious => {
+                let mac_span = info_span!("mac_correlation", users, elapsed_ms = Empty);
+                let start = Instant::now();
+                let mac = timed_blocking(mac_span, || self.generate_mac_correlation(&shuffle, &noise))?;
+                stats.sharing_time_ms += elapsed_ms(start);
+                Some(mac)
+            }
+        };
+
+        let simulation = PlaintextSimulation::new(
+            &shuffle.first_permutation,
+            &shuffle.second_permutation,
+            noise_vector,
+        )?;
+        let correlations = self.split_correlation(shuffle, &noise, mac)?;
+
+        Ok((correlations, simulation, stats))
     }
 
-    /// Generate DP correlation (noise vector)
-    async fn generate_dp_correlation(&self, servers: &mut HashMap<usize, Server>) -> Result<(), ProtocolError> {
-        let auxiliary_server = servers.get_mut(&0).ok_or(ProtocolError::ServerNotFound)?;
-        
+    /// Generate MAC correlation: shares of a global key `α` and of `α·(κ + r) − π₂(m)` for every value
+    ///
+    /// P₁ tags its blinded rows `u` with `α₁·u + m`, and P₂ adds those tags,
+    /// permuted, to its own tag on `π₂(u)`, so the `−π₂(m)` dealt here leaves
+    /// a tag of `α·w` on the whole output `w`. The shuffle check keys bind
+    /// `u` itself to the submissions, which P₁'s own tags cannot.
+    fn generate_mac_correlation(
+        &self,
+        shuffle: &ShuffleCorrelation,
+        noise: &[Vec<Vec<SecretShare>>],
+    ) -> Result<MacCorrelation, ProtocolError> {
+        let mut rng = self.rng(Stream::Mac);
+        let mut key = self.field.random_element_with(&mut rng);
+        while key.is_zero() {
+            key = self.field.random_element_with(&mut rng);
+        }
+
+        let tag_masks: Vec<Vec<FieldElement>> = (0..self.config.num_users)
+            .map(|_| self.field.random_vector_with(&mut rng, self.config.num_features))
+            .collect();
+        let permuted_masks = permute(&shuffle.second_permutation, &tag_masks)?;
+
+        let mut tags = Vec::with_capacity(shuffle.correction.len());
+        for ((correction_row, noise_row), mask_row) in shuffle.correction.iter().zip(noise.iter()).zip(&permuted_masks) {
+            let mut tag_row = Vec::with_capacity(correction_row.len());
+            for ((correction_shares, noise_shares), mask) in correction_row.iter().zip(noise_row.iter()).zip(mask_row) {
+                // Sharing is linear, so the summed shares reconstruct κ + r
+                let summed = self.secret_sharing.add_shares(correction_shares, noise_shares)?;
+                let value = self.secret_sharing.reconstruct_secret(&summed)?;
+                let tag = key.mul(&value)?.sub(mask)?;
+                tag_row.push(self.secret_sharing.share_secret_with(&mut rng, tag)?);
+            }
+            tags.push(tag_row);
+        }
+        self.report(80.0, "Shared MAC tags");
+
+        // P₂ checks u against k and q, and P₁ cancels the honest rows with
+        // −(k + π₁(q)) on π₁(d), leaving ⟨k, c⟩ to the shares
+        let check_keys: Vec<Vec<FieldElement>> = (0..self.config.num_users)
+            .map(|_| self.field.random_vector_with(&mut rng, self.config.num_features))
+            .collect();
+        let check_masks: Vec<Vec<FieldElement>> = (0..self.config.num_users)
+            .map(|_| self.field.random_vector_with(&mut rng, self.config.num_features))
+            .collect();
+        let permuted_masks = permute(&shuffle.first_permutation, &check_masks)?;
+        let first_check_keys = check_keys
+            .iter()
+            .zip(&permuted_masks)
+            .map(|(key_row, mask_row)| {
+                let summed = self.field.vector_add(key_row, mask_row)?;
+                self.field.vector_sub(&vec![self.field.zero(); summed.len()], &summed)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut blinded_check = vec![self.field.zero(); self.config.num_features];
+        for (key_row, blinding_row) in check_keys.iter().zip(&shuffle.blinding) {
+            let products = self.field.vector_mul(key_row, blinding_row)?;
+            blinded_check = self.field.vector_add(&blinded_check, &products)?;
+        }
+
+        Ok(MacCorrelation {
+            key: self.secret_sharing.share_secret_with(&mut rng, key)?,
+            tags,
+            tag_masks,
+            first_check_keys,
+            second_check_keys: check_keys,
+            check_masks,
+            check_shares: self.secret_sharing.share_vector_with(&mut rng, &blinded_check)?,
+        })
+    }
+
+    /// Generate shuffle correlation (the two permutation halves, blinding and correction shares)
+    async fn generate_shuffle_correlation(
+        &self,
+        registrations: &[UserRegistration],
+        stats: &mut OfflineStats,
+    ) -> Result<ShuffleCorrelation, ProtocolError> {
+        // Generate the two halves of the permutation
+        let start = Instant::now();
+        let first_permutation = self.generate_permutation(Stream::FirstPermutation).await?;
+        let second_permutation = self.generate_permutation(Stream::SecondPermutation).await?;
+        stats.permutation_time_ms += elapsed_ms(start);
+        self.report(15.0, "Generated permutation vectors");
+
+        // Derive each user's mask from its seed
+        let start = Instant::now();
+        let masks = self.generate_user_masks(registrations).await?;
+        stats.mask_time_ms += elapsed_ms(start);
+        self.report(30.0, "Generated user masks");
+
+        let start = Instant::now();
+
+        // P₂ receives π₁(d) + c and computes π₂(π₁(d) + c) = π(x) − π(a) + π₂(c),
+        // so the servers need shares of π(a) − π₂(c) to recover π(x)
+        let mut rng = self.rng(Stream::Blinding);
+        let blinding: Vec<Vec<FieldElement>> = (0..self.config.num_users)
+            .map(|_| self.field.random_vector_with(&mut rng, self.config.num_features))
+            .collect();
+        let permuted_masks = permute(&second_permutation, &permute(&first_permutation, &masks)?)?;
+        let permuted_blinding = permute(&second_permutation, &blinding)?;
+        let correction = permuted_masks
+            .iter()
+            .zip(permuted_blinding.iter())
+            .map(|(mask, blind)| self.field.vector_sub(mask, blind))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Share the correction
+        let correction = self.share_user_masks(&correction).await?;
+        stats.sharing_time_ms += elapsed_ms(start);
+        self.report(45.0, "Shared mask correction");
+
+        Ok(ShuffleCorrelation {
+            first_permutation,
+            second_permutation,
+            blinding,
+            correction,
+        })
+    }
+
+    /// Generate DP correlation (the noise vector and its shares)
+    async fn generate_dp_correlation(
+        &self,
+        stats: &mut OfflineStats,
+    ) -> Result<(Vec<Vec<FieldElement>>, Vec<Vec<Vec<SecretShare>>>), ProtocolError> {
         // Generate noise vector for differential privacy
+        let start = Instant::now();
         let noise_vector = self.generate_dp_noise().await?;
-        println!("    ✓ Generated DP noise vector");
+        stats.noise_time_ms += elapsed_ms(start);
+        self.report(55.0, "Generated DP noise vector");
 
         // Share noise vector
+        let start = Instant::now();
         let noise_shares = self.share_noise_vector(&noise_vector).await?;
-        auxiliary_server.store_noise_shares(noise_shares);
-        println!("    ✓ Shared noise vector");
+        stats.sharing_time_ms += elapsed_ms(start);
+        self.report(70.0, "Shared noise vector");
 
-        Ok(())
+        Ok((noise_vector, noise_shares))
     }
 
-    /// Distribute shares to computational servers
-    async fn distribute_shares(&self, servers: &mut HashMap<usize, Server>) -> Result<(), ProtocolError> {
-        let auxiliary_server = servers.get(&0).ok_or(ProtocolError::ServerNotFound)?;
-        
-        // Clone the shares to avoid borrowing conflicts
-        let permutation_shares = auxiliary_server.get_permutation_shares().clone();
-        let mask_shares = auxiliary_server.get_mask_shares().clone();
-        let noise_shares = auxiliary_server.get_noise_shares().clone();
-        
-        // Send shares to computational servers
-        for server_id in 1..=2 {
-            if let Some(server) = servers.get_mut(&server_id) {
-                // For simplicity, just copy the shares directly
-                // In a real implementation, you'd distribute different shares to each server
-                server.receive_permutation_shares(permutation_shares.clone());
-                server.receive_mask_shares(mask_shares.clone());
-                server.receive_noise_shares(noise_shares.clone());
-                
-                println!("    ✓ Distributed shares to server {}", server_id);
-            }
+    /// Split the correlations between the computational servers
+    ///
+    /// Computational server `j` receives only share `j - 1` of every value and
+    /// only its own half of the permutation.
+    fn split_correlation(
+        &self,
+        shuffle: ShuffleCorrelation,
+        noise: &[Vec<Vec<SecretShare>>],
+        mac: Option<MacCorrelation>,
+    ) -> Result<HashMap<usize, ServerCorrelation>, ProtocolError> {
+        let ShuffleCorrelation {
+            first_permutation,
+            second_permutation,
+            blinding,
+            correction,
+        } = shuffle;
+        let halves = [(first_permutation, blinding), (second_permutation, Vec::new())];
+
+        let mut correlations = HashMap::new();
+        for (server_id, (permutation, blinding)) in (1..=2).zip(halves) {
+            let share_index = server_id - 1;
+            let mac = match &mac {
+                Some(mac) => Some(MacShares {
+                    key_share: mac.key.get(share_index).cloned().ok_or(ProtocolError::SharingFailed)?,
+                    tag_shares: select_shares(&mac.tags, share_index)?,
+                    tag_masks: if server_id == 1 { mac.tag_masks.clone() } else { Vec::new() },
+                    check_keys: if server_id == 1 { mac.first_check_keys.clone() } else { mac.second_check_keys.clone() },
+                    check_masks: if server_id == 1 { Vec::new() } else { mac.check_masks.clone() },
+                    check_shares: mac
+                        .check_shares
+                        .iter()
+                        .map(|shares| shares.get(share_index).cloned().ok_or(ProtocolError::SharingFailed))
+                        .collect::<Result<_, _>>()?,
+                }),
+                None => None,
+            };
+            correlations.insert(server_id, ServerCorrelation {
+                permutation,
+                blinding,
+                mask_shares: select_shares(&correction, share_index)?,
+                noise_shares: select_shares(noise, share_index)?,
+                mac,
+            });
         }
 
-        Ok(())
+        Ok(correlations)
     }
 
-    /// Generate random permutation matrix
-    async fn generate_permutation_matrix(&self) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        let n = self.config.num_users;
-        let mut matrix = vec![vec![self.field.zero(); n]; n];
-        
-        // Generate random permutation
-        let mut permutation: Vec<usize> = (0..n).collect();
-        self.shuffle_permutation(&mut permutation);
-        
-        // Create permutation matrix
-        for (i, &pos) in permutation.iter().enumerate() {
-            matrix[i][pos] = self.field.one();
-        }
-        
-        Ok(matrix)
+    /// Generate random permutation vector from `stream`
+    async fn generate_permutation(&self, stream: Stream) -> Result<Vec<usize>, ProtocolError> {
+        let mut permutation: Vec<usize> = (0..self.config.num_users).collect();
+        self.shuffle_permutation(&mut permutation, &mut self.rng(stream));
+
+        Ok(permutation)
     }
 
-    /// Generate random masks for each user
-    async fn generate_user_masks(&self) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        let n = self.config.num_users;
-        let mut masks = Vec::with_capacity(n);
-        
-        for _ in 0..n {
-            let user_mask = self.field.random_vector(2); // Assuming 2 features per user
-            masks.push(user_mask);
-        }
-        
+    /// Derive the mask of each registered user
+    async fn generate_user_masks(&self, registrations: &[UserRegistration]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        let masks = registrations
+            .iter()
+            .map(|user| self.masks.derive(user.user_id, &user.seed))
+            .collect();
+
         Ok(masks)
     }
 
-    /// Generate DP noise vector
-    async fn generate_dp_noise(&self) -> Result<Vec<FieldElement>, ProtocolError> {
+    /// Generate DP noise, one value per user and feature
+    ///
+    /// In histogram mode the servers sum all rows, so only the first row is
+    /// noised and the rest are zero.
+    async fn generate_dp_noise(&self) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
         let n = self.config.num_users;
         let mut noise = Vec::with_capacity(n);
         
-        // Generate Laplace noise scaled by privacy budget
-        let scale = self.config.noise_scale / self.config.epsilon;
-        
-        for _ in 0..n {
-            let noise_value = self.generate_laplace_noise(scale)?;
-            noise.push(noise_value);
-        }
+        // Noise is scaled by each feature's share of the privacy budget
+        let parameters: Vec<f64> = self
+            .budget
+            .iter()
+            .map(|fraction| match self.config.noise {
+                NoiseKind::Laplace => self.config.noise_scale / (self.config.epsilon * fraction),
+                NoiseKind::DiscreteGaussian { sigma } => sigma / fraction.sqrt(),
+            })
+            .collect();
+        let mut rng = self.rng(Stream::Noise);
         
-        Ok(noise)
-    }
+        for row in 0..n {
+            if row > 0 && self.config.output.is_histogram() {
+                noise.push(vec![self.field.zero(); self.config.num_features]);
+                continue;
+            }
 
-    /// Share permutation matrix
-    async fn share_permutation_matrix(&self, matrix: &[Vec<FieldElement>]) -> Result<Vec<Vec<Vec<SecretShare>>>, ProtocolError> {
-        let mut all_shares = Vec::with_capacity(matrix.len());
-        
-        for row in matrix {
-            let row_shares = self.secret_sharing.share_vector(row)
-                .map_err(|_| ProtocolError::SharingFailed)?;
-            all_shares.push(row_shares);
+            let user_noise = parameters
+                .iter()
+                .map(|&parameter| match self.config.noise {
+                    NoiseKind::Laplace => self.generate_laplace_noise(&mut rng, parameter),
+                    NoiseKind::DiscreteGaussian { .. } => self.generate_discrete_gaussian_noise(&mut rng, parameter),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            noise.push(user_noise);
         }
         
-        Ok(all_shares)
+        Ok(noise)
     }
 
-    /// Share user masks
+    /// Share per-user mask values
     async fn share_user_masks(&self, masks: &[Vec<FieldElement>]) -> Result<Vec<Vec<Vec<SecretShare>>>, ProtocolError> {
         let mut all_shares = Vec::with_capacity(masks.len());
+        let mut rng = self.rng(Stream::CorrectionShares);
         
         for mask in masks {
-            let mask_shares = self.secret_sharing.share_vector(mask)
+            let mask_shares = self.secret_sharing.share_vector_with(&mut rng, mask)
                 .map_err(|_| ProtocolError::SharingFailed)?;
             all_shares.push(mask_shares);
         }
@@ -187,36 +613,53 @@ impl OfflinePhase {
     }
 
     /// Share noise vector
-    async fn share_noise_vector(&self, noise: &[FieldElement]) -> Result<Vec<Vec<SecretShare>>, ProtocolError> {
-        self.secret_sharing.share_vector(noise)
+    async fn share_noise_vector(&self, noise: &[Vec<FieldElement>]) -> Result<Vec<Vec<Vec<SecretShare>>>, ProtocolError> {
+        self.secret_sharing.share_matrix_with(&mut self.rng(Stream::NoiseShares), noise)
             .map_err(|_| ProtocolError::SharingFailed)
     }
 
     /// Shuffle permutation using Fisher-Yates
-    fn shuffle_permutation(&self, permutation: &mut [usize]) {
+    fn shuffle_permutation<R: Rng + ?Sized>(&self, permutation: &mut [usize], rng: &mut R) {
         use rand::seq::SliceRandom;
-        use rand::thread_rng;
-        permutation.shuffle(&mut thread_rng());
+        permutation.shuffle(rng);
+    }
+
+    /// Generate discrete Gaussian noise with standard deviation `sigma`, in data units
+    ///
+    /// The sample is drawn exactly over the fixed-point grid, so it is already
+    /// an integer multiple of the encoding's resolution.
+    fn generate_discrete_gaussian_noise<R: Rng + ?Sized>(&self, rng: &mut R, sigma: f64) -> Result<FieldElement, ProtocolError> {
+        let noise = noise::sample_discrete_gaussian(rng, sigma * self.codec.scale());
+        self.codec.encode_integer(noise).map_err(ProtocolError::from)
     }
 
     /// Generate Laplace noise
-    fn generate_laplace_noise(&self, scale: f64) -> Result<FieldElement, ProtocolError> {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
-        
-        let u1: f64 = rng.gen_range(0.0..1.0);
-        let u2: f64 = rng.gen_range(0.0..1.0);
+    fn generate_laplace_noise<R: Rng + ?Sized>(&self, rng: &mut R, scale: f64) -> Result<FieldElement, ProtocolError> {
+        // The difference of two Exp(1) samples is Laplace(1)
+        let u1: f64 = rng.gen_range(f64::MIN_POSITIVE..1.0);
+        let u2: f64 = rng.gen_range(f64::MIN_POSITIVE..1.0);
         
         let noise = scale * (u1.ln() - u2.ln());
         
-        // Convert to field element (modulo field size)
-        let noise_u64 = ((noise.abs() * 1000.0) as u64) % self.field.modulus();
-        Ok(FieldElement::new(noise_u64, self.field.modulus()))
+        // Convert to a signed fixed-point field element
+        self.codec.encode(noise).map_err(ProtocolError::from)
     }
 }
 
+/// Pick one share of every value for a single server
+fn select_shares(shares: &[Vec<Vec<SecretShare>>], share_index: usize) -> Result<Vec<Vec<SecretShare>>, ProtocolError> {
+    shares
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value_shares| value_shares.get(share_index).cloned().ok_or(ProtocolError::SharingFailed))
+                .collect()
+        })
+        .collect()
+}
+
 /// Offline phase statistics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct OfflineStats {
     /// Time taken for permutation generation (ms)
     pub permutation_time_ms: u64,
@@ -224,24 +667,36 @@ pub struct OfflineStats {
     pub mask_time_ms: u64,
     /// Time taken for noise generation (ms)
     pub noise_time_ms: u64,
+    /// Time taken for secret sharing the correction, noise and MAC tags (ms)
+    pub sharing_time_ms: u64,
     /// Time taken for share distribution (ms)
     pub distribution_time_ms: u64,
     /// Total communication (bytes)
     pub total_communication_bytes: usize,
+    /// Communication by message type (bytes)
+    #[serde(default)]
+    pub communication: MessageBytes,
 }
 
-impl Default for OfflineStats {
-    fn default() -> Self {
-        Self {
-            permutation_time_ms: 0,
-            mask_time_ms: 0,
-            noise_time_ms: 0,
-            distribution_time_ms: 0,
-            total_communication_bytes: 0,
-        }
+impl OfflineStats {
+    /// Add the statistics of another run
+    pub fn merge(&mut self, other: &OfflineStats) {
+        self.permutation_time_ms += other.permutation_time_ms;
+        self.mask_time_ms += other.mask_time_ms;
+        self.noise_time_ms += other.noise_time_ms;
+        self.sharing_time_ms += other.sharing_time_ms;
+        self.distribution_time_ms += other.distribution_time_ms;
+        self.total_communication_bytes += other.total_communication_bytes;
+        self.communication.merge(&other.communication);
     }
 }
 
+/// Milliseconds elapsed since `start`
+fn elapsed_ms(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -250,7 +705,7 @@ mod tests {
     async fn test_offline_phase_creation() {
         let config = ToyConfig::default();
         let field = FiniteField::new(config.field_modulus).unwrap();
-        let secret_sharing = ShamirSecretSharing::new(2, 3, config.field_modulus).unwrap();
+        let secret_sharing = AdditiveSecretSharing::new(config.field_modulus).unwrap();
         
         let offline_phase = OfflinePhase::new(config, field, secret_sharing);
         assert!(offline_phase.is_ok());
@@ -260,26 +715,28 @@ mod tests {
     async fn test_permutation_generation() {
         let config = ToyConfig { num_users: 10, ..Default::default() };
         let field = FiniteField::new(config.field_modulus).unwrap();
-        let secret_sharing = ShamirSecretSharing::new(2, 3, config.field_modulus).unwrap();
+        let secret_sharing = AdditiveSecretSharing::new(config.field_modulus).unwrap();
         
         let offline_phase = OfflinePhase::new(config, field, secret_sharing).unwrap();
         
-        // Test permutation matrix generation
-        let matrix = offline_phase.generate_permutation_matrix().await.unwrap();
-        assert_eq!(matrix.len(), 10);
-        assert_eq!(matrix[0].len(), 10);
+        // Test permutation generation
+        let mut permutation = offline_phase.generate_permutation(Stream::FirstPermutation).await.unwrap();
+        assert_eq!(permutation.len(), 10);
+        permutation.sort_unstable();
+        assert_eq!(permutation, (0..10).collect::<Vec<_>>());
     }
 
     #[tokio::test]
     async fn test_mask_generation() {
         let config = ToyConfig { num_users: 10, ..Default::default() };
         let field = FiniteField::new(config.field_modulus).unwrap();
-        let secret_sharing = ShamirSecretSharing::new(2, 3, config.field_modulus).unwrap();
+        let secret_sharing = AdditiveSecretSharing::new(config.field_modulus).unwrap();
         
         let offline_phase = OfflinePhase::new(config, field, secret_sharing).unwrap();
         
         // Test mask generation
-        let masks = offline_phase.generate_user_masks().await.unwrap();
+        let registrations: Vec<UserRegistration> = (0..10).map(|i| UserRegistration::new(i, [i as u8; 32])).collect();
+        let masks = offline_phase.generate_user_masks(&registrations).await.unwrap();
         assert_eq!(masks.len(), 10);
         assert_eq!(masks[0].len(), 2);
     }
@@ -288,7 +745,7 @@ mod tests {
     async fn test_noise_generation() {
         let config = ToyConfig { num_users: 10, ..Default::default() };
         let field = FiniteField::new(config.field_modulus).unwrap();
-        let secret_sharing = ShamirSecretSharing::new(2, 3, config.field_modulus).unwrap();
+        let secret_sharing = AdditiveSecretSharing::new(config.field_modulus).unwrap();
         
         let offline_phase = OfflinePhase::new(config, field, secret_sharing).unwrap();
         
@@ -296,4 +753,91 @@ mod tests {
         let noise = offline_phase.generate_dp_noise().await.unwrap();
         assert_eq!(noise.len(), 10);
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_single_server_view_is_independent_of_secrets() {
+        use crate::server::ServerRole;
+
+        let config = ToyConfig { num_users: 8, ..Default::default() };
+        let field = FiniteField::new(config.field_modulus).unwrap();
+        let secret_sharing = AdditiveSecretSharing::new(config.field_modulus).unwrap();
+        let mut offline_phase = OfflinePhase::new(config.clone(), field, secret_sharing.clone()).unwrap();
+
+        let mut servers = HashMap::new();
+        servers.insert(0, Server::new(0, ServerRole::Auxiliary, config.clone()));
+        servers.insert(1, Server::new(1, ServerRole::Computational, config.clone()));
+        servers.insert(2, Server::new(2, ServerRole::Computational, config.clone()));
+
+        let registrations: Vec<UserRegistration> = (0..8).map(|i| UserRegistration::new(i, [i as u8; 32])).collect();
+        offline_phase.execute(&mut servers, &registrations).await.unwrap();
+
+        // Each server only knows its own half of the permutation
+        let first = servers[&1].get_permutation();
+        let second = servers[&2].get_permutation();
+        assert_eq!(first.len(), 8);
+        assert_eq!(second.len(), 8);
+        assert_eq!(servers[&1].get_blinding().len(), 8);
+        assert!(servers[&2].get_blinding().is_empty());
+
+        let first_shares = servers[&1].get_mask_shares().iter().chain(servers[&1].get_noise_shares());
+        let second_shares = servers[&2].get_mask_shares().iter().chain(servers[&2].get_noise_shares());
+        for (row_1, row_2) in first_shares.zip(second_shares) {
+            for (share_1, share_2) in row_1.iter().zip(row_2.iter()) {
+                // Each server holds a different share of every value
+                assert_eq!(share_1.id(), 0);
+                assert_eq!(share_2.id(), 1);
+
+                // A single share is not enough to reconstruct
+                assert!(secret_sharing.reconstruct_secret(std::slice::from_ref(share_1)).is_err());
+                assert!(secret_sharing.reconstruct_secret(&[share_1.clone(), share_2.clone()]).is_ok());
+            }
+        }
+        assert!(servers[&0].get_permutation().is_empty());
+        assert!(servers[&0].get_mask_shares().is_empty());
+    }
+
+    #[test]
+    fn test_permute() {
+        let rows = vec!['a', 'b', 'c'];
+        assert_eq!(permute(&[2, 0, 1], &rows).unwrap(), vec!['c', 'a', 'b']);
+        assert!(permute(&[0, 1], &rows).is_err());
+        assert!(permute(&[0, 1, 3], &rows).is_err());
+    }
+
+    #[test]
+    fn test_decoded_noise_is_laplace() {
+        let config = ToyConfig { noise_scale: 2.0, epsilon: 0.5, ..Default::default() };
+        let field = FiniteField::new(config.field_modulus).unwrap();
+        let secret_sharing = AdditiveSecretSharing::new(config.field_modulus).unwrap();
+        let codec = FixedPoint::new(config.field_modulus, config.fractional_bits).unwrap();
+        let scale = config.noise_scale / config.epsilon;
+        let offline_phase = OfflinePhase::new(config, field, secret_sharing).unwrap();
+
+        let samples = 20_000;
+        let mut rng = rand::thread_rng();
+        let mut noise: Vec<f64> = (0..samples)
+            .map(|_| codec.decode(&offline_phase.generate_laplace_noise(&mut rng, scale).unwrap()))
+            .collect();
+
+        // Moments of Laplace(b): mean 0, E|X| = b, Var = 2b²
+        let mean = noise.iter().sum::<f64>() / samples as f64;
+        let mean_abs = noise.iter().map(|x| x.abs()).sum::<f64>() / samples as f64;
+        let variance = noise.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples as f64;
+        assert!(mean.abs() < 0.15, "mean {}", mean);
+        assert!((mean_abs - scale).abs() < 0.15, "mean |x| {}", mean_abs);
+        assert!((variance / (2.0 * scale * scale) - 1.0).abs() < 0.1, "variance {}", variance);
+
+        // Kolmogorov-Smirnov distance to the Laplace CDF, at the 0.1% level
+        noise.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let cdf = |x: f64| if x < 0.0 { 0.5 * (x / scale).exp() } else { 1.0 - 0.5 * (-x / scale).exp() };
+        let distance = noise
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                let f = cdf(x);
+                (f - i as f64 / samples as f64).abs().max(((i + 1) as f64 / samples as f64 - f).abs())
+            })
+            .fold(0.0, f64::max);
+        assert!(distance < 1.95 / (samples as f64).sqrt(), "KS distance {}", distance);
+    }
+}
@@ -1,8 +1,30 @@
-use crate::finite_field::{FieldElement, FiniteField, FieldError};
-use crate::secret_sharing::{SecretShare, ShamirSecretSharing};
-use crate::server::{Server, ServerRole};
+use crate::fault::OnlineStep;
+use crate::finite_field::{FieldElement, FiniteField};
+use crate::histogram;
+use crate::mac::{self, AuthenticatedShare, MacShares, SecurityMode};
+use crate::message::{MessageBytes, ToyMessage};
+use crate::offline_phase::permute;
+use crate::parallel;
+use crate::secret_sharing::SecretShare;
+use crate::progress::{self, timed, timed_blocking, Phase, SharedObserver};
+use crate::server::Server;
+use crate::user::UserClient;
 use crate::{UserData, ProtocolError};
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::info_span;
+
+/// What P₂ holds after the shuffle in malicious mode
+#[derive(Debug, Clone, Copy)]
+pub struct TaggedRows<'a> {
+    /// Blinded rows `u` received from P₁
+    pub blinded: &'a [Vec<FieldElement>],
+    /// P₁'s tags on `u`
+    pub tags: &'a [Vec<FieldElement>],
+    /// Shuffled rows `v = π₂(u)`
+    pub shuffled: &'a [Vec<FieldElement>],
+}
 
 /// Online phase implementation
 pub struct OnlinePhase {
@@ -10,10 +32,16 @@ pub struct OnlinePhase {
     config: crate::ToyConfig,
     /// Finite field
     field: FiniteField,
-    /// Secret sharing scheme
-    secret_sharing: ShamirSecretSharing,
+    /// User-side masking, shared with real clients
+    client: UserClient,
     /// Field operation counter
     field_operations: usize,
+    /// Bytes sent in the online phase, by message type
+    communication: MessageBytes,
+    /// Time spent in each step
+    timings: OnlineTimings,
+    /// Receives progress events
+    observer: SharedObserver,
 }
 
 impl OnlinePhase {
@@ -21,95 +49,213 @@ impl OnlinePhase {
     pub fn new(
         config: crate::ToyConfig,
         field: FiniteField,
-        secret_sharing: ShamirSecretSharing,
     ) -> Result<Self, ProtocolError> {
+        let client = UserClient::from_config(&config)?;
+
         Ok(Self {
             config,
             field,
-            secret_sharing,
+            client,
             field_operations: 0,
+            communication: MessageBytes::default(),
+            timings: OnlineTimings::default(),
+            observer: progress::silent(),
         })
     }
 
+    /// Send progress events to `observer`
+    pub fn set_observer(&mut self, observer: SharedObserver) {
+        self.observer = observer;
+    }
+
+    /// Report that a step of the online phase finished
+    fn report(&self, percent: f64, step: &str) {
+        self.observer.progress(Phase::Online, percent, step);
+    }
+
     /// Execute online phase
     pub async fn execute(&mut self, servers: &mut HashMap<usize, Server>, user_data: Vec<UserData>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        println!("  Processing user submissions...");
-        let user_shares = self.process_user_submissions(servers, user_data).await?;
+        let user_shares = self.mask_submissions(user_data)?;
+        self.report(20.0, "Masked user submissions");
 
-        println!("  Performing silent shuffle...");
-        let shuffled_shares = self.silent_shuffle(servers, user_shares).await?;
+        self.execute_submissions(servers, user_shares).await
+    }
 
-        println!("  Performing silent randomization...");
-        let randomized_shares = self.silent_randomization(servers, shuffled_shares).await?;
+    /// Execute online phase on submissions users have already masked, in registration order
+    pub async fn execute_submissions(&mut self, servers: &mut HashMap<usize, Server>, user_shares: Vec<Vec<FieldElement>>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        // Users send their submissions to both computational servers
+        self.enter_step(servers, OnlineStep::Submission)?;
+        self.communication.record(&ToyMessage::Submissions(user_shares.clone()), 2)?;
+
+        let users = user_shares.len();
+        self.enter_step(servers, OnlineStep::Shuffle)?;
+        let shuffle_span = info_span!("shuffle", users, elapsed_ms = Empty);
+        let start = Instant::now();
+        let ShuffleOutput { blinded, shuffled: shuffled_data, tags: blinded_tags } =
+            timed(shuffle_span, self.shuffle(servers, &user_shares)).await?;
+        self.timings.shuffle += start.elapsed();
+        self.report(40.0, "Shuffled submissions");
+
+        self.enter_step(servers, OnlineStep::Randomization)?;
+        if let Some(blinded_tags) = blinded_tags {
+            let randomization_span = info_span!("randomization", users, authenticated = true, elapsed_ms = Empty);
+            let start = Instant::now();
+            let rows = TaggedRows { blinded: &blinded, tags: &blinded_tags, shuffled: &shuffled_data };
+            let shares = timed(randomization_span, self.authenticated_randomization(servers, &user_shares, rows)).await?;
+            self.timings.randomization += start.elapsed();
+            self.report(70.0, "Computed authenticated shares");
+
+            self.enter_step(servers, OnlineStep::Reconstruction)?;
+            let reconstruction_span = info_span!("reconstruction", users, authenticated = true, elapsed_ms = Empty);
+            let start = Instant::now();
+            let final_result = timed_blocking(reconstruction_span, || self.open_authenticated_results(&shares))?;
+            self.timings.reconstruction += start.elapsed();
+            self.report(100.0, "Checked MACs and reconstructed final result");
+            return Ok(final_result);
+        }
 
-        println!("  Reconstructing final result...");
-        let final_result = self.reconstruct_result(servers, randomized_shares).await?;
+        let randomization_span = info_span!("randomization", users, authenticated = false, elapsed_ms = Empty);
+        let start = Instant::now();
+        timed(randomization_span, self.silent_randomization(servers, shuffled_data)).await?;
+        self.timings.randomization += start.elapsed();
+        self.report(70.0, "Performed silent randomization");
+
+        self.enter_step(servers, OnlineStep::Reconstruction)?;
+        let reconstruction_span = info_span!("reconstruction", users, authenticated = false, elapsed_ms = Empty);
+        let start = Instant::now();
+        let final_result = timed(reconstruction_span, self.reconstruct_result(servers)).await?;
+        self.timings.reconstruction += start.elapsed();
+        self.report(100.0, "Reconstructed final result");
 
         Ok(final_result)
     }
 
-    /// Process user submissions (Step 1)
-    async fn process_user_submissions(&mut self, _servers: &mut HashMap<usize, Server>, user_data: Vec<UserData>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        let mut user_shares = Vec::with_capacity(user_data.len());
-
-        for user in user_data {
-            // User computes [x_i]_2 = x_i - a_i
-            let user_mask = self.compute_user_mask(user.user_id, user.seed);
-            let user_share = self.compute_user_share(&user.data, &user_mask)?;
-            user_shares.push(user_share);
+    /// Move both computational servers into `step`, stopping if either fails
+    fn enter_step(&self, servers: &mut HashMap<usize, Server>, step: OnlineStep) -> Result<(), ProtocolError> {
+        for server_id in 1..=2 {
+            let server = servers.get_mut(&server_id).ok_or(ProtocolError::ServerNotFound)?;
+            if !server.enter_step(step) {
+                return Err(ProtocolError::ServerFailed {
+                    server_id,
+                    step,
+                    partial: Box::new(self.stats()),
+                });
+            }
         }
 
-        Ok(user_shares)
+        Ok(())
     }
 
-    /// Silent shuffle (Step 2) - completely local computation
-    async fn silent_shuffle(&mut self, servers: &mut HashMap<usize, Server>, user_shares: Vec<Vec<FieldElement>>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        let mut shuffled_shares = Vec::with_capacity(user_shares.len());
+    /// Compute every user's masked submission `d_i = x_i - a_i`
+    pub fn mask_submissions(&mut self, user_data: Vec<UserData>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        let span = info_span!("submission", users = user_data.len(), elapsed_ms = Empty);
+        let start = Instant::now();
+        let user_shares = timed_blocking(span, || {
+            parallel::try_map(&user_data, |_, user| {
+                // User computes d_i = x_i - a_i
+                self.client.masked_submission(user)
+            })
+        })?;
+        self.timings.submission += start.elapsed();
+        self.field_operations += user_shares.iter().map(Vec::len).sum::<usize>();
 
-        // Each computational server performs local shuffle computation
-        for server_id in 1..=2 {
-            if let Some(server) = servers.get_mut(&server_id) {
-                let server_shuffled = self.compute_local_shuffle(server, &user_shares).await?;
-                
-                if server_id == 1 {
-                    // Use server 1's result as the primary shuffled data
-                    shuffled_shares = server_shuffled;
-                }
+        Ok(user_shares)
+    }
+
+    /// Shuffle (Step 2) - O(n) local work and one message from P₁ to P₂
+    ///
+    /// P₁ sends `u = π₁(d) + c` to P₂, which computes `v = π₂(u)`. Since `u` is
+    /// blinded and P₁ never sees `v`, neither server learns the composed shuffle.
+    /// In malicious mode P₁ also sends its tags on `u`.
+    async fn shuffle(&mut self, servers: &mut HashMap<usize, Server>, user_shares: &[Vec<FieldElement>]) -> Result<ShuffleOutput, ProtocolError> {
+        let first = servers.get(&1).ok_or(ProtocolError::ServerNotFound)?;
+        let blinded = self.shuffle_step(first, user_shares).await?;
+        self.communication.record(&ToyMessage::BlindedRows(blinded.clone()), 1)?;
+
+        let blinded_tags = match self.config.security {
+            SecurityMode::SemiHonest => None,
+            SecurityMode::Malicious => {
+                let tags = self.tag_blinded_rows(first, &blinded)?;
+                self.communication.record(&ToyMessage::BlindedTags(tags.clone()), 1)?;
+                Some(tags)
             }
+        };
+
+        let second = servers.get(&2).ok_or(ProtocolError::ServerNotFound)?;
+        let shuffled = self.shuffle_step(second, &blinded).await?;
+
+        Ok(ShuffleOutput { blinded, shuffled, tags: blinded_tags })
+    }
+
+    /// Apply one server's half of the shuffle to the rows it holds
+    pub async fn shuffle_step(&mut self, server: &Server, rows: &[Vec<FieldElement>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        self.apply_permutation_locally(rows, server.get_permutation(), server.get_blinding()).await
+    }
+
+    /// Compute P₁'s tags `α₁·u + m` on its blinded rows `u`, for P₂ to permute
+    ///
+    /// The masks `m` hide P₁'s key share from P₂, and P₀ dealt `−π₂(m)` into
+    /// the tag shares, so the permuted tags authenticate the rows P₂ adds.
+    pub fn tag_blinded_rows(&mut self, server: &Server, blinded: &[Vec<FieldElement>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        let mac = mac_shares(server)?;
+        if mac.tag_masks.len() != blinded.len() {
+            return Err(ProtocolError::DimensionMismatch);
         }
 
-        Ok(shuffled_shares)
+        let key_share = mac.key_share.value();
+        let tags = parallel::try_map(blinded, |i, row| {
+            let scaled = self.field.vector_scale(row, &key_share)?;
+            self.field.vector_add(&scaled, &mac.tag_masks[i]).map_err(ProtocolError::from)
+        })?;
+        self.field_operations += 2 * tags.iter().map(Vec::len).sum::<usize>();
+
+        Ok(tags)
     }
 
     /// Silent randomization (Step 3) - completely local computation
-    async fn silent_randomization(&mut self, servers: &mut HashMap<usize, Server>, shuffled_shares: Vec<Vec<FieldElement>>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        let mut randomized_shares = Vec::with_capacity(shuffled_shares.len());
+    ///
+    /// Each computational server adds its noise share to its correction share
+    /// and converts the sum to an additive share of the output; P₂ also adds
+    /// the shuffled rows it holds.
+    async fn silent_randomization(&mut self, servers: &mut HashMap<usize, Server>, shuffled_data: Vec<Vec<FieldElement>>) -> Result<(), ProtocolError> {
+        for server_id in 1..=2 {
+            let server = servers.get_mut(&server_id).ok_or(ProtocolError::ServerNotFound)?;
+            let public = if server_id == 2 { Some(shuffled_data.as_slice()) } else { None };
+            self.compute_local_randomization(server, public).await?;
+        }
+
+        Ok(())
+    }
 
-        // Each computational server performs local randomization
+    /// Silent randomization with authenticated shares, returning each server's contribution
+    async fn authenticated_randomization(
+        &mut self,
+        servers: &mut HashMap<usize, Server>,
+        user_shares: &[Vec<FieldElement>],
+        rows: TaggedRows<'_>,
+    ) -> Result<Vec<AuthenticatedShare>, ProtocolError> {
+        let mut shares = Vec::with_capacity(2);
         for server_id in 1..=2 {
-            if let Some(server) = servers.get_mut(&server_id) {
-                let server_randomized = self.compute_local_randomization(server, &shuffled_shares).await?;
-                
-                if server_id == 1 {
-                    // Use server 1's result as the primary randomized data
-                    randomized_shares = server_randomized;
-                }
-            }
+            let server = servers.get_mut(&server_id).ok_or(ProtocolError::ServerNotFound)?;
+            let public = if server_id == 2 { Some(rows) } else { None };
+            let share = self.compute_authenticated_share(server, user_shares, public).await?;
+            self.communication.record(&ToyMessage::AuthenticatedResultShare(share.clone()), 1)?;
+            shares.push(share);
         }
 
-        Ok(randomized_shares)
+        Ok(shares)
     }
 
     /// Reconstruct final result (Step 4)
-    async fn reconstruct_result(&mut self, servers: &mut HashMap<usize, Server>, _randomized_shares: Vec<Vec<FieldElement>>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+    async fn reconstruct_result(&mut self, servers: &mut HashMap<usize, Server>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
         // Collect shares from both computational servers
         let mut server_shares = Vec::new();
-        
+
         for server_id in 1..=2 {
-            if let Some(server) = servers.get(&server_id) {
-                let server_result = server.get_final_result();
-                server_shares.push(server_result);
-            }
+            let server = servers.get(&server_id).ok_or(ProtocolError::ServerNotFound)?;
+            let share = server.get_final_result();
+            self.communication.record(&ToyMessage::ResultShare(share.clone()), 1)?;
+            server_shares.push(share);
         }
 
         // Reconstruct final result by combining shares
@@ -118,189 +264,316 @@ impl OnlinePhase {
         Ok(final_result)
     }
 
-    /// Compute user mask based on seed
-    fn compute_user_mask(&self, user_id: usize, seed: u64) -> Vec<FieldElement> {
-        // Deterministic mask generation using seed
-        let mut mask = Vec::new();
-        let mut rng_seed = seed + (user_id as u64);
-        
-        for _ in 0..2 { // Assuming 2 features per user
-            rng_seed = rng_seed.wrapping_mul(1103515245).wrapping_add(12345);
-            let mask_value = rng_seed % self.field.modulus();
-            mask.push(FieldElement::new(mask_value, self.field.modulus()));
-        }
-        
-        mask
+    /// Compute a server's additive share of the output
+    ///
+    /// `shuffled_data` is the fully shuffled rows, which only the last server holds.
+    pub async fn compute_local_randomization(&mut self, server: &mut Server, shuffled_data: Option<&[Vec<FieldElement>]>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        check_computational(server.id())?;
+
+        // Add noise share to the correction share locally
+        let shares = self.add_shares_locally(server.get_mask_shares(), server.get_noise_shares()).await?;
+        let randomized = match shuffled_data {
+            Some(data) => self.add_public_rows(&shares, data)?,
+            None => shares,
+        };
+        let randomized = self.aggregate_if_histogram(randomized)?;
+
+        // Store final result in server
+        server.set_final_result(randomized.clone());
+
+        Ok(randomized)
+    }
+
+    /// Compute a server's additive share of the output along with its MAC shares
+    ///
+    /// `submissions` is the masked submissions `d` both servers received, and
+    /// `shuffled_data` is what P₂ holds after the shuffle. The rows `v` are
+    /// passed through in the clear, and the server adds its key share times
+    /// `v` and the permuted tags to its tag shares, so the tags cover them too.
+    pub async fn compute_authenticated_share(
+        &mut self,
+        server: &mut Server,
+        submissions: &[Vec<FieldElement>],
+        shuffled_data: Option<TaggedRows<'_>>,
+    ) -> Result<AuthenticatedShare, ProtocolError> {
+        check_computational(server.id())?;
+        let shuffle_check = self.shuffle_check(server, submissions, shuffled_data.map(|rows| rows.blinded))?;
+        let mac = mac_shares(server)?;
+        let mut tag: Vec<Vec<FieldElement>> = mac
+            .tag_shares
+            .iter()
+            .map(|row| row.iter().map(|share| share.value()).collect())
+            .collect();
+        let key_share = mac.key_share.value();
+
+        let share = self.add_shares_locally(server.get_mask_shares(), server.get_noise_shares()).await?;
+        let public = match shuffled_data {
+            Some(TaggedRows { tags: blinded_tags, shuffled: data, .. }) => {
+                // α₂·v + π₂(α₁·u + m) − π₂(m) = α·v
+                let permuted_tags = permute(server.get_permutation(), blinded_tags)?;
+                if data.len() != tag.len() || permuted_tags.len() != tag.len() {
+                    return Err(ProtocolError::DimensionMismatch);
+                }
+
+                tag = parallel::try_map(&tag, |i, row| {
+                    let scaled = self.field.vector_scale(&data[i], &key_share)?;
+                    let summed = self.field.vector_add(row, &scaled)?;
+                    self.field.vector_add(&summed, &permuted_tags[i]).map_err(ProtocolError::from)
+                })?;
+                self.field_operations += 3 * tag.iter().map(Vec::len).sum::<usize>();
+
+                self.aggregate_if_histogram(data.to_vec())?
+            }
+            None => Vec::new(),
+        };
+
+        // MACs are linear, so the summed tags authenticate the summed shares
+        Ok(AuthenticatedShare {
+            share: self.aggregate_if_histogram(share)?,
+            tag: self.aggregate_if_histogram(tag)?,
+            key_share: Some(key_share),
+            public,
+            shuffle_check,
+        })
     }
 
-    /// Compute user share [x_i]_2 = x_i - a_i
-    fn compute_user_share(&mut self, user_data: &[FieldElement], mask: &[FieldElement]) -> Result<Vec<FieldElement>, ProtocolError> {
-        if user_data.len() != mask.len() {
+    /// Compute a server's share of the shuffle check on P₁'s blinded rows
+    ///
+    /// P₂ applies its keys to the `blinded` rows it received and its masks to
+    /// the submissions, while P₁ applies its keys to `π₁(d)`, the rows it
+    /// should have blinded.
+    fn shuffle_check(&mut self, server: &Server, submissions: &[Vec<FieldElement>], blinded: Option<&[Vec<FieldElement>]>) -> Result<Vec<FieldElement>, ProtocolError> {
+        let mac = mac_shares(server)?;
+        let rows = match blinded {
+            Some(rows) => rows.to_vec(),
+            None => permute(server.get_permutation(), submissions)?,
+        };
+        if mac.check_keys.len() != rows.len() || (!mac.check_masks.is_empty() && mac.check_masks.len() != submissions.len()) {
             return Err(ProtocolError::DimensionMismatch);
         }
 
-        let mut share = Vec::with_capacity(user_data.len());
-        for (data, mask_val) in user_data.iter().zip(mask.iter()) {
-            let share_val = data.sub(mask_val)
-                .map_err(|_| ProtocolError::FieldOperationFailed)?;
-            share.push(share_val);
-            self.field_operations += 1;
+        let mut check = vec![self.field.zero(); self.config.num_features];
+        let weighted = rows.iter().zip(&mac.check_keys).chain(submissions.iter().zip(&mac.check_masks));
+        for (row, keys) in weighted {
+            let products = self.field.vector_mul(row, keys)?;
+            check = self.field.vector_add(&check, &products)?;
+            self.field_operations += 2 * products.len();
         }
 
-        Ok(share)
+        let shares: Vec<FieldElement> = mac.check_shares.iter().map(|share| share.value()).collect();
+        self.field_operations += shares.len();
+        self.field.vector_sub(&check, &shares).map_err(ProtocolError::from)
     }
 
-    /// Compute local shuffle for a server
-    async fn compute_local_shuffle(&mut self, server: &mut Server, user_shares: &[Vec<FieldElement>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        // Get permutation shares from server
-        let permutation_shares = server.get_permutation_shares();
-        
-        // Apply permutation locally
-        let shuffled = self.apply_permutation_locally(user_shares, permutation_shares).await?;
-        
-        Ok(shuffled)
-    }
+    /// Check the MACs on the servers' contributions and reconstruct the output
+    ///
+    /// Fails with `MacCheckFailed` if any server tampered with its shares, P₂
+    /// with the shuffled rows it adds in the clear, or P₁ with the blinded
+    /// rows it hands to P₂.
+    pub fn open_authenticated_results(&mut self, shares: &[AuthenticatedShare]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        let mut key = self.field.zero();
+        for share in shares {
+            let key_share = share.key_share.ok_or(ProtocolError::EmptyInput)?;
+            key = key.add(&key_share).map_err(|_| ProtocolError::FieldOperationFailed)?;
+        }
 
-    /// Compute local randomization for a server
-    async fn compute_local_randomization(&mut self, server: &mut Server, shuffled_shares: &[Vec<FieldElement>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        // Get noise shares from server
-        let noise_shares = server.get_noise_shares();
-        
-        // Add noise locally
-        let randomized = self.add_noise_locally(shuffled_shares, noise_shares).await?;
-        
-        // Store final result in server
-        server.set_final_result(randomized.clone());
-        
-        Ok(randomized)
+        let mut values: Vec<_> = shares.iter().map(|share| share.share.clone()).collect();
+        values.extend(shares.iter().filter(|share| !share.public.is_empty()).map(|share| share.public.clone()));
+        let tags: Vec<_> = shares.iter().map(|share| share.tag.clone()).collect();
+        let values = self.combine_server_results(&values)?;
+        let tags = self.combine_server_results(&tags)?;
+        mac::check_macs(&key, &values, &tags)?;
+        self.field_operations += values.iter().map(|row| row.len()).sum::<usize>();
+
+        let checks: Vec<_> = shares.iter().map(|share| vec![share.shuffle_check.clone()]).collect();
+        let check = self.combine_server_results(&checks)?;
+        mac::check_shuffle(values.len(), &check[0])?;
+
+        Ok(values)
     }
 
-    /// Apply permutation locally
-    async fn apply_permutation_locally(&mut self, data: &[Vec<FieldElement>], permutation_shares: &[Vec<Vec<SecretShare>>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        let n = data.len();
-        let mut shuffled = vec![vec![self.field.zero(); 2]; n]; // Assuming 2 features per user
-        
-        // Apply permutation matrix to data
-        for i in 0..n {
-            for j in 0..n {
-                // Get permutation matrix element [i][j]
-                let perm_element = self.get_permutation_element(permutation_shares, i, j)?;
-                
-                if !perm_element.is_zero() {
-                    // Apply permutation: shuffled[i] += perm_element * data[j]
-                    for k in 0..2 { // 2 features
-                        let product = perm_element.mul(&data[j][k])
-                            .map_err(|_| ProtocolError::FieldOperationFailed)?;
-                        shuffled[i][k] = shuffled[i][k].add(&product)
-                            .map_err(|_| ProtocolError::FieldOperationFailed)?;
-                        self.field_operations += 2;
-                    }
-                }
-            }
+    /// Sum a server's rows into a single histogram row in histogram mode
+    fn aggregate_if_histogram(&mut self, rows: Vec<Vec<FieldElement>>) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        if !self.config.output.is_histogram() {
+            return Ok(rows);
         }
-        
-        Ok(shuffled)
+
+        let total = histogram::aggregate(&self.field, &rows)?;
+        self.field_operations += rows.len().saturating_sub(1) * self.config.num_features;
+
+        Ok(total)
     }
 
-    /// Add noise locally
-    async fn add_noise_locally(&mut self, data: &[Vec<FieldElement>], noise_shares: &[Vec<SecretShare>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
-        let mut randomized = Vec::with_capacity(data.len());
-        
-        for (i, user_data) in data.iter().enumerate() {
-            let mut noised_user_data = Vec::with_capacity(user_data.len());
-            
-            for (j, feature) in user_data.iter().enumerate() {
-                // Get noise for this user and feature
-                let noise = self.get_noise_element(noise_shares, i, j)?;
-                
-                // Add noise to feature
-                let noised_feature = feature.add(&noise)
-                    .map_err(|_| ProtocolError::FieldOperationFailed)?;
-                noised_user_data.push(noised_feature);
-                self.field_operations += 1;
-            }
-            
-            randomized.push(noised_user_data);
+    /// Add the public rows `public` to a server's additive shares
+    fn add_public_rows(&mut self, shares: &[Vec<FieldElement>], public: &[Vec<FieldElement>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        if shares.len() != public.len() {
+            return Err(ProtocolError::DimensionMismatch);
         }
-        
-        Ok(randomized)
+
+        let summed = parallel::try_map(shares, |i, row| {
+            self.field.vector_add(row, &public[i]).map_err(ProtocolError::from)
+        })?;
+        self.field_operations += summed.iter().map(Vec::len).sum::<usize>();
+
+        Ok(summed)
     }
 
-    /// Get permutation matrix element
-    fn get_permutation_element(&self, permutation_shares: &[Vec<Vec<SecretShare>>], i: usize, j: usize) -> Result<FieldElement, ProtocolError> {
-        if i < permutation_shares.len() && j < permutation_shares[i].len() {
-            let share = &permutation_shares[i][j];
-            // For simplicity, just return the first share's value
-            // In a real implementation, you'd reconstruct the actual value
-            if !share.is_empty() {
-                Ok(share[0].value())
-            } else {
-                Ok(self.field.zero())
-            }
-        } else {
-            Ok(self.field.zero())
+    /// Apply a permutation half locally, adding the blinding if there is one
+    async fn apply_permutation_locally(&mut self, data: &[Vec<FieldElement>], permutation: &[usize], blinding: &[Vec<FieldElement>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        if data.iter().any(|row| row.len() != self.config.num_features) {
+            return Err(ProtocolError::DimensionMismatch);
+        }
+
+        let shuffled = permute(permutation, data)?;
+        if blinding.is_empty() {
+            return Ok(shuffled);
         }
+
+        if blinding.len() != shuffled.len() {
+            return Err(ProtocolError::DimensionMismatch);
+        }
+
+        let blinded = parallel::try_map(&shuffled, |i, row| {
+            self.field.vector_add(row, &blinding[i]).map_err(ProtocolError::from)
+        })?;
+        self.field_operations += blinded.iter().map(Vec::len).sum::<usize>();
+
+        Ok(blinded)
     }
 
-    /// Get noise element
-    fn get_noise_element(&self, noise_shares: &[Vec<SecretShare>], user_id: usize, feature_id: usize) -> Result<FieldElement, ProtocolError> {
-        if user_id < noise_shares.len() {
-            let share = &noise_shares[user_id];
-            if feature_id < share.len() {
-                Ok(share[feature_id].value())
-            } else {
-                Ok(self.field.zero())
-            }
-        } else {
-            Ok(self.field.zero())
+    /// Add two of a server's share vectors element-wise
+    async fn add_shares_locally(&mut self, a: &[Vec<SecretShare>], b: &[Vec<SecretShare>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        if a.len() != b.len() {
+            return Err(ProtocolError::DimensionMismatch);
         }
+
+        let summed = parallel::try_map(a, |i, row_a| {
+            let row_b = &b[i];
+            if row_a.len() != row_b.len() {
+                return Err(ProtocolError::DimensionMismatch);
+            }
+
+            row_a
+                .iter()
+                .zip(row_b.iter())
+                .map(|(share_a, share_b)| {
+                    share_a.value().add(&share_b.value())
+                        .map_err(|_| ProtocolError::FieldOperationFailed)
+                })
+                .collect()
+        })?;
+        self.field_operations += summed.iter().map(Vec::len).sum::<usize>();
+
+        Ok(summed)
     }
 
-    /// Combine server results
-    fn combine_server_results(&mut self, server_results: &[Vec<Vec<FieldElement>>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+    /// Combine server results by summing the additive shares
+    pub fn combine_server_results(&mut self, server_results: &[Vec<Vec<FieldElement>>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
         if server_results.is_empty() {
             return Err(ProtocolError::EmptyInput);
         }
 
         let n = server_results[0].len();
-        let mut combined = Vec::with_capacity(n);
-        
-        for i in 0..n {
-            let mut combined_user = Vec::new();
+        let combined = parallel::try_map_range(n, |i| {
             let feature_count = server_results[0][i].len();
-            
-            for j in 0..feature_count {
-                let mut sum = self.field.zero();
-                
-                for server_result in server_results {
-                    if i < server_result.len() && j < server_result[i].len() {
-                        sum = sum.add(&server_result[i][j])
+
+            (0..feature_count)
+                .map(|j| {
+                    let mut sum = self.field.zero();
+
+                    for server_result in server_results {
+                        let value = server_result
+                            .get(i)
+                            .and_then(|user| user.get(j))
+                            .ok_or(ProtocolError::DimensionMismatch)?;
+                        sum = sum.add(value)
                             .map_err(|_| ProtocolError::FieldOperationFailed)?;
-                        self.field_operations += 1;
                     }
-                }
-                
-                combined_user.push(sum);
-            }
-            
-            combined.push(combined_user);
-        }
-        
+
+                    Ok(sum)
+                })
+                .collect()
+        })?;
+        let values: usize = combined.iter().map(Vec::len).sum();
+        self.field_operations += values * server_results.len();
+
         Ok(combined)
     }
 
+    /// Get bytes sent in the online phase, by users and servers
+    pub fn communication_bytes(&self) -> usize {
+        self.communication.total()
+    }
+
+    /// Get bytes sent in the online phase, by message type
+    pub fn communication(&self) -> &MessageBytes {
+        &self.communication
+    }
+
     /// Get field operation count
     pub fn field_operations(&self) -> usize {
         self.field_operations
     }
+
+    /// Get the time spent in each step, the field operations and the bytes sent so far
+    pub fn stats(&self) -> OnlineStats {
+        OnlineStats {
+            submission_time_ms: self.timings.submission.as_millis() as u64,
+            shuffle_time_ms: self.timings.shuffle.as_millis() as u64,
+            randomization_time_ms: self.timings.randomization.as_millis() as u64,
+            reconstruction_time_ms: self.timings.reconstruction.as_millis() as u64,
+            field_operations: self.field_operations,
+            communication_bytes: self.communication.total(),
+            communication: self.communication.clone(),
+        }
+    }
+}
+
+/// Time spent in each online step, accumulated across calls
+#[derive(Debug, Clone, Copy, Default)]
+struct OnlineTimings {
+    /// Masking user submissions
+    submission: Duration,
+    /// Shuffling
+    shuffle: Duration,
+    /// Silent randomization
+    randomization: Duration,
+    /// Reconstruction
+    reconstruction: Duration,
+}
+
+/// Rows produced by the shuffle
+struct ShuffleOutput {
+    /// Blinded rows `u` P₁ hands to P₂
+    blinded: Vec<Vec<FieldElement>>,
+    /// Shuffled rows `v = π₂(u)`
+    shuffled: Vec<Vec<FieldElement>>,
+    /// P₁'s tags on `u` (malicious mode only)
+    tags: Option<Vec<Vec<FieldElement>>>,
+}
+
+/// Check that `server_id` is one of the two computational servers holding additive shares
+fn check_computational(server_id: usize) -> Result<(), ProtocolError> {
+    if (1..=2).contains(&server_id) {
+        Ok(())
+    } else {
+        Err(ProtocolError::ServerNotFound)
+    }
+}
+
+/// MAC material of a computational server, which malicious mode requires
+fn mac_shares(server: &Server) -> Result<&MacShares, ProtocolError> {
+    server.get_mac_shares().ok_or_else(|| {
+        ProtocolError::invalid_configuration(format!("Server {} has no MAC shares", server.id()))
+    })
 }
 
 /// Online phase statistics
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct OnlineStats {
     /// Time taken for user submission processing (ms)
     pub submission_time_ms: u64,
-    /// Time taken for silent shuffle (ms)
+    /// Time taken for shuffle (ms)
     pub shuffle_time_ms: u64,
     /// Time taken for silent randomization (ms)
     pub randomization_time_ms: u64,
@@ -308,34 +581,64 @@ pub struct OnlineStats {
     pub reconstruction_time_ms: u64,
     /// Number of field operations
     pub field_operations: usize,
-    /// Communication bytes (should be 0 for online phase)
+    /// Bytes sent in the online phase
     pub communication_bytes: usize,
+    /// Bytes sent in the online phase, by message type
+    #[serde(default)]
+    pub communication: MessageBytes,
 }
 
-impl Default for OnlineStats {
-    fn default() -> Self {
-        Self {
-            submission_time_ms: 0,
-            shuffle_time_ms: 0,
-            randomization_time_ms: 0,
-            reconstruction_time_ms: 0,
-            field_operations: 0,
-            communication_bytes: 0,
-        }
+impl OnlineStats {
+    /// Add the statistics of another run
+    pub fn merge(&mut self, other: &OnlineStats) {
+        self.submission_time_ms += other.submission_time_ms;
+        self.shuffle_time_ms += other.shuffle_time_ms;
+        self.randomization_time_ms += other.randomization_time_ms;
+        self.reconstruction_time_ms += other.reconstruction_time_ms;
+        self.field_operations += other.field_operations;
+        self.communication_bytes += other.communication_bytes;
+        self.communication.merge(&other.communication);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::message::message_size;
+    use crate::offline_phase::{OfflinePhase, UserRegistration};
+    use crate::secret_sharing::AdditiveSecretSharing;
+    use crate::server::ServerRole;
+
+    /// Deal offline material to P₀, P₁ and P₂ for `config.num_users` users, returning the servers and the users' data
+    async fn dealt_servers(config: &crate::ToyConfig) -> (HashMap<usize, Server>, Vec<UserData>) {
+        let field = FiniteField::new(config.field_modulus).unwrap();
+        let secret_sharing = AdditiveSecretSharing::new(config.field_modulus).unwrap();
+
+        let user_data: Vec<UserData> = (0..config.num_users)
+            .map(|i| UserData::new(i, vec![field.element(i as u64), field.element(10 + i as u64)], [i as u8; 32]))
+            .collect();
+        let registrations: Vec<UserRegistration> = user_data
+            .iter()
+            .map(|user| UserRegistration::new(user.user_id, user.seed))
+            .collect();
+
+        let mut servers = HashMap::new();
+        servers.insert(0, Server::new(0, ServerRole::Auxiliary, config.clone()));
+        servers.insert(1, Server::new(1, ServerRole::Computational, config.clone()));
+        servers.insert(2, Server::new(2, ServerRole::Computational, config.clone()));
+
+        let mut offline_phase = OfflinePhase::new(config.clone(), field, secret_sharing).unwrap();
+        offline_phase.execute(&mut servers, &registrations).await.unwrap();
+
+        (servers, user_data)
+    }
 
     #[tokio::test]
     async fn test_online_phase_creation() {
         let config = crate::ToyConfig::default();
         let field = FiniteField::new(config.field_modulus).unwrap();
-        let secret_sharing = ShamirSecretSharing::new(2, 3, config.field_modulus).unwrap();
         
-        let online_phase = OnlinePhase::new(config, field, secret_sharing);
+        let online_phase = OnlinePhase::new(config, field);
         assert!(online_phase.is_ok());
     }
 
@@ -343,21 +646,16 @@ mod tests {
     async fn test_user_mask_computation() {
         let config = crate::ToyConfig::default();
         let field = FiniteField::new(config.field_modulus).unwrap();
-        let secret_sharing = ShamirSecretSharing::new(2, 3, config.field_modulus).unwrap();
         
-        let online_phase = OnlinePhase::new(config, field, secret_sharing).unwrap();
+        let online_phase = OnlinePhase::new(config, field).unwrap();
         
-        let mask = online_phase.compute_user_mask(1, 12345);
+        let mask = online_phase.client.mask(1, &[45; 32]);
         assert_eq!(mask.len(), 2);
     }
 
     #[tokio::test]
     async fn test_user_share_computation() {
         let config = crate::ToyConfig::default();
-        let field = FiniteField::new(config.field_modulus).unwrap();
-        let secret_sharing = ShamirSecretSharing::new(2, 3, config.field_modulus).unwrap();
-        
-        let online_phase = OnlinePhase::new(config, field, secret_sharing).unwrap();
         
         let user_data = vec![
             FieldElement::new(10, config.field_modulus),
@@ -368,7 +666,145 @@ mod tests {
             FieldElement::new(7, config.field_modulus),
         ];
         
-        let share = online_phase.compute_user_share(&user_data, &mask).unwrap();
+        let share = crate::user::mask_record(&user_data, &mask).unwrap();
         assert_eq!(share.len(), 2);
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_shuffle_with_permutation_halves() {
+        let config = crate::ToyConfig { num_users: 6, ..Default::default() };
+        let field = FiniteField::new(config.field_modulus).unwrap();
+        let secret_sharing = AdditiveSecretSharing::new(config.field_modulus).unwrap();
+        let (mut servers, user_data) = dealt_servers(&config).await;
+
+        let mut online_phase = OnlinePhase::new(config, field.clone()).unwrap();
+        let result = online_phase.execute(&mut servers, user_data.clone()).await.unwrap();
+
+        // Expected output is π₂(π₁(x)) + r, reconstructing r from both servers' shares
+        let noise: Vec<Vec<FieldElement>> = servers[&1]
+            .get_noise_shares()
+            .iter()
+            .zip(servers[&2].get_noise_shares())
+            .map(|(row_1, row_2)| {
+                row_1.iter()
+                    .zip(row_2.iter())
+                    .map(|(x, y)| secret_sharing.reconstruct_secret(&[x.clone(), y.clone()]).unwrap())
+                    .collect()
+            })
+            .collect();
+        let data: Vec<Vec<FieldElement>> = user_data.iter().map(|user| user.data.clone()).collect();
+        let shuffled = permute(servers[&2].get_permutation(), &permute(servers[&1].get_permutation(), &data).unwrap()).unwrap();
+
+        for (i, row) in result.iter().enumerate() {
+            for (feature, value) in row.iter().enumerate() {
+                let expected = shuffled[i][feature].add(&noise[i][feature]).unwrap();
+                assert_eq!(*value, expected);
+            }
+        }
+
+        // Users send their 6×2 masked submissions to both servers, P₁ sends its
+        // blinded rows to P₂, and both servers send their result shares
+        let submissions = online_phase.mask_submissions(user_data).unwrap();
+        assert_eq!((submissions.len(), submissions[0].len()), (6, 2));
+        let blinded = online_phase.shuffle_step(&servers[&1], &submissions).await.unwrap();
+        let expected = 2 * message_size(&ToyMessage::Submissions(submissions)).unwrap()
+            + message_size(&ToyMessage::BlindedRows(blinded)).unwrap()
+            + message_size(&ToyMessage::ResultShare(servers[&1].get_final_result())).unwrap()
+            + message_size(&ToyMessage::ResultShare(servers[&2].get_final_result())).unwrap();
+        assert_eq!(online_phase.communication_bytes(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_malicious_mode_detects_tampered_share() {
+        let config = crate::ToyConfig { num_users: 4, security: SecurityMode::Malicious, ..Default::default() };
+        let field = FiniteField::new(config.field_modulus).unwrap();
+
+        for tamper in [false, true] {
+            let (mut servers, user_data) = dealt_servers(&config).await;
+            assert!(servers[&1].get_mac_shares().is_some());
+
+            if tamper {
+                // P₂ shifts one of its noise shares
+                let share = &mut servers.get_mut(&2).unwrap().noise_shares[3][1];
+                share.value = share.value.add(&field.one()).unwrap();
+            }
+
+            let mut online_phase = OnlinePhase::new(config.clone(), field.clone()).unwrap();
+            let result = online_phase.execute(&mut servers, user_data).await;
+            if tamper {
+                assert!(matches!(result, Err(ProtocolError::MacCheckFailed { row: 3, feature: 1 })));
+            } else {
+                assert_eq!(result.unwrap().len(), 4);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malicious_mode_detects_tampered_shuffled_rows() {
+        let config = crate::ToyConfig { num_users: 4, security: SecurityMode::Malicious, ..Default::default() };
+        let field = FiniteField::new(config.field_modulus).unwrap();
+        let (mut servers, user_data) = dealt_servers(&config).await;
+
+        // Run the shuffle by hand to get at P₂'s contribution
+        let mut online_phase = OnlinePhase::new(config.clone(), field.clone()).unwrap();
+        let submissions = online_phase.mask_submissions(user_data).unwrap();
+        let blinded = online_phase.shuffle_step(&servers[&1], &submissions).await.unwrap();
+        let blinded_tags = online_phase.tag_blinded_rows(&servers[&1], &blinded).unwrap();
+        let shuffled = online_phase.shuffle_step(&servers[&2], &blinded).await.unwrap();
+
+        let rows = TaggedRows { blinded: &blinded, tags: &blinded_tags, shuffled: &shuffled };
+        let first = online_phase.compute_authenticated_share(servers.get_mut(&1).unwrap(), &submissions, None).await.unwrap();
+        let second = online_phase
+            .compute_authenticated_share(servers.get_mut(&2).unwrap(), &submissions, Some(rows))
+            .await
+            .unwrap();
+        let shares = vec![first, second];
+
+        assert_eq!(online_phase.open_authenticated_results(&shares).unwrap().len(), 4);
+
+        // P₂ shifts one of the shuffled rows it adds in the clear
+        let mut tampered = shares.clone();
+        tampered[1].public[2][0] = tampered[1].public[2][0].add(&field.one()).unwrap();
+        assert!(matches!(
+            online_phase.open_authenticated_results(&tampered),
+            Err(ProtocolError::MacCheckFailed { row: 2, feature: 0 })
+        ));
+
+        // Shifting its tag by its own key share is not enough without P₁'s
+        let key_share = servers[&2].get_mac_shares().unwrap().key_share.value();
+        tampered[1].tag[2][0] = tampered[1].tag[2][0].add(&key_share).unwrap();
+        assert!(matches!(
+            online_phase.open_authenticated_results(&tampered),
+            Err(ProtocolError::MacCheckFailed { row: 2, feature: 0 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_malicious_mode_detects_tampered_blinded_rows() {
+        let config = crate::ToyConfig { num_users: 4, security: SecurityMode::Malicious, ..Default::default() };
+        let field = FiniteField::new(config.field_modulus).unwrap();
+        let (mut servers, user_data) = dealt_servers(&config).await;
+
+        // P₁ shifts one of its blinded rows before tagging them, so its tags
+        // still match what P₂ receives
+        let mut online_phase = OnlinePhase::new(config.clone(), field.clone()).unwrap();
+        let submissions = online_phase.mask_submissions(user_data).unwrap();
+        let mut blinded = online_phase.shuffle_step(&servers[&1], &submissions).await.unwrap();
+        blinded[0][0] = blinded[0][0].add(&field.element(1000)).unwrap();
+        let blinded_tags = online_phase.tag_blinded_rows(&servers[&1], &blinded).unwrap();
+        let shuffled = online_phase.shuffle_step(&servers[&2], &blinded).await.unwrap();
+
+        let rows = TaggedRows { blinded: &blinded, tags: &blinded_tags, shuffled: &shuffled };
+        let first = online_phase.compute_authenticated_share(servers.get_mut(&1).unwrap(), &submissions, None).await.unwrap();
+        let second = online_phase
+            .compute_authenticated_share(servers.get_mut(&2).unwrap(), &submissions, Some(rows))
+            .await
+            .unwrap();
+
+        // The tags pass, but the shuffle check after the last row does not
+        assert!(matches!(
+            online_phase.open_authenticated_results(&[first, second]),
+            Err(ProtocolError::MacCheckFailed { row: 4, feature: 0 })
+        ));
+    }
+}
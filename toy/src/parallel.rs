@@ -0,0 +1,62 @@
+use crate::ProtocolError;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Map `f` over `items` with their index, stopping at the first error
+///
+/// Runs on the rayon thread pool with the `parallel` feature and sequentially
+/// otherwise; the output order is the same either way.
+pub fn try_map<T, U, F>(items: &[T], f: F) -> Result<Vec<U>, ProtocolError>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(usize, &T) -> Result<U, ProtocolError> + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    {
+        items.par_iter().enumerate().map(|(i, item)| f(i, item)).collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.iter().enumerate().map(|(i, item)| f(i, item)).collect()
+    }
+}
+
+/// Map `f` over the indices `0..len`, stopping at the first error
+pub fn try_map_range<U, F>(len: usize, f: F) -> Result<Vec<U>, ProtocolError>
+where
+    U: Send,
+    F: Fn(usize) -> Result<U, ProtocolError> + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    {
+        (0..len).into_par_iter().map(f).collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        (0..len).map(f).collect()
+    }
+}
+
+/// Whether the helpers run in parallel
+pub fn is_parallel() -> bool {
+    cfg!(feature = "parallel")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_map_keeps_order_and_errors() {
+        let items: Vec<u64> = (0..1000).collect();
+        let doubled = try_map(&items, |i, &x| Ok((i as u64, x * 2))).unwrap();
+        assert!(doubled.iter().enumerate().all(|(i, &(index, x))| index == i as u64 && x == 2 * i as u64));
+
+        let failed = try_map_range(1000, |i| if i == 617 { Err(ProtocolError::DimensionMismatch) } else { Ok(i) });
+        assert!(matches!(failed, Err(ProtocolError::DimensionMismatch)));
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{Instrument, Span};
+
+/// Phase of a toy protocol run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// P₀ deals and distributes correlated randomness
+    Offline,
+    /// Users submit and the computational servers shuffle, randomize and reconstruct
+    Online,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Offline => write!(f, "offline"),
+            Phase::Online => write!(f, "online"),
+        }
+    }
+}
+
+/// Receives progress events from a protocol run
+///
+/// Every method has an empty default, so observers only implement the events
+/// they care about. The offline phase may report from a background task.
+pub trait ProgressObserver: Send + Sync {
+    /// A phase has started
+    fn phase_started(&self, _phase: Phase) {}
+
+    /// A step within `phase` has finished, leaving the phase `percent` (0–100) done
+    fn progress(&self, _phase: Phase, _percent: f64, _step: &str) {}
+
+    /// A phase has finished after `elapsed`
+    fn phase_completed(&self, _phase: Phase, _elapsed: Duration) {}
+
+    /// A batch of `users` users is about to run (batched execution only)
+    fn batch_started(&self, _batch: usize, _users: usize) {}
+}
+
+/// Observer that ignores every event
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SilentObserver;
+
+impl ProgressObserver for SilentObserver {}
+
+/// Observer that prints progress to standard output
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleObserver;
+
+impl ProgressObserver for ConsoleObserver {
+    fn phase_started(&self, phase: Phase) {
+        println!("Starting {} phase...", phase);
+    }
+
+    fn progress(&self, _phase: Phase, percent: f64, step: &str) {
+        println!("  ✓ {} ({:.0}%)", step, percent);
+    }
+
+    fn phase_completed(&self, phase: Phase, elapsed: Duration) {
+        let name = match phase {
+            Phase::Offline => "Offline",
+            Phase::Online => "Online",
+        };
+        println!("✓ {} phase completed in {}ms", name, elapsed.as_millis());
+    }
+
+    fn batch_started(&self, batch: usize, users: usize) {
+        println!("Processing batch {} ({} users)...", batch, users);
+    }
+}
+
+/// Shared handle to an observer
+pub type SharedObserver = Arc<dyn ProgressObserver>;
+
+/// Observer used when none is set
+pub fn silent() -> SharedObserver {
+    Arc::new(SilentObserver)
+}
+
+/// Run `step` inside `span`, recording its duration in the span's `elapsed_ms` field
+pub(crate) async fn timed<T>(span: Span, step: impl Future<Output = T>) -> T {
+    let start = Instant::now();
+    let output = step.instrument(span.clone()).await;
+    span.record("elapsed_ms", start.elapsed().as_millis() as u64);
+    output
+}
+
+/// Run `step` inside `span` synchronously, recording its duration in the span's `elapsed_ms` field
+pub(crate) fn timed_blocking<T>(span: Span, step: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let output = span.in_scope(step);
+    span.record("elapsed_ms", start.elapsed().as_millis() as u64);
+    output
+}
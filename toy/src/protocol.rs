@@ -1,3 +1,5 @@
+use crate::fault::OnlineStep;
+use crate::online_phase::OnlineStats;
 use thiserror::Error;
 
 /// Protocol configuration
@@ -47,6 +49,42 @@ pub enum ProtocolError {
 
     #[error("Internal error: {message}")]
     InternalError { message: String },
+
+    #[error("Storage error: {message}")]
+    StorageError { message: String },
+
+    #[error("User {user_id} is not registered")]
+    UnregisteredUser { user_id: usize },
+
+    #[error("User {user_id} already submitted")]
+    DuplicateSubmission { user_id: usize },
+
+    #[error("{missing} registered users have not submitted")]
+    MissingSubmissions { missing: usize },
+
+    #[error("MAC check failed for row {row}, feature {feature}")]
+    MacCheckFailed { row: usize, feature: usize },
+
+    #[error("Output differs from the plaintext simulation at row {row}, feature {feature}")]
+    VerificationFailed { row: usize, feature: usize },
+
+    #[error("User {user_id} submitted {actual} features, expected {expected}")]
+    FeatureCountMismatch { user_id: usize, expected: usize, actual: usize },
+
+    #[error("User {user_id} feature {feature} is not an element of the configured field")]
+    InvalidFieldElement { user_id: usize, feature: usize },
+
+    #[error("User {user_id} feature {feature} is {value}, outside [{min}, {max}]")]
+    ValueOutOfRange { user_id: usize, feature: usize, value: f64, min: f64, max: f64 },
+
+    #[error("User {user_id} did not submit a one-hot bucket vector")]
+    NotOneHot { user_id: usize },
+
+    #[error("Protocol run was cancelled")]
+    Cancelled,
+
+    #[error("Server {server_id} failed during {step}")]
+    ServerFailed { server_id: usize, step: OnlineStep, partial: Box<OnlineStats> },
 }
 
 impl From<crate::finite_field::FieldError> for ProtocolError {
@@ -80,4 +118,11 @@ impl ProtocolError {
             message: message.into(),
         }
     }
+
+    /// Create a storage error
+    pub fn storage_error(message: impl Into<String>) -> Self {
+        Self::StorageError {
+            message: message.into(),
+        }
+    }
 } 
\ No newline at end of file
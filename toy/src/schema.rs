@@ -0,0 +1,165 @@
+use crate::finite_field::FixedPoint;
+use crate::histogram;
+use crate::{ProtocolError, ToyConfig, UserData};
+use serde::{Deserialize, Serialize};
+
+/// Range of accepted values for one feature, in data units
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FeatureRange {
+    /// Smallest accepted value
+    pub min: f64,
+    /// Largest accepted value
+    pub max: f64,
+}
+
+impl FeatureRange {
+    /// Create a new feature range
+    pub fn new(min: f64, max: f64) -> Self {
+        Self { min, max }
+    }
+
+    /// Check if no value lies in the range
+    pub fn is_empty(&self) -> bool {
+        self.min.is_nan() || self.max.is_nan() || self.min > self.max
+    }
+
+    /// Check if `value` lies in the range
+    pub fn contains(&self, value: f64) -> bool {
+        self.min <= value && value <= self.max
+    }
+}
+
+/// Declared shape of every user record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataSchema {
+    /// Accepted range of each feature, in order
+    pub features: Vec<FeatureRange>,
+}
+
+impl DataSchema {
+    /// Create a schema from per-feature ranges
+    pub fn new(features: Vec<FeatureRange>) -> Self {
+        Self { features }
+    }
+
+    /// Create a schema with `num_features` features sharing one range
+    pub fn uniform(num_features: usize, min: f64, max: f64) -> Self {
+        Self::new(vec![FeatureRange::new(min, max); num_features])
+    }
+
+    /// Number of features
+    pub fn num_features(&self) -> usize {
+        self.features.len()
+    }
+
+    /// Check the schema is consistent with `config`
+    pub fn check(&self, config: &ToyConfig) -> Result<(), ProtocolError> {
+        if self.num_features() != config.num_features {
+            return Err(ProtocolError::invalid_configuration(format!(
+                "Schema declares {} features but the configuration has {}",
+                self.num_features(),
+                config.num_features
+            )));
+        }
+
+        if let Some(feature) = self.features.iter().position(FeatureRange::is_empty) {
+            return Err(ProtocolError::invalid_configuration(format!(
+                "Schema range for feature {} is empty",
+                feature
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Validate a user record before it is masked
+///
+/// Every record must have `num_features` canonical elements of the configured
+/// field; in histogram mode it must be one-hot, and with a schema each decoded
+/// value must also lie in its feature's range.
+pub fn validate_user(config: &ToyConfig, codec: &FixedPoint, user: &UserData) -> Result<(), ProtocolError> {
+    let user_id = user.user_id;
+    if user.len() != config.num_features {
+        return Err(ProtocolError::FeatureCountMismatch {
+            user_id,
+            expected: config.num_features,
+            actual: user.len(),
+        });
+    }
+
+    if let Some(feature) = user
+        .data
+        .iter()
+        .position(|value| value.modulus() != config.field_modulus || value.value() >= config.field_modulus)
+    {
+        return Err(ProtocolError::InvalidFieldElement { user_id, feature });
+    }
+
+    if config.output.is_histogram() {
+        histogram::validate_one_hot(codec, user)?;
+    }
+
+    let Some(schema) = &config.schema else {
+        return Ok(());
+    };
+
+    for (feature, (value, range)) in user.data.iter().zip(schema.features.iter()).enumerate() {
+        let value = codec.decode(value);
+        if !range.contains(value) {
+            return Err(ProtocolError::ValueOutOfRange {
+                user_id,
+                feature,
+                value,
+                min: range.min,
+                max: range.max,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finite_field::FieldElement;
+
+    #[test]
+    fn test_validate_user_rejects_malformed_records() {
+        let config = ToyConfig {
+            schema: Some(DataSchema::new(vec![FeatureRange::new(0.0, 100.0), FeatureRange::new(-1.0, 1.0)])),
+            ..Default::default()
+        };
+        let codec = FixedPoint::new(config.field_modulus, config.fractional_bits).unwrap();
+
+        let valid = UserData::from_values(0, &[42.0, -0.5], [0; 32], &codec).unwrap();
+        assert!(validate_user(&config, &codec, &valid).is_ok());
+
+        let out_of_range = UserData::from_values(1, &[42.0, 3.0], [0; 32], &codec).unwrap();
+        assert!(matches!(
+            validate_user(&config, &codec, &out_of_range),
+            Err(ProtocolError::ValueOutOfRange { user_id: 1, feature: 1, .. })
+        ));
+
+        let short = UserData::from_values(2, &[42.0], [0; 32], &codec).unwrap();
+        assert!(matches!(
+            validate_user(&config, &codec, &short),
+            Err(ProtocolError::FeatureCountMismatch { user_id: 2, expected: 2, actual: 1 })
+        ));
+
+        let wrong_field = UserData::new(3, vec![FieldElement::new(1, 97), FieldElement::new(1, 97)], [0; 32]);
+        assert!(matches!(
+            validate_user(&config, &codec, &wrong_field),
+            Err(ProtocolError::InvalidFieldElement { user_id: 3, feature: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_schema_must_match_configuration() {
+        let config = ToyConfig::default();
+        assert!(DataSchema::uniform(2, 0.0, 1.0).check(&config).is_ok());
+        assert!(DataSchema::uniform(3, 0.0, 1.0).check(&config).is_err());
+        assert!(DataSchema::uniform(2, 1.0, 0.0).check(&config).is_err());
+    }
+}
@@ -2,6 +2,13 @@ use crate::finite_field::{FieldElement, FiniteField, FieldError};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Smallest number of secrets `reconstruct_many` hands to one rayon task
+#[cfg(feature = "parallel")]
+const RECONSTRUCT_CHUNK: usize = 4096;
+
 /// Secret share structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecretShare {
@@ -98,35 +105,39 @@ impl ShamirSecretSharing {
             return Err(FieldError::DimensionMismatch);
         }
 
-        // Use Lagrange interpolation to reconstruct the secret
+        // Use Lagrange interpolation at zero over the shares' evaluation points
+        let points: Vec<FieldElement> = shares.iter().map(|share| share.point()).collect();
+        let coefficients = self.lagrange_coefficients(&points)?;
+
         let mut secret = self.field.zero();
-        let n = shares.len() as u64;
+        for (share, coefficient) in shares.iter().zip(coefficients.iter()) {
+            let contribution = share.value().mul(coefficient)?;
+            secret = secret.add(&contribution)?;
+        }
+
+        Ok(secret)
+    }
+
+    /// Lagrange coefficients for interpolating at zero from the given points
+    pub fn lagrange_coefficients(&self, points: &[FieldElement]) -> Result<Vec<FieldElement>, FieldError> {
+        let mut coefficients = Vec::with_capacity(points.len());
 
-        for (i, share) in shares.iter().enumerate() {
+        for (i, x_i) in points.iter().enumerate() {
             let mut numerator = self.field.one();
             let mut denominator = self.field.one();
 
-            for (j, other_share) in shares.iter().enumerate() {
+            for (j, x_j) in points.iter().enumerate() {
                 if i != j {
-                    // numerator *= (n - j)
-                    let n_minus_j = self.field.element(n - (j as u64 + 1));
-                    numerator = numerator.mul(&n_minus_j)?;
-
-                    // denominator *= (i - j)
-                    let i_minus_j = self.field.element((i as u64 + 1) - (j as u64 + 1));
-                    denominator = denominator.mul(&i_minus_j)?;
+                    // numerator *= x_j, denominator *= (x_j - x_i)
+                    numerator = numerator.mul(x_j)?;
+                    denominator = denominator.mul(&x_j.sub(x_i)?)?;
                 }
             }
 
-            // Compute Lagrange coefficient
-            let lagrange_coeff = numerator.div(&denominator)?;
-            
-            // Add contribution to secret
-            let contribution = share.value().mul(&lagrange_coeff)?;
-            secret = secret.add(&contribution)?;
+            coefficients.push(numerator.div(&denominator)?);
         }
 
-        Ok(secret)
+        Ok(coefficients)
     }
 
     /// Evaluate polynomial at a given point
@@ -161,14 +172,47 @@ impl ShamirSecretSharing {
 
     /// Reconstruct a vector of secrets
     pub fn reconstruct_vector(&self, shares: &[Vec<SecretShare>]) -> Result<Vec<FieldElement>, FieldError> {
-        let mut secrets = Vec::with_capacity(shares.len());
+        self.reconstruct_many(shares)
+    }
 
-        for share_group in shares {
-            let secret = self.reconstruct_secret(share_group)?;
-            secrets.push(secret);
+    /// Reconstruct many secrets, each from its own shares
+    ///
+    /// The Lagrange coefficients are computed once from the evaluation points
+    /// of the first secret's shares, and with the `parallel` feature the secrets
+    /// are reconstructed in chunks on the rayon thread pool. Secrets whose
+    /// shares sit at other points are interpolated on their own.
+    pub fn reconstruct_many(&self, shares: &[Vec<SecretShare>]) -> Result<Vec<FieldElement>, FieldError> {
+        let Some(first) = shares.first() else {
+            return Ok(Vec::new());
+        };
+        if first.len() < self.threshold {
+            return Err(FieldError::DimensionMismatch);
         }
 
-        Ok(secrets)
+        let points: Vec<FieldElement> = first.iter().map(|share| share.point()).collect();
+        let coefficients = self.lagrange_coefficients(&points)?;
+        let reconstruct = |share_group: &Vec<SecretShare>| {
+            let same_points = share_group.len() == points.len()
+                && share_group.iter().zip(&points).all(|(share, point)| share.point() == *point);
+            if !same_points {
+                return self.reconstruct_secret(share_group);
+            }
+
+            share_group
+                .iter()
+                .zip(&coefficients)
+                .try_fold(self.field.zero(), |secret, (share, coefficient)| secret.add(&share.value().mul(coefficient)?))
+        };
+
+        #[cfg(feature = "parallel")]
+        {
+            shares.par_iter().with_min_len(RECONSTRUCT_CHUNK).map(reconstruct).collect()
+        }
+
+        #[cfg(not(feature = "parallel"))]
+        {
+            shares.iter().map(reconstruct).collect()
+        }
     }
 
     /// Share a matrix of secrets
@@ -240,6 +284,110 @@ impl ShamirSecretSharing {
     }
 }
 
+/// Additive 2-of-2 sharing between the two computational servers
+///
+/// A secret `x` is split into `x₁ + x₂` with `x₁` uniform, so each share on
+/// its own is independent of `x`. Share `i` belongs to server `i + 1`.
+#[derive(Clone)]
+pub struct AdditiveSecretSharing {
+    /// Finite field
+    pub field: FiniteField,
+}
+
+impl AdditiveSecretSharing {
+    /// Number of shares of every secret
+    pub const NUM_SHARES: usize = 2;
+
+    /// Create a new additive sharing scheme
+    pub fn new(modulus: u64) -> Result<Self, FieldError> {
+        Ok(Self {
+            field: FiniteField::new(modulus)?,
+        })
+    }
+
+    /// Share a secret value
+    pub fn share_secret(&self, secret: FieldElement) -> Result<Vec<SecretShare>, FieldError> {
+        self.share_secret_with(&mut rand::thread_rng(), secret)
+    }
+
+    /// Share a secret value, drawing the first share from `rng`
+    pub fn share_secret_with<R: rand::Rng + ?Sized>(&self, rng: &mut R, secret: FieldElement) -> Result<Vec<SecretShare>, FieldError> {
+        if secret.modulus() != self.field.modulus() {
+            return Err(FieldError::ModulusMismatch);
+        }
+
+        let first = self.field.random_element_with(rng);
+        let second = secret.sub(&first)?;
+
+        Ok(vec![
+            SecretShare::new(0, first, self.field.element(1)),
+            SecretShare::new(1, second, self.field.element(2)),
+        ])
+    }
+
+    /// Reconstruct a secret by adding both shares
+    pub fn reconstruct_secret(&self, shares: &[SecretShare]) -> Result<FieldElement, FieldError> {
+        if shares.len() != Self::NUM_SHARES {
+            return Err(FieldError::DimensionMismatch);
+        }
+
+        shares[0].value().add(&shares[1].value())
+    }
+
+    /// Share a vector of secrets
+    pub fn share_vector(&self, secrets: &[FieldElement]) -> Result<Vec<Vec<SecretShare>>, FieldError> {
+        self.share_vector_with(&mut rand::thread_rng(), secrets)
+    }
+
+    /// Share a vector of secrets, drawing from `rng`
+    pub fn share_vector_with<R: rand::Rng + ?Sized>(&self, rng: &mut R, secrets: &[FieldElement]) -> Result<Vec<Vec<SecretShare>>, FieldError> {
+        secrets.iter().map(|secret| self.share_secret_with(rng, *secret)).collect()
+    }
+
+    /// Reconstruct a vector of secrets
+    pub fn reconstruct_vector(&self, shares: &[Vec<SecretShare>]) -> Result<Vec<FieldElement>, FieldError> {
+        shares.iter().map(|share_group| self.reconstruct_secret(share_group)).collect()
+    }
+
+    /// Share a matrix of secrets
+    pub fn share_matrix(&self, matrix: &[Vec<FieldElement>]) -> Result<Vec<Vec<Vec<SecretShare>>>, FieldError> {
+        self.share_matrix_with(&mut rand::thread_rng(), matrix)
+    }
+
+    /// Share a matrix of secrets, drawing from `rng`
+    pub fn share_matrix_with<R: rand::Rng + ?Sized>(&self, rng: &mut R, matrix: &[Vec<FieldElement>]) -> Result<Vec<Vec<Vec<SecretShare>>>, FieldError> {
+        matrix.iter().map(|row| self.share_vector_with(rng, row)).collect()
+    }
+
+    /// Reconstruct a matrix of secrets
+    pub fn reconstruct_matrix(&self, shares: &[Vec<Vec<SecretShare>>]) -> Result<Vec<Vec<FieldElement>>, FieldError> {
+        shares.iter().map(|row_shares| self.reconstruct_vector(row_shares)).collect()
+    }
+
+    /// Add two shared values
+    pub fn add_shares(&self, a: &[SecretShare], b: &[SecretShare]) -> Result<Vec<SecretShare>, FieldError> {
+        if a.len() != b.len() {
+            return Err(FieldError::DimensionMismatch);
+        }
+
+        a.iter()
+            .zip(b.iter())
+            .map(|(share_a, share_b)| {
+                if share_a.id() != share_b.id() {
+                    return Err(FieldError::DimensionMismatch);
+                }
+                let sum = share_a.value().add(&share_b.value())?;
+                Ok(SecretShare::new(share_a.id(), sum, share_a.point()))
+            })
+            .collect()
+    }
+
+    /// Get field
+    pub fn field(&self) -> &FiniteField {
+        &self.field
+    }
+}
+
 /// Share distribution for multiple servers
 pub struct ShareDistributor {
     /// Secret sharing scheme
@@ -323,6 +471,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_reconstruct_many() {
+        let shamir = ShamirSecretSharing::new(3, 5, 101).unwrap();
+        let secrets: Vec<FieldElement> = (0..100).map(|i| FieldElement::new(i, 101)).collect();
+        let mut shares: Vec<Vec<SecretShare>> = secrets
+            .iter()
+            .map(|secret| shamir.share_secret(*secret).unwrap()[1..4].to_vec())
+            .collect();
+        // Shares at other evaluation points fall back to interpolating alone
+        shares[7] = shamir.share_secret(secrets[7]).unwrap()[2..].to_vec();
+
+        assert_eq!(shamir.reconstruct_many(&shares).unwrap(), secrets);
+        assert!(shamir.reconstruct_many(&[]).unwrap().is_empty());
+
+        shares[0].truncate(2);
+        assert!(shamir.reconstruct_many(&shares).is_err());
+    }
+
     #[test]
     fn test_share_operations() {
         let shamir = ShamirSecretSharing::new(2, 3, 7).unwrap();
@@ -339,6 +505,21 @@ mod tests {
         assert_eq!(sum.value(), expected.value());
     }
 
+    #[test]
+    fn test_additive_sharing() {
+        let additive = AdditiveSecretSharing::new(7).unwrap();
+        let secret = FieldElement::new(5, 7);
+
+        let shares = additive.share_secret(secret).unwrap();
+        assert_eq!(shares.len(), 2);
+        assert_eq!(additive.reconstruct_secret(&shares).unwrap(), secret);
+        assert!(additive.reconstruct_secret(&shares[..1]).is_err());
+
+        let other = additive.share_secret(FieldElement::new(4, 7)).unwrap();
+        let sum = additive.add_shares(&shares, &other).unwrap();
+        assert_eq!(additive.reconstruct_secret(&sum).unwrap(), FieldElement::new(2, 7));
+    }
+
     #[test]
     fn test_share_distributor() {
         let shamir = ShamirSecretSharing::new(2, 3, 7).unwrap();
@@ -1,4 +1,7 @@
+use crate::fault::OnlineStep;
 use crate::finite_field::FieldElement;
+use crate::mac::MacShares;
+use crate::offline_phase::ServerCorrelation;
 use crate::secret_sharing::SecretShare;
 use crate::ToyConfig;
 use serde::{Deserialize, Serialize};
@@ -49,14 +52,20 @@ pub struct Server {
     pub state: ServerState,
     /// Configuration
     pub config: ToyConfig,
-    /// Permutation shares (for computational servers)
-    pub permutation_shares: Vec<Vec<Vec<SecretShare>>>,
-    /// Mask shares (for computational servers)
-    pub mask_shares: Vec<Vec<Vec<SecretShare>>>,
-    /// Noise shares (for computational servers)
+    /// This server's half of the shuffle permutation
+    pub permutation: Vec<usize>,
+    /// Blinding added before handing shuffled rows to the next server (first server only)
+    pub blinding: Vec<Vec<FieldElement>>,
+    /// This server's share of the mask correction, indexed `[row][feature]`
+    pub mask_shares: Vec<Vec<SecretShare>>,
+    /// This server's share of each noise value, indexed `[row][feature]`
     pub noise_shares: Vec<Vec<SecretShare>>,
+    /// MAC key and tag shares (malicious mode only)
+    pub mac: Option<MacShares>,
     /// Final result (for computational servers)
     pub final_result: Option<Vec<Vec<FieldElement>>>,
+    /// Online step at which an injected failure takes this server down
+    pub fault: Option<OnlineStep>,
 }
 
 impl Server {
@@ -67,10 +76,13 @@ impl Server {
             role,
             state: ServerState::Offline,
             config,
-            permutation_shares: Vec::new(),
+            permutation: Vec::new(),
+            blinding: Vec::new(),
             mask_shares: Vec::new(),
             noise_shares: Vec::new(),
+            mac: None,
             final_result: None,
+            fault: None,
         }
     }
 
@@ -119,15 +131,45 @@ impl Server {
         self.state.is_failed()
     }
 
-    /// Store permutation shares (for computational servers)
-    pub fn store_permutation_shares(&mut self, shares: Vec<Vec<Vec<SecretShare>>>) {
+    /// Make this server fail when the online phase reaches `step`
+    pub fn fail_at(&mut self, step: OnlineStep) {
+        self.fault = Some(step);
+    }
+
+    /// Enter `step` of the online phase, failing if a fault was injected there
+    ///
+    /// Returns whether the server is still up.
+    pub fn enter_step(&mut self, step: OnlineStep) -> bool {
+        if self.fault == Some(step) {
+            self.fault = None;
+            self.state = ServerState::Failed(format!("Injected failure during {}", step));
+        }
+
+        !self.is_failed()
+    }
+
+    /// Bring a failed server back online without any correlated randomness
+    pub fn recover(&mut self) {
+        self.state = ServerState::Online;
+        self.fault = None;
+        self.permutation.clear();
+        self.blinding.clear();
+        self.mask_shares.clear();
+        self.noise_shares.clear();
+        self.mac = None;
+        self.final_result = None;
+    }
+
+    /// Store permutation half and blinding (for computational servers)
+    pub fn store_permutation(&mut self, permutation: Vec<usize>, blinding: Vec<Vec<FieldElement>>) {
         if self.is_computational() {
-            self.permutation_shares = shares;
+            self.permutation = permutation;
+            self.blinding = blinding;
         }
     }
 
     /// Store mask shares (for computational servers)
-    pub fn store_mask_shares(&mut self, shares: Vec<Vec<Vec<SecretShare>>>) {
+    pub fn store_mask_shares(&mut self, shares: Vec<Vec<SecretShare>>) {
         if self.is_computational() {
             self.mask_shares = shares;
         }
@@ -140,13 +182,18 @@ impl Server {
         }
     }
 
-    /// Get permutation shares
-    pub fn get_permutation_shares(&self) -> &Vec<Vec<Vec<SecretShare>>> {
-        &self.permutation_shares
+    /// Get permutation half
+    pub fn get_permutation(&self) -> &Vec<usize> {
+        &self.permutation
+    }
+
+    /// Get blinding
+    pub fn get_blinding(&self) -> &Vec<Vec<FieldElement>> {
+        &self.blinding
     }
 
     /// Get mask shares
-    pub fn get_mask_shares(&self) -> &Vec<Vec<Vec<SecretShare>>> {
+    pub fn get_mask_shares(&self) -> &Vec<Vec<SecretShare>> {
         &self.mask_shares
     }
 
@@ -167,15 +214,36 @@ impl Server {
         self.final_result.clone().unwrap_or_default()
     }
 
-    /// Receive permutation shares
-    pub fn receive_permutation_shares(&mut self, shares: Vec<Vec<Vec<SecretShare>>>) {
+    /// Receive permutation half and blinding
+    pub fn receive_permutation(&mut self, permutation: Vec<usize>, blinding: Vec<Vec<FieldElement>>) {
         if self.is_computational() {
-            self.permutation_shares = shares;
+            self.permutation = permutation;
+            self.blinding = blinding;
         }
     }
 
+    /// Receive everything the auxiliary server dealt to this server
+    pub fn receive_correlation(&mut self, correlation: ServerCorrelation) {
+        self.receive_permutation(correlation.permutation, correlation.blinding);
+        self.receive_mask_shares(correlation.mask_shares);
+        self.receive_noise_shares(correlation.noise_shares);
+        self.receive_mac_shares(correlation.mac);
+    }
+
+    /// Receive MAC key and tag shares
+    pub fn receive_mac_shares(&mut self, mac: Option<MacShares>) {
+        if self.is_computational() {
+            self.mac = mac;
+        }
+    }
+
+    /// Get MAC key and tag shares
+    pub fn get_mac_shares(&self) -> Option<&MacShares> {
+        self.mac.as_ref()
+    }
+
     /// Receive mask shares
-    pub fn receive_mask_shares(&mut self, shares: Vec<Vec<Vec<SecretShare>>>) {
+    pub fn receive_mask_shares(&mut self, shares: Vec<Vec<SecretShare>>) {
         if self.is_computational() {
             self.mask_shares = shares;
         }
@@ -194,7 +262,7 @@ impl Server {
             id: self.id,
             role: self.role.clone(),
             state: self.state.clone(),
-            permutation_shares_count: self.permutation_shares.len(),
+            permutation_len: self.permutation.len(),
             mask_shares_count: self.mask_shares.len(),
             noise_shares_count: self.noise_shares.len(),
             has_final_result: self.final_result.is_some(),
@@ -211,8 +279,8 @@ pub struct ServerStats {
     pub role: ServerRole,
     /// Server state
     pub state: ServerState,
-    /// Number of permutation shares
-    pub permutation_shares_count: usize,
+    /// Length of the permutation half
+    pub permutation_len: usize,
     /// Number of mask shares
     pub mask_shares_count: usize,
     /// Number of noise shares
@@ -0,0 +1,103 @@
+use crate::finite_field::{FieldElement, FiniteField};
+use crate::histogram;
+use crate::offline_phase::permute;
+use crate::ProtocolError;
+
+/// Trusted plaintext view of one offline phase, kept for verification
+///
+/// Holds the composed shuffle and the noise exactly as P₀ dealt them, so the
+/// expected output `π(x) + r` can be computed in the clear and compared with
+/// what the servers reconstruct.
+#[derive(Debug, Clone)]
+pub struct PlaintextSimulation {
+    /// Composed permutation `π = π₂ ∘ π₁`, in the convention of `permute`
+    pub permutation: Vec<usize>,
+    /// Noise added to each output row
+    pub noise: Vec<Vec<FieldElement>>,
+}
+
+impl PlaintextSimulation {
+    /// Record the permutation halves and noise dealt by P₀
+    pub fn new(
+        first_permutation: &[usize],
+        second_permutation: &[usize],
+        noise: Vec<Vec<FieldElement>>,
+    ) -> Result<Self, ProtocolError> {
+        // permute(π₂, permute(π₁, x))[i] = x[π₁[π₂[i]]]
+        let permutation = permute(second_permutation, first_permutation)?;
+
+        Ok(Self { permutation, noise })
+    }
+
+    /// Compute the expected output `π(x) + r` in the clear
+    pub fn simulate(&self, field: &FiniteField, data: &[Vec<FieldElement>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        let shuffled = permute(&self.permutation, data)?;
+        if shuffled.len() != self.noise.len() {
+            return Err(ProtocolError::DimensionMismatch);
+        }
+
+        shuffled
+            .iter()
+            .zip(self.noise.iter())
+            .map(|(row, noise)| field.vector_add(row, noise).map_err(ProtocolError::from))
+            .collect()
+    }
+
+    /// Compute the expected histogram `Σ (π(x) + r)` in the clear
+    pub fn simulate_histogram(&self, field: &FiniteField, data: &[Vec<FieldElement>]) -> Result<Vec<Vec<FieldElement>>, ProtocolError> {
+        histogram::aggregate(field, &self.simulate(field, data)?)
+    }
+
+    /// Check the MPC output against the plaintext simulation of `data`
+    pub fn verify(&self, field: &FiniteField, data: &[Vec<FieldElement>], output: &[Vec<FieldElement>]) -> Result<(), ProtocolError> {
+        compare(&self.simulate(field, data)?, output)
+    }
+
+    /// Check a histogram-mode output against the plaintext simulation of `data`
+    pub fn verify_histogram(&self, field: &FiniteField, data: &[Vec<FieldElement>], output: &[Vec<FieldElement>]) -> Result<(), ProtocolError> {
+        compare(&self.simulate_histogram(field, data)?, output)
+    }
+}
+
+/// Compare an output with its expected value, reporting the first difference
+fn compare(expected: &[Vec<FieldElement>], output: &[Vec<FieldElement>]) -> Result<(), ProtocolError> {
+    if expected.len() != output.len() {
+        return Err(ProtocolError::DimensionMismatch);
+    }
+
+    for (row, (expected_row, output_row)) in expected.iter().zip(output.iter()).enumerate() {
+        if expected_row.len() != output_row.len() {
+            return Err(ProtocolError::DimensionMismatch);
+        }
+        if let Some(feature) = expected_row.iter().zip(output_row.iter()).position(|(a, b)| a != b) {
+            return Err(ProtocolError::VerificationFailed { row, feature });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulation_composes_permutation_halves() {
+        let field = FiniteField::new(0xFFFFFFFFFFFFFFC5).unwrap();
+        let data: Vec<Vec<FieldElement>> = (0..4).map(|i| vec![field.element(i)]).collect();
+        let first = [2, 0, 3, 1];
+        let second = [1, 3, 0, 2];
+        let noise = vec![vec![field.zero()]; 4];
+
+        let simulation = PlaintextSimulation::new(&first, &second, noise).unwrap();
+        let expected = permute(&second, &permute(&first, &data).unwrap()).unwrap();
+        assert_eq!(simulation.simulate(&field, &data).unwrap(), expected);
+
+        let mut wrong = expected.clone();
+        wrong[2][0] = field.element(99);
+        assert!(matches!(
+            simulation.verify(&field, &data, &wrong),
+            Err(ProtocolError::VerificationFailed { row: 2, feature: 0 })
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::finite_field::{FieldElement, FiniteField, FixedPoint};
+use crate::mask::{MaskDerivation, MaskSeed};
+use crate::{ProtocolError, ToyConfig, UserData};
+
+/// User side of the protocol: turns a record into the submission it sends
+///
+/// Only needs field arithmetic and the mask PRF, so it builds for wasm32
+/// without the `runtime` feature. The online phase masks submissions through
+/// the same code, so a client built on this sends exactly what the servers expect.
+#[derive(Debug, Clone)]
+pub struct UserClient {
+    /// Fixed-point encoding of real values
+    codec: FixedPoint,
+    /// PRF the user masks are derived with
+    masks: MaskDerivation,
+}
+
+impl UserClient {
+    /// Create a client for `num_features`-feature records
+    pub fn new(field_modulus: u64, num_features: usize, fractional_bits: u32) -> Result<Self, ProtocolError> {
+        let field = FiniteField::new(field_modulus)?;
+        let codec = FixedPoint::new(field_modulus, fractional_bits)?;
+
+        Ok(Self {
+            codec,
+            masks: MaskDerivation::new(field, num_features),
+        })
+    }
+
+    /// Create a client matching a protocol configuration
+    pub fn from_config(config: &ToyConfig) -> Result<Self, ProtocolError> {
+        Self::new(config.field_modulus, config.num_features, config.fractional_bits)
+    }
+
+    /// Derive the mask `a_i` of `user_id` from its seed
+    pub fn mask(&self, user_id: usize, seed: &MaskSeed) -> Vec<FieldElement> {
+        self.masks.derive(user_id, seed)
+    }
+
+    /// Compute the masked submission `d_i = x_i - a_i` of an encoded record
+    pub fn masked_submission(&self, user: &UserData) -> Result<Vec<FieldElement>, ProtocolError> {
+        mask_record(&user.data, &self.mask(user.user_id, &user.seed))
+    }
+
+    /// Encode real values and compute their masked submission
+    pub fn submit_values(&self, user_id: usize, seed: MaskSeed, values: &[f64]) -> Result<Vec<FieldElement>, ProtocolError> {
+        let user = UserData::from_values(user_id, values, seed, &self.codec)?;
+        self.masked_submission(&user)
+    }
+
+    /// Encode a histogram bucket as a one-hot vector and compute its masked submission
+    pub fn submit_bucket(&self, user_id: usize, seed: MaskSeed, bucket: usize) -> Result<Vec<FieldElement>, ProtocolError> {
+        let user = UserData::one_hot(user_id, bucket, self.masks.num_features(), seed, &self.codec)?;
+        self.masked_submission(&user)
+    }
+
+    /// Get the fixed-point encoding
+    pub fn codec(&self) -> &FixedPoint {
+        &self.codec
+    }
+}
+
+/// Subtract `mask` from an encoded record element-wise
+pub fn mask_record(data: &[FieldElement], mask: &[FieldElement]) -> Result<Vec<FieldElement>, ProtocolError> {
+    if data.len() != mask.len() {
+        return Err(ProtocolError::DimensionMismatch);
+    }
+
+    data.iter()
+        .zip(mask.iter())
+        .map(|(value, mask_value)| value.sub(mask_value).map_err(|_| ProtocolError::FieldOperationFailed))
+        .collect()
+}
+
+/// JavaScript bindings for a browser demo of the user role
+#[cfg(feature = "wasm")]
+mod bindings {
+    use super::UserClient;
+    use wasm_bindgen::prelude::*;
+
+    /// Compute a user's masked submission, returned as raw field elements
+    #[wasm_bindgen(js_name = maskedSubmission)]
+    pub fn masked_submission(
+        field_modulus: u64,
+        fractional_bits: u32,
+        user_id: usize,
+        seed: &[u8],
+        values: &[f64],
+    ) -> Result<Vec<u64>, JsError> {
+        let seed = seed.try_into().map_err(|_| JsError::new("seed must be 32 bytes"))?;
+        let client = UserClient::new(field_modulus, values.len(), fractional_bits).map_err(|e| JsError::new(&e.to_string()))?;
+        let submission = client
+            .submit_values(user_id, seed, values)
+            .map_err(|e| JsError::new(&e.to_string()))?;
+
+        Ok(submission.iter().map(|value| value.value()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_submission_adds_back_to_record() {
+        let config = ToyConfig::default();
+        let client = UserClient::from_config(&config).unwrap();
+
+        let submission = client.submit_values(7, [99; 32], &[1.5, -2.0]).unwrap();
+        let mask = client.mask(7, &[99; 32]);
+        let unmasked: Vec<f64> = submission
+            .iter()
+            .zip(mask.iter())
+            .map(|(d, a)| client.codec().decode(&d.add(a).unwrap()))
+            .collect();
+        assert_eq!(unmasked, vec![1.5, -2.0]);
+
+        assert!(client.submit_values(7, [99; 32], &[1.5]).is_err());
+    }
+}